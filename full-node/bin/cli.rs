@@ -36,7 +36,13 @@ use smoldot::{
         PeerId,
     },
 };
-use std::{io, net::SocketAddr, path::PathBuf};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
 
 // Note: the doc-comments applied to this struct and its field are visible when the binary is
 // started with `--help`.
@@ -60,13 +66,29 @@ pub enum CliOptionsCommand {
     /// Computes the 256 bits BLAKE2 hash of a file and prints the hexadecimal-encoded hash.
     #[command(name = "blake2-256bits-hash")]
     Blake2256BitsHash(CliOptionsBlake2256Hash),
+    /// Writes the finalized blocks of the database of a chain to a file, in the binary format of
+    /// the `export-blocks` command of Substrate. The node must not be running.
+    #[command(name = "export-blocks")]
+    ExportBlocks(CliOptionsExportBlocks),
 }
 
 #[derive(Debug, clap::Parser)]
 pub struct CliOptionsRun {
     /// Path to a file containing the specification of the chain to connect to.
-    #[arg(long)]
-    pub path_to_chain_spec: PathBuf,
+    #[arg(
+        long,
+        required_unless_present = "chain_spec_url",
+        conflicts_with = "chain_spec_url"
+    )]
+    pub path_to_chain_spec: Option<PathBuf>,
+    /// HTTP(S) URL to download the specification of the chain to connect to from. Requires
+    /// `--chain-spec-hash`. Parachains aren't supported.
+    #[arg(long, requires = "chain_spec_hash")]
+    pub chain_spec_url: Option<String>,
+    /// Expected 256 bits BLAKE2 hash of the chain specification downloaded from
+    /// `--chain-spec-url`, as printed by the `blake2-256bits-hash` command.
+    #[arg(long, value_parser = parse_blake2_256_hash)]
+    pub chain_spec_hash: Option<[u8; 32]>,
     /// Output to stdout: auto, none, informant, logs, logs-json.
     #[arg(long, default_value = "auto")]
     pub output: Output,
@@ -79,18 +101,129 @@ pub struct CliOptionsRun {
     /// Ed25519 private key of network identity (as a seed phrase).
     #[arg(long, value_parser = decode_ed25519_private_key)]
     pub libp2p_key: Option<Box<[u8; 32]>>,
-    /// `Multiaddr` to listen on.
+    /// Path to a file containing the hexadecimal-encoded Ed25519 private key of network identity.
+    /// The key is generated and written to this file if it doesn't exist yet. Defaults to a file
+    /// in the base storage directory.
+    #[arg(long, conflicts_with = "libp2p_key")]
+    pub libp2p_key_file: Option<PathBuf>,
+    /// `Multiaddr` to listen on. Use for example `/ip4/0.0.0.0/tcp/30334/ws` in order to accept
+    /// WebSocket connections from light clients running in browsers.
     #[arg(long, value_parser = decode_multiaddr)]
     pub listen_addr: Vec<Multiaddr>,
+    /// Maximum number of outgoing connections towards IP addresses of the same `/24` (IPv4) or
+    /// `/48` (IPv6) subnet. Makes eclipse attacks more difficult. Doesn't apply to loopback and
+    /// private IP addresses.
+    #[arg(long, default_value = "2")]
+    pub max_outbound_connections_per_subnet: NonZeroUsize,
+    /// Maximum number of simultaneous incoming connections from the same IP address. Makes it
+    /// more expensive to exhaust the connection slots of the node. Doesn't apply to loopback and
+    /// private IP addresses.
+    #[arg(long, default_value = "4")]
+    pub max_inbound_connections_per_ip: NonZeroUsize,
+    /// IP address to which `--max-inbound-connections-per-ip` doesn't apply. Can be passed
+    /// multiple times.
+    #[arg(long)]
+    pub inbound_connections_ip_allowlist: Vec<IpAddr>,
+    /// Address (`<ip>:<port>`) of a SOCKS5 proxy, such as a Tor client, through which all the
+    /// outgoing networking connections are established.
+    #[arg(long)]
+    pub socks5_proxy: Option<SocketAddr>,
+    /// Ask the router of the local network, through NAT-PMP or UPnP, to forward the ports of the
+    /// IPv4 listening addresses. The resulting public addresses are advertised to peers.
+    #[arg(long)]
+    pub nat_port_mapping: bool,
+    /// Maximum number of bytes per second sent to the network, all peers combined. Useful when
+    /// running on a metered connection.
+    #[arg(long)]
+    pub max_upload_bytes_per_sec: Option<NonZeroU64>,
+    /// Maximum number of bytes per second received from the network, all peers combined. Useful
+    /// when running on a metered connection.
+    #[arg(long)]
+    pub max_download_bytes_per_sec: Option<NonZeroU64>,
+    /// Maximum number of file descriptors that the node opens for its networking sockets and
+    /// databases. Once reached, incoming connections are refused and outgoing connections are
+    /// postponed. Should be set below the limit of the operating system (`ulimit -n`).
+    #[arg(long)]
+    pub max_file_descriptors: Option<NonZeroUsize>,
+    /// Maximum number of peers the node tries to maintain an outgoing gossip link with, per
+    /// chain.
+    #[arg(long, default_value = "15")]
+    pub max_out_peers: usize,
+    /// Number of outgoing gossip links below which the node considers that it lacks peers and
+    /// discovers new peers more aggressively. Must be inferior or equal to `--max-out-peers`.
+    #[arg(long, default_value = "4")]
+    pub min_out_peers: usize,
+    /// Maximum number of incoming gossip links accepted, per chain.
+    #[arg(long, default_value = "25")]
+    pub max_in_peers: usize,
+    /// Also accept, advertise, and fall back to the legacy protocol names derived from the
+    /// `protocolId` field of the chain specification, for compatibility with older Substrate
+    /// nodes.
+    #[arg(long)]
+    pub legacy_protocol_names: bool,
     /// `Multiaddr` of an additional node to try to connect to on startup.
     #[arg(long, value_parser = parse_bootnode)]
     pub additional_bootnode: Vec<Bootnode>,
     /// Bind point of the JSON-RPC server ("none" or `<ip>:<port>`).
     #[arg(long, default_value = "127.0.0.1:9944", value_parser = parse_json_rpc_address)]
     pub json_rpc_address: JsonRpcAddress,
+    /// Bind point of an additional JSON-RPC server that exposes all the JSON-RPC methods,
+    /// including the unsafe ones (e.g. key management). If passed, the server of
+    /// `--json-rpc-address` only exposes the safe methods.
+    #[arg(long)]
+    pub json_rpc_admin_address: Option<SocketAddr>,
+    /// Bind point of a plain HTTP server serving the `GET /health` and `GET /ready` endpoints.
+    /// These endpoints are also served on the JSON-RPC server(s). Ignored if no JSON-RPC server.
+    #[arg(long)]
+    pub health_address: Option<SocketAddr>,
     /// Maximum number of JSON-RPC clients that can be connected simultaneously. Ignored if no server.
     #[arg(long, default_value = "64")]
     pub json_rpc_max_clients: u32,
+    /// Maximum number of JSON-RPC clients with the same IP address that can be connected
+    /// simultaneously. No limit if not passed.
+    #[arg(long)]
+    pub json_rpc_max_clients_per_ip: Option<u32>,
+    /// Maximum number of JSON-RPC requests per second per client. Requests beyond this limit are
+    /// answered with a "server busy" error. No limit if not passed.
+    #[arg(long)]
+    pub json_rpc_max_requests_per_second: Option<u32>,
+    /// Maximum number of runtime calls initiated by JSON-RPC requests that can run in parallel.
+    /// Runtime calls necessary to verify and author blocks always have priority over them.
+    #[arg(long, default_value = "4")]
+    pub json_rpc_max_runtime_calls: NonZeroUsize,
+    /// Maximum number of subscriptions that each JSON-RPC client can have active simultaneously.
+    #[arg(long, default_value = "128")]
+    pub json_rpc_max_subscriptions_per_client: u32,
+    /// Maximum number of responses and notifications waiting to be sent to each JSON-RPC client.
+    /// Beyond this limit, `--json-rpc-slow-subscriber-policy` applies.
+    #[arg(long, default_value = "4096")]
+    pub json_rpc_max_pending_notifications: NonZeroUsize,
+    /// What to do when a JSON-RPC client doesn't read its notifications fast enough:
+    /// drop-oldest, disconnect.
+    #[arg(long, default_value = "disconnect")]
+    pub json_rpc_slow_subscriber_policy: SlowSubscriberPolicy,
+    /// If passed, the `chain_subscribe*Heads` subscriptions of all JSON-RPC clients share a
+    /// single internal subscription, and each of them buffers up to this number of headers.
+    /// Subscriptions that lag behind lose their oldest headers. Recommended for public nodes.
+    #[arg(long)]
+    pub json_rpc_multiplex_subscriptions: Option<NonZeroUsize>,
+    /// Path to a PEM file containing the TLS certificate chain of the JSON-RPC server. If set,
+    /// the JSON-RPC server only accepts `wss://` connections.
+    #[arg(long, requires = "json_rpc_tls_private_key")]
+    pub json_rpc_tls_certificate: Option<PathBuf>,
+    /// Path to a PEM file containing the private key of the TLS certificate of the JSON-RPC
+    /// server.
+    #[arg(long, requires = "json_rpc_tls_certificate")]
+    pub json_rpc_tls_private_key: Option<PathBuf>,
+    /// Value of the `Origin` header that browsers are allowed to connect to the JSON-RPC server
+    /// from (e.g. `https://polkadot.js.org`). Can be passed multiple times. If not passed, all
+    /// origins are allowed.
+    #[arg(long)]
+    pub json_rpc_allowed_origin: Vec<String>,
+    /// Duration during which the transactions found to be invalid are refused when they are
+    /// submitted again through the JSON-RPC server (e.g. `30min`).
+    #[arg(long, default_value = "30min", value_parser = humantime::parse_duration)]
+    pub json_rpc_transactions_ban_duration: Duration,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     #[arg(long, value_parser = decode_sr25519_private_key)]
     // TODO: also automatically add the same keys through ed25519?
@@ -98,9 +231,158 @@ pub struct CliOptionsRun {
     /// Address of a Jaeger agent to send traces to (hint: port is typically 6831).
     #[arg(long)]
     pub jaeger: Option<SocketAddr>,
+    /// Fraction of the networking events of a protocol for which a Jaeger trace is sent, as
+    /// `<protocol>=<rate>` where protocol is one of: block-announces, block-requests (e.g.
+    /// `block-announces=0.01`). Can be passed multiple times. Defaults to `1` for all protocols.
+    #[arg(long, value_parser = parse_jaeger_sampling)]
+    pub jaeger_sampling: Vec<JaegerSamplingRate>,
+    /// Only track and store the finalized chain, discarding competing forks as soon as possible.
+    /// Appropriate for nodes that never author blocks.
+    #[arg(long)]
+    pub finalized_chain_only: bool,
+    /// Execute each verified block a second time, one extrinsic at a time, in order to measure
+    /// the resources used by each extrinsic. Roughly doubles the block verification time.
+    #[arg(long)]
+    pub block_execution_profiling: bool,
+    /// Take part in the GrandPa rounds of the chain. If the keystore contains a GrandPa Ed25519
+    /// key that belongs to the current authorities set, votes are signed with this key and
+    /// gossiped.
+    /// Experimental.
+    #[arg(long)]
+    pub grandpa_voter: bool,
+    /// Execute the offchain worker of the runtime every time a new best block is imported. The
+    /// offchain local storage is persisted in the database.
+    #[arg(long)]
+    pub offchain_worker: bool,
+    /// Store in the offchain storage of the database the values that the runtime indexes while
+    /// blocks are executed, so that the offchain workers can read them.
+    #[arg(long)]
+    pub offchain_indexing: bool,
+    /// Report the Babe and GrandPa equivocations detected by the node to the chain, so that the
+    /// offenders can be punished. Equivocations are always logged.
+    #[arg(long)]
+    pub report_equivocations: bool,
+    /// Proportion of each slot, between 0 and 1, after which the node stops including
+    /// transactions in the block it is authoring, leaving the rest of the slot to finish and
+    /// propagate the block.
+    #[arg(long, default_value = "0.6667")]
+    pub authoring_slot_proportion: f32,
+    /// Maximum total size, in bytes, of the transactions included in the blocks authored by the
+    /// node.
+    #[arg(long, default_value = "4194304")]
+    pub max_block_transactions_size: usize,
+    /// Maximum number of transactions waiting to be included in a block. Transactions submitted
+    /// while this limit is reached are refused.
+    #[arg(long, default_value = "8192")]
+    pub pool_limit: usize,
+    /// Maximum number of transactions waiting to be included in a block that have been signed by
+    /// the same account. Transactions submitted by the node itself aren't subject to this limit.
+    #[arg(long, default_value = "512")]
+    pub pool_limit_per_submitter: usize,
+    /// Maximum number of blocks downloaded ahead of the latest verified block while syncing. No
+    /// block is requested from peers while this limit is reached. Lower values reduce the memory
+    /// usage of the initial sync.
+    #[arg(long, default_value = "2000")]
+    pub sync_download_ahead_blocks: NonZeroU32,
+    /// Maximum number of block headers whose parent is unknown held in memory.
+    #[arg(long, default_value = "1024")]
+    pub sync_max_disjoint_headers: usize,
+    /// Maximum number of requests in progress at the same time towards the same peer.
+    #[arg(long, default_value = "2")]
+    pub sync_max_requests_per_peer: NonZeroUsize,
+    /// Maximum number of ranges of blocks downloaded in parallel from multiple peers during the
+    /// initial sync. If 0, ranges are downloaded one after the other.
+    #[arg(long, default_value = "8")]
+    pub sync_parallel_block_ranges: usize,
+    /// Path of a file where a checkpoint of the chain, from which light clients can be
+    /// bootstrapped, is periodically written. The file can for example be served over HTTP.
+    #[arg(long)]
+    pub checkpoint_export_path: Option<PathBuf>,
+    /// Delay between two exports of the checkpoint. Ignored if `--checkpoint-export-path` isn't
+    /// passed.
+    #[arg(long, default_value = "5min", value_parser = humantime::parse_duration)]
+    pub checkpoint_export_interval: Duration,
+    /// Path to a file generated by the `export-blocks` command of a Substrate node, in its binary
+    /// format. The blocks of the file are verified and imported after the node has started.
+    /// Combine with `--sync full` in order to bootstrap the node from the file.
+    #[arg(long)]
+    pub import_blocks: Option<PathBuf>,
+    /// If passed, the database of the chain is periodically copied to this file while the node
+    /// is running. The previous backup, if any, is replaced.
+    #[arg(long)]
+    pub database_backup_path: Option<PathBuf>,
+    /// Delay between two backups of the database. Ignored if `--database-backup-path` isn't
+    /// passed.
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    pub database_backup_interval: Duration,
+    /// Path to a snapshot of the state of a finalized block, generated by another node. If the
+    /// database is empty, it is initialized from this snapshot rather than from the genesis
+    /// block, and the node resumes syncing from the finalized block of the snapshot. The content
+    /// of the snapshot is trusted.
+    #[arg(long)]
+    pub state_snapshot: Option<PathBuf>,
+    /// If passed, the space of the database file that is no longer used, for example because
+    /// of the pruning, is given back to the file system at this interval (e.g. `1h`).
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub database_compaction_interval: Option<Duration>,
+    /// How to catch up with the head of the chain: full (download and verify every block), warp
+    /// (jump to the latest finalized block using GrandPa warp sync proofs then download its
+    /// storage).
+    #[arg(long, default_value = "warp")]
+    pub sync: SyncMode,
+    /// Send each warp sync request to two different peers and compare their responses, in order
+    /// to detect a single malicious peer. Doubles the bandwidth used by warp syncing.
+    #[arg(long)]
+    pub cross_check_warp_sync: bool,
+    /// Ignore blocks forking off the best chain more than this number of blocks below the best
+    /// block. Protects against pathological forks when finality is stalled.
+    #[arg(long)]
+    pub max_reorg_depth: Option<u64>,
+    /// Accept blocks whose slot starts up to this duration in the future compared to the local
+    /// clock (e.g. `30s`). Private testnets whose nodes are paused and resumed might need a
+    /// higher value.
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    pub slot_drift_tolerance: Duration,
+    /// When slots have been skipped since the best block, extend the time available for
+    /// authoring a block by one slot per skipped slot, up to this duration (e.g. `1min`).
+    #[arg(long, default_value = "0s", value_parser = humantime::parse_duration)]
+    pub max_slot_lenience: Duration,
+    /// Which blocks to keep in the database: archive (everything), archive-canonical (all the
+    /// finalized blocks, discarding the competing forks), a number N (only the storage and
+    /// body of the last N finalized blocks; headers are always kept), or state-N (only the
+    /// storage of the last N finalized blocks; bodies and headers are always kept).
+    #[arg(long, default_value = "archive", value_parser = parse_pruning)]
+    pub pruning: Pruning,
+    /// If passed, periodically disconnects from the peer that is the most behind in order to
+    /// make room for newly discovered peers (e.g. `10min`).
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub peer_rotation_interval: Option<Duration>,
+    /// If no peer is connected after this delay (e.g. `1min`), try again the peers saved during
+    /// a previous run of the node and connect to the nodes passed with `--fallback-bootnode`.
+    #[arg(long, default_value = "1min", value_parser = humantime::parse_duration)]
+    pub bootstrap_fallback_delay: Duration,
+    /// `Multiaddr` of a well-known node to connect to only if none of the bootnodes is
+    /// reachable. See `--bootstrap-fallback-delay`.
+    #[arg(long, value_parser = parse_bootnode)]
+    pub fallback_bootnode: Vec<Bootnode>,
+    /// Number of threads dedicated to executing the runtime when verifying blocks. If not
+    /// passed, the runtime is executed on the same threads as the rest of the node.
+    #[arg(long)]
+    pub runtime_execution_threads: Option<NonZeroUsize>,
+    /// Comma-separated list of CPU cores to pin the threads of `--runtime-execution-threads` to.
+    #[arg(long, value_delimiter = ',', requires = "runtime_execution_threads")]
+    pub runtime_execution_cores: Vec<usize>,
+    /// Number of threads used to build the genesis trie when the database is empty. Defaults to
+    /// the number of CPU cores.
+    #[arg(long)]
+    pub genesis_build_threads: Option<NonZeroUsize>,
     /// Do not load or store anything on disk.
     #[arg(long)]
     pub tmp: bool,
+    /// If the database turns out to be corrupted, move it aside and start with an empty
+    /// database instead of stopping the node.
+    #[arg(long)]
+    pub quarantine_corrupted_database: bool,
     /// Maximum size of the cache used by the database.
     #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
     pub database_cache_size: MaxBytes,
@@ -108,6 +390,36 @@ pub struct CliOptionsRun {
     /// chain is not a parachain.
     #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
     pub relay_chain_database_cache_size: MaxBytes,
+    /// How the database guarantees the atomicity of its writes: wal (write-ahead log) or one of
+    /// the rollback journal modes. Network volumes might not support the write-ahead log.
+    #[arg(long, default_value = "wal")]
+    pub database_journal_mode: DatabaseJournalMode,
+    /// How often the database waits for its writes to be physically written to the disk.
+    #[arg(long, default_value = "normal")]
+    pub database_synchronous: DatabaseSynchronous,
+    /// Maximum size of the database file that is memory-mapped. 0 disables memory-mapping.
+    #[arg(long, default_value = "1G", value_parser = parse_max_bytes)]
+    pub database_mmap_size: MaxBytes,
+    /// Size in bytes of the pages of the database, between 512 and 65536. Only applies when the
+    /// database is created.
+    #[arg(long)]
+    pub database_page_size: Option<u32>,
+    /// Number of read-only connections to the database used to answer JSON-RPC requests and
+    /// requests of light clients, so that slow requests don't delay the import of blocks. Only
+    /// used with `--database-journal-mode wal`. If non-zero, the database is no longer locked
+    /// exclusively, and other processes can read it while the node is running.
+    #[arg(long, default_value = "0")]
+    pub database_read_connections: usize,
+    /// Don't lock the database exclusively, so that other processes can read it while the node
+    /// is running. Only used with `--database-journal-mode wal`.
+    #[arg(long)]
+    pub database_external_read_access: bool,
+    /// Hexadecimal-encoded 32 bytes seed from which all the internal randomness of the node is
+    /// derived, in order to reproduce the behavior of a previous run against a simulated
+    /// network. For testing purposes only, as it makes the keys generated by the node
+    /// predictable.
+    #[arg(long, value_parser = parse_randomness_seed)]
+    pub randomness_seed: Option<[u8; 32]>,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -122,6 +434,25 @@ pub struct CliOptionsBlake2256Hash {
     pub file: PathBuf,
 }
 
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsExportBlocks {
+    /// Path to a file containing the specification of the chain whose blocks to export.
+    #[arg(long)]
+    pub path_to_chain_spec: PathBuf,
+    /// Path to the database to read the blocks from. Defaults to the database that the `run`
+    /// command uses for this chain.
+    #[arg(long)]
+    pub database_path: Option<PathBuf>,
+    /// Height of the first block to export.
+    #[arg(long, default_value = "0")]
+    pub from: u64,
+    /// Height of the last block to export. Defaults to the finalized block.
+    #[arg(long)]
+    pub to: Option<u64>,
+    /// Path of the file to write the blocks to.
+    pub output: PathBuf,
+}
+
 #[derive(Debug, Clone)]
 pub enum ColorChoice {
     Always,
@@ -197,6 +528,34 @@ pub enum Output {
     LogsJson,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum SyncMode {
+    Full,
+    Warp,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum DatabaseJournalMode {
+    Wal,
+    Delete,
+    Truncate,
+    Persist,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum DatabaseSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum SlowSubscriberPolicy {
+    DropOldest,
+    Disconnect,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonRpcAddress(pub Option<SocketAddr>);
 
@@ -229,6 +588,60 @@ fn parse_bootnode(string: &str) -> Result<Bootnode, String> {
     Ok(Bootnode { address, peer_id })
 }
 
+#[derive(Debug, Clone)]
+pub struct JaegerSamplingRate {
+    pub protocol: JaegerProtocol,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum JaegerProtocol {
+    BlockAnnounces,
+    BlockRequests,
+}
+
+fn parse_jaeger_sampling(string: &str) -> Result<JaegerSamplingRate, String> {
+    let Some((protocol, rate)) = string.split_once('=') else {
+        return Err("Jaeger sampling must be of the form <protocol>=<rate>".into());
+    };
+    let protocol = match protocol {
+        "block-announces" => JaegerProtocol::BlockAnnounces,
+        "block-requests" => JaegerProtocol::BlockRequests,
+        _ => {
+            return Err(
+                "Jaeger sampling protocol must be one of: block-announces, block-requests".into(),
+            )
+        }
+    };
+    let rate = match rate.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => rate,
+        _ => return Err("Jaeger sampling rate must be a number between 0 and 1".into()),
+    };
+    Ok(JaegerSamplingRate { protocol, rate })
+}
+
+#[derive(Debug, Clone)]
+pub enum Pruning {
+    Archive,
+    ArchiveCanonical,
+    KeepLast(u64),
+    KeepStates(u64),
+}
+
+fn parse_pruning(string: &str) -> Result<Pruning, String> {
+    match string {
+        "archive" => Ok(Pruning::Archive),
+        "archive-canonical" => Ok(Pruning::ArchiveCanonical),
+        _ => match string.strip_prefix("state-") {
+            Some(n) => n.parse::<u64>().map(Pruning::KeepStates),
+            None => string.parse::<u64>().map(Pruning::KeepLast),
+        }
+        .map_err(|_| {
+            "Pruning must be one of: archive, archive-canonical, <number>, state-<number>".into()
+        }),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MaxBytes(pub usize);
 
@@ -274,6 +687,18 @@ fn parse_max_bytes(string: &str) -> Result<MaxBytes, String> {
     Ok(MaxBytes(real_value))
 }
 
+fn parse_blake2_256_hash(string: &str) -> Result<[u8; 32], String> {
+    let string = string.strip_prefix("0x").unwrap_or(string);
+    let bytes = hex::decode(string).map_err(|err| err.to_string())?;
+    <[u8; 32]>::try_from(bytes).map_err(|_| "Hash must be 32 bytes long".into())
+}
+
+fn parse_randomness_seed(string: &str) -> Result<[u8; 32], String> {
+    let string = string.strip_prefix("0x").unwrap_or(string);
+    let bytes = hex::decode(string).map_err(|err| err.to_string())?;
+    <[u8; 32]>::try_from(bytes).map_err(|_| "Seed must be 32 bytes long".into())
+}
+
 // `clap` requires error types to implement the `std::error::Error` trait.
 // For this reason, we locally define some wrappers.
 fn decode_ed25519_private_key(phrase: &str) -> Result<Box<[u8; 32]>, String> {
@@ -43,10 +43,50 @@ async fn async_main() {
             let hash = blake2_rfc::blake2b::blake2b(32, &[], &content);
             println!("0x{}", hex::encode(hash));
         }
+        cli::CliOptionsCommand::ExportBlocks(opt) => export_blocks(opt),
+    }
+}
+
+fn export_blocks(cli_options: cli::CliOptionsExportBlocks) {
+    let chain_spec = smoldot::chain_spec::ChainSpec::from_json_bytes(
+        &fs::read(&cli_options.path_to_chain_spec).expect("Failed to read chain specification"),
+    )
+    .expect("Failed to decode chain specification");
+
+    // Same path as the one used by `run`.
+    let database_path = cli_options.database_path.unwrap_or_else(|| {
+        directories::ProjectDirs::from("io", "smoldot", "smoldot")
+            .expect("Failed to fetch $HOME directory")
+            .data_dir()
+            .join(chain_spec.id())
+            .join("database")
+    });
+
+    match smoldot_full_node::export_blocks_from_database_file(
+        &database_path,
+        usize::from(chain_spec.block_number_bytes()),
+        &cli_options.output,
+        cli_options.from..=cli_options.to.unwrap_or(u64::MAX),
+    ) {
+        Ok(num_blocks) => {
+            eprintln!(
+                "Exported {num_blocks} blocks to {}",
+                cli_options.output.display()
+            );
+        }
+        Err(err) => {
+            eprintln!("Failed to export blocks: {err}");
+            std::process::exit(1);
+        }
     }
 }
 
 async fn run(cli_options: cli::CliOptionsRun) {
+    assert!(
+        cli_options.min_out_peers <= cli_options.max_out_peers,
+        "--min-out-peers must be inferior or equal to --max-out-peers"
+    );
+
     // Determine the actual CLI output by replacing `Auto` with the actual value.
     let cli_output = if let cli::Output::Auto = cli_options.output {
         if io::IsTerminal::is_terminal(&io::stderr()) && cli_options.log_level.is_none() {
@@ -187,8 +227,45 @@ async fn run(cli_options: cli::CliOptionsRun) {
         cli::Output::Auto => unreachable!(), // Handled above.
     };
 
-    let chain_spec =
-        fs::read(&cli_options.path_to_chain_spec).expect("Failed to read chain specification");
+    // When the informant is enabled, the progress of the initialization is displayed on a line
+    // that overwrites itself, similar to the informant. Otherwise, the logs are enough.
+    let progress_callback: Arc<dyn smoldot_full_node::ProgressCallback + Send + Sync> =
+        if matches!(cli_output, cli::Output::Informant) {
+            Arc::new(|progress| match progress {
+                smoldot_full_node::Progress::DatabaseOpen { chain } => {
+                    eprint!("Opening the database of {chain}...\r");
+                }
+                smoldot_full_node::Progress::GenesisBuild {
+                    chain,
+                    step,
+                    processed,
+                    total,
+                } => {
+                    let step = match step {
+                        smoldot_full_node::GenesisBuildStep::TrieStructure => "trie structure",
+                        smoldot_full_node::GenesisBuildStep::MerkleValues => "Merkle values",
+                    };
+                    eprint!(
+                        "Building the genesis block of {chain} ({step}): {}%\r",
+                        processed * 100 / total.max(1)
+                    );
+                }
+                smoldot_full_node::Progress::StartupPhase { .. } => {}
+            })
+        } else {
+            Arc::new(|_| {})
+        };
+
+    let chain_spec = match (&cli_options.path_to_chain_spec, &cli_options.chain_spec_url) {
+        (Some(path), _) => fs::read(path).expect("Failed to read chain specification"),
+        (None, Some(url)) => {
+            smoldot_full_node::fetch_chain_spec(url, &cli_options.chain_spec_hash.unwrap())
+                .await
+                .expect("Failed to download chain specification")
+        }
+        // Enforced by the CLI parser.
+        (None, None) => unreachable!(),
+    };
     let parsed_chain_spec = {
         smoldot::chain_spec::ChainSpec::from_json_bytes(&chain_spec)
             .expect("Failed to decode chain specification")
@@ -219,6 +296,25 @@ async fn run(cli_options: cli::CliOptionsRun) {
     let sqlite_database_path = base_storage_directory
         .as_ref()
         .map(|d| d.join(parsed_chain_spec.id()).join("database"));
+    // Shared by the databases of the chain and of the relay chain, which are on the same disk.
+    let sqlite_options = smoldot_full_node::SqliteOptions {
+        journal_mode: match cli_options.database_journal_mode {
+            cli::DatabaseJournalMode::Wal => smoldot_full_node::SqliteJournalMode::Wal,
+            cli::DatabaseJournalMode::Delete => smoldot_full_node::SqliteJournalMode::Delete,
+            cli::DatabaseJournalMode::Truncate => smoldot_full_node::SqliteJournalMode::Truncate,
+            cli::DatabaseJournalMode::Persist => smoldot_full_node::SqliteJournalMode::Persist,
+        },
+        synchronous: match cli_options.database_synchronous {
+            cli::DatabaseSynchronous::Off => smoldot_full_node::SqliteSynchronous::Off,
+            cli::DatabaseSynchronous::Normal => smoldot_full_node::SqliteSynchronous::Normal,
+            cli::DatabaseSynchronous::Full => smoldot_full_node::SqliteSynchronous::Full,
+            cli::DatabaseSynchronous::Extra => smoldot_full_node::SqliteSynchronous::Extra,
+        },
+        memory_map_size: cli_options.database_mmap_size.0,
+        page_size: cli_options.database_page_size,
+        read_connections: cli_options.database_read_connections,
+        external_read_access: cli_options.database_external_read_access,
+    };
     // Directory supposed to contain the keystore.
     let keystore_path = base_storage_directory
         .as_ref()
@@ -230,6 +326,11 @@ async fn run(cli_options: cli::CliOptionsRun) {
             let spec_json = {
                 let relay_chain_path = cli_options
                     .path_to_chain_spec
+                    .as_ref()
+                    .expect(
+                        "Parachains are only supported when the chain specification is loaded \
+                        from a file",
+                    )
                     .parent()
                     .unwrap()
                     .join(format!("{relay_chain_name}.json"));
@@ -251,6 +352,7 @@ async fn run(cli_options: cli::CliOptionsRun) {
             let cfg = smoldot_full_node::ChainConfig {
                 chain_spec: spec_json.into(),
                 additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
                 keystore_memory: Vec::new(),
                 sqlite_database_path: base_storage_directory.as_ref().map(|d| {
                     d.join(parsed_relay_spec.id())
@@ -258,10 +360,48 @@ async fn run(cli_options: cli::CliOptionsRun) {
                         .join("database.sqlite")
                 }),
                 sqlite_cache_size: cli_options.relay_chain_database_cache_size.0,
+                sqlite_options: sqlite_options.clone(),
                 keystore_path: base_storage_directory
                     .as_ref()
                     .map(|path| path.join(parsed_relay_spec.id()).join("keys")),
                 json_rpc_listen: None,
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: match cli_options.sync {
+                    cli::SyncMode::Full => smoldot_full_node::SyncMode::Full,
+                    cli::SyncMode::Warp => smoldot_full_node::SyncMode::Warp,
+                },
+                cross_check_warp_sync: cli_options.cross_check_warp_sync,
+                max_reorg_depth: cli_options.max_reorg_depth,
+                slot_drift_tolerance: cli_options.slot_drift_tolerance,
+                max_slot_lenience: cli_options.max_slot_lenience,
+                pruning: match cli_options.pruning {
+                    cli::Pruning::Archive => smoldot_full_node::Pruning::Archive,
+                    cli::Pruning::ArchiveCanonical => smoldot_full_node::Pruning::ArchiveCanonical,
+                    cli::Pruning::KeepLast(n) => smoldot_full_node::Pruning::KeepLast(n),
+                    cli::Pruning::KeepStates(n) => smoldot_full_node::Pruning::KeepStates(n),
+                },
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: cli_options.max_out_peers,
+                min_out_peers: cli_options.min_out_peers,
+                max_in_peers: cli_options.max_in_peers,
+                legacy_protocol_names: cli_options.legacy_protocol_names,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 0,
+                max_pool_transactions: cli_options.pool_limit,
+                max_pool_transactions_per_submitter: cli_options.pool_limit_per_submitter,
+                sync_limits: Default::default(),
             };
 
             (Some(cfg), Some(relay_chain_name.to_owned()))
@@ -271,38 +411,18 @@ async fn run(cli_options: cli::CliOptionsRun) {
 
     // Determine which networking key to use.
     //
-    // This is either passed as a CLI option, loaded from disk, or generated randomly.
-    // TODO: move this code to `/lib/src/identity`?
+    // This is either passed as a CLI option, loaded from disk (and generated if missing), or
+    // generated randomly.
     let libp2p_key = if let Some(node_key) = cli_options.libp2p_key {
-        node_key
+        smoldot_full_node::Libp2pKey::Memory(node_key)
+    } else if let Some(path) = cli_options.libp2p_key_file {
+        smoldot_full_node::Libp2pKey::File(path)
     } else if let Some(dir) = base_storage_directory.as_ref() {
-        let path = dir.join("libp2p_ed25519_secret_key.secret");
-        let libp2p_key = if path.exists() {
-            let file_content = zeroize::Zeroizing::new(
-                fs::read_to_string(&path).expect("failed to read libp2p secret key file content"),
-            );
-            let mut hex_decoded = Box::new([0u8; 32]);
-            hex::decode_to_slice(file_content, &mut *hex_decoded)
-                .expect("invalid libp2p secret key file content");
-            hex_decoded
-        } else {
-            let mut actual_key = Box::new([0u8; 32]);
-            rand::Fill::try_fill(&mut *actual_key, &mut rand::thread_rng()).unwrap();
-            let mut hex_encoded = Box::new([0; 64]);
-            hex::encode_to_slice(*actual_key, &mut *hex_encoded).unwrap();
-            fs::write(&path, *hex_encoded).expect("failed to write libp2p secret key file");
-            zeroize::Zeroize::zeroize(&mut *hex_encoded);
-            actual_key
-        };
-        // On Unix platforms, set the permission as 0o400 (only reading and by owner is permitted).
-        // TODO: do something equivalent on Windows
-        #[cfg(unix)]
-        let _ = fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o400));
-        libp2p_key
+        smoldot_full_node::Libp2pKey::File(dir.join("libp2p_ed25519_secret_key.secret"))
     } else {
         let mut key = Box::new([0u8; 32]);
         rand::Fill::try_fill(&mut *key, &mut rand::thread_rng()).unwrap();
-        key
+        smoldot_full_node::Libp2pKey::Memory(key)
     };
 
     // Create an executor where tasks are going to be spawned onto.
@@ -359,6 +479,17 @@ async fn run(cli_options: cli::CliOptionsRun) {
             .to_string(),
     );
 
+    // This warning message should be removed once the GrandPa voter has been tested on live
+    // networks.
+    if cli_options.grandpa_voter {
+        log_callback.log(
+            smoldot_full_node::LogLevel::Warn,
+            "The GrandPa voter is experimental.".to_string(),
+        );
+    }
+
+    let json_rpc_uses_tls = cli_options.json_rpc_tls_certificate.is_some();
+
     let client_init_result = smoldot_full_node::start(smoldot_full_node::Config {
         chain: smoldot_full_node::ChainConfig {
             chain_spec: chain_spec.into(),
@@ -367,28 +498,158 @@ async fn run(cli_options: cli::CliOptionsRun) {
                 .iter()
                 .map(|cli::Bootnode { address, peer_id }| (peer_id.clone(), address.clone()))
                 .collect(),
+            bootnodes_providers: Vec::new(),
             keystore_memory: cli_options.keystore_memory,
             sqlite_database_path,
             sqlite_cache_size: cli_options.database_cache_size.0,
+            sqlite_options,
             keystore_path,
             json_rpc_listen: if let Some(address) = cli_options.json_rpc_address.0 {
                 Some(smoldot_full_node::JsonRpcListenConfig {
                     address,
+                    admin_address: cli_options.json_rpc_admin_address,
+                    health_address: cli_options.health_address,
                     max_json_rpc_clients: cli_options.json_rpc_max_clients,
+                    max_json_rpc_clients_per_ip: cli_options
+                        .json_rpc_max_clients_per_ip
+                        .unwrap_or(u32::MAX),
+                    max_requests_per_second_per_client: cli_options
+                        .json_rpc_max_requests_per_second
+                        .unwrap_or(u32::MAX),
+                    max_subscriptions_per_client: cli_options.json_rpc_max_subscriptions_per_client,
+                    max_pending_notifications_per_client: cli_options
+                        .json_rpc_max_pending_notifications,
+                    slow_subscriber_policy: match cli_options.json_rpc_slow_subscriber_policy {
+                        cli::SlowSubscriberPolicy::DropOldest => {
+                            smoldot_full_node::SlowSubscriberPolicy::DropOldestNotifications
+                        }
+                        cli::SlowSubscriberPolicy::Disconnect => {
+                            smoldot_full_node::SlowSubscriberPolicy::Disconnect
+                        }
+                    },
+                    multiplexed_subscriptions_buffer: cli_options.json_rpc_multiplex_subscriptions,
+                    tls: match (
+                        cli_options.json_rpc_tls_certificate,
+                        cli_options.json_rpc_tls_private_key,
+                    ) {
+                        (Some(certificate_chain_path), Some(private_key_path)) => {
+                            Some(smoldot_full_node::JsonRpcTlsConfig {
+                                certificate_chain_path,
+                                private_key_path,
+                            })
+                        }
+                        _ => None,
+                    },
+                    allowed_origins: if cli_options.json_rpc_allowed_origin.is_empty() {
+                        None
+                    } else {
+                        Some(cli_options.json_rpc_allowed_origin)
+                    },
+                    transactions_ban_duration: cli_options.json_rpc_transactions_ban_duration,
                 })
             } else {
                 None
             },
+            finalized_chain_only: cli_options.finalized_chain_only,
+            block_execution_profiling: cli_options.block_execution_profiling,
+            sync_mode: match cli_options.sync {
+                cli::SyncMode::Full => smoldot_full_node::SyncMode::Full,
+                cli::SyncMode::Warp => smoldot_full_node::SyncMode::Warp,
+            },
+            cross_check_warp_sync: cli_options.cross_check_warp_sync,
+            max_reorg_depth: cli_options.max_reorg_depth,
+            slot_drift_tolerance: cli_options.slot_drift_tolerance,
+            max_slot_lenience: cli_options.max_slot_lenience,
+            pruning: match cli_options.pruning {
+                cli::Pruning::Archive => smoldot_full_node::Pruning::Archive,
+                cli::Pruning::ArchiveCanonical => smoldot_full_node::Pruning::ArchiveCanonical,
+                cli::Pruning::KeepLast(n) => smoldot_full_node::Pruning::KeepLast(n),
+                cli::Pruning::KeepStates(n) => smoldot_full_node::Pruning::KeepStates(n),
+            },
+            peer_rotation_interval: cli_options.peer_rotation_interval,
+            bootstrap_fallback_delay: Some(cli_options.bootstrap_fallback_delay),
+            fallback_bootnodes: cli_options
+                .fallback_bootnode
+                .iter()
+                .map(|cli::Bootnode { address, peer_id }| (peer_id.clone(), address.clone()))
+                .collect(),
+            max_out_peers: cli_options.max_out_peers,
+            min_out_peers: cli_options.min_out_peers,
+            max_in_peers: cli_options.max_in_peers,
+            legacy_protocol_names: cli_options.legacy_protocol_names,
+            grandpa_voter: cli_options.grandpa_voter,
+            offchain_worker: cli_options.offchain_worker,
+            offchain_indexing: cli_options.offchain_indexing,
+            checkpoint_export: cli_options.checkpoint_export_path.map(|path| {
+                smoldot_full_node::CheckpointExportConfig {
+                    path,
+                    interval: cli_options.checkpoint_export_interval,
+                }
+            }),
+            database_backup: cli_options.database_backup_path.map(|path| {
+                smoldot_full_node::DatabaseBackupConfig {
+                    path,
+                    interval: cli_options.database_backup_interval,
+                }
+            }),
+            state_snapshot: cli_options.state_snapshot,
+            database_compaction_interval: cli_options.database_compaction_interval,
+            inherent_data_providers: Vec::new(),
+            report_equivocations: cli_options.report_equivocations,
+            authoring_slot_proportion: cli_options.authoring_slot_proportion,
+            max_authored_block_transactions_size: cli_options.max_block_transactions_size,
+            max_pool_transactions: cli_options.pool_limit,
+            max_pool_transactions_per_submitter: cli_options.pool_limit_per_submitter,
+            sync_limits: smoldot_full_node::SyncLimits {
+                download_ahead_blocks: cli_options.sync_download_ahead_blocks,
+                max_disjoint_headers: cli_options.sync_max_disjoint_headers,
+                max_requests_per_source: cli_options.sync_max_requests_per_peer,
+                parallel_block_ranges: cli_options.sync_parallel_block_ranges,
+            },
         },
         relay_chain,
         libp2p_key,
         listen_addresses: cli_options.listen_addr,
+        max_outbound_connections_per_subnet: Some(cli_options.max_outbound_connections_per_subnet),
+        max_inbound_connections_per_ip: Some(cli_options.max_inbound_connections_per_ip),
+        inbound_connections_ip_allowlist: cli_options.inbound_connections_ip_allowlist,
+        socks5_proxy: cli_options.socks5_proxy,
+        nat_port_mapping: cli_options.nat_port_mapping,
+        max_upload_bytes_per_sec: cli_options.max_upload_bytes_per_sec,
+        max_download_bytes_per_sec: cli_options.max_download_bytes_per_sec,
+        max_file_descriptors: cli_options.max_file_descriptors,
         tasks_executor: {
             let executor = executor.clone();
             Arc::new(move |task| executor.spawn(task).detach())
         },
         log_callback: log_callback.clone(),
+        progress_callback,
         jaeger_agent: cli_options.jaeger,
+        jaeger_sampling: {
+            let mut sampling = smoldot_full_node::JaegerSampling::default();
+            for cli::JaegerSamplingRate { protocol, rate } in &cli_options.jaeger_sampling {
+                match protocol {
+                    cli::JaegerProtocol::BlockAnnounces => sampling.block_announces = *rate,
+                    cli::JaegerProtocol::BlockRequests => sampling.block_requests = *rate,
+                }
+            }
+            sampling
+        },
+        runtime_execution_threads: cli_options.runtime_execution_threads.map(|num_threads| {
+            smoldot_full_node::RuntimeExecutionThreadsConfig {
+                num_threads,
+                cores: cli_options.runtime_execution_cores,
+            }
+        }),
+        max_json_rpc_runtime_calls: cli_options.json_rpc_max_runtime_calls,
+        genesis_build_threads: cli_options.genesis_build_threads,
+        quarantine_corrupted_database: cli_options.quarantine_corrupted_database,
+        compiled_runtimes_cache_path: base_storage_directory
+            .as_ref()
+            .map(|d| d.join("compiled_runtimes")),
+        block_export: None,
+        block_import_hook: None,
+        randomness_seed: cli_options.randomness_seed,
     })
     .await;
 
@@ -404,16 +665,57 @@ async fn run(cli_options: cli::CliOptionsRun) {
     };
 
     if let Some(addr) = client.json_rpc_server_addr() {
+        let scheme = if json_rpc_uses_tls { "wss" } else { "ws" };
         log_callback.log(
             smoldot_full_node::LogLevel::Info,
             format!(
                 "JSON-RPC server listening on {addr}. Visit \
-                <https://ipfs.io/ipns/dotapps.io/?rpc=ws%3A%2F%2F{addr}> in order to \
+                <https://ipfs.io/ipns/dotapps.io/?rpc={scheme}%3A%2F%2F{addr}> in order to \
                 interact with the node."
             ),
         );
     }
 
+    if let Some(addr) = client.json_rpc_admin_server_addr() {
+        log_callback.log(
+            smoldot_full_node::LogLevel::Info,
+            format!("JSON-RPC admin server listening on {addr}."),
+        );
+    }
+
+    if let Some(addr) = client.health_server_addr() {
+        log_callback.log(
+            smoldot_full_node::LogLevel::Info,
+            format!("Health server listening on {addr}."),
+        );
+    }
+
+    // Spawn the task importing the blocks of `--import-blocks`, if any.
+    let client = Arc::new(client);
+    let import_blocks_task = cli_options.import_blocks.clone().map(|path| {
+        let client = client.clone();
+        let log_callback = log_callback.clone();
+        executor.spawn(async move {
+            log_callback.log(
+                smoldot_full_node::LogLevel::Info,
+                format!("Importing blocks from {}.", path.display()),
+            );
+            match client.import_blocks(path).await {
+                Ok(report) => log_callback.log(
+                    smoldot_full_node::LogLevel::Info,
+                    format!(
+                        "Finished importing blocks: {} queued for verification, {} already known.",
+                        report.num_queued, report.num_already_known
+                    ),
+                ),
+                Err(err) => log_callback.log(
+                    smoldot_full_node::LogLevel::Error,
+                    format!("Failed to import blocks: {err}"),
+                ),
+            }
+        })
+    });
+
     // Starting from here, a SIGINT (or equivalent) handler is set up. If the user does Ctrl+C,
     // an event will be triggered on `ctrlc_detected`.
     // This should be performed after all the expensive initialization is done, as otherwise these
@@ -462,6 +764,7 @@ async fn run(cli_options: cli::CliOptionsRun) {
                 // informant, and the informant will then print itself below, which is
                 // a fine behaviour.
                 let sync_state = client.sync_state().await;
+                let parachain_inclusion = client.parachain_inclusion().await;
                 eprint!(
                     "{}\r",
                     smoldot::informant::InformantLine {
@@ -471,6 +774,14 @@ async fn run(cli_options: cli::CliOptionsRun) {
                             |relay_sync_state| smoldot::informant::RelayChain {
                                 chain_name: relay_chain_name.as_ref().unwrap(),
                                 best_number: relay_sync_state.best_block_number,
+                                last_inclusion_number: parachain_inclusion
+                                    .as_ref()
+                                    .map(|inclusion| inclusion.relay_block_number),
+                                candidates_backlog: parachain_inclusion.as_ref().map(|inclusion| {
+                                    sync_state
+                                        .best_block_number
+                                        .saturating_sub(inclusion.para_block_number)
+                                }),
                             }
                         ),
                         max_line_width: terminal_size::terminal_size()
@@ -500,6 +811,7 @@ async fn run(cli_options: cli::CliOptionsRun) {
     // After `ctrlc_detected` has triggered, we destroy `main_task`, which cancels it and destroys
     // the smoldot client.
     drop::<smol::Task<_>>(main_task);
+    drop::<Option<smol::Task<_>>>(import_blocks_task);
 
     // TODO: consider running the executor until all tasks shut down gracefully; unfortunately this currently hangs
 }
@@ -0,0 +1,236 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Export of the imported and finalized blocks to a sink provided by the embedder.
+//!
+//! The sink typically forwards the blocks to a message queue (Kafka, NATS, etc.), which makes it
+//! possible to build pipelines without polling the JSON-RPC server.
+//!
+//! Blocks are exported in the order in which the consensus service reports them, and a block is
+//! always exported before it is reported as finalized. The export is at-least-once: if the
+//! sink is too slow and the subscription to the consensus service gets interrupted, the
+//! non-finalized blocks are exported again after re-subscribing.
+
+use futures_util::future;
+use smol::stream::StreamExt as _;
+use std::{num::NonZeroUsize, sync::Arc};
+
+use crate::{consensus_service, database_thread, LogCallback, LogLevel};
+
+/// Destination of the exported blocks. See [`crate::Config::block_export`].
+///
+/// Implemented on closures.
+pub trait BlockExportSink {
+    /// Pushes an event to the sink. The next event is only pushed after the returned future
+    /// has finished.
+    fn push(&self, event: BlockExportEvent) -> future::BoxFuture<'static, ()>;
+}
+
+impl<T: ?Sized + Fn(BlockExportEvent) -> future::BoxFuture<'static, ()>> BlockExportSink for T {
+    fn push(&self, event: BlockExportEvent) -> future::BoxFuture<'static, ()> {
+        (*self)(event)
+    }
+}
+
+/// See [`crate::Config::block_export`].
+#[derive(Clone)]
+pub struct BlockExportConfig {
+    /// Sink the blocks are pushed to.
+    pub sink: Arc<dyn BlockExportSink + Send + Sync>,
+    /// If `true`, the changes to the storage performed by each block are included in the
+    /// exported blocks. Significantly increases the size of the exported data.
+    pub include_storage_diff: bool,
+}
+
+/// Event pushed to a [`BlockExportSink`].
+#[derive(Debug, Clone)]
+pub enum BlockExportEvent {
+    /// A new block has been imported.
+    Imported(ExportedBlock),
+    /// Blocks have been finalized.
+    Finalized {
+        /// Hashes of the blocks that have been finalized, in decreasing block number. The first
+        /// block in this list is the new finalized block.
+        finalized_blocks_newest_to_oldest: Vec<[u8; 32]>,
+        /// Hashes of the blocks that are no longer part of the canonical chain.
+        pruned_blocks_hashes: Vec<[u8; 32]>,
+    },
+}
+
+/// Block exported through [`BlockExportEvent::Imported`].
+#[derive(Debug, Clone)]
+pub struct ExportedBlock {
+    /// Hash of the block.
+    pub hash: [u8; 32],
+    /// Hash of the parent of the block.
+    pub parent_hash: [u8; 32],
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+    /// List of SCALE-encoded extrinsics of the block. `None` if the body isn't available.
+    pub body: Option<Vec<Vec<u8>>>,
+    /// `true` if the block is the new best block.
+    pub is_new_best: bool,
+    /// Changes to the storage of the main trie performed by the block, as a list of keys and
+    /// new values (`None` meaning that the key has been removed).
+    ///
+    /// `None` if [`BlockExportConfig::include_storage_diff`] is `false`, or if the changes
+    /// aren't known, which is the case for the blocks that were already known when the export
+    /// started.
+    pub storage_diff: Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>,
+}
+
+impl BlockExportEvent {
+    /// Serializes the event as JSON, with all the binary fields encoded as hexadecimal strings
+    /// prefixed with `0x`.
+    pub fn to_json(&self) -> String {
+        fn hex(bytes: &[u8]) -> String {
+            format!("0x{}", hex::encode(bytes))
+        }
+
+        let value = match self {
+            BlockExportEvent::Imported(block) => serde_json::json!({
+                "event": "imported",
+                "hash": hex(&block.hash),
+                "parentHash": hex(&block.parent_hash),
+                "header": hex(&block.scale_encoded_header),
+                "body": block.body.as_ref().map(|body| {
+                    body.iter().map(|tx| hex(&tx[..])).collect::<Vec<_>>()
+                }),
+                "isNewBest": block.is_new_best,
+                "storageDiff": block.storage_diff.as_ref().map(|diff| {
+                    diff.iter()
+                        .map(|(key, value)| (hex(key), value.as_ref().map(|v| hex(&v[..]))))
+                        .collect::<Vec<_>>()
+                }),
+            }),
+            BlockExportEvent::Finalized {
+                finalized_blocks_newest_to_oldest,
+                pruned_blocks_hashes,
+            } => serde_json::json!({
+                "event": "finalized",
+                "finalizedBlocksHashes": finalized_blocks_newest_to_oldest
+                    .iter()
+                    .map(|h| hex(&h[..]))
+                    .collect::<Vec<_>>(),
+                "prunedBlocksHashes": pruned_blocks_hashes
+                    .iter()
+                    .map(|h| hex(&h[..]))
+                    .collect::<Vec<_>>(),
+            }),
+        };
+
+        value.to_string()
+    }
+}
+
+/// Runs the task that exports the blocks of the given consensus service. Never returns.
+pub async fn run(
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    database: Arc<database_thread::DatabaseThread>,
+    config: BlockExportConfig,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    loop {
+        let subscribe_all = consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            export_block(&database, &config, block.clone(), None).await;
+            consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        while let Some(notification) = new_blocks.next().await {
+            match notification {
+                consensus_service::Notification::Block {
+                    block,
+                    storage_changes,
+                } => {
+                    let storage_diff = if config.include_storage_diff {
+                        Some(
+                            storage_changes
+                                .main_trie_storage_changes_iter_unordered()
+                                .map(|(key, value)| (key.to_vec(), value.map(|v| v.to_vec())))
+                                .collect(),
+                        )
+                    } else {
+                        None
+                    };
+
+                    export_block(&database, &config, block.clone(), storage_diff).await;
+                    consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks_hashes,
+                    ..
+                } => {
+                    config
+                        .sink
+                        .push(BlockExportEvent::Finalized {
+                            finalized_blocks_newest_to_oldest,
+                            pruned_blocks_hashes,
+                        })
+                        .await;
+                }
+            }
+        }
+
+        // The consensus service has killed the subscription, most likely because the sink is
+        // too slow. Subscribe again.
+        log_callback.log(LogLevel::Debug, "block-export-resubscribe".to_string());
+    }
+}
+
+async fn export_block(
+    database: &database_thread::DatabaseThread,
+    config: &BlockExportConfig,
+    block: consensus_service::BlockNotification,
+    storage_diff: Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>,
+) {
+    let block_hash = block.block_hash;
+    let body = database
+        .with_database(move |database| {
+            database
+                .block_extrinsics(&block_hash)
+                .ok()
+                .flatten()
+                .map(|body| body.collect::<Vec<_>>())
+        })
+        .await;
+
+    config
+        .sink
+        .push(BlockExportEvent::Imported(ExportedBlock {
+            hash: block.block_hash,
+            parent_hash: block.parent_hash,
+            scale_encoded_header: block.scale_encoded_header,
+            body,
+            is_new_best: block.is_new_best,
+            storage_diff,
+        }))
+        .await;
+}
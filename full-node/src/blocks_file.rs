@@ -0,0 +1,492 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Import and export of blocks in the file format of the `export-blocks` and `import-blocks`
+//! commands of Substrate.
+//!
+//! Only the binary format of these commands is supported, not the JSON one. A file contains the
+//! number of blocks in the file encoded as a little endian 64 bits number, followed with the
+//! SCALE-encoded blocks and their justifications, one after the other, in increasing height.
+//!
+//! When importing, the blocks are queued in the consensus service (see
+//! [`consensus_service::ConsensusService::queue_block_import`]), which verifies and executes them
+//! exactly like blocks downloaded from the network. The import is therefore as slow as a full
+//! sync, but doesn't require any networking peer.
+//!
+//! When exporting, the blocks of the finalized chain are read from the database, alongside with
+//! the justifications that the node has verified. Blocks finalized through a GrandPa commit
+//! message, or whose justification hasn't been downloaded, are exported without any
+//! justification.
+
+use smol::io::AsyncReadExt as _;
+use smoldot::{database::full_sqlite, header};
+use std::{
+    cmp,
+    ffi::OsString,
+    fs,
+    io::{self, Write as _},
+    ops,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{consensus_service, database_thread, util::decode_scale_compact_usize};
+
+/// Number of blocks read from the database at once when exporting. The database is locked while
+/// blocks are read, and blocks are read in small batches in order to not block other database
+/// accesses for too long.
+const EXPORT_BLOCKS_PER_BATCH: u64 = 64;
+
+/// Number of bytes read from the file at once.
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Maximum size of a single block in the file. Blocks are decoded from memory, and the file is
+/// considered as invalid if a block can't be decoded after having read this number of bytes.
+const MAX_BLOCK_SIZE: usize = 128 * 1024 * 1024;
+
+/// Delay before trying again to queue a block when the queue of the consensus service is full,
+/// and between two checks of whether the queue has been emptied.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Returned by [`import`] in case of success.
+#[derive(Debug, Clone)]
+pub struct ImportBlocksReport {
+    /// Number of blocks that have been queued for verification.
+    pub num_queued: u64,
+    /// Number of blocks that were skipped because they were already known, for example because
+    /// they were already finalized.
+    pub num_already_known: u64,
+}
+
+/// Error potentially returned by [`import`].
+#[derive(Debug, derive_more::Display)]
+pub enum ImportBlocksError {
+    /// Error while reading the file.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The file doesn't start with the number of blocks it contains.
+    #[display(fmt = "The file is too short")]
+    MissingBlocksCount,
+    /// Failed to decode a block of the file, or the file is truncated.
+    #[display(fmt = "Failed to decode the block at index {index} of the file")]
+    InvalidBlock {
+        /// Index of the block within the file.
+        index: u64,
+    },
+    /// The header of a block of the file is invalid.
+    #[display(fmt = "Invalid header for the block at index {index} of the file: {error}")]
+    InvalidHeader {
+        /// Index of the block within the file.
+        index: u64,
+        /// Error that happened.
+        error: header::Error,
+    },
+    /// The parent of a block of the file is unknown. The blocks must be imported in order, and
+    /// the first block of the file must be a child of a block known by the node.
+    #[display(fmt = "The parent of the block at index {index} of the file is unknown")]
+    UnknownParent {
+        /// Index of the block within the file.
+        index: u64,
+    },
+}
+
+/// Error potentially returned by [`export`] and [`export_blocks_from_database_file`].
+#[derive(Debug, derive_more::Display)]
+pub enum ExportBlocksError {
+    /// Error while writing the file.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// Failed to open the database.
+    #[display(fmt = "Failed to open the database: {_0}")]
+    DatabaseOpen(full_sqlite::InternalError),
+    /// The database file doesn't exist or is empty.
+    DatabaseEmpty,
+    /// The database is corrupted.
+    #[display(fmt = "Database is corrupted: {_0}")]
+    DatabaseCorrupted(full_sqlite::CorruptedError),
+    /// The first block of the range is above the finalized block.
+    #[display(fmt = "Block #{_0} isn't finalized")]
+    NotFinalized(u64),
+    /// The body of a block of the range has been pruned from the database.
+    #[display(fmt = "The body of block #{_0} has been pruned")]
+    BodyPruned(u64),
+}
+
+/// Writes the blocks of the finalized chain whose height is within the given range to the file
+/// at the given path. If the end of the range is above the finalized block, the blocks are
+/// exported up to the finalized block. Returns the number of blocks that have been exported.
+///
+/// The file is written to a temporary file which is then renamed, meaning that the file at the
+/// given path is only created once all the blocks have been exported.
+pub async fn export(
+    database: &database_thread::DatabaseThread,
+    block_number_bytes: usize,
+    path: &Path,
+    range: ops::RangeInclusive<u64>,
+) -> Result<u64, ExportBlocksError> {
+    let (first, last) = database
+        .with_database(move |database| export_range(database, block_number_bytes, range))
+        .await?;
+    let num_blocks = (last + 1).saturating_sub(first);
+
+    let tmp_path = tmp_path(path);
+    let mut file = smol::unblock({
+        let tmp_path = tmp_path.clone();
+        move || {
+            let mut file = io::BufWriter::new(fs::File::create(tmp_path)?);
+            file.write_all(&num_blocks.to_le_bytes())?;
+            Ok(file)
+        }
+    })
+    .await
+    .map_err(ExportBlocksError::Io)?;
+
+    let mut batch_first = first;
+    while batch_first <= last {
+        let batch_last = cmp::min(last, batch_first + EXPORT_BLOCKS_PER_BATCH - 1);
+        let encoded = database
+            .with_database(move |database| encode_blocks(database, batch_first..=batch_last))
+            .await?;
+        file = smol::unblock(move || {
+            file.write_all(&encoded)?;
+            Ok(file)
+        })
+        .await
+        .map_err(ExportBlocksError::Io)?;
+        batch_first = batch_last + 1;
+    }
+
+    let path = path.to_owned();
+    smol::unblock(move || {
+        file.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, &path)
+    })
+    .await
+    .map_err(ExportBlocksError::Io)?;
+
+    Ok(num_blocks)
+}
+
+/// Similar to [`export`], but opens the database file at the given path instead of using the
+/// database of a running node.
+///
+/// The database must not be in use by a running node. This function is blocking.
+pub fn export_blocks_from_database_file(
+    database_path: &Path,
+    block_number_bytes: usize,
+    path: &Path,
+    range: ops::RangeInclusive<u64>,
+) -> Result<u64, ExportBlocksError> {
+    let database = match full_sqlite::open(full_sqlite::Config {
+        block_number_bytes,
+        cache_size: 16 * 1024 * 1024,
+        ty: full_sqlite::ConfigTy::Disk {
+            path: database_path,
+            memory_map_size: 0,
+            journal_mode: full_sqlite::JournalMode::Wal,
+            synchronous: full_sqlite::Synchronous::Normal,
+            page_size: None,
+            shared_access: false,
+        },
+    }) {
+        Ok(full_sqlite::DatabaseOpen::Open(database)) => database,
+        Ok(full_sqlite::DatabaseOpen::Empty(_)) => return Err(ExportBlocksError::DatabaseEmpty),
+        Err(err) => return Err(ExportBlocksError::DatabaseOpen(err)),
+    };
+
+    let (first, last) = export_range(&database, block_number_bytes, range)?;
+    let num_blocks = (last + 1).saturating_sub(first);
+
+    let tmp_path = tmp_path(path);
+    let mut file = io::BufWriter::new(fs::File::create(&tmp_path).map_err(ExportBlocksError::Io)?);
+    file.write_all(&num_blocks.to_le_bytes())
+        .map_err(ExportBlocksError::Io)?;
+    let mut batch_first = first;
+    while batch_first <= last {
+        let batch_last = cmp::min(last, batch_first + EXPORT_BLOCKS_PER_BATCH - 1);
+        file.write_all(&encode_blocks(&database, batch_first..=batch_last)?)
+            .map_err(ExportBlocksError::Io)?;
+        batch_first = batch_last + 1;
+    }
+    file.into_inner()
+        .map_err(|err| ExportBlocksError::Io(err.into_error()))?
+        .sync_all()
+        .map_err(ExportBlocksError::Io)?;
+    fs::rename(&tmp_path, path).map_err(ExportBlocksError::Io)?;
+
+    Ok(num_blocks)
+}
+
+/// Returns the first and last heights of the blocks to export, after clamping the given range
+/// to the finalized block.
+fn export_range(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_number_bytes: usize,
+    range: ops::RangeInclusive<u64>,
+) -> Result<(u64, u64), ExportBlocksError> {
+    let finalized_block_hash = database
+        .finalized_block_hash()
+        .map_err(ExportBlocksError::DatabaseCorrupted)?;
+    let finalized_block_header = database
+        .block_scale_encoded_header(&finalized_block_hash)
+        .map_err(ExportBlocksError::DatabaseCorrupted)?
+        .ok_or(ExportBlocksError::DatabaseCorrupted(
+            full_sqlite::CorruptedError::MissingBlockHeader,
+        ))?;
+    let finalized_block_number = header::decode(&finalized_block_header, block_number_bytes)
+        .map_err(|err| {
+            ExportBlocksError::DatabaseCorrupted(full_sqlite::CorruptedError::BlockHeaderCorrupted(
+                err,
+            ))
+        })?
+        .number;
+
+    if *range.start() > finalized_block_number {
+        return Err(ExportBlocksError::NotFinalized(*range.start()));
+    }
+
+    Ok((
+        *range.start(),
+        cmp::min(*range.end(), finalized_block_number),
+    ))
+}
+
+/// Reads the blocks of the finalized chain whose height is within the given range from the
+/// database, and returns their encoding in the file format.
+fn encode_blocks(
+    database: &full_sqlite::SqliteFullDatabase,
+    range: ops::RangeInclusive<u64>,
+) -> Result<Vec<u8>, ExportBlocksError> {
+    let mut out = Vec::new();
+
+    for number in range {
+        let block_hash = database
+            .best_block_hash_by_number(number)
+            .map_err(ExportBlocksError::DatabaseCorrupted)?
+            .ok_or(ExportBlocksError::DatabaseCorrupted(
+                full_sqlite::CorruptedError::MissingBlockHeader,
+            ))?;
+        let header = database
+            .block_scale_encoded_header(&block_hash)
+            .map_err(ExportBlocksError::DatabaseCorrupted)?
+            .ok_or(ExportBlocksError::DatabaseCorrupted(
+                full_sqlite::CorruptedError::MissingBlockHeader,
+            ))?;
+        let extrinsics = database
+            .block_extrinsics(&block_hash)
+            .map_err(ExportBlocksError::DatabaseCorrupted)?
+            .ok_or(ExportBlocksError::BodyPruned(number))?;
+
+        // The extrinsics are stored with their length prefix, and can be written as they are.
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&encode_scale_compact_usize(extrinsics.len()));
+        for extrinsic in extrinsics {
+            out.extend_from_slice(&extrinsic);
+        }
+        let justifications = database
+            .block_justifications(&block_hash)
+            .map_err(ExportBlocksError::DatabaseCorrupted)?
+            .unwrap_or_default();
+        if justifications.is_empty() {
+            out.push(0);
+        } else {
+            out.push(1);
+            out.extend_from_slice(&encode_scale_compact_usize(justifications.len()));
+            for (engine_id, justification) in justifications {
+                out.extend_from_slice(&engine_id);
+                out.extend_from_slice(&encode_scale_compact_usize(justification.len()));
+                out.extend_from_slice(&justification);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Returns the path of the temporary file that is renamed to the given path once the export is
+/// finished.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = OsString::from(path.as_os_str());
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
+
+/// Reads the blocks of the file at the given path and queues them in the consensus service.
+///
+/// Returns once all the blocks of the file have been passed to the verification. Verification
+/// failures are reported through the logs of the consensus service.
+pub async fn import(
+    consensus_service: &consensus_service::ConsensusService,
+    path: &Path,
+) -> Result<ImportBlocksReport, ImportBlocksError> {
+    let mut file = smol::fs::File::open(path)
+        .await
+        .map_err(ImportBlocksError::Io)?;
+
+    let mut blocks_count = [0; 8];
+    file.read_exact(&mut blocks_count)
+        .await
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => ImportBlocksError::MissingBlocksCount,
+            _ => ImportBlocksError::Io(err),
+        })?;
+    let blocks_count = u64::from_le_bytes(blocks_count);
+
+    let block_number_bytes = consensus_service.block_number_bytes();
+    let mut report = ImportBlocksReport {
+        num_queued: 0,
+        num_already_known: 0,
+    };
+
+    // Bytes read from the file. Only the bytes starting at `buffer_start` haven't been decoded
+    // yet.
+    let mut buffer = Vec::with_capacity(READ_CHUNK_SIZE);
+    let mut buffer_start = 0;
+    let mut end_of_file = false;
+
+    for index in 0..blocks_count {
+        let (header, extrinsics, justifications) = loop {
+            if let Some((block, size)) =
+                decode_signed_block(&buffer[buffer_start..], block_number_bytes)
+            {
+                buffer_start += size;
+                break block;
+            }
+
+            if end_of_file || buffer.len() - buffer_start >= MAX_BLOCK_SIZE {
+                return Err(ImportBlocksError::InvalidBlock { index });
+            }
+
+            buffer.drain(..buffer_start);
+            buffer_start = 0;
+            let previous_len = buffer.len();
+            buffer.resize(previous_len + READ_CHUNK_SIZE, 0);
+            let num_read = file
+                .read(&mut buffer[previous_len..])
+                .await
+                .map_err(ImportBlocksError::Io)?;
+            buffer.truncate(previous_len + num_read);
+            end_of_file = num_read == 0;
+        };
+
+        loop {
+            match consensus_service
+                .queue_block_import(header.clone(), extrinsics.clone(), justifications.clone())
+                .await
+            {
+                Ok(consensus_service::QueueBlockImportOutcome::Queued) => {
+                    report.num_queued += 1;
+                    break;
+                }
+                Ok(consensus_service::QueueBlockImportOutcome::AlreadyKnown) => {
+                    report.num_already_known += 1;
+                    break;
+                }
+                Err(consensus_service::QueueBlockImportError::QueueFull) => {
+                    smol::Timer::after(QUEUE_POLL_INTERVAL).await;
+                }
+                Err(consensus_service::QueueBlockImportError::InvalidHeader(error)) => {
+                    return Err(ImportBlocksError::InvalidHeader { index, error });
+                }
+                Err(consensus_service::QueueBlockImportError::UnknownParent) => {
+                    return Err(ImportBlocksError::UnknownParent { index });
+                }
+            }
+        }
+    }
+
+    while consensus_service.block_import_queue_len().await != 0 {
+        smol::Timer::after(QUEUE_POLL_INTERVAL).await;
+    }
+
+    Ok(report)
+}
+
+/// A block decoded by [`decode_signed_block`]. Contains the SCALE-encoded header, the list of
+/// SCALE-encoded extrinsics, and the list of justifications and their consensus engine.
+type DecodedBlock = (Vec<u8>, Vec<Vec<u8>>, Vec<([u8; 4], Vec<u8>)>);
+
+/// Decodes a SCALE-encoded `SignedBlock` found at the start of the given slice. Returns the
+/// decoded block and its size in bytes.
+///
+/// Returns `None` if the slice is too short to contain the entire block or if the block is
+/// invalid. These two situations can't be distinguished as the size of the block isn't known
+/// in advance.
+fn decode_signed_block(bytes: &[u8], block_number_bytes: usize) -> Option<(DecodedBlock, usize)> {
+    let (_, mut remain) = header::decode_partial(bytes, block_number_bytes).ok()?;
+    let header = bytes[..bytes.len() - remain.len()].to_vec();
+
+    // Contrary to the header, the extrinsics are kept with their length prefix, as this is how
+    // they are stored in block bodies.
+    let (num_extrinsics, after) = decode_scale_compact_usize(remain)?;
+    remain = after;
+    let mut extrinsics = Vec::new();
+    for _ in 0..num_extrinsics {
+        let (extrinsic_len, after) = decode_scale_compact_usize(remain)?;
+        let total_len = (remain.len() - after.len()).checked_add(extrinsic_len)?;
+        extrinsics.push(remain.get(..total_len)?.to_vec());
+        remain = &remain[total_len..];
+    }
+
+    let mut justifications = Vec::new();
+    match remain.first()? {
+        0 => remain = &remain[1..],
+        1 => {
+            let (num_justifications, after) = decode_scale_compact_usize(&remain[1..])?;
+            remain = after;
+            for _ in 0..num_justifications {
+                let engine_id = <[u8; 4]>::try_from(remain.get(..4)?).unwrap();
+                let (justification_len, after) = decode_scale_compact_usize(&remain[4..])?;
+                justifications.push((engine_id, after.get(..justification_len)?.to_vec()));
+                remain = &after[justification_len..];
+            }
+        }
+        _ => return None,
+    }
+
+    Some((
+        (header, extrinsics, justifications),
+        bytes.len() - remain.len(),
+    ))
+}
+
+/// Returns the SCALE-compact encoding of the given number.
+fn encode_scale_compact_usize(value: usize) -> Vec<u8> {
+    let value = u64::try_from(value).unwrap();
+    if value < 1 << 6 {
+        vec![u8::try_from(value << 2).unwrap()]
+    } else if value < 1 << 14 {
+        u16::try_from((value << 2) | 0b01)
+            .unwrap()
+            .to_le_bytes()
+            .to_vec()
+    } else if value < 1 << 30 {
+        u32::try_from((value << 2) | 0b10)
+            .unwrap()
+            .to_le_bytes()
+            .to_vec()
+    } else {
+        let bytes = value.to_le_bytes();
+        let num_bytes = bytes.len() - bytes.iter().rev().take_while(|b| **b == 0).count();
+        let mut out = Vec::with_capacity(1 + num_bytes);
+        out.push(u8::try_from(((num_bytes - 4) << 2) | 0b11).unwrap());
+        out.extend_from_slice(&bytes[..num_bytes]);
+        out
+    }
+}
@@ -0,0 +1,92 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Downloading a chain specification from an HTTP(S) server.
+//!
+//! The chain specification is pinned by its hash, meaning that the server (or anything in
+//! between the server and the node) can't substitute it with a different one.
+
+use std::io::{self, Read as _};
+
+/// Maximum size of a chain specification, in bytes. Chain specifications containing the genesis
+/// storage of a large chain can weigh a few dozen megabytes.
+const MAX_CHAIN_SPEC_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Downloads the chain specification found at the given URL, and verifies that its 256 bits
+/// BLAKE2 hash is equal to `expected_hash`.
+///
+/// The expected hash can be obtained with the `blake2-256bits-hash` command of the full node
+/// binary.
+///
+/// On success, returns the content of the chain specification, which can then be passed to
+/// [`crate::ChainConfig::chain_spec`].
+pub async fn fetch_chain_spec(
+    url: &str,
+    expected_hash: &[u8; 32],
+) -> Result<Vec<u8>, FetchChainSpecError> {
+    let url = url.to_owned();
+    let chain_spec = smol::unblock(move || {
+        let response = ureq::get(&url)
+            .call()
+            .map_err(|err| FetchChainSpecError::Request(Box::new(err)))?;
+
+        let mut chain_spec = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_CHAIN_SPEC_SIZE + 1)
+            .read_to_end(&mut chain_spec)
+            .map_err(FetchChainSpecError::Read)?;
+        if u64::try_from(chain_spec.len()).unwrap_or(u64::MAX) > MAX_CHAIN_SPEC_SIZE {
+            return Err(FetchChainSpecError::TooLarge);
+        }
+
+        Ok(chain_spec)
+    })
+    .await?;
+
+    let actual_hash = blake2_rfc::blake2b::blake2b(32, &[], &chain_spec);
+    if actual_hash.as_bytes() != expected_hash {
+        return Err(FetchChainSpecError::HashMismatch {
+            actual_hash: <[u8; 32]>::try_from(actual_hash.as_bytes()).unwrap(),
+        });
+    }
+
+    Ok(chain_spec)
+}
+
+/// Error potentially returned by [`fetch_chain_spec`].
+#[derive(Debug, derive_more::Display)]
+pub enum FetchChainSpecError {
+    /// Failed to send the request or the server returned an error status code.
+    #[display(fmt = "{_0}")]
+    Request(Box<ureq::Error>),
+    /// Error while reading the body of the response.
+    #[display(fmt = "Failed to read response: {_0}")]
+    Read(io::Error),
+    /// The chain specification exceeds the maximum allowed size.
+    #[display(fmt = "Chain specification is too large")]
+    TooLarge,
+    /// The hash of the downloaded chain specification doesn't match the expected one.
+    #[display(
+        fmt = "Chain specification hash mismatch; actual hash: 0x{}",
+        "hex::encode(actual_hash)"
+    )]
+    HashMismatch {
+        /// 256 bits BLAKE2 hash of what has been downloaded.
+        actual_hash: [u8; 32],
+    },
+}
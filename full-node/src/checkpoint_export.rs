@@ -0,0 +1,119 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Periodic export of a checkpoint of the chain to a file.
+//!
+//! The checkpoint contains the latest finalized block of the database and the consensus-related
+//! information necessary to verify its descendants, in the same format as the responses to
+//! checkpoint requests (see [`smoldot::database::finalized_serialize`]). Light clients can be
+//! bootstrapped from this checkpoint, for example by serving the file through an HTTP server,
+//! rather than from the possibly very old checkpoint found in the chain specification.
+//!
+//! The file is only rewritten when the finalized block has changed since the previous export.
+
+use smoldot::database::{finalized_serialize, full_sqlite};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{database_thread, LogCallback, LogLevel};
+
+/// See [`crate::ChainConfig::checkpoint_export`].
+#[derive(Debug, Clone)]
+pub struct CheckpointExportConfig {
+    /// Path of the file to write the checkpoint to. The file is replaced atomically, meaning
+    /// that readers never observe a partially-written checkpoint.
+    pub path: PathBuf,
+    /// Delay between two exports of the checkpoint.
+    pub interval: Duration,
+}
+
+/// Runs the checkpoint export task. Never returns.
+pub async fn run(
+    database: Arc<database_thread::DatabaseThread>,
+    block_number_bytes: usize,
+    config: CheckpointExportConfig,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    // Hash of the finalized block of the latest checkpoint successfully written.
+    let mut last_exported = None;
+
+    loop {
+        let result = database
+            .with_database(move |database| {
+                let finalized_block_hash = database.finalized_block_hash()?;
+                let chain_information = database.to_chain_information(&finalized_block_hash)?;
+                let finalized_block_number =
+                    chain_information.as_ref().finalized_block_header.number;
+                Ok::<_, full_sqlite::StorageAccessError>((
+                    finalized_block_hash,
+                    finalized_block_number,
+                    finalized_serialize::encode_chain(&chain_information, block_number_bytes),
+                ))
+            })
+            .await;
+
+        match result {
+            Ok((finalized_block_hash, _, _)) if last_exported == Some(finalized_block_hash) => {}
+            Ok((finalized_block_hash, finalized_block_number, checkpoint)) => {
+                let path = config.path.clone();
+                match smol::unblock(move || write_atomically(&path, checkpoint.as_bytes())).await {
+                    Ok(()) => {
+                        last_exported = Some(finalized_block_hash);
+                        log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "checkpoint-exported; path={}; finalized_block_number={}",
+                                config.path.display(),
+                                finalized_block_number
+                            ),
+                        );
+                    }
+                    Err(err) => {
+                        log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "checkpoint-export-error; path={}; error={}",
+                                config.path.display(),
+                                err
+                            ),
+                        );
+                    }
+                }
+            }
+            Err(err) => {
+                log_callback.log(
+                    LogLevel::Warn,
+                    format!("checkpoint-export-error; database_error={}", err),
+                );
+            }
+        }
+
+        smol::Timer::after(config.interval).await;
+    }
+}
+
+/// Writes the given data to a temporary file then renames it to the given path.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), io::Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,243 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Events about the chain and the networking reported through [`crate::Client::events`].
+//!
+//! Each call to [`crate::Client::events`] registers a new subscriber. Events are sent to all the
+//! subscribers through a bounded channel. A subscriber that doesn't pull its events quickly
+//! enough and whose channel is full is removed, in which case its stream ends after the events
+//! that were already buffered, similar to the subscriptions to the consensus service.
+
+use hashbrown::HashMap;
+use smol::stream::StreamExt as _;
+use smoldot::{executor, header, libp2p::PeerId};
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use crate::consensus_service;
+
+/// Number of events that can be buffered for each subscriber before it is removed.
+const SUBSCRIBER_BUFFER_SIZE: usize = 256;
+
+/// Event yielded by the stream returned by [`crate::Client::events`].
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// The best block of the chain has changed.
+    NewBestBlock {
+        /// Hash of the new best block.
+        hash: [u8; 32],
+        /// Height of the new best block.
+        number: u64,
+    },
+    /// A new block has been finalized. Its ancestors are now also finalized, but no event is
+    /// generated for them.
+    Finalized {
+        /// Hash of the new finalized block.
+        hash: [u8; 32],
+        /// Height of the new finalized block.
+        number: u64,
+    },
+    /// A block whose runtime is different from the runtime of its parent has been imported.
+    /// The block isn't necessarily part of the best chain.
+    RuntimeUpgrade {
+        /// Hash of the block containing the new runtime.
+        block_hash: [u8; 32],
+        /// Version of the new runtime.
+        runtime_version: executor::CoreVersion,
+    },
+    /// A gossip link with a peer of the chain has been established.
+    PeerConnected {
+        /// Identity of the peer.
+        peer_id: PeerId,
+        /// Height of the best block of the peer at the time of the connection.
+        best_block_number: u64,
+    },
+    /// A gossip link with a peer of the chain has been closed.
+    PeerDisconnected {
+        /// Identity of the peer.
+        peer_id: PeerId,
+    },
+}
+
+/// List of the subscribers to the events.
+pub struct ClientEvents {
+    subscribers: Mutex<Vec<async_channel::Sender<ClientEvent>>>,
+}
+
+impl ClientEvents {
+    /// Creates a new [`ClientEvents`] without any subscriber.
+    pub fn new() -> Self {
+        ClientEvents {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers a new subscriber and returns the receiving side of its channel.
+    pub fn subscribe(&self) -> async_channel::Receiver<ClientEvent> {
+        let (tx, rx) = async_channel::bounded(SUBSCRIBER_BUFFER_SIZE);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Sends the given event to all the subscribers. Subscribers whose channel is full or closed
+    /// are removed.
+    pub fn broadcast(&self, event: ClientEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+    }
+}
+
+/// Runs the task that reports the blocks-related events of the given consensus service to the
+/// subscribers. Never returns.
+pub async fn run(
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    events: Arc<ClientEvents>,
+) {
+    let block_number_bytes = consensus_service.block_number_bytes();
+
+    // Latest best and finalized blocks reported to the subscribers. Kept between subscriptions
+    // to the consensus service, so that the same blocks aren't reported again.
+    let mut best_block_hash = None;
+    let mut finalized_block_hash = None;
+
+    loop {
+        let subscribe_all = consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Height of each non-finalized block and of the current finalized block.
+        let mut block_numbers: HashMap<[u8; 32], u64, fnv::FnvBuildHasher> =
+            HashMap::with_capacity_and_hasher(
+                subscribe_all.non_finalized_blocks_ancestry_order.len() + 1,
+                Default::default(),
+            );
+
+        let finalized_block_number = decode_number(
+            &subscribe_all.finalized_block_scale_encoded_header,
+            block_number_bytes,
+        );
+        block_numbers.insert(subscribe_all.finalized_block_hash, finalized_block_number);
+        if finalized_block_hash != Some(subscribe_all.finalized_block_hash) {
+            finalized_block_hash = Some(subscribe_all.finalized_block_hash);
+            events.broadcast(ClientEvent::Finalized {
+                hash: subscribe_all.finalized_block_hash,
+                number: finalized_block_number,
+            });
+        }
+        consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+
+        let mut new_best = (subscribe_all.finalized_block_hash, finalized_block_number);
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let number = decode_number(&block.scale_encoded_header, block_number_bytes);
+            block_numbers.insert(block.block_hash, number);
+            if block.is_new_best {
+                new_best = (block.block_hash, number);
+            }
+            consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+        if best_block_hash != Some(new_best.0) {
+            best_block_hash = Some(new_best.0);
+            events.broadcast(ClientEvent::NewBestBlock {
+                hash: new_best.0,
+                number: new_best.1,
+            });
+        }
+
+        while let Some(notification) = new_blocks.next().await {
+            match notification {
+                consensus_service::Notification::Block { block, .. } => {
+                    let number = decode_number(&block.scale_encoded_header, block_number_bytes);
+                    block_numbers.insert(block.block_hash, number);
+
+                    if let Some(runtime) = &block.runtime_update {
+                        events.broadcast(ClientEvent::RuntimeUpgrade {
+                            block_hash: block.block_hash,
+                            runtime_version: runtime.runtime_version().clone(),
+                        });
+                    }
+
+                    if block.is_new_best {
+                        best_block_hash = Some(block.block_hash);
+                        events.broadcast(ClientEvent::NewBestBlock {
+                            hash: block.block_hash,
+                            number,
+                        });
+                    }
+
+                    consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks_hashes,
+                    best_block_hash: new_best_block_hash,
+                } => {
+                    let new_finalized_hash = finalized_blocks_newest_to_oldest[0];
+                    events.broadcast(ClientEvent::Finalized {
+                        hash: new_finalized_hash,
+                        number: block_numbers[&new_finalized_hash],
+                    });
+
+                    if best_block_hash != Some(new_best_block_hash) {
+                        best_block_hash = Some(new_best_block_hash);
+                        events.broadcast(ClientEvent::NewBestBlock {
+                            hash: new_best_block_hash,
+                            number: block_numbers[&new_best_block_hash],
+                        });
+                    }
+
+                    if let Some(previous_finalized) = finalized_block_hash.take() {
+                        block_numbers.remove(&previous_finalized);
+                    }
+                    for hash in finalized_blocks_newest_to_oldest
+                        .iter()
+                        .skip(1)
+                        .chain(pruned_blocks_hashes.iter())
+                    {
+                        block_numbers.remove(hash);
+                    }
+                    finalized_block_hash = Some(new_finalized_hash);
+                }
+            }
+        }
+
+        // The consensus service has killed the subscription. Subscribe again.
+    }
+}
+
+/// Returns the height found in the given header.
+///
+/// # Panic
+///
+/// Panics if the header is invalid. The headers reported by the consensus service have always
+/// been successfully decoded in the past.
+///
+fn decode_number(scale_encoded_header: &[u8], block_number_bytes: usize) -> u64 {
+    header::decode(scale_encoded_header, block_number_bytes)
+        .unwrap()
+        .number
+}
@@ -0,0 +1,206 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! On-disk cache of compiled runtimes.
+//!
+//! Compiling a runtime ahead of time takes several seconds, and happens every time the node
+//! starts and every time a runtime upgrade is verified. The compiled runtimes are saved in a
+//! directory, one file per runtime, so that this compilation can be skipped afterwards.
+//!
+//! The name of each file is the key provided by [`vm::CompiledModulesCache`], which is derived
+//! from the runtime code, the version of smoldot, and the configuration of the compiler. Files
+//! that are no longer relevant, for example after smoldot has been updated, are never loaded
+//! again but are also not removed.
+//!
+//! Compiled runtimes contain machine code that is executed as is. In order to not execute a
+//! file that has been corrupted or modified by a third party, each file starts with a MAC of
+//! its content, calculated with a secret key stored in the same directory in a file named
+//! `mac-key`. On Unix platforms, this file is only readable by the user running the node. Files
+//! whose MAC doesn't match are ignored and compiled again.
+
+use crate::{LogCallback, LogLevel};
+
+use smoldot::executor::vm;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Name of the file, within the directory of the cache, containing the secret key used to
+/// authenticate the compiled runtimes.
+const MAC_KEY_FILE_NAME: &str = "mac-key";
+
+/// Size in bytes of the MAC found at the start of each file.
+const MAC_LEN: usize = 32;
+
+/// See [the module-level documentation](..).
+pub struct CompiledRuntimesCache {
+    /// Directory where the compiled runtimes are stored.
+    directory: PathBuf,
+
+    /// Secret key used to calculate the MAC of the compiled runtimes.
+    mac_key: [u8; 32],
+
+    /// Function called in order to notify of something.
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+}
+
+impl CompiledRuntimesCache {
+    /// Creates a new [`CompiledRuntimesCache`] storing its files in the given directory. The
+    /// directory and the secret key are created if they don't exist yet, in which case the key
+    /// is drawn from `randomness`.
+    pub fn new(
+        directory: PathBuf,
+        log_callback: Arc<dyn LogCallback + Send + Sync>,
+        randomness: &mut impl rand::RngCore,
+    ) -> Result<Self, io::Error> {
+        fs::create_dir_all(&directory)?;
+        let mac_key = load_or_generate_mac_key(&directory.join(MAC_KEY_FILE_NAME), randomness)?;
+        Ok(CompiledRuntimesCache {
+            directory,
+            mac_key,
+            log_callback,
+        })
+    }
+
+    /// Calculates the MAC of the given compiled runtime stored under the given key.
+    fn mac(&self, key: &[u8; 32], compiled_module: &[u8]) -> blake2_rfc::blake2b::Blake2bResult {
+        let mut hasher = blake2_rfc::blake2b::Blake2b::with_key(MAC_LEN, &self.mac_key);
+        hasher.update(key);
+        hasher.update(compiled_module);
+        hasher.finalize()
+    }
+}
+
+// SAFETY: `load` only returns the content of files whose MAC, calculated with a key that only
+// the node knows, matches. Only `store` is capable of generating such files.
+unsafe impl vm::CompiledModulesCache for CompiledRuntimesCache {
+    fn load(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+        let mut file_content = match fs::read(self.directory.join(hex::encode(key))) {
+            Ok(file_content) => file_content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                self.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "compiled-runtime-cache-load-error; key={}; error={}",
+                        hex::encode(key),
+                        err
+                    ),
+                );
+                return None;
+            }
+        };
+
+        if file_content.len() < MAC_LEN
+            || self.mac(key, &file_content[MAC_LEN..]) != file_content[..MAC_LEN]
+        {
+            self.log_callback.log(
+                LogLevel::Warn,
+                format!(
+                    "compiled-runtime-cache-invalid-mac; key={}; size={}",
+                    hex::encode(key),
+                    file_content.len()
+                ),
+            );
+            return None;
+        }
+
+        let compiled_runtime = file_content.split_off(MAC_LEN);
+        self.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "compiled-runtime-cache-hit; key={}; size={}",
+                hex::encode(key),
+                compiled_runtime.len()
+            ),
+        );
+        Some(compiled_runtime)
+    }
+
+    fn store(&self, key: &[u8; 32], compiled_module: &[u8]) {
+        let mut file_content = Vec::with_capacity(MAC_LEN + compiled_module.len());
+        file_content.extend_from_slice(self.mac(key, compiled_module).as_bytes());
+        file_content.extend_from_slice(compiled_module);
+
+        // The file is first written under a temporary name then renamed, so that a node that is
+        // stopped in the middle of the write doesn't leave a truncated file behind.
+        let path = self.directory.join(hex::encode(key));
+        let tmp_path = self.directory.join(format!("{}.tmp", hex::encode(key)));
+        let result =
+            fs::write(&tmp_path, &file_content).and_then(|()| fs::rename(&tmp_path, &path));
+
+        match result {
+            Ok(()) => {
+                self.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "compiled-runtime-cache-store; key={}; size={}",
+                        hex::encode(key),
+                        compiled_module.len()
+                    ),
+                );
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&tmp_path);
+                self.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "compiled-runtime-cache-store-error; key={}; error={}",
+                        hex::encode(key),
+                        err
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Loads the secret key used to authenticate the compiled runtimes from the given file, or
+/// generates a new one using `randomness` and writes it to this file if it doesn't exist.
+fn load_or_generate_mac_key(
+    path: &Path,
+    randomness: &mut impl rand::RngCore,
+) -> Result<[u8; 32], io::Error> {
+    match fs::read(path) {
+        Ok(key) => {
+            return <[u8; 32]>::try_from(key).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid compiled runtimes cache key in {}", path.display()),
+                )
+            })
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut key = [0; 32];
+    randomness.fill_bytes(&mut key);
+
+    // Written under a temporary name then renamed, like the compiled runtimes.
+    let tmp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(&tmp_path)?, &key)?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(key)
+}
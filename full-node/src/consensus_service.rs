@@ -24,9 +24,13 @@
 // TODO: doc
 // TODO: re-review this once finished
 
-use crate::{database_thread, jaeger_service, network_service, LogCallback, LogLevel};
+use crate::{
+    compiled_runtimes_cache, database_thread, equivocation_reporter, jaeger_service,
+    network_service, offchain_http, runtime_calls_limiter, runtime_execution_threads,
+    transactions_pool, LogCallback, LogLevel,
+};
 
-use core::num::NonZeroU32;
+use core::{fmt, num::NonZeroU32};
 use futures_channel::{mpsc, oneshot};
 use futures_lite::FutureExt as _;
 use futures_util::{
@@ -35,10 +39,11 @@ use futures_util::{
     SinkExt as _, StreamExt as _,
 };
 use hashbrown::HashSet;
-use rand::seq::IteratorRandom;
+use rand::{seq::IteratorRandom, Rng as _, SeedableRng as _};
 use smol::lock::Mutex;
 use smoldot::{
     author,
+    chain::chain_information,
     database::full_sqlite,
     executor::{self, host, runtime_call},
     header,
@@ -48,12 +53,13 @@ use smoldot::{
     network::{self, codec::BlockData},
     sync::all,
     trie,
-    verify::body_only,
+    verify::{self, body_only},
 };
 use std::{
     array,
     borrow::Cow,
     cmp,
+    collections::BTreeMap,
     future::Future,
     iter,
     num::{NonZeroU64, NonZeroUsize},
@@ -62,6 +68,22 @@ use std::{
     time::{Duration, Instant, SystemTime},
 };
 
+/// Number of blocks whose [`BlockExecutionProfile`] is kept in memory when
+/// [`Config::block_execution_profiling`] is `true`.
+const BLOCK_EXECUTION_PROFILES_CAPACITY: usize = 256;
+
+/// Number of `(slot, authority)` tuples whose block is remembered in order to detect the
+/// authorities that produce multiple blocks for the same slot.
+const SLOT_CLAIMS_CAPACITY: usize = 1024;
+
+/// Maximum number of blocks requested from a peer in a single blocks request. Also the distance
+/// between two consecutive block range anchors. See [`SyncLimits::parallel_block_ranges`].
+const MAX_BLOCKS_PER_REQUEST: u64 = 64;
+
+/// Maximum number of blocks queued with [`ConsensusService::queue_block_import`] that are waiting
+/// to be verified.
+const MAX_QUEUED_IMPORTED_BLOCKS: usize = 256;
+
 /// Configuration for a [`ConsensusService`].
 pub struct Config {
     /// Closure that spawns background tasks.
@@ -116,6 +138,245 @@ pub struct Config {
     /// Note that this value doesn't determine the moment when creating the block has ended, but
     /// the moment when creating the block should start its final phase.
     pub slot_duration_author_ratio: u16,
+
+    /// Maximum total size, in bytes, of the transactions included in the blocks authored by the
+    /// node. The inherents don't count towards this limit.
+    pub max_block_transactions_size: usize,
+
+    /// Pool whose transactions are included in the blocks built by the node.
+    pub transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// Providers of the inherents to include in the blocks authored by the node, in addition to
+    /// the timestamp which is always included. Their inherents are queried every time a block
+    /// starts being authored.
+    pub inherent_data_providers: Vec<Arc<dyn InherentDataProvider + Send + Sync>>,
+
+    /// If `Some`, called for every block after it has been verified and inserted in the
+    /// database. The processing of the following blocks waits for the returned future to finish.
+    pub block_import_hook: Option<Arc<dyn BlockImportHook + Send + Sync>>,
+
+    /// If `Some`, the Babe equivocations detected while verifying blocks are sent to this
+    /// channel in order to be reported. Equivocations are always logged, even if `None`.
+    pub equivocation_reports: Option<async_channel::Sender<equivocation_reporter::Equivocation>>,
+
+    /// If `true`, the service only cares about the finalized chain. Forks that compete with the
+    /// best chain are buffered as little as possible, and the blocks that are no longer
+    /// descendants of the finalized block are removed from the database as soon as finality
+    /// progresses rather than only when the node restarts.
+    ///
+    /// Note that competing forks are still verified, as any of them might end up finalized.
+    ///
+    /// Appropriate for nodes that serve JSON-RPC requests but never author blocks.
+    pub finalized_chain_only: bool,
+
+    /// If `true`, the offchain storage changes that the runtime performs through the offchain
+    /// indexing host functions while blocks are executed are written to the database when the
+    /// blocks are inserted. They are otherwise discarded.
+    pub offchain_indexing: bool,
+
+    /// If `Some`, the blocks are executed on these threads rather than within the tasks spawned
+    /// through [`Config::tasks_executor`].
+    pub runtime_execution_threads: Option<Arc<runtime_execution_threads::RuntimeExecutionThreads>>,
+
+    /// Bounds of the number of blocks held in memory while syncing.
+    pub sync_limits: SyncLimits,
+
+    /// Seed used to initialize the randomness of the service, such as the choice of the peers
+    /// to send requests to. Two services created with the same seed and exposed to the same
+    /// events send the same requests to the same peers.
+    pub randomness_seed: [u8; 32],
+
+    /// If `Some`, the runtimes compiled ahead of time are loaded from and saved to this cache,
+    /// so that the runtime of the finalized block and the new runtimes found in verified blocks
+    /// don't have to be compiled again every time the node restarts.
+    pub compiled_runtimes_cache: Option<Arc<compiled_runtimes_cache::CompiledRuntimesCache>>,
+
+    /// Limiter shared with the JSON-RPC service. The executions performed in order to verify and
+    /// author blocks always get a permit immediately, and reduce the number of JSON-RPC
+    /// executions that can run in parallel.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// If `true`, each block is executed a second time after it has been verified, one extrinsic
+    /// at a time, in order to build its [`BlockExecutionProfile`]. The profiles of the most
+    /// recently verified blocks can be retrieved with
+    /// [`ConsensusService::block_execution_profile`].
+    ///
+    /// Enabling this roughly doubles the time it takes to verify blocks.
+    pub block_execution_profiling: bool,
+
+    /// If `true`, the node warp syncs to the latest finalized block (if possible) then downloads
+    /// its storage, instead of downloading and verifying every single block starting from the
+    /// finalized block found in the database.
+    ///
+    /// Warp syncing only happens if the finalized block of the database is sufficiently far
+    /// behind the finalized block of the network.
+    pub warp_sync: bool,
+
+    /// If `true`, each warp sync request is also sent to a second peer, and the finalized blocks
+    /// reported by the two peers are compared. If the two peers report different blocks at the
+    /// same height, the response is discarded and a warning is logged.
+    ///
+    /// This makes it harder for a single malicious peer to feed the node with a fake authority
+    /// set or state root, at the cost of doubling the bandwidth used by warp syncing.
+    pub cross_check_warp_sync: bool,
+
+    /// If `Some`, blocks whose fork point with the current best chain is more than this number
+    /// of blocks below the current best block are discarded, without being marked as bad.
+    ///
+    /// Reorganizations can never revert the finalized block. This limit is therefore mostly
+    /// relevant when finality is stalled, in which case reorganizations could otherwise be
+    /// arbitrarily deep.
+    pub max_reorg_depth: Option<u64>,
+
+    /// Maximum duration by which the slot of a block is allowed to start in the future compared
+    /// to the local clock. Blocks whose slot starts later are refused.
+    ///
+    /// A typical value is 30 seconds. Private networks whose nodes are paused and resumed, and
+    /// whose clocks are thus likely to diverge, might want to use a higher value.
+    pub slot_drift_tolerance: Duration,
+
+    /// If slots have been skipped between the best block and the slot during which the local
+    /// node authors a block, the time available for authoring is extended by one slot duration
+    /// per skipped slot, up to this maximum. Passing `0` disables this mechanism.
+    ///
+    /// When the authors of a chain have been paused, the first blocks after the pause typically
+    /// take more time to build, for example because of the hooks executed at the start of each
+    /// block. The slot lenience avoids reporting these blocks as late.
+    pub max_slot_lenience: Duration,
+}
+
+/// Bounds of the number of blocks held in memory by the consensus service while syncing. See
+/// [`Config::sync_limits`].
+///
+/// Lower values reduce the memory usage of the node during the initial sync, at the cost of a
+/// slower sync.
+#[derive(Debug, Clone)]
+pub struct SyncLimits {
+    /// Maximum number of blocks, ahead of the latest verified block, that are downloaded and
+    /// held in memory while waiting to be verified. No block is requested from the network
+    /// while this limit is reached.
+    pub download_ahead_blocks: NonZeroU32,
+
+    /// Maximum number of block headers whose parent is unknown, for example headers announced
+    /// by peers that are far ahead, held in memory.
+    pub max_disjoint_headers: usize,
+
+    /// Maximum number of requests that can be in progress at the same time towards the same
+    /// peer. Higher values make it possible to saturate the bandwidth of peers with a high
+    /// latency.
+    pub max_requests_per_source: NonZeroUsize,
+
+    /// Maximum number of block ranges, ahead of the latest verified block, that are downloaded
+    /// in parallel from multiple peers during the initial sync.
+    ///
+    /// The header at the end of each range, called an anchor, is downloaded first. The blocks
+    /// below each anchor are then downloaded concurrently, possibly from different peers, and
+    /// verified in order once they connect to the chain. If 0, blocks are downloaded one range
+    /// after the other, each range only being requested once the previous one has been
+    /// received.
+    pub parallel_block_ranges: usize,
+}
+
+impl Default for SyncLimits {
+    fn default() -> Self {
+        SyncLimits {
+            // Assuming a verification speed of 1k blocks/sec and a 99th download time
+            // percentile of two second, the number of blocks to download ahead of time
+            // in order to not block is 2000.
+            // In practice, however, the verification speed and download speed depend on
+            // the chain and the machine of the user.
+            download_ahead_blocks: NonZeroU32::new(2000).unwrap(),
+            max_disjoint_headers: 1024,
+            max_requests_per_source: NonZeroUsize::new(2).unwrap(),
+            parallel_block_ranges: 8,
+        }
+    }
+}
+
+/// Source of inherents to include in the blocks authored by the node, such as the
+/// parachain-related inherents, randomness, or the inherents of custom pallets.
+/// See [`Config::inherent_data_providers`].
+///
+/// Implemented on closures.
+pub trait InherentDataProvider {
+    /// Returns the identifiers and SCALE-encoded values of the inherents to include in a block
+    /// that is about to be authored on top of the given parent block. The authoring waits for
+    /// the returned future to finish.
+    fn provide(
+        &self,
+        parent_hash: [u8; 32],
+        parent_number: u64,
+        now_from_unix_epoch: Duration,
+    ) -> future::BoxFuture<'static, Vec<([u8; 8], Vec<u8>)>>;
+}
+
+impl fmt::Debug for dyn InherentDataProvider + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InherentDataProvider")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> InherentDataProvider for T
+where
+    T: ?Sized + Fn([u8; 32], u64, Duration) -> future::BoxFuture<'static, Vec<([u8; 8], Vec<u8>)>>,
+{
+    fn provide(
+        &self,
+        parent_hash: [u8; 32],
+        parent_number: u64,
+        now_from_unix_epoch: Duration,
+    ) -> future::BoxFuture<'static, Vec<([u8; 8], Vec<u8>)>> {
+        (*self)(parent_hash, parent_number, now_from_unix_epoch)
+    }
+}
+
+/// Receives the blocks imported by the node, for example in order to feed an external index.
+/// See [`Config::block_import_hook`].
+///
+/// Contrary to the notifications of [`ConsensusService::subscribe_all`], the hook is called for
+/// every single block and before the processing of the next blocks, meaning that a slow hook
+/// slows down the node rather than missing blocks. In particular, the hook is always called
+/// before the imported block or the storage of its parent can be pruned from the database.
+///
+/// Implemented on closures.
+pub trait BlockImportHook {
+    /// Called after the given block has been verified and inserted in the database. The next
+    /// block is only processed after the returned future has finished.
+    fn on_block_imported(&self, block: ImportedBlock) -> future::BoxFuture<'static, ()>;
+}
+
+impl fmt::Debug for dyn BlockImportHook + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockImportHook").finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized + Fn(ImportedBlock) -> future::BoxFuture<'static, ()>> BlockImportHook for T {
+    fn on_block_imported(&self, block: ImportedBlock) -> future::BoxFuture<'static, ()> {
+        (*self)(block)
+    }
+}
+
+/// Block passed to [`BlockImportHook::on_block_imported`].
+#[derive(Debug, Clone)]
+pub struct ImportedBlock {
+    /// Hash of the block.
+    pub hash: [u8; 32],
+    /// Hash of the parent of the block.
+    pub parent_hash: [u8; 32],
+    /// Height of the block.
+    pub number: u64,
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+    /// List of SCALE-encoded extrinsics of the block.
+    pub body: Vec<Vec<u8>>,
+    /// `true` if the block is the new best block.
+    pub is_new_best: bool,
+    /// Changes to the storage performed by the block, as a list of child trie (`None` for the
+    /// main trie), key, and new value (`None` meaning that the key has been removed). The
+    /// values before the block can be found in the storage of the parent block.
+    pub storage_changes: Vec<(Option<Vec<u8>>, Vec<u8>, Option<Vec<u8>>)>,
 }
 
 /// Identifier for a blocks request to be performed.
@@ -139,6 +400,21 @@ pub struct ConsensusService {
 
     /// See [`Config::block_number_bytes`].
     block_number_bytes: usize,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::inherent_data_providers`].
+    inherent_data_providers: Vec<Arc<dyn InherentDataProvider + Send + Sync>>,
+
+    /// See [`Config::runtime_calls_limiter`].
+    runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// See [`Config::max_block_transactions_size`].
+    max_block_transactions_size: usize,
+
+    /// See [`Config::transactions_pool`].
+    transactions_pool: Arc<transactions_pool::TransactionsPool>,
 }
 
 enum ToBackground {
@@ -163,6 +439,110 @@ enum ToBackground {
     IsMajorSyncingHint {
         result_tx: oneshot::Sender<bool>,
     },
+    GetBlockExecutionProfile {
+        block_hash: [u8; 32],
+        result_tx: oneshot::Sender<Option<BlockExecutionProfile>>,
+    },
+    GetAuthoringStats {
+        result_tx: oneshot::Sender<full_sqlite::AuthoringStats>,
+    },
+    InjectGrandpaCommit {
+        scale_encoded_commit: Vec<u8>,
+    },
+    ForceFinalize {
+        block_hash: [u8; 32],
+        result_tx: oneshot::Sender<Result<(), ForceFinalizeError>>,
+    },
+    GetBestBlock {
+        result_tx: oneshot::Sender<([u8; 32], u64, Arc<executor::host::HostVmPrototype>)>,
+    },
+    SetAuthoringEnabled {
+        enabled: bool,
+    },
+    QueueBlockImport {
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_extrinsics: Vec<Vec<u8>>,
+        justifications: Vec<([u8; 4], Vec<u8>)>,
+        result_tx: oneshot::Sender<Result<QueueBlockImportOutcome, QueueBlockImportError>>,
+    },
+    GetBlockImportQueueLen {
+        result_tx: oneshot::Sender<usize>,
+    },
+}
+
+/// Slot claim to put in the header of the block built by
+/// [`ConsensusService::build_block_template`].
+#[derive(Debug, Clone)]
+pub enum BlockTemplateSlotClaim {
+    /// Chain uses the Aura consensus algorithm.
+    Aura {
+        /// Slot the block is built for.
+        slot_number: u64,
+    },
+    /// Chain uses the Babe consensus algorithm. The block is built as a secondary slot block,
+    /// as building a primary slot block requires the private key of the authority.
+    Babe {
+        /// Slot the block is built for.
+        slot_number: u64,
+        /// Index of the authority within the authorities of the current epoch.
+        authority_index: u32,
+    },
+}
+
+/// Block returned by [`ConsensusService::build_block_template`].
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    /// Hash of the parent of the block, in other words the best block at the time when the
+    /// template was built.
+    pub parent_hash: [u8; 32],
+    /// SCALE-encoded header of the block. The header doesn't contain any seal.
+    pub scale_encoded_header: Vec<u8>,
+    /// List of SCALE-encoded extrinsics of the block.
+    pub body: Vec<Vec<u8>>,
+    /// Root of the storage trie after the execution of the block. Also found in the header.
+    pub state_root: [u8; 32],
+}
+
+/// Error potentially returned by [`ConsensusService::build_block_template`].
+#[derive(Debug, derive_more::Display)]
+pub enum BlockTemplateError {
+    /// Error while building the block.
+    #[display(fmt = "{_0}")]
+    Build(author::runtime::Error),
+    /// Error while accessing the storage of the parent block in the database.
+    #[display(fmt = "Error while accessing the database: {_0}")]
+    DatabaseAccess(full_sqlite::StorageAccessError),
+    /// State trie version stored in the database is invalid.
+    DatabaseInvalidStateTrieVersion,
+}
+
+/// Error potentially returned by [`ConsensusService::force_finalize`].
+#[derive(Debug, derive_more::Display)]
+pub enum ForceFinalizeError {
+    /// The block is unknown, hasn't been verified yet, or isn't a descendant of the current
+    /// finalized block.
+    UnknownBlock,
+}
+
+/// Outcome of a successful call to [`ConsensusService::queue_block_import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueBlockImportOutcome {
+    /// The block has been queued for verification.
+    Queued,
+    /// The block is already finalized, or has already been received from a different source.
+    AlreadyKnown,
+}
+
+/// Error potentially returned by [`ConsensusService::queue_block_import`].
+#[derive(Debug, derive_more::Display)]
+pub enum QueueBlockImportError {
+    /// Failed to decode the header of the block.
+    #[display(fmt = "Failed to decode block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// The parent of the block is neither finalized, nor known by the node, nor queued.
+    UnknownParent,
+    /// Too many blocks are waiting to be verified. The block should be queued again later.
+    QueueFull,
 }
 
 /// Potential error when calling [`ConsensusService::new`].
@@ -191,6 +571,7 @@ impl ConsensusService {
             best_block_hash,
             best_block_number,
             finalized_chain_information,
+            authoring_stats,
         ) = config
             .database
             .with_database({
@@ -259,6 +640,9 @@ impl ConsensusService {
                         Err(full_sqlite::StorageAccessError::IncompleteStorage)
                         | Err(full_sqlite::StorageAccessError::UnknownBlock) => unreachable!(),
                     };
+                    let authoring_stats = database
+                        .authoring_stats()
+                        .map_err(InitError::DatabaseCorruption)?;
                     Ok((
                         finalized_block_number,
                         finalized_heap_pages,
@@ -266,6 +650,7 @@ impl ConsensusService {
                         best_block_hash,
                         best_block_number,
                         finalized_chain_information,
+                        authoring_stats,
                     ))
                 }
             })
@@ -296,26 +681,27 @@ impl ConsensusService {
             chain_information: finalized_chain_information,
             block_number_bytes: config.block_number_bytes,
             allow_unknown_consensus_engines: false,
+            slot_drift_tolerance: config.slot_drift_tolerance,
             sources_capacity: 32,
             blocks_capacity: {
                 // This is the maximum number of blocks between two consecutive justifications.
                 1024
             },
-            max_disjoint_headers: 1024,
-            max_requests_per_block: NonZeroU32::new(3).unwrap(),
-            download_ahead_blocks: {
-                // Assuming a verification speed of 1k blocks/sec and a 99th download time
-                // percentile of two second, the number of blocks to download ahead of time
-                // in order to not block is 2000.
-                // In practice, however, the verification speed and download speed depend on
-                // the chain and the machine of the user.
-                NonZeroU32::new(2000).unwrap()
+            max_disjoint_headers: if config.finalized_chain_only {
+                // Disjoint headers are the ones whose parent isn't known. When only the
+                // finalized chain matters, there is no point in keeping many of them.
+                cmp::min(config.sync_limits.max_disjoint_headers, 64)
+            } else {
+                config.sync_limits.max_disjoint_headers
             },
+            max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            download_ahead_blocks: config.sync_limits.download_ahead_blocks,
             download_bodies: true,
             // We ask for all the chain-information-related storage proofs and call proofs to be
             // downloaded during the warp syncing in order to guarantee that the necessary
             // information will be found in the database at the next reload.
             download_all_chain_information_storage_proofs: true,
+            warp_sync: config.warp_sync,
             code_trie_node_hint: None,
         });
 
@@ -325,27 +711,72 @@ impl ConsensusService {
             // saved in the database, hence the large number of unwraps here.
             let heap_pages = executor::storage_heap_pages_to_value(finalized_heap_pages.as_deref())
                 .map_err(InitError::FinalizedHeapPagesInvalid)?;
-            executor::host::HostVmPrototype::new(executor::host::Config {
-                module: finalized_code,
-                heap_pages,
-                exec_hint: executor::vm::ExecHint::ValidateAndCompile, // TODO: probably should be decided by the optimisticsync
-                allow_unresolved_imports: false,
-            })
+            executor::host::HostVmPrototype::new_with_compiled_modules_cache(
+                executor::host::Config {
+                    module: finalized_code,
+                    heap_pages,
+                    exec_hint: executor::vm::ExecHint::ValidateAndCompile, // TODO: probably should be decided by the optimisticsync
+                    allow_unresolved_imports: false,
+                },
+                config
+                    .compiled_runtimes_cache
+                    .as_deref()
+                    .map(|cache| cache as &dyn executor::vm::CompiledModulesCache),
+            )
             .map_err(InitError::FinalizedRuntimeInit)?
         };
 
         let block_author_sync_source = sync
             .prepare_add_source(best_block_number, best_block_hash)
             .add_source(None, NonFinalizedBlock::NotVerified);
+        let block_import_sync_source = sync
+            .prepare_add_source(best_block_number, best_block_hash)
+            .add_source(None, NonFinalizedBlock::NotVerified);
 
         let (to_background_tx, to_background_rx) = mpsc::channel(4);
 
         let background_sync = SyncBackground {
             sync,
             block_author_sync_source,
+            block_import_sync_source,
+            queued_imported_blocks: hashbrown::HashMap::with_capacity_and_hasher(
+                MAX_QUEUED_IMPORTED_BLOCKS,
+                Default::default(),
+            ),
             block_authoring: None,
+            block_authoring_idle_until: None,
+            babe_slot_duration: None,
             authored_block: None,
+            authoring_stats,
+            authored_blocks_pending_finality: Vec::new(),
+            authoring_enabled: true,
             slot_duration_author_ratio: config.slot_duration_author_ratio,
+            max_block_transactions_size: config.max_block_transactions_size,
+            transactions_pool: config.transactions_pool.clone(),
+            inherent_data_providers: config.inherent_data_providers.clone(),
+            block_import_hook: config.block_import_hook,
+            equivocation_reports: config.equivocation_reports,
+            slot_claims: lru::LruCache::new(NonZeroUsize::new(SLOT_CLAIMS_CAPACITY).unwrap()),
+            finalized_chain_only: config.finalized_chain_only,
+            offchain_indexing: config.offchain_indexing,
+            runtime_execution_threads: config.runtime_execution_threads,
+            max_requests_per_source: config.sync_limits.max_requests_per_source,
+            download_ahead_blocks: config.sync_limits.download_ahead_blocks,
+            parallel_block_ranges: config.sync_limits.parallel_block_ranges,
+            block_range_anchors: BTreeMap::new(),
+            randomness: rand::rngs::StdRng::from_seed(config.randomness_seed),
+            runtime_calls_limiter: config.runtime_calls_limiter.clone(),
+            compiled_runtimes_cache: config.compiled_runtimes_cache,
+            block_execution_profiles: if config.block_execution_profiling {
+                Some(lru::LruCache::new(
+                    NonZeroUsize::new(BLOCK_EXECUTION_PROFILES_CAPACITY).unwrap(),
+                ))
+            } else {
+                None
+            },
+            cross_check_warp_sync: config.cross_check_warp_sync,
+            max_reorg_depth: config.max_reorg_depth,
+            max_slot_lenience: config.max_slot_lenience,
             keystore: config.keystore,
             finalized_runtime: Arc::new(finalized_runtime),
             network_service: config.network_service.0,
@@ -356,7 +787,7 @@ impl ConsensusService {
             blocks_notifications: Vec::with_capacity(8),
             pending_notification: None,
             from_network_service: config.network_events_receiver,
-            database: config.database,
+            database: config.database.clone(),
             database_catch_up_download: DatabaseCatchUpDownload::NoDownloadInProgress,
             database_catch_up_download_block_verification:
                 DatabaseCatchUpDownloadBlockVerification::None,
@@ -371,6 +802,11 @@ impl ConsensusService {
         Ok(Arc::new(ConsensusService {
             block_number_bytes: config.block_number_bytes,
             to_background_tx: Mutex::new(to_background_tx),
+            database: config.database,
+            inherent_data_providers: config.inherent_data_providers,
+            runtime_calls_limiter: config.runtime_calls_limiter,
+            max_block_transactions_size: config.max_block_transactions_size,
+            transactions_pool: config.transactions_pool,
         }))
     }
 
@@ -471,6 +907,199 @@ impl ConsensusService {
             .await;
         result_rx.await.unwrap()
     }
+
+    /// Returns the execution profile of the given block, or `None` if it isn't available.
+    ///
+    /// Profiles are only available if [`Config::block_execution_profiling`] was `true`, and
+    /// only for the blocks among the most recently verified ones.
+    pub async fn block_execution_profile(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Option<BlockExecutionProfile> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::GetBlockExecutionProfile {
+                block_hash,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Returns the statistics about the blocks authored by the local node.
+    ///
+    /// These statistics are stored in the database and thus survive restarts of the node.
+    pub async fn authoring_stats(&self) -> full_sqlite::AuthoringStats {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::GetAuthoringStats { result_tx })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Injects a GrandPa commit assembled by the local node.
+    ///
+    /// The commit is verified like commits received from the network, and the blocks are
+    /// finalized if it is valid.
+    pub async fn inject_grandpa_commit(&self, scale_encoded_commit: Vec<u8>) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::InjectGrandpaCommit {
+                scale_encoded_commit,
+            })
+            .await;
+    }
+
+    /// Pauses or resumes the authoring of blocks.
+    ///
+    /// While the authoring is paused, the node doesn't claim any slot, but continues to verify
+    /// and import the blocks of the chain and to participate in the networking. This is meant
+    /// to be used in order to gracefully rotate or drain a validator. The authoring is enabled
+    /// when the service starts.
+    pub async fn set_authoring_enabled(&self, enabled: bool) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SetAuthoringEnabled { enabled })
+            .await;
+    }
+
+    /// Queues a block for import, as if it had been announced and sent by a networking peer.
+    ///
+    /// The block is verified and executed like the blocks downloaded from the network, and its
+    /// justifications, if any, are verified as well. The parent of the block must either be
+    /// finalized, be known by the node, or have been queued earlier. Blocks are typically queued
+    /// in increasing height.
+    ///
+    /// A block that fails to verify is discarded, and a warning is reported through the logs.
+    /// Use [`ConsensusService::block_import_queue_len`] in order to determine whether the queued
+    /// blocks have been processed.
+    pub async fn queue_block_import(
+        &self,
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_extrinsics: Vec<Vec<u8>>,
+        justifications: Vec<([u8; 4], Vec<u8>)>,
+    ) -> Result<QueueBlockImportOutcome, QueueBlockImportError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::QueueBlockImport {
+                scale_encoded_header,
+                scale_encoded_extrinsics,
+                justifications,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Returns the number of blocks queued with [`ConsensusService::queue_block_import`] that
+    /// haven't been passed to the verification yet.
+    pub async fn block_import_queue_len(&self) -> usize {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::GetBlockImportQueueLen { result_tx })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Marks the given block and all its ancestors as finalized, without verifying any finality
+    /// proof. The blocks that don't descend from it are discarded, both in memory and, if
+    /// [`Config::finalized_chain_only`] is `true`, in the database.
+    ///
+    /// This is meant to be used on development chains that lack a finality mechanism, and in
+    /// order to recover test networks whose finality has stalled. Doing so on a live chain
+    /// might lead the node to diverge from the rest of the network.
+    ///
+    /// Does nothing if the block is already the finalized block.
+    pub async fn force_finalize(&self, block_hash: [u8; 32]) -> Result<(), ForceFinalizeError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForceFinalize {
+                block_hash,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Builds a block on top of the current best block, without sealing, importing, or
+    /// announcing it.
+    ///
+    /// The block contains the inherents, including the ones of
+    /// [`Config::inherent_data_providers`], with a timestamp equal to the current time. The slot
+    /// claim should correspond to the current time, as most runtimes otherwise fail to build the
+    /// block.
+    ///
+    /// The block then contains the transactions of [`Config::transactions_pool`], up to
+    /// [`Config::max_block_transactions_size`] bytes. The transactions that the runtime refuses
+    /// are skipped, but not removed from the pool.
+    ///
+    /// This is useful for testing the runtime, and in order to author blocks through an external
+    /// sealing process.
+    pub async fn build_block_template(
+        &self,
+        slot_claim: BlockTemplateSlotClaim,
+    ) -> Result<BlockTemplate, BlockTemplateError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::GetBestBlock { result_tx })
+            .await;
+        let (parent_hash, parent_number, parent_runtime) = result_rx.await.unwrap();
+
+        let now_from_unix_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let additional_inherents = future::join_all(
+            self.inherent_data_providers
+                .iter()
+                .map(|provider| provider.provide(parent_hash, parent_number, now_from_unix_epoch)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        // Building a template isn't consensus-critical and is thus treated like a JSON-RPC
+        // runtime call.
+        let _permit = self.runtime_calls_limiter.json_rpc_permit().await;
+        let block_transactions = self
+            .transactions_pool
+            .block_transactions(self.max_block_transactions_size);
+        build_block_template_inner(
+            &self.database,
+            self.block_number_bytes,
+            parent_hash,
+            parent_number,
+            (*parent_runtime).clone(),
+            slot_claim,
+            now_from_unix_epoch,
+            additional_inherents,
+            block_transactions,
+        )
+        .await
+    }
 }
 
 /// Return value of [`ConsensusService::subscribe_all`].
@@ -590,8 +1219,10 @@ struct SyncBackground {
     /// the network requests in progress.
     ///
     /// Each peer holds a struct containing either information about a networking peer, or `None`
-    /// if this is the "special source" representing the local block authoring. Only one source
-    /// must contain `None` and its id must be [`SyncBackground::block_author_sync_source`].
+    /// if this is one of the "special sources" representing the local block authoring and the
+    /// blocks queued with [`ConsensusService::queue_block_import`]. Only these two sources must
+    /// contain `None`, and their ids must be [`SyncBackground::block_author_sync_source`] and
+    /// [`SyncBackground::block_import_sync_source`].
     ///
     /// Each block holds its runtime if it has been verified.
     ///
@@ -607,9 +1238,25 @@ struct SyncBackground {
     /// Source within the [`SyncBackground::sync`] to use to import locally-authored blocks.
     block_author_sync_source: all::SourceId,
 
+    /// Source within the [`SyncBackground::sync`] to use to import the blocks queued with
+    /// [`ConsensusService::queue_block_import`].
+    block_import_sync_source: all::SourceId,
+
+    /// Blocks queued with [`ConsensusService::queue_block_import`] and that are waiting for the
+    /// `sync` to request them from [`SyncBackground::block_import_sync_source`]. Indexed by
+    /// hash, and contains the block height, the SCALE-encoded block header, the list of
+    /// SCALE-encoded extrinsics, and the justifications of the block.
+    ///
+    /// Can't contain more than [`MAX_QUEUED_IMPORTED_BLOCKS`] entries.
+    queued_imported_blocks: hashbrown::HashMap<
+        [u8; 32],
+        (u64, Vec<u8>, Vec<Vec<u8>>, Vec<all::Justification>),
+        fnv::FnvBuildHasher,
+    >,
+
     /// State of the authoring. If `None`, the builder should be (re)created. If `Some`, also
-    /// contains the list of public keys that were loaded from the keystore when creating the
-    /// builder.
+    /// contains the list of public keys, and their namespace, that were loaded from the keystore
+    /// when creating the builder.
     ///
     /// The difference between a value of `None` and a value of `Some(Builder::Idle)` is that
     /// `None` indicates that we should try to author a block as soon as possible, while `Idle`
@@ -617,16 +1264,108 @@ struct SyncBackground {
     /// set to `None`). For instance, if the operation of building a block fails, the state is set
     /// to `Idle` so as to avoid trying to create a block over and over again.
     // TODO: this list of public keys is a bit hacky
-    block_authoring: Option<(author::build::Builder, Vec<[u8; 32]>)>,
+    block_authoring: Option<(
+        author::build::Builder,
+        Vec<(keystore::KeyNamespace, [u8; 32])>,
+    )>,
+
+    /// If [`SyncBackground::block_authoring`] is [`author::build::Builder::Idle`], moment when
+    /// it should be reset.
+    block_authoring_idle_until: Option<Instant>,
+
+    /// Duration, in milliseconds, of a Babe slot. Obtained by calling `BabeApi_configuration`
+    /// the first time a Babe block is authored, as it isn't part of the chain information.
+    /// Can't be modified through a runtime upgrade.
+    babe_slot_duration: Option<NonZeroU64>,
+
+    /// If `false`, no slot is claimed. See [`ConsensusService::set_authoring_enabled`].
+    authoring_enabled: bool,
 
     /// See [`Config::slot_duration_author_ratio`].
     slot_duration_author_ratio: u16,
 
+    /// See [`Config::max_block_transactions_size`].
+    max_block_transactions_size: usize,
+
+    /// See [`Config::transactions_pool`].
+    transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// See [`Config::inherent_data_providers`].
+    inherent_data_providers: Vec<Arc<dyn InherentDataProvider + Send + Sync>>,
+
+    /// See [`Config::block_import_hook`].
+    block_import_hook: Option<Arc<dyn BlockImportHook + Send + Sync>>,
+
+    /// See [`Config::equivocation_reports`].
+    equivocation_reports: Option<async_channel::Sender<equivocation_reporter::Equivocation>>,
+
+    /// For each recently-verified `(slot, authority)` tuple, the hash and SCALE-encoded header of
+    /// the block the authority has produced for this slot. Used to detect equivocations.
+    slot_claims: lru::LruCache<(u64, [u8; 32]), ([u8; 32], Vec<u8>)>,
+
+    /// See [`Config::finalized_chain_only`].
+    finalized_chain_only: bool,
+
+    /// See [`Config::offchain_indexing`].
+    offchain_indexing: bool,
+
+    /// See [`Config::runtime_execution_threads`].
+    runtime_execution_threads: Option<Arc<runtime_execution_threads::RuntimeExecutionThreads>>,
+
+    /// See [`SyncLimits::max_requests_per_source`].
+    max_requests_per_source: NonZeroUsize,
+
+    /// See [`SyncLimits::download_ahead_blocks`].
+    download_ahead_blocks: NonZeroU32,
+
+    /// See [`SyncLimits::parallel_block_ranges`].
+    parallel_block_ranges: usize,
+
+    /// Heights of the block range anchors above the current best block that have been
+    /// requested, and the peer the request is still in progress with, if any. Heights are
+    /// always multiples of [`MAX_BLOCKS_PER_REQUEST`].
+    ///
+    /// Once received, anchors are inserted in [`SyncBackground::sync`] as if they had been
+    /// announced by the peer, and the ranges below them are downloaded by the sync state
+    /// machine like the ancestry of any other block.
+    block_range_anchors: BTreeMap<u64, Option<libp2p::PeerId>>,
+
+    /// Source of randomness of the service. Derived from [`Config::randomness_seed`].
+    randomness: rand::rngs::StdRng,
+
+    /// See [`Config::runtime_calls_limiter`].
+    runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// See [`Config::compiled_runtimes_cache`].
+    compiled_runtimes_cache: Option<Arc<compiled_runtimes_cache::CompiledRuntimesCache>>,
+
+    /// Execution profiles of the most recently verified blocks. `None` if
+    /// [`Config::block_execution_profiling`] was `false`.
+    block_execution_profiles: Option<lru::LruCache<[u8; 32], BlockExecutionProfile>>,
+
+    /// See [`Config::cross_check_warp_sync`].
+    cross_check_warp_sync: bool,
+
+    /// See [`Config::max_reorg_depth`].
+    max_reorg_depth: Option<u64>,
+
+    /// See [`Config::max_slot_lenience`].
+    max_slot_lenience: Duration,
+
     /// After a block has been authored, it is inserted here while waiting for the `sync` to
     /// import it. Contains the block height, the block hash, the SCALE-encoded block header, and
     /// the list of SCALE-encoded extrinsics of the block.
     authored_block: Option<(u64, [u8; 32], Vec<u8>, Vec<Vec<u8>>)>,
 
+    /// Statistics about the blocks authored by the local node. Loaded from the database at
+    /// initialization, and written back to the database every time they are modified.
+    authoring_stats: full_sqlite::AuthoringStats,
+
+    /// List of blocks authored by the local node that haven't been finalized or pruned yet.
+    /// Contains the hash of the block and whether the block has been counted in
+    /// [`full_sqlite::AuthoringStats::authored_blocks_best`].
+    authored_blocks_pending_finality: Vec<([u8; 32], bool)>,
+
     /// See [`Config::keystore`].
     keystore: Arc<keystore::Keystore>,
 
@@ -713,6 +1452,25 @@ struct NetworkSourceInfo {
     /// If `true`, this peer is considered disconnected by the network, and no new request should
     /// be started against it.
     is_disconnected: bool,
+    /// If `true`, a previous warp sync request has shown that this peer doesn't support the warp
+    /// sync protocol, and no new warp sync request should be started against it.
+    warp_sync_unsupported: bool,
+    /// If `true`, a previous storage or call proof request has shown that this peer doesn't
+    /// support the light protocol, and no new such request should be started against it.
+    light_unsupported: bool,
+}
+
+impl NetworkSourceInfo {
+    /// Returns `false` if the peer is known to not support the protocol necessary to answer the
+    /// given request.
+    fn supports_request(&self, request: &all::DesiredRequest) -> bool {
+        match request {
+            all::DesiredRequest::BlocksRequest { .. } => true,
+            all::DesiredRequest::WarpSync { .. } => !self.warp_sync_unsupported,
+            all::DesiredRequest::StorageGetMerkleProof { .. }
+            | all::DesiredRequest::RuntimeCallMerkleProof { .. } => !self.light_unsupported,
+        }
+    }
 }
 
 enum SubtaskFinished {
@@ -728,16 +1486,32 @@ enum SubtaskFinished {
             network::service::EncodedGrandpaWarpSyncResponse,
             network_service::WarpSyncRequestError,
         >,
+        /// Peer the same request has been sent to, and its response. `None` if
+        /// [`Config::cross_check_warp_sync`] is `false` or if no other peer was available.
+        cross_check: Option<(
+            libp2p::PeerId,
+            Result<
+                network::service::EncodedGrandpaWarpSyncResponse,
+                network_service::WarpSyncRequestError,
+            >,
+        )>,
     },
     StorageRequestFinished {
         request_id: all::RequestId,
         source_id: all::SourceId,
-        result: Result<network::service::EncodedMerkleProof, ()>,
+        result:
+            Result<network::service::EncodedMerkleProof, network_service::StorageProofRequestError>,
     },
     CallProofRequestFinished {
         request_id: all::RequestId,
         source_id: all::SourceId,
-        result: Result<network::service::EncodedMerkleProof, ()>,
+        result:
+            Result<network::service::EncodedMerkleProof, network_service::CallProofRequestError>,
+    },
+    BlockRangeAnchorRequestFinished {
+        peer_id: libp2p::PeerId,
+        height: u64,
+        result: Result<Vec<BlockData>, network_service::BlocksRequestError>,
     },
 }
 
@@ -786,6 +1560,10 @@ impl SyncBackground {
                     request: all::DesiredRequest,
                     database_catch_up_type: DbCatchUpType,
                 },
+                StartBlockRangeAnchorRequest {
+                    source_id: all::SourceId,
+                    height: u64,
+                },
                 NetworkEvent(network_service::Event),
                 NetworkLocalChainUpdate,
                 AnnounceBlock(Vec<u8>, [u8; 32], u64),
@@ -799,101 +1577,16 @@ impl SyncBackground {
                 Database,
             }
 
+            // Determined ahead of time, as this requires mutably borrowing `self`, which the
+            // futures below can't do.
+            let block_range_anchor_request = self.block_range_anchor_to_request();
+            let authoring_ready_at = if self.authoring_enabled {
+                self.prepare_block_authoring().await
+            } else {
+                None
+            };
+
             let wake_up_reason: WakeUpReason = {
-                // Creating the block authoring state and prepare a future that is ready when something
-                // related to the block authoring is ready.
-                // TODO: refactor as a separate task?
-                // TODO: restore block authoring after https://github.com/smol-dot/smoldot/issues/1109
-                let authoring_ready_future = {
-                    future::pending::<WakeUpReason>()
-                    /*// TODO: overhead to call best_block_consensus() multiple times
-                    let local_authorities = {
-                        let namespace_filter = match self.sync.best_block_consensus() {
-                            chain_information::ChainInformationConsensusRef::Aura { .. } => {
-                                Some(keystore::KeyNamespace::Aura)
-                            }
-                            chain_information::ChainInformationConsensusRef::Babe { .. } => {
-                                Some(keystore::KeyNamespace::Babe)
-                            }
-                            chain_information::ChainInformationConsensusRef::Unknown => {
-                                // In `Unknown` mode, all keys are accepted and there is no
-                                // filter on the namespace, as we can't author blocks anyway.
-                                // TODO: is that correct?
-                                None
-                            }
-                        };
-
-                        // Calling `keys()` on the keystore is racy, but that's considered
-                        // acceptable and part of the design of the node.
-                        self.keystore
-                            .keys()
-                            .await
-                            .filter(|(namespace, _)| {
-                                namespace_filter.map_or(true, |n| *namespace == n)
-                            })
-                            .map(|(_, key)| key)
-                            .collect::<Vec<_>>() // TODO: collect overhead :-/
-                    };
-
-                    let block_authoring =
-                        match (&mut self.block_authoring, self.sync.best_block_consensus()) {
-                            (Some(ba), _) => Some(ba),
-                            (
-                                block_authoring @ None,
-                                chain_information::ChainInformationConsensusRef::Aura {
-                                    finalized_authorities_list, // TODO: field name not appropriate; should probably change the chain_information module
-                                    slot_duration,
-                                },
-                            ) => Some(
-                                block_authoring.insert((
-                                    author::build::Builder::new(author::build::Config {
-                                        consensus: author::build::ConfigConsensus::Aura {
-                                            current_authorities: finalized_authorities_list,
-                                            local_authorities: local_authorities.iter(),
-                                            now_from_unix_epoch: SystemTime::now()
-                                                .duration_since(SystemTime::UNIX_EPOCH)
-                                                .unwrap(),
-                                            slot_duration,
-                                        },
-                                    }),
-                                    local_authorities,
-                                )),
-                            ),
-                            (
-                                None,
-                                chain_information::ChainInformationConsensusRef::Babe { .. },
-                            ) => {
-                                None // TODO: the block authoring doesn't support Babe at the moment
-                            }
-                            (None, _) => todo!(),
-                        };
-
-                    match &block_authoring {
-                        Some((author::build::Builder::Ready(_), _)) => future::Either::Left(
-                            future::Either::Left(future::ready(Instant::now())),
-                        ),
-                        Some((author::build::Builder::WaitSlot(when), _)) => {
-                            let delay = (UNIX_EPOCH + when.when())
-                                .duration_since(SystemTime::now())
-                                .unwrap_or_else(|_| Duration::new(0, 0));
-                            future::Either::Right(future::FutureExt::fuse(smol::Timer::after(
-                                delay,
-                            )))
-                        }
-                        None => future::Either::Left(future::Either::Right(future::pending())),
-                        Some((author::build::Builder::Idle, _)) => {
-                            // If the block authoring is idle, which happens in case of error,
-                            // sleep for an arbitrary duration before resetting it.
-                            // This prevents the authoring from trying over and over again to generate
-                            // a bad block.
-                            let delay = Duration::from_secs(2);
-                            future::Either::Right(future::FutureExt::fuse(smol::Timer::after(
-                                delay,
-                            )))
-                        }
-                    }*/
-                };
-
                 async {
                     if let Some(notification) = self.pending_notification.take() {
                         WakeUpReason::SendPendingNotification(notification)
@@ -902,7 +1595,10 @@ impl SyncBackground {
                     }
                 }
                 .or(async move {
-                    authoring_ready_future.await;
+                    let Some(authoring_ready_at) = authoring_ready_at else {
+                        future::pending().await
+                    };
+                    smol::Timer::at(authoring_ready_at).await;
                     WakeUpReason::ReadyToAuthor
                 })
                 .or(async {
@@ -942,8 +1638,8 @@ impl SyncBackground {
                         // be started.
                         // `desired_requests()` returns, in decreasing order of priority, the
                         // requests that should be started in order for the syncing to proceed. We
-                        // simply pick the first request, but enforce one ongoing request per
-                        // source.
+                        // simply pick the first request, but enforce a maximum number of ongoing
+                        // requests per source.
                         // TODO: desired_requests() is expensive and done at every iteration
                         let request_to_start = self.sync.desired_requests().find(
                             |(source_id, source_info, request_details)| {
@@ -953,9 +1649,26 @@ impl SyncBackground {
                                 {
                                     // Source is a networking source that has already been disconnected.
                                     false
+                                } else if *source_id == self.block_import_sync_source {
+                                    // Source of the queued imported blocks.
+                                    matches!(
+                                        request_details,
+                                        all::DesiredRequest::BlocksRequest { first_block_hash, .. }
+                                            if self.queued_imported_blocks.contains_key(first_block_hash)
+                                    )
                                 } else if *source_id != self.block_author_sync_source {
-                                    // Remote source.
-                                    self.sync.source_num_ongoing_requests(*source_id) == 0
+                                    // Remote source. Sources known to not support the protocol
+                                    // of the request are skipped.
+                                    source_info.as_ref().map_or(true, |info| {
+                                        Self::can_start_request(
+                                            &self.sync,
+                                            &self.block_range_anchors,
+                                            self.max_requests_per_source,
+                                            *source_id,
+                                            info,
+                                            request_details,
+                                        )
+                                    })
                                 } else {
                                     // Locally-authored blocks source.
                                     match (request_details, &self.authored_block) {
@@ -984,6 +1697,15 @@ impl SyncBackground {
                             };
                         }
 
+                        // Download the anchors of the block ranges ahead of the best block, so
+                        // that these ranges can later be downloaded in parallel.
+                        if let Some((source_id, height)) = block_range_anchor_request {
+                            return WakeUpReason::StartBlockRangeAnchorRequest {
+                                source_id,
+                                height,
+                            };
+                        }
+
                         match self.database_catch_up_download_block_verification.clone() {
                             _ if !matches!(
                                 self.database_catch_up_download,
@@ -1006,8 +1728,13 @@ impl SyncBackground {
                                 {
                                     self.sync
                                         .sources()
-                                        .filter(|s| *s != self.block_author_sync_source)
-                                        .choose(&mut rand::thread_rng())
+                                        .filter(|s| {
+                                            *s != self.block_author_sync_source
+                                                && self.sync[*s]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
+                                        })
+                                        .choose(&mut self.randomness)
                                 } else {
                                     self.sync
                                         .knows_non_finalized_block(block_number, &block_hash)
@@ -1015,8 +1742,11 @@ impl SyncBackground {
                                             *source_id != self.block_author_sync_source
                                                 && self.sync.source_num_ongoing_requests(*source_id)
                                                     == 0
+                                                && self.sync[*source_id]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
                                         })
-                                        .choose(&mut rand::thread_rng())
+                                        .choose(&mut self.randomness)
                                 };
 
                                 if let Some(source_id) = source_id {
@@ -1043,8 +1773,13 @@ impl SyncBackground {
                                 {
                                     self.sync
                                         .sources()
-                                        .filter(|s| *s != self.block_author_sync_source)
-                                        .choose(&mut rand::thread_rng())
+                                        .filter(|s| {
+                                            *s != self.block_author_sync_source
+                                                && self.sync[*s]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
+                                        })
+                                        .choose(&mut self.randomness)
                                 } else {
                                     self.sync
                                         .knows_non_finalized_block(block_number, &block_hash)
@@ -1052,8 +1787,11 @@ impl SyncBackground {
                                             *source_id != self.block_author_sync_source
                                                 && self.sync.source_num_ongoing_requests(*source_id)
                                                     == 0
+                                                && self.sync[*source_id]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
                                         })
-                                        .choose(&mut rand::thread_rng())
+                                        .choose(&mut self.randomness)
                                 };
 
                                 if let Some(source_id) = source_id {
@@ -1109,8 +1847,13 @@ impl SyncBackground {
                                     let Some(source_id) = self
                                         .sync
                                         .sources()
-                                        .filter(|s| *s != self.block_author_sync_source)
-                                        .choose(&mut rand::thread_rng())
+                                        .filter(|s| {
+                                            *s != self.block_author_sync_source
+                                                && self.sync[*s]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
+                                        })
+                                        .choose(&mut self.randomness)
                                     else {
                                         break;
                                     };
@@ -1126,8 +1869,11 @@ impl SyncBackground {
                                             *source_id != self.block_author_sync_source
                                                 && self.sync.source_num_ongoing_requests(*source_id)
                                                     == 0
+                                                && self.sync[*source_id]
+                                                    .as_ref()
+                                                    .map_or(false, |info| !info.light_unsupported)
                                         })
-                                        .choose(&mut rand::thread_rng())
+                                        .choose(&mut self.randomness)
                                     else {
                                         continue;
                                     };
@@ -1149,7 +1895,7 @@ impl SyncBackground {
                                                 // key plus a few other random keys.
                                                 .chain((0..32).map(|_| {
                                                     rand::Rng::gen_range(
-                                                        &mut rand::thread_rng(),
+                                                        &mut self.randomness,
                                                         0..16,
                                                     )
                                                 }))
@@ -1199,6 +1945,7 @@ impl SyncBackground {
                         }
                         Some((author::build::Builder::Idle, _)) => {
                             self.block_authoring = None;
+                            self.block_authoring_idle_until = None;
                         }
                         None => {
                             unreachable!()
@@ -1305,6 +2052,133 @@ impl SyncBackground {
                     let _ = result_tx.send(result);
                 }
 
+                WakeUpReason::FrontendEvent(ToBackground::GetBlockExecutionProfile {
+                    block_hash,
+                    result_tx,
+                }) => {
+                    let profile = self
+                        .block_execution_profiles
+                        .as_mut()
+                        .and_then(|profiles| profiles.get(&block_hash))
+                        .cloned();
+                    let _ = result_tx.send(profile);
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::GetAuthoringStats { result_tx }) => {
+                    let _ = result_tx.send(self.authoring_stats.clone());
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::GetBestBlock { result_tx }) => {
+                    let runtime =
+                        if self.sync.best_block_number() != self.sync.finalized_block_number() {
+                            let NonFinalizedBlock::Verified { runtime } = &self.sync
+                                [(self.sync.best_block_number(), self.sync.best_block_hash())]
+                            else {
+                                unreachable!()
+                            };
+                            runtime.clone()
+                        } else {
+                            self.finalized_runtime.clone()
+                        };
+                    let _ = result_tx.send((
+                        *self.sync.best_block_hash(),
+                        self.sync.best_block_number(),
+                        runtime,
+                    ));
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::InjectGrandpaCommit {
+                    scale_encoded_commit,
+                }) => {
+                    // Locally-assembled commits are attributed to the same source as the
+                    // locally-authored blocks.
+                    match self
+                        .sync
+                        .grandpa_commit_message(self.block_author_sync_source, scale_encoded_commit)
+                    {
+                        all::GrandpaCommitMessageOutcome::Queued => {
+                            self.log_callback
+                                .log(LogLevel::Debug, "local-grandpa-commit-queued".to_string());
+                            process_sync = true;
+                        }
+                        all::GrandpaCommitMessageOutcome::Discarded => {
+                            self.log_callback
+                                .log(LogLevel::Warn, "local-grandpa-commit-discarded".to_string());
+                        }
+                    }
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::SetAuthoringEnabled { enabled }) => {
+                    if self.authoring_enabled != enabled {
+                        self.authoring_enabled = enabled;
+                        // Discard any slot that has been claimed in the meanwhile. The authoring
+                        // state is rebuilt from scratch when the authoring is resumed.
+                        self.block_authoring = None;
+                        self.log_callback.log(
+                            LogLevel::Info,
+                            if enabled {
+                                "block-authoring-resumed".to_string()
+                            } else {
+                                "block-authoring-paused".to_string()
+                            },
+                        );
+                    }
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::QueueBlockImport {
+                    scale_encoded_header,
+                    scale_encoded_extrinsics,
+                    justifications,
+                    result_tx,
+                }) => {
+                    let _ = result_tx.send(self.queue_block_import(
+                        scale_encoded_header,
+                        scale_encoded_extrinsics,
+                        justifications,
+                    ));
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::GetBlockImportQueueLen { result_tx }) => {
+                    self.prune_queued_imported_blocks();
+                    let _ = result_tx.send(self.queued_imported_blocks.len());
+                }
+
+                WakeUpReason::FrontendEvent(ToBackground::ForceFinalize {
+                    block_hash,
+                    result_tx,
+                }) => match self.sync.force_finalize(&block_hash) {
+                    Ok(all::FinalityProofVerifyOutcome::NewFinalized {
+                        finalized_blocks_newest_to_oldest,
+                        pruned_blocks,
+                        updates_best_block,
+                    }) => {
+                        self.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "force-finalized; hash={}; num_finalized={}; num_pruned={}",
+                                HashDisplay(&block_hash),
+                                finalized_blocks_newest_to_oldest.len(),
+                                pruned_blocks.len()
+                            ),
+                        );
+                        self.on_new_finalized(
+                            finalized_blocks_newest_to_oldest,
+                            pruned_blocks,
+                            updates_best_block,
+                        )
+                        .await;
+                        let _ = result_tx.send(Ok(()));
+                        process_sync = true;
+                    }
+                    Ok(all::FinalityProofVerifyOutcome::AlreadyFinalized) => {
+                        let _ = result_tx.send(Ok(()));
+                    }
+                    Ok(_) => unreachable!(),
+                    Err(all::ForceFinalizeError::UnknownBlock) => {
+                        let _ = result_tx.send(Err(ForceFinalizeError::UnknownBlock));
+                    }
+                },
+
                 WakeUpReason::NetworkLocalChainUpdate => {
                     self.network_service
                         .set_local_best_block(
@@ -1392,6 +2266,8 @@ impl SyncBackground {
                                     Some(NetworkSourceInfo {
                                         peer_id: entry.key().clone(),
                                         is_disconnected: false,
+                                        warp_sync_unsupported: false,
+                                        light_unsupported: false,
                                     }),
                                     NonFinalizedBlock::NotVerified,
                                 );
@@ -1445,6 +2321,7 @@ impl SyncBackground {
                     chain_id,
                     peer_id,
                     finalized_block_height,
+                    ..
                 }) if chain_id == self.network_chain_id => {
                     let source_id = *self.peers_source_id_map.get(&peer_id).unwrap();
                     self.sync
@@ -1485,6 +2362,38 @@ impl SyncBackground {
                     );
                 }
 
+                WakeUpReason::StartNetworkRequest {
+                    source_id,
+                    request:
+                        request_info @ all::DesiredRequest::BlocksRequest {
+                            first_block_hash, ..
+                        },
+                    database_catch_up_type,
+                } if source_id == self.block_import_sync_source => {
+                    debug_assert!(matches!(database_catch_up_type, DbCatchUpType::No));
+
+                    let (_, scale_encoded_header, scale_encoded_extrinsics, justifications) = self
+                        .queued_imported_blocks
+                        .remove(&first_block_hash)
+                        .unwrap();
+
+                    let _jaeger_span = self
+                        .jaeger_service
+                        .block_import_queue_span(&first_block_hash);
+
+                    // Create a request that is immediately answered right below.
+                    let request_id = self.sync.add_request(source_id, request_info.into(), ());
+                    self.sync.blocks_request_response(
+                        request_id,
+                        iter::once(all::BlockRequestSuccessBlock {
+                            scale_encoded_header,
+                            scale_encoded_extrinsics,
+                            scale_encoded_justifications: justifications,
+                            user_data: NonFinalizedBlock::NotVerified,
+                        }),
+                    );
+                }
+
                 WakeUpReason::StartNetworkRequest {
                     source_id,
                     request:
@@ -1500,7 +2409,9 @@ impl SyncBackground {
                 } => {
                     // Before notifying the syncing of the request, clamp the number of blocks to
                     // the number of blocks we expect to receive.
-                    let num_blocks = NonZeroU64::new(cmp::min(num_blocks.get(), 64)).unwrap();
+                    let num_blocks =
+                        NonZeroU64::new(cmp::min(num_blocks.get(), MAX_BLOCKS_PER_REQUEST))
+                            .unwrap();
 
                     let peer_id = {
                         let info = self.sync[source_id].clone().unwrap();
@@ -1571,6 +2482,47 @@ impl SyncBackground {
                     }));
                 }
 
+                WakeUpReason::StartBlockRangeAnchorRequest { source_id, height } => {
+                    let peer_id = {
+                        let info = self.sync[source_id].clone().unwrap();
+                        // Disconnected sources are filtered out above.
+                        debug_assert!(!info.is_disconnected);
+                        info.peer_id
+                    };
+
+                    // The anchor is requested by number, as its hash isn't known yet. Only its
+                    // header is requested, as its body is later downloaded by the sync state
+                    // machine alongside the range below it.
+                    let request = self.network_service.clone().blocks_request(
+                        peer_id.clone(),
+                        self.network_chain_id,
+                        network::codec::BlocksRequestConfig {
+                            start: network::codec::BlocksRequestConfigStart::Number(height),
+                            desired_count: NonZeroU32::new(1).unwrap(),
+                            direction: network::codec::BlocksRequestDirection::Descending,
+                            fields: network::codec::BlocksRequestFields {
+                                header: true,
+                                body: false,
+                                justifications: false,
+                            },
+                        },
+                    );
+
+                    let _prev_value = self
+                        .block_range_anchors
+                        .insert(height, Some(peer_id.clone()));
+                    debug_assert!(_prev_value.is_none());
+
+                    self.sub_tasks.push(Box::pin(async move {
+                        let result = request.await;
+                        SubtaskFinished::BlockRangeAnchorRequestFinished {
+                            peer_id,
+                            height,
+                            result,
+                        }
+                    }));
+                }
+
                 WakeUpReason::StartNetworkRequest {
                     source_id,
                     request:
@@ -1582,6 +2534,30 @@ impl SyncBackground {
                     // TODO: don't unwrap? could this target the virtual sync source?
                     let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone(); // TODO: why does this require cloning? weird borrow chk issue
 
+                    // Send the same request to a second randomly-chosen peer if cross-checking is
+                    // enabled.
+                    let cross_check_request = if self.cross_check_warp_sync {
+                        self.sync
+                            .sources()
+                            .filter_map(|s| self.sync[s].as_ref())
+                            .filter(|info| {
+                                !info.is_disconnected
+                                    && !info.warp_sync_unsupported
+                                    && info.peer_id != peer_id
+                            })
+                            .choose(&mut self.randomness)
+                            .map(|info| {
+                                let request = self.network_service.clone().warp_sync_request(
+                                    info.peer_id.clone(),
+                                    self.network_chain_id,
+                                    sync_start_block_hash,
+                                );
+                                (info.peer_id.clone(), request)
+                            })
+                    } else {
+                        None
+                    };
+
                     let request = self.network_service.clone().warp_sync_request(
                         peer_id,
                         self.network_chain_id,
@@ -1613,11 +2589,16 @@ impl SyncBackground {
                     }
 
                     self.sub_tasks.push(Box::pin(async move {
-                        let result = request.await;
+                        let (result, cross_check) = future::join(request, async move {
+                            let (peer_id, request) = cross_check_request?;
+                            Some((peer_id, request.await))
+                        })
+                        .await;
                         SubtaskFinished::WarpSyncRequestFinished {
                             request_id,
                             source_id,
                             result,
+                            cross_check,
                         }
                     }));
                 }
@@ -1792,7 +2773,7 @@ impl SyncBackground {
                 WakeUpReason::SubtaskFinished(SubtaskFinished::BlocksRequestFinished {
                     request_id,
                     source_id,
-                    result: Err(_),
+                    result: Err(error),
                 }) => {
                     if matches!(self.database_catch_up_download, DatabaseCatchUpDownload::InProgress(r) if r == request_id)
                     {
@@ -1807,6 +2788,11 @@ impl SyncBackground {
 
                     // Note that we perform the ban even if the source is now disconnected.
                     let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
+                    if let Some(misbehavior) = error.misbehavior() {
+                        self.network_service
+                            .report_misbehavior(peer_id.clone(), self.network_chain_id, misbehavior)
+                            .await;
+                    }
                     self.network_service
                         .ban_and_disconnect(
                             peer_id,
@@ -1836,10 +2822,73 @@ impl SyncBackground {
                     process_sync = true;
                 }
 
+                WakeUpReason::SubtaskFinished(
+                    SubtaskFinished::BlockRangeAnchorRequestFinished {
+                        peer_id,
+                        height,
+                        result,
+                    },
+                ) => {
+                    // The anchor might have been removed in the meanwhile if the best block has
+                    // reached its height, in which case the response is simply discarded.
+                    let Some(anchor) = self.block_range_anchors.get_mut(&height) else {
+                        continue;
+                    };
+                    *anchor = None;
+
+                    // The source of the peer might have been removed since the request started.
+                    let Some(source_id) = self.peers_source_id_map.get(&peer_id).copied() else {
+                        continue;
+                    };
+
+                    let scale_encoded_header = match result {
+                        Ok(mut blocks) if blocks.len() == 1 => blocks.remove(0).header,
+                        _ => None,
+                    };
+                    let Some(scale_encoded_header) = scale_encoded_header.filter(|header| {
+                        header::decode(header, self.sync.block_number_bytes())
+                            .map_or(false, |header| header.number == height)
+                    }) else {
+                        // The request can be retried with another peer. The peer isn't
+                        // punished, as its best block might have been reorganized since it has
+                        // been announced.
+                        self.block_range_anchors.remove(&height);
+                        self.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "block-range-anchor-request-error; peer_id={}; height={}",
+                                peer_id, height
+                            ),
+                        );
+                        continue;
+                    };
+
+                    // The anchor is inserted in the sync state machine as if the peer had
+                    // announced it. Its ancestry is then downloaded like for any other block
+                    // whose parent is unknown.
+                    match self
+                        .sync
+                        .block_announce(source_id, scale_encoded_header, false)
+                    {
+                        all::BlockAnnounceOutcome::TooOld { .. }
+                        | all::BlockAnnounceOutcome::InvalidHeader(_) => {}
+                        all::BlockAnnounceOutcome::AlreadyVerified(known)
+                        | all::BlockAnnounceOutcome::AlreadyPending(known) => {
+                            known.update_source_and_block();
+                        }
+                        all::BlockAnnounceOutcome::Unknown(unknown) => {
+                            unknown.insert_and_update_source(NonFinalizedBlock::NotVerified)
+                        }
+                    }
+
+                    process_sync = true;
+                }
+
                 WakeUpReason::SubtaskFinished(SubtaskFinished::WarpSyncRequestFinished {
                     request_id,
                     source_id,
                     result: Ok(result),
+                    cross_check,
                 }) => {
                     if matches!(self.database_catch_up_download, DatabaseCatchUpDownload::InProgress(r) if r == request_id)
                     {
@@ -1853,19 +2902,56 @@ impl SyncBackground {
                     }
 
                     let decoded = result.decode();
-                    let fragments = decoded
-                        .fragments
-                        .into_iter()
-                        .map(|f| all::WarpSyncFragment {
-                            scale_encoded_header: f.scale_encoded_header.to_vec(),
-                            scale_encoded_justification: f.scale_encoded_justification.to_vec(),
-                        })
-                        .collect();
-                    let _ = self.sync.grandpa_warp_sync_response(
-                        request_id,
-                        fragments,
-                        decoded.is_finished,
-                    );
+
+                    // If the response has been cross-checked with a second peer, make sure that
+                    // the two peers agree on the finalized blocks that they both report. An
+                    // honest peer might lag behind and report fewer fragments, but two blocks
+                    // finalized at the same height must be identical.
+                    // Because it isn't possible to know which of the two peers is lying, neither
+                    // is banned. The response is discarded and will be requested again.
+                    let mismatch = match &cross_check {
+                        Some((cross_check_peer_id, Ok(cross_check_result))) => {
+                            warp_sync_fragments_mismatch(
+                                &decoded,
+                                &cross_check_result.decode(),
+                                self.sync.block_number_bytes(),
+                            )
+                            .map(|mismatch| (cross_check_peer_id, mismatch))
+                        }
+                        _ => None,
+                    };
+
+                    if let Some((cross_check_peer_id, (block_number, hash, cross_check_hash))) =
+                        mismatch
+                    {
+                        self.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "warp-sync-cross-check-mismatch; peer_id={}; cross_check_peer_id={}; block_number={}; hash={}; cross_check_hash={}",
+                                self.sync[source_id].as_ref().unwrap().peer_id,
+                                cross_check_peer_id,
+                                block_number,
+                                HashDisplay(&hash),
+                                HashDisplay(&cross_check_hash),
+                            ),
+                        );
+
+                        self.sync.remove_request(request_id);
+                    } else {
+                        let fragments = decoded
+                            .fragments
+                            .into_iter()
+                            .map(|f| all::WarpSyncFragment {
+                                scale_encoded_header: f.scale_encoded_header.to_vec(),
+                                scale_encoded_justification: f.scale_encoded_justification.to_vec(),
+                            })
+                            .collect();
+                        let _ = self.sync.grandpa_warp_sync_response(
+                            request_id,
+                            fragments,
+                            decoded.is_finished,
+                        );
+                    }
 
                     // If the source was actually disconnected and has no other request in
                     // progress, we clean it up.
@@ -1888,7 +2974,8 @@ impl SyncBackground {
                 WakeUpReason::SubtaskFinished(SubtaskFinished::WarpSyncRequestFinished {
                     request_id,
                     source_id,
-                    result: Err(_),
+                    result: Err(error),
+                    ..
                 }) => {
                     if matches!(self.database_catch_up_download, DatabaseCatchUpDownload::InProgress(r) if r == request_id)
                     {
@@ -1901,16 +2988,31 @@ impl SyncBackground {
                             DatabaseCatchUpDownloadBlockVerification::None;
                     }
 
-                    // Note that we perform the ban even if the source is now disconnected.
-                    let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
-                    self.network_service
-                        .ban_and_disconnect(
-                            peer_id,
-                            self.network_chain_id,
-                            network_service::BanSeverity::Low,
-                            "warp-sync-request-error",
-                        )
-                        .await;
+                    if error.is_protocol_not_available() {
+                        // The peer isn't misbehaving, and is simply no longer asked for warp
+                        // sync fragments.
+                        self.sync[source_id].as_mut().unwrap().warp_sync_unsupported = true;
+                    } else {
+                        // Note that we perform the ban even if the source is now disconnected.
+                        let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
+                        if let Some(misbehavior) = error.misbehavior() {
+                            self.network_service
+                                .report_misbehavior(
+                                    peer_id.clone(),
+                                    self.network_chain_id,
+                                    misbehavior,
+                                )
+                                .await;
+                        }
+                        self.network_service
+                            .ban_and_disconnect(
+                                peer_id,
+                                self.network_chain_id,
+                                network_service::BanSeverity::Low,
+                                "warp-sync-request-error",
+                            )
+                            .await;
+                    }
 
                     let _ = self.sync.remove_request(request_id);
 
@@ -1949,68 +3051,36 @@ impl SyncBackground {
                     }
 
                     if let Ok(result) = &result {
-                        let result = result.clone();
-                        self.database
-                            .with_database(move |database| {
-                                if let Ok(decoded) = trie::proof_decode::decode_and_verify_proof(
-                                    trie::proof_decode::Config {
-                                        proof: result.decode(),
-                                    },
-                                ) {
-                                    for (_, entry) in decoded.iter_ordered() {
-                                        // TODO: check the state root hash; while this can't lead to a vulnerability, it can bloat the database
-                                        database.insert_trie_nodes(
-                                            iter::once(full_sqlite::InsertTrieNode {
-                                                merkle_value: Cow::Borrowed(entry.merkle_value),
-                                                partial_key_nibbles: Cow::Owned(entry.partial_key_nibbles.into_iter().map(|n| u8::from(n)).collect()),
-                                                children_merkle_values: std::array::from_fn(|n| entry.trie_node_info.children.child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap()).merkle_value().map(Cow::Borrowed)),
-                                                storage_value: match entry.trie_node_info.storage_value {
-                                                    trie::proof_decode::StorageValue::HashKnownValueMissing(
-                                                        _,
-                                                    ) => return,
-                                                    trie::proof_decode::StorageValue::None => {
-                                                        full_sqlite::InsertTrieNodeStorageValue::NoValue
-                                                    }
-                                                    trie::proof_decode::StorageValue::Known {
-                                                        value, ..
-                                                    } => full_sqlite::InsertTrieNodeStorageValue::Value {
-                                                        value: Cow::Borrowed(value),
-                                                        references_merkle_value: false, // TODO:
-                                                    },
-                                                },
-                                            }),
-                                            match entry.trie_node_info.storage_value {
-                                                trie::proof_decode::StorageValue::None => 0, // TODO: ?!
-                                                trie::proof_decode::StorageValue::HashKnownValueMissing(
-                                                    ..
-                                                ) => return,
-                                                trie::proof_decode::StorageValue::Known {
-                                                    inline: true,
-                                                    ..
-                                                } => 0,
-                                                trie::proof_decode::StorageValue::Known {
-                                                    inline: false,
-                                                    ..
-                                                } => 1,
-                                            },
-                                        ).unwrap();
-                                    }
-                                }
-                            })
-                            .await;
+                        insert_merkle_proof_in_database(&self.database, result.clone()).await;
                     }
 
-                    if result.is_err() {
-                        // Note that we perform the ban even if the source is now disconnected.
-                        let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
-                        self.network_service
-                            .ban_and_disconnect(
-                                peer_id,
-                                self.network_chain_id,
-                                network_service::BanSeverity::Low,
-                                "storage-proof-request-error",
-                            )
-                            .await;
+                    if let Err(error) = &result {
+                        if error.is_protocol_not_available() {
+                            // The peer isn't misbehaving, and is simply no longer asked for
+                            // proofs.
+                            self.sync[source_id].as_mut().unwrap().light_unsupported = true;
+                        } else {
+                            // Note that we perform the ban even if the source is now
+                            // disconnected.
+                            let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
+                            if let Some(misbehavior) = error.misbehavior() {
+                                self.network_service
+                                    .report_misbehavior(
+                                        peer_id.clone(),
+                                        self.network_chain_id,
+                                        misbehavior,
+                                    )
+                                    .await;
+                            }
+                            self.network_service
+                                .ban_and_disconnect(
+                                    peer_id,
+                                    self.network_chain_id,
+                                    network_service::BanSeverity::Low,
+                                    "storage-proof-request-error",
+                                )
+                                .await;
+                        }
                     }
 
                     if let Ok(result) = result {
@@ -2056,70 +3126,37 @@ impl SyncBackground {
                             DatabaseCatchUpDownloadBlockVerification::None;
                     }
 
-                    // TODO: DRY with above
                     if let Ok(result) = &result {
-                        let result = result.clone();
-                        self.database
-                            .with_database(move |database| {
-                                if let Ok(decoded) = trie::proof_decode::decode_and_verify_proof(
-                                    trie::proof_decode::Config {
-                                        proof: result.decode(),
-                                    },
-                                ) {
-                                    for (_, entry) in decoded.iter_ordered() {
-                                        // TODO: check the state root hash; while this can't lead to a vulnerability, it can bloat the database
-                                        database.insert_trie_nodes(
-                                            iter::once(full_sqlite::InsertTrieNode {
-                                                merkle_value: Cow::Borrowed(entry.merkle_value),
-                                                partial_key_nibbles: Cow::Owned(entry.partial_key_nibbles.into_iter().map(|n| u8::from(n)).collect()),
-                                                children_merkle_values: std::array::from_fn(|n| entry.trie_node_info.children.child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap()).merkle_value().map(Cow::Borrowed)),
-                                                storage_value: match entry.trie_node_info.storage_value {
-                                                    trie::proof_decode::StorageValue::HashKnownValueMissing(
-                                                        _,
-                                                    ) => return,
-                                                    trie::proof_decode::StorageValue::None => {
-                                                        full_sqlite::InsertTrieNodeStorageValue::NoValue
-                                                    }
-                                                    trie::proof_decode::StorageValue::Known {
-                                                        value, ..
-                                                    } => full_sqlite::InsertTrieNodeStorageValue::Value {
-                                                        value: Cow::Borrowed(value),
-                                                        references_merkle_value: false, // TODO:
-                                                    },
-                                                },
-                                            }),
-                                            match entry.trie_node_info.storage_value {
-                                                trie::proof_decode::StorageValue::None => 0, // TODO: ?!
-                                                trie::proof_decode::StorageValue::HashKnownValueMissing(
-                                                    ..
-                                                ) => return,
-                                                trie::proof_decode::StorageValue::Known {
-                                                    inline: true,
-                                                    ..
-                                                } => 0,
-                                                trie::proof_decode::StorageValue::Known {
-                                                    inline: false,
-                                                    ..
-                                                } => 1,
-                                            },
-                                        ).unwrap();
-                                    }
-                                }
-                            })
-                            .await;
+                        insert_merkle_proof_in_database(&self.database, result.clone()).await;
                     }
 
-                    if result.is_err() {
-                        // Note that we perform the ban even if the source is now disconnected.
-                        let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
-                        self.network_service
-                            .ban_and_disconnect(
-                                peer_id,
-                                self.network_chain_id,
-                                network_service::BanSeverity::Low,
-                                "call-proof-request-error",
-                            )
-                            .await;
+                    if let Err(error) = &result {
+                        if error.is_protocol_not_available() {
+                            // The peer isn't misbehaving, and is simply no longer asked for
+                            // proofs.
+                            self.sync[source_id].as_mut().unwrap().light_unsupported = true;
+                        } else {
+                            // Note that we perform the ban even if the source is now
+                            // disconnected.
+                            let peer_id = self.sync[source_id].as_ref().unwrap().peer_id.clone();
+                            if let Some(misbehavior) = error.misbehavior() {
+                                self.network_service
+                                    .report_misbehavior(
+                                        peer_id.clone(),
+                                        self.network_chain_id,
+                                        misbehavior,
+                                    )
+                                    .await;
+                            }
+                            self.network_service
+                                .ban_and_disconnect(
+                                    peer_id,
+                                    self.network_chain_id,
+                                    network_service::BanSeverity::Low,
+                                    "call-proof-request-error",
+                                )
+                                .await;
+                        }
                     }
 
                     if let Ok(result) = result {
@@ -2172,6 +3209,217 @@ impl SyncBackground {
         }
     }
 
+    /// Initializes [`SyncBackground::block_authoring`] if it is `None`, then returns the moment
+    /// when the block authoring is ready to make progress.
+    ///
+    /// Returns `None` if no block can be authored on top of the current best block, for example
+    /// because the keystore doesn't contain any key of the consensus engine of the chain.
+    async fn prepare_block_authoring(&mut self) -> Option<Instant> {
+        if self.block_authoring.is_none() {
+            self.block_authoring = self.start_block_authoring().await;
+            self.block_authoring_idle_until = None;
+        }
+
+        match &self.block_authoring {
+            None => None,
+            Some((author::build::Builder::Ready(_), _)) => Some(Instant::now()),
+            Some((author::build::Builder::WaitSlot(when), _)) => {
+                let delay = (SystemTime::UNIX_EPOCH + when.when())
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_else(|_| Duration::new(0, 0));
+                Some(Instant::now() + delay)
+            }
+            Some((author::build::Builder::Idle, _)) => {
+                // If the block authoring is idle, which happens in case of error or if no slot
+                // can be claimed, sleep for an arbitrary duration before resetting it.
+                // This prevents the authoring from trying over and over again to generate a bad
+                // block.
+                Some(
+                    *self
+                        .block_authoring_idle_until
+                        .get_or_insert_with(|| Instant::now() + Duration::from_secs(2)),
+                )
+            }
+        }
+    }
+
+    /// Builds a new value for [`SyncBackground::block_authoring`], using the keys of the keystore
+    /// that belong to the consensus engine of the current best block.
+    async fn start_block_authoring(
+        &mut self,
+    ) -> Option<(
+        author::build::Builder,
+        Vec<(keystore::KeyNamespace, [u8; 32])>,
+    )> {
+        let namespace = match self.sync.best_block_consensus() {
+            chain_information::ChainInformationConsensusRef::Aura { .. } => {
+                keystore::KeyNamespace::Aura
+            }
+            chain_information::ChainInformationConsensusRef::Babe { .. } => {
+                keystore::KeyNamespace::Babe
+            }
+            // Blocks can't be authored if the consensus engine of the chain isn't known.
+            chain_information::ChainInformationConsensusRef::Unknown => return None,
+        };
+
+        // Calling `keys()` on the keystore is racy, but that's considered acceptable and part of
+        // the design of the node.
+        let local_authorities = self
+            .keystore
+            .keys()
+            .await
+            .filter(|(key_namespace, _)| *key_namespace == namespace)
+            .collect::<Vec<_>>();
+        if local_authorities.is_empty() {
+            return None;
+        }
+
+        if namespace == keystore::KeyNamespace::Babe && self.babe_slot_duration.is_none() {
+            self.babe_slot_duration = self.query_babe_slot_duration().await;
+        }
+
+        let now_from_unix_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let slot_candidate = match self.sync.best_block_consensus() {
+            chain_information::ChainInformationConsensusRef::Aura {
+                finalized_authorities_list, // TODO: field name not appropriate; should probably change the chain_information module
+                slot_duration,
+            } => {
+                let builder = author::build::Builder::new(author::build::Config {
+                    consensus: author::build::ConfigConsensus::Aura {
+                        current_authorities: finalized_authorities_list,
+                        local_authorities: local_authorities.iter().map(|(_, key)| key),
+                        now_from_unix_epoch,
+                        slot_duration,
+                    },
+                });
+                return Some((builder, local_authorities));
+            }
+            chain_information::ChainInformationConsensusRef::Babe {
+                slots_per_epoch,
+                finalized_block_epoch_information, // TODO: field names not appropriate; same as above
+                finalized_next_epoch_transition,
+            } => {
+                // The error has been logged when querying the slot duration.
+                let Some(slot_duration) = self.babe_slot_duration else {
+                    return Some((author::build::Builder::Idle, local_authorities));
+                };
+
+                let parent_slot_number = header::decode(
+                    self.sync.best_block_header(),
+                    self.sync.block_number_bytes(),
+                )
+                .ok()
+                .and_then(|h| h.digest.babe_pre_runtime().map(|pr| pr.slot_number()));
+
+                author::babe::next_slot(author::babe::Config {
+                    now_from_unix_epoch,
+                    slot_duration,
+                    slots_per_epoch,
+                    parent_slot_number,
+                    parent_block_epoch: finalized_block_epoch_information,
+                    parent_block_next_epoch: finalized_next_epoch_transition,
+                    local_authorities: local_authorities.iter().map(|(_, key)| key),
+                })
+            }
+            chain_information::ChainInformationConsensusRef::Unknown => unreachable!(),
+        };
+
+        // Contrary to Aura, claiming a Babe slot requires generating a VRF output with each
+        // local key that belongs to the epoch.
+        let slot_claim = match slot_candidate {
+            Some(candidate) => {
+                let mut vrf_outputs = Vec::with_capacity(candidate.local_authorities().len());
+                for local_authorities_index in candidate.local_authorities() {
+                    let (label, transcript_items) = candidate.vrf_transcript();
+                    match self
+                        .keystore
+                        .sign_sr25519_vrf(
+                            keystore::KeyNamespace::Babe,
+                            &local_authorities[local_authorities_index].1,
+                            label,
+                            transcript_items,
+                        )
+                        .await
+                    {
+                        Ok(signature) => vrf_outputs.push((
+                            local_authorities_index,
+                            signature.output,
+                            signature.proof,
+                        )),
+                        Err(error) => {
+                            // Because the keystore is subject to race conditions, the key might
+                            // have been removed in the meanwhile.
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!("block-author-vrf-error; error={}", error),
+                            );
+                        }
+                    }
+                }
+                candidate.claim(vrf_outputs.into_iter())
+            }
+            None => None,
+        };
+
+        // If the slot can't be claimed, the builder is idle and a new slot will be tried later.
+        let builder = match slot_claim {
+            Some(slot_claim) => {
+                author::build::Builder::new(author::build::Config::<iter::Empty<&[u8; 32]>> {
+                    consensus: author::build::ConfigConsensus::Babe {
+                        now_from_unix_epoch,
+                        slot_claim,
+                    },
+                })
+            }
+            None => author::build::Builder::Idle,
+        };
+
+        Some((builder, local_authorities))
+    }
+
+    /// Calls `BabeApi_configuration` on the finalized block and returns the slot duration it
+    /// reports. Returns `None` and logs an error if the call fails.
+    async fn query_babe_slot_duration(&mut self) -> Option<NonZeroU64> {
+        let result = runtime_call(
+            &self.database,
+            self.sync.finalized_block_hash(),
+            (*self.finalized_runtime).clone(),
+            "BabeApi_configuration",
+            &[],
+            runtime_call::StorageProofSizeBehavior::Unimplemented,
+            runtime_call::StorageChanges::empty(),
+            false,
+        )
+        .await;
+
+        // The slot duration is the first field of the output, encoded as a little endian `u64`.
+        let slot_duration = match &result {
+            Ok(success) => success
+                .output
+                .get(..8)
+                .and_then(|bytes| NonZeroU64::new(u64::from_le_bytes(bytes.try_into().unwrap()))),
+            Err(_) => None,
+        };
+
+        if slot_duration.is_none() {
+            self.log_callback.log(
+                LogLevel::Warn,
+                format!(
+                    "block-author-babe-configuration-error; error={}",
+                    match result {
+                        Ok(_) => "invalid output".to_owned(),
+                        Err(error) => error.to_string(),
+                    }
+                ),
+            );
+        }
+
+        slot_duration
+    }
+
     /// Authors a block, then imports it and gossips it out.
     ///
     /// # Panic
@@ -2186,6 +3434,21 @@ impl SyncBackground {
             _ => panic!(),
         };
 
+        let _permit = self.runtime_calls_limiter.consensus_permit();
+
+        self.authoring_stats.eligible_slots += 1;
+
+        // A block authored while not connected to any peer can't be propagated. The slot is
+        // counted as missed, but the block is authored anyway, as this is the normal situation
+        // for a chain that consists of a single node.
+        if self.network_service.num_peers(self.network_chain_id).await == 0 {
+            self.authoring_stats.missed_slots_no_peers += 1;
+            self.log_callback.log(
+                LogLevel::Warn,
+                "block-author-slot-missed; reason=no-peers".to_string(),
+            );
+        }
+
         // TODO: it is possible that the current best block is already the same authoring slot as the slot we want to claim ; unclear how to solve this
 
         let parent_number = self.sync.best_block_number();
@@ -2212,11 +3475,51 @@ impl SyncBackground {
         // Most parts of the block authorship can't be accelerated, in particular the
         // initialization and the signing at the end. This end of authoring threshold is only
         // checked when deciding whether to continue including more transactions in the block.
-        // TODO: use this
-        // TODO: Substrate nodes increase the time available for authoring if it detects that slots have been skipped, in order to account for the possibility that the initialization of a block or the inclusion of an extrinsic takes too long
+        //
+        // Similar to Substrate, if slots have been skipped since the best block, the time
+        // available for authoring is increased, in order to account for the possibility that the
+        // initialization of a block or the inclusion of an extrinsic takes longer than usual.
+        let slot_lenience = {
+            let start = authoring_start.slot_start_from_unix_epoch();
+            let slot_duration = authoring_start.slot_end_from_unix_epoch() - start;
+            let parent_slot_number = header::decode(
+                self.sync.best_block_header(),
+                self.sync.block_number_bytes(),
+            )
+            .ok()
+            .and_then(|header| {
+                header
+                    .digest
+                    .aura_pre_runtime()
+                    .map(|digest| digest.slot_number)
+                    .or_else(|| {
+                        header
+                            .digest
+                            .babe_pre_runtime()
+                            .map(|digest| digest.slot_number())
+                    })
+            });
+            let num_skipped_slots = match parent_slot_number {
+                Some(parent_slot_number) => (start.as_millis() / slot_duration.as_millis().max(1))
+                    .saturating_sub(u128::from(parent_slot_number) + 1),
+                // The parent is the genesis block.
+                None => 0,
+            };
+            slot_duration
+                .saturating_mul(u32::try_from(num_skipped_slots).unwrap_or(u32::MAX))
+                .min(self.max_slot_lenience)
+        };
+        if slot_lenience != Duration::new(0, 0) {
+            self.log_callback.log(
+                LogLevel::Debug,
+                format!("block-author-slot-lenience; lenience={:?}", slot_lenience),
+            );
+        }
+        let slot_end =
+            SystemTime::UNIX_EPOCH + authoring_start.slot_end_from_unix_epoch() + slot_lenience;
         let authoring_end = {
             let start = authoring_start.slot_start_from_unix_epoch();
-            let end = authoring_start.slot_end_from_unix_epoch();
+            let end = authoring_start.slot_end_from_unix_epoch() + slot_lenience;
             debug_assert!(start < end);
             debug_assert!(SystemTime::now() >= SystemTime::UNIX_EPOCH + start);
             SystemTime::UNIX_EPOCH
@@ -2241,15 +3544,32 @@ impl SyncBackground {
                 };
             let parent_runtime = (*parent_runtime_arc).clone();
 
+            let now_from_unix_epoch = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap();
+
+            // Query the inherents that aren't built in.
+            let additional_inherents =
+                future::join_all(self.inherent_data_providers.iter().map(|provider| {
+                    provider.provide(
+                        parent_hash,
+                        self.sync.best_block_number(),
+                        now_from_unix_epoch,
+                    )
+                }))
+                .await
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+
             // Start the block authoring process.
             let mut block_authoring = {
                 authoring_start.start(author::build::AuthoringStartConfig {
                     block_number_bytes: self.sync.block_number_bytes(),
                     parent_hash: &self.sync.best_block_hash(),
                     parent_number: self.sync.best_block_number(),
-                    now_from_unix_epoch: SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap(),
+                    now_from_unix_epoch,
+                    additional_inherents,
                     parent_runtime,
                     block_body_capacity: 0, // TODO: could be set to the size of the tx pool
                     max_log_level: 0,
@@ -2257,6 +3577,15 @@ impl SyncBackground {
                 })
             };
 
+            // Transactions of the pool to include in the block. Their total size is guaranteed
+            // to not exceed the limit.
+            let mut block_transactions = self
+                .transactions_pool
+                .block_transactions(self.max_block_transactions_size);
+
+            // Transaction most recently passed to the runtime, if any.
+            let mut pending_transaction = None::<Vec<u8>>;
+
             // The block authoring process jumps through various states, interrupted when it needs
             // access to the storage of the best block.
             loop {
@@ -2266,13 +3595,12 @@ impl SyncBackground {
                         // successful, and the only thing remaining to do is sign the block
                         // header. Signing is done through `self.keystore`.
 
-                        // TODO: correct key namespace
                         let data_to_sign = seal.to_sign();
-                        let sign_future = self.keystore.sign(
-                            keystore::KeyNamespace::Aura,
-                            &local_authorities[seal.authority_index()],
-                            &data_to_sign,
-                        );
+                        let (key_namespace, public_key) =
+                            &local_authorities[seal.authority_index()];
+                        let sign_future =
+                            self.keystore
+                                .sign(*key_namespace, public_key, &data_to_sign);
 
                         let success = match sign_future.await {
                             Ok(signature) => seal.inject_sr25519_signature(signature),
@@ -2287,6 +3615,9 @@ impl SyncBackground {
                                     format!("block-author-signing-error; error={}", error),
                                 );
                                 self.block_authoring = None;
+                                self.authoring_stats.missed_slots_error += 1;
+                                Self::save_authoring_stats(&self.database, &self.authoring_stats)
+                                    .await;
                                 return;
                             }
                         };
@@ -2306,29 +3637,64 @@ impl SyncBackground {
                             LogLevel::Warn,
                             format!("block-author-error; error={}", error),
                         );
+                        self.authoring_stats.missed_slots_error += 1;
+                        Self::save_authoring_stats(&self.database, &self.authoring_stats).await;
                         return;
                     }
 
                     // Part of the block production consists in adding transactions to the block.
                     // These transactions are extracted from the transactions pool.
                     author::build::BuilderAuthoring::ApplyExtrinsic(apply) => {
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = apply.finish();
+                        // Transactions are included as long as the end of the authoring hasn't
+                        // been reached.
+                        pending_transaction = if SystemTime::now() < authoring_end {
+                            block_transactions.next_transaction()
+                        } else {
+                            None
+                        };
+                        block_authoring = match &pending_transaction {
+                            Some(transaction) => apply.add_extrinsic(transaction.clone()),
+                            None => apply.finish(),
+                        };
                     }
                     author::build::BuilderAuthoring::ApplyExtrinsicResult { result, resume } => {
                         if let Err(error) = result {
-                            // TODO: include transaction bytes or something?
+                            block_transactions.report_not_included();
+
+                            // Transactions that are invalid will never be included and are
+                            // removed from the pool, while the validity of the other ones
+                            // might change in the future.
+                            let transaction = pending_transaction.take().unwrap();
+                            if matches!(
+                                error,
+                                author::runtime::TransactionValidityError::Invalid(_)
+                            ) {
+                                self.transactions_pool.remove(&transaction);
+                            }
+
                             self.log_callback.log(
                                 LogLevel::Warn,
                                 format!(
-                                    "block-author-transaction-inclusion-error; error={}",
+                                    "block-author-transaction-inclusion-error; transaction={}; \
+                                    error={}",
+                                    HashDisplay(
+                                        &blake2_rfc::blake2b::blake2b(32, &[], &transaction)
+                                            .as_bytes()
+                                    ),
                                     error
                                 ),
                             );
                         }
 
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = resume.finish();
+                        pending_transaction = if SystemTime::now() < authoring_end {
+                            block_transactions.next_transaction()
+                        } else {
+                            None
+                        };
+                        block_authoring = match &pending_transaction {
+                            Some(transaction) => resume.add_extrinsic(transaction.clone()),
+                            None => resume.finish(),
+                        };
                     }
 
                     // Access to the best block storage.
@@ -2464,6 +3830,24 @@ impl SyncBackground {
             }
         }
 
+        // A block generated after the end of its slot will be refused by the rest of the network,
+        // unless slots have been skipped, in which case the slot lenience is taken into account.
+        if SystemTime::now() > slot_end {
+            self.authoring_stats.missed_slots_late += 1;
+            self.log_callback.log(
+                LogLevel::Warn,
+                format!(
+                    "block-author-slot-missed; reason=late; hash={}",
+                    HashDisplay(&new_block_hash)
+                ),
+            );
+        }
+
+        self.authoring_stats.authored_blocks += 1;
+        self.authored_blocks_pending_finality
+            .push((new_block_hash, false));
+        Self::save_authoring_stats(&self.database, &self.authoring_stats).await;
+
         // Switch the block authoring to a state where we won't try to generate a new block again
         // until something new happens.
         // TODO: nothing prevents the node from generating two blocks at the same height at the moment
@@ -2489,6 +3873,282 @@ impl SyncBackground {
         ));
     }
 
+    /// Called when a block is queued with [`ConsensusService::queue_block_import`].
+    fn queue_block_import(
+        &mut self,
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_extrinsics: Vec<Vec<u8>>,
+        justifications: Vec<([u8; 4], Vec<u8>)>,
+    ) -> Result<QueueBlockImportOutcome, QueueBlockImportError> {
+        let decoded_header = header::decode(&scale_encoded_header, self.sync.block_number_bytes())
+            .map_err(QueueBlockImportError::InvalidHeader)?;
+        let block_hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+        let block_number = decoded_header.number;
+        let parent_hash = *decoded_header.parent_hash;
+
+        let finalized_block_number = self.sync.finalized_block_number();
+        if block_number <= finalized_block_number
+            || self.queued_imported_blocks.contains_key(&block_hash)
+        {
+            return Ok(QueueBlockImportOutcome::AlreadyKnown);
+        }
+
+        // The sync state machine would otherwise try to download the ancestors of the block from
+        // the networking peers, which isn't what the API user wants.
+        let parent_known = if block_number - 1 == finalized_block_number {
+            parent_hash == *self.sync.finalized_block_hash()
+        } else {
+            self.queued_imported_blocks.contains_key(&parent_hash)
+                || self
+                    .sync
+                    .knows_non_finalized_block(block_number - 1, &parent_hash)
+                    .next()
+                    .is_some()
+        };
+        if !parent_known {
+            return Err(QueueBlockImportError::UnknownParent);
+        }
+
+        self.prune_queued_imported_blocks();
+        if self.queued_imported_blocks.len() >= MAX_QUEUED_IMPORTED_BLOCKS {
+            return Err(QueueBlockImportError::QueueFull);
+        }
+
+        match self.sync.block_announce(
+            self.block_import_sync_source,
+            scale_encoded_header.clone(),
+            true,
+        ) {
+            all::BlockAnnounceOutcome::TooOld { .. }
+            | all::BlockAnnounceOutcome::AlreadyVerified(_) => {
+                return Ok(QueueBlockImportOutcome::AlreadyKnown);
+            }
+            all::BlockAnnounceOutcome::AlreadyPending(known) => {
+                known.update_source_and_block();
+            }
+            all::BlockAnnounceOutcome::Unknown(unknown) => {
+                unknown.insert_and_update_source(NonFinalizedBlock::NotVerified);
+            }
+            all::BlockAnnounceOutcome::InvalidHeader(error) => {
+                return Err(QueueBlockImportError::InvalidHeader(error));
+            }
+        }
+
+        self.queued_imported_blocks.insert(
+            block_hash,
+            (
+                block_number,
+                scale_encoded_header,
+                scale_encoded_extrinsics,
+                justifications
+                    .into_iter()
+                    .map(|(engine_id, justification)| all::Justification {
+                        engine_id,
+                        justification,
+                    })
+                    .collect(),
+            ),
+        );
+
+        Ok(QueueBlockImportOutcome::Queued)
+    }
+
+    /// Removes from [`SyncBackground::queued_imported_blocks`] the blocks that have been
+    /// finalized in the meanwhile, for example because they have been downloaded from the
+    /// network before the `sync` requested them from the local source.
+    fn prune_queued_imported_blocks(&mut self) {
+        let finalized_block_number = self.sync.finalized_block_number();
+        self.queued_imported_blocks
+            .retain(|_, (height, ..)| *height > finalized_block_number);
+    }
+
+    /// Updates the state of the service after blocks have been finalized in
+    /// [`SyncBackground::sync`], either following the verification of a finality proof or
+    /// after [`ConsensusService::force_finalize`] has been called.
+    ///
+    /// Must only be called if [`SyncBackground::pending_notification`] is `None`.
+    async fn on_new_finalized(
+        &mut self,
+        finalized_blocks_newest_to_oldest: Vec<all::Block<NonFinalizedBlock>>,
+        pruned_blocks: Vec<[u8; 32]>,
+        updates_best_block: bool,
+    ) {
+        if updates_best_block {
+            // Update the networking.
+            self.network_local_chain_update_needed = true;
+            // Reset the block authoring, in order to potentially build a
+            // block on top of this new best.
+            self.block_authoring = None;
+        }
+
+        self.finalized_runtime = match &finalized_blocks_newest_to_oldest.first().unwrap().user_data
+        {
+            NonFinalizedBlock::Verified { runtime } => runtime.clone(),
+            _ => unreachable!(),
+        };
+        // TODO: what if best block changed?
+        let new_finalized_hash = finalized_blocks_newest_to_oldest
+            .first()
+            .unwrap()
+            .block_hash;
+        let finalized_chain_only = self.finalized_chain_only;
+        let state_pins = self.database.state_pins();
+        self.database
+            .with_database_detached(move |database| {
+                database.set_finalized(&new_finalized_hash).unwrap();
+                if finalized_chain_only {
+                    // Blocks whose state is pinned are kept, and will be purged
+                    // during a later finalization once they are unpinned.
+                    database
+                        .purge_finality_orphans_except(|hash| state_pins.is_pinned(hash))
+                        .unwrap();
+                }
+            })
+            .await;
+
+        // Update the statistics of the locally-authored blocks that are now
+        // either finalized or pruned.
+        let num_authored_before = self.authored_blocks_pending_finality.len();
+        self.authored_blocks_pending_finality.retain(|(hash, _)| {
+            if finalized_blocks_newest_to_oldest
+                .iter()
+                .any(|b| b.block_hash == *hash)
+            {
+                self.authoring_stats.authored_blocks_finalized += 1;
+                false
+            } else {
+                !pruned_blocks.contains(hash)
+            }
+        });
+        if self.authored_blocks_pending_finality.len() != num_authored_before {
+            Self::save_authoring_stats(&self.database, &self.authoring_stats).await;
+        }
+
+        // Notify the subscribers.
+        debug_assert!(self.pending_notification.is_none());
+        self.pending_notification = Some(Notification::Finalized {
+            finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
+                .iter()
+                .map(|b| b.block_hash)
+                .collect::<Vec<_>>(),
+            pruned_blocks_hashes: pruned_blocks,
+            best_block_hash: *self.sync.best_block_hash(),
+        });
+    }
+
+    /// Returns the number of requests in progress towards the given source, including the
+    /// requests for block range anchors, which the sync state machine isn't aware of.
+    ///
+    /// Takes the fields as parameters rather than `&self`, as it is called from within futures
+    /// that borrow other fields of `self`.
+    fn source_num_ongoing_requests(
+        sync: &all::AllSync<(), Option<NetworkSourceInfo>, NonFinalizedBlock>,
+        block_range_anchors: &BTreeMap<u64, Option<libp2p::PeerId>>,
+        source_id: all::SourceId,
+        info: &NetworkSourceInfo,
+    ) -> usize {
+        sync.source_num_ongoing_requests(source_id)
+            + block_range_anchors
+                .values()
+                .filter(|peer_id| peer_id.as_ref() == Some(&info.peer_id))
+                .count()
+    }
+
+    /// Returns `true` if the given request can be started towards the given remote source, in
+    /// other words if the source isn't known to not support the protocol of the request and if
+    /// the maximum number of requests in progress towards this source hasn't been reached.
+    ///
+    /// Takes the fields as parameters rather than `&self`, for the same reason as
+    /// [`SyncBackground::source_num_ongoing_requests`].
+    fn can_start_request(
+        sync: &all::AllSync<(), Option<NetworkSourceInfo>, NonFinalizedBlock>,
+        block_range_anchors: &BTreeMap<u64, Option<libp2p::PeerId>>,
+        max_requests_per_source: NonZeroUsize,
+        source_id: all::SourceId,
+        info: &NetworkSourceInfo,
+        request: &all::DesiredRequest,
+    ) -> bool {
+        Self::source_num_ongoing_requests(sync, block_range_anchors, source_id, info)
+            < max_requests_per_source.get()
+            && info.supports_request(request)
+    }
+
+    /// Determines whether the anchor of a block range ahead of the best block should be
+    /// requested, and if so returns the source to request it from and its height.
+    ///
+    /// See [`SyncLimits::parallel_block_ranges`].
+    fn block_range_anchor_to_request(&mut self) -> Option<(all::SourceId, u64)> {
+        // Anchors at or below the best block are no longer useful.
+        let best_block_number = self.sync.best_block_number();
+        self.block_range_anchors = self
+            .block_range_anchors
+            .split_off(&best_block_number.saturating_add(1));
+
+        if self.block_range_anchors.len() >= self.parallel_block_ranges {
+            return None;
+        }
+
+        let max_height =
+            best_block_number.saturating_add(u64::from(self.download_ahead_blocks.get()));
+        let first_height = (best_block_number / MAX_BLOCKS_PER_REQUEST)
+            .saturating_add(1)
+            .saturating_mul(MAX_BLOCKS_PER_REQUEST);
+
+        // The anchors are requested from the lowest to the highest, as the lowest ones are the
+        // ones that are verified first.
+        for height in (0..u64::try_from(self.parallel_block_ranges).unwrap_or(u64::MAX))
+            .map(|n| first_height.saturating_add(n.saturating_mul(MAX_BLOCKS_PER_REQUEST)))
+            .take_while(|height| *height <= max_height)
+        {
+            if self.block_range_anchors.contains_key(&height) {
+                continue;
+            }
+
+            let candidates = self
+                .sync
+                .sources()
+                .filter(|source_id| {
+                    *source_id != self.block_author_sync_source
+                        && self.sync.source_best_block(*source_id).0 >= height
+                        && self.sync[*source_id].as_ref().map_or(false, |info| {
+                            !info.is_disconnected
+                                && Self::source_num_ongoing_requests(
+                                    &self.sync,
+                                    &self.block_range_anchors,
+                                    *source_id,
+                                    info,
+                                ) < self.max_requests_per_source.get()
+                        })
+                })
+                .collect::<Vec<_>>();
+            let source_id = candidates.into_iter().choose(&mut self.randomness);
+
+            // Sources are filtered by their best block, meaning that if no source is available
+            // for this height, it is possible that one is available for a lower height but not
+            // for a higher one. Since the heights are iterated in increasing order, we can stop
+            // iterating.
+            return source_id.map(|source_id| (source_id, height));
+        }
+
+        None
+    }
+
+    /// Writes [`SyncBackground::authoring_stats`] to the database.
+    ///
+    /// Takes the fields as parameters rather than `&self`, as it is also called while
+    /// [`SyncBackground::sync`] is temporarily moved out.
+    async fn save_authoring_stats(
+        database: &database_thread::DatabaseThread,
+        stats: &full_sqlite::AuthoringStats,
+    ) {
+        let stats = stats.clone();
+        database
+            .with_database_detached(move |database| {
+                database.set_authoring_stats(&stats).unwrap();
+            })
+            .await;
+    }
+
     async fn process_blocks(mut self) -> (Self, bool) {
         // The sync state machine can be in a few various states. At the time of writing:
         // idle, verifying header, verifying block, verifying grandpa warp sync proof,
@@ -2512,7 +4172,7 @@ impl SyncBackground {
                     .proof_sender()
                     .map(|(_, s)| s.as_ref().unwrap().peer_id.clone());
 
-                let (new_sync, outcome) = verify.perform(rand::random());
+                let (new_sync, outcome) = verify.perform(self.randomness.gen());
                 self.sync = new_sync;
                 match outcome {
                     Ok((fragment_hash, fragment_height)) => {
@@ -2527,6 +4187,13 @@ impl SyncBackground {
                     }
                     Err(err) => {
                         if let Some(sender) = &sender {
+                            self.network_service
+                                .report_misbehavior(
+                                    sender.clone(),
+                                    self.network_chain_id,
+                                    network_service::PeerMisbehavior::BadWarpSyncFragment,
+                                )
+                                .await;
                             self.network_service
                                 .ban_and_disconnect(
                                     sender.clone(),
@@ -2650,6 +4317,31 @@ impl SyncBackground {
                         }
                     };
 
+                if let Some(max_reorg_depth) = self.max_reorg_depth {
+                    let reorg_depth = header_verification_success.reorg_depth();
+                    if reorg_depth > max_reorg_depth {
+                        // The block is checked even if it doesn't become the new best block, as
+                        // its descendants could otherwise later cause the reorganization.
+                        // Print a warning because a fork this deep indicates that something is
+                        // very wrong with the network.
+                        self.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "reorg-depth-exceeded; hash={}; height={}; reorg_depth={}; \
+                                max_reorg_depth={}",
+                                HashDisplay(&hash_to_verify),
+                                header_verification_success.height(),
+                                reorg_depth,
+                                max_reorg_depth,
+                            ),
+                        );
+                        // The block isn't necessarily invalid, and is therefore not marked as
+                        // bad. It can be downloaded again if a source announces it again.
+                        self.sync = header_verification_success.discard();
+                        return (self, true);
+                    }
+                }
+
                 let parent_info = header_verification_success.parent_user_data().map(|b| {
                     let NonFinalizedBlock::Verified { runtime } = b else {
                         unreachable!()
@@ -2667,20 +4359,102 @@ impl SyncBackground {
                 let scale_encoded_header =
                     header_verification_success.scale_encoded_header().to_vec();
 
-                let execute_block_success = match execute_block_and_insert(
-                    &self.database,
-                    (*parent_runtime_arc).clone(),
-                    &header_verification_success.parent_hash(),
-                    header_verification_success.scale_encoded_header(),
-                    block_number_bytes,
-                    header_verification_success
-                        .scale_encoded_extrinsics()
-                        .unwrap(),
-                    unix_time,
-                    is_new_best,
-                )
-                .await
-                {
+                // An authority that has produced two different blocks for the same slot has
+                // equivocated. The signature of the header has been verified above, meaning that
+                // the authority can't have been impersonated.
+                let slot_claim = (
+                    header_verification_success.slot_number(),
+                    *header_verification_success.author_public_key(),
+                );
+                match self.slot_claims.get(&slot_claim) {
+                    Some((first_hash, _)) if *first_hash == hash_to_verify => {}
+                    Some((first_hash, first_header)) => {
+                        self.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "block-equivocation; slot={}; authority={}; first={}; second={}",
+                                slot_claim.0,
+                                HashDisplay(&slot_claim.1),
+                                HashDisplay(first_hash),
+                                HashDisplay(&hash_to_verify)
+                            ),
+                        );
+
+                        // Aura doesn't provide any way to report equivocations.
+                        let is_babe = header::decode(&scale_encoded_header, block_number_bytes)
+                            .map_or(false, |header| header.digest.has_any_babe());
+                        if let Some(equivocation_reports) =
+                            self.equivocation_reports.as_ref().filter(|_| is_babe)
+                        {
+                            let _ = equivocation_reports.try_send(
+                                equivocation_reporter::Equivocation::Babe {
+                                    offender: slot_claim.1,
+                                    slot: slot_claim.0,
+                                    first_header: first_header.clone(),
+                                    second_header: scale_encoded_header.clone(),
+                                },
+                            );
+                        }
+                    }
+                    None => {
+                        self.slot_claims
+                            .put(slot_claim, (hash_to_verify, scale_encoded_header.clone()));
+                    }
+                }
+
+                let execution_permit = self.runtime_calls_limiter.consensus_permit();
+                let execute_block_result =
+                    if let Some(runtime_execution_threads) = &self.runtime_execution_threads {
+                        // The parameters are copied so that the execution can be moved to a
+                        // different thread.
+                        let database = self.database.clone();
+                        let compiled_runtimes_cache = self.compiled_runtimes_cache.clone();
+                        let offchain_indexing = self.offchain_indexing;
+                        let parent_runtime = (*parent_runtime_arc).clone();
+                        let parent_hash = *header_verification_success.parent_hash();
+                        let block_body = header_verification_success
+                            .scale_encoded_extrinsics()
+                            .unwrap()
+                            .map(|extrinsic| extrinsic.as_ref().to_vec())
+                            .collect::<Vec<_>>();
+                        let scale_encoded_header = scale_encoded_header.clone();
+                        runtime_execution_threads
+                            .run(async move {
+                                execute_block_and_insert(
+                                    &database,
+                                    compiled_runtimes_cache.as_deref(),
+                                    parent_runtime,
+                                    &parent_hash,
+                                    &scale_encoded_header,
+                                    block_number_bytes,
+                                    block_body.iter(),
+                                    unix_time,
+                                    is_new_best,
+                                    offchain_indexing,
+                                )
+                                .await
+                            })
+                            .await
+                    } else {
+                        execute_block_and_insert(
+                            &self.database,
+                            self.compiled_runtimes_cache.as_deref(),
+                            (*parent_runtime_arc).clone(),
+                            header_verification_success.parent_hash(),
+                            header_verification_success.scale_encoded_header(),
+                            block_number_bytes,
+                            header_verification_success
+                                .scale_encoded_extrinsics()
+                                .unwrap(),
+                            unix_time,
+                            is_new_best,
+                            self.offchain_indexing,
+                        )
+                        .await
+                    };
+                drop(execution_permit);
+
+                let execute_block_success = match execute_block_result {
                     Ok(success) => success,
                     Err(ExecuteBlockError::VerificationFailure(
                         ExecuteBlockVerificationFailureError::DatabaseParentAccess {
@@ -2779,12 +4553,100 @@ impl SyncBackground {
                     ),
                 );
 
+                if is_new_best {
+                    if let Some((_, counted_best)) = self
+                        .authored_blocks_pending_finality
+                        .iter_mut()
+                        .find(|(hash, counted_best)| *hash == hash_to_verify && !*counted_best)
+                    {
+                        *counted_best = true;
+                        self.authoring_stats.authored_blocks_best += 1;
+                        Self::save_authoring_stats(&self.database, &self.authoring_stats).await;
+                    }
+                }
+
                 match execute_block_success.block_insertion {
                     Ok(()) => {}
                     Err(full_sqlite::InsertError::Duplicate) => {} // TODO: this should be an error ; right now we silence them because non-finalized blocks aren't loaded from the database at startup, resulting in them being downloaded again
                     Err(error) => panic!("failed to insert block in database: {error}"),
                 }
 
+                if let Some(block_execution_profiles) = &mut self.block_execution_profiles {
+                    // The parameters are copied so that the execution can be moved to a
+                    // different thread.
+                    let database = self.database.clone();
+                    let parent_runtime = (*parent_runtime_arc).clone();
+                    let parent_hash = *header_verification_success.parent_hash();
+                    let block_body = header_verification_success
+                        .scale_encoded_extrinsics()
+                        .unwrap()
+                        .map(|extrinsic| extrinsic.as_ref().to_vec())
+                        .collect::<Vec<_>>();
+                    let scale_encoded_header = scale_encoded_header.clone();
+                    let profiling = async move {
+                        profile_block(
+                            &database,
+                            parent_runtime,
+                            &parent_hash,
+                            &scale_encoded_header,
+                            block_number_bytes,
+                            &block_body,
+                        )
+                        .await
+                    };
+                    let profiling_permit = self.runtime_calls_limiter.consensus_permit();
+                    let profile_result =
+                        if let Some(runtime_execution_threads) = &self.runtime_execution_threads {
+                            runtime_execution_threads.run(profiling).await
+                        } else {
+                            profiling.await
+                        };
+                    drop(profiling_permit);
+
+                    match profile_result {
+                        Ok(profile) => {
+                            block_execution_profiles.put(hash_to_verify, profile);
+                        }
+                        Err(error) => {
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "block-execution-profiling-failed; hash={}; error={error}",
+                                    HashDisplay(&hash_to_verify)
+                                ),
+                            );
+                        }
+                    }
+                }
+
+                if let Some(block_import_hook) = &self.block_import_hook {
+                    block_import_hook
+                        .on_block_imported(ImportedBlock {
+                            hash: hash_to_verify,
+                            parent_hash: *header_verification_success.parent_hash(),
+                            number: height,
+                            scale_encoded_header: scale_encoded_header.clone(),
+                            body: header_verification_success
+                                .scale_encoded_extrinsics()
+                                .unwrap()
+                                .map(|extrinsic| extrinsic.as_ref().to_vec())
+                                .collect(),
+                            is_new_best,
+                            storage_changes: execute_block_success
+                                .storage_changes
+                                .storage_changes_iter_unordered()
+                                .map(|(child_trie, key, value)| {
+                                    (
+                                        child_trie.map(|t| t.to_vec()),
+                                        key.to_vec(),
+                                        value.map(|v| v.to_vec()),
+                                    )
+                                })
+                                .collect(),
+                        })
+                        .await;
+                }
+
                 // Notify the subscribers.
                 debug_assert!(self.pending_notification.is_none());
                 self.pending_notification = Some(Notification::Block {
@@ -2831,14 +4693,19 @@ impl SyncBackground {
             }
 
             all::ProcessOne::VerifyFinalityProof(verify) => {
-                let sender = verify
-                    .sender()
-                    .1
+                // `None` if the finality proof has been assembled by the local node. See
+                // [`ConsensusService::inject_grandpa_commit`].
+                let sender_peer_id = verify.sender().1.as_ref().map(|s| s.peer_id.clone());
+                let sender = sender_peer_id
                     .as_ref()
-                    .map(|s| s.peer_id.clone())
-                    .unwrap();
+                    .map_or_else(|| "local".to_owned(), |peer_id| peer_id.to_string());
 
-                match verify.perform(rand::random()) {
+                // Commit messages aren't justifications, and are therefore not stored.
+                let justification = verify
+                    .justification()
+                    .map(|(engine_id, justification)| (engine_id, justification.to_vec()));
+
+                match verify.perform(self.randomness.gen()) {
                     (
                         sync_out,
                         all::FinalityProofVerifyOutcome::NewFinalized {
@@ -2861,36 +4728,28 @@ impl SyncBackground {
                             ),
                         );
 
-                        if updates_best_block {
-                            // Update the networking.
-                            self.network_local_chain_update_needed = true;
-                            // Reset the block authoring, in order to potentially build a
-                            // block on top of this new best.
-                            self.block_authoring = None;
-                        }
-
-                        self.finalized_runtime =
-                            match &finalized_blocks_newest_to_oldest.first().unwrap().user_data {
-                                NonFinalizedBlock::Verified { runtime } => runtime.clone(),
-                                _ => unreachable!(),
-                            };
-                        // TODO: what if best block changed?
-                        self.database
-                            .with_database_detached(move |database| {
-                                database.set_finalized(&new_finalized_hash).unwrap();
-                            })
-                            .await;
+                        self.on_new_finalized(
+                            finalized_blocks_newest_to_oldest,
+                            pruned_blocks,
+                            updates_best_block,
+                        )
+                        .await;
 
-                        // Notify the subscribers.
-                        debug_assert!(self.pending_notification.is_none());
-                        self.pending_notification = Some(Notification::Finalized {
-                            finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
-                                .iter()
-                                .map(|b| b.block_hash)
-                                .collect::<Vec<_>>(),
-                            pruned_blocks_hashes: pruned_blocks.clone(),
-                            best_block_hash: *self.sync.best_block_hash(),
-                        });
+                        // The justification is stored so that it can later be served or
+                        // exported alongside the block.
+                        if let Some((engine_id, justification)) = justification {
+                            self.database
+                                .with_database_detached(move |database| {
+                                    database
+                                        .set_block_justification(
+                                            &new_finalized_hash,
+                                            engine_id,
+                                            &justification,
+                                        )
+                                        .unwrap();
+                                })
+                                .await;
+                        }
 
                         (self, true)
                     }
@@ -2912,14 +4771,23 @@ impl SyncBackground {
                         (self, true)
                     }
                     (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitError(error)) => {
-                        self.network_service
-                            .ban_and_disconnect(
-                                sender.clone(),
-                                self.network_chain_id,
-                                network_service::BanSeverity::High,
-                                "bad-warp-sync-fragment",
-                            )
-                            .await;
+                        if let Some(sender_peer_id) = &sender_peer_id {
+                            self.network_service
+                                .report_misbehavior(
+                                    sender_peer_id.clone(),
+                                    self.network_chain_id,
+                                    network_service::PeerMisbehavior::BadJustification,
+                                )
+                                .await;
+                            self.network_service
+                                .ban_and_disconnect(
+                                    sender_peer_id.clone(),
+                                    self.network_chain_id,
+                                    network_service::BanSeverity::High,
+                                    "bad-warp-sync-fragment",
+                                )
+                                .await;
+                        }
                         self.log_callback.log(
                             LogLevel::Warn,
                             format!(
@@ -2934,13 +4802,23 @@ impl SyncBackground {
                         // Errors of type `JustificationEngineMismatch` indicate that the chain
                         // uses a finality engine that smoldot doesn't recognize. This is a benign
                         // error that shouldn't lead to a ban.
-                        if !matches!(
-                            error,
-                            all::JustificationVerifyError::JustificationEngineMismatch
+                        if let (Some(sender_peer_id), false) = (
+                            &sender_peer_id,
+                            matches!(
+                                error,
+                                all::JustificationVerifyError::JustificationEngineMismatch
+                            ),
                         ) {
+                            self.network_service
+                                .report_misbehavior(
+                                    sender_peer_id.clone(),
+                                    self.network_chain_id,
+                                    network_service::PeerMisbehavior::BadJustification,
+                                )
+                                .await;
                             self.network_service
                                 .ban_and_disconnect(
-                                    sender.clone(),
+                                    sender_peer_id.clone(),
                                     self.network_chain_id,
                                     network_service::BanSeverity::High,
                                     "bad-warp-sync-fragment",
@@ -2964,9 +4842,13 @@ impl SyncBackground {
 }
 
 /// Executes the given block. On success, inserts it and its storage into the database.
+///
+/// If `offchain_indexing` is `true`, the offchain storage changes performed by the execution are
+/// also written to the database.
 // TODO: use a config struct for the parameters?
 pub async fn execute_block_and_insert(
     database: &database_thread::DatabaseThread,
+    compiled_runtimes_cache: Option<&compiled_runtimes_cache::CompiledRuntimesCache>,
     mut parent_runtime: host::HostVmPrototype,
     parent_block_hash: &[u8; 32],
     block_header: &[u8],
@@ -2974,11 +4856,13 @@ pub async fn execute_block_and_insert(
     block_body: impl ExactSizeIterator<Item = impl AsRef<[u8]> + Clone> + Clone,
     now_from_unix_epoch: Duration,
     is_new_best: bool,
+    offchain_indexing: bool,
 ) -> Result<ExecuteBlockSuccess, ExecuteBlockError> {
     let mut database_accesses_duration = Duration::new(0, 0);
     let mut runtime_build_duration = Duration::new(0, 0);
 
     let mut storage_changes = runtime_call::StorageChanges::empty();
+    let mut offchain_storage_changes = Vec::new();
     let mut state_trie_version = runtime_call::TrieEntryVersion::V0; // TODO: shouldn't have to be initialized
     for (call_function, call_parameter) in [
         (
@@ -3017,6 +4901,7 @@ pub async fn execute_block_and_insert(
             &call_parameter,
             runtime_call::StorageProofSizeBehavior::Unimplemented,
             storage_changes,
+            true,
         )
         .await
         {
@@ -3037,6 +4922,9 @@ pub async fn execute_block_and_insert(
 
                 parent_runtime = success.runtime;
                 storage_changes = success.storage_changes;
+                if offchain_indexing {
+                    offchain_storage_changes.extend(success.offchain_storage_changes);
+                }
                 state_trie_version = success.state_trie_version;
                 database_accesses_duration += success.database_accesses_duration;
             }
@@ -3071,6 +4959,13 @@ pub async fn execute_block_and_insert(
                     ExecuteBlockVerificationFailureError::DatabaseInvalidStateTrieVersion,
                 ))
             }
+            Err(
+                RuntimeCallError::DatabaseOffchainStorageAccess(_)
+                | RuntimeCallError::InvalidOffchainStorageOldValue,
+            ) => {
+                // The offchain host functions are forbidden by `runtime_call`.
+                unreachable!()
+            }
         }
     }
 
@@ -3152,12 +5047,16 @@ pub async fn execute_block_and_insert(
             };
 
             let before_runtime_build = Instant::now();
-            let vm = host::HostVmPrototype::new(host::Config {
-                module: &new_code,
-                heap_pages: new_heap_pages,
-                exec_hint: executor::vm::ExecHint::ValidateAndCompile,
-                allow_unresolved_imports: false,
-            })
+            let vm = host::HostVmPrototype::new_with_compiled_modules_cache(
+                host::Config {
+                    module: &new_code,
+                    heap_pages: new_heap_pages,
+                    exec_hint: executor::vm::ExecHint::ValidateAndCompile,
+                    allow_unresolved_imports: false,
+                },
+                compiled_runtimes_cache
+                    .map(|cache| cache as &dyn executor::vm::CompiledModulesCache),
+            )
             .map_err(ExecuteBlockInvalidBlockError::InvalidNewRuntime)?;
             runtime_build_duration += before_runtime_build.elapsed();
             Some(vm)
@@ -3179,6 +5078,12 @@ pub async fn execute_block_and_insert(
             move |database| {
                 database.insert(&block_header, is_new_best, block_body.into_iter())?;
 
+                for (key, value) in &offchain_storage_changes {
+                    database
+                        .offchain_storage_compare_set(key, value.as_deref(), None)
+                        .map_err(full_sqlite::InsertError::Corrupted)?;
+                }
+
                 let trie_nodes = storage_changes
                     .trie_changes_iter_ordered()
                     .unwrap()
@@ -3355,7 +5260,167 @@ pub async fn runtime_call(
     parameter: &[u8],
     storage_proof_size_behavior: runtime_call::StorageProofSizeBehavior,
     initial_storage_changes: runtime_call::StorageChanges,
+    calculate_trie_changes: bool,
+) -> Result<RuntimeCallSuccess, RuntimeCallError> {
+    runtime_call_inner(
+        database,
+        storage_block_hash,
+        runtime,
+        function_to_call,
+        parameter,
+        storage_proof_size_behavior,
+        initial_storage_changes,
+        calculate_trie_changes,
+        None,
+    )
+    .await
+}
+
+/// Environment that the runtime interacts with during an [`offchain_runtime_call()`].
+pub struct OffchainEnvironment<'a> {
+    /// The transactions that the runtime submits are pushed to this list and are always reported
+    /// to the runtime as successfully submitted. It is the responsibility of the caller to
+    /// validate and propagate them.
+    pub submitted_transactions: &'a mut Vec<Vec<u8>>,
+
+    /// The HTTP requests that the runtime performs go through this collection. If `None`, these
+    /// requests always fail.
+    pub http_requests: Option<&'a mut offchain_http::HttpRequests>,
+
+    /// Source of the random seeds that the runtime requests.
+    pub randomness: &'a mut rand::rngs::StdRng,
+}
+
+/// Similar to [`runtime_call()`], but the runtime is allowed to call the offchain host
+/// functions, as is the case for offchain workers.
+///
+/// The offchain storage is read from and written to the database. Everything else that the
+/// offchain host functions access is provided by `environment`.
+pub async fn offchain_runtime_call(
+    database: &database_thread::DatabaseThread,
+    storage_block_hash: &[u8; 32],
+    runtime: host::HostVmPrototype,
+    function_to_call: &str,
+    parameter: &[u8],
+    environment: OffchainEnvironment<'_>,
+) -> Result<RuntimeCallSuccess, RuntimeCallError> {
+    runtime_call_inner(
+        database,
+        storage_block_hash,
+        runtime,
+        function_to_call,
+        parameter,
+        runtime_call::StorageProofSizeBehavior::Unimplemented,
+        runtime_call::StorageChanges::empty(),
+        false,
+        Some(environment),
+    )
+    .await
+}
+
+/// Decodes the given Merkle proof, downloaded from the network, and inserts the trie nodes it
+/// contains in the database.
+///
+/// Decoding and verifying the proof is CPU-intensive and is done on a separate thread. The
+/// database thread is then only used to insert the nodes, sorted by Merkle value, in a single
+/// transaction for each state version.
+async fn insert_merkle_proof_in_database(
+    database: &database_thread::DatabaseThread,
+    proof: network::service::EncodedMerkleProof,
+) {
+    let batches = smol::unblock(move || {
+        // Trie nodes to insert, indexed by state version.
+        let mut batches = [Vec::new(), Vec::new()];
+
+        let Ok(decoded) = trie::proof_decode::decode_and_verify_proof(trie::proof_decode::Config {
+            proof: proof.decode(),
+        }) else {
+            return batches;
+        };
+
+        for (_, entry) in decoded.iter_ordered() {
+            // TODO: check the state root hash; while this can't lead to a vulnerability, it can bloat the database
+            let (storage_value, state_version) = match entry.trie_node_info.storage_value {
+                trie::proof_decode::StorageValue::HashKnownValueMissing(_) => continue,
+                trie::proof_decode::StorageValue::None => {
+                    (full_sqlite::InsertTrieNodeStorageValue::NoValue, 0) // TODO: ?!
+                }
+                trie::proof_decode::StorageValue::Known { value, inline } => (
+                    full_sqlite::InsertTrieNodeStorageValue::Value {
+                        value: Cow::Owned(value.to_vec()),
+                        references_merkle_value: false, // TODO:
+                    },
+                    if inline { 0 } else { 1 },
+                ),
+            };
+
+            batches[state_version].push(full_sqlite::InsertTrieNode {
+                merkle_value: Cow::Owned(entry.merkle_value.to_vec()),
+                partial_key_nibbles: Cow::Owned(entry.partial_key_nibbles.map(u8::from).collect()),
+                children_merkle_values: array::from_fn(|n| {
+                    entry
+                        .trie_node_info
+                        .children
+                        .child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap())
+                        .merkle_value()
+                        .map(|merkle_value| Cow::Owned(merkle_value.to_vec()))
+                }),
+                storage_value,
+            });
+        }
+
+        // Nodes are sorted by Merkle value, which is the primary key of the table they are
+        // inserted in, in order to make the insertion cheaper.
+        for batch in &mut batches {
+            batch.sort_unstable_by(|a, b| a.merkle_value.cmp(&b.merkle_value));
+        }
+
+        batches
+    })
+    .await;
+
+    if batches.iter().all(|batch| batch.is_empty()) {
+        return;
+    }
+
+    database
+        .with_database(move |database| {
+            for (state_version, batch) in batches.into_iter().enumerate() {
+                if batch.is_empty() {
+                    continue;
+                }
+
+                database
+                    .insert_trie_nodes(batch.into_iter(), u8::try_from(state_version).unwrap())
+                    .unwrap();
+            }
+        })
+        .await;
+}
+
+/// Implementation of [`runtime_call()`] and [`offchain_runtime_call()`]. The offchain host
+/// functions are forbidden if `offchain_environment` is `None`.
+async fn runtime_call_inner(
+    database: &database_thread::DatabaseThread,
+    storage_block_hash: &[u8; 32],
+    runtime: host::HostVmPrototype,
+    function_to_call: &str,
+    parameter: &[u8],
+    storage_proof_size_behavior: runtime_call::StorageProofSizeBehavior,
+    initial_storage_changes: runtime_call::StorageChanges,
+    calculate_trie_changes: bool,
+    offchain_environment: Option<OffchainEnvironment<'_>>,
 ) -> Result<RuntimeCallSuccess, RuntimeCallError> {
+    let (mut offchain_submitted_transactions, mut offchain_http_requests, mut offchain_randomness) =
+        match offchain_environment {
+            Some(environment) => (
+                Some(environment.submitted_transactions),
+                environment.http_requests,
+                Some(environment.randomness),
+            ),
+            None => (None, None, None),
+        };
+
     let mut call = runtime_call::run(runtime_call::Config {
         virtual_machine: runtime,
         function_to_call,
@@ -3363,11 +5428,13 @@ pub async fn runtime_call(
         storage_proof_size_behavior,
         storage_main_trie_changes: initial_storage_changes.into_main_trie_diff(),
         max_log_level: 0,
-        calculate_trie_changes: true,
+        calculate_trie_changes,
     })
     .map_err(|(err, _)| RuntimeCallError::RuntimeStartError(err))?;
 
     let mut database_accesses_duration = Duration::new(0, 0);
+    let mut database_read_bytes = 0u64;
+    let mut offchain_storage_changes = Vec::new();
 
     loop {
         match call {
@@ -3385,8 +5452,10 @@ pub async fn runtime_call(
                     output,
                     runtime: virtual_machine.into_prototype(),
                     storage_changes,
+                    offchain_storage_changes,
                     state_trie_version,
                     database_accesses_duration,
+                    database_read_bytes,
                 });
             }
 
@@ -3411,6 +5480,9 @@ pub async fn runtime_call(
                         )
                     })
                     .await;
+                if let Ok(Some((val, _))) = &value {
+                    database_read_bytes += (req.key().as_ref().len() + val.len()) as u64;
+                }
                 let value = match value {
                     Ok(Some((ref val, vers))) => Some((
                         iter::once(&val[..]),
@@ -3449,6 +5521,7 @@ pub async fn runtime_call(
                     Ok(mv) => mv,
                     Err(error) => return Err(RuntimeCallError::DatabaseParentAccess(error)),
                 };
+                database_read_bytes += merkle_value.as_ref().map_or(0, |mv| mv.len() as u64);
 
                 database_accesses_duration += when_database_access_started.elapsed();
                 call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
@@ -3486,6 +5559,7 @@ pub async fn runtime_call(
                     Ok(k) => k,
                     Err(error) => return Err(RuntimeCallError::DatabaseParentAccess(error)),
                 };
+                database_read_bytes += next_key.as_ref().map_or(0, |k| (k.len() / 2) as u64);
 
                 database_accesses_duration += when_database_access_started.elapsed();
                 call = req.inject_key(
@@ -3493,7 +5567,12 @@ pub async fn runtime_call(
                 );
             }
             runtime_call::RuntimeCall::OffchainStorageSet(req) => {
-                // Ignore offchain storage writes at the moment.
+                // It is the responsibility of the caller to write these changes to the database
+                // or not.
+                offchain_storage_changes.push((
+                    req.key().as_ref().to_vec(),
+                    req.value().map(|v| v.as_ref().to_vec()),
+                ));
                 call = req.resume();
             }
             runtime_call::RuntimeCall::LogEmit(req) => {
@@ -3503,14 +5582,151 @@ pub async fn runtime_call(
             runtime_call::RuntimeCall::SignatureVerification(sig) => {
                 call = sig.verify_and_resume();
             }
-            runtime_call::RuntimeCall::Offchain(_) => {
+            runtime_call::RuntimeCall::Offchain(_) if offchain_submitted_transactions.is_none() => {
                 // Offchain storage calls are forbidden.
                 return Err(RuntimeCallError::ForbiddenHostFunction);
             }
+            runtime_call::RuntimeCall::Offchain(runtime_call::OffchainContext::StorageGet(req)) => {
+                let key = req.key().as_ref().to_vec();
+                let value = database
+                    .with_database(move |db| db.offchain_storage_get(&key))
+                    .await
+                    .map_err(RuntimeCallError::DatabaseOffchainStorageAccess)?;
+                call = req.inject_value(value);
+            }
+            runtime_call::RuntimeCall::Offchain(runtime_call::OffchainContext::StorageSet(req)) => {
+                let key = req.key().as_ref().to_vec();
+                let new_value = req.value().map(|v| v.as_ref().to_vec());
+                // The old value, if any, is a SCALE-encoded `Option<Vec<u8>>`.
+                let old_value = match req.old_value() {
+                    None => None,
+                    Some(old_value) => match decode_scale_option_bytes(old_value.as_ref()) {
+                        Some(old_value) => Some(old_value.map(|v| v.to_vec())),
+                        None => return Err(RuntimeCallError::InvalidOffchainStorageOldValue),
+                    },
+                };
+                let replaced = database
+                    .with_database(move |db| {
+                        db.offchain_storage_compare_set(
+                            &key,
+                            new_value.as_deref(),
+                            old_value.as_ref().map(|v| v.as_deref()),
+                        )
+                    })
+                    .await
+                    .map_err(RuntimeCallError::DatabaseOffchainStorageAccess)?;
+                call = req.resume(replaced);
+            }
+            runtime_call::RuntimeCall::Offchain(runtime_call::OffchainContext::Timestamp(req)) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or(Duration::new(0, 0));
+                call =
+                    req.inject_timestamp(u64::try_from(timestamp.as_millis()).unwrap_or(u64::MAX));
+            }
+            runtime_call::RuntimeCall::Offchain(runtime_call::OffchainContext::RandomSeed(req)) => {
+                // Guaranteed to be `Some`, as offchain host functions are otherwise forbidden.
+                let randomness = offchain_randomness.as_mut().unwrap();
+                call = req.inject_random_seed(randomness.gen());
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::SubmitTransaction(req),
+            ) => {
+                if let Some(submitted_transactions) = &mut offchain_submitted_transactions {
+                    submitted_transactions.push(req.transaction().as_ref().to_vec());
+                }
+                call = req.resume(true);
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpRequestStart(req),
+            ) => {
+                let request_id = offchain_http_requests
+                    .as_mut()
+                    .and_then(|requests| requests.start(req.method().as_ref(), req.uri().as_ref()));
+                call = req.resume(request_id);
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpRequestAddHeader(req),
+            ) => {
+                let success = offchain_http_requests.as_mut().is_some_and(|requests| {
+                    requests.add_header(req.request_id(), req.name().as_ref(), req.value().as_ref())
+                });
+                call = req.resume(success);
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpRequestWriteBody(req),
+            ) => {
+                // The body is buffered in memory, meaning that writing it never blocks and that
+                // the deadline can be ignored.
+                let result = match &mut offchain_http_requests {
+                    Some(requests) => requests.write_body(req.request_id(), req.chunk().as_ref()),
+                    None => Err(runtime_call::HttpError::Invalid),
+                };
+                call = req.resume(result);
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpResponseWait(req),
+            ) => {
+                let statuses = match &mut offchain_http_requests {
+                    Some(requests) => requests.wait(req.request_ids(), req.deadline()).await,
+                    None => vec![runtime_call::HttpRequestStatus::Invalid; req.request_ids().len()],
+                };
+                call = req.resume(&statuses);
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpResponseHeaders(req),
+            ) => {
+                let headers = match &offchain_http_requests {
+                    Some(requests) => requests.response_headers(req.request_id()),
+                    None => &[],
+                };
+                call = req.resume(headers.iter().map(|(name, value)| (&name[..], &value[..])));
+            }
+            runtime_call::RuntimeCall::Offchain(
+                runtime_call::OffchainContext::HttpResponseReadBody(req),
+            ) => {
+                let result = match &mut offchain_http_requests {
+                    Some(requests) => {
+                        requests
+                            .read_body(req.request_id(), req.buffer_size(), req.deadline())
+                            .await
+                    }
+                    None => Err(runtime_call::HttpError::Invalid),
+                };
+                call = req.resume(result.as_deref().map_err(|error| *error));
+            }
         }
     }
 }
 
+/// Decodes a SCALE-encoded `Option<Vec<u8>>`. Returns `None` if the encoding is invalid.
+fn decode_scale_option_bytes(encoded: &[u8]) -> Option<Option<&[u8]>> {
+    match encoded.split_first()? {
+        (0, []) => Some(None),
+        (1, rest) => {
+            // Compact encoding of the length.
+            let (len, rest) = match rest.first()? & 0b11 {
+                0b00 => (usize::from(rest[0] >> 2), &rest[1..]),
+                0b01 => (
+                    usize::from(u16::from_le_bytes(<[u8; 2]>::try_from(rest.get(..2)?).ok()?) >> 2),
+                    &rest[2..],
+                ),
+                0b10 => (
+                    usize::try_from(
+                        u32::from_le_bytes(<[u8; 4]>::try_from(rest.get(..4)?).ok()?) >> 2,
+                    )
+                    .ok()?,
+                    &rest[4..],
+                ),
+                // Lengths superior to 2^30 can't fit in the memory of the runtime anyway.
+                _ => return None,
+            };
+            (rest.len() == len).then_some(Some(rest))
+        }
+        _ => None,
+    }
+}
+
 /// Returned by [`runtime_call()`] in case of success.
 #[derive(Debug)]
 pub struct RuntimeCallSuccess {
@@ -3523,11 +5739,19 @@ pub struct RuntimeCallSuccess {
     /// Changes to the storage performed during the execution.
     pub storage_changes: runtime_call::StorageChanges,
 
+    /// Changes to the offchain storage performed during the execution through the offchain
+    /// indexing host functions. A value of `None` means that the key must be removed.
+    pub offchain_storage_changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+
     /// Version of the trie entries of the storage changes.
     pub state_trie_version: runtime_call::TrieEntryVersion,
 
     /// Total time the database accesses combined took.
     pub database_accesses_duration: Duration,
+
+    /// Total number of bytes of storage keys, storage values, and Merkle values read from the
+    /// database.
+    pub database_read_bytes: u64,
 }
 
 /// Error returned by [`runtime_call()`].
@@ -3542,8 +5766,326 @@ pub enum RuntimeCallError {
     /// Error while accessing the parent block in the database.
     #[display(fmt = "{_0}")]
     DatabaseParentAccess(full_sqlite::StorageAccessError),
+    /// Error while accessing the offchain storage in the database.
+    #[display(fmt = "Error while accessing the offchain storage: {_0}")]
+    DatabaseOffchainStorageAccess(full_sqlite::CorruptedError),
     /// State trie version stored in database is invalid.
     DatabaseInvalidStateTrieVersion,
+    /// The old value passed by the runtime to the offchain storage compare-and-set host
+    /// function isn't a valid SCALE-encoded `Option<Vec<u8>>`.
+    InvalidOffchainStorageOldValue,
     /// Runtime has tried to call a forbidden host function.
     ForbiddenHostFunction,
 }
+
+/// Implementation of [`ConsensusService::build_block_template`].
+async fn build_block_template_inner(
+    database: &database_thread::DatabaseThread,
+    block_number_bytes: usize,
+    parent_hash: [u8; 32],
+    parent_number: u64,
+    parent_runtime: host::HostVmPrototype,
+    slot_claim: BlockTemplateSlotClaim,
+    now_from_unix_epoch: Duration,
+    additional_inherents: Vec<([u8; 8], Vec<u8>)>,
+    mut block_transactions: transactions_pool::BlockTransactions,
+) -> Result<BlockTemplate, BlockTemplateError> {
+    let mut block_build = author::runtime::build_block(author::runtime::Config {
+        block_number_bytes,
+        parent_hash: &parent_hash,
+        parent_number,
+        parent_runtime,
+        consensus_digest_log_item: match slot_claim {
+            BlockTemplateSlotClaim::Aura { slot_number } => {
+                author::runtime::ConfigPreRuntime::Aura(header::AuraPreDigest { slot_number })
+            }
+            BlockTemplateSlotClaim::Babe {
+                slot_number,
+                authority_index,
+            } => author::runtime::ConfigPreRuntime::Babe(header::BabePreDigestRef::SecondaryPlain(
+                header::BabeSecondaryPlainPreDigest {
+                    authority_index,
+                    slot_number,
+                },
+            )),
+        },
+        block_body_capacity: 0,
+        max_log_level: 0,
+        calculate_trie_changes: false,
+    });
+
+    let mut inherent_data = Some(
+        verify::inherents::InherentData {
+            timestamp: u64::try_from(now_from_unix_epoch.as_millis()).unwrap_or(u64::MAX),
+        }
+        .into_raw_list()
+        .map(|(id, value)| (id, value.as_ref().to_vec()))
+        .chain(additional_inherents)
+        .collect::<Vec<_>>(),
+    );
+
+    loop {
+        match block_build {
+            author::runtime::BlockBuild::Finished(Ok(success)) => {
+                let state_root = *header::decode(&success.scale_encoded_header, block_number_bytes)
+                    .unwrap()
+                    .state_root;
+                return Ok(BlockTemplate {
+                    parent_hash,
+                    scale_encoded_header: success.scale_encoded_header,
+                    body: success.body,
+                    state_root,
+                });
+            }
+            author::runtime::BlockBuild::Finished(Err((error, _))) => {
+                return Err(BlockTemplateError::Build(error));
+            }
+            author::runtime::BlockBuild::InherentExtrinsics(req) => {
+                // Injecting the inherents is guaranteed to be done only once per block.
+                block_build =
+                    req.inject_raw_inherents_list(inherent_data.take().unwrap().into_iter());
+            }
+            author::runtime::BlockBuild::ApplyExtrinsic(req) => {
+                block_build = match block_transactions.next_transaction() {
+                    Some(transaction) => req.add_extrinsic(transaction),
+                    None => req.finish(),
+                };
+            }
+            author::runtime::BlockBuild::ApplyExtrinsicResult { result, resume } => {
+                // Transactions that the runtime refuses aren't part of the block.
+                if result.is_err() {
+                    block_transactions.report_not_included();
+                }
+                block_build = match block_transactions.next_transaction() {
+                    Some(transaction) => resume.add_extrinsic(transaction),
+                    None => resume.finish(),
+                };
+            }
+            author::runtime::BlockBuild::StorageGet(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key = trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database(move |db| {
+                        db.block_storage_get(
+                            &parent_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(BlockTemplateError::DatabaseAccess)?;
+                let value = match value {
+                    Some((ref val, vers)) => Some((
+                        iter::once(&val[..]),
+                        runtime_call::TrieEntryVersion::try_from(vers)
+                            .map_err(|_| BlockTemplateError::DatabaseInvalidStateTrieVersion)?,
+                    )),
+                    None => None,
+                };
+                block_build = req.inject_value(value);
+            }
+            author::runtime::BlockBuild::ClosestDescendantMerkleValue(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+                let merkle_value = database
+                    .with_database(move |db| {
+                        db.block_storage_closest_descendant_merkle_value(
+                            &parent_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(BlockTemplateError::DatabaseAccess)?;
+                block_build = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            author::runtime::BlockBuild::NextKey(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+                let next_key = database
+                    .with_database(move |db| {
+                        db.block_storage_next_key(
+                            &parent_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .map_err(BlockTemplateError::DatabaseAccess)?;
+                block_build = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+            author::runtime::BlockBuild::OffchainStorageSet(req) => {
+                // Offchain storage writes are ignored, as the block is never imported.
+                block_build = req.resume();
+            }
+        }
+    }
+}
+
+/// Resources used by the execution of a block, split between the various steps of the
+/// execution. See [`Config::block_execution_profiling`].
+#[derive(Debug, Clone)]
+pub struct BlockExecutionProfile {
+    /// Hash of the block that has been profiled.
+    pub block_hash: [u8; 32],
+    /// Height of the block that has been profiled.
+    pub block_number: u64,
+    /// Resources used by the call to `Core_initialize_block`.
+    pub initialization: ExecutionStepProfile,
+    /// Resources used by each extrinsic of the block, in the same order as in the block body.
+    pub extrinsics: Vec<ExecutionStepProfile>,
+    /// Resources used by the call to `BlockBuilder_finalize_block`.
+    pub finalization: ExecutionStepProfile,
+}
+
+/// See [`BlockExecutionProfile`].
+#[derive(Debug, Clone)]
+pub struct ExecutionStepProfile {
+    /// Total time the step took, including the database accesses.
+    pub duration: Duration,
+    /// Time the database accesses of the step took.
+    pub database_accesses_duration: Duration,
+    /// Number of bytes of storage items read from the storage of the parent block. Approximates
+    /// the contribution of the step to the size of a storage proof of the block.
+    ///
+    /// Storage items that have already been read by a previous step are counted again.
+    pub proof_size: u64,
+}
+
+/// Executes the given block one extrinsic at a time, and measures the resources used by each
+/// step of the execution.
+///
+/// Contrary to [`execute_block_and_insert`], nothing is written to the database. The block is
+/// assumed to have been verified beforehand, and the outputs of the runtime calls are ignored.
+pub async fn profile_block(
+    database: &database_thread::DatabaseThread,
+    mut parent_runtime: host::HostVmPrototype,
+    parent_block_hash: &[u8; 32],
+    block_header: &[u8],
+    block_number_bytes: usize,
+    block_body: &[Vec<u8>],
+) -> Result<BlockExecutionProfile, ProfileBlockError> {
+    let mut unsealed_header = header::decode(block_header, block_number_bytes)
+        .map_err(ProfileBlockError::InvalidHeader)?;
+    let block_number = unsealed_header.number;
+    let _seal_log = unsealed_header.digest.pop_seal();
+
+    // The `Core_initialize_block` function expects a SCALE-encoded header without its seal.
+    let initialize_block_parameter = unsealed_header.scale_encoding_vec(block_number_bytes);
+
+    let mut storage_changes = runtime_call::StorageChanges::empty();
+    let mut steps = Vec::with_capacity(block_body.len() + 2);
+
+    for (function_to_call, parameter) in
+        iter::once(("Core_initialize_block", &initialize_block_parameter[..]))
+            .chain(
+                block_body
+                    .iter()
+                    .map(|extrinsic| ("BlockBuilder_apply_extrinsic", &extrinsic[..])),
+            )
+            .chain(iter::once(("BlockBuilder_finalize_block", &[][..])))
+    {
+        let when_started = Instant::now();
+        let success = runtime_call(
+            database,
+            parent_block_hash,
+            parent_runtime,
+            function_to_call,
+            parameter,
+            runtime_call::StorageProofSizeBehavior::Unimplemented,
+            storage_changes,
+            false,
+        )
+        .await
+        .map_err(ProfileBlockError::RuntimeCall)?;
+
+        steps.push(ExecutionStepProfile {
+            duration: when_started.elapsed(),
+            database_accesses_duration: success.database_accesses_duration,
+            proof_size: success.database_read_bytes,
+        });
+        parent_runtime = success.runtime;
+        storage_changes = success.storage_changes;
+    }
+
+    let finalization = steps.pop().unwrap();
+    let initialization = steps.remove(0);
+
+    Ok(BlockExecutionProfile {
+        block_hash: header::hash_from_scale_encoded_header(block_header),
+        block_number,
+        initialization,
+        extrinsics: steps,
+        finalization,
+    })
+}
+
+/// Error returned by [`profile_block`].
+#[derive(Debug, derive_more::Display)]
+pub enum ProfileBlockError {
+    /// Failed to decode the header of the block.
+    #[display(fmt = "Invalid block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// Error while performing one of the runtime calls.
+    #[display(fmt = "{_0}")]
+    RuntimeCall(RuntimeCallError),
+}
+
+/// Compares the fragments of two warp sync responses. Returns the height and the two different
+/// hashes of a block that the two responses report as finalized, or `None` if the responses
+/// agree with each other.
+///
+/// Headers that fail to decode are ignored, as they are refused anyway when verifying the
+/// fragments.
+fn warp_sync_fragments_mismatch(
+    response: &network::codec::GrandpaWarpSyncResponse,
+    cross_check: &network::codec::GrandpaWarpSyncResponse,
+    block_number_bytes: usize,
+) -> Option<(u64, [u8; 32], [u8; 32])> {
+    let finalized_blocks = |response: &network::codec::GrandpaWarpSyncResponse| {
+        response
+            .fragments
+            .iter()
+            .filter_map(|fragment| {
+                let decoded =
+                    header::decode(fragment.scale_encoded_header, block_number_bytes).ok()?;
+                Some((decoded.number, decoded.hash(block_number_bytes)))
+            })
+            .collect::<hashbrown::HashMap<_, _, fnv::FnvBuildHasher>>()
+    };
+
+    let cross_check = finalized_blocks(cross_check);
+    finalized_blocks(response)
+        .into_iter()
+        .find_map(|(number, hash)| match cross_check.get(&number) {
+            Some(other_hash) if *other_hash != hash => Some((number, hash, *other_hash)),
+            _ => None,
+        })
+}
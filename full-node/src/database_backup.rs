@@ -0,0 +1,134 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Backups of the database of the chain while the node is running.
+//!
+//! Backups are performed from the database thread through the online backup API of SQLite (see
+//! [`smoldot::database::full_sqlite::SqliteFullDatabase::backup`]). The copy is consistent with
+//! the state of the database at the time when the backup starts, even if blocks are imported in
+//! the meanwhile. Accesses to the database, including the import of blocks, are paused while the
+//! copy is in progress.
+//!
+//! The backup is written to a temporary file which is then renamed, meaning that the file at the
+//! path of the backup is always a complete database. The backup can be restored by stopping the
+//! node and replacing its database file with it.
+
+use smoldot::database::full_sqlite;
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{database_thread, LogCallback, LogLevel};
+
+/// See [`crate::ChainConfig::database_backup`].
+#[derive(Debug, Clone)]
+pub struct DatabaseBackupConfig {
+    /// Path of the file to write the backup to. The previous backup, if any, is replaced.
+    pub path: PathBuf,
+    /// Delay between two backups. The first backup is performed after this delay has elapsed
+    /// since the start of the node.
+    pub interval: Duration,
+}
+
+/// Error potentially returned by [`backup`].
+#[derive(Debug, derive_more::Display)]
+pub enum DatabaseBackupError {
+    /// Error while copying the database.
+    #[display(fmt = "Failed to copy the database: {_0}")]
+    Database(full_sqlite::InternalError),
+    /// Error while removing the temporary file or renaming it to the path of the backup.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+}
+
+/// Copies the database to the file at the given path.
+pub async fn backup(
+    database: &database_thread::DatabaseThread,
+    path: &Path,
+) -> Result<(), DatabaseBackupError> {
+    let tmp_path = PathBuf::from({
+        let mut tmp_path = OsString::from(path.as_os_str());
+        tmp_path.push(".tmp");
+        tmp_path
+    });
+
+    // A temporary file might have been left behind if the node was stopped in the middle of a
+    // previous backup. It must be removed, as SQLite would otherwise try to open it as a
+    // database.
+    smol::unblock({
+        let tmp_path = tmp_path.clone();
+        move || match fs::remove_file(&tmp_path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    })
+    .await
+    .map_err(DatabaseBackupError::Io)?;
+
+    database
+        .with_database({
+            let tmp_path = tmp_path.clone();
+            move |database| database.backup(&tmp_path)
+        })
+        .await
+        .map_err(DatabaseBackupError::Database)?;
+
+    let path = path.to_owned();
+    smol::unblock(move || fs::rename(&tmp_path, &path))
+        .await
+        .map_err(DatabaseBackupError::Io)
+}
+
+/// Runs the task that periodically backs up the database. Never returns.
+pub async fn run(
+    database: Arc<database_thread::DatabaseThread>,
+    config: DatabaseBackupConfig,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    loop {
+        smol::Timer::after(config.interval).await;
+
+        let start = Instant::now();
+        match backup(&database, &config.path).await {
+            Ok(()) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "database-backup-written; path={}; duration={:?}",
+                        config.path.display(),
+                        start.elapsed()
+                    ),
+                );
+            }
+            Err(err) => {
+                log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "database-backup-error; path={}; error={}",
+                        config.path.display(),
+                        err
+                    ),
+                );
+            }
+        }
+    }
+}
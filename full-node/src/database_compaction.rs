@@ -0,0 +1,99 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compaction of the database.
+//!
+//! When data is removed from the database, for example by the pruning, SQLite keeps the pages
+//! that contained this data in order to reuse them later, and the database file doesn't shrink.
+//! Compacting the database gives these pages back to the file system.
+//!
+//! The pages are given back a few at a time (see
+//! [`smoldot::database::full_sqlite::SqliteFullDatabase::incremental_vacuum`]), so that the
+//! other accesses to the database, such as the import of blocks, are only briefly delayed.
+
+use smoldot::database::full_sqlite;
+use std::{
+    num::NonZeroU32,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{database_thread, LogCallback, LogLevel};
+
+/// Maximum number of pages given back to the file system during each access to the database.
+const PAGES_PER_STEP: u32 = 256;
+
+/// Error potentially returned by [`compact`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to compact the database: {_0}")]
+pub struct CompactDatabaseError(full_sqlite::InternalError);
+
+/// Gives back to the file system all the unused pages of the database. Returns the number of
+/// bytes that have been freed.
+pub async fn compact(
+    database: &database_thread::DatabaseThread,
+) -> Result<u64, CompactDatabaseError> {
+    let mut freed_bytes = 0;
+
+    loop {
+        let outcome = database
+            .with_database(|database| {
+                database.incremental_vacuum(NonZeroU32::new(PAGES_PER_STEP).unwrap())
+            })
+            .await
+            .map_err(CompactDatabaseError)?;
+
+        freed_bytes += outcome.freed_bytes;
+
+        // Stop if no progress was made, in order to not loop forever in case the unused pages
+        // can't be given back.
+        if outcome.remaining_free_bytes == 0 || outcome.freed_bytes == 0 {
+            break Ok(freed_bytes);
+        }
+    }
+}
+
+/// Runs the task that periodically compacts the database. Never returns.
+pub async fn run(
+    database: Arc<database_thread::DatabaseThread>,
+    interval: Duration,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    loop {
+        smol::Timer::after(interval).await;
+
+        let start = Instant::now();
+        match compact(&database).await {
+            Ok(freed_bytes) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "database-compacted; freed_bytes={}; duration={:?}",
+                        freed_bytes,
+                        start.elapsed()
+                    ),
+                );
+            }
+            Err(err) => {
+                log_callback.log(
+                    LogLevel::Warn,
+                    format!("database-compaction-error; error={}", err),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Read-only access to the database of a running node.
+//!
+//! The database of a node can be read from another process, or from another part of the
+//! program, while the node is running, as long as [`crate::SqliteOptions::journal_mode`] is
+//! [`crate::SqliteJournalMode::Wal`] and [`crate::SqliteOptions::external_read_access`] is
+//! `true`, which isn't the default. Thanks to the write-ahead log, these reads never delay the
+//! node, and the node never delays these reads.
+//!
+//! Each query sees the database as it was when the query started. Two successive queries might
+//! see a different state of the database, as the node continues to import and finalize blocks
+//! in the meanwhile and, depending on [`crate::Pruning`], to remove the old blocks.
+
+use smoldot::database::full_sqlite;
+use std::path::Path;
+
+use crate::SqliteOptions;
+
+/// Error potentially returned by [`open_database_read_only`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to open the database: {_0}")]
+pub struct OpenDatabaseReadOnlyError(full_sqlite::InternalError);
+
+/// Opens the database found at the given path, which is normally
+/// [`crate::ChainConfig::sqlite_database_path`], through a connection that can only read it.
+///
+/// `block_number_bytes` must be the value found in the specification of the chain, and
+/// `cache_size` is the maximum size, in bytes, of the cache of the connection.
+///
+/// The database must have been created by a node beforehand. It can be opened while the node
+/// is running, in which case the node must have been configured as described in
+/// [`crate::SqliteOptions::external_read_access`]. All the functions of the returned object
+/// that modify the database return an error.
+pub fn open_database_read_only(
+    database_path: &Path,
+    block_number_bytes: usize,
+    cache_size: usize,
+) -> Result<full_sqlite::SqliteFullDatabase, OpenDatabaseReadOnlyError> {
+    let sqlite_options = SqliteOptions::default();
+    full_sqlite::open_read_only(full_sqlite::Config {
+        block_number_bytes,
+        cache_size,
+        ty: full_sqlite::ConfigTy::Disk {
+            path: database_path,
+            memory_map_size: sqlite_options.memory_map_size,
+            journal_mode: sqlite_options.journal_mode,
+            synchronous: sqlite_options.synchronous,
+            page_size: sqlite_options.page_size,
+            shared_access: true,
+        },
+    })
+    .map_err(OpenDatabaseReadOnlyError)
+}
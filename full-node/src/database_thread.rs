@@ -17,14 +17,34 @@
 
 //! As explained in the documentation of smoldot, the database uses synchronous I/O operations.
 //! For this reason, it is undesirable to access it from an asynchronous context.
+//!
+//! All the modifications of the database are performed by a single thread. Optionally, closures
+//! that only read the database can be executed by a pool of threads each holding a read-only
+//! connection to the database (see [`DatabaseThread::with_database_read`]), so that slow queries
+//! don't delay the modifications.
+//!
+//! The duration of each access is measured. See [`DatabaseThread::metrics`].
 
 use futures_channel::oneshot;
 use smol::{channel, lock::Mutex, stream::StreamExt as _};
 use smoldot::database::full_sqlite::SqliteFullDatabase;
-use std::{pin::pin, thread};
+use std::{
+    future::Future,
+    panic::Location,
+    pin::pin,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
+pub use metrics::{
+    DatabaseConnectionKind, DatabaseMetrics, DatabaseOperationsMetrics, SlowDatabaseOperation,
+    LATENCY_BUCKETS,
+};
 pub use smoldot::database::full_sqlite::StorageAccessError;
 
+mod metrics;
+
 /// Handle to the thread were the database accesses are performed.
 ///
 /// Destroying this object stops the thread.
@@ -32,6 +52,13 @@ pub use smoldot::database::full_sqlite::StorageAccessError;
 /// Use the `From` trait implementation to build a [`DatabaseThread`].
 pub struct DatabaseThread {
     sender: Mutex<channel::Sender<Exec>>,
+    /// Channel shared by the threads of the read-only connections. `None` if there isn't any
+    /// read-only connection.
+    read_sender: Option<channel::Sender<Exec>>,
+    /// Blocks whose state is currently pinned. See [`DatabaseThread::pin_state`].
+    state_pins: StatePins,
+    /// Statistics about the closures executed so far. See [`DatabaseThread::metrics`].
+    metrics: Arc<metrics::Metrics>,
 }
 
 type Exec = Box<dyn FnOnce(&SqliteFullDatabase) + Send>;
@@ -39,42 +66,215 @@ type Exec = Box<dyn FnOnce(&SqliteFullDatabase) + Send>;
 impl DatabaseThread {
     /// Sends a closure to the database thread, executes it, then returns the value that the
     /// closure returned.
-    pub async fn with_database<T: Send + 'static>(
+    #[track_caller]
+    pub fn with_database<T: Send + 'static>(
         &self,
         closure: impl FnOnce(&SqliteFullDatabase) -> T + Send + 'static,
-    ) -> T {
-        let (tx, rx) = oneshot::channel();
-        self.sender
-            .lock()
-            .await
-            .send(Box::new(move |db| {
-                let _ = tx.send(closure(db));
-            }))
-            .await
-            .unwrap();
-        rx.await.unwrap()
+    ) -> impl Future<Output = T> + '_ {
+        // The location must be obtained outside of the `async` block in order for
+        // `#[track_caller]` to have an effect.
+        let location = Location::caller();
+        async move {
+            let (tx, rx) = oneshot::channel();
+            let exec = self.instrument(DatabaseConnectionKind::Write, location, closure, Some(tx));
+            self.sender.lock().await.send(exec).await.unwrap();
+            rx.await.unwrap()
+        }
     }
 
     /// Similar to [`DatabaseThread::with_database`], but without any return value. This function
     /// is slightly more optimized for this use case.
-    pub async fn with_database_detached(
+    #[track_caller]
+    pub fn with_database_detached(
         &self,
         closure: impl FnOnce(&SqliteFullDatabase) + Send + 'static,
-    ) {
-        self.sender
+    ) -> impl Future<Output = ()> + '_ {
+        let location = Location::caller();
+        async move {
+            let exec = self.instrument(DatabaseConnectionKind::Write, location, closure, None);
+            self.sender.lock().await.send(exec).await.unwrap();
+        }
+    }
+
+    /// Similar to [`DatabaseThread::with_database`], but the closure is executed by one of the
+    /// read-only connections if any. The closure must only read the database.
+    ///
+    /// Contrary to [`DatabaseThread::with_database`], closures passed to this function can run
+    /// in parallel with each other and with the modifications of the database. Each query
+    /// observes all the modifications that were finished when it starts, meaning that a closure
+    /// that performs multiple queries might observe modifications performed in between them.
+    #[track_caller]
+    pub fn with_database_read<T: Send + 'static>(
+        &self,
+        closure: impl FnOnce(&SqliteFullDatabase) -> T + Send + 'static,
+    ) -> impl Future<Output = T> + '_ {
+        let location = Location::caller();
+        async move {
+            let (tx, rx) = oneshot::channel();
+
+            if let Some(read_sender) = &self.read_sender {
+                let exec =
+                    self.instrument(DatabaseConnectionKind::Read, location, closure, Some(tx));
+                read_sender.send(exec).await.unwrap();
+            } else {
+                let exec =
+                    self.instrument(DatabaseConnectionKind::Write, location, closure, Some(tx));
+                self.sender.lock().await.send(exec).await.unwrap();
+            }
+
+            rx.await.unwrap()
+        }
+    }
+
+    /// Returns statistics about the closures executed since the [`DatabaseThread`] has been
+    /// created.
+    pub async fn metrics(&self) -> DatabaseMetrics {
+        let mut metrics = self.metrics.snapshot();
+        metrics.write_connection.queue_length = self.sender.lock().await.len();
+        metrics.read_connections.queue_length =
+            self.read_sender.as_ref().map_or(0, |sender| sender.len());
+        metrics
+    }
+
+    /// Wraps the given closure into an [`Exec`] that measures its execution.
+    ///
+    /// The value returned by the closure is sent on `result_tx` only after the execution has
+    /// been recorded, so that the operations whose result has been received are always included
+    /// in [`DatabaseThread::metrics`].
+    fn instrument<T: Send + 'static>(
+        &self,
+        connection: DatabaseConnectionKind,
+        location: &'static Location<'static>,
+        closure: impl FnOnce(&SqliteFullDatabase) -> T + Send + 'static,
+        result_tx: Option<oneshot::Sender<T>>,
+    ) -> Exec {
+        let metrics = self.metrics.clone();
+        let queued_at = Instant::now();
+        Box::new(move |db| {
+            let started_at = Instant::now();
+            let result = closure(db);
+            metrics.record(connection, location, queued_at, started_at);
+            if let Some(result_tx) = result_tx {
+                let _ = result_tx.send(result);
+            }
+        })
+    }
+
+    /// Pins the state of the given block, guaranteeing that the block and its storage aren't
+    /// removed by the pruning of the blocks that aren't descendants of the finalized block.
+    ///
+    /// The block stays pinned until the returned [`StatePin`] is destroyed or until
+    /// `max_duration` has elapsed, whichever comes first. This expiry is a safeguard against
+    /// pins that are accidentally never released, which would otherwise make the database grow
+    /// forever.
+    ///
+    /// Pinning a block doesn't bring it back if it has already been removed from the database.
+    /// The state is only guaranteed to be available if the block is still in the database after
+    /// this function has returned.
+    pub fn pin_state(&self, block_hash: [u8; 32], max_duration: Duration) -> StatePin {
+        let mut inner = self.state_pins.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner
+            .pins
+            .insert(id, (block_hash, Instant::now() + max_duration));
+        drop(inner);
+
+        StatePin {
+            state_pins: self.state_pins.clone(),
+            id,
+        }
+    }
+
+    /// Returns an object that indicates which blocks are currently pinned.
+    ///
+    /// This object is meant to be moved into the closures passed to
+    /// [`DatabaseThread::with_database`] that remove blocks from the database, so that the
+    /// list of pinned blocks is checked at the moment when the blocks are removed.
+    pub fn state_pins(&self) -> StatePins {
+        self.state_pins.clone()
+    }
+}
+
+/// List of the blocks whose state is pinned. See [`DatabaseThread::pin_state`].
+#[derive(Clone)]
+pub struct StatePins {
+    inner: Arc<std::sync::Mutex<StatePinsInner>>,
+}
+
+struct StatePinsInner {
+    /// Identifier to assign to the next pin.
+    next_id: u64,
+    /// For each pin, the hash of the pinned block and when the pin expires.
+    pins: hashbrown::HashMap<u64, ([u8; 32], Instant), fnv::FnvBuildHasher>,
+}
+
+impl StatePins {
+    /// Returns `true` if the state of the given block is pinned by at least one pin that hasn't
+    /// expired.
+    pub fn is_pinned(&self, block_hash: &[u8; 32]) -> bool {
+        let now = Instant::now();
+        self.inner
             .lock()
-            .await
-            .send(Box::new(move |db| {
-                closure(db);
-            }))
-            .await
-            .unwrap();
+            .unwrap()
+            .pins
+            .values()
+            .any(|(hash, expiry)| hash == block_hash && *expiry > now)
+    }
+}
+
+/// Pin of the state of a block. See [`DatabaseThread::pin_state`].
+///
+/// Destroying this object releases the pin.
+pub struct StatePin {
+    state_pins: StatePins,
+    id: u64,
+}
+
+impl Drop for StatePin {
+    fn drop(&mut self) {
+        self.state_pins.inner.lock().unwrap().pins.remove(&self.id);
+    }
+}
+
+impl DatabaseThread {
+    /// Builds a [`DatabaseThread`] that modifies the database through `db`, and that executes
+    /// the closures passed to [`DatabaseThread::with_database_read`] on the given read-only
+    /// connections, each in a separate thread.
+    ///
+    /// If `read_connections` is empty, all the closures are executed through `db`.
+    pub fn with_read_connections(
+        db: SqliteFullDatabase,
+        read_connections: Vec<SqliteFullDatabase>,
+    ) -> Self {
+        let mut database_thread = DatabaseThread::from(db);
+        if read_connections.is_empty() {
+            return database_thread;
+        }
+
+        let (read_sender, rx) = channel::bounded::<Exec>(256);
+        for (index, read_db) in read_connections.into_iter().enumerate() {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("sqlite-database-read-{index}"))
+                .spawn(move || {
+                    // As for the writing thread, the loop ends when the `DatabaseThread` is
+                    // dropped.
+                    while let Ok(closure) = smol::block_on(rx.recv()) {
+                        closure(&read_db)
+                    }
+                })
+                .unwrap();
+        }
+
+        database_thread.read_sender = Some(read_sender);
+        database_thread
     }
 }
 
 impl From<SqliteFullDatabase> for DatabaseThread {
     fn from(db: SqliteFullDatabase) -> DatabaseThread {
-        let (sender, rx) = channel::bounded::<Box<dyn FnOnce(&SqliteFullDatabase) + Send>>(256);
+        let (sender, rx) = channel::bounded::<Exec>(256);
 
         thread::Builder::new()
             .name("sqlite-database".into())
@@ -90,6 +290,14 @@ impl From<SqliteFullDatabase> for DatabaseThread {
 
         DatabaseThread {
             sender: Mutex::new(sender),
+            read_sender: None,
+            state_pins: StatePins {
+                inner: Arc::new(std::sync::Mutex::new(StatePinsInner {
+                    next_id: 0,
+                    pins: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+                })),
+            },
+            metrics: Arc::new(metrics::Metrics::new()),
         }
     }
 }
@@ -0,0 +1,170 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Statistics about the accesses to the database.
+//!
+//! Each closure passed to the [`super::DatabaseThread`] is an operation. The statistics are
+//! gathered separately for the connection that modifies the database and for the read-only
+//! connections, in order to make it possible to tell whether the modifications of the database,
+//! such as the import of blocks, are slowed down by the database.
+
+use std::{
+    panic::Location,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the buckets of the duration histograms of [`DatabaseOperationsMetrics`].
+pub const LATENCY_BUCKETS: [Duration; 8] = [
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+/// Maximum number of entries in [`DatabaseMetrics::slowest_operations`].
+const NUM_SLOWEST_OPERATIONS: usize = 16;
+
+/// Statistics about the accesses to the database since the node has started.
+#[derive(Debug, Clone)]
+pub struct DatabaseMetrics {
+    /// Operations executed by the connection that modifies the database.
+    pub write_connection: DatabaseOperationsMetrics,
+    /// Operations executed by the read-only connections, if any. Read operations are executed by
+    /// the connection that modifies the database if there isn't any read-only connection.
+    pub read_connections: DatabaseOperationsMetrics,
+    /// Slowest operations executed so far, slowest first.
+    pub slowest_operations: Vec<SlowDatabaseOperation>,
+}
+
+/// Statistics about the operations executed by one kind of connection to the database.
+#[derive(Debug, Clone)]
+pub struct DatabaseOperationsMetrics {
+    /// Number of operations whose execution has finished.
+    pub num_operations: u64,
+    /// Number of operations currently waiting to be executed.
+    pub queue_length: usize,
+    /// Sum of the time each operation has waited before its execution started.
+    pub total_queue_time: Duration,
+    /// Sum of the durations of the execution of all the operations.
+    pub total_duration: Duration,
+    /// Number of operations per execution duration bucket. Entry `n` contains the number of
+    /// operations whose duration is inferior or equal to `LATENCY_BUCKETS[n]` and, if `n != 0`,
+    /// strictly superior to `LATENCY_BUCKETS[n - 1]`. The last entry contains the operations
+    /// slower than all the buckets.
+    pub duration_histogram: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// See [`DatabaseMetrics::slowest_operations`].
+#[derive(Debug, Clone)]
+pub struct SlowDatabaseOperation {
+    /// Connection that has executed the operation.
+    pub connection: DatabaseConnectionKind,
+    /// Location in the source code of the full node where the operation has been started.
+    pub location: &'static Location<'static>,
+    /// Duration of the execution of the operation, not including its waiting time.
+    pub duration: Duration,
+}
+
+/// Kind of connection to the database. See [`SlowDatabaseOperation::connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseConnectionKind {
+    /// Connection that modifies the database.
+    Write,
+    /// One of the read-only connections.
+    Read,
+}
+
+/// Statistics shared between the [`super::DatabaseThread`] and the operations it executes.
+pub struct Metrics {
+    inner: Mutex<DatabaseMetrics>,
+}
+
+impl Metrics {
+    /// Creates a new empty [`Metrics`].
+    pub fn new() -> Self {
+        let empty = || DatabaseOperationsMetrics {
+            num_operations: 0,
+            queue_length: 0,
+            total_queue_time: Duration::new(0, 0),
+            total_duration: Duration::new(0, 0),
+            duration_histogram: [0; LATENCY_BUCKETS.len() + 1],
+        };
+
+        Metrics {
+            inner: Mutex::new(DatabaseMetrics {
+                write_connection: empty(),
+                read_connections: empty(),
+                slowest_operations: Vec::with_capacity(NUM_SLOWEST_OPERATIONS),
+            }),
+        }
+    }
+
+    /// Returns the statistics gathered so far. [`DatabaseOperationsMetrics::queue_length`] is
+    /// set to `0`, as the queues aren't known to this object.
+    pub fn snapshot(&self) -> DatabaseMetrics {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Must be called when the execution of an operation has finished. `queued_at` is the moment
+    /// when the operation has been sent to the connection, and `started_at` the moment when its
+    /// execution has started.
+    pub fn record(
+        &self,
+        connection: DatabaseConnectionKind,
+        location: &'static Location<'static>,
+        queued_at: Instant,
+        started_at: Instant,
+    ) {
+        let duration = started_at.elapsed();
+        let mut inner = self.inner.lock().unwrap();
+
+        let entry = match connection {
+            DatabaseConnectionKind::Write => &mut inner.write_connection,
+            DatabaseConnectionKind::Read => &mut inner.read_connections,
+        };
+        entry.num_operations += 1;
+        entry.total_queue_time += started_at.saturating_duration_since(queued_at);
+        entry.total_duration += duration;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        entry.duration_histogram[bucket] += 1;
+
+        let position = inner
+            .slowest_operations
+            .iter()
+            .position(|op| op.duration < duration)
+            .unwrap_or(inner.slowest_operations.len());
+        if position < NUM_SLOWEST_OPERATIONS {
+            inner.slowest_operations.insert(
+                position,
+                SlowDatabaseOperation {
+                    connection,
+                    location,
+                    duration,
+                },
+            );
+            inner.slowest_operations.truncate(NUM_SLOWEST_OPERATIONS);
+        }
+    }
+}
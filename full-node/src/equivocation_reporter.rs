@@ -0,0 +1,435 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reporting of the equivocations detected by the node.
+//!
+//! An equivocation is committed by an authority that produces two different blocks for the same
+//! slot, or that casts two different GrandPa votes of the same kind during the same round. The
+//! consensus service and the GrandPa voter send the equivocations that they detect to this
+//! task, which turns them into unsigned extrinsics and announces these extrinsics to the peers.
+//!
+//! Reporting an equivocation is done in two steps, both against the current finalized block:
+//! the `*Api_generate_key_ownership_proof` runtime function builds a proof that the offender is
+//! part of the authorities of the session, then the
+//! `*Api_submit_report_equivocation_unsigned_extrinsic` runtime function builds the extrinsic and
+//! submits it through the offchain transactions host function.
+//!
+//! > **Note**: Aura doesn't provide any runtime function to report equivocations. Aura
+//! >           equivocations are detected and logged by the consensus service but never
+//! >           reported.
+
+use crate::{
+    consensus_service, database_thread, network_service, offchain_worker, runtime_calls_limiter,
+    transactions_pool, LogCallback, LogLevel,
+};
+
+use futures_lite::FutureExt as _;
+use hashbrown::HashMap;
+use rand::SeedableRng as _;
+use smol::stream::StreamExt as _;
+use smoldot::{executor, finality::voter, informant::HashDisplay, transactions::validate};
+use std::{num::NonZeroUsize, sync::Arc};
+
+/// Maximum number of equivocations remembered in order to not report the same offence twice.
+const REPORTED_EQUIVOCATIONS_CAPACITY: usize = 256;
+
+/// Equivocation detected by the node.
+#[derive(Debug, Clone)]
+pub enum Equivocation {
+    /// A Babe authority has produced two different blocks for the same slot.
+    Babe {
+        /// Sr25519 public key of the authority that has produced both blocks.
+        offender: [u8; 32],
+        /// Slot both blocks have been produced for.
+        slot: u64,
+        /// SCALE-encoded header of the first block.
+        first_header: Vec<u8>,
+        /// SCALE-encoded header of the second block.
+        second_header: Vec<u8>,
+    },
+    /// A GrandPa authority has cast two different votes of the same kind during the same round.
+    Grandpa {
+        /// Identifier of the authorities set of the round.
+        set_id: u64,
+        /// Number of the round both votes belong to.
+        round_number: u64,
+        /// Ed25519 public key of the authority that has cast both votes.
+        offender: [u8; 32],
+        /// Kind of both votes.
+        kind: voter::VoteKind,
+        /// First vote received.
+        first: voter::Vote,
+        /// Second vote received.
+        second: voter::Vote,
+    },
+}
+
+impl Equivocation {
+    /// Returns a value identifying the offence, independently of which blocks or votes have
+    /// been seen.
+    fn offence_id(&self) -> (u8, u64, u64, [u8; 32]) {
+        match self {
+            Equivocation::Babe { offender, slot, .. } => (0, *slot, 0, *offender),
+            Equivocation::Grandpa {
+                set_id,
+                round_number,
+                offender,
+                kind: voter::VoteKind::Prevote,
+                ..
+            } => (1, *set_id, *round_number, *offender),
+            Equivocation::Grandpa {
+                set_id,
+                round_number,
+                offender,
+                kind: voter::VoteKind::Precommit,
+                ..
+            } => (2, *set_id, *round_number, *offender),
+        }
+    }
+}
+
+/// Configuration for [`run`].
+pub struct Config {
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Consensus service of the chain. Used to track the finalized block.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Database of the chain. Used to access the storage of the finalized block.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Service used to announce the equivocation reports.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Pool where the equivocation reports are inserted, so that they are included in the
+    /// blocks authored by the node.
+    pub transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// Limiter of the runtime executions. Reports aren't consensus-critical and are thus treated
+    /// like JSON-RPC runtime calls.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+
+    /// Receiver of the equivocations to report.
+    pub equivocations_rx: async_channel::Receiver<Equivocation>,
+
+    /// Seed used to initialize the source of the random seeds that the runtime requests while
+    /// building the reports.
+    pub randomness_seed: [u8; 32],
+}
+
+/// Runs the equivocations reporter. Returns when all the senders of
+/// [`Config::equivocations_rx`] have been dropped.
+pub async fn run(config: Config) {
+    let mut reported =
+        lru::LruCache::new(NonZeroUsize::new(REPORTED_EQUIVOCATIONS_CAPACITY).unwrap());
+    let mut randomness = rand::rngs::StdRng::from_seed(config.randomness_seed);
+
+    loop {
+        let subscribe_all = config
+            .consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Runtime of each block of the subscription, including the finalized block.
+        let mut runtimes = HashMap::<_, _, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+            subscribe_all.non_finalized_blocks_ancestry_order.len() + 1,
+            Default::default(),
+        );
+        let mut finalized_block_hash = subscribe_all.finalized_block_hash;
+        runtimes.insert(
+            subscribe_all.finalized_block_hash,
+            subscribe_all.finalized_block_runtime,
+        );
+        config
+            .consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let runtime = match block.runtime_update {
+                Some(runtime) => runtime,
+                None => runtimes.get(&block.parent_hash).unwrap().clone(),
+            };
+            runtimes.insert(block.block_hash, runtime);
+            config
+                .consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        loop {
+            enum WakeUpReason {
+                Notification(Option<consensus_service::Notification>),
+                Equivocation(Option<Equivocation>),
+            }
+
+            let wake_up_reason = async { WakeUpReason::Notification(new_blocks.next().await) }
+                .or(async { WakeUpReason::Equivocation(config.equivocations_rx.recv().await.ok()) })
+                .await;
+
+            match wake_up_reason {
+                WakeUpReason::Notification(Some(consensus_service::Notification::Block {
+                    block,
+                    ..
+                })) => {
+                    let runtime = match block.runtime_update {
+                        Some(runtime) => runtime,
+                        None => runtimes.get(&block.parent_hash).unwrap().clone(),
+                    };
+                    runtimes.insert(block.block_hash, runtime);
+                    config
+                        .consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                WakeUpReason::Notification(Some(consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks_hashes,
+                    ..
+                })) => {
+                    runtimes.remove(&finalized_block_hash);
+                    for hash in finalized_blocks_newest_to_oldest.iter().skip(1) {
+                        runtimes.remove(hash);
+                    }
+                    for hash in &pruned_blocks_hashes {
+                        runtimes.remove(hash);
+                    }
+                    finalized_block_hash = finalized_blocks_newest_to_oldest[0];
+                }
+                WakeUpReason::Notification(None) => {
+                    // The consensus service has killed the subscription. Subscribe again.
+                    break;
+                }
+                WakeUpReason::Equivocation(Some(equivocation)) => {
+                    if reported.put(equivocation.offence_id(), ()).is_some() {
+                        continue;
+                    }
+
+                    let runtime = runtimes.get(&finalized_block_hash).unwrap().clone();
+                    let result = report(
+                        &config,
+                        &mut randomness,
+                        &finalized_block_hash,
+                        &runtime,
+                        &equivocation,
+                    )
+                    .await;
+                    let (engine, offender) = match &equivocation {
+                        Equivocation::Babe { offender, .. } => ("babe", offender),
+                        Equivocation::Grandpa { offender, .. } => ("grandpa", offender),
+                    };
+
+                    match result {
+                        Ok(()) => config.log_callback.log(
+                            LogLevel::Info,
+                            format!(
+                                "equivocation-reported; engine={}; offender={}",
+                                engine,
+                                HashDisplay(offender)
+                            ),
+                        ),
+                        Err(error) => config.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "equivocation-report-error; engine={}; offender={}; error={}",
+                                engine,
+                                HashDisplay(offender),
+                                error
+                            ),
+                        ),
+                    }
+                }
+                WakeUpReason::Equivocation(None) => return,
+            }
+        }
+    }
+}
+
+/// Builds the report of the given equivocation against the given block, then validates and
+/// announces it.
+async fn report(
+    config: &Config,
+    randomness: &mut rand::rngs::StdRng,
+    block_hash: &[u8; 32],
+    runtime: &executor::host::HostVmPrototype,
+    equivocation: &Equivocation,
+) -> Result<(), ReportError> {
+    let (api_name, key_ownership_parameter, equivocation_proof) = match equivocation {
+        Equivocation::Babe {
+            offender,
+            slot,
+            first_header,
+            second_header,
+        } => {
+            let mut proof = Vec::with_capacity(32 + 8 + first_header.len() + second_header.len());
+            proof.extend_from_slice(offender);
+            proof.extend_from_slice(&slot.to_le_bytes());
+            proof.extend_from_slice(first_header);
+            proof.extend_from_slice(second_header);
+            (
+                "BabeApi",
+                [&slot.to_le_bytes()[..], offender].concat(),
+                proof,
+            )
+        }
+        Equivocation::Grandpa {
+            set_id,
+            round_number,
+            offender,
+            kind,
+            first,
+            second,
+        } => {
+            let mut proof = Vec::with_capacity(8 + 1 + 8 + 32 + 2 * (32 + 8 + 64));
+            proof.extend_from_slice(&set_id.to_le_bytes());
+            proof.push(match kind {
+                voter::VoteKind::Prevote => 0,
+                voter::VoteKind::Precommit => 1,
+            });
+            proof.extend_from_slice(&round_number.to_le_bytes());
+            proof.extend_from_slice(offender);
+            for vote in [first, second] {
+                proof.extend_from_slice(&vote.target_hash);
+                proof.extend_from_slice(
+                    &vote.target_number.to_le_bytes()[..config.block_number_bytes],
+                );
+                proof.extend_from_slice(&vote.signature);
+            }
+            (
+                "GrandpaApi",
+                [&set_id.to_le_bytes()[..], offender].concat(),
+                proof,
+            )
+        }
+    };
+
+    if runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version(api_name)
+        .is_none()
+    {
+        return Err(ReportError::UnsupportedRuntime);
+    }
+
+    // The key ownership proof is returned as an `Option` of an opaque proof, whose encoding is
+    // passed as-is to the submission function.
+    let key_ownership_proof = {
+        let _permit = config.runtime_calls_limiter.json_rpc_permit().await;
+        consensus_service::runtime_call(
+            &config.database,
+            block_hash,
+            runtime.clone(),
+            &format!("{api_name}_generate_key_ownership_proof"),
+            &key_ownership_parameter,
+            executor::runtime_call::StorageProofSizeBehavior::Unimplemented,
+            executor::runtime_call::StorageChanges::empty(),
+            false,
+        )
+        .await
+        .map_err(ReportError::RuntimeCall)?
+        .output
+    };
+    let key_ownership_proof = match key_ownership_proof.split_first() {
+        Some((0, [])) => return Err(ReportError::NoKeyOwnershipProof),
+        Some((1, proof)) => proof,
+        _ => return Err(ReportError::OutputDecode),
+    };
+
+    let mut submitted_transactions = Vec::new();
+    let output = {
+        let _permit = config.runtime_calls_limiter.json_rpc_permit().await;
+        consensus_service::offchain_runtime_call(
+            &config.database,
+            block_hash,
+            runtime.clone(),
+            &format!("{api_name}_submit_report_equivocation_unsigned_extrinsic"),
+            &[&equivocation_proof[..], key_ownership_proof].concat(),
+            consensus_service::OffchainEnvironment {
+                submitted_transactions: &mut submitted_transactions,
+                http_requests: None,
+                randomness,
+            },
+        )
+        .await
+        .map_err(ReportError::RuntimeCall)?
+        .output
+    };
+    if output != [1] {
+        return Err(ReportError::SubmitRefused);
+    }
+
+    for transaction in submitted_transactions {
+        let validity = offchain_worker::validate_transaction(
+            &config.database,
+            &config.runtime_calls_limiter,
+            block_hash,
+            runtime,
+            &transaction,
+            validate::TransactionSource::Local,
+        )
+        .await
+        .map_err(ReportError::Validate)?;
+
+        // A report that can't be inserted in the pool, for example because the pool is full, is
+        // nonetheless announced to the peers.
+        let propagate = validity.propagate;
+        let _ = config.transactions_pool.insert(
+            transaction.clone(),
+            validity,
+            validate::TransactionSource::Local,
+        );
+
+        if propagate {
+            config
+                .network_service
+                .0
+                .announce_transaction(config.network_service.1, transaction)
+                .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Error that can happen while reporting an equivocation.
+#[derive(Debug, derive_more::Display)]
+enum ReportError {
+    /// The runtime doesn't support reporting equivocations.
+    UnsupportedRuntime,
+    /// Error while executing a runtime function.
+    #[display(fmt = "{_0}")]
+    RuntimeCall(consensus_service::RuntimeCallError),
+    /// The offender isn't part of the authorities of the current session anymore.
+    NoKeyOwnershipProof,
+    /// Failed to decode the output of a runtime function.
+    OutputDecode,
+    /// The runtime has refused to submit the report.
+    SubmitRefused,
+    /// The report submitted by the runtime isn't valid.
+    #[display(fmt = "{_0}")]
+    Validate(offchain_worker::ValidateError),
+}
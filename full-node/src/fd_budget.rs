@@ -0,0 +1,139 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Accounting of the file descriptors used by the node.
+//!
+//! Operating systems limit the number of file descriptors (or handles) that a process can have
+//! open at the same time, and this limit is often low when running in a container. Once the
+//! limit is reached, opening a socket or a file fails at a random place of the code, which is
+//! hard to recover from.
+//!
+//! Instead of relying on the errors returned by the operating system, the node keeps track of
+//! the sockets and database handles that it opens and compares them with a budget, which works
+//! identically on all platforms. Incoming connections are refused and outgoing connections are
+//! postponed while the budget is exhausted. Listening sockets and database handles, which are
+//! necessary for the node to function, are always accounted for even if they exceed the budget.
+
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+/// Number of file descriptors used by an SQLite database stored on disk: the database file,
+/// the write-ahead log, and the shared memory index.
+pub const SQLITE_DISK_DATABASE_FILE_DESCRIPTORS: usize = 3;
+
+/// Utilization of the file descriptors budget. See [`crate::Client::file_descriptors_usage`].
+#[derive(Debug, Clone)]
+pub struct FileDescriptorsUsage {
+    /// Maximum number of file descriptors that the node opens. `None` if no limit is enforced.
+    pub max: Option<NonZeroUsize>,
+    /// Number of networking sockets currently open, including the listening sockets.
+    pub sockets: usize,
+    /// Number of file descriptors used by the databases.
+    pub database: usize,
+    /// Number of times a socket hasn't been opened or has been closed immediately because the
+    /// budget was exhausted, since the node has started.
+    pub num_refused_sockets: u64,
+}
+
+/// Budget of file descriptors shared between the services of the node.
+pub struct FdBudget {
+    /// See [`FileDescriptorsUsage::max`].
+    max: Option<NonZeroUsize>,
+    /// Current utilization. Its [`FileDescriptorsUsage::max`] is always equal to
+    /// [`FdBudget::max`].
+    usage: Mutex<FileDescriptorsUsage>,
+}
+
+impl FdBudget {
+    /// Creates a new [`FdBudget`] with nothing allocated yet.
+    pub fn new(max: Option<NonZeroUsize>) -> Self {
+        FdBudget {
+            max,
+            usage: Mutex::new(FileDescriptorsUsage {
+                max,
+                sockets: 0,
+                database: 0,
+                num_refused_sockets: 0,
+            }),
+        }
+    }
+
+    /// Returns `true` if [`FdBudget::try_acquire_socket`] would currently succeed.
+    pub fn has_available_socket(&self) -> bool {
+        let usage = self.usage.lock().unwrap();
+        self.max
+            .map_or(true, |max| usage.sockets + usage.database < max.get())
+    }
+
+    /// Allocates one socket from the budget. Returns `None` if the budget is exhausted, in which
+    /// case the socket must not be opened or must be closed.
+    ///
+    /// The socket is given back to the budget when the returned [`SocketPermit`] is destroyed.
+    pub fn try_acquire_socket(self: &Arc<Self>) -> Option<SocketPermit> {
+        let mut usage = self.usage.lock().unwrap();
+        if self
+            .max
+            .map_or(false, |max| usage.sockets + usage.database >= max.get())
+        {
+            usage.num_refused_sockets += 1;
+            return None;
+        }
+
+        usage.sockets += 1;
+        Some(SocketPermit {
+            budget: self.clone(),
+        })
+    }
+
+    /// Allocates one socket from the budget, even if the budget is exhausted.
+    ///
+    /// Must only be used for sockets that the node can't function without, such as the
+    /// listening sockets.
+    pub fn acquire_socket(self: &Arc<Self>) -> SocketPermit {
+        self.usage.lock().unwrap().sockets += 1;
+        SocketPermit {
+            budget: self.clone(),
+        }
+    }
+
+    /// Allocates the given number of file descriptors to the databases, even if the budget is
+    /// exhausted.
+    ///
+    /// The databases are kept open for the entire lifetime of the node, and these file
+    /// descriptors are thus never given back.
+    pub fn add_database_handles(&self, num: usize) {
+        self.usage.lock().unwrap().database += num;
+    }
+
+    /// Returns the current utilization of the budget.
+    pub fn usage(&self) -> FileDescriptorsUsage {
+        self.usage.lock().unwrap().clone()
+    }
+}
+
+/// Socket allocated from a [`FdBudget`]. Gives back the socket to the budget when destroyed.
+pub struct SocketPermit {
+    budget: Arc<FdBudget>,
+}
+
+impl Drop for SocketPermit {
+    fn drop(&mut self) {
+        self.budget.usage.lock().unwrap().sockets -= 1;
+    }
+}
@@ -0,0 +1,1289 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Participation of the local node in the GrandPa rounds.
+//!
+//! The voter follows the rounds of the current GrandPa authorities set by collecting the votes
+//! gossiped on the network. If the keystore contains a GrandPa key that belongs to the
+//! authorities set, the voter additionally casts prevotes and precommits signed with this key.
+//! Once a round is completable, the voter assembles a commit, gossips it, and injects it in the
+//! consensus service, which finalizes the blocks.
+//!
+//! If the local node is the primary of a round, it broadcasts the estimate of the previous round
+//! as its primary proposal, provided that this estimate hasn't been finalized yet. The primary
+//! proposals of the other authorities are taken into account when choosing the target of the
+//! prevotes.
+//!
+//! The votes of the local node never go beyond a block that triggers a change of authorities,
+//! whether this change has been scheduled by a finalized or a non-finalized block.
+//!
+//! In order to never cast conflicting votes in the same round, which is considered as
+//! misbehavior, the latest round the local node has voted in and the votes it has cast in this
+//! round are stored in the database before each vote is sent out. After a restart, these votes
+//! are restored and gossiped again rather than cast anew.
+//!
+//! If a peer is more than one round ahead, according to its neighbor packets, the voter sends it
+//! a catch up request. The peer answers with the votes of a round it has completed, which the
+//! voter verifies and uses to complete this round and move on to the next one. Additionally, if
+//! authorities weighing more than a third of the set are voting in a later round, the voter
+//! assumes that it is lagging behind and jumps to this round.
+
+use crate::{
+    consensus_service, database_thread, equivocation_reporter, network_service, LogCallback,
+    LogLevel,
+};
+
+use futures_lite::FutureExt as _;
+use futures_util::{future, stream, StreamExt as _};
+use hashbrown::HashMap;
+use smoldot::{
+    chain::chain_information,
+    database::full_sqlite::GrandpaVoterState,
+    finality::{decode::PrecommitRef, voter},
+    header,
+    identity::keystore,
+    informant::HashDisplay,
+    libp2p::peer_id::PeerId,
+    network::{
+        codec,
+        service::{EncodedGrandpaCatchUp, EncodedGrandpaVoteMessage},
+    },
+};
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Time it takes for a gossip message to reach all the authorities. Corresponds to `T` in the
+/// GrandPa paper. Prevotes are cast `2T` after the start of a round, and precommits at the
+/// latest `4T` after the start of a round.
+const GOSSIP_DURATION: Duration = Duration::from_secs(1);
+
+/// Maximum number of votes for rounds later than the current one that are kept in memory.
+const MAX_FUTURE_VOTES: usize = 1024;
+
+/// Time after which a catch up request that hasn't been answered is considered lost, and a new
+/// one can be sent.
+const CATCH_UP_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Configuration for [`run`].
+pub struct Config {
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Consensus service of the chain. Used to track the blocks and to inject the commits.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Service used to gossip votes and commits.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Receiver of the events of the network service. Used to receive the votes of the other
+    /// authorities.
+    pub network_events_receiver: stream::BoxStream<'static, network_service::Event>,
+
+    /// Database of the chain. Used to load the authorities set.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Keystore containing the GrandPa key used to sign votes.
+    pub keystore: Arc<keystore::Keystore>,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+
+    /// If `Some`, the equivocations of the other authorities are sent to this channel in order
+    /// to be reported. Equivocations are always logged, even if `None`.
+    pub equivocation_reports: Option<async_channel::Sender<equivocation_reporter::Equivocation>>,
+}
+
+/// Same as [`Config`], minus [`Config::network_events_receiver`]. Contrary to [`Config`], this
+/// struct is `Sync` and can thus be borrowed across `await` points.
+struct Services {
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+    database: Arc<database_thread::DatabaseThread>,
+    keystore: Arc<keystore::Keystore>,
+    block_number_bytes: usize,
+    equivocation_reports: Option<async_channel::Sender<equivocation_reporter::Equivocation>>,
+}
+
+/// Runs the voter. Never returns, unless the network service has shut down.
+pub async fn run(config: Config) {
+    let mut network_events_receiver = config.network_events_receiver;
+    let config = Services {
+        log_callback: config.log_callback,
+        consensus_service: config.consensus_service,
+        network_service: config.network_service,
+        database: config.database,
+        keystore: config.keystore,
+        block_number_bytes: config.block_number_bytes,
+        equivocation_reports: config.equivocation_reports,
+    };
+
+    let (persisted_state, can_vote) = match config
+        .database
+        .with_database(|database| database.grandpa_voter_state())
+        .await
+    {
+        Ok(state) => (state, true),
+        Err(err) => {
+            // Not being able to load the state is handled by never voting, which is the safe
+            // option.
+            config.log_callback.log(
+                LogLevel::Warn,
+                format!("grandpa-voter-state-load-error; error={}", err),
+            );
+            (None, false)
+        }
+    };
+
+    let mut voter = Voter {
+        blocks: HashMap::with_capacity_and_hasher(0, Default::default()),
+        finalized: ([0; 32], 0),
+        best: ([0; 32], 0),
+        round: None,
+        next_round_start: Instant::now(),
+        last_completed_round: None,
+        persisted_state,
+        can_vote,
+        future_votes: Vec::new(),
+        pending_jump: None,
+        pending_catch_up: None,
+    };
+
+    loop {
+        let subscribe_all = config
+            .consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Only the hashes and numbers of the blocks are needed, and the blocks are thus
+        // immediately unpinned.
+        voter.blocks.clear();
+        if let Ok(decoded) = header::decode(
+            &subscribe_all.finalized_block_scale_encoded_header,
+            config.block_number_bytes,
+        ) {
+            voter.finalized = (subscribe_all.finalized_block_hash, decoded.number);
+            voter.best = voter.finalized;
+            voter.blocks.insert(
+                subscribe_all.finalized_block_hash,
+                Block {
+                    parent_hash: *decoded.parent_hash,
+                    number: decoded.number,
+                    // Changes scheduled by the finalized block are part of the chain
+                    // information.
+                    scheduled_change: None,
+                },
+            );
+        }
+        config
+            .consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            voter.insert_block(&config, &block);
+            config
+                .consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        loop {
+            voter.progress(&config).await;
+
+            let deadline = voter.next_deadline();
+            let wake_up_reason = async { WakeUpReason::Notification(new_blocks.next().await) }
+                .or(async { WakeUpReason::NetworkEvent(network_events_receiver.next().await) })
+                .or(async {
+                    match deadline {
+                        Some(deadline) => {
+                            smol::Timer::at(deadline).await;
+                        }
+                        None => future::pending().await,
+                    }
+                    WakeUpReason::Timer
+                })
+                .await;
+
+            match wake_up_reason {
+                WakeUpReason::Notification(Some(consensus_service::Notification::Block {
+                    block,
+                    ..
+                })) => {
+                    voter.insert_block(&config, &block);
+                    config
+                        .consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                WakeUpReason::Notification(Some(consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    best_block_hash,
+                    pruned_blocks_hashes,
+                })) => {
+                    if let Some(block) = voter.blocks.get(&finalized_blocks_newest_to_oldest[0]) {
+                        voter.finalized = (finalized_blocks_newest_to_oldest[0], block.number);
+                    }
+                    if let Some(block) = voter.blocks.get(&best_block_hash) {
+                        voter.best = (best_block_hash, block.number);
+                    }
+                    for hash in pruned_blocks_hashes {
+                        voter.blocks.remove(&hash);
+                    }
+
+                    // If the finalized block is past a change of authorities, the round in
+                    // progress belongs to an outdated authorities set and is abandoned.
+                    if voter.round.as_ref().map_or(false, |round| {
+                        round
+                            .scheduled_change_number
+                            .map_or(false, |n| voter.finalized.1 >= n)
+                    }) {
+                        voter.round = None;
+                        voter.last_completed_round = None;
+                    }
+                }
+                WakeUpReason::Notification(None) => {
+                    // The consensus service has killed the subscription. Subscribe again.
+                    break;
+                }
+                WakeUpReason::NetworkEvent(Some(network_service::Event::GrandpaVoteMessage {
+                    chain_id,
+                    peer_id,
+                    message,
+                })) if chain_id == config.network_service.1 => {
+                    voter.on_vote_message(&config, &peer_id, message);
+                }
+                WakeUpReason::NetworkEvent(Some(
+                    network_service::Event::GrandpaNeighborPacket {
+                        chain_id,
+                        peer_id,
+                        round_number,
+                        set_id,
+                        ..
+                    },
+                )) if chain_id == config.network_service.1 => {
+                    voter
+                        .on_neighbor_packet(&config, peer_id, round_number, set_id)
+                        .await;
+                }
+                WakeUpReason::NetworkEvent(Some(
+                    network_service::Event::GrandpaCatchUpRequest {
+                        chain_id,
+                        peer_id,
+                        round_number,
+                        set_id,
+                    },
+                )) if chain_id == config.network_service.1 => {
+                    voter
+                        .on_catch_up_request(&config, peer_id, round_number, set_id)
+                        .await;
+                }
+                WakeUpReason::NetworkEvent(Some(network_service::Event::GrandpaCatchUp {
+                    chain_id,
+                    peer_id,
+                    message,
+                })) if chain_id == config.network_service.1 => {
+                    voter.on_catch_up(&config, &peer_id, message);
+                }
+                WakeUpReason::NetworkEvent(Some(_)) => {}
+                WakeUpReason::NetworkEvent(None) => {
+                    // The network service has shut down.
+                    return;
+                }
+                WakeUpReason::Timer => {}
+            }
+        }
+    }
+}
+
+enum WakeUpReason {
+    Notification(Option<consensus_service::Notification>),
+    NetworkEvent(Option<network_service::Event>),
+    Timer,
+}
+
+struct Voter {
+    /// Blocks known to the voter, including the finalized block.
+    blocks: HashMap<[u8; 32], Block, fnv::FnvBuildHasher>,
+    /// Hash and number of the latest finalized block.
+    finalized: ([u8; 32], u64),
+    /// Hash and number of the current best block.
+    best: ([u8; 32], u64),
+    /// Round in progress. `None` if the next round hasn't started yet.
+    round: Option<Round>,
+    /// If [`Voter::round`] is `None`, when to start the next round.
+    next_round_start: Instant,
+    /// Information about the latest completed round.
+    last_completed_round: Option<CompletedRound>,
+    /// State stored in the database: latest round the local node has voted in, and votes cast
+    /// in this round.
+    persisted_state: Option<GrandpaVoterState>,
+    /// If `false`, the local node never votes.
+    can_vote: bool,
+    /// Votes belonging to rounds later than the current round.
+    future_votes: Vec<EncodedGrandpaVoteMessage>,
+    /// If `Some`, the next round to start is the given one rather than the one that follows the
+    /// latest completed round.
+    pending_jump: Option<u64>,
+    /// If `Some`, a catch up request has been sent to the given peer, and is considered lost
+    /// after the given instant.
+    pending_catch_up: Option<(PeerId, Instant)>,
+}
+
+struct Block {
+    parent_hash: [u8; 32],
+    number: u64,
+    /// If the block schedules a change of authorities, number of the block at which the change
+    /// is triggered.
+    scheduled_change: Option<u64>,
+}
+
+struct CompletedRound {
+    /// Votes of the round. Used to answer catch up requests.
+    inner: voter::Round,
+    set_id: u64,
+    round_number: u64,
+    /// Hash and number of the estimate of the round.
+    estimate: ([u8; 32], u64),
+    /// Hash and number of the prevote GHOST of the round.
+    prevote_ghost: ([u8; 32], u64),
+}
+
+struct Round {
+    inner: voter::Round,
+    /// Authorities of the set the round belongs to.
+    authorities: Vec<header::GrandpaAuthority>,
+    /// Public key of the local authority, or `None` if the local node doesn't vote in this
+    /// round.
+    local_authority: Option<[u8; 32]>,
+    /// When the round has started.
+    start: Instant,
+    /// Estimate of the previous round. The votes of the local node must be descendants of
+    /// this block.
+    previous_estimate: ([u8; 32], u64),
+    /// Prevote GHOST of the previous round. Used to determine whether the primary proposal
+    /// should be taken into account.
+    previous_prevote_ghost: ([u8; 32], u64),
+    /// Number of the block after which the authorities set changes, if any. The votes of the
+    /// local node must not go beyond this block.
+    scheduled_change_number: Option<u64>,
+    proposed: bool,
+    prevoted: bool,
+    precommitted: bool,
+}
+
+impl Voter {
+    fn insert_block(&mut self, config: &Services, block: &consensus_service::BlockNotification) {
+        let Ok(decoded) = header::decode(&block.scale_encoded_header, config.block_number_bytes)
+        else {
+            return;
+        };
+
+        // Forced changes are triggered when the block that schedules them is imported rather
+        // than finalized, and thus don't restrict the targets of the votes.
+        let scheduled_change = decoded.digest.logs().find_map(|log| match log {
+            header::DigestItemRef::GrandpaConsensus(
+                header::GrandpaConsensusLogRef::ScheduledChange(change),
+            ) => Some(decoded.number.saturating_add(change.delay)),
+            _ => None,
+        });
+
+        self.blocks.insert(
+            block.block_hash,
+            Block {
+                parent_hash: block.parent_hash,
+                number: decoded.number,
+                scheduled_change,
+            },
+        );
+        if block.is_new_best {
+            self.best = (block.block_hash, decoded.number);
+        }
+    }
+
+    fn parent_of(&self, hash: &[u8; 32]) -> Option<[u8; 32]> {
+        self.blocks.get(hash).map(|block| block.parent_hash)
+    }
+
+    /// Returns the hash of the ancestor of the given block (inclusive) whose number is
+    /// `ancestor_number`, or `None` if unknown.
+    fn ancestor_at(&self, block: ([u8; 32], u64), ancestor_number: u64) -> Option<[u8; 32]> {
+        let mut current = block;
+        while current.1 > ancestor_number {
+            current = (self.parent_of(&current.0)?, current.1 - 1);
+        }
+        (current.1 == ancestor_number).then_some(current.0)
+    }
+
+    fn is_descendant_or_equal(&self, block: ([u8; 32], u64), ancestor: ([u8; 32], u64)) -> bool {
+        self.ancestor_at(block, ancestor.1) == Some(ancestor.0)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let Some(round) = &self.round else {
+            return Some(self.next_round_start);
+        };
+
+        if round.local_authority.is_none() {
+            None
+        } else if !round.prevoted {
+            Some(round.start + 2 * GOSSIP_DURATION)
+        } else if !round.precommitted {
+            Some(round.start + 4 * GOSSIP_DURATION)
+        } else {
+            None
+        }
+    }
+
+    /// Starts a round, casts votes, or completes a round, depending on the current state.
+    async fn progress(&mut self, config: &Services) {
+        if self.round.is_none() {
+            if Instant::now() < self.next_round_start {
+                return;
+            }
+            self.next_round_start = Instant::now() + GOSSIP_DURATION;
+            self.start_round(config, self.pending_jump).await;
+        }
+
+        let Some(round) = &self.round else { return };
+        let now = Instant::now();
+        let parent_of = |hash: &[u8; 32]| self.parent_of(hash);
+
+        if round.local_authority.is_some() && !round.proposed {
+            // The primary proposes the estimate of the previous round, unless it is already
+            // finalized.
+            if round.local_authority.as_ref() == Some(round.inner.primary())
+                && round.previous_estimate.1 > self.finalized.1
+            {
+                let target = round.previous_estimate;
+                self.cast_vote(
+                    config,
+                    codec::MessageRef::PrimaryPropose(codec::PrimaryProposeRef {
+                        target_hash: &target.0,
+                        target_number: target.1,
+                    }),
+                )
+                .await;
+            } else {
+                self.round.as_mut().unwrap().proposed = true;
+            }
+            return;
+        }
+
+        if round.local_authority.is_some() && !round.prevoted {
+            if now < round.start + 2 * GOSSIP_DURATION {
+                return;
+            }
+
+            // The primary proposal is taken into account only if it is a descendant of the
+            // estimate of the previous round and an ancestor of the prevote GHOST of the previous
+            // round. In other words, if it could still have been finalized by the previous round.
+            let base = match round.inner.primary_proposal() {
+                Some(proposal)
+                    if proposal.1 > round.previous_estimate.1
+                        && self.is_descendant_or_equal(proposal, round.previous_estimate)
+                        && self.is_descendant_or_equal(round.previous_prevote_ghost, proposal) =>
+                {
+                    proposal
+                }
+                _ => round.previous_estimate,
+            };
+
+            // Prevote for the best block, provided that it is a descendant of the base
+            // determined above.
+            let target = if self.is_descendant_or_equal(self.best, base) {
+                self.best
+            } else {
+                base
+            };
+            let target = self.cap_vote_target(target);
+            self.cast_vote(
+                config,
+                codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+                    target_hash: &target.0,
+                    target_number: target.1,
+                }),
+            )
+            .await;
+            return;
+        }
+
+        if round.local_authority.is_some() && !round.precommitted {
+            let Some(prevote_ghost) = round.inner.prevote_ghost(parent_of) else {
+                return;
+            };
+            if !self.is_descendant_or_equal(prevote_ghost, round.previous_estimate) {
+                return;
+            }
+            if now < round.start + 4 * GOSSIP_DURATION && !round.inner.is_completable(parent_of) {
+                return;
+            }
+
+            let target = self.cap_vote_target(prevote_ghost);
+            self.cast_vote(
+                config,
+                codec::MessageRef::Precommit(codec::UnsignedPrecommitRef {
+                    target_hash: &target.0,
+                    target_number: target.1,
+                }),
+            )
+            .await;
+            return;
+        }
+
+        if !round.inner.is_completable(parent_of) {
+            return;
+        }
+
+        let estimate = round
+            .inner
+            .estimate(parent_of)
+            .unwrap_or_else(|| round.inner.base());
+        let prevote_ghost = round.inner.prevote_ghost(parent_of).unwrap_or(estimate);
+        let commit = round.inner.commit(parent_of);
+
+        if let Some(commit) = commit.filter(|c| c.target_number > self.finalized.1) {
+            config.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "grandpa-commit; round={}; target={}; num_precommits={}",
+                    commit.round_number,
+                    HashDisplay(&commit.target_hash),
+                    commit.precommits.len()
+                ),
+            );
+
+            let scale_encoded_commit = commit.scale_encoding_vec(config.block_number_bytes);
+            config
+                .network_service
+                .0
+                .broadcast_grandpa_commit(config.network_service.1, scale_encoded_commit.clone())
+                .await;
+            config
+                .consensus_service
+                .inject_grandpa_commit(scale_encoded_commit)
+                .await;
+        }
+
+        let round = self.round.take().unwrap();
+        self.last_completed_round = Some(CompletedRound {
+            set_id: round.inner.set_id(),
+            round_number: round.inner.round_number(),
+            inner: round.inner,
+            estimate,
+            prevote_ghost,
+        });
+        self.next_round_start = Instant::now();
+    }
+
+    /// Starts a new round. If `round_number` is `None`, the round that follows the latest
+    /// completed round is started.
+    ///
+    /// Does nothing if the authorities set couldn't be loaded from the database.
+    async fn start_round(&mut self, config: &Services, round_number: Option<u64>) {
+        let finalized_hash = self.finalized.0;
+        let authorities_set = config
+            .database
+            .with_database(move |database| {
+                let chain_information = database.to_chain_information(&finalized_hash).ok()?;
+                match chain_information.as_ref().finality {
+                    chain_information::ChainInformationFinalityRef::Grandpa {
+                        after_finalized_block_authorities_set_id,
+                        finalized_triggered_authorities,
+                        finalized_scheduled_change,
+                    } => Some((
+                        after_finalized_block_authorities_set_id,
+                        finalized_triggered_authorities.to_vec(),
+                        finalized_scheduled_change.map(|(number, _)| number),
+                    )),
+                    chain_information::ChainInformationFinalityRef::Outsourced => None,
+                }
+            })
+            .await;
+
+        // The database might be temporarily out of sync with the consensus service. Try again
+        // later.
+        let Some((set_id, authorities, scheduled_change_number)) =
+            authorities_set.filter(|(_, authorities, _)| !authorities.is_empty())
+        else {
+            return;
+        };
+        self.pending_jump = None;
+
+        let (round_number, previous_estimate, previous_prevote_ghost) =
+            match (round_number, &self.last_completed_round) {
+                (Some(n), _) => (n, self.finalized, self.finalized),
+                (None, Some(last)) if last.set_id == set_id => {
+                    (last.round_number + 1, last.estimate, last.prevote_ghost)
+                }
+                (None, _) => (1, self.finalized, self.finalized),
+            };
+        let (previous_estimate, previous_prevote_ghost) =
+            if self.is_descendant_or_equal(previous_estimate, self.finalized) {
+                (previous_estimate, previous_prevote_ghost)
+            } else {
+                (self.finalized, self.finalized)
+            };
+
+        // Blocks below the base of the round are no longer needed.
+        let base_number = self.finalized.1;
+        self.blocks.retain(|_, block| block.number >= base_number);
+
+        let local_authority = {
+            let mut local_authority = None;
+            for (namespace, public_key) in config.keystore.keys().await {
+                if namespace == keystore::KeyNamespace::Grandpa
+                    && authorities
+                        .iter()
+                        .any(|authority| authority.public_key == public_key)
+                {
+                    local_authority = Some(public_key);
+                    break;
+                }
+            }
+            local_authority
+        };
+
+        // Never vote in a round earlier than the latest round the local node has voted in. The
+        // votes already cast in this latest round are restored below.
+        let local_authority = local_authority.filter(|_| {
+            self.can_vote
+                && self.persisted_state.as_ref().map_or(true, |state| {
+                    state.set_id != set_id || state.round_number <= round_number
+                })
+        });
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "grandpa-round-start; set_id={}; round={}; base={}; is_voter={}",
+                set_id,
+                round_number,
+                HashDisplay(&self.finalized.0),
+                local_authority.is_some()
+            ),
+        );
+
+        let mut round = Round {
+            inner: voter::Round::new(voter::Config {
+                round_number,
+                set_id,
+                authorities: authorities.iter().cloned(),
+                base: self.finalized,
+                block_number_bytes: config.block_number_bytes,
+            }),
+            authorities,
+            local_authority,
+            start: Instant::now(),
+            previous_estimate,
+            previous_prevote_ghost,
+            scheduled_change_number,
+            proposed: false,
+            prevoted: false,
+            precommitted: false,
+        };
+
+        // If the local node has already voted in this round before a restart, import its votes
+        // and gossip them again instead of casting new ones.
+        if let (Some(local_authority), Some(state)) = (round.local_authority, &self.persisted_state)
+        {
+            if state.set_id == set_id && state.round_number == round_number {
+                for encoded_vote in &state.votes {
+                    let vote = match codec::decode_grandpa_notification(
+                        encoded_vote,
+                        config.block_number_bytes,
+                    ) {
+                        Ok(codec::GrandpaNotificationRef::Vote(vote)) => vote,
+                        _ => {
+                            config.log_callback.log(
+                                LogLevel::Warn,
+                                "grandpa-voter-state-corrupted-vote".to_string(),
+                            );
+                            round.local_authority = None;
+                            break;
+                        }
+                    };
+
+                    if let Err(err) = round.inner.import_vote(&vote) {
+                        config.log_callback.log(
+                            LogLevel::Warn,
+                            format!("grandpa-voter-state-vote-import-error; error={}", err),
+                        );
+                        round.local_authority = None;
+                        break;
+                    }
+
+                    if matches!(vote.message, codec::MessageRef::PrimaryPropose(_)) {
+                        round.proposed = true;
+                    }
+
+                    config
+                        .network_service
+                        .0
+                        .broadcast_grandpa_vote(
+                            config.network_service.1,
+                            vote.scale_encoding_vec(config.block_number_bytes),
+                        )
+                        .await;
+                }
+
+                round.prevoted = round
+                    .inner
+                    .vote(voter::VoteKind::Prevote, &local_authority)
+                    .is_some();
+                round.precommitted = round
+                    .inner
+                    .vote(voter::VoteKind::Precommit, &local_authority)
+                    .is_some();
+                // Proposing always happens before prevoting.
+                round.proposed |= round.prevoted;
+
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-votes-restored; set_id={}; round={}; num_votes={}",
+                        set_id,
+                        round_number,
+                        state.votes.len()
+                    ),
+                );
+            }
+        }
+
+        self.round = Some(round);
+
+        // Inform the peers of the round the local node is in.
+        config
+            .network_service
+            .0
+            .set_grandpa_state(
+                config.network_service.1,
+                round_number,
+                set_id,
+                self.finalized.1,
+            )
+            .await;
+
+        // Import the votes that have been received in advance.
+        let future_votes = std::mem::take(&mut self.future_votes);
+        for message in future_votes {
+            let decoded = message.decode();
+            if decoded.set_id == set_id && decoded.round_number > round_number {
+                self.future_votes.push(message);
+            } else if decoded.set_id == set_id && decoded.round_number == round_number {
+                let _ = self.round.as_mut().unwrap().inner.import_vote(&decoded);
+            }
+        }
+    }
+
+    /// Returns the ancestor of the given block that the local node is allowed to vote for.
+    fn cap_vote_target(&self, target: ([u8; 32], u64)) -> ([u8; 32], u64) {
+        let round = self.round.as_ref().unwrap();
+
+        // Find the earliest change of authorities scheduled by the non-finalized ancestors of
+        // the target.
+        let mut limit = round.scheduled_change_number;
+        let mut current = target.0;
+        while let Some(block) = self
+            .blocks
+            .get(&current)
+            .filter(|block| block.number > self.finalized.1)
+        {
+            if let Some(n) = block.scheduled_change {
+                limit = Some(limit.map_or(n, |limit| limit.min(n)));
+            }
+            current = block.parent_hash;
+        }
+
+        match limit {
+            Some(n) if target.1 > n => match self.ancestor_at(target, n) {
+                Some(hash) => (hash, n),
+                None => round.previous_estimate,
+            },
+            _ => target,
+        }
+    }
+
+    /// Signs the given message with the key of the local authority, stores it in the database,
+    /// imports it in the current round, and broadcasts it.
+    async fn cast_vote(&mut self, config: &Services, message: codec::MessageRef<'_>) {
+        let round = self.round.as_mut().unwrap();
+        let Some(local_authority) = round.local_authority else {
+            return;
+        };
+        let round_id = (round.inner.set_id(), round.inner.round_number());
+
+        let (kind, target) = match &message {
+            codec::MessageRef::PrimaryPropose(v) => {
+                round.proposed = true;
+                ("PrimaryPropose", (*v.target_hash, v.target_number))
+            }
+            codec::MessageRef::Prevote(v) => {
+                round.prevoted = true;
+                ("Prevote", (*v.target_hash, v.target_number))
+            }
+            codec::MessageRef::Precommit(v) => {
+                round.precommitted = true;
+                ("Precommit", (*v.target_hash, v.target_number))
+            }
+        };
+
+        let signature = match config
+            .keystore
+            .sign(
+                keystore::KeyNamespace::Grandpa,
+                &local_authority,
+                &message.signature_payload(round_id.1, round_id.0, config.block_number_bytes),
+            )
+            .await
+        {
+            Ok(signature) => signature,
+            Err(err) => {
+                config.log_callback.log(
+                    LogLevel::Warn,
+                    format!("grandpa-vote-sign-error; error={}", err),
+                );
+                round.local_authority = None;
+                return;
+            }
+        };
+
+        let vote = codec::VoteMessageRef {
+            round_number: round_id.1,
+            set_id: round_id.0,
+            message,
+            signature: &signature,
+            authority_public_key: &local_authority,
+        };
+        let scale_encoded_vote = vote.scale_encoding_vec(config.block_number_bytes);
+
+        // Store the vote in the database before it is sent out, so that the local node never
+        // casts a conflicting vote after a restart.
+        let mut state = match self.persisted_state.take() {
+            Some(state) if (state.set_id, state.round_number) == round_id => state,
+            _ => GrandpaVoterState {
+                set_id: round_id.0,
+                round_number: round_id.1,
+                votes: Vec::new(),
+            },
+        };
+        state.votes.push(
+            [0u8]
+                .into_iter()
+                .chain(scale_encoded_vote.iter().copied())
+                .collect(),
+        );
+        let result = config
+            .database
+            .with_database(move |database| database.set_grandpa_voter_state(&state).map(|()| state))
+            .await;
+        match result {
+            Ok(state) => self.persisted_state = Some(state),
+            Err(err) => {
+                config.log_callback.log(
+                    LogLevel::Warn,
+                    format!("grandpa-voter-state-store-error; error={}", err),
+                );
+                // The state in the database is now unknown. Stop voting altogether rather than
+                // risk an equivocation.
+                self.can_vote = false;
+                round.local_authority = None;
+                return;
+            }
+        }
+
+        // Importing the vote checks its signature, which fails if the key isn't an Ed25519 key.
+        if let Err(err) = round.inner.import_vote(&vote) {
+            config.log_callback.log(
+                LogLevel::Warn,
+                format!("grandpa-vote-local-error; error={}", err),
+            );
+            round.local_authority = None;
+            return;
+        }
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "grandpa-vote; kind={}; set_id={}; round={}; target_hash={}; target_number={}",
+                kind,
+                round_id.0,
+                round_id.1,
+                HashDisplay(&target.0),
+                target.1
+            ),
+        );
+
+        config
+            .network_service
+            .0
+            .broadcast_grandpa_vote(config.network_service.1, scale_encoded_vote)
+            .await;
+    }
+
+    /// Called when a peer has sent a neighbor packet. Sends a catch up request to this peer if it
+    /// is more than one round ahead of the local node.
+    async fn on_neighbor_packet(
+        &mut self,
+        config: &Services,
+        peer_id: PeerId,
+        round_number: u64,
+        set_id: u64,
+    ) {
+        let Some(round) = &self.round else {
+            return;
+        };
+
+        if set_id != round.inner.set_id()
+            || round_number <= round.inner.round_number().saturating_add(1)
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        if self
+            .pending_catch_up
+            .as_ref()
+            .is_some_and(|(_, timeout)| *timeout > now)
+        {
+            return;
+        }
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "grandpa-catch-up-request-send; peer_id={}; set_id={}; round={}; peer_round={}",
+                peer_id,
+                set_id,
+                round.inner.round_number(),
+                round_number
+            ),
+        );
+
+        config
+            .network_service
+            .0
+            .send_grandpa_catch_up_request(
+                peer_id.clone(),
+                config.network_service.1,
+                round.inner.round_number(),
+                set_id,
+            )
+            .await;
+        self.pending_catch_up = Some((peer_id, now + CATCH_UP_REQUEST_TIMEOUT));
+    }
+
+    /// Called when a peer has sent a catch up request. Answers with the votes of the latest
+    /// completed round, provided that it is later than the round of the peer.
+    async fn on_catch_up_request(
+        &mut self,
+        config: &Services,
+        peer_id: PeerId,
+        round_number: u64,
+        set_id: u64,
+    ) {
+        let Some(completed) = &self.last_completed_round else {
+            return;
+        };
+        if completed.set_id != set_id || completed.round_number <= round_number {
+            return;
+        }
+
+        let prevotes = completed
+            .inner
+            .votes(voter::VoteKind::Prevote)
+            .map(|(authority_public_key, vote)| codec::PrevoteRef {
+                target_hash: &vote.target_hash,
+                target_number: vote.target_number,
+                signature: &vote.signature,
+                authority_public_key,
+            })
+            .collect::<Vec<_>>();
+        let precommits = completed
+            .inner
+            .votes(voter::VoteKind::Precommit)
+            .map(|(authority_public_key, vote)| PrecommitRef {
+                target_hash: &vote.target_hash,
+                target_number: vote.target_number,
+                signature: &vote.signature,
+                authority_public_key,
+            })
+            .collect::<Vec<_>>();
+        let base = completed.inner.base();
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "grandpa-catch-up-send; peer_id={}; set_id={}; round={}; num_prevotes={}; num_precommits={}",
+                peer_id,
+                set_id,
+                completed.round_number,
+                prevotes.len(),
+                precommits.len()
+            ),
+        );
+
+        let scale_encoded_catch_up = codec::catch_up_scale_encoding_vec(
+            &codec::CatchUpRef {
+                set_id,
+                round_number: completed.round_number,
+                prevotes,
+                precommits,
+                base_hash: &base.0,
+                base_number: base.1,
+            },
+            config.block_number_bytes,
+        );
+        config
+            .network_service
+            .0
+            .send_grandpa_catch_up(peer_id, config.network_service.1, scale_encoded_catch_up)
+            .await;
+    }
+
+    /// Called when a peer has sent a catch up message. If it answers the catch up request that
+    /// has been sent and contains enough valid votes to complete a round not earlier than the
+    /// current one, the current round is replaced with the caught up round, which then gets
+    /// completed like any other round.
+    fn on_catch_up(&mut self, config: &Services, peer_id: &PeerId, message: EncodedGrandpaCatchUp) {
+        if self
+            .pending_catch_up
+            .as_ref()
+            .map_or(true, |(requested, _)| requested != peer_id)
+        {
+            return;
+        }
+        self.pending_catch_up = None;
+
+        let Some(round) = &self.round else {
+            return;
+        };
+
+        let catch_up = message.decode();
+        if catch_up.set_id != round.inner.set_id()
+            || catch_up.round_number < round.inner.round_number()
+        {
+            return;
+        }
+
+        // The base of the caught up round must be a known block that isn't below the finalized
+        // block.
+        let base = (*catch_up.base_hash, catch_up.base_number);
+        if !self.is_descendant_or_equal(base, self.finalized) {
+            return;
+        }
+
+        let mut inner = voter::Round::new(voter::Config {
+            round_number: catch_up.round_number,
+            set_id: catch_up.set_id,
+            authorities: round.authorities.iter().cloned(),
+            base,
+            block_number_bytes: config.block_number_bytes,
+        });
+
+        let votes = catch_up
+            .prevotes
+            .iter()
+            .map(|prevote| {
+                (
+                    codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+                        target_hash: prevote.target_hash,
+                        target_number: prevote.target_number,
+                    }),
+                    prevote.signature,
+                    prevote.authority_public_key,
+                )
+            })
+            .chain(catch_up.precommits.iter().map(|precommit| {
+                (
+                    codec::MessageRef::Precommit(codec::UnsignedPrecommitRef {
+                        target_hash: precommit.target_hash,
+                        target_number: precommit.target_number,
+                    }),
+                    precommit.signature,
+                    precommit.authority_public_key,
+                )
+            }));
+        for (message, signature, authority_public_key) in votes {
+            let result = inner.import_vote(&codec::VoteMessageRef {
+                round_number: catch_up.round_number,
+                set_id: catch_up.set_id,
+                message,
+                signature,
+                authority_public_key,
+            });
+            if let Err(err) = result {
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-catch-up-bad-vote; peer_id={}; error={}",
+                        peer_id, err
+                    ),
+                );
+                return;
+            }
+        }
+
+        if !inner.is_completable(|hash| self.parent_of(hash)) {
+            config.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "grandpa-catch-up-not-completable; peer_id={}; round={}",
+                    peer_id, catch_up.round_number
+                ),
+            );
+            return;
+        }
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "grandpa-catch-up; peer_id={}; from={}; to={}",
+                peer_id,
+                round.inner.round_number(),
+                catch_up.round_number
+            ),
+        );
+
+        // The local node doesn't vote in the caught up round. The round is completed and the
+        // next round started during the next call to `progress`.
+        let round = self.round.take().unwrap();
+        self.round = Some(Round {
+            inner,
+            authorities: round.authorities,
+            local_authority: None,
+            start: Instant::now(),
+            previous_estimate: self.finalized,
+            previous_prevote_ghost: self.finalized,
+            scheduled_change_number: round.scheduled_change_number,
+            proposed: true,
+            prevoted: true,
+            precommitted: true,
+        });
+        self.pending_jump = None;
+    }
+
+    fn on_vote_message(
+        &mut self,
+        config: &Services,
+        peer_id: &PeerId,
+        message: EncodedGrandpaVoteMessage,
+    ) {
+        let Some(round) = &mut self.round else {
+            return;
+        };
+
+        let decoded = message.decode();
+        if decoded.set_id != round.inner.set_id() {
+            return;
+        }
+
+        if decoded.round_number > round.inner.round_number() {
+            if round.inner.is_authority(decoded.authority_public_key)
+                && self.future_votes.len() < MAX_FUTURE_VOTES
+            {
+                self.future_votes.push(message.clone());
+            }
+
+            // If authorities weighing more than a third of the set are voting in a later round,
+            // the local node is lagging behind. Jump to this round.
+            // Note that the signatures of the votes haven't been verified at this point. They
+            // are verified when importing the votes into the new round.
+            let mut voters = self
+                .future_votes
+                .iter()
+                .map(|vote| vote.decode())
+                .filter(|vote| vote.round_number == decoded.round_number)
+                .map(|vote| *vote.authority_public_key)
+                .collect::<Vec<_>>();
+            voters.sort_unstable();
+            voters.dedup();
+            if round.inner.is_more_than_faulty(&voters) {
+                let round_number = decoded.round_number;
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-round-jump; from={}; to={}",
+                        round.inner.round_number(),
+                        round_number
+                    ),
+                );
+                self.round = None;
+                self.last_completed_round = None;
+                self.next_round_start = Instant::now();
+                self.pending_jump = Some(round_number);
+            }
+            return;
+        }
+
+        if decoded.round_number < round.inner.round_number() {
+            return;
+        }
+
+        match round.inner.import_vote(&decoded) {
+            Ok(voter::VoteImportOutcome::Equivocation {
+                kind,
+                authority_public_key,
+                first,
+                second,
+            }) => {
+                config.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "grandpa-equivocation; kind={:?}; set_id={}; round={}; authority={}",
+                        kind,
+                        decoded.set_id,
+                        decoded.round_number,
+                        HashDisplay(&authority_public_key)
+                    ),
+                );
+
+                if let Some(equivocation_reports) = &config.equivocation_reports {
+                    let _ = equivocation_reports.try_send(
+                        equivocation_reporter::Equivocation::Grandpa {
+                            set_id: decoded.set_id,
+                            round_number: decoded.round_number,
+                            offender: authority_public_key,
+                            kind,
+                            first: *first,
+                            second: *second,
+                        },
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-vote-import-error; peer_id={}; error={}",
+                        peer_id, err
+                    ),
+                );
+            }
+        }
+    }
+}
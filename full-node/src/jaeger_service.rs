@@ -31,14 +31,24 @@
 //! docker run -d --name jaeger -e COLLECTOR_ZIPKIN_HTTP_PORT=9411 -p 5775:5775/udp -p 6831:6831/udp -p 6832:6832/udp -p 5778:5778 -p 16686:16686 -p 14268:14268 -p 14250:14250 -p 9411:9411 jaegertracing/all-in-one:1
 //! ```
 //!
+//! On nodes with a lot of networking traffic, reporting a span for every single networking event
+//! can flood the Jaeger agent. [`Config::network_sampling`] makes it possible to only report a
+//! fraction of the spans of each networking protocol, or none at all.
+//!
 
 // TODO: more documentation
 
+use rand::{Rng as _, SeedableRng as _};
 use smol::{future, net::UdpSocket};
 use smoldot::libp2p::PeerId;
 use std::{
-    convert::TryFrom as _, future::Future, io, net::SocketAddr, num::NonZeroU128, pin::Pin,
-    sync::Arc,
+    convert::TryFrom as _,
+    future::Future,
+    io,
+    net::SocketAddr,
+    num::NonZeroU128,
+    pin::Pin,
+    sync::{Arc, Mutex},
 };
 
 /// Configuration for a [`JaegerService`].
@@ -53,11 +63,45 @@ pub struct Config<'a> {
     ///
     /// If this is `None`, the service will still be created but do nothing.
     pub jaeger_agent: Option<SocketAddr>,
+
+    /// Fraction of the networking events, per protocol, for which a span is reported.
+    pub network_sampling: JaegerSampling,
+
+    /// Seed used to initialize the randomness of the service, which decides which networking
+    /// events are sampled.
+    pub randomness_seed: [u8; 32],
+}
+
+/// Fraction of the networking events, per protocol, for which a span is reported.
+///
+/// Each value is a probability between `0.0` (no span is ever reported) and `1.0` (a span is
+/// reported for every event).
+#[derive(Debug, Clone, PartialEq)]
+pub struct JaegerSampling {
+    /// Block announces received on the block announces gossip protocol.
+    pub block_announces: f64,
+    /// Block requests received on the sync request-response protocol.
+    pub block_requests: f64,
+}
+
+impl Default for JaegerSampling {
+    fn default() -> Self {
+        JaegerSampling {
+            block_announces: 1.0,
+            block_requests: 1.0,
+        }
+    }
 }
 
 pub struct JaegerService {
     traces_in: Arc<mick_jaeger::TracesIn>,
 
+    /// See [`Config::network_sampling`].
+    network_sampling: JaegerSampling,
+
+    /// Source of randomness of the service. Derived from [`Config::randomness_seed`].
+    randomness: Mutex<rand::rngs::StdRng>,
+
     /// Notified when the service is destroyed.
     shutdown_notify: event_listener::Event,
 }
@@ -99,6 +143,8 @@ impl JaegerService {
 
         Ok(Arc::new(JaegerService {
             traces_in,
+            network_sampling: config.network_sampling,
+            randomness: Mutex::new(rand::rngs::StdRng::from_seed(config.randomness_seed)),
             shutdown_notify,
         }))
     }
@@ -109,14 +155,18 @@ impl JaegerService {
         remote_peer_id: &PeerId,
         block_number: u64,
         block_hash: &[u8; 32],
-    ) -> mick_jaeger::Span {
+    ) -> Option<mick_jaeger::Span> {
+        if !self.sample(self.network_sampling.block_announces) {
+            return None;
+        }
+
         let mut span =
             self.net_connection_span(local_peer_id, remote_peer_id, "block-announce-received");
         if let Ok(block_number) = i64::try_from(block_number) {
             span.add_int_tag("number", block_number);
         }
         span.add_string_tag("hash", &hex::encode(block_hash));
-        span
+        Some(span)
     }
 
     pub fn block_announce_process_span(&self, block_hash: &[u8; 32]) -> mick_jaeger::Span {
@@ -148,6 +198,10 @@ impl JaegerService {
         num_requested_blocks: u32,
         block_hash: Option<&[u8; 32]>,
     ) -> [Option<mick_jaeger::Span>; 2] {
+        if !self.sample(self.network_sampling.block_requests) {
+            return [None, None];
+        }
+
         let mut span1 =
             self.net_connection_span(local_peer_id, remote_peer_id, "incoming-blocks-request");
         span1.add_int_tag("num-blocks", num_requested_blocks.into());
@@ -233,6 +287,11 @@ impl JaegerService {
         let trace_id = NonZeroU128::new(u128::from_be_bytes(buf)).unwrap();
         self.traces_in.span(trace_id, operation_name)
     }
+
+    /// Returns `true` with a probability equal to `rate`.
+    fn sample(&self, rate: f64) -> bool {
+        rate >= 1.0 || (rate > 0.0 && self.randomness.lock().unwrap().gen::<f64>() < rate)
+    }
 }
 
 impl Drop for JaegerService {
@@ -15,31 +15,60 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{consensus_service, database_thread, network_service, LogCallback, LogLevel};
+use crate::{
+    consensus_service, database_thread, network_service, runtime_caches_service,
+    runtime_calls_limiter, transactions_pool, JsonRpcTlsConfig, LogCallback, LogLevel,
+    SlowSubscriberPolicy,
+};
 use futures_channel::oneshot;
-use futures_util::FutureExt;
+use futures_rustls::rustls;
 use smol::{
     future,
+    io::{AsyncRead, AsyncWrite},
     net::{TcpListener, TcpStream},
 };
-use smoldot::json_rpc::{methods, service};
+use smoldot::{
+    chain::chain_information,
+    identity::ss58,
+    json_rpc::{methods, parse, service},
+    metadata,
+};
 use std::{
+    cmp,
+    collections::VecDeque,
+    fs,
     future::Future,
     io, mem,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
     pin::Pin,
     sync::{
         atomic::{AtomicU32, Ordering},
-        Arc,
+        Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+mod account_nonce;
+mod block_authorities;
+mod block_tracing;
 mod chain_head_subscriptions;
+mod fee_constants;
+mod health;
 mod legacy_api_subscriptions;
+mod metrics;
 mod requests_handler;
-mod runtime_caches_service;
+mod subscriptions_multiplexer;
+mod transactions;
+
+pub use account_nonce::AccountNextIndexError;
+pub use block_authorities::{BlockAuthorities, BlockAuthoritiesError};
+pub use block_tracing::{BlockTrace, BlockTraceEvent, TraceBlockError};
+pub use fee_constants::FeeConstantsError;
+pub use legacy_api_subscriptions::SubscribeRuntimeVersion;
+pub use metrics::{JsonRpcMethodMetrics, LATENCY_BUCKETS as JSON_RPC_LATENCY_BUCKETS};
+pub use transactions::BannedTransactions;
 
 /// Configuration for a [`JsonRpcService`].
 pub struct Config {
@@ -62,14 +91,70 @@ pub struct Config {
     ),
 
     /// Where to bind the WebSocket server. If `None`, no TCP server is started.
+    ///
+    /// If [`Config::admin_bind_address`] is `Some`, this server only exposes the JSON-RPC
+    /// methods that are considered safe. Otherwise, it exposes all of them.
     pub bind_address: Option<SocketAddr>,
 
+    /// Where to bind the WebSocket server exposing all the JSON-RPC methods, including the ones
+    /// that are considered unsafe, such as key management or node administration. If `None`,
+    /// no such server is started.
+    ///
+    /// All the other options, such as the limits or the TLS configuration, apply to both servers.
+    pub admin_bind_address: Option<SocketAddr>,
+
+    /// Where to bind a plain HTTP server serving only the `GET /health` and `GET /ready`
+    /// endpoints. If `None`, no such server is started.
+    ///
+    /// Note that these endpoints are also served by the WebSocket servers.
+    pub health_bind_address: Option<SocketAddr>,
+
     /// Maximum number of requests to process in parallel.
     pub max_parallel_requests: u32,
 
     /// Maximum number of JSON-RPC clients until new ones are rejected.
     pub max_json_rpc_clients: u32,
 
+    /// Maximum number of JSON-RPC clients with the same IP address until new ones from this
+    /// IP address are rejected.
+    pub max_json_rpc_clients_per_ip: u32,
+
+    /// Maximum number of requests per second that each JSON-RPC client can send. Requests
+    /// beyond this limit are answered with an error indicating that the server is busy.
+    pub max_requests_per_second_per_client: u32,
+
+    /// Maximum number of subscriptions that each JSON-RPC client can have active at the same
+    /// time. Subscription requests beyond this limit are answered with an error.
+    pub max_subscriptions_per_client: u32,
+
+    /// Maximum number of responses and notifications waiting to be sent to a JSON-RPC client.
+    /// When this limit is reached, [`Config::slow_subscriber_policy`] applies.
+    pub max_pending_notifications_per_client: NonZeroUsize,
+
+    /// What to do when a JSON-RPC client doesn't read its responses and notifications fast
+    /// enough.
+    pub slow_subscriber_policy: SlowSubscriberPolicy,
+
+    /// If `Some`, the `chain_subscribeAllHeads`, `chain_subscribeNewHeads`, and
+    /// `chain_subscribeFinalizedHeads` subscriptions of all the JSON-RPC clients share a single
+    /// subscription to the consensus service per kind of subscription, and each JSON-RPC
+    /// subscription buffers up to the given number of headers. Subscriptions that lag behind
+    /// lose their oldest headers.
+    ///
+    /// If `None`, each JSON-RPC subscription has its own subscription to the consensus service.
+    pub multiplexed_subscriptions_buffer: Option<NonZeroUsize>,
+
+    /// If `Some`, incoming connections must perform a TLS handshake using the given certificate
+    /// before the WebSocket handshake. Ignored if [`Config::bind_address`] is `None`.
+    pub tls: Option<JsonRpcTlsConfig>,
+
+    /// List of values of the `Origin` HTTP header that are accepted during the WebSocket
+    /// handshake. If `None`, all origins are accepted.
+    ///
+    /// Clients that don't provide any `Origin` header, which is typically the case for
+    /// everything that isn't a browser, are always accepted.
+    pub allowed_origins: Option<Vec<String>>,
+
     /// Name of the chain, as found in the chain specification.
     pub chain_name: String,
 
@@ -79,6 +164,9 @@ pub struct Config {
     /// JSON-encoded properties of the chain, as found in the chain specification.
     pub chain_properties_json: String,
 
+    /// Prefix of the SS58 addresses of the chain.
+    pub chain_ss58_prefix: ss58::ChainPrefix,
+
     /// Whether the chain is a live network. Found in the chain specification.
     pub chain_is_live: bool,
 
@@ -86,8 +174,25 @@ pub struct Config {
     // TODO: load from database maybe?
     pub genesis_block_hash: [u8; 32],
 
+    /// Information about the genesis block. Used as a starting point in order to determine the
+    /// authorities of past blocks.
+    pub genesis_chain_information: Arc<chain_information::ValidChainInformation>,
+
     /// Consensus service of the chain.
     pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Limiter that the runtime executions of JSON-RPC requests must go through.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// Cache of the runtimes of the blocks of [`Config::database`].
+    pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
+    /// Pool where the transactions submitted by the JSON-RPC clients are inserted.
+    pub transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// Transactions that have been found invalid, and that are refused without being validated
+    /// again when they are submitted.
+    pub banned_transactions: Arc<BannedTransactions>,
 }
 
 /// Running JSON-RPC service.
@@ -103,8 +208,43 @@ pub struct JsonRpcService {
     /// Address the server is listening on. Not necessarily equal to [`Config::bind_address`].
     listen_addr: Option<SocketAddr>,
 
+    /// Address the admin server is listening on. Not necessarily equal to
+    /// [`Config::admin_bind_address`].
+    admin_listen_addr: Option<SocketAddr>,
+
+    /// Address the health server is listening on. Not necessarily equal to
+    /// [`Config::health_bind_address`].
+    health_listen_addr: Option<SocketAddr>,
+
     /// I/O for the virtual endpoint.
     virtual_client_io: service::SerializedRequestsIo,
+
+    /// Requests sent to the virtual endpoint whose response hasn't been pulled yet.
+    virtual_client_in_flight: Mutex<metrics::InFlightRequests>,
+
+    /// Statistics about the requests of all the clients, including the virtual endpoint.
+    metrics: Arc<metrics::Metrics>,
+
+    /// See [`Config::log_callback`].
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::runtime_caches_service`].
+    runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
+    /// See [`Config::runtime_calls_limiter`].
+    runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// See [`Config::transactions_pool`].
+    transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// Number of bytes of the block number in the headers of the chain.
+    block_number_bytes: usize,
+
+    /// See [`Config::genesis_chain_information`].
+    genesis_chain_information: Arc<chain_information::ValidChainInformation>,
 }
 
 impl Drop for JsonRpcService {
@@ -116,33 +256,41 @@ impl Drop for JsonRpcService {
 impl JsonRpcService {
     /// Initializes a new [`JsonRpcService`].
     pub async fn new(config: Config) -> Result<Self, InitError> {
-        let (tcp_listener, listen_addr) = match &config.bind_address {
-            Some(addr) => match TcpListener::bind(addr).await {
-                Ok(listener) => {
-                    let listen_addr = match listener.local_addr() {
-                        Ok(addr) => addr,
-                        Err(error) => {
-                            return Err(InitError::ListenError {
-                                bind_address: *addr,
-                                error,
-                            })
-                        }
-                    };
+        let (tcp_listener, listen_addr) = match config.bind_address {
+            Some(addr) => {
+                let (listener, listen_addr) = bind(addr).await?;
+                (Some(listener), Some(listen_addr))
+            }
+            None => (None, None),
+        };
 
-                    (Some(listener), Some(listen_addr))
-                }
-                Err(error) => {
-                    return Err(InitError::ListenError {
-                        bind_address: *addr,
-                        error,
-                    })
-                }
-            },
+        let (admin_tcp_listener, admin_listen_addr) = match config.admin_bind_address {
+            Some(addr) => {
+                let (listener, listen_addr) = bind(addr).await?;
+                (Some(listener), Some(listen_addr))
+            }
             None => (None, None),
         };
 
+        let (health_tcp_listener, health_listen_addr) = match config.health_bind_address {
+            Some(addr) => {
+                let (listener, listen_addr) = bind(addr).await?;
+                (Some(listener), Some(listen_addr))
+            }
+            None => (None, None),
+        };
+
+        let tls_acceptor = match (
+            tcp_listener.is_some() || admin_tcp_listener.is_some(),
+            &config.tls,
+        ) {
+            (true, Some(tls_config)) => Some(build_tls_acceptor(tls_config)?),
+            _ => None,
+        };
+
         let service_dropped = event_listener::Event::new();
-        let on_service_dropped = service_dropped.listen();
+
+        let metrics = Arc::new(metrics::Metrics::new());
 
         let (to_requests_handlers, from_background) = async_channel::bounded(8);
 
@@ -157,16 +305,22 @@ impl JsonRpcService {
             config.consensus_service.clone(),
             config.database.clone(),
             to_requests_handlers.clone(),
+            true,
             virtual_client_main_task,
         );
 
-        let runtime_caches_service = Arc::new(runtime_caches_service::RuntimeCachesService::new(
-            runtime_caches_service::Config {
-                tasks_executor: config.tasks_executor.clone(),
-                database: config.database.clone(),
-                num_cache_entries: NonZeroUsize::new(16).unwrap(), // TODO: configurable?
-            },
-        ));
+        let runtime_caches_service = config.runtime_caches_service;
+
+        let subscriptions_multiplexer =
+            config.multiplexed_subscriptions_buffer.map(|buffer_size| {
+                Arc::new(subscriptions_multiplexer::SubscriptionsMultiplexer::new(
+                    subscriptions_multiplexer::Config {
+                        tasks_executor: config.tasks_executor.clone(),
+                        consensus_service: config.consensus_service.clone(),
+                        buffer_size,
+                    },
+                ))
+            });
 
         for _ in 0..config.max_parallel_requests {
             requests_handler::spawn_requests_handler(requests_handler::Config {
@@ -178,24 +332,72 @@ impl JsonRpcService {
                 chain_name: config.chain_name.clone(),
                 chain_type: config.chain_type.clone(),
                 chain_properties_json: config.chain_properties_json.clone(),
+                chain_ss58_prefix: config.chain_ss58_prefix,
                 chain_is_live: config.chain_is_live,
                 genesis_block_hash: config.genesis_block_hash,
+                genesis_chain_information: config.genesis_chain_information.clone(),
                 consensus_service: config.consensus_service.clone(),
                 runtime_caches_service: runtime_caches_service.clone(),
+                runtime_calls_limiter: config.runtime_calls_limiter.clone(),
+                subscriptions_multiplexer: subscriptions_multiplexer.clone(),
+                banned_transactions: config.banned_transactions.clone(),
+                transactions_pool: config.transactions_pool.clone(),
             });
         }
 
-        if let Some(tcp_listener) = tcp_listener {
+        let health_checker = Arc::new(health::HealthChecker {
+            consensus_service: config.consensus_service.clone(),
+            network_service: config.network_service.clone(),
+            chain_is_live: config.chain_is_live,
+        });
+
+        if let Some(health_tcp_listener) = health_tcp_listener {
+            (config.tasks_executor)(Box::pin(health::run_server(
+                health_tcp_listener,
+                health_checker.clone(),
+                config.tasks_executor.clone(),
+                config.log_callback.clone(),
+                service_dropped.listen(),
+            )));
+        }
+
+        // Unsafe methods are only exposed on the public server if there is no admin server.
+        let listeners = tcp_listener
+            .map(|listener| (listener, admin_tcp_listener.is_none()))
+            .into_iter()
+            .chain(admin_tcp_listener.map(|listener| (listener, true)));
+
+        // The limits are shared between the two servers.
+        let num_json_rpc_clients = Arc::new(AtomicU32::new(0));
+        let num_json_rpc_clients_per_ip =
+            Arc::new(Mutex::new(hashbrown::HashMap::with_capacity_and_hasher(
+                usize::try_from(config.max_json_rpc_clients).unwrap_or(usize::MAX),
+                Default::default(),
+            )));
+        let allowed_origins = config.allowed_origins.map(Arc::<[String]>::from);
+
+        for (tcp_listener, allow_unsafe_methods) in listeners {
             let background = JsonRpcBackground {
                 tcp_listener,
-                on_service_dropped,
+                allow_unsafe_methods,
+                on_service_dropped: service_dropped.listen(),
                 tasks_executor: config.tasks_executor.clone(),
-                log_callback: config.log_callback,
+                log_callback: config.log_callback.clone(),
                 consensus_service: config.consensus_service.clone(),
                 database: config.database.clone(),
-                to_requests_handlers,
-                num_json_rpc_clients: Arc::new(AtomicU32::new(0)),
+                to_requests_handlers: to_requests_handlers.clone(),
+                num_json_rpc_clients: num_json_rpc_clients.clone(),
                 max_json_rpc_clients: config.max_json_rpc_clients,
+                num_json_rpc_clients_per_ip: num_json_rpc_clients_per_ip.clone(),
+                max_json_rpc_clients_per_ip: config.max_json_rpc_clients_per_ip,
+                max_requests_per_second_per_client: config.max_requests_per_second_per_client,
+                max_subscriptions_per_client: config.max_subscriptions_per_client,
+                max_pending_notifications_per_client: config.max_pending_notifications_per_client,
+                slow_subscriber_policy: config.slow_subscriber_policy,
+                tls_acceptor: tls_acceptor.clone(),
+                allowed_origins: allowed_origins.clone(),
+                health_checker: health_checker.clone(),
+                metrics: metrics.clone(),
             };
 
             (config.tasks_executor)(Box::pin(async move { background.run().await }));
@@ -204,7 +406,18 @@ impl JsonRpcService {
         Ok(JsonRpcService {
             service_dropped,
             listen_addr,
+            admin_listen_addr,
+            health_listen_addr,
             virtual_client_io,
+            virtual_client_in_flight: Mutex::new(metrics::InFlightRequests::new()),
+            metrics,
+            log_callback: config.log_callback,
+            block_number_bytes: config.consensus_service.block_number_bytes(),
+            database: config.database,
+            runtime_caches_service,
+            runtime_calls_limiter: config.runtime_calls_limiter,
+            transactions_pool: config.transactions_pool,
+            genesis_chain_information: config.genesis_chain_information,
         })
     }
 
@@ -216,10 +429,101 @@ impl JsonRpcService {
         self.listen_addr
     }
 
+    /// Returns the address the admin server is listening on.
+    ///
+    /// Returns `None` if and only if [`Config::admin_bind_address`] was `None`. However, if
+    /// `Some`, the address is not necessarily equal to the one in [`Config::admin_bind_address`].
+    pub fn admin_listen_addr(&self) -> Option<SocketAddr> {
+        self.admin_listen_addr
+    }
+
+    /// Returns the address the health server is listening on.
+    ///
+    /// Returns `None` if and only if [`Config::health_bind_address`] was `None`. However, if
+    /// `Some`, the address is not necessarily equal to the one in [`Config::health_bind_address`].
+    pub fn health_listen_addr(&self) -> Option<SocketAddr> {
+        self.health_listen_addr
+    }
+
+    /// Returns statistics about the JSON-RPC requests that have been answered so far, grouped
+    /// by method.
+    pub fn metrics(&self) -> Vec<JsonRpcMethodMetrics> {
+        self.metrics.snapshot()
+    }
+
+    /// Re-executes the given block, and returns the storage accesses and host function calls
+    /// that the runtime has performed.
+    ///
+    /// This is the function used to answer `state_traceBlock` JSON-RPC requests.
+    pub async fn trace_block(&self, block_hash: [u8; 32]) -> Result<BlockTrace, TraceBlockError> {
+        block_tracing::trace_block(
+            &self.database,
+            &self.runtime_caches_service,
+            &self.runtime_calls_limiter,
+            self.block_number_bytes,
+            block_hash,
+        )
+        .await
+    }
+
+    /// Returns the GrandPa authorities and Babe epoch of the given block.
+    ///
+    /// This is the function used to answer `sudo_unstable_blockAuthorities` JSON-RPC requests.
+    pub async fn block_authorities(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Result<BlockAuthorities, BlockAuthoritiesError> {
+        block_authorities::block_authorities(
+            &self.database,
+            &self.genesis_chain_information,
+            self.block_number_bytes,
+            block_hash,
+        )
+        .await
+    }
+
+    /// Returns the fee-related constants found in the metadata of the runtime of the given
+    /// block.
+    pub async fn fee_constants(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Result<metadata::FeeConstants, FeeConstantsError> {
+        fee_constants::fee_constants(
+            &self.database,
+            &self.runtime_caches_service,
+            &self.runtime_calls_limiter,
+            block_hash,
+        )
+        .await
+    }
+
+    /// Returns the nonce that the next transaction of the given SCALE-encoded account id must
+    /// use, taking into account the transactions of the transactions pool.
+    ///
+    /// This is the function used to answer `system_accountNextIndex` JSON-RPC requests.
+    pub async fn account_next_index(
+        &self,
+        account_id: &[u8],
+    ) -> Result<u64, AccountNextIndexError> {
+        account_nonce::account_next_index(
+            &self.database,
+            &self.runtime_caches_service,
+            &self.runtime_calls_limiter,
+            &self.transactions_pool,
+            account_id,
+        )
+        .await
+    }
+
     /// Adds a JSON-RPC request to the queue of requests of the virtual endpoint.
     ///
     /// The virtual endpoint doesn't have any limit.
     pub fn send_request(&self, request: String) {
+        self.virtual_client_in_flight
+            .lock()
+            .unwrap()
+            .on_request(&request);
+
         match self.virtual_client_io.try_send_request(request) {
             Ok(()) => (),
             Err(err) => match err.cause {
@@ -235,10 +539,18 @@ impl JsonRpcService {
     /// If this function is called multiple times simultaneously, only one invocation will receive
     /// each response. Which one is unspecified.
     pub async fn next_response(&self) -> String {
-        match self.virtual_client_io.wait_next_response().await {
+        let response = match self.virtual_client_io.wait_next_response().await {
             Ok(r) => r,
             Err(service::WaitNextResponseError::ClientMainTaskDestroyed) => unreachable!(),
-        }
+        };
+
+        self.virtual_client_in_flight.lock().unwrap().on_response(
+            &response,
+            &self.metrics,
+            &*self.log_callback,
+        );
+
+        response
     }
 }
 
@@ -253,12 +565,99 @@ pub enum InitError {
         /// Error returned by the operating system.
         error: io::Error,
     },
+    /// Failed to read the file containing the TLS certificate chain.
+    #[display(
+        fmt = "Failed to read TLS certificate chain from {}: {error}",
+        "path.display()"
+    )]
+    TlsCertificateRead {
+        /// Path that was attempted.
+        path: PathBuf,
+        /// Error that happened.
+        error: io::Error,
+    },
+    /// Failed to read the file containing the TLS private key.
+    #[display(
+        fmt = "Failed to read TLS private key from {}: {error}",
+        "path.display()"
+    )]
+    TlsPrivateKeyRead {
+        /// Path that was attempted.
+        path: PathBuf,
+        /// Error that happened.
+        error: io::Error,
+    },
+    /// The file supposed to contain the TLS private key doesn't contain any.
+    #[display(fmt = "No TLS private key found in {}", "path.display()")]
+    TlsNoPrivateKey {
+        /// Path that was attempted.
+        path: PathBuf,
+    },
+    /// The TLS certificate chain or private key is invalid.
+    #[display(fmt = "Invalid TLS certificate or private key: {_0}")]
+    TlsInvalidCertificate(rustls::Error),
+}
+
+/// Binds a TCP listener to the given address.
+async fn bind(bind_address: SocketAddr) -> Result<(TcpListener, SocketAddr), InitError> {
+    let listener =
+        TcpListener::bind(bind_address)
+            .await
+            .map_err(|error| InitError::ListenError {
+                bind_address,
+                error,
+            })?;
+    let listen_addr = listener
+        .local_addr()
+        .map_err(|error| InitError::ListenError {
+            bind_address,
+            error,
+        })?;
+    Ok((listener, listen_addr))
+}
+
+/// Builds the TLS acceptor used for all the incoming connections.
+fn build_tls_acceptor(config: &JsonRpcTlsConfig) -> Result<futures_rustls::TlsAcceptor, InitError> {
+    let certificate_chain = fs::read(&config.certificate_chain_path)
+        .and_then(|file_content| {
+            rustls_pemfile::certs(&mut &file_content[..]).collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|error| InitError::TlsCertificateRead {
+            path: config.certificate_chain_path.clone(),
+            error,
+        })?;
+
+    let private_key = fs::read(&config.private_key_path)
+        .and_then(|file_content| rustls_pemfile::private_key(&mut &file_content[..]))
+        .map_err(|error| InitError::TlsPrivateKeyRead {
+            path: config.private_key_path.clone(),
+            error,
+        })?
+        .ok_or_else(|| InitError::TlsNoPrivateKey {
+            path: config.private_key_path.clone(),
+        })?;
+
+    // The crypto provider is passed explicitly rather than relying on a process-wide default.
+    let server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(InitError::TlsInvalidCertificate)?
+    .with_no_client_auth()
+    .with_single_cert(certificate_chain, private_key)
+    .map_err(InitError::TlsInvalidCertificate)?;
+
+    Ok(futures_rustls::TlsAcceptor::from(Arc::new(server_config)))
 }
 
 struct JsonRpcBackground {
     /// TCP listener for new incoming connections.
     tcp_listener: TcpListener,
 
+    /// If `false`, the JSON-RPC methods considered unsafe are rejected. See
+    /// [`is_unsafe_method`].
+    allow_unsafe_methods: bool,
+
     /// Event notified when the frontend is dropped.
     on_service_dropped: event_listener::EventListener,
 
@@ -282,6 +681,37 @@ struct JsonRpcBackground {
 
     /// See [`Config::max_json_rpc_clients`].
     max_json_rpc_clients: u32,
+
+    /// Number of clients currently alive, grouped by IP address. IP addresses without any
+    /// client are absent from the map.
+    num_json_rpc_clients_per_ip: Arc<Mutex<hashbrown::HashMap<IpAddr, u32, fnv::FnvBuildHasher>>>,
+
+    /// See [`Config::max_json_rpc_clients_per_ip`].
+    max_json_rpc_clients_per_ip: u32,
+
+    /// See [`Config::max_requests_per_second_per_client`].
+    max_requests_per_second_per_client: u32,
+
+    /// See [`Config::max_subscriptions_per_client`].
+    max_subscriptions_per_client: u32,
+
+    /// See [`Config::max_pending_notifications_per_client`].
+    max_pending_notifications_per_client: NonZeroUsize,
+
+    /// See [`Config::slow_subscriber_policy`].
+    slow_subscriber_policy: SlowSubscriberPolicy,
+
+    /// Built from [`Config::tls`]. `None` if TLS is disabled.
+    tls_acceptor: Option<futures_rustls::TlsAcceptor>,
+
+    /// See [`Config::allowed_origins`].
+    allowed_origins: Option<Arc<[String]>>,
+
+    /// Used to answer requests to the health endpoints.
+    health_checker: Arc<health::HealthChecker>,
+
+    /// Statistics about the requests, shared between all the clients.
+    metrics: Arc<metrics::Metrics>,
 }
 
 impl JsonRpcBackground {
@@ -342,13 +772,42 @@ impl JsonRpcBackground {
                 continue;
             }
 
+            // Try to increase the number of clients of this IP address. Fails if the maximum is
+            // reached, in which case the socket is rejected the same way as above.
+            let ip_limit_reached = {
+                let mut num_json_rpc_clients_per_ip =
+                    self.num_json_rpc_clients_per_ip.lock().unwrap();
+                let num_clients = num_json_rpc_clients_per_ip.entry(address.ip()).or_insert(0);
+                if *num_clients >= self.max_json_rpc_clients_per_ip {
+                    if *num_clients == 0 {
+                        num_json_rpc_clients_per_ip.remove(&address.ip());
+                    }
+                    true
+                } else {
+                    *num_clients += 1;
+                    false
+                }
+            };
+            if ip_limit_reached {
+                self.num_json_rpc_clients.fetch_sub(1, Ordering::Release);
+                self.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "json-rpc-incoming-connection-rejected; address={}; reason=ip-limit",
+                        address
+                    ),
+                );
+                smol::Timer::after(Duration::from_millis(50)).await;
+                continue;
+            }
+
             // Spawn two tasks: one for the socket I/O, and one to process requests.
             self.log_callback.log(
                 LogLevel::Debug,
                 format!("json-rpc-incoming-connection; address={}", address),
             );
             let (client_main_task, io) = service::client_main_task(service::Config {
-                max_active_subscriptions: 128,
+                max_active_subscriptions: self.max_subscriptions_per_client,
                 max_pending_requests: NonZeroU32::new(64).unwrap(),
             });
             spawn_client_io_task(
@@ -356,14 +815,23 @@ impl JsonRpcBackground {
                 self.log_callback.clone(),
                 tcp_socket,
                 address,
+                self.tls_acceptor.clone(),
+                self.allowed_origins.clone(),
+                self.health_checker.clone(),
+                self.max_requests_per_second_per_client,
+                self.max_pending_notifications_per_client,
+                self.slow_subscriber_policy,
+                self.metrics.clone(),
                 io,
                 self.num_json_rpc_clients.clone(),
+                self.num_json_rpc_clients_per_ip.clone(),
             );
             spawn_client_main_task(
                 self.tasks_executor.clone(),
                 self.consensus_service.clone(),
                 self.database.clone(),
                 self.to_requests_handlers.clone(),
+                self.allow_unsafe_methods,
                 client_main_task,
             );
         }
@@ -375,171 +843,419 @@ fn spawn_client_io_task(
     log_callback: Arc<dyn LogCallback + Send + Sync>,
     tcp_socket: TcpStream,
     socket_address: SocketAddr,
+    tls_acceptor: Option<futures_rustls::TlsAcceptor>,
+    allowed_origins: Option<Arc<[String]>>,
+    health_checker: Arc<health::HealthChecker>,
+    max_requests_per_second: u32,
+    max_pending_responses: NonZeroUsize,
+    slow_subscriber_policy: SlowSubscriberPolicy,
+    metrics: Arc<metrics::Metrics>,
     io: service::SerializedRequestsIo,
     num_json_rpc_clients: Arc<AtomicU32>,
+    num_json_rpc_clients_per_ip: Arc<Mutex<hashbrown::HashMap<IpAddr, u32, fnv::FnvBuildHasher>>>,
 ) {
     let run_future = async move {
-        // Perform the WebSocket handshake.
-        let (mut ws_sender, mut ws_receiver) = {
-            let mut ws_server = soketto::handshake::Server::new(tcp_socket);
-
-            // TODO: enabling the `deflate` extension leads to "flate stream corrupted" errors
-            //let deflate = soketto::extension::deflate::Deflate::new(soketto::Mode::Server);
-            //ws_server.add_extension(Box::new(deflate));
-
-            let key = match ws_server.receive_request().await {
-                Ok(req) => req.key(),
+        // If TLS is enabled, perform the TLS handshake before the WebSocket handshake.
+        match tls_acceptor {
+            Some(tls_acceptor) => match tls_acceptor.accept(tcp_socket).await {
+                Ok(tls_socket) => {
+                    run_client_io(
+                        tls_socket,
+                        log_callback,
+                        socket_address,
+                        allowed_origins,
+                        health_checker,
+                        max_requests_per_second,
+                        max_pending_responses,
+                        slow_subscriber_policy,
+                        metrics,
+                        io,
+                    )
+                    .await
+                }
                 Err(error) => {
                     log_callback.log(
                         LogLevel::Debug,
                         format!(
-                            "json-rpc-connection-error; address={socket_address}, error={error}"
+                            "json-rpc-tls-handshake-error; address={socket_address}, error={error}"
                         ),
                     );
-                    return;
                 }
-            };
+            },
+            None => {
+                run_client_io(
+                    tcp_socket,
+                    log_callback,
+                    socket_address,
+                    allowed_origins,
+                    health_checker,
+                    max_requests_per_second,
+                    max_pending_responses,
+                    slow_subscriber_policy,
+                    metrics,
+                    io,
+                )
+                .await
+            }
+        }
+    };
 
-            let accept = soketto::handshake::server::Response::Accept {
-                key,
-                protocol: None,
-            };
+    tasks_executor(Box::pin(async move {
+        run_future.await;
+        num_json_rpc_clients.fetch_sub(1, Ordering::Release);
 
-            match ws_server.send_response(&accept).await {
-                Ok(()) => {}
-                Err(error) => {
-                    log_callback.log(
-                        LogLevel::Debug,
-                        format!(
-                            "json-rpc-connection-error; address={socket_address}, error={error}"
-                        ),
-                    );
-                    return;
-                }
+        let mut num_json_rpc_clients_per_ip = num_json_rpc_clients_per_ip.lock().unwrap();
+        let num_clients = num_json_rpc_clients_per_ip
+            .get_mut(&socket_address.ip())
+            .unwrap();
+        *num_clients -= 1;
+        if *num_clients == 0 {
+            num_json_rpc_clients_per_ip.remove(&socket_address.ip());
+        }
+    }))
+}
+
+async fn run_client_io(
+    socket: impl AsyncRead + AsyncWrite + Unpin,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+    socket_address: SocketAddr,
+    allowed_origins: Option<Arc<[String]>>,
+    health_checker: Arc<health::HealthChecker>,
+    max_requests_per_second: u32,
+    max_pending_responses: NonZeroUsize,
+    slow_subscriber_policy: SlowSubscriberPolicy,
+    metrics: Arc<metrics::Metrics>,
+    io: service::SerializedRequestsIo,
+) {
+    // Plain HTTP requests to the health endpoints are served on the same port as the WebSocket
+    // server. This requires looking at the beginning of the request before the WebSocket
+    // handshake. The bytes read are replayed to the WebSocket server afterwards.
+    let request_line = future::or(health::read_request_line(socket), async {
+        smol::Timer::after(health::REQUEST_LINE_TIMEOUT).await;
+        Err(io::Error::from(io::ErrorKind::TimedOut))
+    });
+    let mut socket = match request_line.await {
+        Ok(socket) => socket,
+        Err(error) => {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+            );
+            return;
+        }
+    };
+    if let Some(health_endpoint) = health::detect_endpoint(socket.peeked()) {
+        if let Err(error) = health_checker.respond(health_endpoint, &mut socket).await {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+            );
+        }
+        return;
+    }
+
+    // Perform the WebSocket handshake.
+    let (mut ws_sender, mut ws_receiver) = {
+        let mut ws_server = soketto::handshake::Server::new(socket);
+
+        // TODO: enabling the `deflate` extension leads to "flate stream corrupted" errors
+        //let deflate = soketto::extension::deflate::Deflate::new(soketto::Mode::Server);
+        //ws_server.add_extension(Box::new(deflate));
+
+        let (key, origin) = match ws_server.receive_request().await {
+            Ok(req) => (
+                req.key(),
+                req.headers()
+                    .origin
+                    .map(|origin| String::from_utf8_lossy(origin).into_owned()),
+            ),
+            Err(error) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+                );
+                return;
+            }
+        };
+
+        // Browsers always indicate the origin of the page that opens the connection. Reject
+        // the connection if this origin isn't allowed.
+        if let (Some(allowed_origins), Some(origin)) = (&allowed_origins, &origin) {
+            if !allowed_origins.iter().any(|allowed| allowed == origin) {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "json-rpc-connection-origin-rejected; address={socket_address}; origin={origin}"
+                    ),
+                );
+                let reject = soketto::handshake::server::Response::Reject { status_code: 403 };
+                let _ = ws_server.send_response(&reject).await;
+                return;
             }
+        }
 
-            ws_server.into_builder().finish()
+        let accept = soketto::handshake::server::Response::Accept {
+            key,
+            protocol: None,
         };
 
-        // Create a future responsible for pulling responses and sending them back.
-        let sending_future = async {
-            let mut must_flush_asap = false;
+        match ws_server.send_response(&accept).await {
+            Ok(()) => {}
+            Err(error) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+                );
+                return;
+            }
+        }
+
+        ws_server.into_builder().finish()
+    };
 
-            loop {
-                // If `must_flush_asap`, we simply peek for the next response but without awaiting.
-                // If `!must_flush_asap`, we wait for as long as necessary.
-                let maybe_response = if must_flush_asap {
-                    io.wait_next_response().now_or_never()
-                } else {
-                    Some(io.wait_next_response().await)
-                };
+    // Channel used by the receiving side to send back errors to requests that have been
+    // rejected because of the rate limiting.
+    let (busy_responses_tx, busy_responses_rx) = async_channel::bounded::<String>(16);
 
-                match maybe_response {
-                    None => {
-                        if let Err(err) = ws_sender.flush().await {
-                            break Err(err.to_string());
-                        }
-                        must_flush_asap = false;
+    // Responses and notifications pulled from the main task but not sent to the socket yet.
+    // The responses are pulled as soon as possible in order to be able to apply the
+    // `slow_subscriber_policy` when the client doesn't read them fast enough.
+    let pending_responses = Mutex::new(VecDeque::<String>::with_capacity(cmp::min(
+        max_pending_responses.get(),
+        64,
+    )));
+    let on_pending_response_pushed = event_listener::Event::new();
+
+    // Requests sent to the main task whose response hasn't been pulled yet.
+    let in_flight_requests = Mutex::new(metrics::InFlightRequests::new());
+
+    // Create a future responsible for pulling responses and queuing them.
+    let pulling_future = async {
+        loop {
+            let response = match future::or(
+                async {
+                    match busy_responses_rx.recv().await {
+                        Ok(response) => Ok(response),
+                        // The sender is alive for as long as the receiving side is running.
+                        Err(_) => future::pending().await,
                     }
-                    Some(Ok(response)) => {
-                        log_callback.log(
-                            LogLevel::Debug,
-                            format!(
-                                "json-rpc-response; address={}; response={}",
-                                socket_address,
-                                crate::util::truncated_str(
-                                    response.chars().filter(|c| !c.is_control()),
-                                    128
-                                )
-                            ),
+                },
+                async {
+                    let response = io.wait_next_response().await;
+                    if let Ok(response) = &response {
+                        in_flight_requests.lock().unwrap().on_response(
+                            response,
+                            &metrics,
+                            &*log_callback,
                         );
+                    }
+                    response
+                },
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(service::WaitNextResponseError::ClientMainTaskDestroyed) => {
+                    // The client main task never closes by itself but only as a consequence
+                    // to the I/O task closing.
+                    unreachable!()
+                }
+            };
 
-                        if let Err(err) = ws_sender.send_text_owned(response).await {
-                            break Err(err.to_string());
-                        }
-                        must_flush_asap = true;
+            let mut pending_responses = pending_responses.lock().unwrap();
+            if pending_responses.len() >= max_pending_responses.get() {
+                match slow_subscriber_policy {
+                    SlowSubscriberPolicy::Disconnect => {
+                        break Err::<(), _>("Client too slow to read responses".to_string());
                     }
-                    Some(Err(service::WaitNextResponseError::ClientMainTaskDestroyed)) => {
-                        // The client main task never closes by itself but only as a consequence
-                        // to the I/O task closing.
-                        unreachable!()
+                    SlowSubscriberPolicy::DropOldestNotifications => {
+                        // Responses to requests are never dropped, as the client would wait for
+                        // them forever. Their number is bounded by the maximum number of pending
+                        // requests anyway.
+                        let is_notification = |response: &str| {
+                            matches!(
+                                parse::parse_request(response),
+                                Ok(parse::Request { id_json: None, .. })
+                            )
+                        };
+                        if let Some(position) = pending_responses
+                            .iter()
+                            .position(|response| is_notification(response))
+                        {
+                            pending_responses.remove(position);
+                            log_callback.log(
+                                LogLevel::Debug,
+                                format!("json-rpc-notification-dropped; address={socket_address}"),
+                            );
+                        } else if is_notification(&response) {
+                            // The queue only contains responses. The new notification is dropped
+                            // rather than making the queue grow.
+                            log_callback.log(
+                                LogLevel::Debug,
+                                format!("json-rpc-notification-dropped; address={socket_address}"),
+                            );
+                            continue;
+                        }
                     }
-                };
+                }
             }
-        };
+            pending_responses.push_back(response);
+            on_pending_response_pushed.notify(usize::MAX);
+        }
+    };
 
-        // Create a future responsible for pulling messages from the socket and sending them to
-        // the main task.
-        let receiving_future = async {
-            let mut message = Vec::new();
-            loop {
-                message.clear();
+    // Create a future responsible for sending the queued responses back.
+    let sending_future = async {
+        let mut must_flush_asap = false;
 
-                match ws_receiver.receive_data(&mut message).await {
-                    Ok(soketto::Data::Binary(_)) => {
-                        break Err("Unexpected binary frame".to_string());
+        loop {
+            // If `must_flush_asap`, we simply peek for the next response but without awaiting.
+            // If `!must_flush_asap`, we wait for as long as necessary.
+            let maybe_response = {
+                let mut wait = None;
+                loop {
+                    if let Some(response) = pending_responses.lock().unwrap().pop_front() {
+                        break Some(response);
                     }
-                    Ok(soketto::Data::Text(_)) => {} // Handled below.
-                    Err(soketto::connection::Error::Closed) => break Ok(()),
-                    Err(err) => {
+                    if must_flush_asap {
+                        break None;
+                    }
+                    if let Some(wait) = wait.take() {
+                        wait.await
+                    } else {
+                        wait = Some(on_pending_response_pushed.listen());
+                    }
+                }
+            };
+
+            match maybe_response {
+                None => {
+                    if let Err(err) = ws_sender.flush().await {
                         break Err(err.to_string());
                     }
+                    must_flush_asap = false;
                 }
+                Some(response) => {
+                    log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "json-rpc-response; address={}; response={}",
+                            socket_address,
+                            crate::util::truncated_str(
+                                response.chars().filter(|c| !c.is_control()),
+                                128
+                            )
+                        ),
+                    );
 
-                let request = match String::from_utf8(mem::take(&mut message)) {
-                    Ok(r) => r,
-                    Err(error) => {
-                        break Err(format!("Non-UTF8 text frame: {error}"));
+                    if let Err(err) = ws_sender.send_text_owned(response).await {
+                        break Err(err.to_string());
                     }
-                };
+                    must_flush_asap = true;
+                }
+            };
+        }
+    };
 
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!(
-                        "json-rpc-request; address={}; request={}",
-                        socket_address,
-                        crate::util::truncated_str(
-                            request.chars().filter(|c| !c.is_control()),
-                            128
-                        )
-                    ),
-                );
+    // Create a future responsible for pulling messages from the socket and sending them to
+    // the main task.
+    let receiving_future = async {
+        let mut message = Vec::new();
 
-                match io.send_request(request).await {
-                    Ok(()) => {}
-                    Err(service::SendRequestError {
-                        cause: service::SendRequestErrorCause::ClientMainTaskDestroyed,
-                        ..
-                    }) => {
-                        // The client main task never closes by itself but only as a
-                        // consequence to the I/O task closing.
-                        unreachable!()
-                    }
+        // Requests are counted within windows of one second.
+        let mut rate_limit_window_start = Instant::now();
+        let mut rate_limit_window_requests = 0u32;
+
+        loop {
+            message.clear();
+
+            match ws_receiver.receive_data(&mut message).await {
+                Ok(soketto::Data::Binary(_)) => {
+                    break Err("Unexpected binary frame".to_string());
+                }
+                Ok(soketto::Data::Text(_)) => {} // Handled below.
+                Err(soketto::connection::Error::Closed) => break Ok(()),
+                Err(err) => {
+                    break Err(err.to_string());
                 }
             }
-        };
 
-        // Run these two futures until completion.
-        match future::or(sending_future, receiving_future).await {
-            Ok(()) => {
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!("json-rpc-connection-closed; address={socket_address}"),
-                );
+            let request = match String::from_utf8(mem::take(&mut message)) {
+                Ok(r) => r,
+                Err(error) => {
+                    break Err(format!("Non-UTF8 text frame: {error}"));
+                }
+            };
+
+            log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "json-rpc-request; address={}; request={}",
+                    socket_address,
+                    crate::util::truncated_str(request.chars().filter(|c| !c.is_control()), 128)
+                ),
+            );
+
+            // Apply the rate limiting.
+            if rate_limit_window_start.elapsed() >= Duration::from_secs(1) {
+                rate_limit_window_start = Instant::now();
+                rate_limit_window_requests = 0;
             }
-            Err(error) => {
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
-                );
+            if rate_limit_window_requests >= max_requests_per_second {
+                // Requests without an identifier are notifications, which are simply ignored.
+                // Requests that fail to parse are also ignored, as there is no identifier to
+                // answer to.
+                if let Ok(parse::Request {
+                    id_json: Some(id_json),
+                    ..
+                }) = parse::parse_request(&request)
+                {
+                    let response = parse::build_error_response(
+                        id_json,
+                        parse::ErrorResponse::ServerError(-32000, "Server is busy"),
+                        None,
+                    );
+                    if busy_responses_tx.try_send(response).is_err() {
+                        // The client keeps sending requests while being rate limited and
+                        // doesn't even read the responses.
+                        break Err("Too many rate-limited requests".to_string());
+                    }
+                }
+                continue;
+            }
+            rate_limit_window_requests += 1;
+
+            in_flight_requests.lock().unwrap().on_request(&request);
+
+            match io.send_request(request).await {
+                Ok(()) => {}
+                Err(service::SendRequestError {
+                    cause: service::SendRequestErrorCause::ClientMainTaskDestroyed,
+                    ..
+                }) => {
+                    // The client main task never closes by itself but only as a
+                    // consequence to the I/O task closing.
+                    unreachable!()
+                }
             }
         }
     };
 
-    tasks_executor(Box::pin(async move {
-        run_future.await;
-        num_json_rpc_clients.fetch_sub(1, Ordering::Release);
-    }))
+    // Run these two futures until completion.
+    match future::or(future::or(pulling_future, sending_future), receiving_future).await {
+        Ok(()) => {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("json-rpc-connection-closed; address={socket_address}"),
+            );
+        }
+        Err(error) => {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+            );
+        }
+    }
 }
 
 fn spawn_client_main_task(
@@ -547,6 +1263,7 @@ fn spawn_client_main_task(
     consensus_service: Arc<consensus_service::ConsensusService>,
     database: Arc<database_thread::DatabaseThread>,
     to_requests_handlers: async_channel::Sender<requests_handler::Message>,
+    allow_unsafe_methods: bool,
     mut client_main_task: service::ClientMainTask,
 ) {
     let tasks_executor2 = tasks_executor.clone();
@@ -565,6 +1282,11 @@ fn spawn_client_main_task(
                 } => {
                     client_main_task = task;
 
+                    if !allow_unsafe_methods && is_unsafe_method(&request_process.request()) {
+                        request_process.fail(service::ErrorResponse::MethodNotFound);
+                        continue;
+                    }
+
                     match request_process.request() {
                         methods::MethodCall::chainHead_v1_header {
                             follow_subscription,
@@ -681,3 +1403,32 @@ fn spawn_client_main_task(
         }
     }));
 }
+
+/// Returns `true` if the given JSON-RPC method shouldn't be exposed to untrusted clients, either
+/// because it gives access to secret keys, modifies the state of the node, reveals information
+/// about the node's networking, or is very expensive to answer.
+fn is_unsafe_method(request: &methods::MethodCall) -> bool {
+    matches!(
+        request,
+        methods::MethodCall::author_hasKey { .. }
+            | methods::MethodCall::author_hasSessionKeys { .. }
+            | methods::MethodCall::author_insertKey { .. }
+            | methods::MethodCall::author_removeExtrinsic { .. }
+            | methods::MethodCall::author_rotateKeys { .. }
+            | methods::MethodCall::offchain_localStorageGet { .. }
+            | methods::MethodCall::offchain_localStorageSet { .. }
+            | methods::MethodCall::state_queryStorage { .. }
+            | methods::MethodCall::state_traceBlock { .. }
+            | methods::MethodCall::sudo_unstable_blockAuthorities { .. }
+            | methods::MethodCall::sudo_unstable_blockExecutionProfile { .. }
+            | methods::MethodCall::sudo_unstable_databaseStatistics { .. }
+            | methods::MethodCall::sudo_unstable_forceFinalize { .. }
+            | methods::MethodCall::sudo_unstable_setAuthoringEnabled { .. }
+            | methods::MethodCall::sudo_unstable_storageDiff { .. }
+            | methods::MethodCall::system_addReservedPeer { .. }
+            | methods::MethodCall::system_dryRun { .. }
+            | methods::MethodCall::system_networkState { .. }
+            | methods::MethodCall::system_peers { .. }
+            | methods::MethodCall::system_removeReservedPeer { .. }
+    )
+}
@@ -0,0 +1,124 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Determination of the nonce that the next transaction of an account must use.
+//!
+//! The nonce of the account is obtained by calling `AccountNonceApi_account_nonce` against the
+//! storage of the current best block. Similar to what Substrate does, this value is then
+//! increased for each transaction of the account that is in the transactions pool. These
+//! transactions are detected thanks to the tags they provide, which, in the case of the
+//! `frame_system` pallet, consist in the SCALE encoding of the account id and nonce.
+
+use crate::{
+    consensus_service, database_thread, runtime_caches_service, runtime_calls_limiter,
+    transactions_pool,
+};
+
+use smoldot::executor;
+use std::sync::Arc;
+
+/// Returns the nonce that the next transaction of the given account must use.
+///
+/// `account_id` is the SCALE-encoded account id, typically 32 bytes.
+pub async fn account_next_index(
+    database: &database_thread::DatabaseThread,
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    runtime_calls_limiter: &Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+    transactions_pool: &transactions_pool::TransactionsPool,
+    account_id: &[u8],
+) -> Result<u64, AccountNextIndexError> {
+    let best_block_hash = database
+        .with_database_read(|db| db.best_block_hash())
+        .await
+        .map_err(|_| AccountNextIndexError::CorruptedDatabase)?;
+
+    let runtime = runtime_caches_service
+        .get(best_block_hash)
+        .await
+        .map_err(AccountNextIndexError::Runtime)?;
+    let runtime = (*runtime).clone();
+
+    if runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version("AccountNonceApi")
+        != Some(1)
+    {
+        return Err(AccountNextIndexError::UnsupportedRuntime);
+    }
+
+    let _permit = runtime_calls_limiter.json_rpc_permit().await;
+    let output = consensus_service::runtime_call(
+        database,
+        &best_block_hash,
+        runtime,
+        "AccountNonceApi_account_nonce",
+        account_id,
+        executor::runtime_call::StorageProofSizeBehavior::Unimplemented,
+        executor::runtime_call::StorageChanges::empty(),
+        false,
+    )
+    .await
+    .map_err(AccountNextIndexError::RuntimeCall)?
+    .output;
+
+    // The type of the nonce depends on the chain. Most chains use a `u32`, but some use a `u64`.
+    let mut nonce = match output.len() {
+        4 => u64::from(u32::from_le_bytes(
+            <[u8; 4]>::try_from(&output[..]).unwrap(),
+        )),
+        8 => u64::from_le_bytes(<[u8; 8]>::try_from(&output[..]).unwrap()),
+        _ => return Err(AccountNextIndexError::OutputDecode),
+    };
+
+    // Skip the nonces already used by the transactions of the pool.
+    // The tags are built using the same nonce width as the output of the runtime.
+    let max_nonce = if output.len() == 4 {
+        u64::from(u32::MAX)
+    } else {
+        u64::MAX
+    };
+    while nonce < max_nonce
+        && transactions_pool.is_provided(&{
+            let mut tag = account_id.to_vec();
+            tag.extend_from_slice(&nonce.to_le_bytes()[..output.len()]);
+            tag
+        })
+    {
+        nonce += 1;
+    }
+
+    Ok(nonce)
+}
+
+/// Error returned by [`account_next_index`].
+#[derive(Debug, derive_more::Display)]
+pub enum AccountNextIndexError {
+    /// Database is corrupted.
+    CorruptedDatabase,
+    /// Failed to obtain the runtime of the best block.
+    #[display(fmt = "Failed to obtain the runtime of the best block: {_0}")]
+    Runtime(runtime_caches_service::GetError),
+    /// The runtime of the best block doesn't support the `AccountNonceApi` runtime API.
+    UnsupportedRuntime,
+    /// Error while executing the runtime function.
+    #[display(fmt = "{_0}")]
+    RuntimeCall(consensus_service::RuntimeCallError),
+    /// Failed to decode the output of the runtime function.
+    OutputDecode,
+}
@@ -0,0 +1,157 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Determining the GrandPa authorities and Babe epoch of a block found in the database.
+//!
+//! The database only contains the consensus-related information of the finalized block. The
+//! information of any other block is obtained by replaying the digests of all the headers of the
+//! chain, starting from the genesis block, using
+//! [`authorities_history`](smoldot::chain::chain_information::authorities_history).
+//!
+//! Consequently, the cost of determining the authorities of a block is proportional to its
+//! height.
+
+use crate::database_thread;
+
+use smoldot::{
+    chain::chain_information::{self, authorities_history},
+    database::full_sqlite,
+    header,
+};
+
+/// Number of headers loaded from the database at once. The database is unavailable to the rest
+/// of the node while headers are being loaded, and the value should therefore not be too large.
+const HEADERS_PER_BATCH: u64 = 1024;
+
+/// Authorities of a block. See [`block_authorities`].
+#[derive(Debug, Clone)]
+pub struct BlockAuthorities {
+    /// Number of the block.
+    pub block_number: u64,
+    /// Identifier and list of the GrandPa authorities responsible for finalizing the block.
+    /// `None` if the chain doesn't use GrandPa.
+    pub grandpa: Option<(u64, Vec<header::GrandpaAuthority>)>,
+    /// Index and information of the Babe epoch the block belongs to. `None` if the chain doesn't
+    /// use Babe, or if the block is the genesis block.
+    pub babe: Option<(u64, chain_information::BabeEpochInformation)>,
+}
+
+/// Returns the GrandPa authorities and Babe epoch of the given block.
+pub async fn block_authorities(
+    database: &database_thread::DatabaseThread,
+    genesis_chain_information: &chain_information::ValidChainInformation,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+) -> Result<BlockAuthorities, BlockAuthoritiesError> {
+    // Walk up the ancestry of the requested block until reaching a block of the best chain.
+    // Since finalized blocks are always part of the best chain, this only ever loads the headers
+    // of non-finalized blocks.
+    let (fork_point_number, non_best_headers) = database
+        .with_database_read(move |database| {
+            let mut non_best_headers = Vec::new();
+            let mut current = block_hash;
+            loop {
+                let header = database
+                    .block_scale_encoded_header(&current)
+                    .map_err(|_| BlockAuthoritiesError::DatabaseCorrupted)?
+                    .ok_or(BlockAuthoritiesError::UnknownBlock)?;
+                let decoded = header::decode(&header, block_number_bytes)
+                    .map_err(BlockAuthoritiesError::InvalidHeader)?;
+
+                if database
+                    .best_block_hash_by_number(decoded.number)
+                    .map_err(|_| BlockAuthoritiesError::DatabaseCorrupted)?
+                    == Some(current)
+                {
+                    break Ok((decoded.number, non_best_headers));
+                }
+
+                current = *decoded.parent_hash;
+                non_best_headers.push(header);
+            }
+        })
+        .await?;
+
+    let mut history = authorities_history::AuthoritiesHistory::new(
+        genesis_chain_information.into(),
+        block_number_bytes,
+    );
+
+    // Replay the headers of the best chain, in batches.
+    let mut next_number = history.block_number() + 1;
+    while next_number <= fork_point_number {
+        let batch_end = fork_point_number.min(next_number + HEADERS_PER_BATCH - 1);
+        let headers = database
+            .with_database_read(move |database| {
+                (next_number..=batch_end)
+                    .map(|number| {
+                        let hash = database
+                            .best_block_hash_by_number(number)?
+                            .ok_or(full_sqlite::CorruptedError::BrokenChain)?;
+                        database
+                            .block_scale_encoded_header(&hash)?
+                            .ok_or(full_sqlite::CorruptedError::MissingBlockHeader)
+                    })
+                    .collect::<Result<Vec<_>, full_sqlite::CorruptedError>>()
+            })
+            .await
+            .map_err(|_| BlockAuthoritiesError::DatabaseCorrupted)?;
+
+        for header in headers {
+            history
+                .apply_header(&header)
+                .map_err(BlockAuthoritiesError::Replay)?;
+        }
+
+        next_number = batch_end + 1;
+    }
+
+    // Then the headers of the blocks that aren't in the best chain.
+    for header in non_best_headers.iter().rev() {
+        history
+            .apply_header(header)
+            .map_err(BlockAuthoritiesError::Replay)?;
+    }
+
+    debug_assert_eq!(*history.block_hash(), block_hash);
+
+    Ok(BlockAuthorities {
+        block_number: history.block_number(),
+        grandpa: history
+            .grandpa_authorities()
+            .map(|set| (set.set_id, set.authorities.to_vec())),
+        babe: history
+            .babe_epoch()
+            .map(|epoch| (epoch.epoch_index, epoch.information.clone())),
+    })
+}
+
+/// Error potentially returned by [`block_authorities`].
+#[derive(Debug, derive_more::Display)]
+pub enum BlockAuthoritiesError {
+    /// Requested block couldn't be found in the database.
+    UnknownBlock,
+    /// Database is corrupted.
+    DatabaseCorrupted,
+    /// Failed to decode the header of a block.
+    #[display(fmt = "Invalid block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// Error while replaying the headers of the chain. Can happen if the best chain has been
+    /// modified in parallel.
+    #[display(fmt = "Error while replaying headers: {_0}")]
+    Replay(authorities_history::ApplyHeaderError),
+}
@@ -0,0 +1,411 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Re-execution of blocks while recording what the runtime does.
+//!
+//! A block is re-executed by calling `Core_execute_block` on the runtime of its parent, using the
+//! storage of its parent found in the database. Every time the runtime needs something from
+//! outside of the virtual machine, such as a storage value, a signature verification, or
+//! printing a log, the corresponding event is recorded. The changes to the storage performed by
+//! the block are added at the end of the trace.
+//!
+//! Storage accesses that concern values that have been modified earlier during the same
+//! execution are answered by the executor without going through the client, and are thus not
+//! part of the trace.
+
+use crate::{database_thread, runtime_caches_service, runtime_calls_limiter};
+
+use smoldot::{
+    database::full_sqlite,
+    executor::{host, runtime_call},
+    header, trie,
+    verify::body_only,
+};
+use std::{iter, sync::Arc, time::Duration};
+
+/// Maximum duration during which the state of the parent of the block being traced is pinned.
+const PARENT_STATE_PIN_MAX_DURATION: Duration = Duration::from_secs(600);
+
+/// Trace of the execution of a block.
+#[derive(Debug, Clone)]
+pub struct BlockTrace {
+    /// Hash of the block that has been executed.
+    pub block_hash: [u8; 32],
+    /// Hash of the parent of the block that has been executed. The block has been executed
+    /// against the storage of this block.
+    pub parent_hash: [u8; 32],
+    /// List of events, in the order in which they happened.
+    pub events: Vec<BlockTraceEvent>,
+}
+
+/// Event in a [`BlockTrace`].
+#[derive(Debug, Clone)]
+pub enum BlockTraceEvent {
+    /// The runtime has read a storage value.
+    StorageGet {
+        /// Child trie the value belongs to, or `None` for the main trie.
+        child_trie: Option<Vec<u8>>,
+        key: Vec<u8>,
+        /// Value that has been read, or `None` if there is no value at this key.
+        value: Option<Vec<u8>>,
+    },
+    /// The runtime has requested the key that follows a certain key.
+    StorageNextKey {
+        /// Child trie the key belongs to, or `None` for the main trie.
+        child_trie: Option<Vec<u8>>,
+        /// Key whose next key is requested. Each element is a nibble.
+        key_nibbles: Vec<u8>,
+        /// Key that has been found, or `None` if there is no next key. Each element is a nibble.
+        next_key_nibbles: Option<Vec<u8>>,
+    },
+    /// The runtime has requested the Merkle value of the trie node that is the closest
+    /// descendant of a certain key, typically in order to calculate a trie root hash.
+    StorageClosestDescendantMerkleValue {
+        /// Child trie the node belongs to, or `None` for the main trie.
+        child_trie: Option<Vec<u8>>,
+        /// Key whose closest descendant is requested. Each element is a nibble.
+        key_nibbles: Vec<u8>,
+        /// Merkle value that has been found, or `None` if there is no descendant.
+        merkle_value: Option<Vec<u8>>,
+    },
+    /// The runtime has verified a signature.
+    SignatureVerification {
+        public_key: Vec<u8>,
+        message: Vec<u8>,
+        signature: Vec<u8>,
+        /// `true` if the signature is valid.
+        success: bool,
+    },
+    /// The runtime has written to the off-chain storage.
+    OffchainStorageSet {
+        key: Vec<u8>,
+        /// Value written, or `None` if the value is erased.
+        value: Option<Vec<u8>>,
+    },
+    /// The runtime has emitted a log.
+    Log {
+        /// Subsystem of the runtime that has emitted the log, if any.
+        target: Option<String>,
+        message: String,
+    },
+    /// The block modifies a storage value. These events are always found at the end of the
+    /// trace, ordered by child trie then key.
+    StorageSet {
+        /// Child trie the value belongs to, or `None` for the main trie.
+        child_trie: Option<Vec<u8>>,
+        key: Vec<u8>,
+        /// New value, or `None` if the value is erased.
+        value: Option<Vec<u8>>,
+    },
+}
+
+/// Re-executes the given block and returns the trace of its execution.
+pub async fn trace_block(
+    database: &database_thread::DatabaseThread,
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    runtime_calls_limiter: &Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+    block_number_bytes: usize,
+    block_hash: [u8; 32],
+) -> Result<BlockTrace, TraceBlockError> {
+    let (scale_encoded_header, body) = database
+        .with_database_read(move |database| {
+            let Some(header) = database.block_scale_encoded_header(&block_hash)? else {
+                return Ok(None);
+            };
+            let Some(body) = database.block_extrinsics(&block_hash)? else {
+                return Ok(None);
+            };
+            Ok(Some((header, body.collect::<Vec<_>>())))
+        })
+        .await
+        .map_err(|_: full_sqlite::CorruptedError| TraceBlockError::DatabaseCorrupted)?
+        .ok_or(TraceBlockError::UnknownBlock)?;
+
+    let decoded_header = header::decode(&scale_encoded_header, block_number_bytes)
+        .map_err(TraceBlockError::InvalidHeader)?;
+    if decoded_header.number == 0 {
+        return Err(TraceBlockError::GenesisBlock);
+    }
+    let parent_hash = *decoded_header.parent_hash;
+
+    // Make sure that the storage of the parent isn't pruned while the block is executing.
+    let _parent_state_pin = database.pin_state(parent_hash, PARENT_STATE_PIN_MAX_DURATION);
+
+    let runtime = match runtime_caches_service.get(parent_hash).await {
+        Ok(runtime) => (*runtime).clone(),
+        Err(runtime_caches_service::GetError::UnknownBlock)
+        | Err(runtime_caches_service::GetError::Pruned) => {
+            return Err(TraceBlockError::ParentStatePruned)
+        }
+        Err(runtime_caches_service::GetError::NoCode)
+        | Err(runtime_caches_service::GetError::InvalidHeapPages)
+        | Err(runtime_caches_service::GetError::InvalidRuntime(_)) => {
+            return Err(TraceBlockError::InvalidParentRuntime)
+        }
+        Err(runtime_caches_service::GetError::CorruptedDatabase) => {
+            return Err(TraceBlockError::DatabaseCorrupted)
+        }
+    };
+
+    let parameter =
+        body_only::execute_block_parameter(&scale_encoded_header, block_number_bytes, body.iter())
+            .map_err(|err| match err {
+                body_only::ExecuteBlockParameterError::InvalidHeader(err) => {
+                    TraceBlockError::InvalidHeader(err)
+                }
+            })?;
+
+    let _permit = runtime_calls_limiter.json_rpc_permit().await;
+    let mut call = runtime_call::run(runtime_call::Config {
+        virtual_machine: runtime,
+        function_to_call: body_only::EXECUTE_BLOCK_FUNCTION_NAME,
+        parameter,
+        storage_proof_size_behavior:
+            runtime_call::StorageProofSizeBehavior::proof_recording_disabled(),
+        storage_main_trie_changes: Default::default(),
+        // All the logs are traced, no matter their level.
+        max_log_level: 5,
+        calculate_trie_changes: false,
+    })
+    .map_err(|(err, _)| TraceBlockError::RuntimeStart(err))?;
+
+    let mut events = Vec::new();
+
+    loop {
+        match call {
+            runtime_call::RuntimeCall::Finished(Err(error)) => {
+                return Err(TraceBlockError::RuntimeExecution(error.detail));
+            }
+            runtime_call::RuntimeCall::Finished(Ok(success)) => {
+                body_only::check_execute_block_output(success.virtual_machine.value().as_ref())
+                    .map_err(TraceBlockError::ExecuteBlockOutput)?;
+
+                let mut storage_changes = success
+                    .storage_changes
+                    .storage_changes_iter_unordered()
+                    .collect::<Vec<_>>();
+                storage_changes.sort_unstable_by_key(|(child_trie, key, _)| (*child_trie, *key));
+                events.extend(storage_changes.into_iter().map(|(child_trie, key, value)| {
+                    BlockTraceEvent::StorageSet {
+                        child_trie: child_trie.map(|t| t.to_vec()),
+                        key: key.to_vec(),
+                        value: value.map(|v| v.to_vec()),
+                    }
+                }));
+
+                return Ok(BlockTrace {
+                    block_hash,
+                    parent_hash,
+                    events,
+                });
+            }
+
+            runtime_call::RuntimeCall::StorageGet(req) => {
+                let child_trie = req.child_trie().map(|t| t.as_ref().to_vec());
+                let key = req.key().as_ref().to_vec();
+
+                let parent_paths = child_trie
+                    .as_ref()
+                    .map(|child_trie| child_trie_path(child_trie));
+                let key_nibbles = trie::bytes_to_nibbles(key.iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database_read(move |db| {
+                        db.block_storage_get(
+                            &parent_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(TraceBlockError::from_storage_access)?;
+                let value = match value {
+                    Some((value, version)) => Some((
+                        value,
+                        runtime_call::TrieEntryVersion::try_from(version)
+                            .map_err(|_| TraceBlockError::DatabaseCorrupted)?,
+                    )),
+                    None => None,
+                };
+
+                events.push(BlockTraceEvent::StorageGet {
+                    child_trie,
+                    key,
+                    value: value.as_ref().map(|(value, _)| value.clone()),
+                });
+                call = req.inject_value(
+                    value
+                        .as_ref()
+                        .map(|(value, version)| (iter::once(&value[..]), *version)),
+                );
+            }
+            runtime_call::RuntimeCall::ClosestDescendantMerkleValue(req) => {
+                let child_trie = req.child_trie().map(|t| t.as_ref().to_vec());
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+
+                let parent_paths = child_trie
+                    .as_ref()
+                    .map(|child_trie| child_trie_path(child_trie));
+                let merkle_value = database
+                    .with_database_read({
+                        let key_nibbles = key_nibbles.clone();
+                        move |db| {
+                            db.block_storage_closest_descendant_merkle_value(
+                                &parent_hash,
+                                parent_paths.into_iter().map(|p| p.into_iter()),
+                                key_nibbles.iter().copied(),
+                            )
+                        }
+                    })
+                    .await
+                    .map_err(TraceBlockError::from_storage_access)?;
+
+                call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+                events.push(BlockTraceEvent::StorageClosestDescendantMerkleValue {
+                    child_trie,
+                    key_nibbles,
+                    merkle_value,
+                });
+            }
+            runtime_call::RuntimeCall::NextKey(req) => {
+                let child_trie = req.child_trie().map(|t| t.as_ref().to_vec());
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+
+                let parent_paths = child_trie
+                    .as_ref()
+                    .map(|child_trie| child_trie_path(child_trie));
+                let search_key_nibbles = key_nibbles
+                    .iter()
+                    .copied()
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+                let next_key_nibbles = database
+                    .with_database_read(move |db| {
+                        db.block_storage_next_key(
+                            &parent_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            search_key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .map_err(TraceBlockError::from_storage_access)?;
+
+                call = req.inject_key(
+                    next_key_nibbles
+                        .as_ref()
+                        .map(|k| k.iter().map(|b| trie::Nibble::try_from(*b).unwrap())),
+                );
+                events.push(BlockTraceEvent::StorageNextKey {
+                    child_trie,
+                    key_nibbles,
+                    next_key_nibbles,
+                });
+            }
+            runtime_call::RuntimeCall::SignatureVerification(req) => {
+                let success = req.is_valid();
+                events.push(BlockTraceEvent::SignatureVerification {
+                    public_key: req.public_key().as_ref().to_vec(),
+                    message: req.message().as_ref().to_vec(),
+                    signature: req.signature().as_ref().to_vec(),
+                    success,
+                });
+                call = if success {
+                    req.resume_success()
+                } else {
+                    req.resume_failed()
+                };
+            }
+            runtime_call::RuntimeCall::OffchainStorageSet(req) => {
+                events.push(BlockTraceEvent::OffchainStorageSet {
+                    key: req.key().as_ref().to_vec(),
+                    value: req.value().map(|v| v.as_ref().to_vec()),
+                });
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::LogEmit(req) => {
+                let (target, message) = match req.info() {
+                    runtime_call::LogEmitInfo::Num(num) => (None, num.to_string()),
+                    runtime_call::LogEmitInfo::Utf8(string) => (None, string.to_string()),
+                    runtime_call::LogEmitInfo::Hex(hex) => (None, hex.to_string()),
+                    runtime_call::LogEmitInfo::Log {
+                        target, message, ..
+                    } => (Some(target.to_string()), message.to_string()),
+                };
+                events.push(BlockTraceEvent::Log { target, message });
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::Offchain(_) => {
+                // Offchain storage calls are forbidden during block execution.
+                return Err(TraceBlockError::ForbiddenHostFunction);
+            }
+        }
+    }
+}
+
+/// Returns the path, in nibbles, of the root of the given child trie within the main trie.
+fn child_trie_path(child_trie: &[u8]) -> Vec<u8> {
+    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+        .chain(trie::bytes_to_nibbles(child_trie.iter().copied()))
+        .map(u8::from)
+        .collect()
+}
+
+/// Error returned by [`trace_block`].
+#[derive(Debug, derive_more::Display)]
+pub enum TraceBlockError {
+    /// Requested block couldn't be found in the database.
+    UnknownBlock,
+    /// The genesis block can't be executed, as it doesn't have any parent.
+    GenesisBlock,
+    /// Storage of the parent of the requested block is no longer in the database.
+    ParentStatePruned,
+    /// The runtime of the parent of the requested block is invalid.
+    InvalidParentRuntime,
+    /// Database is corrupted.
+    DatabaseCorrupted,
+    /// Failed to decode the header of the block.
+    #[display(fmt = "Invalid block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// Error starting the runtime execution.
+    #[display(fmt = "{_0}")]
+    RuntimeStart(host::StartErr),
+    /// Error while executing the runtime.
+    #[display(fmt = "{_0}")]
+    RuntimeExecution(runtime_call::ErrorDetail),
+    /// Error in the output of `Core_execute_block`.
+    #[display(fmt = "Error in the output of Core_execute_block: {_0}")]
+    ExecuteBlockOutput(body_only::ExecuteBlockOutputError),
+    /// Runtime has tried to call a host function that is forbidden during block execution.
+    ForbiddenHostFunction,
+}
+
+impl TraceBlockError {
+    fn from_storage_access(error: database_thread::StorageAccessError) -> Self {
+        match error {
+            database_thread::StorageAccessError::UnknownBlock
+            | database_thread::StorageAccessError::IncompleteStorage => {
+                TraceBlockError::ParentStatePruned
+            }
+            database_thread::StorageAccessError::Corrupted(_) => TraceBlockError::DatabaseCorrupted,
+        }
+    }
+}
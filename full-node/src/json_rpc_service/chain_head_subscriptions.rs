@@ -29,10 +29,16 @@ use std::{
     num::NonZeroUsize,
     pin::{self, Pin},
     sync::Arc,
+    time::Duration,
 };
 
 use crate::{consensus_service, database_thread};
 
+/// Maximum duration during which the state of a block pinned by a `chainHead_v1_follow`
+/// subscription is guaranteed to stay in the database. JSON-RPC clients are expected to unpin
+/// blocks well before this delay.
+const STATE_PIN_MAX_DURATION: Duration = Duration::from_secs(3600);
+
 pub struct Config {
     /// Function that can be used to spawn background tasks.
     ///
@@ -85,10 +91,16 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
         let mut foreground_receiver = pin::pin!(config.receiver);
 
         let mut pinned_blocks =
-            hashbrown::HashSet::with_capacity_and_hasher(32, fnv::FnvBuildHasher::default());
+            hashbrown::HashMap::with_capacity_and_hasher(32, fnv::FnvBuildHasher::default());
         let mut current_best_block = consensus_service_subscription.finalized_block_hash;
 
-        pinned_blocks.insert(consensus_service_subscription.finalized_block_hash);
+        pinned_blocks.insert(
+            consensus_service_subscription.finalized_block_hash,
+            config.database.pin_state(
+                consensus_service_subscription.finalized_block_hash,
+                STATE_PIN_MAX_DURATION,
+            ),
+        );
         json_rpc_subscription
             .send_notification(methods::ServerToClient::chainHead_v1_followEvent {
                 subscription: (&json_rpc_subscription_id).into(),
@@ -110,7 +122,12 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
             .await;
 
         for block in consensus_service_subscription.non_finalized_blocks_ancestry_order {
-            pinned_blocks.insert(block.block_hash);
+            pinned_blocks.insert(
+                block.block_hash,
+                config
+                    .database
+                    .pin_state(block.block_hash, STATE_PIN_MAX_DURATION),
+            );
             json_rpc_subscription
                 .send_notification(methods::ServerToClient::chainHead_v1_followEvent {
                     subscription: (&json_rpc_subscription_id).into(),
@@ -174,14 +191,16 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                         unreachable!()
                     };
 
-                    if !pinned_blocks.contains(&hash.0) {
+                    if !pinned_blocks.contains_key(&hash.0) {
                         request.fail(service::ErrorResponse::InvalidParams);
                         continue;
                     }
 
                     let database_outcome = config
                         .database
-                        .with_database(move |database| database.block_scale_encoded_header(&hash.0))
+                        .with_database_read(move |database| {
+                            database.block_scale_encoded_header(&hash.0)
+                        })
                         .await;
 
                     match database_outcome {
@@ -205,7 +224,7 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                     block_hashes,
                     outcome,
                 }) => {
-                    if block_hashes.iter().any(|h| !pinned_blocks.contains(h)) {
+                    if block_hashes.iter().any(|h| !pinned_blocks.contains_key(h)) {
                         let _ = outcome.send(Err(()));
                     } else {
                         for block_hash in block_hashes {
@@ -222,7 +241,12 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                     block,
                     ..
                 }) => {
-                    pinned_blocks.insert(block.block_hash);
+                    pinned_blocks.insert(
+                        block.block_hash,
+                        config
+                            .database
+                            .pin_state(block.block_hash, STATE_PIN_MAX_DURATION),
+                    );
                     json_rpc_subscription
                         .send_notification(methods::ServerToClient::chainHead_v1_followEvent {
                             subscription: (&json_rpc_subscription_id).into(),
@@ -0,0 +1,231 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Extraction of the fee-related constants of the runtime of a block.
+//!
+//! The metadata of the runtime is obtained by calling `Metadata_metadata` against the storage of
+//! the block found in the database, then decoded with [`smoldot::metadata`]. Contrary to
+//! `TransactionPaymentApi_query_info`, this works even with runtimes that don't implement the
+//! transaction payment runtime API.
+
+use crate::{database_thread, runtime_caches_service, runtime_calls_limiter};
+
+use smoldot::{
+    executor::{host, runtime_call},
+    json_rpc::methods,
+    metadata, trie,
+};
+use std::{iter, sync::Arc, time::Duration};
+
+/// Maximum duration during which the state of the block whose metadata is obtained is pinned.
+const STATE_PIN_MAX_DURATION: Duration = Duration::from_secs(60);
+
+/// Returns the fee-related constants of the runtime of the given block.
+pub async fn fee_constants(
+    database: &database_thread::DatabaseThread,
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    runtime_calls_limiter: &Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+    block_hash: [u8; 32],
+) -> Result<metadata::FeeConstants, FeeConstantsError> {
+    // Make sure that the storage of the block isn't pruned while the runtime is executing.
+    let _state_pin = database.pin_state(block_hash, STATE_PIN_MAX_DURATION);
+
+    let runtime = match runtime_caches_service.get(block_hash).await {
+        Ok(runtime) => (*runtime).clone(),
+        Err(runtime_caches_service::GetError::UnknownBlock) => {
+            return Err(FeeConstantsError::UnknownBlock)
+        }
+        Err(runtime_caches_service::GetError::Pruned) => {
+            return Err(FeeConstantsError::StatePruned)
+        }
+        Err(runtime_caches_service::GetError::NoCode)
+        | Err(runtime_caches_service::GetError::InvalidHeapPages)
+        | Err(runtime_caches_service::GetError::InvalidRuntime(_)) => {
+            return Err(FeeConstantsError::InvalidRuntime)
+        }
+        Err(runtime_caches_service::GetError::CorruptedDatabase) => {
+            return Err(FeeConstantsError::DatabaseCorrupted)
+        }
+    };
+
+    let _permit = runtime_calls_limiter.json_rpc_permit().await;
+    let mut call = runtime_call::run(runtime_call::Config {
+        virtual_machine: runtime,
+        function_to_call: "Metadata_metadata",
+        parameter: iter::empty::<&'static [u8]>(),
+        max_log_level: 0,
+        storage_proof_size_behavior:
+            runtime_call::StorageProofSizeBehavior::proof_recording_disabled(),
+        storage_main_trie_changes: Default::default(),
+        calculate_trie_changes: false,
+    })
+    .map_err(|(err, _)| FeeConstantsError::RuntimeStart(err))?;
+
+    loop {
+        match call {
+            runtime_call::RuntimeCall::Finished(Err(error)) => {
+                return Err(FeeConstantsError::RuntimeExecution(error.detail));
+            }
+            runtime_call::RuntimeCall::Finished(Ok(success)) => {
+                let output = success.virtual_machine.value();
+                let metadata = methods::remove_metadata_length_prefix(output.as_ref())
+                    .map_err(FeeConstantsError::MetadataLengthPrefix)?;
+                return metadata::fee_constants(metadata).map_err(FeeConstantsError::Metadata);
+            }
+            runtime_call::RuntimeCall::StorageGet(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key_nibbles = trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database_read(move |db| {
+                        db.block_storage_get(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(FeeConstantsError::from_storage_access)?;
+                let value = match value {
+                    Some((value, version)) => Some((
+                        value,
+                        runtime_call::TrieEntryVersion::try_from(version)
+                            .map_err(|_| FeeConstantsError::DatabaseCorrupted)?,
+                    )),
+                    None => None,
+                };
+
+                call = req.inject_value(
+                    value
+                        .as_ref()
+                        .map(|(value, version)| (iter::once(&value[..]), *version)),
+                );
+            }
+            runtime_call::RuntimeCall::ClosestDescendantMerkleValue(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+                let merkle_value = database
+                    .with_database_read(move |db| {
+                        db.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(FeeConstantsError::from_storage_access)?;
+
+                call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            runtime_call::RuntimeCall::NextKey(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+                let next_key_nibbles = database
+                    .with_database_read(move |db| {
+                        db.block_storage_next_key(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .map_err(FeeConstantsError::from_storage_access)?;
+
+                call = req.inject_key(
+                    next_key_nibbles
+                        .map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+            runtime_call::RuntimeCall::SignatureVerification(req) => {
+                call = req.verify_and_resume();
+            }
+            runtime_call::RuntimeCall::OffchainStorageSet(req) => {
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::LogEmit(req) => {
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::Offchain(_) => {
+                return Err(FeeConstantsError::ForbiddenHostFunction);
+            }
+        }
+    }
+}
+
+/// Returns the path, in nibbles, of the root of the given child trie within the main trie.
+fn child_trie_path(child_trie: &[u8]) -> Vec<u8> {
+    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+        .chain(trie::bytes_to_nibbles(child_trie.iter().copied()))
+        .map(u8::from)
+        .collect()
+}
+
+/// Error returned by [`fee_constants`].
+#[derive(Debug, derive_more::Display)]
+pub enum FeeConstantsError {
+    /// Requested block couldn't be found in the database.
+    UnknownBlock,
+    /// Storage of the requested block is no longer in the database.
+    StatePruned,
+    /// The runtime of the requested block is invalid.
+    InvalidRuntime,
+    /// Database is corrupted.
+    DatabaseCorrupted,
+    /// Error starting the runtime execution.
+    #[display(fmt = "{_0}")]
+    RuntimeStart(host::StartErr),
+    /// Error while executing the runtime.
+    #[display(fmt = "{_0}")]
+    RuntimeExecution(runtime_call::ErrorDetail),
+    /// Runtime has tried to call a host function that is forbidden when obtaining the metadata.
+    ForbiddenHostFunction,
+    /// The output of `Metadata_metadata` doesn't start with a valid length prefix.
+    #[display(fmt = "Invalid metadata length prefix: {_0}")]
+    MetadataLengthPrefix(methods::RemoveMetadataLengthPrefixError),
+    /// Failed to decode the metadata.
+    #[display(fmt = "Failed to decode the metadata: {_0}")]
+    Metadata(metadata::DecodeError),
+}
+
+impl FeeConstantsError {
+    fn from_storage_access(error: database_thread::StorageAccessError) -> Self {
+        match error {
+            database_thread::StorageAccessError::UnknownBlock
+            | database_thread::StorageAccessError::IncompleteStorage => {
+                FeeConstantsError::StatePruned
+            }
+            database_thread::StorageAccessError::Corrupted(_) => {
+                FeeConstantsError::DatabaseCorrupted
+            }
+        }
+    }
+}
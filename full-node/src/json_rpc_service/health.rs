@@ -0,0 +1,327 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Plain HTTP endpoints indicating the health of the node, destined to be used by load balancers
+//! and orchestration tools that don't speak JSON-RPC.
+//!
+//! - `GET /health` always returns a `200 OK` status code, and a JSON body describing the state
+//!   of the node.
+//! - `GET /ready` returns the same body, but with a `503 Service Unavailable` status code if the
+//!   node is still syncing or has no peer while it should.
+
+use crate::{consensus_service, network_service, LogCallback, LogLevel};
+
+use smol::{
+    future,
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::TcpListener,
+};
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Maximum number of bytes that are read from a socket while waiting for the end of the HTTP
+/// request line. If no `\r\n` has been received after this many bytes, the endpoint is
+/// determined from what has been received so far.
+pub const MAX_REQUEST_LINE_LEN: usize = 8192;
+
+/// Maximum time a client has to send its HTTP request line.
+pub const REQUEST_LINE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of simultaneous connections to the server started with [`run_server`].
+/// Connections beyond this limit are closed immediately.
+const MAX_SERVER_CONNECTIONS: usize = 32;
+
+/// Endpoint requested by an HTTP client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Health,
+    Ready,
+}
+
+/// Checks whether the beginning of an HTTP request targets one of the health endpoints.
+///
+/// Returns `None` if this isn't the case, for example if this is a WebSocket handshake.
+pub fn detect_endpoint(request_start: &[u8]) -> Option<Endpoint> {
+    let path = request_start.strip_prefix(b"GET ")?;
+    let path_end = path.iter().position(|c| *c == b' ' || *c == b'?')?;
+    match &path[..path_end] {
+        b"/health" => Some(Endpoint::Health),
+        b"/ready" => Some(Endpoint::Ready),
+        _ => None,
+    }
+}
+
+/// Reads from the socket until the end of the HTTP request line, the end of the stream, or
+/// [`MAX_REQUEST_LINE_LEN`] bytes have been received, whichever comes first.
+///
+/// The bytes that have been read are available through [`PeekedSocket::peeked`] and are
+/// returned again when reading from the [`PeekedSocket`], meaning that the socket can then be
+/// passed as is to, for example, a WebSocket server.
+///
+/// No timeout is applied. See [`REQUEST_LINE_TIMEOUT`].
+pub async fn read_request_line<S: AsyncRead + Unpin>(
+    mut socket: S,
+) -> Result<PeekedSocket<S>, io::Error> {
+    let mut buffer = vec![0; MAX_REQUEST_LINE_LEN];
+    let mut len = 0;
+
+    while len < buffer.len() {
+        let num_read = socket.read(&mut buffer[len..]).await?;
+        if num_read == 0 {
+            break;
+        }
+
+        // Only the newly-read bytes, plus the one before in case `\r` and `\n` have been
+        // received separately, need to be searched.
+        let search_start = len.saturating_sub(1);
+        len += num_read;
+        if buffer[search_start..len].windows(2).any(|w| w == b"\r\n") {
+            break;
+        }
+    }
+
+    buffer.truncate(len);
+    Ok(PeekedSocket {
+        inner: socket,
+        peeked: buffer,
+        read_cursor: 0,
+    })
+}
+
+/// Socket whose beginning has been read by [`read_request_line`].
+pub struct PeekedSocket<S> {
+    inner: S,
+    peeked: Vec<u8>,
+    /// Number of bytes of `peeked` that have been returned by the [`AsyncRead`] implementation.
+    read_cursor: usize,
+}
+
+impl<S> PeekedSocket<S> {
+    /// Returns the bytes that have been read by [`read_request_line`].
+    pub fn peeked(&self) -> &[u8] {
+        &self.peeked
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PeekedSocket<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        if this.read_cursor < this.peeked.len() {
+            let remaining = &this.peeked[this.read_cursor..];
+            let num_copied = remaining.len().min(buf.len());
+            buf[..num_copied].copy_from_slice(&remaining[..num_copied]);
+            this.read_cursor += num_copied;
+            return Poll::Ready(Ok(num_copied));
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PeekedSocket<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Source of the information reported by the health endpoints.
+pub struct HealthChecker {
+    /// Consensus service of the chain.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Access to the network, and identifier of the chain from the point of view of the network
+    /// service.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Whether the chain is a live network. If `false`, the node isn't expected to have peers.
+    pub chain_is_live: bool,
+}
+
+impl HealthChecker {
+    /// Writes the HTTP response to the given endpoint on the socket.
+    ///
+    /// The request itself isn't read. The connection is meant to be closed afterwards.
+    pub async fn respond(
+        &self,
+        endpoint: Endpoint,
+        mut socket: impl AsyncWrite + Unpin,
+    ) -> Result<(), io::Error> {
+        let ((sync_state, is_syncing), (peers, genesis_mismatches)) = future::zip(
+            future::zip(
+                self.consensus_service.sync_state(),
+                self.consensus_service.is_major_syncing_hint(),
+            ),
+            future::zip(
+                self.network_service.0.num_peers(self.network_service.1),
+                self.network_service
+                    .0
+                    .num_genesis_mismatches(self.network_service.1),
+            ),
+        )
+        .await;
+
+        let is_ready = !is_syncing && (peers != 0 || !self.chain_is_live);
+        let status_line = match endpoint {
+            Endpoint::Ready if !is_ready => "503 Service Unavailable",
+            Endpoint::Health | Endpoint::Ready => "200 OK",
+        };
+
+        let body = serde_json::json!({
+            "isSyncing": is_syncing,
+            "peers": peers,
+            "genesisMismatches": genesis_mismatches,
+            "shouldHavePeers": self.chain_is_live,
+            "bestBlockNumber": sync_state.best_block_number,
+            "bestBlockHash": format!("0x{}", hex::encode(sync_state.best_block_hash)),
+            "finalizedBlockNumber": sync_state.finalized_block_number,
+            "finalizedBlockHash": format!("0x{}", hex::encode(sync_state.finalized_block_hash)),
+        })
+        .to_string();
+
+        let response = format!(
+            "HTTP/1.1 {status_line}\r\nContent-Type: application/json\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+
+        socket.write_all(response.as_bytes()).await?;
+        socket.flush().await?;
+        Ok(())
+    }
+}
+
+/// Writes a `404 Not Found` HTTP response on the socket.
+pub async fn respond_not_found(mut socket: impl AsyncWrite + Unpin) -> Result<(), io::Error> {
+    socket
+        .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        .await?;
+    socket.flush().await?;
+    Ok(())
+}
+
+/// Accepts connections on the given TCP listener and serves the health endpoints on them, until
+/// `on_service_dropped` is notified.
+///
+/// At most [`MAX_SERVER_CONNECTIONS`] connections are served simultaneously.
+pub async fn run_server(
+    tcp_listener: TcpListener,
+    health_checker: Arc<HealthChecker>,
+    tasks_executor: Arc<dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync>,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+    mut on_service_dropped: event_listener::EventListener,
+) {
+    let num_connections = Arc::new(AtomicUsize::new(0));
+
+    loop {
+        let Some(accept_result) = future::or(
+            async {
+                (&mut on_service_dropped).await;
+                None
+            },
+            async { Some(tcp_listener.accept().await) },
+        )
+        .await
+        else {
+            return;
+        };
+
+        let (tcp_socket, address) = match accept_result {
+            Ok(v) => v,
+            Err(error) => {
+                // See the equivalent situation in the JSON-RPC server.
+                log_callback.log(
+                    LogLevel::Warn,
+                    format!("health-tcp-listener-error; error={error}"),
+                );
+                smol::Timer::after(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+
+        // Reject the socket without sending back anything if the limit is reached, in order to
+        // not allocate any resource for it.
+        if num_connections
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |old_value| {
+                (old_value < MAX_SERVER_CONNECTIONS).then_some(old_value + 1)
+            })
+            .is_err()
+        {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("health-incoming-connection-rejected; address={address}"),
+            );
+            smol::Timer::after(Duration::from_millis(50)).await;
+            continue;
+        }
+
+        let health_checker = health_checker.clone();
+        let log_callback = log_callback.clone();
+        let num_connections = num_connections.clone();
+        tasks_executor(Box::pin(async move {
+            let serve = async {
+                let mut socket = read_request_line(tcp_socket).await?;
+                match detect_endpoint(socket.peeked()) {
+                    Some(endpoint) => health_checker.respond(endpoint, &mut socket).await,
+                    None => respond_not_found(&mut socket).await,
+                }
+            };
+
+            // Clients that don't send their request in a timely manner are dropped.
+            let timeout = async {
+                smol::Timer::after(REQUEST_LINE_TIMEOUT).await;
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            };
+
+            if let Err(error) = future::or(serve, timeout).await {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("health-connection-error; address={address}; error={error}"),
+                );
+            }
+
+            num_connections.fetch_sub(1, Ordering::Release);
+        }));
+    }
+}
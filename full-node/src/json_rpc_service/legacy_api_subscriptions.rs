@@ -745,7 +745,7 @@ impl SubscribeStorage {
 
                 let (key, result) = self
                     .database
-                    .with_database(move |database| {
+                    .with_database_read(move |database| {
                         let result = database.block_storage_get(
                             &best_block_hash,
                             iter::empty::<iter::Empty<_>>(),
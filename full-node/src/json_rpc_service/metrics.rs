@@ -0,0 +1,186 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Per-method statistics about the JSON-RPC requests.
+//!
+//! The statistics are gathered by looking at the serialized requests and responses exchanged
+//! with the clients, in other words independently of how each request is processed. A request
+//! is considered finished when its response is generated, which, for subscriptions, is when the
+//! subscription starts.
+
+use crate::{LogCallback, LogLevel};
+
+use smoldot::json_rpc::{methods, parse};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the buckets of the latency histograms of [`JsonRpcMethodMetrics`].
+pub const LATENCY_BUCKETS: [Duration; 8] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+/// Name under which the calls to methods that aren't supported are grouped.
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// Statistics about one JSON-RPC method.
+#[derive(Debug, Clone)]
+pub struct JsonRpcMethodMetrics {
+    /// Name of the JSON-RPC method. Calls to methods that aren't supported are grouped under
+    /// the name `unknown`.
+    pub method: &'static str,
+    /// Number of calls whose response has been generated.
+    pub num_calls: u64,
+    /// Number of calls whose response is an error. Always inferior or equal to
+    /// [`JsonRpcMethodMetrics::num_calls`].
+    pub num_errors: u64,
+    /// Sum of the latencies of all the calls.
+    pub total_latency: Duration,
+    /// Number of calls per latency bucket. Entry `n` contains the number of calls whose latency
+    /// is inferior or equal to `LATENCY_BUCKETS[n]` and, if `n != 0`, strictly superior to
+    /// `LATENCY_BUCKETS[n - 1]`. The last entry contains the calls slower than all the buckets.
+    pub latency_histogram: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// Statistics shared between all the clients of a JSON-RPC service.
+pub struct Metrics {
+    /// Statistics of each method, in no specific order. Methods that have never been called are
+    /// absent.
+    methods: Mutex<Vec<JsonRpcMethodMetrics>>,
+}
+
+impl Metrics {
+    /// Creates a new empty [`Metrics`].
+    pub fn new() -> Self {
+        Metrics {
+            methods: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the statistics of all the methods that have been called at least once.
+    pub fn snapshot(&self) -> Vec<JsonRpcMethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    fn record(&self, method: &'static str, latency: Duration, is_error: bool) {
+        let mut methods = self.methods.lock().unwrap();
+        let entry = match methods.iter().position(|m| m.method == method) {
+            Some(position) => &mut methods[position],
+            None => {
+                methods.push(JsonRpcMethodMetrics {
+                    method,
+                    num_calls: 0,
+                    num_errors: 0,
+                    total_latency: Duration::new(0, 0),
+                    latency_histogram: [0; LATENCY_BUCKETS.len() + 1],
+                });
+                methods.last_mut().unwrap()
+            }
+        };
+
+        entry.num_calls += 1;
+        if is_error {
+            entry.num_errors += 1;
+        }
+        entry.total_latency += latency;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        entry.latency_histogram[bucket] += 1;
+    }
+}
+
+/// Requests of a single client whose response hasn't been generated yet.
+pub struct InFlightRequests {
+    /// JSON-formatted identifier of each request, the method being called, and when the request
+    /// has been received.
+    requests: Vec<(String, &'static str, Instant)>,
+}
+
+impl InFlightRequests {
+    /// Creates a new empty [`InFlightRequests`].
+    pub fn new() -> Self {
+        InFlightRequests {
+            requests: Vec::new(),
+        }
+    }
+
+    /// Must be called when a request is about to be processed.
+    pub fn on_request(&mut self, request: &str) {
+        // Notifications and requests that can't be parsed don't receive any response that
+        // could be matched with them, and are thus ignored.
+        let Ok(parse::Request {
+            id_json: Some(id_json),
+            method,
+            ..
+        }) = parse::parse_request(request)
+        else {
+            return;
+        };
+
+        let method = methods::MethodCall::method_names()
+            .find(|m| *m == method)
+            .unwrap_or(UNKNOWN_METHOD);
+
+        // If the client re-uses the identifier of a request that is still in progress, it is
+        // impossible to know which response corresponds to which request.
+        self.requests.retain(|(id, _, _)| *id != id_json);
+        self.requests
+            .push((id_json.to_owned(), method, Instant::now()));
+    }
+
+    /// Must be called when a response or notification is generated. Records the statistics
+    /// of the corresponding request, if any.
+    pub fn on_response(
+        &mut self,
+        response: &str,
+        metrics: &Metrics,
+        log_callback: &(dyn LogCallback + Send + Sync),
+    ) {
+        let (id_json, is_error) = match parse::parse_response(response) {
+            Ok(parse::Response::Success { id_json, .. }) => (id_json, false),
+            Ok(parse::Response::Error { id_json, .. }) => (id_json, true),
+            // Notifications fail to parse as responses.
+            Ok(parse::Response::ParseError { .. }) | Err(_) => return,
+        };
+
+        let Some(position) = self.requests.iter().position(|(id, _, _)| id == id_json) else {
+            return;
+        };
+        let (_, method, start) = self.requests.swap_remove(position);
+        let latency = start.elapsed();
+
+        log_callback.log(
+            LogLevel::Trace,
+            format!(
+                "json-rpc-request-finished; method={method}; duration={latency:?}; success={:?}",
+                !is_error
+            ),
+        );
+
+        metrics.record(method, latency, is_error);
+    }
+}
@@ -18,8 +18,14 @@
 use futures_lite::future;
 use smol::stream::StreamExt as _;
 use smoldot::{
+    chain::chain_information,
     executor,
+    identity::ss58,
+    informant::HashDisplay,
     json_rpc::{methods, parse, service},
+    libp2p::{multiaddr, PeerId},
+    network::codec,
+    transactions::validate,
     trie,
 };
 use std::{
@@ -31,10 +37,27 @@ use std::{
 
 use crate::{
     consensus_service, database_thread,
-    json_rpc_service::{legacy_api_subscriptions, runtime_caches_service},
-    network_service, LogCallback, LogLevel,
+    json_rpc_service::{
+        account_nonce, block_authorities, block_tracing, legacy_api_subscriptions,
+        subscriptions_multiplexer, transactions,
+    },
+    network_service, runtime_caches_service, runtime_calls_limiter, transactions_pool, LogCallback,
+    LogLevel,
 };
 
+/// Maximum number of keys that can be passed to `state_queryStorage` and `state_queryStorageAt`.
+/// Requests with more keys are refused with an "invalid parameters" error.
+const MAX_QUERY_STORAGE_KEYS: usize = 1024;
+
+/// Maximum number of blocks, including both ends, in the range passed to `state_queryStorage`.
+/// Requests whose range is larger are refused with an "invalid parameters" error, exactly like
+/// requests where the first block isn't an ancestor of the last block.
+const MAX_QUERY_STORAGE_BLOCKS: usize = 1000;
+
+/// Number of blocks whose storage is read by `state_queryStorage` in a single database access.
+/// The other users of the database can access it between two chunks.
+const QUERY_STORAGE_BLOCKS_PER_CHUNK: usize = 32;
+
 pub struct Config {
     /// Function that can be used to spawn background tasks.
     ///
@@ -65,6 +88,9 @@ pub struct Config {
     /// JSON-encoded properties of the chain, as found in the chain specification.
     pub chain_properties_json: String,
 
+    /// Prefix of the SS58 addresses of the chain.
+    pub chain_ss58_prefix: ss58::ChainPrefix,
+
     /// Whether the chain is a live network. Found in the chain specification.
     pub chain_is_live: bool,
 
@@ -72,11 +98,27 @@ pub struct Config {
     // TODO: load from database maybe?
     pub genesis_block_hash: [u8; 32],
 
+    /// Information about the genesis block.
+    pub genesis_chain_information: Arc<chain_information::ValidChainInformation>,
+
     /// Consensus service of the chain.
     pub consensus_service: Arc<consensus_service::ConsensusService>,
 
     /// Runtime caches service of the JSON-RPC service.
     pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
+    /// Limiter that runtime executions must go through.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// If `Some`, the headers subscriptions are served through this multiplexer rather than
+    /// through a dedicated subscription to the consensus service.
+    pub subscriptions_multiplexer: Option<Arc<subscriptions_multiplexer::SubscriptionsMultiplexer>>,
+
+    /// Transactions recently found to be invalid, shared between all the requests handlers.
+    pub banned_transactions: Arc<transactions::BannedTransactions>,
+
+    /// Pool where the transactions submitted through the JSON-RPC server are inserted.
+    pub transactions_pool: Arc<transactions_pool::TransactionsPool>,
 }
 
 pub enum Message {
@@ -115,6 +157,101 @@ pub fn spawn_requests_handler(config: Config) {
                         ));
                     }
 
+                    methods::MethodCall::author_submitExtrinsic { transaction } => {
+                        let hash: [u8; 32] = blake2_rfc::blake2b::blake2b(32, &[], &transaction.0)
+                            .as_bytes()
+                            .try_into()
+                            .unwrap();
+
+                        // Error code identical to the one of Substrate.
+                        if config.banned_transactions.is_banned(&hash) {
+                            request.fail(service::ErrorResponse::ServerError(
+                                1012,
+                                "Transaction is temporarily banned",
+                            ));
+                            continue;
+                        }
+
+                        match transactions::validate_transaction(
+                            &config.database,
+                            &config.runtime_caches_service,
+                            &config.runtime_calls_limiter,
+                            &transaction.0,
+                        )
+                        .await
+                        {
+                            Ok(validity) => {
+                                let propagate = validity.propagate;
+
+                                // The transaction is included in the blocks authored by the node,
+                                // and announced to the peers in order for them to include it in
+                                // theirs. Error codes identical to the ones of Substrate.
+                                match config.transactions_pool.insert(
+                                    transaction.0.clone(),
+                                    validity,
+                                    validate::TransactionSource::External,
+                                ) {
+                                    Ok(()) => {}
+                                    Err(transactions_pool::InsertError::AlreadyInPool) => {
+                                        request.fail(service::ErrorResponse::ServerError(
+                                            1013,
+                                            "Transaction Already Imported",
+                                        ));
+                                        continue;
+                                    }
+                                    Err(
+                                        transactions_pool::InsertError::PoolFull
+                                        | transactions_pool::InsertError::SubmitterLimitReached,
+                                    ) => {
+                                        request.fail(service::ErrorResponse::ServerError(
+                                            1016,
+                                            "Immediately Dropped",
+                                        ));
+                                        continue;
+                                    }
+                                }
+
+                                if propagate {
+                                    config
+                                        .network_service
+                                        .0
+                                        .announce_transaction(
+                                            config.network_service.1,
+                                            transaction.0.clone(),
+                                        )
+                                        .await;
+                                }
+
+                                request.respond(methods::Response::author_submitExtrinsic(
+                                    methods::HashHexString(hash),
+                                ));
+                            }
+                            // Error codes identical to the ones of Substrate.
+                            Err(transactions::ValidateError::Invalid(
+                                error @ validate::TransactionValidityError::Invalid(_),
+                            )) => {
+                                config.banned_transactions.ban(hash);
+                                request.fail(service::ErrorResponse::ServerError(
+                                    1010,
+                                    &error.to_string(),
+                                ));
+                            }
+                            Err(transactions::ValidateError::Invalid(
+                                error @ validate::TransactionValidityError::Unknown(_),
+                            )) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    1011,
+                                    &error.to_string(),
+                                ));
+                            }
+                            Err(error) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    &error.to_string(),
+                                ));
+                            }
+                        }
+                    }
                     methods::MethodCall::chain_getBlockHash { height: Some(0) } => {
                         // In the case where the database was populated through a warp sync, it
                         // might not store block 0 in it. However, the hash of block 0 is
@@ -128,7 +265,7 @@ pub fn spawn_requests_handler(config: Config) {
                     methods::MethodCall::chain_getBlockHash { height } => {
                         let outcome = config
                             .database
-                            .with_database(move |database| match height {
+                            .with_database_read(move |database| match height {
                                 Some(height) => database.best_block_hash_by_number(height),
                                 None => database.best_block_hash().map(Some),
                             })
@@ -149,7 +286,7 @@ pub fn spawn_requests_handler(config: Config) {
                             Some(h) => h.0,
                             None => match config
                                 .database
-                                .with_database(|db| db.best_block_hash())
+                                .with_database_read(|db| db.best_block_hash())
                                 .await
                             {
                                 Ok(b) => b,
@@ -162,7 +299,7 @@ pub fn spawn_requests_handler(config: Config) {
 
                         let result = config
                             .database
-                            .with_database(move |db| db.block_scale_encoded_header(&hash))
+                            .with_database_read(move |db| db.block_scale_encoded_header(&hash))
                             .await;
 
                         match result {
@@ -207,69 +344,46 @@ pub fn spawn_requests_handler(config: Config) {
                                 .map(u8::from)
                                 .collect()
                         });
-                        let mut start_key_nibbles = start_key.map_or(Vec::new(), |p| {
+                        let start_key_nibbles = start_key.map_or(Vec::new(), |p| {
                             trie::bytes_to_nibbles(p.0.iter().copied())
                                 .map(u8::from)
                                 .collect()
                         });
 
-                        // There's a difference of semantics between `state_getKeysPaged` and
-                        // the database query we perform below in the situation where `start_key`
-                        // isn't within `prefix`: the database request will return nothing while
-                        // the JSON-RPC request expects the first key within `prefix`. As such,
-                        // we adjust the start key if necessary.
-                        // TODO: add documentation and a test in the database code regarding this behavior
-                        if start_key_nibbles < prefix_nibbles {
-                            start_key_nibbles = prefix_nibbles.clone();
-                        }
-
                         // Continue in the background.
+                        // Note that if `start_key` is inferior to `prefix`, the iteration starts
+                        // at the first key within `prefix`.
                         let result = config
                             .database
-                            .with_database(
+                            .with_database_read(
                                 move |db| -> Result<_, database_thread::StorageAccessError> {
                                     let hash = match hash {
                                         Some(h) => h.0,
                                         None => db.best_block_hash()?,
                                     };
 
-                                    let mut out =
-                                        Vec::with_capacity(usize::try_from(count).unwrap());
-
-                                    let mut key_iter = start_key_nibbles;
-
-                                    // The query is performed by repeatedly asking for the next
-                                    // key.
-                                    while out.len() < usize::try_from(count).unwrap() {
-                                        let next_key_nibbles = db.block_storage_next_key(
-                                            &hash,
-                                            iter::empty::<iter::Empty<_>>(),
-                                            key_iter.iter().copied(),
-                                            prefix_nibbles.iter().copied(),
-                                            false,
-                                        )?;
-
-                                        let Some(next_key_nibbles) = next_key_nibbles else {
-                                            break;
-                                        };
-
-                                        out.push(methods::HexString(
-                                            trie::nibbles_to_bytes_truncate(
-                                                next_key_nibbles
-                                                    .iter()
-                                                    .copied()
-                                                    .map(|n| trie::Nibble::try_from(n).unwrap()),
-                                            )
-                                            .collect::<Vec<_>>(),
-                                        ));
-
-                                        // Push an extra nibble as otherwise `block_storage_next_key`
-                                        // will return the same key again.
-                                        key_iter = next_key_nibbles;
-                                        key_iter.push(0);
-                                    }
+                                    let entries = db.block_storage_entries(
+                                        &hash,
+                                        iter::empty::<iter::Empty<_>>(),
+                                        prefix_nibbles.iter().copied(),
+                                        start_key_nibbles.iter().copied(),
+                                        usize::try_from(count).unwrap(),
+                                        false,
+                                    )?;
 
-                                    Ok(out)
+                                    Ok(entries
+                                        .into_iter()
+                                        .map(|entry| {
+                                            methods::HexString(
+                                                trie::nibbles_to_bytes_truncate(
+                                                    entry.key_nibbles.into_iter().map(|n| {
+                                                        trie::Nibble::try_from(n).unwrap()
+                                                    }),
+                                                )
+                                                .collect::<Vec<_>>(),
+                                            )
+                                        })
+                                        .collect::<Vec<_>>())
                                 },
                             )
                             .await;
@@ -295,7 +409,7 @@ pub fn spawn_requests_handler(config: Config) {
                             Some(h) => h.0,
                             None => match config
                                 .database
-                                .with_database(|db| db.best_block_hash())
+                                .with_database_read(|db| db.best_block_hash())
                                 .await
                             {
                                 Ok(b) => b,
@@ -322,6 +436,7 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         };
 
+                        let _permit = config.runtime_calls_limiter.json_rpc_permit().await;
                         let mut call =
                             match executor::runtime_call::run(executor::runtime_call::Config {
                                 virtual_machine: runtime,
@@ -371,7 +486,7 @@ pub fn spawn_requests_handler(config: Config) {
                                             .collect::<Vec<_>>();
                                     let value = config
                                         .database
-                                        .with_database(move |db| {
+                                        .with_database_read(move |db| {
                                             db.block_storage_get(
                                                 &hash,
                                                 parent_paths.into_iter().map(|p| p.into_iter()),
@@ -408,7 +523,7 @@ pub fn spawn_requests_handler(config: Config) {
 
                                     let merkle_value = config
                                         .database
-                                        .with_database(move |db| {
+                                        .with_database_read(move |db| {
                                             db.block_storage_closest_descendant_merkle_value(
                                                 &hash,
                                                 parent_paths.into_iter().map(|p| p.into_iter()),
@@ -447,7 +562,7 @@ pub fn spawn_requests_handler(config: Config) {
                                     let branch_nodes = req.branch_nodes();
                                     let next_key = config
                                         .database
-                                        .with_database(move |db| {
+                                        .with_database_read(move |db| {
                                             db.block_storage_next_key(
                                                 &hash,
                                                 parent_paths.into_iter().map(|p| p.into_iter()),
@@ -489,7 +604,7 @@ pub fn spawn_requests_handler(config: Config) {
                             Some(h) => h.0,
                             None => match config
                                 .database
-                                .with_database(|db| db.best_block_hash())
+                                .with_database_read(|db| db.best_block_hash())
                                 .await
                             {
                                 Ok(b) => b,
@@ -518,8 +633,151 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::state_queryStorage {
+                        keys,
+                        from_block,
+                        to_block,
+                    } => {
+                        if keys.len() > MAX_QUERY_STORAGE_KEYS {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        }
+
+                        // Convert the list of keys into a format suitable for the database.
+                        let keys_nibbles = keys
+                            .iter()
+                            .map(|key| {
+                                trie::bytes_to_nibbles(key.0.iter().copied())
+                                    .map(u8::from)
+                                    .collect::<Vec<_>>()
+                            })
+                            .collect::<Vec<_>>();
+                        let keys = Arc::new((keys, keys_nibbles));
+
+                        // Walk the chain backwards from `to_block` until `from_block` is found.
+                        // `None` is returned if `from_block` isn't an ancestor of `to_block` or
+                        // if the range is too large.
+                        let blocks: Result<
+                            Option<Vec<[u8; 32]>>,
+                            database_thread::StorageAccessError,
+                        > = config
+                            .database
+                            .with_database_read(move |db| {
+                                let to_block = match to_block {
+                                    Some(h) => h.0,
+                                    None => db.best_block_hash()?,
+                                };
+
+                                let mut blocks = vec![to_block];
+                                while *blocks.last().unwrap() != from_block.0 {
+                                    if blocks.len() >= MAX_QUERY_STORAGE_BLOCKS {
+                                        return Ok(None);
+                                    }
+
+                                    let parent = db
+                                        .block_parent(blocks.last().unwrap())?
+                                        .ok_or(database_thread::StorageAccessError::UnknownBlock)?;
+                                    if parent == [0; 32] {
+                                        return Ok(None);
+                                    }
+                                    blocks.push(parent);
+                                }
+
+                                blocks.reverse();
+                                Ok(Some(blocks))
+                            })
+                            .await;
+
+                        // The storage of the blocks is then read in chunks, in order to not
+                        // monopolize the database for the entire duration of the request.
+                        let result: Result<_, database_thread::StorageAccessError> = async {
+                            let Some(blocks) = blocks? else {
+                                return Ok(None);
+                            };
+
+                            // Value of each key at the block that was last iterated over.
+                            // Initially set to `None`, so that all the keys are reported as
+                            // part of the changes of `from_block`, even if they have no value.
+                            let mut previous_values = vec![None::<Option<Vec<u8>>>; keys.0.len()];
+
+                            let mut out = Vec::new();
+                            for chunk in blocks.chunks(QUERY_STORAGE_BLOCKS_PER_CHUNK) {
+                                let chunk = chunk.to_vec();
+                                let keys = keys.clone();
+                                let (chunk_out, new_previous_values) = config
+                                    .database
+                                    .with_database_read(move |db| {
+                                        let (keys, keys_nibbles) = &*keys;
+                                        let mut out = Vec::new();
+
+                                        for block in chunk {
+                                            let mut changes = methods::StorageChangeSet {
+                                                block: methods::HashHexString(block),
+                                                changes: Vec::new(),
+                                            };
+
+                                            for ((key_nibbles, key), previous_value) in keys_nibbles
+                                                .iter()
+                                                .zip(keys.iter())
+                                                .zip(previous_values.iter_mut())
+                                            {
+                                                let value = db
+                                                    .block_storage_get(
+                                                        &block,
+                                                        iter::empty::<iter::Empty<_>>(),
+                                                        key_nibbles.iter().copied(),
+                                                    )?
+                                                    .map(|(v, _)| v);
+
+                                                if previous_value.as_ref() != Some(&value) {
+                                                    changes.changes.push((
+                                                        key.clone(),
+                                                        value.clone().map(methods::HexString),
+                                                    ));
+                                                    *previous_value = Some(value);
+                                                }
+                                            }
+
+                                            if !changes.changes.is_empty() {
+                                                out.push(changes);
+                                            }
+                                        }
+
+                                        Ok::<_, database_thread::StorageAccessError>((
+                                            out,
+                                            previous_values,
+                                        ))
+                                    })
+                                    .await?;
+
+                                out.extend(chunk_out);
+                                previous_values = new_previous_values;
+                            }
+
+                            Ok(Some(out))
+                        }
+                        .await;
+
+                        // Send back the response.
+                        match result {
+                            Ok(Some(out)) => {
+                                request.respond(methods::Response::state_queryStorage(out));
+                            }
+                            Ok(None)
+                            | Err(database_thread::StorageAccessError::IncompleteStorage)
+                            | Err(database_thread::StorageAccessError::UnknownBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
                     methods::MethodCall::state_queryStorageAt { keys, at } => {
-                        // TODO: add a limit to the number of keys?
+                        if keys.len() > MAX_QUERY_STORAGE_KEYS {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        }
 
                         // Convert the list of keys into a format suitable for the database.
                         let keys_nibbles = keys
@@ -534,7 +792,7 @@ pub fn spawn_requests_handler(config: Config) {
                         // The bulk of the request is performed in the database thread.
                         let result = config
                             .database
-                            .with_database(move |db| {
+                            .with_database_read(move |db| {
                                 let at = match at {
                                     Some(h) => h.0,
                                     None => db.best_block_hash()?,
@@ -601,6 +859,316 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::state_traceBlock {
+                        block,
+                        targets,
+                        storage_keys,
+                        methods: methods_filter,
+                    } => {
+                        let split_list = |list: Option<&str>| {
+                            list.map(|list| {
+                                list.split(',')
+                                    .map(|item| item.trim().to_owned())
+                                    .filter(|item| !item.is_empty())
+                                    .collect::<Vec<_>>()
+                            })
+                        };
+                        let targets = split_list(targets.as_deref());
+                        let methods_filter = split_list(methods_filter.as_deref());
+                        let storage_keys = match split_list(storage_keys.as_deref())
+                            .map(|keys| {
+                                keys.iter()
+                                    .map(|key| hex::decode(key.trim_start_matches("0x")))
+                                    .collect::<Result<Vec<_>, _>>()
+                            })
+                            .transpose()
+                        {
+                            Ok(keys) => keys,
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                                continue;
+                            }
+                        };
+
+                        let trace = match block_tracing::trace_block(
+                            &config.database,
+                            &config.runtime_caches_service,
+                            &config.runtime_calls_limiter,
+                            config.consensus_service.block_number_bytes(),
+                            block.0,
+                        )
+                        .await
+                        {
+                            Ok(trace) => trace,
+                            Err(block_tracing::TraceBlockError::UnknownBlock)
+                            | Err(block_tracing::TraceBlockError::GenesisBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                                continue;
+                            }
+                            Err(error) => {
+                                config.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "json-rpc; request=state_traceBlock; block={}; error={}",
+                                        HashDisplay(&block.0),
+                                        error
+                                    ),
+                                );
+                                let message = error.to_string();
+                                request.fail(service::ErrorResponse::ServerError(-32000, &message));
+                                continue;
+                            }
+                        };
+
+                        let events = trace
+                            .events
+                            .into_iter()
+                            .filter(|event| {
+                                block_trace_event_matches(
+                                    event,
+                                    targets.as_deref(),
+                                    storage_keys.as_deref(),
+                                    methods_filter.as_deref(),
+                                )
+                            })
+                            .map(convert_block_trace_event)
+                            .collect();
+
+                        request.respond(methods::Response::state_traceBlock(methods::BlockTrace {
+                            block_hash: methods::HashHexString(trace.block_hash),
+                            parent_hash: methods::HashHexString(trace.parent_hash),
+                            events,
+                        }));
+                    }
+                    methods::MethodCall::sudo_unstable_blockExecutionProfile { hash } => {
+                        let profile = config
+                            .consensus_service
+                            .block_execution_profile(hash.0)
+                            .await
+                            .map(|profile| {
+                                let convert_step =
+                                    |step: consensus_service::ExecutionStepProfile| {
+                                        methods::ExecutionStepProfile {
+                                            duration_micros: u64::try_from(
+                                                step.duration.as_micros(),
+                                            )
+                                            .unwrap_or(u64::MAX),
+                                            database_accesses_duration_micros: u64::try_from(
+                                                step.database_accesses_duration.as_micros(),
+                                            )
+                                            .unwrap_or(u64::MAX),
+                                            proof_size: step.proof_size,
+                                        }
+                                    };
+
+                                methods::BlockExecutionProfile {
+                                    block_hash: methods::HashHexString(profile.block_hash),
+                                    block_number: profile.block_number,
+                                    initialization: convert_step(profile.initialization),
+                                    extrinsics: profile
+                                        .extrinsics
+                                        .into_iter()
+                                        .map(convert_step)
+                                        .collect(),
+                                    finalization: convert_step(profile.finalization),
+                                }
+                            });
+
+                        request.respond(methods::Response::sudo_unstable_blockExecutionProfile(
+                            profile,
+                        ));
+                    }
+                    methods::MethodCall::sudo_unstable_databaseStatistics {} => {
+                        let statistics = match config
+                            .database
+                            .with_database_read(|db| db.statistics())
+                            .await
+                        {
+                            Ok(statistics) => statistics,
+                            Err(error) => {
+                                config.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "json-rpc; request=sudo_unstable_databaseStatistics; database_error={}",
+                                        error
+                                    ),
+                                );
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        request.respond(methods::Response::sudo_unstable_databaseStatistics(
+                            methods::DatabaseStatistics {
+                                tables: statistics
+                                    .tables
+                                    .into_iter()
+                                    .map(|table| methods::DatabaseTableStatistics {
+                                        name: table.name.to_owned(),
+                                        num_rows: table.num_rows,
+                                        size_bytes: table.size_bytes,
+                                    })
+                                    .collect(),
+                                finalized_block_number: statistics.finalized_block_number,
+                                oldest_block_with_state: statistics.oldest_block_with_state,
+                                oldest_block_with_body: statistics.oldest_block_with_body,
+                                num_justifications: statistics.num_justifications,
+                            },
+                        ));
+                    }
+                    methods::MethodCall::sudo_unstable_forceFinalize { hash } => {
+                        match config.consensus_service.force_finalize(hash.0).await {
+                            Ok(()) => {
+                                request.respond(methods::Response::sudo_unstable_forceFinalize(()));
+                            }
+                            Err(consensus_service::ForceFinalizeError::UnknownBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                        }
+                    }
+                    methods::MethodCall::sudo_unstable_setAuthoringEnabled { enabled } => {
+                        config
+                            .consensus_service
+                            .set_authoring_enabled(enabled)
+                            .await;
+                        request.respond(methods::Response::sudo_unstable_setAuthoringEnabled(()));
+                    }
+                    methods::MethodCall::sudo_unstable_storageDiff { old_hash, new_hash } => {
+                        let result = config
+                            .database
+                            .with_database_read(move |db| {
+                                db.block_storage_diff(&old_hash.0, &new_hash.0)
+                            })
+                            .await;
+
+                        match result {
+                            Ok(diff) => {
+                                request.respond(methods::Response::sudo_unstable_storageDiff(
+                                    diff.into_iter()
+                                        .map(|entry| methods::StorageDiffItem {
+                                            key: methods::HexString(
+                                                trie::nibbles_to_bytes_truncate(
+                                                    entry.key_nibbles.into_iter().map(|n| {
+                                                        trie::Nibble::try_from(n).unwrap()
+                                                    }),
+                                                )
+                                                .collect(),
+                                            ),
+                                            old_value: entry
+                                                .old_value
+                                                .map(|(value, _)| methods::HexString(value)),
+                                            new_value: entry
+                                                .new_value
+                                                .map(|(value, _)| methods::HexString(value)),
+                                        })
+                                        .collect(),
+                                ));
+                            }
+                            Err(database_thread::StorageAccessError::IncompleteStorage)
+                            | Err(database_thread::StorageAccessError::UnknownBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
+                    methods::MethodCall::sudo_unstable_blockAuthorities { hash } => {
+                        let authorities = match block_authorities::block_authorities(
+                            &config.database,
+                            &config.genesis_chain_information,
+                            config.consensus_service.block_number_bytes(),
+                            hash.0,
+                        )
+                        .await
+                        {
+                            Ok(authorities) => authorities,
+                            Err(block_authorities::BlockAuthoritiesError::UnknownBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                                continue;
+                            }
+                            Err(error) => {
+                                config.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "json-rpc; request=sudo_unstable_blockAuthorities; block={}; error={}",
+                                        HashDisplay(&hash.0),
+                                        error
+                                    ),
+                                );
+                                let message = error.to_string();
+                                request.fail(service::ErrorResponse::ServerError(-32000, &message));
+                                continue;
+                            }
+                        };
+
+                        request.respond(methods::Response::sudo_unstable_blockAuthorities(
+                            methods::BlockAuthorities {
+                                block_hash: hash,
+                                block_number: authorities.block_number,
+                                grandpa: authorities.grandpa.map(|(set_id, list)| {
+                                    methods::GrandpaAuthoritySet {
+                                        set_id,
+                                        authorities: list
+                                            .into_iter()
+                                            .map(|authority| methods::WeightedAuthority {
+                                                public_key: methods::HashHexString(
+                                                    authority.public_key,
+                                                ),
+                                                address: ss58::encode(ss58::Decoded {
+                                                    chain_prefix: config.chain_ss58_prefix,
+                                                    public_key: authority.public_key,
+                                                }),
+                                                weight: authority.weight.get(),
+                                            })
+                                            .collect(),
+                                    }
+                                }),
+                                babe: authorities.babe.map(|(epoch_index, epoch)| {
+                                    methods::BabeEpoch {
+                                        epoch_index,
+                                        start_slot: epoch.start_slot_number,
+                                        authorities: epoch
+                                            .authorities
+                                            .into_iter()
+                                            .map(|authority| methods::WeightedAuthority {
+                                                public_key: methods::HashHexString(
+                                                    authority.public_key,
+                                                ),
+                                                address: ss58::encode(ss58::Decoded {
+                                                    chain_prefix: config.chain_ss58_prefix,
+                                                    public_key: authority.public_key,
+                                                }),
+                                                weight: authority.weight,
+                                            })
+                                            .collect(),
+                                        randomness: methods::HashHexString(epoch.randomness),
+                                    }
+                                }),
+                            },
+                        ));
+                    }
+                    methods::MethodCall::system_accountNextIndex { account } => {
+                        match account_nonce::account_next_index(
+                            &config.database,
+                            &config.runtime_caches_service,
+                            &config.runtime_calls_limiter,
+                            &config.transactions_pool,
+                            &account.0,
+                        )
+                        .await
+                        {
+                            Ok(index) => {
+                                request.respond(methods::Response::system_accountNextIndex(index));
+                            }
+                            Err(error) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    &error.to_string(),
+                                ));
+                            }
+                        }
+                    }
                     methods::MethodCall::system_chain {} => {
                         request
                             .respond(methods::Response::system_chain((&config.chain_name).into()));
@@ -610,6 +1178,70 @@ pub fn spawn_requests_handler(config: Config) {
                             (&config.chain_type).into(),
                         ));
                     }
+                    methods::MethodCall::system_dryRun { extrinsic, at } => {
+                        let at = match at {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database_read(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        let runtime = match config.runtime_caches_service.get(at).await {
+                            Ok(runtime) => (*runtime).clone(),
+                            Err(runtime_caches_service::GetError::UnknownBlock)
+                            | Err(runtime_caches_service::GetError::Pruned) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                                continue;
+                            }
+                            Err(runtime_caches_service::GetError::InvalidRuntime(_))
+                            | Err(runtime_caches_service::GetError::NoCode)
+                            | Err(runtime_caches_service::GetError::InvalidHeapPages)
+                            | Err(runtime_caches_service::GetError::CorruptedDatabase) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        // Similar to what Substrate does, the extrinsic is applied directly on top
+                        // of the state of the block, without initializing a new block first.
+                        let _permit = config.runtime_calls_limiter.json_rpc_permit().await;
+                        match consensus_service::runtime_call(
+                            &config.database,
+                            &at,
+                            runtime,
+                            "BlockBuilder_apply_extrinsic",
+                            &extrinsic.0,
+                            executor::runtime_call::StorageProofSizeBehavior::Unimplemented,
+                            executor::runtime_call::StorageChanges::empty(),
+                            false,
+                        )
+                        .await
+                        {
+                            Ok(success) => {
+                                request.respond(methods::Response::system_dryRun(
+                                    methods::HexString(success.output),
+                                ));
+                            }
+                            Err(consensus_service::RuntimeCallError::DatabaseParentAccess(
+                                database_thread::StorageAccessError::IncompleteStorage
+                                | database_thread::StorageAccessError::UnknownBlock,
+                            )) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(error) => {
+                                let message = error.to_string();
+                                request.fail(service::ErrorResponse::ServerError(-32000, &message));
+                            }
+                        }
+                    }
                     methods::MethodCall::system_health {} => {
                         let (is_syncing, peers) = future::zip(
                             config.consensus_service.is_major_syncing_hint(),
@@ -623,6 +1255,28 @@ pub fn spawn_requests_handler(config: Config) {
                             should_have_peers: config.chain_is_live,
                         }));
                     }
+                    methods::MethodCall::system_addReservedPeer { peer } => {
+                        let Ok(mut address) = peer.parse::<multiaddr::Multiaddr>() else {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        };
+                        let Some(multiaddr::Protocol::P2p(peer_id)) = address.iter().last() else {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        };
+                        let Ok(peer_id) = PeerId::from_bytes(peer_id.into_bytes().to_vec()) else {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        };
+                        address.pop();
+
+                        config
+                            .network_service
+                            .0
+                            .dial(config.network_service.1, peer_id, address)
+                            .await;
+                        request.respond(methods::Response::system_addReservedPeer(()));
+                    }
                     methods::MethodCall::system_localPeerId {} => {
                         let peer_id = config.network_service.0.local_peer_id().to_base58();
                         request.respond(methods::Response::system_localPeerId(peer_id.into()));
@@ -632,11 +1286,44 @@ pub fn spawn_requests_handler(config: Config) {
                             env!("CARGO_PKG_NAME").into(),
                         ));
                     }
+                    methods::MethodCall::system_peers {} => {
+                        let peers = config
+                            .network_service
+                            .0
+                            .peers(config.network_service.1)
+                            .await;
+                        request.respond(methods::Response::system_peers(
+                            peers
+                                .into_iter()
+                                .map(|peer| methods::SystemPeer {
+                                    peer_id: peer.peer_id.to_string(),
+                                    roles: match peer.role {
+                                        codec::Role::Authority => {
+                                            methods::SystemPeerRole::Authority
+                                        }
+                                        codec::Role::Full => methods::SystemPeerRole::Full,
+                                        codec::Role::Light => methods::SystemPeerRole::Light,
+                                    },
+                                    best_hash: methods::HashHexString(peer.best_hash),
+                                    best_number: peer.best_number,
+                                })
+                                .collect(),
+                        ));
+                    }
                     methods::MethodCall::system_properties {} => {
                         request.respond(methods::Response::system_properties(
                             serde_json::from_str(&config.chain_properties_json).unwrap(),
                         ));
                     }
+                    methods::MethodCall::system_removeReservedPeer { peer_id } => {
+                        let Ok(peer_id) = peer_id.parse::<PeerId>() else {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        };
+
+                        config.network_service.0.disconnect(peer_id).await;
+                        request.respond(methods::Response::system_removeReservedPeer(()));
+                    }
                     methods::MethodCall::system_version {} => {
                         request.respond(methods::Response::system_version(
                             env!("CARGO_PKG_VERSION").into(),
@@ -651,8 +1338,10 @@ pub fn spawn_requests_handler(config: Config) {
                 Some(Message::SubscriptionStart(request)) => match request.request() {
                     methods::MethodCall::chain_subscribeAllHeads {} => {
                         let block_number_bytes = config.consensus_service.block_number_bytes();
-                        let mut blocks_to_report = legacy_api_subscriptions::SubscribeAllHeads::new(
-                            config.consensus_service.clone(),
+                        let mut blocks_to_report = subscriptions_multiplexer::HeadersSource::new(
+                            config.subscriptions_multiplexer.as_deref(),
+                            &config.consensus_service,
+                            subscriptions_multiplexer::HeadersKind::All,
                         );
 
                         (config.tasks_executor)(Box::pin(async move {
@@ -687,10 +1376,11 @@ pub fn spawn_requests_handler(config: Config) {
 
                     methods::MethodCall::chain_subscribeFinalizedHeads {} => {
                         let block_number_bytes = config.consensus_service.block_number_bytes();
-                        let mut blocks_to_report =
-                            legacy_api_subscriptions::SubscribeFinalizedHeads::new(
-                                config.consensus_service.clone(),
-                            );
+                        let mut blocks_to_report = subscriptions_multiplexer::HeadersSource::new(
+                            config.subscriptions_multiplexer.as_deref(),
+                            &config.consensus_service,
+                            subscriptions_multiplexer::HeadersKind::Finalized,
+                        );
 
                         (config.tasks_executor)(Box::pin(async move {
                             let mut subscription = request.accept();
@@ -726,8 +1416,10 @@ pub fn spawn_requests_handler(config: Config) {
 
                     methods::MethodCall::chain_subscribeNewHeads {} => {
                         let block_number_bytes = config.consensus_service.block_number_bytes();
-                        let mut blocks_to_report = legacy_api_subscriptions::SubscribeNewHeads::new(
-                            config.consensus_service.clone(),
+                        let mut blocks_to_report = subscriptions_multiplexer::HeadersSource::new(
+                            config.subscriptions_multiplexer.as_deref(),
+                            &config.consensus_service,
+                            subscriptions_multiplexer::HeadersKind::New,
                         );
 
                         (config.tasks_executor)(Box::pin(async move {
@@ -740,7 +1432,7 @@ pub fn spawn_requests_handler(config: Config) {
 
                                 let json_rpc_header =
                                     match methods::Header::from_scale_encoded_header(
-                                        scale_encoded_header,
+                                        &scale_encoded_header,
                                         block_number_bytes,
                                     ) {
                                         Ok(h) => h,
@@ -786,6 +1478,202 @@ pub fn spawn_requests_handler(config: Config) {
                         }));
                     }
 
+                    methods::MethodCall::transactionWatch_v1_submitAndWatch { transaction } => {
+                        let database = config.database.clone();
+                        let runtime_caches_service = config.runtime_caches_service.clone();
+                        let runtime_calls_limiter = config.runtime_calls_limiter.clone();
+                        let consensus_service = config.consensus_service.clone();
+                        let network_service = config.network_service.clone();
+                        let banned_transactions = config.banned_transactions.clone();
+                        let transactions_pool = config.transactions_pool.clone();
+
+                        (config.tasks_executor)(Box::pin(async move {
+                            let mut subscription = request.accept();
+                            let subscription_id = subscription.subscription_id().to_owned();
+
+                            let hash: [u8; 32] =
+                                blake2_rfc::blake2b::blake2b(32, &[], &transaction.0)
+                                    .as_bytes()
+                                    .try_into()
+                                    .unwrap();
+                            if banned_transactions.is_banned(&hash) {
+                                subscription
+                                    .send_notification(
+                                        methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionWatchEvent::Invalid {
+                                                error: "Transaction is temporarily banned".into(),
+                                            },
+                                        },
+                                    )
+                                    .await;
+                                return;
+                            }
+
+                            let validity = match transactions::validate_transaction(
+                                &database,
+                                &runtime_caches_service,
+                                &runtime_calls_limiter,
+                                &transaction.0,
+                            )
+                            .await
+                            {
+                                Ok(validity) => validity,
+                                Err(transactions::ValidateError::Invalid(error)) => {
+                                    if matches!(
+                                        error,
+                                        validate::TransactionValidityError::Invalid(_)
+                                    ) {
+                                        banned_transactions.ban(hash);
+                                    }
+                                    subscription
+                                        .send_notification(
+                                            methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                                subscription: (&subscription_id).into(),
+                                                result: methods::TransactionWatchEvent::Invalid {
+                                                    error: error.to_string().into(),
+                                                },
+                                            },
+                                        )
+                                        .await;
+                                    return;
+                                }
+                                Err(error) => {
+                                    subscription
+                                        .send_notification(
+                                            methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                                subscription: (&subscription_id).into(),
+                                                result: methods::TransactionWatchEvent::Error {
+                                                    error: error.to_string().into(),
+                                                },
+                                            },
+                                        )
+                                        .await;
+                                    return;
+                                }
+                            };
+
+                            subscription
+                                .send_notification(
+                                    methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Validated {},
+                                    },
+                                )
+                                .await;
+
+                            // A transaction that is already in the pool has been submitted by
+                            // someone else, and can nonetheless be watched.
+                            if let Err(
+                                error @ (transactions_pool::InsertError::PoolFull
+                                | transactions_pool::InsertError::SubmitterLimitReached),
+                            ) = transactions_pool.insert(
+                                transaction.0.clone(),
+                                validity.clone(),
+                                validate::TransactionSource::External,
+                            ) {
+                                subscription
+                                    .send_notification(
+                                        methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionWatchEvent::Dropped {
+                                                broadcasted: false,
+                                                error: error.to_string().into(),
+                                            },
+                                        },
+                                    )
+                                    .await;
+                                return;
+                            }
+
+                            // The watch is started before sending the transaction in order to not
+                            // miss the blocks that include it.
+                            let mut watch = transactions::TransactionWatch::new(
+                                consensus_service,
+                                database,
+                                transaction.0.clone(),
+                                validity.longevity,
+                            )
+                            .await;
+
+                            let mut broadcasted = false;
+                            if validity.propagate {
+                                let peers = network_service
+                                    .0
+                                    .announce_transaction(network_service.1, transaction.0)
+                                    .await;
+                                broadcasted = !peers.is_empty();
+                                subscription
+                                    .send_notification(
+                                        methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionWatchEvent::Broadcasted {
+                                                num_peers: u32::try_from(peers.len())
+                                                    .unwrap_or(u32::MAX),
+                                            },
+                                        },
+                                    )
+                                    .await;
+                            }
+
+                            loop {
+                                let event =
+                                    future::or(async { Some(watch.next_event().await) }, async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    })
+                                    .await;
+
+                                let (result, is_last) = match event {
+                                    None => return,
+                                    Some(transactions::WatchEvent::BestChainBlockIncluded(
+                                        block,
+                                    )) => (
+                                        methods::TransactionWatchEvent::BestChainBlockIncluded {
+                                            block: block.map(|(hash, index)| {
+                                                methods::TransactionWatchEventBlock {
+                                                    hash: methods::HashHexString(hash),
+                                                    index,
+                                                }
+                                            }),
+                                        },
+                                        false,
+                                    ),
+                                    Some(transactions::WatchEvent::Finalized(hash, index)) => (
+                                        methods::TransactionWatchEvent::Finalized {
+                                            block: methods::TransactionWatchEventBlock {
+                                                hash: methods::HashHexString(hash),
+                                                index,
+                                            },
+                                        },
+                                        true,
+                                    ),
+                                    Some(transactions::WatchEvent::Dropped) => (
+                                        methods::TransactionWatchEvent::Dropped {
+                                            broadcasted,
+                                            error: "Transaction not included in a block in time"
+                                                .into(),
+                                        },
+                                        true,
+                                    ),
+                                };
+
+                                subscription
+                                    .send_notification(
+                                        methods::ServerToClient::transactionWatch_v1_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result,
+                                        },
+                                    )
+                                    .await;
+
+                                if is_last {
+                                    return;
+                                }
+                            }
+                        }));
+                    }
+
                     methods::MethodCall::state_subscribeStorage { list } => {
                         let mut notifications_to_report =
                             legacy_api_subscriptions::SubscribeStorage::new(
@@ -849,3 +1737,142 @@ fn convert_runtime_version(runtime_spec: &executor::CoreVersion) -> methods::Run
             .collect(),
     }
 }
+
+/// Returns `true` if the given event passes the filters of a `state_traceBlock` request.
+///
+/// `targets` filters the logs by target, `storage_keys` filters the storage-related events by
+/// key prefix, and `methods` filters the events by type. A filter that is `None` lets all the
+/// events through.
+fn block_trace_event_matches(
+    event: &block_tracing::BlockTraceEvent,
+    targets: Option<&[String]>,
+    storage_keys: Option<&[Vec<u8>]>,
+    methods: Option<&[String]>,
+) -> bool {
+    let (event_type, log_target, storage_key) = match event {
+        block_tracing::BlockTraceEvent::StorageGet { key, .. } => {
+            ("storageGet", None, Some(either::Left(key)))
+        }
+        block_tracing::BlockTraceEvent::StorageNextKey { key_nibbles, .. } => {
+            ("storageNextKey", None, Some(either::Right(key_nibbles)))
+        }
+        block_tracing::BlockTraceEvent::StorageClosestDescendantMerkleValue {
+            key_nibbles, ..
+        } => (
+            "storageClosestDescendantMerkleValue",
+            None,
+            Some(either::Right(key_nibbles)),
+        ),
+        block_tracing::BlockTraceEvent::SignatureVerification { .. } => {
+            ("signatureVerification", None, None)
+        }
+        block_tracing::BlockTraceEvent::OffchainStorageSet { .. } => {
+            ("offchainStorageSet", None, None)
+        }
+        block_tracing::BlockTraceEvent::Log { target, .. } => ("log", target.as_deref(), None),
+        block_tracing::BlockTraceEvent::StorageSet { key, .. } => {
+            ("storageSet", None, Some(either::Left(key)))
+        }
+    };
+
+    if let Some(methods) = methods {
+        if !methods.iter().any(|m| m == event_type) {
+            return false;
+        }
+    }
+
+    if let (Some(targets), "log") = (targets, event_type) {
+        if !log_target.map_or(false, |log_target| targets.iter().any(|t| t == log_target)) {
+            return false;
+        }
+    }
+
+    if let (Some(storage_keys), Some(storage_key)) = (storage_keys, storage_key) {
+        let matches = storage_keys.iter().any(|prefix| match storage_key {
+            either::Left(key) => key.starts_with(prefix),
+            either::Right(key_nibbles) => {
+                key_nibbles.len() >= prefix.len() * 2
+                    && trie::bytes_to_nibbles(prefix.iter().copied())
+                        .zip(key_nibbles.iter())
+                        .all(|(a, b)| u8::from(a) == *b)
+            }
+        });
+        if !matches {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn convert_block_trace_event(event: block_tracing::BlockTraceEvent) -> methods::BlockTraceEvent {
+    let nibbles_to_hex = |nibbles: Vec<u8>| {
+        iter::once('0')
+            .chain(iter::once('x'))
+            .chain(
+                nibbles
+                    .into_iter()
+                    .map(|n| char::from_digit(u32::from(n), 16).unwrap()),
+            )
+            .collect::<String>()
+    };
+
+    match event {
+        block_tracing::BlockTraceEvent::StorageGet {
+            child_trie,
+            key,
+            value,
+        } => methods::BlockTraceEvent::StorageGet {
+            child_trie: child_trie.map(methods::HexString),
+            key: methods::HexString(key),
+            value: value.map(methods::HexString),
+        },
+        block_tracing::BlockTraceEvent::StorageNextKey {
+            child_trie,
+            key_nibbles,
+            next_key_nibbles,
+        } => methods::BlockTraceEvent::StorageNextKey {
+            child_trie: child_trie.map(methods::HexString),
+            key_nibbles: nibbles_to_hex(key_nibbles),
+            next_key_nibbles: next_key_nibbles.map(nibbles_to_hex),
+        },
+        block_tracing::BlockTraceEvent::StorageClosestDescendantMerkleValue {
+            child_trie,
+            key_nibbles,
+            merkle_value,
+        } => methods::BlockTraceEvent::StorageClosestDescendantMerkleValue {
+            child_trie: child_trie.map(methods::HexString),
+            key_nibbles: nibbles_to_hex(key_nibbles),
+            merkle_value: merkle_value.map(methods::HexString),
+        },
+        block_tracing::BlockTraceEvent::SignatureVerification {
+            public_key,
+            message,
+            signature,
+            success,
+        } => methods::BlockTraceEvent::SignatureVerification {
+            public_key: methods::HexString(public_key),
+            message: methods::HexString(message),
+            signature: methods::HexString(signature),
+            success,
+        },
+        block_tracing::BlockTraceEvent::OffchainStorageSet { key, value } => {
+            methods::BlockTraceEvent::OffchainStorageSet {
+                key: methods::HexString(key),
+                value: value.map(methods::HexString),
+            }
+        }
+        block_tracing::BlockTraceEvent::Log { target, message } => {
+            methods::BlockTraceEvent::Log { target, message }
+        }
+        block_tracing::BlockTraceEvent::StorageSet {
+            child_trie,
+            key,
+            value,
+        } => methods::BlockTraceEvent::StorageSet {
+            child_trie: child_trie.map(methods::HexString),
+            key: methods::HexString(key),
+            value: value.map(methods::HexString),
+        },
+    }
+}
@@ -0,0 +1,257 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Sharing the subscriptions to the consensus service between JSON-RPC subscriptions.
+//!
+//! By default, each `chain_subscribeAllHeads`, `chain_subscribeNewHeads`, and
+//! `chain_subscribeFinalizedHeads` JSON-RPC subscription creates its own subscription to the
+//! consensus service. When thousands of JSON-RPC clients are connected, the consensus service
+//! has to notify thousands of subscriptions of each new block.
+//!
+//! The [`SubscriptionsMultiplexer`] instead holds one subscription to the consensus service
+//! per kind of JSON-RPC subscription, pulled by a background task. Each header is then copied
+//! into a buffer dedicated to each JSON-RPC subscription.
+//!
+//! When a buffer is full, which happens if the JSON-RPC client doesn't read its notifications
+//! fast enough, the oldest header of the buffer is discarded. In other words, a JSON-RPC client
+//! that lags behind misses some notifications but never slows down the other clients.
+
+use crate::{consensus_service, json_rpc_service::legacy_api_subscriptions};
+
+use futures_lite::Future;
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// Configuration of the multiplexer.
+pub struct Config {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: Arc<dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync>,
+
+    /// Consensus service of the chain.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Maximum number of headers waiting to be pulled by each JSON-RPC subscription.
+    pub buffer_size: NonZeroUsize,
+}
+
+/// Kind of headers a subscription reports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeadersKind {
+    /// All the new blocks, as in `chain_subscribeAllHeads`.
+    All,
+    /// Blocks that become the new best block, as in `chain_subscribeNewHeads`.
+    New,
+    /// Blocks that become finalized, as in `chain_subscribeFinalizedHeads`.
+    Finalized,
+}
+
+/// See the module-level documentation.
+pub struct SubscriptionsMultiplexer {
+    all_heads: Arc<Hub>,
+    new_heads: Arc<Hub>,
+    finalized_heads: Arc<Hub>,
+}
+
+/// State shared between the background task of a kind of subscription and the JSON-RPC
+/// subscriptions.
+struct Hub {
+    /// See [`Config::buffer_size`].
+    buffer_size: NonZeroUsize,
+    /// List of the JSON-RPC subscriptions. Subscriptions that have been destroyed are removed
+    /// the next time a header is pushed.
+    subscribers: Mutex<Vec<Weak<Queue>>>,
+}
+
+/// Headers waiting to be pulled by one JSON-RPC subscription.
+struct Queue {
+    headers: Mutex<VecDeque<Arc<[u8]>>>,
+    /// Notified whenever a header is pushed to [`Queue::headers`].
+    on_new_header: event_listener::Event,
+}
+
+impl SubscriptionsMultiplexer {
+    /// Initializes a new multiplexer and spawns its background tasks.
+    pub fn new(config: Config) -> Self {
+        let new_hub = || {
+            Arc::new(Hub {
+                buffer_size: config.buffer_size,
+                subscribers: Mutex::new(Vec::new()),
+            })
+        };
+
+        let multiplexer = SubscriptionsMultiplexer {
+            all_heads: new_hub(),
+            new_heads: new_hub(),
+            finalized_heads: new_hub(),
+        };
+
+        // Each background task stops as soon as it notices that the multiplexer has been
+        // destroyed.
+        let hub = Arc::downgrade(&multiplexer.all_heads);
+        let mut upstream =
+            legacy_api_subscriptions::SubscribeAllHeads::new(config.consensus_service.clone());
+        (config.tasks_executor)(Box::pin(async move {
+            loop {
+                let header = upstream.next_scale_encoded_header().await;
+                let Some(hub) = hub.upgrade() else { return };
+                hub.push(Arc::from(header));
+            }
+        }));
+
+        let hub = Arc::downgrade(&multiplexer.new_heads);
+        let mut upstream =
+            legacy_api_subscriptions::SubscribeNewHeads::new(config.consensus_service.clone());
+        (config.tasks_executor)(Box::pin(async move {
+            loop {
+                let header = Arc::from(&upstream.next_scale_encoded_header().await[..]);
+                let Some(hub) = hub.upgrade() else { return };
+                hub.push(header);
+            }
+        }));
+
+        let hub = Arc::downgrade(&multiplexer.finalized_heads);
+        let mut upstream =
+            legacy_api_subscriptions::SubscribeFinalizedHeads::new(config.consensus_service);
+        (config.tasks_executor)(Box::pin(async move {
+            loop {
+                let header = upstream.next_scale_encoded_header().await;
+                let Some(hub) = hub.upgrade() else { return };
+                hub.push(Arc::from(header));
+            }
+        }));
+
+        multiplexer
+    }
+
+    /// Starts a new subscription. Only the headers that arrive after this function has returned
+    /// are reported.
+    pub fn subscribe(&self, kind: HeadersKind) -> HeadersSubscription {
+        let hub = match kind {
+            HeadersKind::All => &self.all_heads,
+            HeadersKind::New => &self.new_heads,
+            HeadersKind::Finalized => &self.finalized_heads,
+        };
+
+        let queue = Arc::new(Queue {
+            headers: Mutex::new(VecDeque::with_capacity(hub.buffer_size.get())),
+            on_new_header: event_listener::Event::new(),
+        });
+
+        hub.subscribers.lock().unwrap().push(Arc::downgrade(&queue));
+
+        HeadersSubscription { queue }
+    }
+}
+
+impl Hub {
+    /// Pushes a header to the buffer of all the JSON-RPC subscriptions.
+    fn push(&self, header: Arc<[u8]>) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|queue| {
+            let Some(queue) = queue.upgrade() else {
+                return false;
+            };
+
+            let mut headers = queue.headers.lock().unwrap();
+            if headers.len() >= self.buffer_size.get() {
+                // The subscriber lags behind. Discard the oldest header.
+                headers.pop_front();
+            }
+            headers.push_back(header.clone());
+            drop(headers);
+
+            queue.on_new_header.notify(1);
+            true
+        });
+    }
+}
+
+/// Subscription started with [`SubscriptionsMultiplexer::subscribe`].
+pub struct HeadersSubscription {
+    queue: Arc<Queue>,
+}
+
+impl HeadersSubscription {
+    /// Returns the SCALE-encoded header of the next block to provide as part of the subscription.
+    pub async fn next_scale_encoded_header(&mut self) -> Arc<[u8]> {
+        loop {
+            let listener = self.queue.on_new_header.listen();
+            if let Some(header) = self.queue.headers.lock().unwrap().pop_front() {
+                return header;
+            }
+            listener.await;
+        }
+    }
+}
+
+/// Source of the headers of a `chain_subscribeAllHeads`, `chain_subscribeNewHeads`, or
+/// `chain_subscribeFinalizedHeads` JSON-RPC subscription.
+pub enum HeadersSource {
+    /// Headers come from a [`SubscriptionsMultiplexer`].
+    Multiplexed(HeadersSubscription),
+    /// Subscription dedicated to this JSON-RPC subscription.
+    AllHeads(legacy_api_subscriptions::SubscribeAllHeads),
+    /// Subscription dedicated to this JSON-RPC subscription.
+    NewHeads(legacy_api_subscriptions::SubscribeNewHeads),
+    /// Subscription dedicated to this JSON-RPC subscription.
+    FinalizedHeads(legacy_api_subscriptions::SubscribeFinalizedHeads),
+}
+
+impl HeadersSource {
+    /// Subscribes through the multiplexer if any is passed, or creates a dedicated subscription
+    /// to the consensus service otherwise.
+    pub fn new(
+        multiplexer: Option<&SubscriptionsMultiplexer>,
+        consensus_service: &Arc<consensus_service::ConsensusService>,
+        kind: HeadersKind,
+    ) -> Self {
+        match (multiplexer, kind) {
+            (Some(multiplexer), _) => HeadersSource::Multiplexed(multiplexer.subscribe(kind)),
+            (None, HeadersKind::All) => HeadersSource::AllHeads(
+                legacy_api_subscriptions::SubscribeAllHeads::new(consensus_service.clone()),
+            ),
+            (None, HeadersKind::New) => HeadersSource::NewHeads(
+                legacy_api_subscriptions::SubscribeNewHeads::new(consensus_service.clone()),
+            ),
+            (None, HeadersKind::Finalized) => HeadersSource::FinalizedHeads(
+                legacy_api_subscriptions::SubscribeFinalizedHeads::new(consensus_service.clone()),
+            ),
+        }
+    }
+
+    /// Returns the SCALE-encoded header of the next block to provide as part of the subscription.
+    pub async fn next_scale_encoded_header(&mut self) -> Arc<[u8]> {
+        match self {
+            HeadersSource::Multiplexed(subscription) => {
+                subscription.next_scale_encoded_header().await
+            }
+            HeadersSource::AllHeads(subscription) => {
+                Arc::from(subscription.next_scale_encoded_header().await)
+            }
+            HeadersSource::NewHeads(subscription) => {
+                Arc::from(&subscription.next_scale_encoded_header().await[..])
+            }
+            HeadersSource::FinalizedHeads(subscription) => {
+                Arc::from(subscription.next_scale_encoded_header().await)
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Transactions submitted through the JSON-RPC server.
+//!
+//! Transactions submitted by JSON-RPC clients are validated against the current best block, then
+//! inserted in the transactions pool of the node (see [`crate::transactions_pool`]) and sent to the
+//! peers of the node. They are thus included either in the blocks authored by the node or in the
+//! ones authored by its peers. Their status is then determined by looking at the bodies of the
+//! blocks that the node imports.
+//!
+//! Transactions that the runtime has reported as invalid are banned for a configurable duration
+//! (see [`BannedTransactions`]), during which submitting them again is refused without
+//! validating them, in order to not waste resources on clients that submit the same invalid
+//! transactions over and over again.
+
+use hashbrown::HashMap;
+use smol::stream::StreamExt as _;
+use smoldot::{executor, transactions::validate};
+use std::{
+    iter,
+    num::{NonZeroU64, NonZeroUsize},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{consensus_service, database_thread, runtime_caches_service, runtime_calls_limiter};
+
+/// Validates the given transaction against the current best block of the chain.
+pub async fn validate_transaction(
+    database: &database_thread::DatabaseThread,
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    runtime_calls_limiter: &Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+    scale_encoded_transaction: &[u8],
+) -> Result<validate::ValidTransaction, ValidateError> {
+    let best_block_hash = database
+        .with_database_read(|db| db.best_block_hash())
+        .await
+        .map_err(|_| ValidateError::CorruptedDatabase)?;
+
+    let runtime = runtime_caches_service
+        .get(best_block_hash)
+        .await
+        .map_err(ValidateError::Runtime)?;
+    let runtime = (*runtime).clone();
+
+    let parameters = match runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version("TaggedTransactionQueue")
+    {
+        Some(2) => validate::validate_transaction_runtime_parameters_v2(
+            iter::once(scale_encoded_transaction),
+            validate::TransactionSource::External,
+        )
+        .fold(Vec::new(), |mut params, chunk| {
+            params.extend_from_slice(chunk.as_ref());
+            params
+        }),
+        Some(3) => validate::validate_transaction_runtime_parameters_v3(
+            iter::once(scale_encoded_transaction),
+            validate::TransactionSource::External,
+            &best_block_hash,
+        )
+        .fold(Vec::new(), |mut params, chunk| {
+            params.extend_from_slice(chunk.as_ref());
+            params
+        }),
+        _ => return Err(ValidateError::UnsupportedRuntime),
+    };
+
+    let _permit = runtime_calls_limiter.json_rpc_permit().await;
+    let output = consensus_service::runtime_call(
+        database,
+        &best_block_hash,
+        runtime,
+        validate::VALIDATION_FUNCTION_NAME,
+        &parameters,
+        executor::runtime_call::StorageProofSizeBehavior::Unimplemented,
+        executor::runtime_call::StorageChanges::empty(),
+        false,
+    )
+    .await
+    .map_err(ValidateError::RuntimeCall)?
+    .output;
+
+    match validate::decode_validate_transaction_return_value(&output) {
+        Ok(Ok(valid)) => Ok(valid),
+        Ok(Err(error)) => Err(ValidateError::Invalid(error)),
+        Err(_) => Err(ValidateError::OutputDecode),
+    }
+}
+
+/// Error returned by [`validate_transaction`].
+#[derive(Debug, derive_more::Display)]
+pub enum ValidateError {
+    /// Database is corrupted.
+    CorruptedDatabase,
+    /// Failed to obtain the runtime of the best block.
+    #[display(fmt = "Failed to obtain the runtime of the best block: {_0}")]
+    Runtime(runtime_caches_service::GetError),
+    /// The runtime of the best block doesn't support validating transactions.
+    UnsupportedRuntime,
+    /// Error while executing the validation function.
+    #[display(fmt = "{_0}")]
+    RuntimeCall(consensus_service::RuntimeCallError),
+    /// Failed to decode the output of the validation function.
+    OutputDecode,
+    /// The runtime has reported the transaction as invalid.
+    #[display(fmt = "{_0}")]
+    Invalid(validate::TransactionValidityError),
+}
+
+/// Tracks the inclusion of a transaction in the blocks imported by the node.
+pub struct TransactionWatch {
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// Transaction being watched.
+    scale_encoded_transaction: Arc<[u8]>,
+
+    /// Subscription to the blocks of the consensus service.
+    subscription_id: consensus_service::SubscriptionId,
+    new_blocks: Pin<Box<async_channel::Receiver<consensus_service::Notification>>>,
+
+    /// List of all non-finalized blocks, with the hash of their parent and the index of the
+    /// transaction within their body if they include it.
+    non_finalized_blocks: HashMap<[u8; 32], ([u8; 32], Option<u32>), fnv::FnvBuildHasher>,
+
+    /// Hash of the current best block.
+    best_block_hash: [u8; 32],
+
+    /// Number of blocks that have been finalized since the watch started.
+    num_finalized_blocks: u64,
+
+    /// Number of finalized blocks after which the transaction is considered as dropped.
+    longevity: NonZeroU64,
+
+    /// Block of the best chain that includes the transaction, as most recently returned by
+    /// [`TransactionWatch::next_event`].
+    reported_best_chain_inclusion: Option<([u8; 32], u32)>,
+}
+
+/// Event returned by [`TransactionWatch::next_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// The block of the best chain that includes the transaction, and the index of the
+    /// transaction within its body, has changed. `None` if the best chain no longer includes the
+    /// transaction.
+    BestChainBlockIncluded(Option<([u8; 32], u32)>),
+    /// The transaction has been included in a finalized block, at the given index within its
+    /// body. No further event will be generated.
+    Finalized([u8; 32], u32),
+    /// The transaction hasn't been included in a block in time, or the node has stopped tracking
+    /// the blocks of the chain. No further event will be generated.
+    Dropped,
+}
+
+impl TransactionWatch {
+    /// Starts watching the given transaction.
+    ///
+    /// `longevity` is the number of blocks that can be finalized before the transaction is
+    /// considered as dropped, as returned by [`validate_transaction`].
+    pub async fn new(
+        consensus_service: Arc<consensus_service::ConsensusService>,
+        database: Arc<database_thread::DatabaseThread>,
+        scale_encoded_transaction: Vec<u8>,
+        longevity: NonZeroU64,
+    ) -> Self {
+        let subscribe_all = consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+
+        let mut watch = TransactionWatch {
+            consensus_service,
+            database,
+            scale_encoded_transaction: scale_encoded_transaction.into(),
+            subscription_id: subscribe_all.id,
+            new_blocks: Box::pin(subscribe_all.new_blocks),
+            non_finalized_blocks: HashMap::with_capacity_and_hasher(
+                subscribe_all.non_finalized_blocks_ancestry_order.len(),
+                Default::default(),
+            ),
+            best_block_hash: subscribe_all.finalized_block_hash,
+            num_finalized_blocks: 0,
+            longevity,
+            reported_best_chain_inclusion: None,
+        };
+
+        watch
+            .consensus_service
+            .unpin_block(watch.subscription_id, subscribe_all.finalized_block_hash)
+            .await;
+
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            watch.insert_block(block).await;
+        }
+
+        watch
+    }
+
+    /// Returns the next change in the status of the transaction.
+    ///
+    /// Must not be called again after [`WatchEvent::Finalized`] or [`WatchEvent::Dropped`] has
+    /// been returned.
+    pub async fn next_event(&mut self) -> WatchEvent {
+        loop {
+            let best_chain_inclusion = self.best_chain_inclusion();
+            if best_chain_inclusion != self.reported_best_chain_inclusion {
+                self.reported_best_chain_inclusion = best_chain_inclusion;
+                return WatchEvent::BestChainBlockIncluded(best_chain_inclusion);
+            }
+
+            match self.new_blocks.next().await {
+                None => {
+                    // The consensus service has killed the subscription.
+                    return WatchEvent::Dropped;
+                }
+                Some(consensus_service::Notification::Block { block, .. }) => {
+                    self.insert_block(block).await;
+                }
+                Some(consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    best_block_hash,
+                    pruned_blocks_hashes,
+                }) => {
+                    if let Some((hash, index)) =
+                        finalized_blocks_newest_to_oldest.iter().find_map(|hash| {
+                            match self.non_finalized_blocks.get(hash) {
+                                Some((_, Some(index))) => Some((*hash, *index)),
+                                _ => None,
+                            }
+                        })
+                    {
+                        return WatchEvent::Finalized(hash, index);
+                    }
+
+                    for hash in finalized_blocks_newest_to_oldest
+                        .iter()
+                        .chain(pruned_blocks_hashes.iter())
+                    {
+                        self.non_finalized_blocks.remove(hash);
+                    }
+                    self.best_block_hash = best_block_hash;
+
+                    self.num_finalized_blocks = self.num_finalized_blocks.saturating_add(
+                        u64::try_from(finalized_blocks_newest_to_oldest.len()).unwrap(),
+                    );
+                    if self.num_finalized_blocks > self.longevity.get() {
+                        return WatchEvent::Dropped;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Looks for the transaction in the body of the given block, then adds it to
+    /// [`TransactionWatch::non_finalized_blocks`].
+    async fn insert_block(&mut self, block: consensus_service::BlockNotification) {
+        let transaction_index = self
+            .database
+            .with_database_read({
+                let block_hash = block.block_hash;
+                let transaction = self.scale_encoded_transaction.clone();
+                move |db| {
+                    // Errors are treated the same way as the transaction not being included,
+                    // as it is not possible to do anything better.
+                    db.block_extrinsics(&block_hash)
+                        .ok()
+                        .flatten()?
+                        .position(|extrinsic| *extrinsic == *transaction)
+                        .map(|index| u32::try_from(index).unwrap())
+                }
+            })
+            .await;
+
+        self.consensus_service
+            .unpin_block(self.subscription_id, block.block_hash)
+            .await;
+
+        self.non_finalized_blocks
+            .insert(block.block_hash, (block.parent_hash, transaction_index));
+        if block.is_new_best {
+            self.best_block_hash = block.block_hash;
+        }
+    }
+
+    /// Returns the block of the best chain that includes the transaction, if any.
+    fn best_chain_inclusion(&self) -> Option<([u8; 32], u32)> {
+        let mut iter = self.best_block_hash;
+        while let Some((parent_hash, transaction_index)) = self.non_finalized_blocks.get(&iter) {
+            if let Some(transaction_index) = transaction_index {
+                return Some((iter, *transaction_index));
+            }
+            iter = *parent_hash;
+        }
+        None
+    }
+}
+
+/// Hashes of the transactions recently found to be invalid.
+pub struct BannedTransactions {
+    /// Hashes of the banned transactions, and when the ban expires. The oldest entries are
+    /// removed if the cache is full.
+    banned: Mutex<lru::LruCache<[u8; 32], Instant>>,
+
+    /// Duration of the ban of invalid transactions.
+    ban_duration: Duration,
+}
+
+impl BannedTransactions {
+    /// Creates a new empty [`BannedTransactions`]. Invalid transactions are banned for the given
+    /// duration.
+    pub fn new(ban_duration: Duration) -> Self {
+        BannedTransactions {
+            banned: Mutex::new(lru::LruCache::new(NonZeroUsize::new(4096).unwrap())),
+            ban_duration,
+        }
+    }
+
+    /// Bans the transaction with the given hash, after it has been reported as invalid by the
+    /// runtime. Extends the ban if the transaction is already banned.
+    pub fn ban(&self, transaction_hash: [u8; 32]) {
+        let expiration = Instant::now() + self.ban_duration;
+        self.banned
+            .lock()
+            .unwrap()
+            .put(transaction_hash, expiration);
+    }
+
+    /// Returns `true` if the transaction with the given hash is currently banned.
+    pub fn is_banned(&self, transaction_hash: &[u8; 32]) -> bool {
+        let mut banned = self.banned.lock().unwrap();
+        match banned.peek(transaction_hash) {
+            Some(expiration) if *expiration > Instant::now() => true,
+            Some(_) => {
+                banned.pop(transaction_hash);
+                false
+            }
+            None => false,
+        }
+    }
+}
@@ -19,29 +19,97 @@
 // TODO: #![deny(unused_crate_dependencies)] doesn't work because some deps are used only by the binary, figure if this can be fixed?
 
 use futures_util::{future, StreamExt as _};
-use rand::RngCore as _;
+use rand::{Rng as _, RngCore as _, SeedableRng as _};
 use smol::lock::Mutex;
 use smoldot::{
     chain, chain_spec,
     database::full_sqlite,
     executor, header,
-    identity::keystore,
+    identity::{keystore, ss58},
     informant::HashDisplay,
     libp2p::{
         connection, multiaddr,
         peer_id::{self, PeerId},
     },
-    trie,
+    metadata, trie,
+};
+use std::{
+    borrow::Cow,
+    cmp,
+    collections::BTreeMap,
+    fs, io, iter, mem,
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU64, NonZeroUsize},
+    ops,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::{array, borrow::Cow, io, iter, mem, net::SocketAddr, path::PathBuf, sync::Arc};
 
+mod block_export;
+mod blocks_file;
+mod chain_spec_fetch;
+mod checkpoint_export;
+mod client_events;
+mod compiled_runtimes_cache;
 mod consensus_service;
+mod database_backup;
+mod database_compaction;
+mod database_read_only;
 mod database_thread;
+mod equivocation_reporter;
+mod fd_budget;
+mod grandpa_voter;
 mod jaeger_service;
 mod json_rpc_service;
 mod network_service;
+mod offchain_http;
+mod offchain_worker;
+mod parachain_inclusion;
+mod pruning;
+mod runtime_caches_service;
+mod runtime_calls_limiter;
+mod runtime_execution_threads;
+mod state_snapshot;
+mod transactions_pool;
 mod util;
 
+pub use block_export::{BlockExportConfig, BlockExportEvent, BlockExportSink, ExportedBlock};
+pub use blocks_file::{
+    export_blocks_from_database_file, ExportBlocksError, ImportBlocksError, ImportBlocksReport,
+};
+pub use chain_spec_fetch::{fetch_chain_spec, FetchChainSpecError};
+pub use checkpoint_export::CheckpointExportConfig;
+pub use client_events::ClientEvent;
+pub use consensus_service::{
+    BlockExecutionProfile, BlockImportHook, BlockTemplate, BlockTemplateError,
+    BlockTemplateSlotClaim, ExecutionStepProfile, ForceFinalizeError, ImportedBlock,
+    InherentDataProvider, SyncLimits,
+};
+pub use database_backup::{DatabaseBackupConfig, DatabaseBackupError};
+pub use database_compaction::CompactDatabaseError;
+pub use database_read_only::{open_database_read_only, OpenDatabaseReadOnlyError};
+pub use database_thread::{
+    DatabaseConnectionKind, DatabaseMetrics, DatabaseOperationsMetrics, SlowDatabaseOperation,
+    LATENCY_BUCKETS as DATABASE_LATENCY_BUCKETS,
+};
+pub use fd_budget::FileDescriptorsUsage;
+pub use jaeger_service::JaegerSampling;
+pub use json_rpc_service::{
+    AccountNextIndexError, BlockAuthorities, BlockAuthoritiesError, BlockTrace, BlockTraceEvent,
+    FeeConstantsError, JsonRpcMethodMetrics, TraceBlockError, JSON_RPC_LATENCY_BUCKETS,
+};
+pub use network_service::{
+    Bandwidth, BootnodesProvider, GenesisMismatch, PeerBandwidth, PeerInfo, ProtocolBandwidth,
+    ProtocolKind, StaticBootnodes,
+};
+pub use parachain_inclusion::ParachainInclusion;
+pub use smoldot::database::full_sqlite::{
+    AuthoringStats, JournalMode as SqliteJournalMode, SqliteFullDatabase, StorageAccessError,
+    StorageDiffEntry, Synchronous as SqliteSynchronous,
+};
+pub use state_snapshot::{ExportStateSnapshotError, LoadStateSnapshotError};
+
 pub struct Config<'a> {
     /// Chain to connect to.
     pub chain: ChainConfig<'a>,
@@ -49,26 +117,267 @@ pub struct Config<'a> {
     /// relay chain.
     pub relay_chain: Option<ChainConfig<'a>>,
     /// Ed25519 private key of network identity.
-    pub libp2p_key: Box<[u8; 32]>,
+    pub libp2p_key: Libp2pKey,
     /// List of addresses to listen on.
     pub listen_addresses: Vec<multiaddr::Multiaddr>,
+    /// Maximum number of outgoing connections towards IP addresses of the same `/24` (IPv4) or
+    /// `/48` (IPv6) subnet. Loopback and private IP addresses aren't limited. If `None`, no
+    /// limit is enforced.
+    pub max_outbound_connections_per_subnet: Option<NonZeroUsize>,
+    /// Maximum number of simultaneous incoming connections from the same IP address. Loopback and
+    /// private IP addresses aren't limited. If `None`, no limit is enforced.
+    pub max_inbound_connections_per_ip: Option<NonZeroUsize>,
+    /// IP addresses to which [`Config::max_inbound_connections_per_ip`] doesn't apply.
+    pub inbound_connections_ip_allowlist: Vec<IpAddr>,
+    /// If `Some`, all the outgoing networking connections are established through the SOCKS5
+    /// proxy found at this address.
+    pub socks5_proxy: Option<SocketAddr>,
+    /// If `true`, the ports of the listening addresses are mapped on the gateway of the local
+    /// network through NAT-PMP or, if the gateway doesn't support NAT-PMP, through UPnP.
+    pub nat_port_mapping: bool,
+    /// If `Some`, maximum number of bytes per second sent to the network, all peers combined.
+    pub max_upload_bytes_per_sec: Option<NonZeroU64>,
+    /// If `Some`, maximum number of bytes per second received from the network, all peers
+    /// combined.
+    pub max_download_bytes_per_sec: Option<NonZeroU64>,
+    /// Maximum number of file descriptors that the node opens for its networking sockets and
+    /// databases. Once reached, incoming connections are refused and outgoing connections are
+    /// postponed, rather than failing when the limit of the operating system is reached. If
+    /// `None`, no limit is enforced. See [`Client::file_descriptors_usage`].
+    pub max_file_descriptors: Option<NonZeroUsize>,
     /// Function that can be used to spawn background tasks.
     ///
     /// The tasks passed as parameter must be executed until they shut down.
     pub tasks_executor: Arc<dyn Fn(future::BoxFuture<'static, ()>) + Send + Sync>,
     /// Function called whenever a part of the node wants to notify of something.
     pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+    /// Function called in order to report the progress of the initialization of the node, for
+    /// example in order to display a progress bar. Pass a closure that does nothing in order to
+    /// ignore these reports.
+    pub progress_callback: Arc<dyn ProgressCallback + Send + Sync>,
     /// Address of a Jaeger agent to send traces to. If `None`, do not send Jaeger traces.
     pub jaeger_agent: Option<SocketAddr>,
+    /// Fraction of the networking events, per protocol, for which a Jaeger trace is sent.
+    /// Ignored if [`Config::jaeger_agent`] is `None`.
+    pub jaeger_sampling: JaegerSampling,
+    /// If `Some`, the runtime executions necessary to verify blocks are performed on dedicated
+    /// threads rather than through [`Config::tasks_executor`]. Prevents heavy executions from
+    /// starving the other tasks, in particular on machines with few CPU cores.
+    pub runtime_execution_threads: Option<RuntimeExecutionThreadsConfig>,
+    /// Maximum number of runtime executions initiated by JSON-RPC requests, of all chains, that
+    /// can run in parallel. JSON-RPC executions also wait while the verification or authoring of
+    /// blocks uses all the CPU cores of the machine.
+    pub max_json_rpc_runtime_calls: NonZeroUsize,
+    /// Number of threads used to compute the trie of the genesis block when the database is
+    /// empty. Building the genesis trie of chains with a very large genesis storage can take a
+    /// long time. If `None`, the number of threads is the available parallelism of the machine.
+    pub genesis_build_threads: Option<NonZeroUsize>,
+    /// If `true` and a database file turns out to be corrupted when opening it, the file is moved
+    /// aside to a timestamped path and an empty database is created instead. If `false`, a
+    /// corrupted database makes the node panic.
+    pub quarantine_corrupted_database: bool,
+    /// If `Some`, path to a directory where the runtimes compiled ahead of time are saved, so
+    /// that they don't need to be compiled again when the node restarts. The directory is
+    /// created if it doesn't exist.
+    pub compiled_runtimes_cache_path: Option<PathBuf>,
+    /// If `Some`, every block of [`Config::chain`] that is imported or finalized is pushed to the
+    /// given sink, for example in order to forward it to a message queue.
+    pub block_export: Option<BlockExportConfig>,
+    /// If `Some`, called for every block of [`Config::chain`] that is imported, with its header,
+    /// body and storage changes. Contrary to [`Config::block_export`], no block is ever missed,
+    /// as the node waits for the hook to finish before processing the next block.
+    pub block_import_hook: Option<Arc<dyn BlockImportHook + Send + Sync>>,
+    /// If `Some`, all the internal randomness of the node (choice of the peers to connect to and
+    /// to send requests to, Kademlia random walks, noise keys, etc.) is derived from this seed,
+    /// so that two runs against the same simulated network behave identically. If `None`, the
+    /// randomness comes from the operating system. Must only be used for testing purposes, as
+    /// it makes the keys generated by the node predictable.
+    pub randomness_seed: Option<[u8; 32]>,
+}
+
+/// See [`Config::runtime_execution_threads`].
+#[derive(Debug, Clone)]
+pub struct RuntimeExecutionThreadsConfig {
+    /// Number of dedicated threads. The threads are shared between the chain and the relay
+    /// chain.
+    pub num_threads: NonZeroUsize,
+    /// Identifiers of the CPU cores to pin the threads to. Thread number `n` is pinned to
+    /// `cores[n % cores.len()]`. If empty, the threads aren't pinned to any core.
+    pub cores: Vec<usize>,
 }
 
 /// See [`ChainConfig::json_rpc_listen`].
 #[derive(Debug, Clone)]
 pub struct JsonRpcListenConfig {
     /// Bind point of the JSON-RPC server.
+    ///
+    /// If [`JsonRpcListenConfig::admin_address`] is `Some`, this server only exposes the
+    /// JSON-RPC methods that are safe to expose publicly. Otherwise, it exposes all of them.
     pub address: SocketAddr,
+    /// Bind point of an additional JSON-RPC server that exposes all the JSON-RPC methods,
+    /// including key management and node administration. All the other options apply to both
+    /// servers.
+    pub admin_address: Option<SocketAddr>,
+    /// Bind point of an additional plain HTTP server that only serves the `GET /health` and
+    /// `GET /ready` endpoints. These endpoints are also always served on
+    /// [`JsonRpcListenConfig::address`] and [`JsonRpcListenConfig::admin_address`].
+    pub health_address: Option<SocketAddr>,
     /// Maximum number of JSON-RPC clients that can be connected at the same time.
     pub max_json_rpc_clients: u32,
+    /// Maximum number of JSON-RPC clients with the same IP address that can be connected at the
+    /// same time.
+    pub max_json_rpc_clients_per_ip: u32,
+    /// Maximum number of requests per second that each JSON-RPC client can send before
+    /// receiving errors indicating that the server is busy.
+    pub max_requests_per_second_per_client: u32,
+    /// Maximum number of subscriptions that each JSON-RPC client can have active at the same
+    /// time.
+    pub max_subscriptions_per_client: u32,
+    /// Maximum number of responses and notifications waiting to be sent to each JSON-RPC
+    /// client. Beyond this limit, [`JsonRpcListenConfig::slow_subscriber_policy`] applies.
+    pub max_pending_notifications_per_client: NonZeroUsize,
+    /// What to do when a JSON-RPC client doesn't read its notifications fast enough.
+    pub slow_subscriber_policy: SlowSubscriberPolicy,
+    /// If `Some`, the headers subscriptions of all the JSON-RPC clients are served from a single
+    /// subscription to the consensus service per kind of subscription, and each JSON-RPC
+    /// subscription buffers up to the given number of headers, beyond which its oldest headers
+    /// are discarded. Recommended when serving a large number of clients.
+    pub multiplexed_subscriptions_buffer: Option<NonZeroUsize>,
+    /// If `Some`, the JSON-RPC server only accepts TLS connections (i.e. `wss://`).
+    pub tls: Option<JsonRpcTlsConfig>,
+    /// List of values of the `Origin` HTTP header that are accepted. If `None`, all origins are
+    /// accepted. Clients that don't provide an `Origin`, which is the case of everything that
+    /// isn't a browser, are always accepted.
+    pub allowed_origins: Option<Vec<String>>,
+    /// Duration during which the transactions that the runtime has reported as invalid are
+    /// refused, without being validated again, when they are submitted.
+    pub transactions_ban_duration: Duration,
+}
+
+/// See [`JsonRpcListenConfig::slow_subscriber_policy`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SlowSubscriberPolicy {
+    /// Discard the oldest notifications that haven't been sent yet. Responses to requests are
+    /// never discarded. If only responses are waiting to be sent, the new notification is
+    /// discarded instead.
+    ///
+    /// Note that some subscriptions, such as `chainHead_v1_follow`, can't be properly followed
+    /// by the client if some of their notifications are missing.
+    DropOldestNotifications,
+    /// Close the connection with the client.
+    Disconnect,
+}
+
+/// See [`ChainConfig::sync_mode`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Download and verify every block starting from the finalized block of the database.
+    Full,
+    /// Download and verify GrandPa warp sync proofs in order to jump to the latest finalized
+    /// block, then download its storage. Falls back to [`SyncMode::Full`] if the chain doesn't
+    /// use GrandPa or if the database is close to the head of the chain.
+    Warp,
+}
+
+/// See [`ChainConfig::pruning`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pruning {
+    /// Keep everything. Blocks that aren't descendants of the finalized block are only removed
+    /// from the database when the node restarts.
+    Archive,
+    /// Keep the storage and body of all the finalized blocks, but regularly remove the blocks
+    /// that aren't descendants of the finalized block.
+    ArchiveCanonical,
+    /// Same as [`Pruning::ArchiveCanonical`], but additionally remove the storage and body of
+    /// the finalized blocks whose height is more than this number of blocks below the finalized
+    /// block. Block headers and justifications are always kept.
+    KeepLast(u64),
+    /// Same as [`Pruning::KeepLast`], but only the storage of the old finalized blocks is
+    /// removed. Their body is kept.
+    ///
+    /// The trie nodes that are shared with the storage of more recent blocks are kept as well.
+    KeepStates(u64),
+}
+
+/// See [`ChainConfig::sqlite_options`].
+#[derive(Debug, Clone)]
+pub struct SqliteOptions {
+    /// How SQLite guarantees the atomicity of transactions. [`SqliteJournalMode::Wal`] is
+    /// generally the fastest, but requires the file system to support shared memory, which
+    /// network volumes might not.
+    pub journal_mode: SqliteJournalMode,
+    /// How often SQLite waits for the data to be physically written to the disk. Lower levels
+    /// are faster, especially on disks with a high latency, at the cost of durability.
+    pub synchronous: SqliteSynchronous,
+    /// Maximum amount of memory, in bytes, that SQLite reserves in order to memory-map the
+    /// database file. `0` disables memory-mapping.
+    pub memory_map_size: usize,
+    /// Size, in bytes, of the pages of the database. Must be a power of two between 512 and
+    /// 65536. Only applies when the database is created. If `None`, the default of SQLite is
+    /// used.
+    pub page_size: Option<u32>,
+    /// Number of additional read-only connections to the database, each in its own thread, used
+    /// to answer the JSON-RPC requests and the requests of light clients. Makes it possible for
+    /// slow queries to not delay the import of blocks. Each connection has its own cache, whose
+    /// size is [`ChainConfig::sqlite_cache_size`].
+    ///
+    /// Ignored if [`SqliteOptions::journal_mode`] isn't [`SqliteJournalMode::Wal`], as reading
+    /// the database would then prevent modifying it. If non-zero, the database isn't locked
+    /// exclusively, meaning that other processes can read it while the node is running. A lock
+    /// file next to the database nonetheless prevents other nodes from opening it.
+    ///
+    /// Defaults to 0, in which case the database is locked exclusively.
+    pub read_connections: usize,
+    /// If `true`, the database isn't locked exclusively, and can be read by other processes or
+    /// by other parts of the program with [`open_database_read_only`] while the node is
+    /// running. If `false`, the database is only accessible to others if
+    /// [`SqliteOptions::read_connections`] is non-zero. Similarly, a lock file next to the
+    /// database nonetheless prevents other nodes from opening it.
+    ///
+    /// Ignored if [`SqliteOptions::journal_mode`] isn't [`SqliteJournalMode::Wal`]. Defaults to
+    /// `false`.
+    pub external_read_access: bool,
+}
+
+impl SqliteOptions {
+    /// Returns the number of read-only connections to open, taking into account the journal
+    /// mode.
+    fn num_read_connections(&self) -> usize {
+        if self.journal_mode == SqliteJournalMode::Wal {
+            self.read_connections
+        } else {
+            0
+        }
+    }
+
+    /// Returns `true` if the database must be opened without being locked exclusively.
+    fn shared_access(&self) -> bool {
+        self.journal_mode == SqliteJournalMode::Wal
+            && (self.read_connections != 0 || self.external_read_access)
+    }
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        SqliteOptions {
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Normal,
+            memory_map_size: 1000000000,
+            page_size: None,
+            read_connections: 0,
+            external_read_access: false,
+        }
+    }
+}
+
+/// See [`JsonRpcListenConfig::tls`].
+#[derive(Debug, Clone)]
+pub struct JsonRpcTlsConfig {
+    /// Path to a PEM file containing the certificate chain presented to clients, starting with
+    /// the end-entity certificate.
+    pub certificate_chain_path: PathBuf,
+    /// Path to a PEM file containing the private key corresponding to the end-entity certificate.
+    pub private_key_path: PathBuf,
 }
 
 /// Allow generating logs.
@@ -97,12 +406,96 @@ pub enum LogLevel {
     Trace = 5,
 }
 
+/// Allow reporting the progress of the initialization of the node in a structured way.
+///
+/// Implemented on closures.
+pub trait ProgressCallback {
+    /// Report progress.
+    fn report(&self, progress: Progress);
+}
+
+impl<T: ?Sized + Fn(Progress)> ProgressCallback for T {
+    fn report(&self, progress: Progress) {
+        (*self)(progress)
+    }
+}
+
+/// Progress reported through a [`ProgressCallback`].
+#[derive(Debug, Clone)]
+pub enum Progress {
+    /// The database of the given chain is being opened. If the database is new, this is
+    /// followed with [`Progress::GenesisBuild`] reports.
+    DatabaseOpen {
+        /// Identifier of the chain, as found in its chain specification.
+        chain: String,
+    },
+    /// The genesis block of the given chain is being written to a new database. Can take a long
+    /// time for chains whose genesis storage is large.
+    GenesisBuild {
+        /// Identifier of the chain, as found in its chain specification.
+        chain: String,
+        /// Which step of the genesis build is in progress.
+        step: GenesisBuildStep,
+        /// Number of items of the step that have been processed.
+        processed: usize,
+        /// Total number of items of the step.
+        total: usize,
+    },
+    /// A phase of the startup has finished. See also [`Client::startup_report`].
+    StartupPhase {
+        /// Phase that has finished.
+        phase: StartupPhase,
+        /// Time the phase has taken. For phases that happen after [`start`] has returned, this is
+        /// the time elapsed since [`start`] has been called.
+        duration: Duration,
+    },
+}
+
+/// See [`Progress::GenesisBuild`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenesisBuildStep {
+    /// Determining the structure of the trie from the genesis storage. Items are storage entries.
+    TrieStructure,
+    /// Calculating the Merkle values of the trie nodes and writing them to the database. Items
+    /// are trie nodes.
+    MerkleValues,
+}
+
+/// See [`Progress::StartupPhase`] and [`StartupReport`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum StartupPhase {
+    /// See [`StartupReport::chain_spec_parse`].
+    #[display(fmt = "chain-spec-parse")]
+    ChainSpecParse,
+    /// See [`StartupReport::genesis_build`].
+    #[display(fmt = "genesis-build")]
+    GenesisBuild,
+    /// See [`StartupReport::database_open`].
+    #[display(fmt = "database-open")]
+    DatabaseOpen,
+    /// See [`StartupReport::network_listen`].
+    #[display(fmt = "network-listen")]
+    NetworkListen,
+    /// See [`StartupReport::initialization`].
+    #[display(fmt = "initialization")]
+    Initialization,
+    /// See [`StartupReport::first_peer`].
+    #[display(fmt = "first-peer")]
+    FirstPeer,
+    /// See [`StartupReport::first_block_import`].
+    #[display(fmt = "first-block-import")]
+    FirstBlockImport,
+}
+
 #[derive(Debug)]
 pub struct ChainConfig<'a> {
     /// Specification of the chain.
     pub chain_spec: Cow<'a, [u8]>,
     /// Identity and address of nodes to try to connect to on startup.
     pub additional_bootnodes: Vec<(peer_id::PeerId, multiaddr::Multiaddr)>,
+    /// Additional sources of bootnodes, queried on startup then periodically. Makes it possible
+    /// to use networks that publish their bootnodes dynamically.
+    pub bootnodes_providers: Vec<Arc<dyn BootnodesProvider>>,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     // TODO: also automatically add the same keys through ed25519?
     pub keystore_memory: Vec<Box<[u8; 64]>>,
@@ -110,12 +503,131 @@ pub struct ChainConfig<'a> {
     pub sqlite_database_path: Option<PathBuf>,
     /// Maximum size, in bytes, of the cache SQLite uses.
     pub sqlite_cache_size: usize,
+    /// Tuning of the way SQLite accesses the disk. Ignored if
+    /// [`ChainConfig::sqlite_database_path`] is `None`.
+    pub sqlite_options: SqliteOptions,
     /// Path to the directory where cryptographic keys are stored on disk.
     ///
     /// If `None`, no keys are stored in disk.
     pub keystore_path: Option<PathBuf>,
     /// Configuration of the JSON-RPC server. If `None`, no TCP server is started.
     pub json_rpc_listen: Option<JsonRpcListenConfig>,
+    /// If `true`, only the finalized chain is tracked and stored, and competing forks are
+    /// discarded as soon as possible. Appropriate for nodes that never author blocks.
+    pub finalized_chain_only: bool,
+    /// If `true`, each verified block is executed a second time one extrinsic at a time in order
+    /// to measure the resources used by each extrinsic. See [`Client::block_execution_profile`].
+    pub block_execution_profiling: bool,
+    /// How the node catches up with the head of the chain.
+    pub sync_mode: SyncMode,
+    /// If `true`, warp sync requests are also sent to a second peer and the two responses are
+    /// compared, in order to detect a single malicious peer.
+    pub cross_check_warp_sync: bool,
+    /// If `Some`, blocks whose fork point with the best chain is more than this number of blocks
+    /// below the best block are discarded. Protects against pathological forks when finality is
+    /// stalled.
+    pub max_reorg_depth: Option<u64>,
+    /// Maximum duration by which the slot of a block is allowed to start in the future compared
+    /// to the local clock. A typical value is 30 seconds.
+    pub slot_drift_tolerance: Duration,
+    /// Maximum extension of the time available for authoring a block when slots have been
+    /// skipped since the best block. `0` disables the extension.
+    pub max_slot_lenience: Duration,
+    /// Which blocks, storage and bodies are kept in the database.
+    pub pruning: Pruning,
+    /// If `Some`, the node periodically disconnects from the connected peer that is the most
+    /// behind and temporarily prevents reconnecting to it, in order to make room for newly
+    /// discovered peers. Rotations only happen if more peers than slots are known.
+    pub peer_rotation_interval: Option<Duration>,
+    /// If `Some`, every time this delay elapses while the node isn't connected to any peer of
+    /// the chain, the node tries again the peers saved in the database during a previous run and
+    /// the nodes of [`ChainConfig::fallback_bootnodes`].
+    pub bootstrap_fallback_delay: Option<Duration>,
+    /// Identity and address of well-known nodes, such as public DHT entry points, to try to
+    /// connect to only if none of the bootnodes is reachable. See
+    /// [`ChainConfig::bootstrap_fallback_delay`].
+    pub fallback_bootnodes: Vec<(peer_id::PeerId, multiaddr::Multiaddr)>,
+    /// Maximum number of peers the node tries to maintain an outgoing gossip link with.
+    pub max_out_peers: usize,
+    /// Number of outgoing gossip links below which the node considers that it lacks peers and
+    /// discovers new peers more aggressively. Must be inferior or equal to
+    /// [`ChainConfig::max_out_peers`].
+    pub min_out_peers: usize,
+    /// Maximum number of incoming gossip links the node accepts beyond the outgoing ones.
+    pub max_in_peers: usize,
+    /// If `true` and the chain specification contains a `protocolId`, the legacy protocol names
+    /// derived from it are accepted and advertised in addition to the protocol names derived
+    /// from the genesis hash. Makes it possible for older Substrate nodes to connect.
+    pub legacy_protocol_names: bool,
+    /// If `true`, the node takes part in the GrandPa rounds of the chain. If the keystore
+    /// contains a GrandPa key that belongs to the current authorities set, the node casts votes
+    /// signed with this key. Ignored for the relay chain.
+    ///
+    /// The voter is experimental.
+    pub grandpa_voter: bool,
+    /// If `true`, the offchain worker of the runtime is executed every time a new best block is
+    /// imported. Ignored for the relay chain.
+    pub offchain_worker: bool,
+    /// If `true`, the values that the runtime writes through the offchain indexing host
+    /// functions while blocks are being executed are stored in the offchain storage of the
+    /// database, where the offchain workers can read them. Ignored for the relay chain.
+    pub offchain_indexing: bool,
+    /// If `Some`, a checkpoint of the chain, from which light clients can be bootstrapped, is
+    /// periodically written to a file. Ignored for the relay chain.
+    pub checkpoint_export: Option<CheckpointExportConfig>,
+    /// If `Some`, the database of the chain is periodically copied to a file while the node is
+    /// running. Ignored for the relay chain.
+    pub database_backup: Option<DatabaseBackupConfig>,
+    /// If `Some`, path to a file generated by [`Client::export_state_snapshot`]. If the database
+    /// is empty, it is initialized from the finalized block and state found in this file rather
+    /// than from the genesis block, and the node resumes syncing from this finalized block.
+    /// Ignored if the database already contains data.
+    ///
+    /// The content of the file is trusted. The time spent importing it is reported as
+    /// [`StartupReport::genesis_build`].
+    pub state_snapshot: Option<PathBuf>,
+    /// If `Some`, the space of the database file that is no longer used, for example because of
+    /// the pruning, is periodically given back to the file system. See
+    /// [`Client::compact_database`]. Ignored for the relay chain.
+    pub database_compaction_interval: Option<Duration>,
+    /// Providers of the inherents to include in the blocks authored by the node, in addition to
+    /// the timestamp. Necessary in order to author blocks on chains whose runtime expects other
+    /// inherents. Ignored for the relay chain.
+    pub inherent_data_providers: Vec<Arc<dyn InherentDataProvider + Send + Sync>>,
+    /// If `true`, the Babe and GrandPa equivocations detected by the node are reported to the
+    /// chain through unsigned extrinsics, so that the offenders can be punished. Equivocations
+    /// are always logged, even if `false`. Ignored for the relay chain.
+    pub report_equivocations: bool,
+    /// Proportion of each slot, between `0.0` and `1.0`, after which the node stops including
+    /// transactions in the block it is authoring, in order to leave enough time for the block to
+    /// be finished and propagated before the end of the slot. Ignored for the relay chain.
+    pub authoring_slot_proportion: f32,
+    /// Maximum total size, in bytes, of the transactions included in the blocks authored by the
+    /// node. The inherents don't count towards this limit. Ignored for the relay chain.
+    pub max_authored_block_transactions_size: usize,
+    /// Maximum number of transactions waiting to be included in a block. Transactions submitted
+    /// while this limit is reached are refused.
+    pub max_pool_transactions: usize,
+    /// Maximum number of transactions waiting to be included in a block that have been signed by
+    /// the same account. Transactions submitted by the node itself aren't subject to this limit.
+    pub max_pool_transactions_per_submitter: usize,
+    /// Bounds of the number of blocks held in memory while syncing. Lower values make it
+    /// possible to run the node within a smaller memory budget, at the cost of a slower initial
+    /// sync.
+    pub sync_limits: SyncLimits,
+}
+
+/// Where to find the Ed25519 private key of the network identity of the node. See
+/// [`Config::libp2p_key`].
+pub enum Libp2pKey {
+    /// Key passed directly.
+    Memory(Box<[u8; 32]>),
+    /// Path to a file containing the hexadecimal-encoded key. If the file doesn't exist, a key is
+    /// randomly generated and written to this file, so that the node keeps the same [`PeerId`]
+    /// across restarts.
+    ///
+    /// On Unix platforms, a newly-created file is only readable and writable by its owner.
+    File(PathBuf),
 }
 
 /// Running client. As long as this object is alive, the client reads/writes the database and has
@@ -126,10 +638,44 @@ pub struct Client {
     consensus_service: Arc<consensus_service::ConsensusService>,
     relay_chain_consensus_service: Option<Arc<consensus_service::ConsensusService>>,
     network_service: Arc<network_service::NetworkService>,
+    network_service_chain_id: network_service::ChainId,
+    local_peer_id: PeerId,
     network_known_best: Arc<Mutex<Option<u64>>>,
+    parachain_inclusion: Arc<Mutex<Option<ParachainInclusion>>>,
+    startup_report: Arc<Mutex<StartupReport>>,
+    fd_budget: Arc<fd_budget::FdBudget>,
+    client_events: Arc<client_events::ClientEvents>,
+    database: Arc<database_thread::DatabaseThread>,
+}
+
+/// Duration of the phases of the startup of the client. See [`Client::startup_report`].
+#[derive(Debug, Clone)]
+pub struct StartupReport {
+    /// Time spent parsing the chain specifications and building their genesis chain information.
+    pub chain_spec_parse: Duration,
+    /// Time spent opening the databases, including [`StartupReport::genesis_build`].
+    pub database_open: Duration,
+    /// Time spent building the genesis block of the chain. `None` if the database already
+    /// existed.
+    pub genesis_build: Option<Duration>,
+    /// Time spent initializing the networking, including binding the listening sockets.
+    pub network_listen: Duration,
+    /// Total time spent in [`start`].
+    pub initialization: Duration,
+    /// Time between the call to [`start`] and the first gossip link with a peer of the chain.
+    /// `None` if no such link has been established yet.
+    pub first_peer: Option<Duration>,
+    /// Time between the call to [`start`] and the import of the first new block. `None` if no
+    /// block has been imported yet.
+    pub first_block_import: Option<Duration>,
 }
 
 impl Client {
+    /// Returns the [`PeerId`] of the local node, derived from [`Config::libp2p_key`].
+    pub fn local_peer_id(&self) -> &PeerId {
+        &self.local_peer_id
+    }
+
     /// Returns the address the JSON-RPC server is listening on.
     ///
     /// Returns `None` if and only if [`ChainConfig::json_rpc_listen`] was `None`
@@ -138,6 +684,174 @@ impl Client {
         self.json_rpc_service.listen_addr()
     }
 
+    /// Returns the address the admin JSON-RPC server is listening on.
+    ///
+    /// Returns `None` if and only if [`ChainConfig::json_rpc_listen`] was `None` or if
+    /// [`JsonRpcListenConfig::admin_address`] was `None` in [`Config::chain`].
+    pub fn json_rpc_admin_server_addr(&self) -> Option<SocketAddr> {
+        self.json_rpc_service.admin_listen_addr()
+    }
+
+    /// Returns the address the health server is listening on.
+    ///
+    /// Returns `None` if and only if [`ChainConfig::json_rpc_listen`] was `None` or if
+    /// [`JsonRpcListenConfig::health_address`] was `None` in [`Config::chain`].
+    pub fn health_server_addr(&self) -> Option<SocketAddr> {
+        self.json_rpc_service.health_listen_addr()
+    }
+
+    /// Returns statistics about the JSON-RPC requests of the chain that have been answered so
+    /// far, grouped by method. Includes the requests sent through
+    /// [`Client::send_json_rpc_request`].
+    pub fn json_rpc_metrics(&self) -> Vec<JsonRpcMethodMetrics> {
+        self.json_rpc_service.metrics()
+    }
+
+    /// Returns statistics about the accesses to the database of the chain since the client has
+    /// started, such as the duration of the reads and writes and the number of accesses waiting
+    /// to be executed.
+    pub async fn database_metrics(&self) -> DatabaseMetrics {
+        self.database.metrics().await
+    }
+
+    /// Returns the utilization of the budget of file descriptors configured through
+    /// [`Config::max_file_descriptors`].
+    pub fn file_descriptors_usage(&self) -> FileDescriptorsUsage {
+        self.fd_budget.usage()
+    }
+
+    /// Copies the database of the chain to the file at the given path. The file is replaced if
+    /// it already exists.
+    ///
+    /// The copy reflects the state of the database when the backup starts. The node keeps
+    /// running during the backup, but blocks aren't imported until the copy has finished.
+    pub async fn backup(&self, path: PathBuf) -> Result<(), DatabaseBackupError> {
+        database_backup::backup(&self.database, &path).await
+    }
+
+    /// Gives back to the file system the space of the database file that is no longer used,
+    /// for example because blocks have been pruned. Returns the number of bytes that have been
+    /// freed.
+    ///
+    /// The space is given back progressively, and the node keeps importing blocks in the
+    /// meanwhile.
+    pub async fn compact_database(&self) -> Result<u64, CompactDatabaseError> {
+        database_compaction::compact(&self.database).await
+    }
+
+    /// Imports the blocks of a file generated by the `export-blocks` command of Substrate, in
+    /// its binary format.
+    ///
+    /// The blocks are verified and executed exactly like blocks downloaded from the network,
+    /// which makes it possible to bootstrap the node from the data of another node. The first
+    /// block of the file must be a child of a block known by the node, and the blocks that are
+    /// already finalized are skipped.
+    ///
+    /// Returns once all the blocks of the file have been passed to the verification. Blocks
+    /// that fail to verify are reported through the logs.
+    ///
+    /// > **Note**: If [`SyncMode::Warp`] is used, the node might warp sync beyond the blocks of
+    /// >           the file, in which case these blocks are skipped. Use [`SyncMode::Full`] in
+    /// >           order to import all the blocks of the file.
+    pub async fn import_blocks(
+        &self,
+        path: PathBuf,
+    ) -> Result<ImportBlocksReport, ImportBlocksError> {
+        blocks_file::import(&self.consensus_service, &path).await
+    }
+
+    /// Writes the finalized block of the chain, its storage, and the information necessary to
+    /// verify its descendants to a file. The file is replaced if it already exists.
+    ///
+    /// The file can then be passed as [`ChainConfig::state_snapshot`] in order to initialize the
+    /// database of another node without having to sync the chain. Returns the number of the
+    /// block whose state has been exported.
+    ///
+    /// The node keeps running during the export, but blocks aren't imported until the storage
+    /// has been read. Child tries aren't included in the snapshot.
+    pub async fn export_state_snapshot(
+        &self,
+        path: PathBuf,
+    ) -> Result<u64, ExportStateSnapshotError> {
+        state_snapshot::export(
+            &self.database,
+            self.consensus_service.block_number_bytes(),
+            &path,
+        )
+        .await
+    }
+
+    /// Writes the blocks of the finalized chain whose height is within the given range to a
+    /// file, in the binary format of the `export-blocks` command of Substrate. The file can then
+    /// be imported by smoldot with [`Client::import_blocks`] or by other clients.
+    ///
+    /// If the end of the range is above the finalized block, the blocks are exported up to the
+    /// finalized block. Returns the number of blocks that have been exported.
+    ///
+    /// The bodies of the blocks must still be in the database. The justifications that the node
+    /// has verified are exported alongside their block.
+    pub async fn export_blocks(
+        &self,
+        path: PathBuf,
+        range: ops::RangeInclusive<u64>,
+    ) -> Result<u64, ExportBlocksError> {
+        blocks_file::export(
+            &self.database,
+            self.consensus_service.block_number_bytes(),
+            &path,
+            range,
+        )
+        .await
+    }
+
+    /// Re-executes the given block of the chain, and returns the storage accesses and host
+    /// function calls that the runtime has performed during the execution.
+    ///
+    /// The storage of the parent of the block must still be in the database.
+    pub async fn trace_block(&self, block_hash: [u8; 32]) -> Result<BlockTrace, TraceBlockError> {
+        self.json_rpc_service.trace_block(block_hash).await
+    }
+
+    /// Returns the GrandPa authorities set responsible for finalizing the given block, and the
+    /// Babe epoch the given block belongs to.
+    ///
+    /// This information is determined by replaying the headers of the chain starting from the
+    /// genesis block, and is therefore expensive to obtain for blocks with a high number.
+    pub async fn block_authorities(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Result<BlockAuthorities, BlockAuthoritiesError> {
+        self.json_rpc_service.block_authorities(block_hash).await
+    }
+
+    /// Returns the fee-related constants and weight-to-fee polynomials found in the metadata of
+    /// the runtime of the given block.
+    ///
+    /// Contrary to the `payment_queryInfo` JSON-RPC function, this doesn't require the runtime to
+    /// implement the `TransactionPaymentApi` runtime API. Use
+    /// [`metadata::FeeConstants::estimate_fee`] to estimate the fee of a transaction.
+    ///
+    /// The storage of the block must still be in the database.
+    pub async fn fee_constants(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Result<metadata::FeeConstants, FeeConstantsError> {
+        self.json_rpc_service.fee_constants(block_hash).await
+    }
+
+    /// Returns the nonce that the next transaction of the given account must use.
+    ///
+    /// `account_id` is the SCALE-encoded account id, typically a 32 bytes public key. The nonce
+    /// is obtained through the `AccountNonceApi` runtime API against the state of the current
+    /// best block, then increased for each transaction of this account submitted through the
+    /// JSON-RPC server that hasn't been included in a block yet.
+    pub async fn account_next_index(
+        &self,
+        account_id: &[u8],
+    ) -> Result<u64, AccountNextIndexError> {
+        self.json_rpc_service.account_next_index(account_id).await
+    }
+
     /// Returns the address the relay chain JSON-RPC server is listening on.
     ///
     /// Returns `None` if and only if [`Config::relay_chain`] was `None` or if
@@ -153,6 +867,23 @@ impl Client {
         *self.network_known_best.lock().await
     }
 
+    /// Returns the most recent inclusion of a candidate of the chain in the best chain of the
+    /// relay chain.
+    ///
+    /// Returns `None` if [`Config::relay_chain`] was `None`, or if no inclusion has been
+    /// detected yet since the client has started.
+    pub async fn parachain_inclusion(&self) -> Option<ParachainInclusion> {
+        self.parachain_inclusion.lock().await.clone()
+    }
+
+    /// Returns the duration of each phase of the startup of the client.
+    ///
+    /// The phases that happen after [`start`] has returned, such as the connection to the first
+    /// peer, are `None` until they have happened.
+    pub async fn startup_report(&self) -> StartupReport {
+        self.startup_report.lock().await.clone()
+    }
+
     /// Returns the current total number of peers of the client.
     // TODO: weird API
     pub async fn num_peers(&self) -> u64 {
@@ -165,11 +896,164 @@ impl Client {
         u64::try_from(self.network_service.num_connections().await).unwrap_or(u64::MAX)
     }
 
+    /// Returns the number of bytes exchanged with the network, in total, per peer, and per
+    /// protocol.
+    pub async fn bandwidth(&self) -> Bandwidth {
+        self.network_service.bandwidth().await
+    }
+
+    /// Returns the list of peers the node is gossip-connected to on the chain, alongside with
+    /// their role, best block, addresses, and the information they have reported through the
+    /// identify protocol.
+    pub async fn peers(&self) -> Vec<PeerInfo> {
+        self.network_service
+            .peers(self.network_service_chain_id)
+            .await
+    }
+
+    /// Adds the given address to the address book of the given peer and opens a connection to
+    /// this peer on the chain, if there isn't any already.
+    pub async fn dial(&self, peer_id: PeerId, address: multiaddr::Multiaddr) {
+        self.network_service
+            .dial(self.network_service_chain_id, peer_id, address)
+            .await
+    }
+
+    /// Starts disconnecting all the connections with the given peer.
+    pub async fn disconnect(&self, peer_id: PeerId) {
+        self.network_service.disconnect(peer_id).await
+    }
+
+    /// Returns the list of the most recent peers that have reported a genesis block hash
+    /// different from the one of the chain. Useful in order to diagnose misconfigured chain
+    /// specifications.
+    pub async fn genesis_mismatches(&self) -> Vec<GenesisMismatch> {
+        self.network_service
+            .genesis_mismatches(self.network_service_chain_id)
+            .await
+    }
+
+    /// Returns the total number of times a peer has reported a genesis block hash different
+    /// from the one of the chain since the node has started.
+    ///
+    /// Contrary to [`Client::genesis_mismatches`], this number isn't limited to the most recent
+    /// peers.
+    pub async fn num_genesis_mismatches(&self) -> u64 {
+        self.network_service
+            .num_genesis_mismatches(self.network_service_chain_id)
+            .await
+    }
+
     // TODO: not the best API
     pub async fn sync_state(&self) -> consensus_service::SyncState {
         self.consensus_service.sync_state().await
     }
 
+    /// Returns the execution profile of the given block of the chain, or `None` if it isn't
+    /// available.
+    ///
+    /// Profiles are only available if [`ChainConfig::block_execution_profiling`] was `true`, and
+    /// only for the most recently verified blocks.
+    pub async fn block_execution_profile(
+        &self,
+        block_hash: [u8; 32],
+    ) -> Option<BlockExecutionProfile> {
+        self.consensus_service
+            .block_execution_profile(block_hash)
+            .await
+    }
+
+    /// Returns the storage entries whose value differs between the storage of `old_block_hash`
+    /// and the storage of `new_block_hash`, ordered by key.
+    ///
+    /// The storage of both blocks must still be in the database. Comparing a block with its
+    /// parent is cheap, as only the parts of the storage that the block has modified are
+    /// visited.
+    pub async fn storage_diff(
+        &self,
+        old_block_hash: [u8; 32],
+        new_block_hash: [u8; 32],
+    ) -> Result<Vec<StorageDiffEntry>, StorageAccessError> {
+        self.database
+            .with_database_read(move |database| {
+                database.block_storage_diff(&old_block_hash, &new_block_hash)
+            })
+            .await
+    }
+
+    /// Returns statistics about the blocks authored by the local node for the chain, such as the
+    /// number of slots that have been missed.
+    ///
+    /// These statistics are persisted in the database and cover all the previous runs of the
+    /// node that have used the same database.
+    pub async fn authoring_stats(&self) -> AuthoringStats {
+        self.consensus_service.authoring_stats().await
+    }
+
+    /// Pauses or resumes the authoring of blocks of the chain, without interrupting the syncing
+    /// and the networking. The authoring is enabled when the client starts.
+    ///
+    /// Also available through the `sudo_unstable_setAuthoringEnabled` JSON-RPC function.
+    pub async fn set_authoring_enabled(&self, enabled: bool) {
+        self.consensus_service.set_authoring_enabled(enabled).await
+    }
+
+    /// Marks the given block of the chain and all its ancestors as finalized, without any
+    /// finality proof, then discards the blocks that don't descend from it.
+    ///
+    /// This is meant to be used on development chains that lack a finality mechanism, or in
+    /// order to recover a test network whose finality has stalled. The same functionality is
+    /// available through the `sudo_unstable_forceFinalize` JSON-RPC function.
+    ///
+    /// The block must have been verified by the node. Does nothing if the block is already the
+    /// finalized block.
+    pub async fn force_finalize(&self, block_hash: [u8; 32]) -> Result<(), ForceFinalizeError> {
+        self.consensus_service.force_finalize(block_hash).await
+    }
+
+    /// Builds a block on top of the current best block of the chain, without sealing, importing,
+    /// or announcing it. See [`BlockTemplate`].
+    ///
+    /// The block contains the inherents with a timestamp equal to the current time, and the
+    /// slot claim should thus correspond to the current time.
+    pub async fn build_block_template(
+        &self,
+        slot_claim: BlockTemplateSlotClaim,
+    ) -> Result<BlockTemplate, BlockTemplateError> {
+        self.consensus_service
+            .build_block_template(slot_claim)
+            .await
+    }
+
+    /// Returns a stream of events about the best and finalized blocks of the chain, its runtime
+    /// upgrades, and the peers of the chain that the node connects to or disconnects from.
+    ///
+    /// Only the events that happen after this function has been called are yielded. The stream
+    /// ends if it isn't polled quickly enough and too many events are waiting to be yielded,
+    /// in which case this function must be called again.
+    pub fn events(&self) -> impl futures_util::Stream<Item = ClientEvent> + Unpin + Send + 'static {
+        Box::pin(self.client_events.subscribe())
+    }
+
+    /// Returns a stream that yields the runtime version of the best block of the chain, then
+    /// yields it again every time it changes, either because of a runtime upgrade or because the
+    /// best block has switched to a fork with a different runtime.
+    ///
+    /// Useful in order to invalidate cached information about the runtime, such as its metadata.
+    pub fn runtime_version_updates(
+        &self,
+    ) -> impl futures_util::Stream<Item = executor::CoreVersion> + Unpin + Send + 'static {
+        let subscription =
+            json_rpc_service::SubscribeRuntimeVersion::new(self.consensus_service.clone());
+        Box::pin(futures_util::stream::unfold(
+            subscription,
+            |mut subscription| async move {
+                let runtime_version = subscription.next_runtime_version().await.clone();
+                Some((runtime_version, subscription))
+            },
+        ))
+    }
+
     // TODO: not the best API
     pub async fn relay_chain_sync_state(&self) -> Option<consensus_service::SyncState> {
         if let Some(s) = &self.relay_chain_consensus_service {
@@ -252,6 +1136,27 @@ pub enum StartError {
     RelayChainKeystoreInit(io::Error),
     /// Error initializing the Jaeger service.
     JaegerInit(io::Error),
+    /// Error loading or generating the network identity of the node.
+    Libp2pKeyLoad(io::Error),
+    /// Error creating the directory of the cache of compiled runtimes.
+    CompiledRuntimesCacheInit(io::Error),
+    /// Error moving the files of a corrupted database aside.
+    DatabaseQuarantine(io::Error),
+    /// Error loading the state snapshot used to initialize the database.
+    #[display(fmt = "Failed to load state snapshot {}: {error}", "path.display()")]
+    StateSnapshotLoad {
+        /// Path to the state snapshot.
+        path: PathBuf,
+        /// Error that happened.
+        error: LoadStateSnapshotError,
+    },
+    /// The storage found in the state snapshot doesn't match the state root of its finalized
+    /// block header.
+    #[display(fmt = "Mismatch between the storage and the header of the state snapshot")]
+    StateSnapshotStateRootMismatch,
+    /// Error opening one of the read-only connections to the database.
+    #[display(fmt = "Failed to open read-only connection: {_0}")]
+    DatabaseReadOnlyConnection(full_sqlite::InternalError),
 }
 
 /// Error potentially returned by [`Client::relay_chain_send_json_rpc_request`].
@@ -264,6 +1169,14 @@ pub enum RelayChainSendJsonRpcRequestError {
 /// Runs the node using the given configuration.
 // TODO: this function has several code paths that panic instead of returning an error; it is especially unclear what to do in case of database corruption, given that a database corruption would crash the node later on anyway
 pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
+    let start_instant = Instant::now();
+
+    // All the randomness used by the node is derived from this generator.
+    let mut randomness = match config.randomness_seed {
+        Some(seed) => rand::rngs::StdRng::from_seed(seed),
+        None => rand::rngs::StdRng::from_entropy(),
+    };
+
     let chain_spec = {
         chain_spec::ChainSpec::from_json_bytes(&config.chain.chain_spec)
             .map_err(StartError::ChainSpecParse)?
@@ -295,6 +1208,14 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         None => None,
     };
 
+    let chain_spec_parse_duration = start_instant.elapsed();
+    report_startup_phase(
+        &*config.log_callback,
+        &*config.progress_callback,
+        StartupPhase::ChainSpecParse,
+        chain_spec_parse_duration,
+    );
+
     // The `protocolId` field of chain specifications is deprecated. Print a warning.
     if chain_spec.protocol_id().is_some() {
         config.log_callback.log(
@@ -343,33 +1264,97 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         format!("sqlite-version; version={}", full_sqlite::sqlite_version()),
     );
 
-    let (database, database_existed) = {
-        let (db, existed) = open_database(
+    let genesis_build_threads = config.genesis_build_threads.unwrap_or_else(|| {
+        std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap())
+    });
+
+    // Budget of file descriptors shared between the databases and the networking.
+    let fd_budget = Arc::new(fd_budget::FdBudget::new(config.max_file_descriptors));
+    if config.chain.sqlite_database_path.is_some() {
+        fd_budget.add_database_handles(
+            fd_budget::SQLITE_DISK_DATABASE_FILE_DESCRIPTORS
+                * (1 + config.chain.sqlite_options.num_read_connections()),
+        );
+    }
+    if let Some(relay_chain) = &config.relay_chain {
+        if relay_chain.sqlite_database_path.is_some() {
+            fd_budget.add_database_handles(
+                fd_budget::SQLITE_DISK_DATABASE_FILE_DESCRIPTORS
+                    * (1 + relay_chain.sqlite_options.num_read_connections()),
+            );
+        }
+    }
+
+    let compiled_runtimes_cache = match config.compiled_runtimes_cache_path {
+        Some(path) => Some(Arc::new(
+            compiled_runtimes_cache::CompiledRuntimesCache::new(
+                path,
+                config.log_callback.clone(),
+                &mut randomness,
+            )
+            .map_err(StartError::CompiledRuntimesCacheInit)?,
+        )),
+        None => None,
+    };
+
+    let database_open_start = Instant::now();
+    let (database, genesis_build_duration) = {
+        let (db, genesis_build_duration) = open_database(
             &chain_spec,
             genesis_chain_information.as_ref(),
             config.chain.sqlite_database_path,
             config.chain.sqlite_cache_size,
+            &config.chain.sqlite_options,
+            config.chain.state_snapshot.as_deref(),
+            genesis_build_threads,
+            config.quarantine_corrupted_database,
+            compiled_runtimes_cache.as_deref(),
+            &*config.log_callback,
+            &*config.progress_callback,
         )
-        .await;
+        .await?;
 
-        (Arc::new(database_thread::DatabaseThread::from(db)), existed)
+        (Arc::new(db), genesis_build_duration)
     };
 
     let relay_chain_database = if let Some(relay_chain) = &config.relay_chain {
-        Some(Arc::new(database_thread::DatabaseThread::from(
+        Some(Arc::new(
             open_database(
                 relay_chain_spec.as_ref().unwrap(),
                 relay_genesis_chain_information.as_ref().unwrap().as_ref(),
                 relay_chain.sqlite_database_path.clone(),
                 relay_chain.sqlite_cache_size,
+                &relay_chain.sqlite_options,
+                relay_chain.state_snapshot.as_deref(),
+                genesis_build_threads,
+                config.quarantine_corrupted_database,
+                compiled_runtimes_cache.as_deref(),
+                &*config.log_callback,
+                &*config.progress_callback,
             )
-            .await
+            .await?
             .0,
-        )))
+        ))
     } else {
         None
     };
 
+    let database_open_duration = database_open_start.elapsed();
+    if let Some(genesis_build_duration) = genesis_build_duration {
+        report_startup_phase(
+            &*config.log_callback,
+            &*config.progress_callback,
+            StartupPhase::GenesisBuild,
+            genesis_build_duration,
+        );
+    }
+    report_startup_phase(
+        &*config.log_callback,
+        &*config.progress_callback,
+        StartupPhase::DatabaseOpen,
+        database_open_duration,
+    );
+
     let database_finalized_block_hash = database
         .with_database(|db| db.finalized_block_hash().unwrap())
         .await;
@@ -386,12 +1371,17 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     .unwrap()
     .number;
 
+    let mut libp2p_key = match config.libp2p_key {
+        Libp2pKey::Memory(key) => key,
+        Libp2pKey::File(path) => load_or_generate_libp2p_key(&path, &mut randomness)
+            .map_err(StartError::Libp2pKeyLoad)?,
+    };
     let noise_key = {
         let mut noise_static_key = zeroize::Zeroizing::new([0u8; 32]);
-        rand::thread_rng().fill_bytes(&mut *noise_static_key);
-        connection::NoiseKey::new(&config.libp2p_key, &noise_static_key)
+        randomness.fill_bytes(&mut *noise_static_key);
+        connection::NoiseKey::new(&libp2p_key, &noise_static_key)
     };
-    zeroize::Zeroize::zeroize(&mut *config.libp2p_key);
+    zeroize::Zeroize::zeroize(&mut *libp2p_key);
     let local_peer_id =
         peer_id::PublicKey::Ed25519(*noise_key.libp2p_public_ed25519_key()).into_peer_id();
 
@@ -404,19 +1394,59 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         tasks_executor: &mut |task| (config.tasks_executor)(task),
         service_name: local_peer_id.to_string(),
         jaeger_agent: config.jaeger_agent,
+        network_sampling: config.jaeger_sampling,
+        randomness_seed: randomness.gen(),
     })
     .await
     .map_err(StartError::JaegerInit)?;
 
+    // Shared between all the chains and between the consensus, JSON-RPC, and network services, so
+    // that JSON-RPC requests and call proof requests can't delay the verification and authoring of blocks.
+    let runtime_calls_limiter = Arc::new(runtime_calls_limiter::RuntimeCallsLimiter::new(
+        runtime_calls_limiter::Config {
+            max_total: std::thread::available_parallelism()
+                .unwrap_or(NonZeroUsize::new(1).unwrap()),
+            max_json_rpc: config.max_json_rpc_runtime_calls,
+        },
+    ));
+
+    // Shared between the JSON-RPC service and the network service, which both need the runtimes
+    // of the blocks of the chain.
+    let runtime_caches_service = Arc::new(runtime_caches_service::RuntimeCachesService::new(
+        runtime_caches_service::Config {
+            tasks_executor: config.tasks_executor.clone(),
+            database: database.clone(),
+            num_cache_entries: NonZeroUsize::new(16).unwrap(), // TODO: configurable?
+        },
+    ));
+    let relay_chain_runtime_caches_service = relay_chain_database.as_ref().map(|database| {
+        Arc::new(runtime_caches_service::RuntimeCachesService::new(
+            runtime_caches_service::Config {
+                tasks_executor: config.tasks_executor.clone(),
+                database: database.clone(),
+                num_cache_entries: NonZeroUsize::new(16).unwrap(), // TODO: configurable?
+            },
+        ))
+    });
+
+    let network_listen_start = Instant::now();
     let (network_service, network_service_chain_ids, network_events_receivers) =
         network_service::NetworkService::new(network_service::Config {
             listen_addresses: config.listen_addresses,
-            num_events_receivers: 2 + if relay_chain_database.is_some() { 1 } else { 0 },
+            num_events_receivers: 2
+                + if relay_chain_database.is_some() { 1 } else { 0 }
+                + if config.chain.grandpa_voter { 1 } else { 0 },
             chains: iter::once(network_service::ChainConfig {
                 log_name: chain_spec.id().to_owned(),
                 fork_id: chain_spec.fork_id().map(|n| n.to_owned()),
+                legacy_protocol_id: if config.chain.legacy_protocol_names {
+                    chain_spec.protocol_id().map(|n| n.to_owned())
+                } else {
+                    None
+                },
                 block_number_bytes: usize::from(chain_spec.block_number_bytes()),
                 database: database.clone(),
+                runtime_caches_service: runtime_caches_service.clone(),
                 grandpa_protocol_finalized_block_height: if matches!(
                     genesis_chain_information.as_ref().finality,
                     chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
@@ -446,8 +1476,12 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                         })
                         .await
                 },
-                max_in_peers: 25,
-                max_slots: 15,
+                max_in_peers: config.chain.max_in_peers,
+                max_out_peers: config.chain.max_out_peers,
+                min_out_peers: config.chain.min_out_peers,
+                peer_rotation_interval: config.chain.peer_rotation_interval,
+                bootstrap_fallback_delay: config.chain.bootstrap_fallback_delay,
+                fallback_bootnodes: config.chain.fallback_bootnodes,
                 bootstrap_nodes: {
                     let mut list = Vec::with_capacity(
                         chain_spec.boot_nodes().len() + config.chain.additional_bootnodes.len(),
@@ -481,14 +1515,21 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     list.extend(config.chain.additional_bootnodes);
                     list
                 },
+                bootnodes_providers: config.chain.bootnodes_providers.clone(),
             })
             .chain(
                 if let Some(relay_chains_specs) = &relay_chain_spec {
                     Some(network_service::ChainConfig {
                         log_name: relay_chains_specs.id().to_owned(),
                         fork_id: relay_chains_specs.fork_id().map(|n| n.to_owned()),
+                        legacy_protocol_id: if config.relay_chain.as_ref().unwrap().legacy_protocol_names {
+                            relay_chains_specs.protocol_id().map(|n| n.to_owned())
+                        } else {
+                            None
+                        },
                         block_number_bytes: usize::from(relay_chains_specs.block_number_bytes()),
                         database: relay_chain_database.clone().unwrap(),
+                        runtime_caches_service: relay_chain_runtime_caches_service.clone().unwrap(),
                         grandpa_protocol_finalized_block_height: if matches!(
                             genesis_chain_information.as_ref().finality,
                             chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
@@ -526,8 +1567,25 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                                 }
                             })
                             .await,
-                        max_in_peers: 25,
-                        max_slots: 15,
+                        max_in_peers: config.relay_chain.as_ref().unwrap().max_in_peers,
+                        max_out_peers: config.relay_chain.as_ref().unwrap().max_out_peers,
+                        min_out_peers: config.relay_chain.as_ref().unwrap().min_out_peers,
+                        peer_rotation_interval: config
+                            .relay_chain
+                            .as_ref()
+                            .unwrap()
+                            .peer_rotation_interval,
+                        bootstrap_fallback_delay: config
+                            .relay_chain
+                            .as_ref()
+                            .unwrap()
+                            .bootstrap_fallback_delay,
+                        fallback_bootnodes: config
+                            .relay_chain
+                            .as_ref()
+                            .unwrap()
+                            .fallback_bootnodes
+                            .clone(),
                         bootstrap_nodes: {
                             let mut list =
                                 Vec::with_capacity(relay_chains_specs.boot_nodes().len());
@@ -557,6 +1615,12 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                             }
                             list
                         },
+                        bootnodes_providers: config
+                            .relay_chain
+                            .as_ref()
+                            .unwrap()
+                            .bootnodes_providers
+                            .clone(),
                     })
                 } else {
                     None
@@ -572,14 +1636,46 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
             },
             log_callback: config.log_callback.clone(),
             jaeger_service: jaeger_service.clone(),
+            runtime_calls_limiter: runtime_calls_limiter.clone(),
+            max_outbound_connections_per_subnet: config.max_outbound_connections_per_subnet,
+            max_inbound_connections_per_ip: config.max_inbound_connections_per_ip,
+            inbound_connections_ip_allowlist: config.inbound_connections_ip_allowlist,
+            fd_budget: fd_budget.clone(),
+            socks5_proxy: config.socks5_proxy,
+            nat_port_mapping: config.nat_port_mapping,
+            max_upload_bytes_per_sec: config.max_upload_bytes_per_sec,
+            max_download_bytes_per_sec: config.max_download_bytes_per_sec,
+            randomness_seed: randomness.gen(),
         })
         .await
         .map_err(StartError::NetworkInit)?;
+    let network_listen_duration = network_listen_start.elapsed();
+
+    // The listening sockets and the databases are always opened, even if this exceeds the
+    // budget, in which case no connection can ever be established.
+    if !fd_budget.has_available_socket() {
+        let usage = fd_budget.usage();
+        config.log_callback.log(
+            LogLevel::Warn,
+            format!(
+                "file-descriptors-budget-exhausted; max={}; sockets={}; database={}",
+                usage.max.map_or(0, |max| max.get()),
+                usage.sockets,
+                usage.database
+            ),
+        );
+    }
+    report_startup_phase(
+        &*config.log_callback,
+        &*config.progress_callback,
+        StartupPhase::NetworkListen,
+        network_listen_duration,
+    );
 
     let mut network_events_receivers = network_events_receivers.into_iter();
 
     let keystore = Arc::new({
-        let mut keystore = keystore::Keystore::new(config.chain.keystore_path, rand::random())
+        let mut keystore = keystore::Keystore::new(config.chain.keystore_path, randomness.gen())
             .await
             .map_err(StartError::KeystoreInit)?;
         for mut private_key in config.chain.keystore_memory {
@@ -589,6 +1685,87 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         keystore
     });
 
+    // Print the addresses of the keys of the keystore using the SS58 prefix of the chain, so
+    // that they match what wallets display.
+    {
+        let chain_prefix = chain_spec
+            .ss58_prefix()
+            .unwrap_or(ss58::ChainPrefix::GENERIC_SUBSTRATE);
+        let mut keys = BTreeMap::<[u8; 32], Vec<keystore::KeyNamespace>>::new();
+        for (namespace, public_key) in keystore.keys().await {
+            keys.entry(public_key).or_default().push(namespace);
+        }
+        for (public_key, namespaces) in keys {
+            config.log_callback.log(
+                LogLevel::Info,
+                format!(
+                    "keystore-key; address={}; namespaces={:?}",
+                    ss58::encode(ss58::Decoded {
+                        chain_prefix,
+                        public_key
+                    }),
+                    namespaces
+                ),
+            );
+        }
+    }
+
+    let runtime_execution_threads = config.runtime_execution_threads.as_ref().map(|cfg| {
+        Arc::new(runtime_execution_threads::RuntimeExecutionThreads::new(
+            runtime_execution_threads::Config {
+                log_callback: &*config.log_callback,
+                num_threads: cfg.num_threads,
+                cores: &cfg.cores,
+            },
+        ))
+    });
+
+    // Channel towards the task that reports equivocations, spawned below.
+    let (equivocation_reports_tx, equivocation_reports_rx) = if config.chain.report_equivocations {
+        let (tx, rx) = async_channel::bounded(16);
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
+    // Pools of the transactions waiting to be included in a block.
+    let transactions_pool = Arc::new(transactions_pool::TransactionsPool::new(
+        transactions_pool::Config {
+            max_transactions: config.chain.max_pool_transactions,
+            max_transactions_per_submitter: config.chain.max_pool_transactions_per_submitter,
+        },
+    ));
+    let relay_chain_transactions_pool = config.relay_chain.as_ref().map(|relay_chain| {
+        Arc::new(transactions_pool::TransactionsPool::new(
+            transactions_pool::Config {
+                max_transactions: relay_chain.max_pool_transactions,
+                max_transactions_per_submitter: relay_chain.max_pool_transactions_per_submitter,
+            },
+        ))
+    });
+
+    // Transactions found invalid, either when submitted through the JSON-RPC service or when
+    // the transactions of the pools are validated again.
+    let banned_transactions = Arc::new(json_rpc_service::BannedTransactions::new(
+        config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(Duration::from_secs(30 * 60), |cfg| {
+                cfg.transactions_ban_duration
+            }),
+    ));
+    let relay_chain_banned_transactions = config.relay_chain.as_ref().map(|relay_chain| {
+        Arc::new(json_rpc_service::BannedTransactions::new(
+            relay_chain
+                .json_rpc_listen
+                .as_ref()
+                .map_or(Duration::from_secs(30 * 60), |cfg| {
+                    cfg.transactions_ban_duration
+                }),
+        ))
+    });
+
     let consensus_service = consensus_service::ConsensusService::new(consensus_service::Config {
         tasks_executor: {
             let executor = config.tasks_executor.clone();
@@ -600,9 +1777,29 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         network_service: (network_service.clone(), network_service_chain_ids[0]),
         database: database.clone(),
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-        keystore,
+        keystore: keystore.clone(),
         jaeger_service: jaeger_service.clone(),
-        slot_duration_author_ratio: 43691_u16,
+        // `u16::MAX` represents the entire slot.
+        slot_duration_author_ratio: (config.chain.authoring_slot_proportion.clamp(0.0, 1.0)
+            * f32::from(u16::MAX)) as u16,
+        max_block_transactions_size: config.chain.max_authored_block_transactions_size,
+        transactions_pool: transactions_pool.clone(),
+        inherent_data_providers: config.chain.inherent_data_providers.clone(),
+        block_import_hook: config.block_import_hook.clone(),
+        equivocation_reports: equivocation_reports_tx.clone(),
+        finalized_chain_only: config.chain.finalized_chain_only,
+        offchain_indexing: config.chain.offchain_indexing,
+        runtime_execution_threads: runtime_execution_threads.clone(),
+        sync_limits: config.chain.sync_limits.clone(),
+        randomness_seed: randomness.gen(),
+        compiled_runtimes_cache: compiled_runtimes_cache.clone(),
+        runtime_calls_limiter: runtime_calls_limiter.clone(),
+        block_execution_profiling: config.chain.block_execution_profiling,
+        warp_sync: matches!(config.chain.sync_mode, SyncMode::Warp),
+        cross_check_warp_sync: config.chain.cross_check_warp_sync,
+        max_reorg_depth: config.chain.max_reorg_depth,
+        slot_drift_tolerance: config.chain.slot_drift_tolerance,
+        max_slot_lenience: config.chain.max_slot_lenience,
     })
     .await
     .map_err(StartError::ConsensusServiceInit)?;
@@ -632,7 +1829,7 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                 keystore: Arc::new({
                     let mut keystore = keystore::Keystore::new(
                         config.relay_chain.as_ref().unwrap().keystore_path.clone(),
-                        rand::random(),
+                        randomness.gen(),
                     )
                     .await
                     .map_err(StartError::RelayChainKeystoreInit)?;
@@ -646,6 +1843,31 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                 }),
                 jaeger_service, // TODO: consider passing a different jaeger service with a different service name
                 slot_duration_author_ratio: 43691_u16,
+                max_block_transactions_size: 0,
+                transactions_pool: relay_chain_transactions_pool.clone().unwrap(),
+                inherent_data_providers: Vec::new(),
+                block_import_hook: None,
+                equivocation_reports: None,
+                finalized_chain_only: config.relay_chain.as_ref().unwrap().finalized_chain_only,
+                offchain_indexing: false,
+                runtime_execution_threads,
+                sync_limits: config.relay_chain.as_ref().unwrap().sync_limits.clone(),
+                randomness_seed: randomness.gen(),
+                compiled_runtimes_cache: compiled_runtimes_cache.clone(),
+                runtime_calls_limiter: runtime_calls_limiter.clone(),
+                block_execution_profiling: config
+                    .relay_chain
+                    .as_ref()
+                    .unwrap()
+                    .block_execution_profiling,
+                warp_sync: matches!(
+                    config.relay_chain.as_ref().unwrap().sync_mode,
+                    SyncMode::Warp
+                ),
+                cross_check_warp_sync: config.relay_chain.as_ref().unwrap().cross_check_warp_sync,
+                max_reorg_depth: config.relay_chain.as_ref().unwrap().max_reorg_depth,
+                slot_drift_tolerance: config.relay_chain.as_ref().unwrap().slot_drift_tolerance,
+                max_slot_lenience: config.relay_chain.as_ref().unwrap().max_slot_lenience,
             })
             .await
             .map_err(StartError::RelayChainConsensusServiceInit)?,
@@ -664,27 +1886,95 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     let json_rpc_service = json_rpc_service::JsonRpcService::new(json_rpc_service::Config {
         tasks_executor: config.tasks_executor.clone(),
         log_callback: config.log_callback.clone(),
-        database,
+        database: database.clone(),
         consensus_service: consensus_service.clone(),
         network_service: (network_service.clone(), network_service_chain_ids[0]),
+        runtime_calls_limiter: runtime_calls_limiter.clone(),
+        runtime_caches_service: runtime_caches_service.clone(),
+        transactions_pool: transactions_pool.clone(),
         bind_address: config.chain.json_rpc_listen.as_ref().map(|cfg| cfg.address),
+        admin_bind_address: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .and_then(|cfg| cfg.admin_address),
+        health_bind_address: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .and_then(|cfg| cfg.health_address),
         max_parallel_requests: 32,
         max_json_rpc_clients: config
             .chain
             .json_rpc_listen
+            .as_ref()
             .map_or(0, |cfg| cfg.max_json_rpc_clients),
+        max_json_rpc_clients_per_ip: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(0, |cfg| cfg.max_json_rpc_clients_per_ip),
+        max_requests_per_second_per_client: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(0, |cfg| cfg.max_requests_per_second_per_client),
+        max_subscriptions_per_client: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(0, |cfg| cfg.max_subscriptions_per_client),
+        max_pending_notifications_per_client: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(NonZeroUsize::new(1).unwrap(), |cfg| {
+                cfg.max_pending_notifications_per_client
+            }),
+        slow_subscriber_policy: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(SlowSubscriberPolicy::Disconnect, |cfg| {
+                cfg.slow_subscriber_policy
+            }),
+        multiplexed_subscriptions_buffer: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .and_then(|cfg| cfg.multiplexed_subscriptions_buffer),
+        tls: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .and_then(|cfg| cfg.tls.clone()),
+        banned_transactions: banned_transactions.clone(),
+        allowed_origins: config
+            .chain
+            .json_rpc_listen
+            .and_then(|cfg| cfg.allowed_origins),
         chain_name: chain_spec.name().to_owned(),
         chain_type: chain_spec.chain_type().to_owned(),
         chain_properties_json: chain_spec.properties().to_owned(),
+        chain_ss58_prefix: chain_spec
+            .ss58_prefix()
+            .unwrap_or(ss58::ChainPrefix::GENERIC_SUBSTRATE),
         chain_is_live: chain_spec.has_live_network(),
         genesis_block_hash: genesis_chain_information
             .as_ref()
             .finalized_block_header
             .hash(usize::from(chain_spec.block_number_bytes())),
+        genesis_chain_information: Arc::new(genesis_chain_information.clone()),
     })
     .await
     .map_err(StartError::JsonRpcServiceInit)?;
 
+    // Extracted ahead of time, as `config.relay_chain` is moved below.
+    let relay_chain_pruning = config
+        .relay_chain
+        .as_ref()
+        .map(|relay_chain| relay_chain.pruning);
+
     // Start the JSON-RPC service of the relay chain.
     // See remarks above.
     let relay_chain_json_rpc_service = if let Some(relay_chain_cfg) = config.relay_chain {
@@ -696,17 +1986,68 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                 database: relay_chain_database.clone().unwrap(),
                 consensus_service: relay_chain_consensus_service.clone().unwrap(),
                 network_service: (network_service.clone(), network_service_chain_ids[1]),
+                runtime_calls_limiter: runtime_calls_limiter.clone(),
+                runtime_caches_service: relay_chain_runtime_caches_service.clone().unwrap(),
+                transactions_pool: relay_chain_transactions_pool.clone().unwrap(),
                 bind_address: relay_chain_cfg
                     .json_rpc_listen
                     .as_ref()
                     .map(|cfg| cfg.address),
+                admin_bind_address: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .and_then(|cfg| cfg.admin_address),
+                health_bind_address: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .and_then(|cfg| cfg.health_address),
                 max_parallel_requests: 32,
                 max_json_rpc_clients: relay_chain_cfg
                     .json_rpc_listen
+                    .as_ref()
                     .map_or(0, |cfg| cfg.max_json_rpc_clients),
+                max_json_rpc_clients_per_ip: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(0, |cfg| cfg.max_json_rpc_clients_per_ip),
+                max_requests_per_second_per_client: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(0, |cfg| cfg.max_requests_per_second_per_client),
+                max_subscriptions_per_client: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(0, |cfg| cfg.max_subscriptions_per_client),
+                max_pending_notifications_per_client: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(NonZeroUsize::new(1).unwrap(), |cfg| {
+                        cfg.max_pending_notifications_per_client
+                    }),
+                slow_subscriber_policy: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(SlowSubscriberPolicy::Disconnect, |cfg| {
+                        cfg.slow_subscriber_policy
+                    }),
+                multiplexed_subscriptions_buffer: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .and_then(|cfg| cfg.multiplexed_subscriptions_buffer),
+                tls: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .and_then(|cfg| cfg.tls.clone()),
+                banned_transactions: relay_chain_banned_transactions.clone().unwrap(),
+                allowed_origins: relay_chain_cfg
+                    .json_rpc_listen
+                    .and_then(|cfg| cfg.allowed_origins),
                 chain_name: relay_chain_spec.name().to_owned(),
                 chain_type: relay_chain_spec.chain_type().to_owned(),
                 chain_properties_json: relay_chain_spec.properties().to_owned(),
+                chain_ss58_prefix: relay_chain_spec
+                    .ss58_prefix()
+                    .unwrap_or(ss58::ChainPrefix::GENERIC_SUBSTRATE),
                 chain_is_live: relay_chain_spec.has_live_network(),
                 genesis_block_hash: relay_genesis_chain_information
                     .as_ref()
@@ -714,6 +2055,9 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     .as_ref()
                     .finalized_block_header
                     .hash(usize::from(relay_chain_spec.block_number_bytes())),
+                genesis_chain_information: Arc::new(
+                    relay_genesis_chain_information.clone().unwrap(),
+                ),
             })
             .await
             .map_err(StartError::JsonRpcServiceInit)?,
@@ -722,6 +2066,165 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         None
     };
 
+    // Spawn the tasks pruning the databases.
+    for (database, pruning) in iter::once((&database, config.chain.pruning))
+        .chain(relay_chain_database.as_ref().zip(relay_chain_pruning))
+    {
+        let (keep_last, prune_bodies) = match pruning {
+            Pruning::Archive => continue,
+            Pruning::ArchiveCanonical => (None, false),
+            Pruning::KeepLast(n) => (Some(n), true),
+            Pruning::KeepStates(n) => (Some(n), false),
+        };
+
+        (config.tasks_executor)(Box::pin(pruning::run(
+            database.clone(),
+            keep_last,
+            prune_bodies,
+            config.log_callback.clone(),
+        )));
+    }
+
+    // Spawn the task exporting the blocks, if enabled.
+    if let Some(block_export) = config.block_export.clone() {
+        (config.tasks_executor)(Box::pin(block_export::run(
+            consensus_service.clone(),
+            database.clone(),
+            block_export,
+            config.log_callback.clone(),
+        )));
+    }
+
+    // Spawn the task reporting the blocks-related events to the subscribers of
+    // `Client::events`. The networking events are reported by the informant task below.
+    let client_events = Arc::new(client_events::ClientEvents::new());
+    (config.tasks_executor)(Box::pin(client_events::run(
+        consensus_service.clone(),
+        client_events.clone(),
+    )));
+
+    // Spawn the task exporting the checkpoints, if enabled.
+    if let Some(checkpoint_export) = config.chain.checkpoint_export.clone() {
+        (config.tasks_executor)(Box::pin(checkpoint_export::run(
+            database.clone(),
+            usize::from(chain_spec.block_number_bytes()),
+            checkpoint_export,
+            config.log_callback.clone(),
+        )));
+    }
+
+    // Spawn the task backing up the database, if enabled.
+    if let Some(database_backup) = config.chain.database_backup.clone() {
+        (config.tasks_executor)(Box::pin(database_backup::run(
+            database.clone(),
+            database_backup,
+            config.log_callback.clone(),
+        )));
+    }
+
+    // Spawn the task compacting the database, if enabled.
+    if let Some(interval) = config.chain.database_compaction_interval {
+        (config.tasks_executor)(Box::pin(database_compaction::run(
+            database.clone(),
+            interval,
+            config.log_callback.clone(),
+        )));
+    }
+
+    // Spawn the GrandPa voter, if enabled.
+    if config.chain.grandpa_voter {
+        (config.tasks_executor)(Box::pin(grandpa_voter::run(grandpa_voter::Config {
+            log_callback: config.log_callback.clone(),
+            consensus_service: consensus_service.clone(),
+            network_service: (network_service.clone(), network_service_chain_ids[0]),
+            network_events_receiver: network_events_receivers.next().unwrap(),
+            database: database.clone(),
+            keystore,
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            equivocation_reports: equivocation_reports_tx,
+        })));
+    }
+
+    // Spawn the equivocations reporter, if enabled.
+    if let Some(equivocations_rx) = equivocation_reports_rx {
+        (config.tasks_executor)(Box::pin(equivocation_reporter::run(
+            equivocation_reporter::Config {
+                log_callback: config.log_callback.clone(),
+                consensus_service: consensus_service.clone(),
+                database: database.clone(),
+                network_service: (network_service.clone(), network_service_chain_ids[0]),
+                transactions_pool: transactions_pool.clone(),
+                runtime_calls_limiter: runtime_calls_limiter.clone(),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                equivocations_rx,
+                randomness_seed: randomness.gen(),
+            },
+        )));
+    }
+
+    // Spawn the offchain workers, if enabled.
+    if config.chain.offchain_worker {
+        (config.tasks_executor)(Box::pin(offchain_worker::run(offchain_worker::Config {
+            log_callback: config.log_callback.clone(),
+            consensus_service: consensus_service.clone(),
+            database: database.clone(),
+            network_service: (network_service.clone(), network_service_chain_ids[0]),
+            transactions_pool: transactions_pool.clone(),
+            runtime_calls_limiter: runtime_calls_limiter.clone(),
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            randomness_seed: randomness.gen(),
+        })));
+    }
+
+    // Spawn the tasks that remove from the pools the transactions included in the best chain
+    // and the ones that are no longer valid.
+    (config.tasks_executor)(Box::pin(transactions_pool::run_maintenance(
+        transactions_pool::MaintenanceConfig {
+            log_callback: config.log_callback.clone(),
+            consensus_service: consensus_service.clone(),
+            database: database.clone(),
+            runtime_calls_limiter: runtime_calls_limiter.clone(),
+            transactions_pool: transactions_pool.clone(),
+            banned_transactions: banned_transactions.clone(),
+        },
+    )));
+    if let (
+        Some(relay_chain_consensus_service),
+        Some(relay_chain_database),
+        Some(pool),
+        Some(banned_transactions),
+    ) = (
+        &relay_chain_consensus_service,
+        &relay_chain_database,
+        &relay_chain_transactions_pool,
+        &relay_chain_banned_transactions,
+    ) {
+        (config.tasks_executor)(Box::pin(transactions_pool::run_maintenance(
+            transactions_pool::MaintenanceConfig {
+                log_callback: config.log_callback.clone(),
+                consensus_service: relay_chain_consensus_service.clone(),
+                database: relay_chain_database.clone(),
+                runtime_calls_limiter: runtime_calls_limiter.clone(),
+                transactions_pool: pool.clone(),
+                banned_transactions: banned_transactions.clone(),
+            },
+        )));
+    }
+
+    // Spawn the task tracking the inclusion of the candidates of the parachain in the relay
+    // chain.
+    let parachain_inclusion = Arc::new(Mutex::new(None));
+    if let (Some(relay_chain_consensus_service), Some((_, para_id))) =
+        (&relay_chain_consensus_service, chain_spec.relay_chain())
+    {
+        (config.tasks_executor)(Box::pin(parachain_inclusion::run(
+            relay_chain_consensus_service.clone(),
+            para_id,
+            usize::from(chain_spec.block_number_bytes()),
+            parachain_inclusion.clone(),
+        )));
+    }
+
     // Spawn the task printing the informant.
     // This is not just a dummy task that just prints on the output, but is actually the main
     // task that holds everything else alive. Without it, all the services that we have created
@@ -729,10 +2232,23 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     // For this reason, it must be spawned even if no informant is started, in which case we simply
     // inhibit the printing.
     let network_known_best = Arc::new(Mutex::new(None));
+    let startup_report = Arc::new(Mutex::new(StartupReport {
+        chain_spec_parse: chain_spec_parse_duration,
+        database_open: database_open_duration,
+        genesis_build: genesis_build_duration,
+        network_listen: network_listen_duration,
+        initialization: Duration::new(0, 0),
+        first_peer: None,
+        first_block_import: None,
+    }));
     (config.tasks_executor)(Box::pin({
         let mut main_network_events_receiver = network_events_receivers.next().unwrap();
         let network_service_chain_id = network_service_chain_ids[0];
         let network_known_best = network_known_best.clone();
+        let startup_report = startup_report.clone();
+        let client_events = client_events.clone();
+        let log_callback = config.log_callback.clone();
+        let progress_callback = config.progress_callback.clone();
 
         // TODO: shut down this task if the client stops?
         async move {
@@ -761,25 +2277,92 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     },
                     network_service::Event::Connected {
                         chain_id,
+                        peer_id,
                         best_block_number,
                         ..
-                    } if chain_id == network_service_chain_id => match *network_known_best {
-                        Some(n) if n >= best_block_number => {}
-                        _ => *network_known_best = Some(best_block_number),
-                    },
+                    } if chain_id == network_service_chain_id => {
+                        client_events.broadcast(ClientEvent::PeerConnected {
+                            peer_id,
+                            best_block_number,
+                        });
+
+                        let mut startup_report = startup_report.lock().await;
+                        if startup_report.first_peer.is_none() {
+                            let elapsed = start_instant.elapsed();
+                            startup_report.first_peer = Some(elapsed);
+                            report_startup_phase(
+                                &*log_callback,
+                                &*progress_callback,
+                                StartupPhase::FirstPeer,
+                                elapsed,
+                            );
+                        }
+                        drop(startup_report);
+
+                        match *network_known_best {
+                            Some(n) if n >= best_block_number => {}
+                            _ => *network_known_best = Some(best_block_number),
+                        }
+                    }
+                    network_service::Event::Disconnected { chain_id, peer_id }
+                        if chain_id == network_service_chain_id =>
+                    {
+                        client_events.broadcast(ClientEvent::PeerDisconnected { peer_id });
+                    }
                     _ => {}
                 }
             }
         }
     }));
 
+    // Spawn the task that waits for the first block to be imported, for the purpose of the
+    // startup report.
+    (config.tasks_executor)(Box::pin({
+        let consensus_service = consensus_service.clone();
+        let startup_report = startup_report.clone();
+        let log_callback = config.log_callback.clone();
+        let progress_callback = config.progress_callback.clone();
+        async move {
+            let subscription = consensus_service
+                .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+                .await;
+            let mut new_blocks = Box::pin(subscription.new_blocks);
+            loop {
+                match new_blocks.next().await {
+                    Some(consensus_service::Notification::Block { .. }) => break,
+                    Some(consensus_service::Notification::Finalized { .. }) => {}
+                    None => return,
+                }
+            }
+
+            let elapsed = start_instant.elapsed();
+            startup_report.lock().await.first_block_import = Some(elapsed);
+            report_startup_phase(
+                &*log_callback,
+                &*progress_callback,
+                StartupPhase::FirstBlockImport,
+                elapsed,
+            );
+            // Dropping `new_blocks` unsubscribes from the consensus service.
+        }
+    }));
+
+    let initialization_duration = start_instant.elapsed();
+    startup_report.lock().await.initialization = initialization_duration;
+    report_startup_phase(
+        &*config.log_callback,
+        &*config.progress_callback,
+        StartupPhase::Initialization,
+        initialization_duration,
+    );
+
     config.log_callback.log(
         LogLevel::Info,
         format!(
             "successful-initialization; local_peer_id={}; database_is_new={:?}; \
                 finalized_block_hash={}; finalized_block_number={}",
             local_peer_id,
-            !database_existed,
+            genesis_build_duration.is_some(),
             HashDisplay(&database_finalized_block_hash),
             database_finalized_block_number
         ),
@@ -792,42 +2375,169 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         json_rpc_service,
         relay_chain_json_rpc_service,
         network_service,
+        network_service_chain_id: network_service_chain_ids[0],
+        local_peer_id,
         network_known_best,
+        parachain_inclusion,
+        startup_report,
+        fd_budget,
+        client_events,
+        database,
     })
 }
 
+/// Loads the Ed25519 private key of the network identity from the given file, or generates one
+/// using `randomness` and writes it to the file if it doesn't exist.
+fn load_or_generate_libp2p_key(
+    path: &Path,
+    randomness: &mut impl rand::RngCore,
+) -> Result<Box<[u8; 32]>, io::Error> {
+    match fs::read_to_string(path) {
+        Ok(file_content) => {
+            let file_content = zeroize::Zeroizing::new(file_content);
+            let mut key = Box::new([0u8; 32]);
+            hex::decode_to_slice(file_content.trim(), &mut *key).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid libp2p secret key")
+            })?;
+            Ok(key)
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let mut key = Box::new([0u8; 32]);
+            randomness.fill_bytes(&mut *key);
+
+            let mut hex_encoded = zeroize::Zeroizing::new([0u8; 64]);
+            hex::encode_to_slice(*key, &mut *hex_encoded).unwrap();
+
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            // TODO: do something equivalent on Windows
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            io::Write::write_all(&mut options.open(path)?, &*hex_encoded)?;
+
+            Ok(key)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Reports through the logs that a startup phase has finished.
+fn report_startup_phase(
+    log_callback: &(dyn LogCallback + Send + Sync),
+    progress_callback: &(dyn ProgressCallback + Send + Sync),
+    phase: StartupPhase,
+    duration: Duration,
+) {
+    log_callback.log(
+        LogLevel::Debug,
+        format!("startup-phase; phase={}; duration={:?}", phase, duration),
+    );
+    progress_callback.report(Progress::StartupPhase { phase, duration });
+}
+
+/// Moves the database file at the given path, and its associated SQLite files, to a path that
+/// contains the current UNIX timestamp. Returns the new path of the database file.
+fn quarantine_database_files(path: &std::path::Path) -> Result<PathBuf, io::Error> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    let mut quarantine_path = None;
+    for suffix in ["", "-wal", "-shm"] {
+        let mut source = path.as_os_str().to_owned();
+        source.push(suffix);
+        let source = PathBuf::from(source);
+
+        let mut destination = path.as_os_str().to_owned();
+        destination.push(format!(".corrupted-{timestamp}{suffix}"));
+        let destination = PathBuf::from(destination);
+
+        // The write-ahead log and shared memory files don't necessarily exist.
+        match std::fs::rename(&source, &destination) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !suffix.is_empty() => {}
+            Err(err) => return Err(err),
+        }
+
+        quarantine_path.get_or_insert(destination);
+    }
+
+    Ok(quarantine_path.unwrap())
+}
+
 /// Opens the database from the file system, or create a new database if none is found.
 ///
 /// If `db_path` is `None`, open the database in memory instead.
 ///
-/// The returned boolean is `true` if the database existed before.
+/// The database is returned with the read-only connections configured in `sqlite_options`,
+/// alongside `None` if the database existed before, or the time it took to build the genesis
+/// block otherwise.
 ///
-/// # Panic
+/// Returns an error if the database is corrupted and its files can't be moved aside, or if the
+/// state snapshot used to initialize an empty database can't be loaded or is invalid, or if the
+/// read-only connections can't be opened.
 ///
-/// Panics if the database can't be open. This function is expected to be called from the `main`
-/// function.
+/// # Panic
 ///
+/// Panics if the database can't be open, unless `quarantine_corrupted_database` is `true` and
+/// the database is corrupted, in which case the database is moved aside and a new one is created.
+/// This function is expected to be called from the `main` function.
 async fn open_database(
     chain_spec: &chain_spec::ChainSpec,
     genesis_chain_information: chain::chain_information::ChainInformationRef<'_>,
     db_path: Option<PathBuf>,
     sqlite_cache_size: usize,
-) -> (full_sqlite::SqliteFullDatabase, bool) {
-    // The `unwrap()` here can panic for example in case of access denied.
-    match full_sqlite::open(full_sqlite::Config {
-        block_number_bytes: chain_spec.block_number_bytes().into(),
-        cache_size: sqlite_cache_size,
-        ty: if let Some(path) = &db_path {
-            full_sqlite::ConfigTy::Disk {
-                path,
-                memory_map_size: 1000000000, // TODO: make configurable
+    sqlite_options: &SqliteOptions,
+    state_snapshot: Option<&Path>,
+    genesis_build_threads: NonZeroUsize,
+    quarantine_corrupted_database: bool,
+    compiled_runtimes_cache: Option<&compiled_runtimes_cache::CompiledRuntimesCache>,
+    log_callback: &(dyn LogCallback + Send + Sync),
+    progress_callback: &(dyn ProgressCallback + Send + Sync),
+) -> Result<(database_thread::DatabaseThread, Option<Duration>), StartError> {
+    progress_callback.report(Progress::DatabaseOpen {
+        chain: chain_spec.id().to_owned(),
+    });
+
+    let database_open = loop {
+        let result = full_sqlite::open(full_sqlite::Config {
+            block_number_bytes: chain_spec.block_number_bytes().into(),
+            cache_size: sqlite_cache_size,
+            ty: if let Some(path) = &db_path {
+                full_sqlite::ConfigTy::Disk {
+                    path,
+                    memory_map_size: sqlite_options.memory_map_size,
+                    journal_mode: sqlite_options.journal_mode,
+                    synchronous: sqlite_options.synchronous,
+                    page_size: sqlite_options.page_size,
+                    shared_access: sqlite_options.shared_access(),
+                }
+            } else {
+                full_sqlite::ConfigTy::Memory
+            },
+        });
+
+        match (result, &db_path) {
+            (Ok(database_open), _) => break database_open,
+            (Err(error), Some(path)) if quarantine_corrupted_database && error.is_corruption() => {
+                let quarantine_path =
+                    quarantine_database_files(path).map_err(StartError::DatabaseQuarantine)?;
+                log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "database-quarantined; chain={}; error={}; quarantine_path={}",
+                        chain_spec.id(),
+                        error,
+                        quarantine_path.display()
+                    ),
+                );
             }
-        } else {
-            full_sqlite::ConfigTy::Memory
-        },
-    })
-    .unwrap()
-    {
+            // This can happen for example in case of access denied.
+            (Err(error), _) => panic!("Failed to open the database: {error}"),
+        }
+    };
+
+    let (database, genesis_build_duration) = match database_open {
         // Database already exists and contains data.
         full_sqlite::DatabaseOpen::Open(database) => {
             if database.block_hash_by_number(0).unwrap().next().unwrap()
@@ -838,26 +2548,80 @@ async fn open_database(
                 panic!("Mismatch between database and chain specification. Shutting down node.");
             }
 
-            (database, true)
+            (database, None)
         }
 
         // The database doesn't exist or is empty.
         full_sqlite::DatabaseOpen::Empty(empty) => {
-            let genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap(); // TODO: return error instead
+            let build_start = Instant::now();
+
+            // The database is initialized either from the genesis block or from the finalized
+            // block of the state snapshot, if any.
+            let snapshot = match state_snapshot {
+                Some(path) => Some(
+                    state_snapshot::load(path, chain_spec.block_number_bytes().into()).map_err(
+                        |error| StartError::StateSnapshotLoad {
+                            path: path.to_owned(),
+                            error,
+                        },
+                    )?,
+                ),
+                None => None,
+            };
+            let genesis_storage;
+            let (finalized_block_header, storage) = match &snapshot {
+                Some(snapshot) => (
+                    snapshot
+                        .chain_information
+                        .as_ref()
+                        .finalized_block_header
+                        .scale_encoding_vec(chain_spec.block_number_bytes().into()),
+                    snapshot
+                        .storage
+                        .as_ref()
+                        .unwrap()
+                        .iter()
+                        .map(|(key, value)| (&key[..], &value[..]))
+                        .collect::<Vec<_>>(),
+                ),
+                None => {
+                    genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap();
+                    (
+                        genesis_chain_information
+                            .finalized_block_header
+                            .scale_encoding_vec(chain_spec.block_number_bytes().into()),
+                        genesis_storage.iter().collect::<Vec<_>>(),
+                    )
+                }
+            };
+            let storage_value = |key: &[u8]| {
+                storage
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| *value)
+            };
 
             // In order to determine the state_version of the genesis block, we need to compile
             // the runtime.
+            // If compiled runtimes are cached, the runtime is compiled ahead of time so that the
+            // consensus service can later load it from the cache rather than compile it again.
             // TODO: return errors instead of panicking
             // TODO: consider not throwing away the runtime
-            let state_version = executor::host::HostVmPrototype::new(executor::host::Config {
-                module: genesis_storage.value(b":code").unwrap(),
-                heap_pages: executor::storage_heap_pages_to_value(
-                    genesis_storage.value(b":heappages"),
-                )
-                .unwrap(),
-                exec_hint: executor::vm::ExecHint::ValidateAndExecuteOnce,
-                allow_unresolved_imports: true,
-            })
+            let state_version = executor::host::HostVmPrototype::new_with_compiled_modules_cache(
+                executor::host::Config {
+                    module: storage_value(b":code").unwrap(),
+                    heap_pages: executor::storage_heap_pages_to_value(storage_value(b":heappages"))
+                        .unwrap(),
+                    exec_hint: if compiled_runtimes_cache.is_some() {
+                        executor::vm::ExecHint::ValidateAndCompile
+                    } else {
+                        executor::vm::ExecHint::ValidateAndExecuteOnce
+                    },
+                    allow_unresolved_imports: true,
+                },
+                compiled_runtimes_cache
+                    .map(|cache| cache as &dyn executor::vm::CompiledModulesCache),
+            )
             .unwrap()
             .runtime_version()
             .decode()
@@ -873,7 +2637,27 @@ async fn open_database(
             // TODO: poorly optimized
             let mut trie_structure = {
                 let mut trie_structure = trie::trie_structure::TrieStructure::new();
-                for (key, value) in genesis_storage.iter() {
+                let num_items = storage.len();
+                let progress_report_interval = cmp::max(1, num_items / 10);
+                for (item_index, (key, value)) in storage.iter().copied().enumerate() {
+                    if item_index % progress_report_interval == 0 {
+                        log_callback.log(
+                            LogLevel::Info,
+                            format!(
+                                "genesis-build-progress; chain={}; phase=trie-structure; processed={}; total={}",
+                                chain_spec.id(),
+                                item_index,
+                                num_items
+                            ),
+                        );
+                        progress_callback.report(Progress::GenesisBuild {
+                            chain: chain_spec.id().to_owned(),
+                            step: GenesisBuildStep::TrieStructure,
+                            processed: item_index,
+                            total: num_items,
+                        });
+                    }
+
                     match trie_structure.node(trie::bytes_to_nibbles(key.iter().copied())) {
                         trie::trie_structure::Entry::Vacant(e) => {
                             e.insert_storage_value().insert(
@@ -896,105 +2680,196 @@ async fn open_database(
                     }
                 }
 
-                // Calculate the Merkle values of the nodes.
-                for node_index in trie_structure
-                    .iter_ordered()
-                    .collect::<Vec<_>>()
-                    .into_iter()
-                    .rev()
-                {
-                    let mut node_access = trie_structure.node_by_index(node_index).unwrap();
-
-                    let children = core::array::from_fn::<_, 16, _>(|n| {
-                        node_access
-                            .child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap())
-                            .map(|mut child| child.user_data().1.as_ref().unwrap().clone())
-                    });
+                trie_structure
+            };
 
-                    let is_root_node = node_access.is_root_node();
-                    let partial_key = node_access.partial_key().collect::<Vec<_>>().into_iter();
-
-                    // We have to hash the storage value ahead of time if necessary due to borrow
-                    // checking difficulties.
-                    let storage_value_hashed =
-                        match (node_access.user_data().0.as_ref(), state_version) {
-                            (Some(v), 1) => {
-                                if v.len() >= 33 {
-                                    Some(blake2_rfc::blake2b::blake2b(32, &[], v))
-                                } else {
-                                    None
-                                }
-                            }
-                            _ => None,
-                        };
-                    let storage_value = match (
-                        node_access.user_data().0.as_ref(),
-                        storage_value_hashed.as_ref(),
-                    ) {
-                        (_, Some(storage_value_hashed)) => trie::trie_node::StorageValue::Hashed(
-                            <&[u8; 32]>::try_from(storage_value_hashed.as_bytes()).unwrap(),
-                        ),
-                        (Some(v), None) => trie::trie_node::StorageValue::Unhashed(&v[..]),
-                        (None, _) => trie::trie_node::StorageValue::None,
+            // Calculate the Merkle values of the nodes.
+            // The Merkle value of a node depends on the Merkle values of its children. Nodes
+            // are thus grouped by depth, and the nodes of each depth, starting from the
+            // deepest, are processed in parallel.
+            let levels = {
+                let mut depths = hashbrown::HashMap::with_capacity_and_hasher(
+                    trie_structure.len(),
+                    fnv::FnvBuildHasher::default(),
+                );
+                let mut levels = Vec::<Vec<trie::trie_structure::NodeIndex>>::new();
+                // Parents are always yielded before their children.
+                for node_index in trie_structure.iter_ordered().collect::<Vec<_>>() {
+                    let depth = match trie_structure
+                        .node_by_index(node_index)
+                        .unwrap()
+                        .into_parent()
+                    {
+                        Some(parent) => depths[&parent.node_index()] + 1,
+                        None => 0,
                     };
-
-                    let merkle_value = trie::trie_node::calculate_merkle_value(
-                        trie::trie_node::Decoded {
-                            children,
-                            partial_key,
-                            storage_value,
-                        },
-                        trie::HashFunction::Blake2,
-                        is_root_node,
-                    )
-                    .unwrap();
-
-                    node_access.into_user_data().1 = Some(merkle_value);
+                    depths.insert(node_index, depth);
+                    if levels.len() <= depth {
+                        levels.resize_with(depth + 1, Vec::new);
+                    }
+                    levels[depth].push(node_index);
                 }
-
-                trie_structure
+                levels
             };
 
-            // Build the iterator of trie nodes.
-            let genesis_storage_full_trie = trie_structure
-                .iter_unordered()
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|node_index| {
-                    let (storage_value, Some(merkle_value)) = &trie_structure[node_index] else {
-                        unreachable!()
-                    };
-                    // Cloning to solve borrow checker restriction. // TODO: optimize?
-                    let storage_value = if let Some(storage_value) = storage_value {
-                        // TODO: child tries support?
-                        full_sqlite::InsertTrieNodeStorageValue::Value {
-                            value: Cow::Owned(storage_value.to_vec()),
-                            references_merkle_value: false,
-                        }
-                    } else {
-                        full_sqlite::InsertTrieNodeStorageValue::NoValue
-                    };
-                    let merkle_value = merkle_value.as_ref().to_owned();
-                    let mut node_access = trie_structure.node_by_index(node_index).unwrap();
-
-                    full_sqlite::InsertTrieNode {
-                        storage_value,
-                        merkle_value: Cow::Owned(merkle_value),
-                        children_merkle_values: array::from_fn::<_, 16, _>(|n| {
-                            let child_index =
-                                trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap();
-                            node_access.child(child_index).map(|mut child| {
-                                Cow::Owned(child.user_data().1.as_ref().unwrap().as_ref().to_vec())
-                            })
-                        }),
-                        partial_key_nibbles: Cow::Owned(
-                            node_access.partial_key().map(u8::from).collect::<Vec<_>>(),
-                        ),
+            // Once the Merkle values of a level have been calculated, the nodes of this level
+            // are sent to a dedicated thread that inserts them in the database while the next
+            // level is being calculated. The channel is bounded in order to not accumulate
+            // nodes in memory if the database is slower than the calculation.
+            // The nodes are inserted before the database is initialized, so that the database
+            // is still considered empty, and is thus built again, if the node is interrupted
+            // in the middle of the insertion.
+            let mut empty = empty;
+            std::thread::scope(|outer_scope| {
+                let (batches_tx, batches_rx) =
+                    std::sync::mpsc::sync_channel::<Vec<full_sqlite::InsertTrieNode>>(2);
+                let empty = &mut empty;
+                let database_writer = outer_scope.spawn(move || {
+                    for batch in batches_rx {
+                        empty
+                            .insert_trie_nodes(batch.into_iter(), state_version)
+                            .unwrap();
                     }
                 });
 
-            // The finalized block is the genesis block. As such, it has an empty body and
-            // no justification.
+                let total_nodes = trie_structure.len();
+                let mut num_nodes_processed = 0;
+                let mut next_progress_report = 0;
+                for level in levels.into_iter().rev() {
+                    let inputs = level
+                        .iter()
+                        .map(|node_index| {
+                            let mut node_access =
+                                trie_structure.node_by_index(*node_index).unwrap();
+                            let children = core::array::from_fn::<_, 16, _>(|n| {
+                                node_access
+                                    .child(
+                                        trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap(),
+                                    )
+                                    .map(|mut child| child.user_data().1.as_ref().unwrap().clone())
+                            });
+                            let is_root_node = node_access.is_root_node();
+                            let partial_key = node_access.partial_key().collect::<Vec<_>>();
+                            let storage_value = node_access.user_data().0;
+                            (children, partial_key, storage_value, is_root_node)
+                        })
+                        .collect::<Vec<_>>();
+
+                    let merkle_values = std::thread::scope(|scope| {
+                        let chunk_size = inputs.len().div_ceil(genesis_build_threads.get());
+                        let threads = inputs
+                            .chunks(chunk_size)
+                            .map(|chunk| {
+                                scope.spawn(move || {
+                                    chunk
+                                        .iter()
+                                        .map(
+                                            |(
+                                                children,
+                                                partial_key,
+                                                storage_value,
+                                                is_root_node,
+                                            )| {
+                                                genesis_node_merkle_value(
+                                                    children,
+                                                    partial_key,
+                                                    *storage_value,
+                                                    *is_root_node,
+                                                    state_version,
+                                                )
+                                            },
+                                        )
+                                        .collect::<Vec<_>>()
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        threads
+                            .into_iter()
+                            .flat_map(|thread| thread.join().unwrap())
+                            .collect::<Vec<_>>()
+                    });
+
+                    // Build the list of trie nodes of this level to insert in the database.
+                    // Nodes are sorted by Merkle value, which is the primary key of the table
+                    // they are inserted in, in order to make the insertion cheaper.
+                    let mut batch = Vec::with_capacity(inputs.len());
+                    for ((node_index, merkle_value), (children, partial_key, storage_value, _)) in
+                        level.into_iter().zip(merkle_values).zip(inputs)
+                    {
+                        batch.push(full_sqlite::InsertTrieNode {
+                            storage_value: if let Some(storage_value) = storage_value {
+                                // TODO: child tries support?
+                                full_sqlite::InsertTrieNodeStorageValue::Value {
+                                    value: Cow::Borrowed(storage_value),
+                                    references_merkle_value: false,
+                                }
+                            } else {
+                                full_sqlite::InsertTrieNodeStorageValue::NoValue
+                            },
+                            merkle_value: Cow::Owned(merkle_value.as_ref().to_vec()),
+                            children_merkle_values: children.map(|child| {
+                                child.map(|child| Cow::Owned(child.as_ref().to_vec()))
+                            }),
+                            partial_key_nibbles: Cow::Owned(
+                                partial_key.into_iter().map(u8::from).collect::<Vec<_>>(),
+                            ),
+                        });
+
+                        trie_structure
+                            .node_by_index(node_index)
+                            .unwrap()
+                            .into_user_data()
+                            .1 = Some(merkle_value);
+                    }
+                    batch.sort_unstable_by(|a, b| a.merkle_value.cmp(&b.merkle_value));
+
+                    num_nodes_processed += batch.len();
+                    batches_tx.send(batch).unwrap();
+
+                    if num_nodes_processed >= next_progress_report {
+                        log_callback.log(
+                            LogLevel::Info,
+                            format!(
+                                "genesis-build-progress; chain={}; phase=merkle-values; processed={}; total={}",
+                                chain_spec.id(),
+                                num_nodes_processed,
+                                total_nodes
+                            ),
+                        );
+                        progress_callback.report(Progress::GenesisBuild {
+                            chain: chain_spec.id().to_owned(),
+                            step: GenesisBuildStep::MerkleValues,
+                            processed: num_nodes_processed,
+                            total: total_nodes,
+                        });
+                        next_progress_report = num_nodes_processed + cmp::max(1, total_nodes / 10);
+                    }
+                }
+
+                drop(batches_tx);
+                database_writer.join().unwrap();
+            });
+
+            // The storage of the snapshot is verified before the database is initialized, so
+            // that the database never contains a finalized block with an invalid state.
+            if snapshot.is_some() {
+                let state_root = header::decode(
+                    &finalized_block_header,
+                    chain_spec.block_number_bytes().into(),
+                )
+                .unwrap()
+                .state_root;
+                if trie_structure
+                    .root_user_data()
+                    .and_then(|(_, merkle_value)| merkle_value.as_ref())
+                    .map(|merkle_value| merkle_value.as_ref())
+                    != Some(&state_root[..])
+                {
+                    return Err(StartError::StateSnapshotStateRootMismatch);
+                }
+            }
+
+            // The genesis block has an empty body and no justification.
             let database = empty
                 .initialize(
                     &genesis_chain_information
@@ -1004,10 +2879,87 @@ async fn open_database(
                     None,
                 )
                 .unwrap();
-            database
-                .insert_trie_nodes(genesis_storage_full_trie, state_version)
-                .unwrap();
-            (database, false)
+
+            // Similar to what happens at the end of a warp sync, the finalized block of the
+            // snapshot is inserted without its ancestors, with the exception of the genesis
+            // block. Its body isn't part of the snapshot.
+            if snapshot.is_some() {
+                database
+                    .reset(&finalized_block_header, iter::empty(), None)
+                    .unwrap();
+            }
+
+            let build_duration = build_start.elapsed();
+            log_callback.log(
+                LogLevel::Info,
+                format!(
+                    "genesis-build-finished; chain={}; duration={:?}",
+                    chain_spec.id(),
+                    build_duration
+                ),
+            );
+
+            (database, Some(build_duration))
         }
-    }
+    };
+
+    // The read-only connections can only be opened once the database has been initialized.
+    let read_connections = match &db_path {
+        Some(path) => (0..sqlite_options.num_read_connections())
+            .map(|_| {
+                full_sqlite::open_read_only(full_sqlite::Config {
+                    block_number_bytes: chain_spec.block_number_bytes().into(),
+                    cache_size: sqlite_cache_size,
+                    ty: full_sqlite::ConfigTy::Disk {
+                        path,
+                        memory_map_size: sqlite_options.memory_map_size,
+                        journal_mode: sqlite_options.journal_mode,
+                        synchronous: sqlite_options.synchronous,
+                        page_size: sqlite_options.page_size,
+                        shared_access: true,
+                    },
+                })
+                .map_err(StartError::DatabaseReadOnlyConnection)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    Ok((
+        database_thread::DatabaseThread::with_read_connections(database, read_connections),
+        genesis_build_duration,
+    ))
+}
+
+/// Calculates the Merkle value of a node of the genesis trie, given the Merkle values of its
+/// children.
+fn genesis_node_merkle_value(
+    children: &[Option<trie::trie_node::MerkleValueOutput>; 16],
+    partial_key: &[trie::Nibble],
+    storage_value: Option<&[u8]>,
+    is_root_node: bool,
+    state_version: u8,
+) -> trie::trie_node::MerkleValueOutput {
+    let storage_value_hashed = match (storage_value, state_version) {
+        (Some(v), 1) if v.len() >= 33 => Some(blake2_rfc::blake2b::blake2b(32, &[], v)),
+        _ => None,
+    };
+    let storage_value = match (storage_value, storage_value_hashed.as_ref()) {
+        (_, Some(storage_value_hashed)) => trie::trie_node::StorageValue::Hashed(
+            <&[u8; 32]>::try_from(storage_value_hashed.as_bytes()).unwrap(),
+        ),
+        (Some(v), None) => trie::trie_node::StorageValue::Unhashed(v),
+        (None, _) => trie::trie_node::StorageValue::None,
+    };
+
+    trie::trie_node::calculate_merkle_value(
+        trie::trie_node::Decoded {
+            children: core::array::from_fn::<_, 16, _>(|n| children[n].as_ref()),
+            partial_key: partial_key.iter().copied(),
+            storage_value,
+        },
+        trie::HashFunction::Blake2,
+        is_root_node,
+    )
+    .unwrap()
 }
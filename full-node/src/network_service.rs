@@ -27,13 +27,17 @@
 // TODO: doc
 // TODO: re-review this once finished
 
-use crate::{database_thread, jaeger_service, LogCallback, LogLevel};
+use crate::{
+    database_thread, fd_budget, jaeger_service, runtime_caches_service, runtime_calls_limiter,
+    LogCallback, LogLevel,
+};
 
-use core::{cmp, future::Future, mem, pin::Pin, task::Poll, time::Duration};
+use core::{cmp, fmt, future::Future, mem, pin::Pin, task::Poll, time::Duration};
 use futures_channel::oneshot;
 use futures_lite::FutureExt as _;
 use futures_util::stream::{self, SelectAll};
 use hashbrown::HashMap;
+use rand::{Rng as _, SeedableRng as _};
 use smol::{
     channel, future,
     lock::Mutex,
@@ -41,6 +45,7 @@ use smol::{
     stream::{Stream, StreamExt as _},
 };
 use smoldot::{
+    chain::chain_information,
     database::full_sqlite,
     header,
     informant::{BytesDisplay, HashDisplay},
@@ -48,20 +53,81 @@ use smoldot::{
         connection,
         multiaddr::{self, Multiaddr, Protocol},
         peer_id::{self, PeerId},
+        websocket,
     },
-    network::{basic_peering_strategy, codec, service},
+    network::{basic_peering_strategy, codec, kademlia, service},
 };
 use std::{
     io,
-    net::{IpAddr, SocketAddr},
-    sync::Arc,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::{NonZeroU64, NonZeroUsize},
+    sync::{atomic, Arc},
     time::Instant,
     vec,
 };
 
-pub use smoldot::network::service::ChainId;
+pub use smoldot::network::service::{ChainId, ProtocolBandwidth, ProtocolKind};
 
+mod light_proofs;
+mod nat_pmp;
+mod state_response;
 mod tasks;
+mod upnp;
+
+/// Maximum number of peers that are queried simultaneously during a Kademlia discovery.
+const DISCOVERY_PARALLELISM: usize = 3;
+
+/// If a chain knows fewer peers than this value, discoveries are performed at a fixed and
+/// short interval rather than at an increasing interval. The same applies if a chain has fewer
+/// outgoing gossip links than [`ChainConfig::min_out_peers`].
+const DISCOVERY_PEERS_SHORTAGE_THRESHOLD: usize = 25;
+
+/// Period between two discoveries when a chain doesn't know or isn't connected to enough peers.
+/// See [`DISCOVERY_PEERS_SHORTAGE_THRESHOLD`].
+const DISCOVERY_SHORTAGE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Lifetime of the port mappings requested from the gateway. Mappings are renewed when half of
+/// their lifetime has elapsed.
+const PORT_MAPPING_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Delay before trying again to map a port after a failure.
+const PORT_MAPPING_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Number of different peers that must report the same IP address as being the one of the
+/// local node before this IP address is considered as an external address.
+const OBSERVED_ADDRESS_CONFIRMATIONS: usize = 3;
+
+/// Interval between two saves in the database of the peers known for each chain. The peers are
+/// also saved when the network service shuts down.
+const PEERS_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Interval between two queries to the same [`BootnodesProvider`].
+const BOOTNODES_PROVIDER_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Delay before querying again a [`BootnodesProvider`] that has returned an error.
+const BOOTNODES_PROVIDER_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Maximum number of incoming state requests whose response is being built at the same time.
+/// Further state requests are refused until one of these responses is sent back.
+const MAX_CONCURRENT_INCOMING_STATE_REQUESTS: usize = 4;
+
+/// Maximum number of incoming storage proof requests whose response is being built at the same
+/// time. Further storage proof requests are refused until one of these responses is sent back.
+const MAX_CONCURRENT_INCOMING_STORAGE_PROOF_REQUESTS: usize = 8;
+
+/// Maximum number of incoming call proof requests whose response is being built at the same
+/// time. Further call proof requests are refused until one of these responses is sent back.
+const MAX_CONCURRENT_INCOMING_CALL_PROOF_REQUESTS: usize = 4;
+
+/// Maximum duration of the building of the response to an incoming call proof request,
+/// including the time spent waiting for the runtime calls limiter. The request is refused if
+/// it takes longer than this.
+const CALL_PROOF_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum total size of the block bodies in a response to a blocks request. Once this size is
+/// reached, the response contains fewer blocks than requested and the remote is expected to
+/// request the remaining blocks afterwards.
+const MAX_BLOCKS_RESPONSE_BODIES_SIZE: u64 = 8 * 1024 * 1024;
 
 /// Configuration for a [`NetworkService`].
 pub struct Config {
@@ -75,6 +141,9 @@ pub struct Config {
     pub num_events_receivers: usize,
 
     /// Addresses to listen for incoming connections.
+    ///
+    /// Addresses ending with `/ws` accept WebSocket connections, which makes it possible for
+    /// light clients running in browsers to connect to the node.
     pub listen_addresses: Vec<Multiaddr>,
 
     /// List of block chains to be connected to.
@@ -90,6 +159,59 @@ pub struct Config {
 
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
+
+    /// Limiter that the runtime executions performed in order to answer call proof requests
+    /// must go through. These executions are subject to the same limit as the runtime
+    /// executions of the JSON-RPC requests.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// Maximum number of outgoing connections towards IP addresses of the same `/24` (IPv4) or
+    /// `/48` (IPv6) subnet. Makes it harder for an attacker controlling a small range of IP
+    /// addresses to surround the node. If `None`, no limit is enforced.
+    ///
+    /// The connections towards loopback or private IP addresses are never limited, as they are
+    /// typically used by local test networks.
+    pub max_outbound_connections_per_subnet: Option<NonZeroUsize>,
+
+    /// Maximum number of simultaneous incoming connections from the same IP address, either
+    /// being established or established. Makes it more expensive for an attacker to exhaust the
+    /// connection slots of the node. If `None`, no limit is enforced.
+    ///
+    /// The connections from loopback or private IP addresses are never limited, as they are
+    /// typically a local test network or a reverse proxy.
+    pub max_inbound_connections_per_ip: Option<NonZeroUsize>,
+
+    /// IP addresses to which [`Config::max_inbound_connections_per_ip`] doesn't apply, for
+    /// example the address of a reverse proxy or of other nodes of the same operator.
+    pub inbound_connections_ip_allowlist: Vec<IpAddr>,
+
+    /// Budget of file descriptors from which the listening sockets and the sockets of the
+    /// connections are allocated. Incoming connections are refused and outgoing connections are
+    /// postponed while the budget is exhausted.
+    pub fd_budget: Arc<fd_budget::FdBudget>,
+
+    /// If `Some`, all the outgoing TCP connections are established through the SOCKS5 proxy
+    /// found at this address, for example a Tor client. Domain names are then resolved by the
+    /// proxy.
+    pub socks5_proxy: Option<SocketAddr>,
+
+    /// If `true`, the node asks the gateway of the local network, through NAT-PMP or, if the
+    /// gateway doesn't support NAT-PMP, through UPnP, to forward the ports of the IPv4 TCP
+    /// listeners. The resulting public addresses are advertised to peers.
+    pub nat_port_mapping: bool,
+
+    /// If `Some`, maximum number of bytes per second sent to all the peers combined. Meant for
+    /// nodes running on metered connections.
+    pub max_upload_bytes_per_sec: Option<NonZeroU64>,
+
+    /// If `Some`, maximum number of bytes per second received from all the peers combined.
+    /// Meant for nodes running on metered connections.
+    pub max_download_bytes_per_sec: Option<NonZeroU64>,
+
+    /// Seed used to initialize all the randomness of the service, such as the choice of the
+    /// peers to connect to or the Kademlia random walks. Two services created with the same seed
+    /// and exposed to the same events behave identically.
+    pub randomness_seed: [u8; 32],
 }
 
 /// Configuration for one chain.
@@ -101,9 +223,17 @@ pub struct ChainConfig {
     /// network.
     pub bootstrap_nodes: Vec<(PeerId, Multiaddr)>,
 
+    /// Additional sources of bootnodes, queried when the service starts and then periodically.
+    /// The nodes they return are added to [`ChainConfig::bootstrap_nodes`].
+    pub bootnodes_providers: Vec<Arc<dyn BootnodesProvider>>,
+
     /// Database to use to read blocks from when answering requests.
     pub database: Arc<database_thread::DatabaseThread>,
 
+    /// Cache of the runtimes of the blocks of [`ChainConfig::database`]. Used when answering
+    /// call proof requests.
+    pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
     /// Hash of the genesis block of the chain. Sent to other nodes in order to determine whether
     /// the chains match.
     pub genesis_block_hash: [u8; 32],
@@ -115,6 +245,11 @@ pub struct ChainConfig {
     /// between chains with the same genesis hash.
     pub fork_id: Option<String>,
 
+    /// If `Some`, the legacy protocol names derived from this identifier are accepted,
+    /// advertised, and used as a fallback, in addition to the ones derived from the genesis hash
+    /// and fork id.
+    pub legacy_protocol_id: Option<String>,
+
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
@@ -122,12 +257,66 @@ pub struct ChainConfig {
     /// number of the finalized block at the time of the initialization.
     pub grandpa_protocol_finalized_block_height: Option<u64>,
 
-    /// Maximum number of peers that have slots attributed to them.
-    pub max_slots: usize,
+    /// Maximum number of peers that have slots attributed to them, in other words to which the
+    /// local node tries to maintain an outgoing gossip link.
+    pub max_out_peers: usize,
+
+    /// Number of outgoing gossip links below which the chain is considered as lacking peers, in
+    /// which case new peers are discovered more aggressively and peers are no longer rotated.
+    /// Must be inferior or equal to [`ChainConfig::max_out_peers`].
+    pub min_out_peers: usize,
 
     /// Maximum number of peers that have gossip links open but without having slots attributed
-    /// to them.
+    /// to them. Incoming gossip links beyond this limit are refused.
     pub max_in_peers: usize,
+
+    /// If `Some`, every time this interval elapses, the peer with a slot whose best block is
+    /// the furthest behind is disconnected in order to make room for a different peer. This
+    /// prevents a long-running node from staying stuck with the same neighbours.
+    ///
+    /// Nothing happens if no other peer is known to be able to replace the disconnected peer.
+    pub peer_rotation_interval: Option<Duration>,
+
+    /// If `Some`, every time this delay elapses while the chain has no peer at all, the peers
+    /// saved in the database during a previous run are tried again, and the nodes in
+    /// [`ChainConfig::fallback_bootnodes`] are added to the list of peers to try.
+    pub bootstrap_fallback_delay: Option<Duration>,
+
+    /// Well-known nodes of the peer-to-peer network, such as public DHT entry points, that are
+    /// only connected to if none of the [`ChainConfig::bootstrap_nodes`] is reachable. See
+    /// [`ChainConfig::bootstrap_fallback_delay`].
+    pub fallback_bootnodes: Vec<(PeerId, Multiaddr)>,
+}
+
+/// Source of bootnodes of a chain. See [`ChainConfig::bootnodes_providers`].
+///
+/// This makes it possible for networks whose bootnodes change over time to publish them
+/// dynamically, for example through DNS TXT records or through an HTTP registry.
+pub trait BootnodesProvider: Send + Sync {
+    /// Returns the identities and addresses of nodes that belong to the chain.
+    ///
+    /// In case of error, the error is logged and the provider is queried again later.
+    fn bootnodes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<(PeerId, Multiaddr)>, String>> + Send + '_>>;
+}
+
+impl fmt::Debug for dyn BootnodesProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BootnodesProvider").finish_non_exhaustive()
+    }
+}
+
+/// [`BootnodesProvider`] that always returns the same list of nodes.
+#[derive(Debug, Clone)]
+pub struct StaticBootnodes(pub Vec<(PeerId, Multiaddr)>);
+
+impl BootnodesProvider for StaticBootnodes {
+    fn bootnodes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<(PeerId, Multiaddr)>, String>> + Send + '_>> {
+        Box::pin(future::ready(Ok(self.0.clone())))
+    }
 }
 
 /// Event generated by the events reporters returned by [`NetworkService::new`].
@@ -152,8 +341,26 @@ pub enum Event {
     GrandpaNeighborPacket {
         chain_id: ChainId,
         peer_id: PeerId,
+        round_number: u64,
+        set_id: u64,
         finalized_block_height: u64,
     },
+    GrandpaVoteMessage {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        message: service::EncodedGrandpaVoteMessage,
+    },
+    GrandpaCatchUpRequest {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        round_number: u64,
+        set_id: u64,
+    },
+    GrandpaCatchUp {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        message: service::EncodedGrandpaCatchUp,
+    },
 }
 
 pub struct NetworkService {
@@ -178,6 +385,11 @@ enum ToBackground {
         severity: BanSeverity,
         reason: &'static str,
     },
+    ForegroundReportMisbehavior {
+        peer_id: PeerId,
+        chain_id: ChainId,
+        misbehavior: PeerMisbehavior,
+    },
     ForegroundAnnounceBlock {
         target: PeerId,
         chain_id: ChainId,
@@ -185,11 +397,34 @@ enum ToBackground {
         is_best: bool,
         result_tx: oneshot::Sender<Result<(), service::QueueNotificationError>>,
     },
+    ForegroundAnnounceTransaction {
+        chain_id: ChainId,
+        transaction: Vec<u8>,
+        result_tx: oneshot::Sender<Vec<PeerId>>,
+    },
     ForegroundSetLocalBestBlock {
         chain_id: ChainId,
         best_hash: [u8; 32],
         best_number: u64,
     },
+    ForegroundBroadcastGrandpaNotification {
+        chain_id: ChainId,
+        scale_encoded_notification: Vec<u8>,
+    },
+    ForegroundSendGrandpaCatchUpRequest {
+        target: PeerId,
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    },
+    ForegroundSendGrandpaCatchUp {
+        target: PeerId,
+        chain_id: ChainId,
+        scale_encoded_notification: Vec<u8>,
+    },
+    ForegroundSetGrandpaState {
+        chain_id: ChainId,
+        state: service::GrandpaState,
+    },
     ForegroundBlocksRequest {
         target: PeerId,
         chain_id: ChainId,
@@ -207,13 +442,13 @@ enum ToBackground {
         target: PeerId,
         chain_id: ChainId,
         config: codec::StorageProofRequestConfig<vec::IntoIter<Vec<u8>>>,
-        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, ()>>,
+        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, StorageProofRequestError>>,
     },
     ForegroundCallProofRequest {
         target: PeerId, // TODO: takes by value because of futures longevity issue
         chain_id: ChainId,
         config: codec::CallProofRequestConfig<'static, vec::IntoIter<Vec<u8>>>,
-        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, ()>>,
+        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, CallProofRequestError>>,
     },
     ForegroundGetNumConnections {
         result_tx: oneshot::Sender<usize>,
@@ -225,6 +460,29 @@ enum ToBackground {
     ForegroundGetNumTotalPeers {
         result_tx: oneshot::Sender<usize>,
     },
+    ForegroundGetGenesisMismatches {
+        chain_id: ChainId,
+        result_tx: oneshot::Sender<Vec<GenesisMismatch>>,
+    },
+    ForegroundGetNumGenesisMismatches {
+        chain_id: ChainId,
+        result_tx: oneshot::Sender<u64>,
+    },
+    ForegroundGetBandwidth {
+        result_tx: oneshot::Sender<Bandwidth>,
+    },
+    ForegroundGetPeers {
+        chain_id: ChainId,
+        result_tx: oneshot::Sender<Vec<PeerInfo>>,
+    },
+    ForegroundDial {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    ForegroundDisconnect {
+        peer_id: PeerId,
+    },
 }
 
 struct Inner {
@@ -252,6 +510,9 @@ struct Inner {
     /// Service to use to report traces.
     jaeger_service: Arc<jaeger_service::JaegerService>,
 
+    /// See [`Config::runtime_calls_limiter`].
+    runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
     /// Data structure holding the entire state of the networking.
     network:
         service::ChainNetwork<Chain, channel::Sender<service::CoordinatorToConnection>, Instant>,
@@ -259,14 +520,52 @@ struct Inner {
     /// Data structure holding the addresses and assigned slots.
     peering_strategy: basic_peering_strategy::BasicPeeringStrategy<ChainId, Instant>,
 
+    /// Source of randomness of the service. Derived from [`Config::randomness_seed`].
+    randomness: rand::rngs::StdRng,
+
     /// Current number of outgoing connection attempts.
     ///
     /// This counter is used to limit the number of simultaneous connection attempts, as some
     /// ISPs/cloud providers don't like seeing too many dialing connections at the same time.
     num_pending_out_attempts: usize,
 
-    /// Stream of incoming connections.
-    incoming_connections: SelectAll<Pin<Box<dyn Stream<Item = (TcpStream, SocketAddr)> + Send>>>,
+    /// Stream of incoming connections. The boolean indicates whether the socket must perform a
+    /// WebSocket handshake.
+    incoming_connections:
+        SelectAll<Pin<Box<dyn Stream<Item = (TcpStream, SocketAddr, bool)> + Send>>>,
+
+    /// Local address of each TCP listener, and whether it accepts WebSocket connections.
+    tcp_listeners: Vec<(SocketAddr, bool)>,
+
+    /// Sockets of the TCP listeners allocated from [`Inner::fd_budget`]. Only kept alive.
+    _tcp_listeners_fd_permits: Vec<fd_budget::SocketPermit>,
+
+    /// See [`Config::fd_budget`].
+    fd_budget: Arc<fd_budget::FdBudget>,
+
+    /// Stream of port mappings results. Each item contains the index within
+    /// [`Inner::tcp_listeners`] of the listener whose port has been mapped. Empty if
+    /// [`Config::nat_port_mapping`] is `false`.
+    port_mappings: SelectAll<
+        Pin<Box<dyn Stream<Item = (usize, Result<PortMapping, PortMappingError>)> + Send>>,
+    >,
+
+    /// For each entry in [`Inner::tcp_listeners`], the public address obtained through a port
+    /// mapping, if any.
+    nat_mapped_addresses: Vec<Option<Multiaddr>>,
+
+    /// Stream of results of the [`ChainConfig::bootnodes_providers`] of all chains.
+    bootnodes_providers: SelectAll<
+        Pin<Box<dyn Stream<Item = (ChainId, Result<Vec<(PeerId, Multiaddr)>, String>)> + Send>>,
+    >,
+
+    /// For each IP address that peers have reported as being the one of the local node, the
+    /// list of peers that have reported it. Contains at most
+    /// [`OBSERVED_ADDRESS_CONFIRMATIONS`] peers per address.
+    observed_ips: lru::LruCache<IpAddr, Vec<PeerId>>,
+
+    /// Addresses currently advertised to peers through the identify protocol.
+    external_addresses: Vec<Multiaddr>,
 
     /// See [`Config::tasks_executor`].
     tasks_executor: Box<dyn FnMut(Pin<Box<dyn Future<Output = ()> + Send>>) + Send>,
@@ -293,6 +592,20 @@ struct Inner {
         Option<service::ConnectionToCoordinator>,
     )>,
 
+    /// Incoming requests whose response is being built by a separate task. Entries are removed
+    /// when the task sends back the response on [`Inner::incoming_requests_responses_rx`], even
+    /// if the request has been cancelled in the meanwhile.
+    incoming_requests_tasks:
+        hashbrown::HashMap<service::SubstreamId, IncomingRequestTask, fnv::FnvBuildHasher>,
+
+    /// Channel where the tasks of [`Inner::incoming_requests_tasks`] send back their response.
+    incoming_requests_responses_rx:
+        Pin<Box<channel::Receiver<(service::SubstreamId, IncomingRequestResponse)>>>,
+
+    /// Sending side of [`Inner::incoming_requests_responses_rx`].
+    incoming_requests_responses_tx:
+        channel::Sender<(service::SubstreamId, IncomingRequestResponse)>,
+
     /// List of all block requests that have been started but not finished yet.
     blocks_requests: HashMap<
         service::SubstreamId,
@@ -310,14 +623,14 @@ struct Inner {
     /// List of all storage requests that have been started but not finished yet.
     storage_requests: HashMap<
         service::SubstreamId,
-        oneshot::Sender<Result<service::EncodedMerkleProof, ()>>,
+        oneshot::Sender<Result<service::EncodedMerkleProof, StorageProofRequestError>>,
         fnv::FnvBuildHasher,
     >,
 
     /// List of all call proof requests that have been started but not finished yet.
     call_proof_requests: HashMap<
         service::SubstreamId,
-        oneshot::Sender<Result<service::EncodedMerkleProof, ()>>,
+        oneshot::Sender<Result<service::EncodedMerkleProof, CallProofRequestError>>,
         fnv::FnvBuildHasher,
     >,
 
@@ -326,6 +639,50 @@ struct Inner {
 
     /// Time between [`Inner::next_discovery`] and the follow-up discovery.
     next_discovery_period: Duration,
+
+    /// Fires when the earliest [`Chain::next_peer_rotation`] is reached.
+    next_peer_rotation: smol::Timer,
+
+    /// Fires when the earliest [`Chain::next_bootstrap_fallback_check`] is reached.
+    next_bootstrap_fallback_check: smol::Timer,
+
+    /// When to save the known peers in the database next. See [`PEERS_SAVE_INTERVAL`].
+    next_peers_save: smol::Timer,
+
+    /// See [`Config::max_outbound_connections_per_subnet`].
+    max_outbound_connections_per_subnet: Option<NonZeroUsize>,
+
+    /// See [`Config::max_inbound_connections_per_ip`].
+    max_inbound_connections_per_ip: Option<NonZeroUsize>,
+
+    /// See [`Config::inbound_connections_ip_allowlist`].
+    inbound_connections_ip_allowlist: hashbrown::HashSet<IpAddr, fnv::FnvBuildHasher>,
+
+    /// Remote IP address of each incoming connection, either being established or established.
+    inbound_connections_ips: hashbrown::HashMap<service::ConnectionId, IpAddr, fnv::FnvBuildHasher>,
+
+    /// See [`Config::socks5_proxy`].
+    socks5_proxy: Option<SocketAddr>,
+
+    /// Subnet of the remote address of each outgoing connection, either being established or
+    /// established. Connections towards addresses that aren't IP addresses aren't in the list.
+    outbound_connections_subnets:
+        hashbrown::HashMap<service::ConnectionId, AddressSubnet, fnv::FnvBuildHasher>,
+
+    /// Bandwidth counters and rate limiters shared with all the connection tasks.
+    shared_bandwidth: Arc<tasks::SharedBandwidth>,
+
+    /// Bandwidth counters of each connection, and identity of the remote if known. The identity
+    /// is the expected one until the handshake is finished, then the actual one.
+    connections_bandwidth: hashbrown::HashMap<
+        service::ConnectionId,
+        (Option<PeerId>, Arc<tasks::BandwidthCounters>),
+        fnv::FnvBuildHasher,
+    >,
+
+    /// Information that peers we are connected to have sent as answer to identify requests.
+    /// Entries are removed when the last connection with the peer is closed.
+    peers_identify: hashbrown::HashMap<PeerId, PeerIdentify, fnv::FnvBuildHasher>,
 }
 
 /// Extra information of a chain.
@@ -336,14 +693,280 @@ struct Chain {
     /// How to access data to answer requests from the remotes.
     database: Arc<database_thread::DatabaseThread>,
 
-    /// Maximum number of peers that have slots attributed to them.
-    max_slots: usize,
+    /// See [`ChainConfig::runtime_caches_service`].
+    runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
 
-    /// Maximum number of peers that have gossip links open but without having slots attributed
-    /// to them.
+    /// See [`ChainConfig::max_out_peers`].
+    max_out_peers: usize,
+
+    /// See [`ChainConfig::min_out_peers`].
+    min_out_peers: usize,
+
+    /// See [`ChainConfig::max_in_peers`].
     max_in_peers: usize,
+
+    /// Peers whose block announces handshake has most recently reported a genesis block hash
+    /// different from the local one. Used for diagnostic purposes.
+    genesis_mismatches: lru::LruCache<PeerId, GenesisMismatch>,
+
+    /// Total number of genesis block hash mismatches detected since the service has started.
+    /// Contrary to [`Chain::genesis_mismatches`], not subject to any eviction.
+    num_genesis_mismatches: u64,
+
+    /// Request-response protocols that peers are known to not support, as learned from
+    /// previous protocol negotiation failures.
+    ///
+    /// Requests targeting a peer and a protocol found in this cache immediately fail without
+    /// any negotiation being attempted.
+    ///
+    /// Entries are intentionally not removed when peers disconnect, as peers typically reconnect
+    /// while still running the same software.
+    unsupported_protocols: lru::LruCache<PeerId, UnsupportedProtocols>,
+
+    /// Peers that are gossip-connected to this chain.
+    gossip_peers: hashbrown::HashMap<PeerId, GossipPeer, fnv::FnvBuildHasher>,
+
+    /// See [`ChainConfig::peer_rotation_interval`].
+    peer_rotation_interval: Option<Duration>,
+
+    /// When to disconnect the next peer because of [`Chain::peer_rotation_interval`].
+    /// Irrelevant if [`Chain::peer_rotation_interval`] is `None`.
+    next_peer_rotation: Instant,
+
+    /// See [`ChainConfig::bootstrap_fallback_delay`].
+    bootstrap_fallback_delay: Option<Duration>,
+
+    /// See [`ChainConfig::fallback_bootnodes`].
+    fallback_bootnodes: Vec<(PeerId, Multiaddr)>,
+
+    /// When to check next whether the chain has no peer and needs to fall back to other
+    /// sources of peers. Irrelevant if [`Chain::bootstrap_fallback_delay`] is `None`.
+    next_bootstrap_fallback_check: Instant,
+
+    /// Reputation of the peers that have misbehaved recently on this chain. Peers that aren't in
+    /// this list have a reputation of 0.
+    peers_reputation: lru::LruCache<PeerId, PeerReputation>,
+}
+
+/// Reputation of a peer on a chain. See [`Chain::peers_reputation`].
+#[derive(Debug, Copy, Clone)]
+struct PeerReputation {
+    /// Always inferior or equal to 0. Decreased when the peer misbehaves, and slowly increases
+    /// back towards 0 over time.
+    value: i32,
+    /// When [`PeerReputation::value`] was last updated.
+    last_update: Instant,
+}
+
+/// Subnet an IP address belongs to, used to limit the number of outgoing connections towards
+/// the same range of addresses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum AddressSubnet {
+    /// First three bytes of an IPv4 address.
+    Ipv4([u8; 3]),
+    /// First six bytes of an IPv6 address.
+    Ipv6([u8; 6]),
+}
+
+impl AddressSubnet {
+    /// Returns the subnet of the given address, or `None` if the address doesn't start with an
+    /// IP address (e.g. a DNS address) or if this IP address can't be reached from the public
+    /// Internet (e.g. a loopback address).
+    fn from_multiaddr(multiaddr: &Multiaddr) -> Option<Self> {
+        match multiaddr.iter().next()? {
+            Protocol::Ip4(ip) if is_global_ip(&IpAddr::from(ip)) => {
+                Some(AddressSubnet::Ipv4([ip[0], ip[1], ip[2]]))
+            }
+            Protocol::Ip6(ip) if is_global_ip(&IpAddr::from(ip)) => Some(AddressSubnet::Ipv6([
+                ip[0], ip[1], ip[2], ip[3], ip[4], ip[5],
+            ])),
+            _ => None,
+        }
+    }
+}
+
+/// See [`Chain::gossip_peers`].
+struct GossipPeer {
+    /// Role the peer has reported in its handshake.
+    role: codec::Role,
+    /// Best block of the peer, as reported by its handshake and block announces.
+    best_number: u64,
+    /// Hash of the block whose number is [`GossipPeer::best_number`].
+    best_hash: [u8; 32],
+}
+
+/// See [`Inner::peers_identify`].
+struct PeerIdentify {
+    /// See [`PeerInfo::agent_version`].
+    agent_version: String,
+    /// See [`PeerInfo::protocol_version`].
+    protocol_version: String,
+    /// See [`PeerInfo::protocols`].
+    protocols: Vec<String>,
+}
+
+/// See [`Inner::incoming_requests_tasks`].
+struct IncomingRequestTask {
+    /// Kind of request, used to limit the number of requests of each kind being served at the
+    /// same time.
+    kind: IncomingRequestKind,
+    /// `true` if the remote has cancelled the request, in which case the response must be
+    /// discarded.
+    cancelled: bool,
+}
+
+/// See [`IncomingRequestTask::kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IncomingRequestKind {
+    State,
+    StorageProof,
+    CallProof,
+}
+
+impl IncomingRequestKind {
+    /// Returns the maximum number of requests of this kind whose response can be built at the
+    /// same time.
+    fn max_concurrent(&self) -> usize {
+        match self {
+            IncomingRequestKind::State => MAX_CONCURRENT_INCOMING_STATE_REQUESTS,
+            IncomingRequestKind::StorageProof => MAX_CONCURRENT_INCOMING_STORAGE_PROOF_REQUESTS,
+            IncomingRequestKind::CallProof => MAX_CONCURRENT_INCOMING_CALL_PROOF_REQUESTS,
+        }
+    }
+}
+
+/// Response built by a task of [`Inner::incoming_requests_tasks`].
+enum IncomingRequestResponse {
+    State(Result<Vec<u8>, full_sqlite::StorageAccessError>),
+    StorageProof(Result<Vec<u8>, full_sqlite::StorageAccessError>),
+    CallProof(Result<Vec<u8>, light_proofs::CallProofError>),
+}
+
+/// Request-response protocols that a peer is known to not support.
+#[derive(Debug, Default, Copy, Clone)]
+struct UnsupportedProtocols {
+    /// Peer doesn't support GrandPa warp sync requests.
+    warp_sync: bool,
+    /// Peer doesn't support the light protocol, used for storage and call proof requests.
+    light: bool,
+}
+
+/// Information about a peer that reported, during the block announces handshake, a genesis block
+/// hash different from the local one.
+///
+/// This typically indicates that either the local node or the remote uses an incorrect chain
+/// specification.
+///
+/// > **Note**: Peers with a different fork id can't be detected, as the fork id is part of the
+/// >           name of the protocol and the remote will thus refuse to open the substream.
+#[derive(Debug, Clone)]
+pub struct GenesisMismatch {
+    /// Identity of the remote.
+    pub peer_id: PeerId,
+    /// Hash of the genesis block reported by the remote.
+    pub remote_genesis_hash: [u8; 32],
+    /// Role the remote reports playing on the network.
+    pub remote_role: codec::Role,
+    /// Number of times a mismatch has been detected with this remote.
+    pub num_mismatches: u32,
+    /// When the last mismatch has been detected.
+    pub last_mismatch: Instant,
+}
+
+/// Number of bytes exchanged with the network. See [`NetworkService::bandwidth`].
+#[derive(Debug, Clone)]
+pub struct Bandwidth {
+    /// Total number of bytes received on all the connections since the service has started,
+    /// including the overhead of the encryption and multiplexing layers.
+    pub total_bytes_in: u64,
+    /// Total number of bytes sent on all the connections since the service has started,
+    /// including the overhead of the encryption and multiplexing layers.
+    pub total_bytes_out: u64,
+    /// Number of bytes exchanged with each peer the node is currently connected to, through
+    /// all its connections. Includes the overhead of the encryption and multiplexing layers.
+    pub peers: Vec<PeerBandwidth>,
+    /// Number of bytes of payload exchanged through each kind of protocol since the service has
+    /// started, all peers and chains combined.
+    pub protocols: Vec<(ProtocolKind, ProtocolBandwidth)>,
+}
+
+/// Number of bytes exchanged with a peer. See [`Bandwidth::peers`].
+#[derive(Debug, Clone)]
+pub struct PeerBandwidth {
+    /// Identity of the remote.
+    pub peer_id: PeerId,
+    /// Number of bytes received from this peer through its current connections.
+    pub bytes_in: u64,
+    /// Number of bytes sent to this peer through its current connections.
+    pub bytes_out: u64,
+}
+
+/// Information about a peer we are gossip-connected to. See [`NetworkService::peers`].
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    /// Identity of the remote.
+    pub peer_id: PeerId,
+    /// Role the remote reports playing on the network.
+    pub role: codec::Role,
+    /// Height of the best block of the remote, as reported by its handshake and block announces.
+    pub best_number: u64,
+    /// Hash of the best block of the remote.
+    pub best_hash: [u8; 32],
+    /// Addresses of the connections with the remote that are currently established.
+    pub addresses: Vec<Multiaddr>,
+    /// Name and version of the software of the remote, as reported through the identify
+    /// protocol. `None` if the remote hasn't answered the identify request yet.
+    pub agent_version: Option<String>,
+    /// Name of the set of protocols supported by the remote, as reported through the identify
+    /// protocol. `None` if the remote hasn't answered the identify request yet.
+    pub protocol_version: Option<String>,
+    /// Names of the protocols supported by the remote, as reported through the identify
+    /// protocol. Empty if the remote hasn't answered the identify request yet.
+    pub protocols: Vec<String>,
+}
+
+/// Way in which a peer has misbehaved. See [`NetworkService::report_misbehavior`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum PeerMisbehavior {
+    /// Peer has announced a block whose header is invalid.
+    #[display(fmt = "invalid-block-announce")]
+    InvalidBlockAnnounce,
+    /// Peer has sent a justification or GrandPa commit that failed to verify.
+    #[display(fmt = "bad-justification")]
+    BadJustification,
+    /// Peer has sent a warp sync fragment that failed to verify.
+    #[display(fmt = "bad-warp-sync-fragment")]
+    BadWarpSyncFragment,
+    /// Peer has answered a request with a response that is invalid.
+    #[display(fmt = "malformed-response")]
+    MalformedResponse,
+    /// Peer hasn't answered a request in time.
+    #[display(fmt = "request-timeout")]
+    RequestTimeout,
+}
+
+impl PeerMisbehavior {
+    /// Value subtracted from the reputation of the peer.
+    fn reputation_cost(&self) -> i32 {
+        match self {
+            PeerMisbehavior::InvalidBlockAnnounce => 35,
+            PeerMisbehavior::BadJustification => 50,
+            PeerMisbehavior::BadWarpSyncFragment => 50,
+            PeerMisbehavior::MalformedResponse => 25,
+            PeerMisbehavior::RequestTimeout => 10,
+        }
+    }
 }
 
+/// Reputation below which a peer gets banned.
+const REPUTATION_BAN_THRESHOLD: i32 = -100;
+
+/// Reputation that peers recover every second.
+const REPUTATION_RECOVERY_PER_SECOND: i32 = 1;
+
+/// Duration of the ban of a peer whose reputation has dropped below [`REPUTATION_BAN_THRESHOLD`].
+const REPUTATION_BAN_DURATION: Duration = Duration::from_secs(5 * 60);
+
 /// Severity of a ban. See [`NetworkService::ban_and_disconnect`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BanSeverity {
@@ -353,6 +976,12 @@ pub enum BanSeverity {
 
 impl NetworkService {
     /// Initializes the network service with the given configuration.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainConfig::min_out_peers`] is superior to [`ChainConfig::max_out_peers`] for
+    /// one of the chains.
+    ///
     pub async fn new(
         config: Config,
     ) -> Result<
@@ -367,16 +996,19 @@ impl NetworkService {
             .map(|_| channel::bounded(16))
             .unzip();
 
+        let mut randomness = rand::rngs::StdRng::from_seed(config.randomness_seed);
+
         let mut network = service::ChainNetwork::new(service::Config {
             chains_capacity: config.chains.len(),
             connections_capacity: 100, // TODO: ?
             handshake_timeout: Duration::from_secs(8),
-            randomness_seed: rand::random(),
+            randomness_seed: randomness.gen(),
+            compress_response: Some(compress_response),
         });
 
         let mut peering_strategy =
             basic_peering_strategy::BasicPeeringStrategy::new(basic_peering_strategy::Config {
-                randomness_seed: rand::random(),
+                randomness_seed: randomness.gen(),
                 peers_capacity: 200, // TODO: ?
                 chains_capacity: config.chains.len(),
             });
@@ -384,10 +1016,21 @@ impl NetworkService {
         let mut chain_names =
             hashbrown::HashMap::with_capacity_and_hasher(config.chains.len(), Default::default());
 
+        let mut bootnodes_providers = SelectAll::new();
+
         for chain in config.chains {
+            assert!(chain.min_out_peers <= chain.max_out_peers);
+
+            // Peers saved in the database during a previous run of the node.
+            let known_peers = chain
+                .database
+                .with_database(|database| database.known_peers())
+                .await;
+
             let chain_id = network
                 .add_chain(service::ChainConfig {
                     fork_id: chain.fork_id.clone(),
+                    legacy_protocol_id: chain.legacy_protocol_id.clone(),
                     block_number_bytes: chain.block_number_bytes,
                     best_hash: chain.best_block.1,
                     best_number: chain.best_block.0,
@@ -402,11 +1045,31 @@ impl NetworkService {
                         },
                     ),
                     allow_inbound_block_requests: true,
+                    allow_inbound_checkpoint_requests: true,
+                    allow_inbound_state_requests: true,
+                    allow_inbound_light_requests: true,
                     user_data: Chain {
                         log_name: chain.log_name.clone(),
                         database: chain.database,
+                        runtime_caches_service: chain.runtime_caches_service,
                         max_in_peers: chain.max_in_peers,
-                        max_slots: chain.max_slots,
+                        max_out_peers: chain.max_out_peers,
+                        min_out_peers: chain.min_out_peers,
+                        genesis_mismatches: lru::LruCache::new(NonZeroUsize::new(32).unwrap()),
+                        num_genesis_mismatches: 0,
+                        unsupported_protocols: lru::LruCache::new(NonZeroUsize::new(256).unwrap()),
+                        peers_reputation: lru::LruCache::new(NonZeroUsize::new(1024).unwrap()),
+                        gossip_peers: hashbrown::HashMap::with_capacity_and_hasher(
+                            chain.max_out_peers + chain.max_in_peers,
+                            Default::default(),
+                        ),
+                        peer_rotation_interval: chain.peer_rotation_interval,
+                        next_peer_rotation: Instant::now()
+                            + chain.peer_rotation_interval.unwrap_or_default(),
+                        bootstrap_fallback_delay: chain.bootstrap_fallback_delay,
+                        fallback_bootnodes: chain.fallback_bootnodes,
+                        next_bootstrap_fallback_check: Instant::now()
+                            + chain.bootstrap_fallback_delay.unwrap_or_default(),
                     },
                 })
                 .unwrap(); // TODO: don't unwrap?
@@ -418,11 +1081,61 @@ impl NetworkService {
                 peering_strategy.insert_address(&peer_id, addr.into_bytes(), usize::MAX);
             }
 
+            match known_peers {
+                Ok(known_peers) => {
+                    let num_restored = restore_known_peers(
+                        &mut peering_strategy,
+                        &mut network[chain_id],
+                        chain_id,
+                        known_peers,
+                    );
+
+                    config.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "known-peers-restored; chain={}; num_peers={}",
+                            chain.log_name, num_restored
+                        ),
+                    );
+                }
+                Err(error) => {
+                    config.log_callback.log(
+                        LogLevel::Warn,
+                        format!(
+                            "known-peers-restore-error; chain={}; error={}",
+                            chain.log_name, error
+                        ),
+                    );
+                }
+            }
+
+            for provider in chain.bootnodes_providers {
+                bootnodes_providers.push(Box::pin(stream::unfold(
+                    None,
+                    move |next_query: Option<Duration>| {
+                        let provider = provider.clone();
+                        async move {
+                            if let Some(next_query) = next_query {
+                                smol::Timer::after(next_query).await;
+                            }
+
+                            let result = provider.bootnodes().await;
+                            let next_query = match &result {
+                                Ok(_) => BOOTNODES_PROVIDER_REFRESH_INTERVAL,
+                                Err(_) => BOOTNODES_PROVIDER_RETRY_DELAY,
+                            };
+                            Some(((chain_id, result), Some(next_query)))
+                        }
+                    },
+                )) as Pin<Box<_>>);
+            }
+
             chain_names.insert(chain_id, chain.log_name);
         }
 
         let (to_background_tx, to_background_rx) = channel::bounded(16);
         let (from_connections_tx, from_connections_rx) = channel::bounded(64);
+        let (incoming_requests_responses_tx, incoming_requests_responses_rx) = channel::unbounded();
 
         let local_peer_id =
             peer_id::PublicKey::Ed25519(*config.noise_key.libp2p_public_ed25519_key())
@@ -431,38 +1144,71 @@ impl NetworkService {
         // For each listening address in the configuration, create a background task dedicated to
         // listening on that address.
         let mut incoming_connections = SelectAll::new();
+        let mut tcp_listeners = Vec::with_capacity(config.listen_addresses.len());
+        let mut tcp_listeners_fd_permits = Vec::with_capacity(config.listen_addresses.len());
         for listen_address in config.listen_addresses {
             // Try to parse the requested address and create the corresponding listening socket.
-            let tcp_listener: smol::net::TcpListener = {
+            let (tcp_listener, is_websocket): (smol::net::TcpListener, bool) = {
                 let addr = {
                     let mut iter = listen_address.iter();
                     let proto1 = iter.next();
                     let proto2 = iter.next();
                     let proto3 = iter.next();
-                    match (proto1, proto2, proto3) {
-                        (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port)), None) => {
-                            Some(SocketAddr::from((ip, port)))
+                    let proto4 = iter.next();
+                    match (proto1, proto2, proto3, proto4) {
+                        (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port)), None, None) => {
+                            Some((SocketAddr::from((ip, port)), false))
                         }
-                        (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port)), None) => {
-                            Some(SocketAddr::from((ip, port)))
+                        (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port)), None, None) => {
+                            Some((SocketAddr::from((ip, port)), false))
                         }
+                        (
+                            Some(Protocol::Ip4(ip)),
+                            Some(Protocol::Tcp(port)),
+                            Some(Protocol::Ws),
+                            None,
+                        ) => Some((SocketAddr::from((ip, port)), true)),
+                        (
+                            Some(Protocol::Ip6(ip)),
+                            Some(Protocol::Tcp(port)),
+                            Some(Protocol::Ws),
+                            None,
+                        ) => Some((SocketAddr::from((ip, port)), true)),
                         _ => None,
                     }
                 };
 
-                if let Some(addr) = addr {
+                // Addresses that are well-formed but use a transport other than TCP or
+                // WebSocket are reported with a dedicated error rather than as malformed.
+                if listen_address.iter().any(|protocol| {
+                    matches!(
+                        protocol,
+                        Protocol::WebRtcDirect | Protocol::Quic | Protocol::QuicV1
+                    )
+                }) {
+                    return Err(InitError::UnsupportedListenTransport(listen_address));
+                }
+
+                if let Some((addr, is_websocket)) = addr {
                     match smol::net::TcpListener::bind(addr).await {
-                        Ok(l) => l,
+                        Ok(l) => (l, is_websocket),
                         Err(err) => {
                             return Err(InitError::ListenerIo(listen_address, err));
                         }
                     }
                 } else {
-                    // TODO: support WebSocket server
                     return Err(InitError::BadListenMultiaddr(listen_address));
                 }
             };
 
+            // The local address might differ from the requested one, for example if the
+            // requested port is 0.
+            match tcp_listener.local_addr() {
+                Ok(local_addr) => tcp_listeners.push((local_addr, is_websocket)),
+                Err(err) => return Err(InitError::ListenerIo(listen_address, err)),
+            }
+            tcp_listeners_fd_permits.push(config.fd_budget.acquire_socket());
+
             // Add a task dedicated to this listener.
             let log_callback = config.log_callback.clone();
             incoming_connections.push(Box::pin(stream::unfold(tcp_listener, move |tcp_listener| {
@@ -471,7 +1217,7 @@ impl NetworkService {
                     loop {
                         match tcp_listener.accept().await {
                             Ok((socket, socket_addr)) => {
-                                break Some(((socket, socket_addr), tcp_listener))
+                                break Some(((socket, socket_addr, is_websocket), tcp_listener))
                             }
                             Err(error) => {
                                 // Errors here can happen if the accept failed, for example
@@ -490,6 +1236,45 @@ impl NetworkService {
             })) as Pin<Box<_>>);
         }
 
+        // For each IPv4 listener, create a stream that maps its port on the gateway and renews
+        // the mapping periodically.
+        let mut port_mappings = SelectAll::new();
+        if config.nat_port_mapping {
+            if let Err(error) = nat_pmp::default_gateway() {
+                config.log_callback.log(
+                    LogLevel::Warn,
+                    format!("nat-pmp-unavailable; error={error}; only UPnP is used to map ports"),
+                );
+            }
+
+            for (listener_index, (local_addr, _)) in tcp_listeners.iter().enumerate() {
+                if !local_addr.is_ipv4() {
+                    continue;
+                }
+
+                let port = local_addr.port();
+                port_mappings.push(Box::pin(stream::unfold(
+                    None,
+                    move |next_renewal: Option<Duration>| async move {
+                        if let Some(next_renewal) = next_renewal {
+                            smol::Timer::after(next_renewal).await;
+                        }
+
+                        let result = map_tcp_port(port).await;
+                        let next_renewal = match &result {
+                            Ok(mapping) => cmp::max(mapping.lifetime / 2, Duration::from_secs(30)),
+                            Err(_) => PORT_MAPPING_RETRY_DELAY,
+                        };
+                        Some(((listener_index, result), Some(next_renewal)))
+                    },
+                )) as Pin<Box<_>>);
+            }
+        }
+
+        // The timers are derived from the network state, which is moved in `Inner` below.
+        let next_peer_rotation = next_peer_rotation_timer(&network);
+        let next_bootstrap_fallback_check = next_bootstrap_fallback_timer(&network);
+
         // Initialize the inner network service.
         run(Inner {
             local_peer_id: local_peer_id.clone(),
@@ -500,9 +1285,18 @@ impl NetworkService {
             to_background_rx: Box::pin(to_background_rx),
             from_connections_rx: Box::pin(from_connections_rx),
             from_connections_tx,
+            incoming_requests_tasks: hashbrown::HashMap::with_capacity_and_hasher(
+                MAX_CONCURRENT_INCOMING_STATE_REQUESTS
+                    + MAX_CONCURRENT_INCOMING_STORAGE_PROOF_REQUESTS
+                    + MAX_CONCURRENT_INCOMING_CALL_PROOF_REQUESTS,
+                Default::default(),
+            ),
+            incoming_requests_responses_rx: Box::pin(incoming_requests_responses_rx),
+            incoming_requests_responses_tx,
             tasks_executor: config.tasks_executor,
             log_callback: config.log_callback,
             network,
+            randomness,
             noise_key: config.noise_key,
             peering_strategy,
             blocks_requests: hashbrown::HashMap::with_capacity_and_hasher(
@@ -522,9 +1316,48 @@ impl NetworkService {
                 Default::default(),
             ),
             jaeger_service: config.jaeger_service.clone(),
+            runtime_calls_limiter: config.runtime_calls_limiter,
             next_discovery: smol::Timer::after(Duration::from_secs(1)),
             next_discovery_period: Duration::from_secs(1),
+            next_peer_rotation,
+            next_bootstrap_fallback_check,
+            next_peers_save: smol::Timer::after(PEERS_SAVE_INTERVAL),
+            max_outbound_connections_per_subnet: config.max_outbound_connections_per_subnet,
+            socks5_proxy: config.socks5_proxy,
+            max_inbound_connections_per_ip: config.max_inbound_connections_per_ip,
+            inbound_connections_ip_allowlist: config
+                .inbound_connections_ip_allowlist
+                .into_iter()
+                .collect(),
+            inbound_connections_ips: hashbrown::HashMap::with_capacity_and_hasher(
+                32,
+                Default::default(),
+            ),
+            outbound_connections_subnets: hashbrown::HashMap::with_capacity_and_hasher(
+                32,
+                Default::default(),
+            ),
+            shared_bandwidth: Arc::new(tasks::SharedBandwidth {
+                total: Default::default(),
+                upload_limiter: config.max_upload_bytes_per_sec.map(tasks::RateLimiter::new),
+                download_limiter: config
+                    .max_download_bytes_per_sec
+                    .map(tasks::RateLimiter::new),
+            }),
+            connections_bandwidth: hashbrown::HashMap::with_capacity_and_hasher(
+                32,
+                Default::default(),
+            ),
+            peers_identify: hashbrown::HashMap::with_capacity_and_hasher(32, Default::default()),
             incoming_connections,
+            nat_mapped_addresses: vec![None; tcp_listeners.len()],
+            bootnodes_providers,
+            tcp_listeners,
+            _tcp_listeners_fd_permits: tcp_listeners_fd_permits,
+            fd_budget: config.fd_budget,
+            port_mappings,
+            observed_ips: lru::LruCache::new(NonZeroUsize::new(32).unwrap()),
+            external_addresses: Vec::new(),
         });
 
         // Build the final network service.
@@ -604,38 +1437,223 @@ impl NetworkService {
         result_rx.await.unwrap()
     }
 
-    pub async fn set_local_best_block(
-        &self,
-        chain_id: ChainId,
-        best_hash: [u8; 32],
-        best_number: u64,
-    ) {
+    /// Returns the list of the most recent peers that have reported a genesis block hash
+    /// different from the local one.
+    pub async fn genesis_mismatches(&self, chain_id: ChainId) -> Vec<GenesisMismatch> {
+        let (result_tx, result_rx) = oneshot::channel();
+
         let _ = self
             .to_background_tx
             .lock()
             .await
-            .send(ToBackground::ForegroundSetLocalBestBlock {
+            .send(ToBackground::ForegroundGetGenesisMismatches {
                 chain_id,
-                best_hash,
-                best_number,
+                result_tx,
             })
             .await;
+
+        result_rx.await.unwrap()
     }
 
-    /// Starts asynchronously disconnecting the given peer. A [`Event::Disconnected`] will later be
-    /// generated. Prevents a new gossip link with the same peer from being reopened for a
-    /// little while.
-    ///
-    /// `reason` is a human-readable string printed in the logs.
-    ///
-    /// Due to race conditions, it is possible to reconnect to the peer soon after, in case the
-    /// reconnection was already happening as the call to this function is still being processed.
-    /// If that happens another [`Event::Disconnected`] will be delivered afterwards. In other
-    /// words, this function guarantees that we will be disconnected in the future rather than
-    /// guarantees that we will disconnect.
-    pub async fn ban_and_disconnect(
-        &self,
-        peer_id: PeerId,
+    /// Returns the total number of times a peer has reported a genesis block hash different
+    /// from the local one since the service has started.
+    pub async fn num_genesis_mismatches(&self, chain_id: ChainId) -> u64 {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundGetNumGenesisMismatches {
+                chain_id,
+                result_tx,
+            })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
+    /// Returns the number of bytes exchanged with the network.
+    pub async fn bandwidth(&self) -> Bandwidth {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundGetBandwidth { result_tx })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
+    pub async fn set_local_best_block(
+        &self,
+        chain_id: ChainId,
+        best_hash: [u8; 32],
+        best_number: u64,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSetLocalBestBlock {
+                chain_id,
+                best_hash,
+                best_number,
+            })
+            .await;
+    }
+
+    /// Sends the given SCALE-encoded GrandPa vote to all the peers we are connected to on the
+    /// given chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chain doesn't use GrandPa.
+    ///
+    pub async fn broadcast_grandpa_vote(&self, chain_id: ChainId, scale_encoded_vote: Vec<u8>) {
+        let mut scale_encoded_notification = Vec::with_capacity(1 + scale_encoded_vote.len());
+        scale_encoded_notification.push(0);
+        scale_encoded_notification.extend(scale_encoded_vote);
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundBroadcastGrandpaNotification {
+                chain_id,
+                scale_encoded_notification,
+            })
+            .await;
+    }
+
+    /// Sends the given SCALE-encoded GrandPa commit to all the peers we are connected to on the
+    /// given chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chain doesn't use GrandPa.
+    ///
+    pub async fn broadcast_grandpa_commit(&self, chain_id: ChainId, scale_encoded_commit: Vec<u8>) {
+        let mut scale_encoded_notification = Vec::with_capacity(1 + scale_encoded_commit.len());
+        scale_encoded_notification.push(1);
+        scale_encoded_notification.extend(scale_encoded_commit);
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundBroadcastGrandpaNotification {
+                chain_id,
+                scale_encoded_notification,
+            })
+            .await;
+    }
+
+    /// Sends a GrandPa catch up request to the given peer, asking for the votes of a round of the
+    /// given authorities set later than the given round. The peer answers with a
+    /// [`Event::GrandpaCatchUp`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chain doesn't use GrandPa.
+    ///
+    pub async fn send_grandpa_catch_up_request(
+        &self,
+        target: PeerId,
+        chain_id: ChainId,
+        round_number: u64,
+        set_id: u64,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSendGrandpaCatchUpRequest {
+                target,
+                chain_id,
+                request: codec::CatchUpRequest {
+                    round_number,
+                    set_id,
+                },
+            })
+            .await;
+    }
+
+    /// Sends the given SCALE-encoded GrandPa catch up message to the given peer, normally in
+    /// response to a [`Event::GrandpaCatchUpRequest`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chain doesn't use GrandPa.
+    ///
+    pub async fn send_grandpa_catch_up(
+        &self,
+        target: PeerId,
+        chain_id: ChainId,
+        scale_encoded_catch_up: Vec<u8>,
+    ) {
+        let mut scale_encoded_notification = Vec::with_capacity(1 + scale_encoded_catch_up.len());
+        scale_encoded_notification.push(4);
+        scale_encoded_notification.extend(scale_encoded_catch_up);
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSendGrandpaCatchUp {
+                target,
+                chain_id,
+                scale_encoded_notification,
+            })
+            .await;
+    }
+
+    /// Updates the GrandPa round, authorities set id, and finalized block height of the local
+    /// node, and sends a neighbor packet containing them to all the peers we are connected to on
+    /// the given chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chain doesn't use GrandPa.
+    ///
+    pub async fn set_grandpa_state(
+        &self,
+        chain_id: ChainId,
+        round_number: u64,
+        set_id: u64,
+        commit_finalized_height: u64,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSetGrandpaState {
+                chain_id,
+                state: service::GrandpaState {
+                    round_number,
+                    set_id,
+                    commit_finalized_height,
+                },
+            })
+            .await;
+    }
+
+    /// Starts asynchronously disconnecting the given peer. A [`Event::Disconnected`] will later be
+    /// generated. Prevents a new gossip link with the same peer from being reopened for a
+    /// little while.
+    ///
+    /// `reason` is a human-readable string printed in the logs.
+    ///
+    /// Due to race conditions, it is possible to reconnect to the peer soon after, in case the
+    /// reconnection was already happening as the call to this function is still being processed.
+    /// If that happens another [`Event::Disconnected`] will be delivered afterwards. In other
+    /// words, this function guarantees that we will be disconnected in the future rather than
+    /// guarantees that we will disconnect.
+    pub async fn ban_and_disconnect(
+        &self,
+        peer_id: PeerId,
         chain_id: ChainId,
         severity: BanSeverity,
         reason: &'static str,
@@ -653,6 +1671,107 @@ impl NetworkService {
             .await;
     }
 
+    /// Returns the list of peers we are gossip-connected to on the given chain, alongside with
+    /// information about them.
+    pub async fn peers(&self, chain_id: ChainId) -> Vec<PeerInfo> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundGetPeers {
+                chain_id,
+                result_tx,
+            })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
+    /// Adds the given address to the address book of the given peer, then assigns an out slot
+    /// to this peer on the given chain, which leads to a connection being opened with it if
+    /// there isn't any already.
+    ///
+    /// The peer is treated the same way as any other peer afterwards. In particular, it might
+    /// later be disconnected and lose its slot if it misbehaves or is unreachable.
+    pub async fn dial(&self, chain_id: ChainId, peer_id: PeerId, address: Multiaddr) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundDial {
+                chain_id,
+                peer_id,
+                address,
+            })
+            .await;
+    }
+
+    /// Starts asynchronously disconnecting all the connections with the given peer, on all
+    /// chains. A [`Event::Disconnected`] will later be generated for each chain the peer was
+    /// connected to.
+    ///
+    /// Contrary to [`NetworkService::ban_and_disconnect`], the peer is only banned for the few
+    /// seconds that follow any disconnection, and might be picked again afterwards.
+    pub async fn disconnect(&self, peer_id: PeerId) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundDisconnect { peer_id })
+            .await;
+    }
+
+    /// Lowers the reputation of the given peer on the given chain. If the reputation drops too
+    /// low, the peer is disconnected and banned for a few minutes, similar to
+    /// [`NetworkService::ban_and_disconnect`].
+    ///
+    /// The reputation of peers slowly recovers over time, meaning that occasional misbehaviors
+    /// don't lead to a ban.
+    pub async fn report_misbehavior(
+        &self,
+        peer_id: PeerId,
+        chain_id: ChainId,
+        misbehavior: PeerMisbehavior,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundReportMisbehavior {
+                peer_id,
+                chain_id,
+                misbehavior,
+            })
+            .await;
+    }
+
+    /// Sends a transaction to all the peers we are connected to on the given chain.
+    ///
+    /// Returns the list of peers the transaction has been sent to, which can be empty. Note that
+    /// the remotes don't confirm that they have received the transaction.
+    pub async fn announce_transaction(
+        &self,
+        chain_id: ChainId,
+        transaction: Vec<u8>,
+    ) -> Vec<PeerId> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundAnnounceTransaction {
+                chain_id,
+                transaction,
+                result_tx,
+            })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
     pub async fn send_block_announce(
         self: Arc<Self>,
         target: PeerId,
@@ -732,13 +1851,12 @@ impl NetworkService {
 
     /// Sends a storage proof request to the given peer.
     // TODO: more docs
-    // TODO: proper error type
     pub async fn storage_request(
         self: Arc<Self>,
         target: PeerId, // TODO: by value?
         chain_id: ChainId,
         config: codec::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
-    ) -> Result<service::EncodedMerkleProof, ()> {
+    ) -> Result<service::EncodedMerkleProof, StorageProofRequestError> {
         // TODO: logs and jaeger integration
         let (result_tx, result_rx) = oneshot::channel();
 
@@ -766,13 +1884,12 @@ impl NetworkService {
 
     /// Sends a call proof request to the given peer.
     // TODO: more docs
-    // TODO: proper error type
     pub async fn call_proof_request(
         self: Arc<Self>,
         target: PeerId, // TODO: by value?
         chain_id: ChainId,
         config: codec::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
-    ) -> Result<service::EncodedMerkleProof, ()> {
+    ) -> Result<service::EncodedMerkleProof, CallProofRequestError> {
         // TODO: logs and jaeger integration
         let (result_tx, result_rx) = oneshot::channel();
 
@@ -809,6 +1926,10 @@ pub enum InitError {
     /// A listening address passed through the configuration isn't valid.
     #[display(fmt = "A listening address passed through the configuration isn't valid: {_0}")]
     BadListenMultiaddr(Multiaddr),
+    /// A listening address passed through the configuration is well-formed, but uses a
+    /// transport that the node can't listen on. Only TCP and WebSocket are supported.
+    #[display(fmt = "The transport of this listening address isn't supported: {_0}")]
+    UnsupportedListenTransport(Multiaddr),
 }
 
 /// Error returned by [`NetworkService::blocks_request`].
@@ -821,61 +1942,625 @@ pub enum BlocksRequestError {
     Request(service::BlocksRequestError),
 }
 
+impl BlocksRequestError {
+    /// Returns the misbehavior of the target that the error indicates, if any. See
+    /// [`NetworkService::report_misbehavior`].
+    pub fn misbehavior(&self) -> Option<PeerMisbehavior> {
+        match self {
+            BlocksRequestError::NoConnection => None,
+            BlocksRequestError::Request(service::BlocksRequestError::Request(err)) => {
+                request_error_misbehavior(err)
+            }
+            BlocksRequestError::Request(service::BlocksRequestError::Decode(_)) => {
+                Some(PeerMisbehavior::MalformedResponse)
+            }
+        }
+    }
+}
+
 /// Error returned by [`NetworkService::warp_sync_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum WarpSyncRequestError {
     /// No established connection with the target.
     NoConnection,
+    /// The target is known to not support the warp sync protocol.
+    ProtocolNotAvailable,
     /// Error during the request.
     #[display(fmt = "{_0}")]
     Request(service::GrandpaWarpSyncRequestError),
 }
 
-fn run(mut inner: Inner) {
-    // This function is a small hack because I didn't find a better way to store the executor
-    // within `Inner` while at the same time spawning the `Inner` using said executor.
-    let mut actual_executor = mem::replace(&mut inner.tasks_executor, Box::new(|_| unreachable!()));
-    let (tx, rx) = oneshot::channel();
-    actual_executor(Box::pin(async move {
-        let actual_executor = rx.await.unwrap();
-        inner.tasks_executor = actual_executor;
-        background_task(inner).await;
-    }));
-    tx.send(actual_executor).unwrap_or_else(|_| panic!());
-}
-
-async fn background_task(mut inner: Inner) {
-    loop {
-        enum WakeUpReason {
-            IncomingConnection {
-                socket: TcpStream,
-                socket_addr: SocketAddr,
-            },
-            NetworkEvent(service::Event<channel::Sender<service::CoordinatorToConnection>>),
-            Message(ToBackground),
-            ForegroundClosed,
-            FromConnectionTask {
-                connection_id: service::ConnectionId,
-                // TODO: this Option is weird
-                message: Option<service::ConnectionToCoordinator>,
-            },
-            EventSendersReady,
-            CanAssignSlot(PeerId, ChainId),
-            CanStartConnect(PeerId),
-            CanOpenGossip(PeerId, ChainId),
-            StartKademliaDiscoveries,
-            MessageToConnection {
-                connection_id: service::ConnectionId,
-                message: service::CoordinatorToConnection,
-            },
+impl WarpSyncRequestError {
+    /// Returns `true` if the error indicates that the target doesn't support the warp sync
+    /// protocol, in which case sending other warp sync requests to it is pointless.
+    pub fn is_protocol_not_available(&self) -> bool {
+        match self {
+            WarpSyncRequestError::ProtocolNotAvailable => true,
+            WarpSyncRequestError::Request(service::GrandpaWarpSyncRequestError::Request(err)) => {
+                is_protocol_not_available(err)
+            }
+            _ => false,
         }
+    }
 
-        let wake_up_reason = async {
-            inner
-                .to_background_rx
-                .next()
-                .await
-                .map_or(WakeUpReason::ForegroundClosed, WakeUpReason::Message)
+    /// Returns the misbehavior of the target that the error indicates, if any. See
+    /// [`NetworkService::report_misbehavior`].
+    pub fn misbehavior(&self) -> Option<PeerMisbehavior> {
+        match self {
+            WarpSyncRequestError::NoConnection | WarpSyncRequestError::ProtocolNotAvailable => None,
+            WarpSyncRequestError::Request(service::GrandpaWarpSyncRequestError::Request(err)) => {
+                request_error_misbehavior(err)
+            }
+            WarpSyncRequestError::Request(service::GrandpaWarpSyncRequestError::Decode(_)) => {
+                Some(PeerMisbehavior::MalformedResponse)
+            }
+        }
+    }
+}
+
+/// Error returned by [`NetworkService::storage_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StorageProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// The target is known to not support the light protocol.
+    ProtocolNotAvailable,
+    /// Request is too large to be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::StorageProofRequestError),
+}
+
+impl StorageProofRequestError {
+    /// Returns `true` if the error indicates that the target doesn't support the light protocol,
+    /// in which case sending other storage or call proof requests to it is pointless.
+    pub fn is_protocol_not_available(&self) -> bool {
+        match self {
+            StorageProofRequestError::ProtocolNotAvailable => true,
+            StorageProofRequestError::Request(service::StorageProofRequestError::Request(err)) => {
+                is_protocol_not_available(err)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the misbehavior of the target that the error indicates, if any. See
+    /// [`NetworkService::report_misbehavior`].
+    pub fn misbehavior(&self) -> Option<PeerMisbehavior> {
+        match self {
+            StorageProofRequestError::NoConnection
+            | StorageProofRequestError::ProtocolNotAvailable
+            | StorageProofRequestError::RequestTooLarge
+            | StorageProofRequestError::Request(
+                service::StorageProofRequestError::RemoteCouldntAnswer,
+            ) => None,
+            StorageProofRequestError::Request(service::StorageProofRequestError::Request(err)) => {
+                request_error_misbehavior(err)
+            }
+            StorageProofRequestError::Request(service::StorageProofRequestError::Decode(_)) => {
+                Some(PeerMisbehavior::MalformedResponse)
+            }
+        }
+    }
+}
+
+/// Error returned by [`NetworkService::call_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum CallProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// The target is known to not support the light protocol.
+    ProtocolNotAvailable,
+    /// Request is too large to be sent.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::CallProofRequestError),
+}
+
+impl CallProofRequestError {
+    /// Returns `true` if the error indicates that the target doesn't support the light protocol,
+    /// in which case sending other storage or call proof requests to it is pointless.
+    pub fn is_protocol_not_available(&self) -> bool {
+        match self {
+            CallProofRequestError::ProtocolNotAvailable => true,
+            CallProofRequestError::Request(service::CallProofRequestError::Request(err)) => {
+                is_protocol_not_available(err)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the misbehavior of the target that the error indicates, if any. See
+    /// [`NetworkService::report_misbehavior`].
+    pub fn misbehavior(&self) -> Option<PeerMisbehavior> {
+        match self {
+            CallProofRequestError::NoConnection
+            | CallProofRequestError::ProtocolNotAvailable
+            | CallProofRequestError::RequestTooLarge
+            | CallProofRequestError::Request(service::CallProofRequestError::RemoteCouldntAnswer) => {
+                None
+            }
+            CallProofRequestError::Request(service::CallProofRequestError::Request(err)) => {
+                request_error_misbehavior(err)
+            }
+            CallProofRequestError::Request(service::CallProofRequestError::Decode(_)) => {
+                Some(PeerMisbehavior::MalformedResponse)
+            }
+        }
+    }
+}
+
+/// Returns the misbehavior that the given request error indicates, if any.
+fn request_error_misbehavior(error: &service::RequestError) -> Option<PeerMisbehavior> {
+    match error {
+        service::RequestError::Substream(connection::established::RequestError::Timeout) => {
+            Some(PeerMisbehavior::RequestTimeout)
+        }
+        _ if is_protocol_not_available(error) => None,
+        _ if error.is_protocol_error() => Some(PeerMisbehavior::MalformedResponse),
+        _ => None,
+    }
+}
+
+/// Returns `true` if the given request error was caused by the remote not supporting the
+/// protocol of the request.
+fn is_protocol_not_available(error: &service::RequestError) -> bool {
+    matches!(
+        error,
+        service::RequestError::Substream(
+            connection::established::RequestError::ProtocolNotAvailable
+        )
+    )
+}
+
+/// Unassigns the slot of the given peer, bans it for the given duration, and closes the gossip
+/// link with it, if any. `reason` is printed in the logs.
+///
+/// Must only be called if [`Inner::event_pending_send`] is `None`.
+fn disconnect_and_ban(
+    inner: &mut Inner,
+    chain_id: ChainId,
+    peer_id: PeerId,
+    ban_duration: Duration,
+    reason: &str,
+) {
+    // Note that peer doesn't necessarily have an out slot.
+    inner.peering_strategy.unassign_slot_and_ban(
+        &chain_id,
+        &peer_id,
+        Instant::now() + ban_duration,
+    );
+    if inner.network.gossip_remove_desired(
+        chain_id,
+        &peer_id,
+        service::GossipKind::ConsensusTransactions,
+    ) {
+        inner.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "slot-unassigned; peer_id={}; chain={}; reason={}",
+                peer_id, inner.network[chain_id].log_name, reason
+            ),
+        );
+    }
+
+    if inner.network.gossip_is_connected(
+        chain_id,
+        &peer_id,
+        service::GossipKind::ConsensusTransactions,
+    ) {
+        let _close_result = inner.network.gossip_close(
+            chain_id,
+            &peer_id,
+            service::GossipKind::ConsensusTransactions,
+        );
+        debug_assert!(_close_result.is_ok());
+        inner.network[chain_id].gossip_peers.remove(&peer_id);
+
+        inner.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "chain-disconnected; peer_id={}; chain={}",
+                peer_id, inner.network[chain_id].log_name
+            ),
+        );
+
+        debug_assert!(inner.event_pending_send.is_none());
+        inner.event_pending_send = Some(Event::Disconnected { chain_id, peer_id });
+    }
+}
+
+/// Lowers the reputation of the given peer according to the given misbehavior, taking into
+/// account the reputation recovered since the last misbehavior. Returns the new reputation.
+fn lower_reputation(chain: &mut Chain, peer_id: &PeerId, misbehavior: PeerMisbehavior) -> i32 {
+    let now = Instant::now();
+    let current = current_reputation(chain, peer_id, now);
+    let value = current.saturating_sub(misbehavior.reputation_cost());
+    chain.peers_reputation.put(
+        peer_id.clone(),
+        PeerReputation {
+            value,
+            last_update: now,
+        },
+    );
+    value
+}
+
+/// Returns the reputation of the given peer at the given moment, taking into account the
+/// reputation recovered since the last misbehavior.
+fn current_reputation(chain: &Chain, peer_id: &PeerId, now: Instant) -> i32 {
+    match chain.peers_reputation.peek(peer_id) {
+        Some(reputation) => {
+            let recovered = i32::try_from(
+                now.saturating_duration_since(reputation.last_update)
+                    .as_secs(),
+            )
+            .unwrap_or(i32::MAX)
+            .saturating_mul(REPUTATION_RECOVERY_PER_SECOND);
+            cmp::min(reputation.value.saturating_add(recovered), 0)
+        }
+        None => 0,
+    }
+}
+
+/// Returns the list of peers known for the given chain, in the format stored in the database.
+fn chain_known_peers(inner: &Inner, chain_id: ChainId) -> Vec<full_sqlite::KnownPeer> {
+    let now = Instant::now();
+    inner
+        .peering_strategy
+        .chain_peers_unordered(&chain_id)
+        .map(|peer_id| full_sqlite::KnownPeer {
+            peer_id: peer_id.as_bytes().to_vec(),
+            addresses: inner
+                .peering_strategy
+                .peer_addresses(peer_id)
+                .map(|addr| addr.to_vec())
+                .collect(),
+            reputation: current_reputation(&inner.network[chain_id], peer_id, now),
+        })
+        .filter(|peer| !peer.addresses.is_empty())
+        .collect()
+}
+
+/// Inserts in the peering strategy the peers that have been saved in the database, and restores
+/// their reputation. Returns the number of peers that have been inserted.
+fn restore_known_peers(
+    peering_strategy: &mut basic_peering_strategy::BasicPeeringStrategy<ChainId, Instant>,
+    chain: &mut Chain,
+    chain_id: ChainId,
+    known_peers: Vec<full_sqlite::KnownPeer>,
+) -> usize {
+    let mut num_restored = 0;
+    for known_peer in known_peers {
+        let Ok(peer_id) = PeerId::from_bytes(known_peer.peer_id) else {
+            continue;
+        };
+        let addresses = known_peer
+            .addresses
+            .into_iter()
+            .filter(|addr| Multiaddr::from_bytes(&addr[..]).is_ok())
+            .collect::<Vec<_>>();
+        if addresses.is_empty() {
+            continue;
+        }
+
+        // Note that we must call this function before `insert_address`, as documented in
+        // `basic_peering_strategy`.
+        peering_strategy.insert_chain_peer(chain_id, peer_id.clone(), 100); // TODO: constant
+        for addr in addresses {
+            peering_strategy.insert_address(&peer_id, addr, 10);
+            // TODO: constant
+        }
+
+        if known_peer.reputation < 0 {
+            chain.peers_reputation.put(
+                peer_id,
+                PeerReputation {
+                    value: known_peer.reputation,
+                    last_update: Instant::now(),
+                },
+            );
+        }
+
+        num_restored += 1;
+    }
+    num_restored
+}
+
+/// Returns the number of peers of the given chain that have a slot and an open gossip link.
+fn num_out_peers(
+    network: &service::ChainNetwork<
+        Chain,
+        channel::Sender<service::CoordinatorToConnection>,
+        Instant,
+    >,
+    chain_id: ChainId,
+) -> usize {
+    network
+        .gossip_desired_iter(chain_id, service::GossipKind::ConsensusTransactions)
+        .filter(|peer_id| {
+            network.gossip_is_connected(
+                chain_id,
+                peer_id,
+                service::GossipKind::ConsensusTransactions,
+            )
+        })
+        .count()
+}
+
+/// Builds a timer that fires when the earliest [`Chain::next_peer_rotation`] is reached, or never
+/// if no chain has peer rotation enabled.
+fn next_peer_rotation_timer(
+    network: &service::ChainNetwork<
+        Chain,
+        channel::Sender<service::CoordinatorToConnection>,
+        Instant,
+    >,
+) -> smol::Timer {
+    network
+        .chains()
+        .filter(|chain_id| network[*chain_id].peer_rotation_interval.is_some())
+        .map(|chain_id| network[chain_id].next_peer_rotation)
+        .min()
+        .map_or_else(smol::Timer::never, smol::Timer::at)
+}
+
+/// Builds a timer that fires when the earliest [`Chain::next_bootstrap_fallback_check`] is
+/// reached, or never if no chain has a bootstrap fallback.
+fn next_bootstrap_fallback_timer(
+    network: &service::ChainNetwork<
+        Chain,
+        channel::Sender<service::CoordinatorToConnection>,
+        Instant,
+    >,
+) -> smol::Timer {
+    network
+        .chains()
+        .filter(|chain_id| network[*chain_id].bootstrap_fallback_delay.is_some())
+        .map(|chain_id| network[chain_id].next_bootstrap_fallback_check)
+        .min()
+        .map_or_else(smol::Timer::never, smol::Timer::at)
+}
+
+/// Registers in [`Inner::incoming_requests_tasks`] an incoming request whose response is going
+/// to be built by a separate task.
+///
+/// Returns `false`, and doesn't register anything, if the maximum number of requests of this
+/// kind are already being answered.
+fn start_incoming_request_task(
+    inner: &mut Inner,
+    substream_id: service::SubstreamId,
+    kind: IncomingRequestKind,
+) -> bool {
+    let num_pending = inner
+        .incoming_requests_tasks
+        .values()
+        .filter(|task| task.kind == kind)
+        .count();
+    if num_pending >= kind.max_concurrent() {
+        return false;
+    }
+
+    inner.incoming_requests_tasks.insert(
+        substream_id,
+        IncomingRequestTask {
+            kind,
+            cancelled: false,
+        },
+    );
+    true
+}
+
+/// Successful mapping of a port of the public IP address of the gateway to a local port.
+#[derive(Debug, Clone)]
+struct PortMapping {
+    /// Public IP address of the gateway.
+    external_ip: Ipv4Addr,
+    /// Port of [`PortMapping::external_ip`] that is forwarded to the local port.
+    external_port: u16,
+    /// Duration after which the mapping expires if it isn't renewed.
+    lifetime: Duration,
+}
+
+/// Error while mapping a port on the gateway. See [`Config::nat_port_mapping`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "NAT-PMP: {nat_pmp}; UPnP: {upnp}")]
+struct PortMappingError {
+    /// Error while trying NAT-PMP.
+    nat_pmp: nat_pmp::NatPmpError,
+    /// Error while trying UPnP, after NAT-PMP has failed.
+    upnp: upnp::UpnpError,
+}
+
+/// Asks the gateway of the local network to forward a TCP port of its public IP address to the
+/// given local port, through NAT-PMP or, if that fails, through UPnP.
+async fn map_tcp_port(port: u16) -> Result<PortMapping, PortMappingError> {
+    let nat_pmp = match nat_pmp::map_tcp_port(port, PORT_MAPPING_LIFETIME).await {
+        Ok(mapping) => return Ok(mapping),
+        Err(err) => err,
+    };
+
+    match upnp::map_tcp_port(port, PORT_MAPPING_LIFETIME).await {
+        Ok(mapping) => Ok(mapping),
+        Err(upnp) => Err(PortMappingError { nat_pmp, upnp }),
+    }
+}
+
+/// Recalculates the external addresses of the local node from the port mappings and the IP
+/// addresses reported by peers, and updates the addresses advertised to peers.
+fn update_external_addresses(inner: &mut Inner) {
+    let confirmed_ips = inner
+        .observed_ips
+        .iter()
+        .filter(|(_, reporters)| reporters.len() >= OBSERVED_ADDRESS_CONFIRMATIONS)
+        .map(|(ip, _)| *ip)
+        .collect::<Vec<_>>();
+
+    let mut external_addresses = inner
+        .nat_mapped_addresses
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // Peers report the address of the connections that the local node has opened, whose port
+    // is unrelated to the ports the node listens on. Only the IP address is used, and is
+    // combined with the port of each listener of the same IP version.
+    for ip in confirmed_ips {
+        for (local_addr, is_websocket) in &inner.tcp_listeners {
+            if local_addr.is_ipv4() != ip.is_ipv4() {
+                continue;
+            }
+
+            let addr = tcp_multiaddr(SocketAddr::new(ip, local_addr.port()), *is_websocket);
+            if !external_addresses.contains(&addr) {
+                external_addresses.push(addr);
+            }
+        }
+    }
+
+    for addr in &external_addresses {
+        if !inner.external_addresses.contains(addr) {
+            inner.log_callback.log(
+                LogLevel::Info,
+                format!("external-address-discovered; address={addr}"),
+            );
+        }
+    }
+
+    inner.network.set_external_addresses(
+        external_addresses
+            .iter()
+            .map(|addr| addr.clone().into_bytes()),
+    );
+    inner.external_addresses = external_addresses;
+}
+
+/// Builds the multiaddress corresponding to the given TCP socket address.
+fn tcp_multiaddr(socket_addr: SocketAddr, is_websocket: bool) -> Multiaddr {
+    [
+        match socket_addr.ip() {
+            IpAddr::V4(ip) => Protocol::<&[u8]>::Ip4(ip.octets()),
+            IpAddr::V6(ip) => Protocol::Ip6(ip.octets()),
+        },
+        Protocol::Tcp(socket_addr.port()),
+    ]
+    .into_iter()
+    .chain(is_websocket.then_some(Protocol::Ws))
+    .collect::<Multiaddr>()
+}
+
+/// Returns `true` if an incoming connection from the given IP address must be refused because
+/// of [`Config::max_inbound_connections_per_ip`]. `existing_connections` contains the IP address
+/// of each incoming connection that already exists.
+///
+/// The connections from loopback and private IP addresses are never refused, as they are
+/// typically a local test network or a reverse proxy.
+fn inbound_connections_limit_reached(
+    ip: IpAddr,
+    max_per_ip: Option<NonZeroUsize>,
+    allowlist: &hashbrown::HashSet<IpAddr, fnv::FnvBuildHasher>,
+    existing_connections: impl Iterator<Item = IpAddr>,
+) -> bool {
+    let Some(max_per_ip) = max_per_ip else {
+        return false;
+    };
+
+    if !is_global_ip(&ip) || allowlist.contains(&ip) {
+        return false;
+    }
+
+    existing_connections
+        .filter(|existing| *existing == ip)
+        .count()
+        >= max_per_ip.get()
+}
+
+/// Returns `true` if the given IP address can be reached from the public Internet.
+// TODO: use `IpAddr::is_global` once it is stable
+fn is_global_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                // Shared address space (RFC 6598), used by carrier-grade NATs.
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0b1100_0000) == 0b0100_0000))
+        }
+        IpAddr::V6(ip) => {
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local addresses (`fc00::/7`).
+                || (ip.segments()[0] & 0xfe00) == 0xfc00
+                // Link-local addresses (`fe80::/10`).
+                || (ip.segments()[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+fn run(mut inner: Inner) {
+    // This function is a small hack because I didn't find a better way to store the executor
+    // within `Inner` while at the same time spawning the `Inner` using said executor.
+    let mut actual_executor = mem::replace(&mut inner.tasks_executor, Box::new(|_| unreachable!()));
+    let (tx, rx) = oneshot::channel();
+    actual_executor(Box::pin(async move {
+        let actual_executor = rx.await.unwrap();
+        inner.tasks_executor = actual_executor;
+        background_task(inner).await;
+    }));
+    tx.send(actual_executor).unwrap_or_else(|_| panic!());
+}
+
+async fn background_task(mut inner: Inner) {
+    loop {
+        enum WakeUpReason {
+            IncomingConnection {
+                socket: TcpStream,
+                socket_addr: SocketAddr,
+                is_websocket: bool,
+            },
+            NetworkEvent(service::Event<channel::Sender<service::CoordinatorToConnection>>),
+            Message(ToBackground),
+            ForegroundClosed,
+            FromConnectionTask {
+                connection_id: service::ConnectionId,
+                // TODO: this Option is weird
+                message: Option<service::ConnectionToCoordinator>,
+            },
+            EventSendersReady,
+            CanAssignSlot(PeerId, ChainId),
+            CanStartConnect(PeerId),
+            CanOpenGossip(PeerId, ChainId),
+            StartKademliaDiscoveries,
+            RotatePeers,
+            BootstrapFallback,
+            SavePeers,
+            MessageToConnection {
+                connection_id: service::ConnectionId,
+                message: service::CoordinatorToConnection,
+            },
+            PortMapping {
+                listener_index: usize,
+                result: Result<PortMapping, PortMappingError>,
+            },
+            BootnodesProvided {
+                chain_id: ChainId,
+                result: Result<Vec<(PeerId, Multiaddr)>, String>,
+            },
+            IncomingRequestResponse(service::SubstreamId, IncomingRequestResponse),
+        }
+
+        let wake_up_reason = async {
+            inner
+                .to_background_rx
+                .next()
+                .await
+                .map_or(WakeUpReason::ForegroundClosed, WakeUpReason::Message)
         }
         .or({
             let event_senders_ready = matches!(inner.event_senders, either::Left(_));
@@ -883,6 +2568,7 @@ async fn background_task(mut inner: Inner) {
             let network = &mut inner.network;
             let peering_strategy = &mut inner.peering_strategy;
             let num_pending_out_attempts = &inner.num_pending_out_attempts;
+            let fd_budget = &inner.fd_budget;
             async move {
                 if let Some(event) = (event_senders_ready && event_pending_send.is_none())
                     .then(|| network.next_event())
@@ -901,9 +2587,10 @@ async fn background_task(mut inner: Inner) {
                     .map(|(peer_id, chain_id, _)| (peer_id.clone(), chain_id))
                 {
                     WakeUpReason::CanOpenGossip(peer_id, chain_id)
-                } else if let Some(peer_id) = (*num_pending_out_attempts < 16)
-                    .then(|| network.unconnected_desired().next().cloned())
-                    .flatten()
+                } else if let Some(peer_id) = (*num_pending_out_attempts < 16
+                    && fd_budget.has_available_socket())
+                .then(|| network.unconnected_desired().next().cloned())
+                .flatten()
                 {
                     WakeUpReason::CanStartConnect(peer_id)
                 } else {
@@ -914,7 +2601,7 @@ async fn background_task(mut inner: Inner) {
                             if network.gossip_desired_num(
                                 chain_id,
                                 service::GossipKind::ConsensusTransactions,
-                            ) >= network[chain_id].max_slots
+                            ) >= network[chain_id].max_out_peers
                             {
                                 continue;
                             }
@@ -965,6 +2652,24 @@ async fn background_task(mut inner: Inner) {
                 cmp::min(inner.next_discovery_period * 2, Duration::from_secs(120));
             WakeUpReason::StartKademliaDiscoveries
         })
+        .or(async {
+            // Rotating a peer generates an event, and thus can only happen if no other event is
+            // waiting to be sent.
+            if inner.event_pending_send.is_some() {
+                future::pending::<()>().await;
+            }
+            (&mut inner.next_peer_rotation).await;
+            WakeUpReason::RotatePeers
+        })
+        .or(async {
+            (&mut inner.next_bootstrap_fallback_check).await;
+            WakeUpReason::BootstrapFallback
+        })
+        .or(async {
+            (&mut inner.next_peers_save).await;
+            inner.next_peers_save = smol::Timer::after(PEERS_SAVE_INTERVAL);
+            WakeUpReason::SavePeers
+        })
         .or(async {
             let (connection_id, message) = inner.from_connections_rx.next().await.unwrap();
             WakeUpReason::FromConnectionTask {
@@ -973,14 +2678,36 @@ async fn background_task(mut inner: Inner) {
             }
         })
         .or(async {
-            let Some((socket, socket_addr)) = inner.incoming_connections.next().await else {
+            let Some((socket, socket_addr, is_websocket)) = inner.incoming_connections.next().await
+            else {
                 future::pending().await
             };
             WakeUpReason::IncomingConnection {
                 socket,
                 socket_addr,
+                is_websocket,
+            }
+        })
+        .or(async {
+            let Some((listener_index, result)) = inner.port_mappings.next().await else {
+                future::pending().await
+            };
+            WakeUpReason::PortMapping {
+                listener_index,
+                result,
             }
         })
+        .or(async {
+            let Some((chain_id, result)) = inner.bootnodes_providers.next().await else {
+                future::pending().await
+            };
+            WakeUpReason::BootnodesProvided { chain_id, result }
+        })
+        .or(async {
+            let (substream_id, response) =
+                inner.incoming_requests_responses_rx.next().await.unwrap();
+            WakeUpReason::IncomingRequestResponse(substream_id, response)
+        })
         .await;
 
         match wake_up_reason {
@@ -1012,7 +2739,21 @@ async fn background_task(mut inner: Inner) {
             WakeUpReason::IncomingConnection {
                 socket,
                 socket_addr,
+                is_websocket,
             } => {
+                // Refuse the connection if opening it would exceed the budget of file
+                // descriptors. The socket is closed immediately when dropped.
+                let Some(fd_permit) = inner.fd_budget.try_acquire_socket() else {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "incoming-connection-refused; ip={}; reason=file-descriptors-budget",
+                            socket_addr.ip()
+                        ),
+                    );
+                    continue;
+                };
+
                 // The Nagle algorithm, implemented in the kernel, consists in buffering the
                 // data to be sent out and waiting a bit before actually sending it out, in
                 // order to potentially merge multiple writes in a row into one packet. In
@@ -1022,6 +2763,24 @@ async fn background_task(mut inner: Inner) {
                 // an artificial delay to all sends.
                 let _ = socket.set_nodelay(true);
 
+                // Refuse the connection if there are already too many incoming connections from
+                // the same IP address.
+                if inbound_connections_limit_reached(
+                    socket_addr.ip(),
+                    inner.max_inbound_connections_per_ip,
+                    &inner.inbound_connections_ip_allowlist,
+                    inner.inbound_connections_ips.values().copied(),
+                ) {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "incoming-connection-refused; ip={}; reason=too-many-connections-from-ip",
+                            socket_addr.ip()
+                        ),
+                    );
+                    continue;
+                }
+
                 let multiaddr = [
                     match socket_addr.ip() {
                         IpAddr::V4(ip) => Protocol::<&[u8]>::Ip4(ip.octets()),
@@ -1030,6 +2789,7 @@ async fn background_task(mut inner: Inner) {
                     Protocol::Tcp(socket_addr.port()),
                 ]
                 .into_iter()
+                .chain(is_websocket.then_some(Protocol::Ws))
                 .collect::<Multiaddr>();
 
                 inner.log_callback.log(
@@ -1050,33 +2810,90 @@ async fn background_task(mut inner: Inner) {
                     tx,
                 );
 
-                (inner.tasks_executor)(Box::pin(tasks::connection_task(
+                inner
+                    .inbound_connections_ips
+                    .insert(connection_id, socket_addr.ip());
+
+                let bandwidth = Arc::new(tasks::BandwidthCounters::default());
+                inner
+                    .connections_bandwidth
+                    .insert(connection_id, (None, bandwidth.clone()));
+
+                // The permit is destroyed, and the socket given back to the budget, when the
+                // connection task ends.
+                let task = tasks::connection_task(
                     inner.log_callback.clone(),
                     multiaddr.to_string(),
-                    async move { Ok(socket) },
+                    async move {
+                        if is_websocket {
+                            websocket::websocket_server_handshake(socket)
+                                .await
+                                .map(futures_util::future::Either::Right)
+                        } else {
+                            Ok(futures_util::future::Either::Left(socket))
+                        }
+                    },
                     connection_id,
                     connection_task,
                     rx,
                     inner.from_connections_tx.clone(),
-                )));
+                    bandwidth,
+                    inner.shared_bandwidth.clone(),
+                );
+                (inner.tasks_executor)(Box::pin(async move {
+                    task.await;
+                    drop(fd_permit);
+                }));
             }
 
             WakeUpReason::StartKademliaDiscoveries => {
+                let mut peers_shortage = false;
+
                 for chain_id in inner.network.chains().collect::<Vec<_>>() {
-                    let random_peer_id =
-                        PeerId::from_public_key(&peer_id::PublicKey::Ed25519(rand::random()));
+                    // Random walk: look for a random peer id, which has the consequence of
+                    // discovering the peers that are the closest to it.
+                    let random_peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                        inner.randomness.gen(),
+                    ));
+
+                    if inner
+                        .peering_strategy
+                        .chain_peers_unordered(&chain_id)
+                        .count()
+                        < DISCOVERY_PEERS_SHORTAGE_THRESHOLD
+                        || num_out_peers(&inner.network, chain_id)
+                            < inner.network[chain_id].min_out_peers
+                    {
+                        peers_shortage = true;
+                    }
 
-                    // TODO: select target closest to the random peer instead
-                    let target = inner
+                    // Query the connected peers that are the closest to the random peer id, as
+                    // they are the most likely to know the peers close to it.
+                    let mut targets = inner
                         .network
                         .gossip_connected_peers(
                             chain_id,
                             service::GossipKind::ConsensusTransactions,
                         )
-                        .next()
-                        .cloned();
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    targets.sort_by_cached_key(|target| {
+                        kademlia::peers_distance(target, &random_peer_id)
+                    });
+                    targets.truncate(DISCOVERY_PARALLELISM);
+
+                    if targets.is_empty() {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "discovery-skipped; chain={}; reason=no-connected-peer",
+                                inner.network[chain_id].log_name
+                            ),
+                        );
+                        continue;
+                    }
 
-                    if let Some(target) = target {
+                    for target in targets {
                         match inner.network.start_kademlia_find_node_request(
                             &target,
                             chain_id,
@@ -1086,58 +2903,107 @@ async fn background_task(mut inner: Inner) {
                             Ok(_) => {}
                             Err(service::StartRequestError::NoConnection) => unreachable!(),
                         };
-                    } else {
-                        // TODO: log message
+
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "discovery-started; chain={}; target={target}; searched={random_peer_id}",
+                                inner.network[chain_id].log_name
+                            ),
+                        );
                     }
                 }
-            }
 
-            WakeUpReason::ForegroundClosed => {
-                // TODO: do a clean shutdown of all the connections
-                return;
+                // As long as one of the chains doesn't know enough peers, don't let the period
+                // between two discoveries grow, so that a node that only knows a single
+                // bootnode quickly builds a healthy peer set.
+                if peers_shortage && inner.next_discovery_period > DISCOVERY_SHORTAGE_PERIOD {
+                    inner.next_discovery = smol::Timer::after(DISCOVERY_SHORTAGE_PERIOD);
+                    inner.next_discovery_period = DISCOVERY_SHORTAGE_PERIOD;
+                }
             }
 
-            WakeUpReason::Message(ToBackground::ForegroundDisconnectAndBan {
-                peer_id,
-                chain_id,
-                severity,
-                reason,
-            }) => {
-                // Note that peer doesn't necessarily have an out slot.
-                inner.peering_strategy.unassign_slot_and_ban(
-                    &chain_id,
-                    &peer_id,
-                    Instant::now()
-                        + Duration::from_secs(match severity {
-                            BanSeverity::Low => 10,
-                            BanSeverity::High => 40,
-                        }),
-                );
-                if inner.network.gossip_remove_desired(
-                    chain_id,
-                    &peer_id,
-                    service::GossipKind::ConsensusTransactions,
-                ) {
-                    inner.log_callback.log(
-                        LogLevel::Debug,
-                        format!(
-                            "slot-unassigned; peer_id={}; chain={}; reason=user-ban; user-reason={}",
-                            peer_id, inner.network[chain_id].log_name, reason
-                        ),
+            WakeUpReason::RotatePeers => {
+                let now = Instant::now();
+
+                for chain_id in inner.network.chains().collect::<Vec<_>>() {
+                    let Some(interval) = inner.network[chain_id].peer_rotation_interval else {
+                        continue;
+                    };
+                    if inner.network[chain_id].next_peer_rotation > now {
+                        continue;
+                    }
+                    inner.network[chain_id].next_peer_rotation = now + interval;
+
+                    // Disconnecting a peer is counter-productive if the chain already lacks peers.
+                    if num_out_peers(&inner.network, chain_id)
+                        <= inner.network[chain_id].min_out_peers
+                    {
+                        continue;
+                    }
+
+                    // Disconnecting a peer is pointless if no other peer can take its slot.
+                    if inner
+                        .peering_strategy
+                        .chain_peers_unordered(&chain_id)
+                        .count()
+                        <= inner.network.gossip_desired_num(
+                            chain_id,
+                            service::GossipKind::ConsensusTransactions,
+                        )
+                    {
+                        continue;
+                    }
+
+                    let Some(peer_id) = inner
+                        .network
+                        .gossip_desired_iter(chain_id, service::GossipKind::ConsensusTransactions)
+                        .filter(|peer_id| {
+                            inner.network.gossip_is_connected(
+                                chain_id,
+                                peer_id,
+                                service::GossipKind::ConsensusTransactions,
+                            )
+                        })
+                        .min_by_key(|peer_id| {
+                            inner.network[chain_id]
+                                .gossip_peers
+                                .get(*peer_id)
+                                .map_or(0, |peer| peer.best_number)
+                        })
+                        .cloned()
+                    else {
+                        continue;
+                    };
+
+                    // The peer is banned for the duration of the interval in order to guarantee
+                    // that its slot is attributed to a different peer.
+                    inner.peering_strategy.unassign_slot_and_ban(
+                        &chain_id,
+                        &peer_id,
+                        now + interval,
                     );
-                }
+                    if inner.network.gossip_remove_desired(
+                        chain_id,
+                        &peer_id,
+                        service::GossipKind::ConsensusTransactions,
+                    ) {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "slot-unassigned; peer_id={}; chain={}; reason=rotation",
+                                peer_id, inner.network[chain_id].log_name
+                            ),
+                        );
+                    }
 
-                if inner.network.gossip_is_connected(
-                    chain_id,
-                    &peer_id,
-                    service::GossipKind::ConsensusTransactions,
-                ) {
                     let _close_result = inner.network.gossip_close(
                         chain_id,
                         &peer_id,
                         service::GossipKind::ConsensusTransactions,
                     );
                     debug_assert!(_close_result.is_ok());
+                    inner.network[chain_id].gossip_peers.remove(&peer_id);
 
                     inner.log_callback.log(
                         LogLevel::Debug,
@@ -1147,8 +3013,269 @@ async fn background_task(mut inner: Inner) {
                         ),
                     );
 
+                    // Only one event can be emitted at a time. Other chains whose rotation is
+                    // due are processed after the event has been sent, as the timer below
+                    // fires immediately.
                     debug_assert!(inner.event_pending_send.is_none());
                     inner.event_pending_send = Some(Event::Disconnected { chain_id, peer_id });
+                    break;
+                }
+
+                inner.next_peer_rotation = next_peer_rotation_timer(&inner.network);
+            }
+
+            WakeUpReason::BootstrapFallback => {
+                let now = Instant::now();
+
+                for chain_id in inner.network.chains().collect::<Vec<_>>() {
+                    let Some(delay) = inner.network[chain_id].bootstrap_fallback_delay else {
+                        continue;
+                    };
+                    if inner.network[chain_id].next_bootstrap_fallback_check > now {
+                        continue;
+                    }
+                    inner.network[chain_id].next_bootstrap_fallback_check = now + delay;
+
+                    if !inner.network[chain_id].gossip_peers.is_empty() {
+                        continue;
+                    }
+
+                    // The chain has no peer at all, most likely because none of the bootnodes is
+                    // reachable. The peers saved by a previous run of the node might have been
+                    // removed from the peering strategy after failing to connect to them, and are
+                    // thus inserted again.
+                    let num_known_peers = match inner.network[chain_id]
+                        .database
+                        .with_database(|database| database.known_peers())
+                        .await
+                    {
+                        Ok(known_peers) => restore_known_peers(
+                            &mut inner.peering_strategy,
+                            &mut inner.network[chain_id],
+                            chain_id,
+                            known_peers,
+                        ),
+                        Err(error) => {
+                            inner.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "known-peers-restore-error; chain={}; error={}",
+                                    inner.network[chain_id].log_name, error
+                                ),
+                            );
+                            0
+                        }
+                    };
+
+                    for (peer_id, addr) in inner.network[chain_id].fallback_bootnodes.clone() {
+                        // Note that we must call this function before `insert_address`, as
+                        // documented in `basic_peering_strategy`.
+                        inner.peering_strategy.insert_chain_peer(
+                            chain_id,
+                            peer_id.clone(),
+                            usize::MAX,
+                        );
+                        inner.peering_strategy.insert_address(
+                            &peer_id,
+                            addr.into_bytes(),
+                            usize::MAX,
+                        );
+                    }
+
+                    inner.log_callback.log(
+                        LogLevel::Warn,
+                        format!(
+                            "bootstrap-fallback; chain={}; num_known_peers={}; \
+                            num_fallback_bootnodes={}",
+                            inner.network[chain_id].log_name,
+                            num_known_peers,
+                            inner.network[chain_id].fallback_bootnodes.len()
+                        ),
+                    );
+
+                    if num_known_peers == 0 && inner.network[chain_id].fallback_bootnodes.is_empty()
+                    {
+                        inner.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "bootstrap-fallback-no-peer-source; chain={}",
+                                inner.network[chain_id].log_name
+                            ),
+                        );
+                    }
+                }
+
+                inner.next_bootstrap_fallback_check = next_bootstrap_fallback_timer(&inner.network);
+            }
+
+            WakeUpReason::SavePeers => {
+                for chain_id in inner.network.chains().collect::<Vec<_>>() {
+                    let known_peers = chain_known_peers(&inner, chain_id);
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "known-peers-save; chain={}; num_peers={}",
+                            inner.network[chain_id].log_name,
+                            known_peers.len()
+                        ),
+                    );
+
+                    let log_callback = inner.log_callback.clone();
+                    inner.network[chain_id]
+                        .database
+                        .with_database_detached(move |database| {
+                            if let Err(error) = database.set_known_peers(known_peers.into_iter()) {
+                                log_callback.log(
+                                    LogLevel::Warn,
+                                    format!("known-peers-save-error; error={}", error),
+                                );
+                            }
+                        })
+                        .await;
+                }
+            }
+
+            WakeUpReason::ForegroundClosed => {
+                // Save the known peers one last time, so that they can be restored when the
+                // node restarts.
+                for chain_id in inner.network.chains().collect::<Vec<_>>() {
+                    let known_peers = chain_known_peers(&inner, chain_id);
+                    if let Err(error) = inner.network[chain_id]
+                        .database
+                        .with_database(move |database| {
+                            database.set_known_peers(known_peers.into_iter())
+                        })
+                        .await
+                    {
+                        inner.log_callback.log(
+                            LogLevel::Warn,
+                            format!("known-peers-save-error; error={}", error),
+                        );
+                    }
+                }
+
+                // TODO: do a clean shutdown of all the connections
+                return;
+            }
+
+            WakeUpReason::Message(ToBackground::ForegroundDisconnectAndBan {
+                peer_id,
+                chain_id,
+                severity,
+                reason,
+            }) => {
+                disconnect_and_ban(
+                    &mut inner,
+                    chain_id,
+                    peer_id,
+                    Duration::from_secs(match severity {
+                        BanSeverity::Low => 10,
+                        BanSeverity::High => 40,
+                    }),
+                    &format!("user-ban; user-reason={reason}"),
+                );
+            }
+
+            WakeUpReason::Message(ToBackground::ForegroundDial {
+                chain_id,
+                peer_id,
+                address,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "user-dial; peer_id={}; chain={}; address={}",
+                        peer_id, inner.network[chain_id].log_name, address
+                    ),
+                );
+
+                // Note that we must call this function before `insert_address`, as documented
+                // in `basic_peering_strategy`.
+                inner
+                    .peering_strategy
+                    .insert_chain_peer(chain_id, peer_id.clone(), usize::MAX);
+                inner
+                    .peering_strategy
+                    .insert_address(&peer_id, address.into_bytes(), usize::MAX);
+
+                // Note that the slot is assigned even if the peer is currently banned, as this is
+                // an explicit request.
+                inner.peering_strategy.assign_slot(&chain_id, &peer_id);
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "slot-assigned; peer_id={}; chain={}",
+                        peer_id, inner.network[chain_id].log_name
+                    ),
+                );
+
+                inner.network.gossip_insert_desired(
+                    chain_id,
+                    peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                );
+            }
+
+            WakeUpReason::Message(ToBackground::ForegroundDisconnect { peer_id }) => {
+                // The peer must no longer be desired, otherwise the networking state machine
+                // would immediately try to reconnect to it.
+                inner.network.gossip_remove_desired_all(
+                    &peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                );
+                for (&chain_id, what_happened) in inner
+                    .peering_strategy
+                    .unassign_slots_and_ban(&peer_id, Instant::now() + Duration::from_secs(5))
+                {
+                    if matches!(
+                        what_happened,
+                        basic_peering_strategy::UnassignSlotsAndBan::Banned { had_slot: true }
+                    ) {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "slot-unassigned; peer_id={}; chain={}; reason=user-disconnect",
+                                peer_id, inner.network[chain_id].log_name
+                            ),
+                        );
+                    }
+                }
+
+                let num_connections = inner.network.start_shutdown_peer(&peer_id);
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "user-disconnect; peer_id={}; num_connections={}",
+                        peer_id, num_connections
+                    ),
+                );
+            }
+
+            WakeUpReason::Message(ToBackground::ForegroundReportMisbehavior {
+                peer_id,
+                chain_id,
+                misbehavior,
+            }) => {
+                let reputation =
+                    lower_reputation(&mut inner.network[chain_id], &peer_id, misbehavior);
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "reputation-lowered; peer_id={}; chain={}; misbehavior={}; reputation={}",
+                        peer_id, inner.network[chain_id].log_name, misbehavior, reputation
+                    ),
+                );
+
+                if reputation < REPUTATION_BAN_THRESHOLD {
+                    // The reputation starts again from scratch once the ban expires.
+                    inner.network[chain_id].peers_reputation.pop(&peer_id);
+                    disconnect_and_ban(
+                        &mut inner,
+                        chain_id,
+                        peer_id,
+                        REPUTATION_BAN_DURATION,
+                        "bad-reputation",
+                    );
                 }
             }
 
@@ -1166,14 +3293,140 @@ async fn background_task(mut inner: Inner) {
                     is_best,
                 ));
             }
+            WakeUpReason::Message(ToBackground::ForegroundAnnounceTransaction {
+                chain_id,
+                transaction,
+                result_tx,
+            }) => {
+                let peers_to_send = inner
+                    .network
+                    .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                let mut peers_sent = Vec::with_capacity(peers_to_send.len());
+                for peer_id in peers_to_send {
+                    match inner
+                        .network
+                        .gossip_send_transaction(&peer_id, chain_id, &transaction)
+                    {
+                        Ok(()) => peers_sent.push(peer_id),
+                        Err(service::QueueNotificationError::QueueFull) => {}
+                        Err(service::QueueNotificationError::NoConnection) => unreachable!(),
+                        Err(service::QueueNotificationError::TooLarge) => {
+                            // The size limit is the same for all peers.
+                            break;
+                        }
+                    }
+                }
+
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "transaction-announced; chain={}; transaction={}; size={}; num_peers={}",
+                        inner.network[chain_id].log_name,
+                        HashDisplay(blake2_rfc::blake2b::blake2b(32, &[], &transaction).as_bytes()),
+                        transaction.len(),
+                        peers_sent.len()
+                    ),
+                );
+
+                let _ = result_tx.send(peers_sent);
+            }
             WakeUpReason::Message(ToBackground::ForegroundSetLocalBestBlock {
                 chain_id,
-                best_hash,
-                best_number,
+                best_hash,
+                best_number,
+            }) => {
+                inner
+                    .network
+                    .set_chain_local_best_block(chain_id, best_hash, best_number);
+            }
+            WakeUpReason::Message(ToBackground::ForegroundBroadcastGrandpaNotification {
+                chain_id,
+                scale_encoded_notification,
+            }) => {
+                match codec::decode_grandpa_notification(
+                    &scale_encoded_notification,
+                    inner.network.block_number_bytes(chain_id),
+                ) {
+                    Ok(codec::GrandpaNotificationRef::Vote(vote)) => {
+                        inner.network.gossip_broadcast_grandpa_vote(chain_id, vote)
+                    }
+                    Ok(codec::GrandpaNotificationRef::Commit(commit)) => inner
+                        .network
+                        .gossip_broadcast_grandpa_commit(chain_id, commit),
+                    Ok(_) | Err(_) => {
+                        // The public API of the network service only allows passing votes and
+                        // commits.
+                        inner.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "grandpa-broadcast-invalid-notification; chain={}",
+                                inner.network[chain_id].log_name
+                            ),
+                        );
+                    }
+                }
+            }
+            WakeUpReason::Message(ToBackground::ForegroundSendGrandpaCatchUpRequest {
+                target,
+                chain_id,
+                request,
+            }) => {
+                if let Err(err) = inner
+                    .network
+                    .gossip_send_grandpa_catch_up_request(&target, chain_id, request)
+                {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "grandpa-catch-up-request-send-error; peer_id={}; chain={}; error={}",
+                            target, inner.network[chain_id].log_name, err
+                        ),
+                    );
+                }
+            }
+            WakeUpReason::Message(ToBackground::ForegroundSendGrandpaCatchUp {
+                target,
+                chain_id,
+                scale_encoded_notification,
             }) => {
+                let result = match codec::decode_grandpa_notification(
+                    &scale_encoded_notification,
+                    inner.network.block_number_bytes(chain_id),
+                ) {
+                    Ok(codec::GrandpaNotificationRef::CatchUp(catch_up)) => inner
+                        .network
+                        .gossip_send_grandpa_catch_up(&target, chain_id, catch_up),
+                    Ok(_) | Err(_) => {
+                        // The public API of the network service only allows passing catch up
+                        // messages.
+                        inner.log_callback.log(
+                            LogLevel::Warn,
+                            format!(
+                                "grandpa-send-invalid-catch-up; chain={}",
+                                inner.network[chain_id].log_name
+                            ),
+                        );
+                        Ok(())
+                    }
+                };
+
+                if let Err(err) = result {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "grandpa-catch-up-send-error; peer_id={}; chain={}; error={}",
+                            target, inner.network[chain_id].log_name, err
+                        ),
+                    );
+                }
+            }
+            WakeUpReason::Message(ToBackground::ForegroundSetGrandpaState { chain_id, state }) => {
                 inner
                     .network
-                    .set_chain_local_best_block(chain_id, best_hash, best_number);
+                    .gossip_broadcast_grandpa_state_and_update(chain_id, state);
             }
             WakeUpReason::Message(ToBackground::ForegroundBlocksRequest {
                 target,
@@ -1238,6 +3491,23 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
 
+                if inner.network[chain_id]
+                    .unsupported_protocols
+                    .get(&target)
+                    .map_or(false, |p| p.warp_sync)
+                {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "warp-sync-request-ended; peer_id={}; chain={}; outcome=failure; error=protocol-not-available-cached",
+                            target,
+                            inner.network[chain_id].log_name,
+                        ),
+                    );
+                    let _ = result_tx.send(Err(WarpSyncRequestError::ProtocolNotAvailable));
+                    continue;
+                }
+
                 match inner.network.start_grandpa_warp_sync_request(
                     &target,
                     chain_id,
@@ -1278,6 +3548,23 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
 
+                if inner.network[chain_id]
+                    .unsupported_protocols
+                    .get(&target)
+                    .map_or(false, |p| p.light)
+                {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "storage-request-ended; peer_id={}; chain={}; outcome=failure; error=protocol-not-available-cached",
+                            target,
+                            inner.network[chain_id].log_name,
+                        ),
+                    );
+                    let _ = result_tx.send(Err(StorageProofRequestError::ProtocolNotAvailable));
+                    continue;
+                }
+
                 match inner.network.start_storage_proof_request(
                     &target,
                     chain_id,
@@ -1297,7 +3584,7 @@ async fn background_task(mut inner: Inner) {
                                 inner.network[chain_id].log_name,
                             ),
                         );
-                        let _ = result_tx.send(Err(()));
+                        let _ = result_tx.send(Err(StorageProofRequestError::NoConnection));
                     }
                     Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
                         inner.log_callback.log(
@@ -1308,7 +3595,7 @@ async fn background_task(mut inner: Inner) {
                                 inner.network[chain_id].log_name,
                             ),
                         );
-                        let _ = result_tx.send(Err(()));
+                        let _ = result_tx.send(Err(StorageProofRequestError::RequestTooLarge));
                     }
                 }
             }
@@ -1329,6 +3616,23 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
 
+                if inner.network[chain_id]
+                    .unsupported_protocols
+                    .get(&target)
+                    .map_or(false, |p| p.light)
+                {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "call-proof-request-ended; peer_id={}; chain={}; outcome=failure; error=protocol-not-available-cached",
+                            target,
+                            inner.network[chain_id].log_name,
+                        ),
+                    );
+                    let _ = result_tx.send(Err(CallProofRequestError::ProtocolNotAvailable));
+                    continue;
+                }
+
                 match inner.network.start_call_proof_request(
                     &target,
                     chain_id,
@@ -1348,7 +3652,7 @@ async fn background_task(mut inner: Inner) {
                                 inner.network[chain_id].log_name,
                             ),
                         );
-                        let _ = result_tx.send(Err(()));
+                        let _ = result_tx.send(Err(CallProofRequestError::NoConnection));
                     }
                     Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
                         inner.log_callback.log(
@@ -1359,7 +3663,7 @@ async fn background_task(mut inner: Inner) {
                                 inner.network[chain_id].log_name,
                             ),
                         );
-                        let _ = result_tx.send(Err(()));
+                        let _ = result_tx.send(Err(CallProofRequestError::RequestTooLarge));
                     }
                 }
             }
@@ -1381,6 +3685,92 @@ async fn background_task(mut inner: Inner) {
                         .count(),
                 );
             }
+            WakeUpReason::Message(ToBackground::ForegroundGetGenesisMismatches {
+                chain_id,
+                result_tx,
+            }) => {
+                let _ = result_tx.send(
+                    inner.network[chain_id]
+                        .genesis_mismatches
+                        .iter()
+                        .map(|(_, mismatch)| mismatch.clone())
+                        .collect(),
+                );
+            }
+            WakeUpReason::Message(ToBackground::ForegroundGetNumGenesisMismatches {
+                chain_id,
+                result_tx,
+            }) => {
+                let _ = result_tx.send(inner.network[chain_id].num_genesis_mismatches);
+            }
+            WakeUpReason::Message(ToBackground::ForegroundGetBandwidth { result_tx }) => {
+                // Connections whose remote is still unknown are only accounted in the total.
+                let mut peers = hashbrown::HashMap::<_, (u64, u64), fnv::FnvBuildHasher>::default();
+                for (peer_id, counters) in inner.connections_bandwidth.values() {
+                    let Some(peer_id) = peer_id else { continue };
+                    let entry = peers.entry(peer_id.clone()).or_default();
+                    entry.0 += counters.bytes_in.load(atomic::Ordering::Relaxed);
+                    entry.1 += counters.bytes_out.load(atomic::Ordering::Relaxed);
+                }
+
+                let _ = result_tx.send(Bandwidth {
+                    total_bytes_in: inner
+                        .shared_bandwidth
+                        .total
+                        .bytes_in
+                        .load(atomic::Ordering::Relaxed),
+                    total_bytes_out: inner
+                        .shared_bandwidth
+                        .total
+                        .bytes_out
+                        .load(atomic::Ordering::Relaxed),
+                    peers: peers
+                        .into_iter()
+                        .map(|(peer_id, (bytes_in, bytes_out))| PeerBandwidth {
+                            peer_id,
+                            bytes_in,
+                            bytes_out,
+                        })
+                        .collect(),
+                    protocols: inner.network.protocols_bandwidth().collect(),
+                });
+            }
+            WakeUpReason::Message(ToBackground::ForegroundGetPeers {
+                chain_id,
+                result_tx,
+            }) => {
+                let _ = result_tx.send(
+                    inner.network[chain_id]
+                        .gossip_peers
+                        .iter()
+                        .map(|(peer_id, peer)| {
+                            let identify = inner.peers_identify.get(peer_id);
+                            PeerInfo {
+                                peer_id: peer_id.clone(),
+                                role: peer.role,
+                                best_number: peer.best_number,
+                                best_hash: peer.best_hash,
+                                addresses: inner
+                                    .network
+                                    .established_connections(peer_id)
+                                    .filter_map(|connection_id| {
+                                        Multiaddr::from_bytes(
+                                            inner
+                                                .network
+                                                .connection_remote_addr(connection_id)
+                                                .to_vec(),
+                                        )
+                                        .ok()
+                                    })
+                                    .collect(),
+                                agent_version: identify.map(|i| i.agent_version.clone()),
+                                protocol_version: identify.map(|i| i.protocol_version.clone()),
+                                protocols: identify.map_or(Vec::new(), |i| i.protocols.clone()),
+                            }
+                        })
+                        .collect(),
+                );
+            }
             WakeUpReason::Message(ToBackground::ForegroundGetNumTotalPeers { result_tx }) => {
                 // TODO: optimize?
                 let total = inner
@@ -1433,6 +3823,10 @@ async fn background_task(mut inner: Inner) {
             }) => {
                 inner.num_pending_out_attempts -= 1;
 
+                if let Some((remote, _)) = inner.connections_bandwidth.get_mut(&id) {
+                    *remote = Some(peer_id.clone());
+                }
+
                 let remote_addr =
                     Multiaddr::from_bytes(inner.network.connection_remote_addr(id).to_owned())
                         .unwrap(); // TODO: review this unwrap
@@ -1467,6 +3861,12 @@ async fn background_task(mut inner: Inner) {
                         .log_callback
                         .log(LogLevel::Debug, format!("connected; peer_id={}", peer_id));
                 }
+
+                // Ask the peer for information about itself, and also which address it sees us
+                // as in order to discover the external address of the local node.
+                let _ = inner
+                    .network
+                    .start_identify_request(&peer_id, Duration::from_secs(10));
             }
 
             WakeUpReason::NetworkEvent(service::Event::PreHandshakeDisconnected {
@@ -1474,20 +3874,34 @@ async fn background_task(mut inner: Inner) {
                 ..
             })
             | WakeUpReason::NetworkEvent(service::Event::Disconnected { .. }) => {
-                let (address, peer_id, handshake_finished) = match wake_up_reason {
+                let (id, address, peer_id, handshake_finished) = match wake_up_reason {
                     WakeUpReason::NetworkEvent(service::Event::PreHandshakeDisconnected {
+                        id,
                         address,
                         expected_peer_id: Some(peer_id),
                         ..
-                    }) => (address, peer_id, false),
+                    }) => (id, address, peer_id, false),
                     WakeUpReason::NetworkEvent(service::Event::Disconnected {
+                        id,
                         address,
                         peer_id,
                         ..
-                    }) => (address, peer_id, true),
+                    }) => (id, address, peer_id, true),
                     _ => unreachable!(),
                 };
 
+                inner.outbound_connections_subnets.remove(&id);
+                inner.inbound_connections_ips.remove(&id);
+                inner.connections_bandwidth.remove(&id);
+                if inner
+                    .network
+                    .established_connections(&peer_id)
+                    .next()
+                    .is_none()
+                {
+                    inner.peers_identify.remove(&peer_id);
+                }
+
                 if !handshake_finished {
                     inner.num_pending_out_attempts -= 1;
                 }
@@ -1537,10 +3951,14 @@ async fn background_task(mut inner: Inner) {
             }
 
             WakeUpReason::NetworkEvent(service::Event::PreHandshakeDisconnected {
+                id,
                 expected_peer_id: None,
                 address,
                 ..
             }) => {
+                inner.inbound_connections_ips.remove(&id);
+                inner.connections_bandwidth.remove(&id);
+
                 inner.log_callback.log(
                     LogLevel::Debug,
                     format!(
@@ -1589,6 +4007,15 @@ async fn background_task(mut inner: Inner) {
                             peer_id, inner.network[chain_id].log_name, HashDisplay(&header_hash), decoded_header.number, decoded.is_best
                         ));
 
+                        if decoded.is_best {
+                            if let Some(peer) =
+                                inner.network[chain_id].gossip_peers.get_mut(&peer_id)
+                            {
+                                peer.best_number = decoded_header.number;
+                                peer.best_hash = header_hash;
+                            }
+                        }
+
                         debug_assert!(inner.event_pending_send.is_none());
                         inner.event_pending_send = Some(Event::BlockAnnounce {
                             chain_id,
@@ -1603,6 +4030,19 @@ async fn background_task(mut inner: Inner) {
                             peer_id, inner.network[chain_id].log_name, HashDisplay(&header_hash), decoded.is_best, error
                         ));
 
+                        // Peers that repeatedly send bad announces are banned for longer.
+                        let ban_duration = if lower_reputation(
+                            &mut inner.network[chain_id],
+                            &peer_id,
+                            PeerMisbehavior::InvalidBlockAnnounce,
+                        ) < REPUTATION_BAN_THRESHOLD
+                        {
+                            inner.network[chain_id].peers_reputation.pop(&peer_id);
+                            REPUTATION_BAN_DURATION
+                        } else {
+                            Duration::from_secs(10)
+                        };
+
                         if inner.network.gossip_remove_desired(
                             chain_id,
                             &peer_id,
@@ -1611,7 +4051,7 @@ async fn background_task(mut inner: Inner) {
                             inner.peering_strategy.unassign_slot_and_ban(
                                 &chain_id,
                                 &peer_id,
-                                Instant::now() + Duration::from_secs(10),
+                                Instant::now() + ban_duration,
                             );
                             inner.log_callback.log(
                                 LogLevel::Debug,
@@ -1632,6 +4072,7 @@ async fn background_task(mut inner: Inner) {
             WakeUpReason::NetworkEvent(service::Event::GossipConnected {
                 peer_id,
                 chain_id,
+                role,
                 best_number,
                 best_hash,
                 ..
@@ -1646,6 +4087,14 @@ async fn background_task(mut inner: Inner) {
                         HashDisplay(&best_hash),
                     ),
                 );
+                inner.network[chain_id].gossip_peers.insert(
+                    peer_id.clone(),
+                    GossipPeer {
+                        role,
+                        best_number,
+                        best_hash,
+                    },
+                );
                 debug_assert!(inner.event_pending_send.is_none());
                 inner.event_pending_send = Some(Event::Connected {
                     peer_id,
@@ -1667,6 +4116,8 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
 
+                inner.network[chain_id].gossip_peers.remove(&peer_id);
+
                 // Note that peer doesn't necessarily have an out slot, as this event
                 // might happen as a result of an inbound gossip connection.
                 inner.peering_strategy.unassign_slot_and_ban(
@@ -1721,7 +4172,41 @@ async fn background_task(mut inner: Inner) {
                     );
                 }
 
-                if let service::GossipConnectError::GenesisMismatch { .. } = error {
+                if let service::GossipConnectError::GenesisMismatch {
+                    local_genesis,
+                    remote_genesis,
+                    remote_role,
+                } = error
+                {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "chain-genesis-mismatch; peer_id={}; chain={}; local_genesis={}; \
+                            remote_genesis={}; remote_role={:?}",
+                            peer_id,
+                            inner.network[chain_id].log_name,
+                            HashDisplay(&local_genesis),
+                            HashDisplay(&remote_genesis),
+                            remote_role
+                        ),
+                    );
+
+                    let num_mismatches = inner.network[chain_id]
+                        .genesis_mismatches
+                        .get(&peer_id)
+                        .map_or(0, |mismatch| mismatch.num_mismatches);
+                    inner.network[chain_id].genesis_mismatches.put(
+                        peer_id.clone(),
+                        GenesisMismatch {
+                            peer_id: peer_id.clone(),
+                            remote_genesis_hash: remote_genesis,
+                            remote_role,
+                            num_mismatches: num_mismatches.saturating_add(1),
+                            last_mismatch: Instant::now(),
+                        },
+                    );
+                    inner.network[chain_id].num_genesis_mismatches += 1;
+
                     inner
                         .peering_strategy
                         .unassign_slot_and_remove_chain_peer(&chain_id, &peer_id);
@@ -1829,6 +4314,15 @@ async fn background_task(mut inner: Inner) {
                             format!("warp-sync-request-ended; outcome=failure; peer_id={peer_id}; chain={}; error={}",
                             inner.network[chain_id].log_name, err),
                         );
+
+                        if let service::GrandpaWarpSyncRequestError::Request(err) = err {
+                            if is_protocol_not_available(err) {
+                                inner.network[chain_id]
+                                    .unsupported_protocols
+                                    .get_or_insert_mut(peer_id.clone(), Default::default)
+                                    .warp_sync = true;
+                            }
+                        }
                     }
                 }
 
@@ -1863,6 +4357,15 @@ async fn background_task(mut inner: Inner) {
                                 inner.network[chain_id].log_name, err
                             ),
                         );
+
+                        if let service::StorageProofRequestError::Request(err) = err {
+                            if is_protocol_not_available(err) {
+                                inner.network[chain_id]
+                                    .unsupported_protocols
+                                    .get_or_insert_mut(peer_id.clone(), Default::default)
+                                    .light = true;
+                            }
+                        }
                     }
                 }
 
@@ -1870,7 +4373,7 @@ async fn background_task(mut inner: Inner) {
                     .storage_requests
                     .remove(&substream_id)
                     .unwrap()
-                    .send(response.map_err(|_| ()));
+                    .send(response.map_err(StorageProofRequestError::Request));
             }
             WakeUpReason::NetworkEvent(service::Event::RequestResult {
                 substream_id,
@@ -1898,6 +4401,15 @@ async fn background_task(mut inner: Inner) {
                                 err
                             ),
                         );
+
+                        if let service::CallProofRequestError::Request(err) = err {
+                            if is_protocol_not_available(err) {
+                                inner.network[chain_id]
+                                    .unsupported_protocols
+                                    .get_or_insert_mut(peer_id.clone(), Default::default)
+                                    .light = true;
+                            }
+                        }
                     }
                 }
 
@@ -1905,7 +4417,7 @@ async fn background_task(mut inner: Inner) {
                     .call_proof_requests
                     .remove(&substream_id)
                     .unwrap()
-                    .send(response.map_err(|_| ()));
+                    .send(response.map_err(CallProofRequestError::Request));
             }
             WakeUpReason::NetworkEvent(service::Event::RequestResult {
                 peer_id: kademlia_request_target,
@@ -1990,72 +4502,388 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
             }
-            WakeUpReason::NetworkEvent(service::Event::RequestResult { .. }) => {
-                // We never start a request of any other kind.
-                unreachable!()
-            }
-            WakeUpReason::NetworkEvent(service::Event::RequestInCancel { .. }) => {
-                // Requests are answered immediately, and thus cancelling events can't happen.
-                unreachable!()
-            }
-            WakeUpReason::NetworkEvent(service::Event::IdentifyRequestIn {
+            WakeUpReason::NetworkEvent(service::Event::RequestResult { .. }) => {
+                // We never start a request of any other kind.
+                unreachable!()
+            }
+            WakeUpReason::NetworkEvent(service::Event::IdentifyRequestResult {
+                peer_id,
+                response: Ok(response),
+                ..
+            }) => {
+                let decoded = response.decode();
+                inner.peers_identify.insert(
+                    peer_id.clone(),
+                    PeerIdentify {
+                        agent_version: decoded.agent_version.to_owned(),
+                        protocol_version: decoded.protocol_version.to_owned(),
+                        protocols: decoded.protocols.map(|p| p.to_owned()).collect(),
+                    },
+                );
+
+                // Discovering the external address is pointless if the node isn't listening.
+                if inner.tcp_listeners.is_empty() {
+                    continue;
+                }
+
+                let observed_ip = Multiaddr::from_bytes(decoded.observed_addr.to_vec())
+                    .ok()
+                    .and_then(|addr| match addr.iter().next() {
+                        Some(Protocol::Ip4(ip)) => Some(IpAddr::from(ip)),
+                        Some(Protocol::Ip6(ip)) => Some(IpAddr::from(ip)),
+                        _ => None,
+                    })
+                    .filter(is_global_ip);
+                let Some(observed_ip) = observed_ip else {
+                    continue;
+                };
+
+                let reporters = inner.observed_ips.get_or_insert_mut(observed_ip, Vec::new);
+                if reporters.len() >= OBSERVED_ADDRESS_CONFIRMATIONS || reporters.contains(&peer_id)
+                {
+                    continue;
+                }
+                reporters.push(peer_id);
+                if reporters.len() == OBSERVED_ADDRESS_CONFIRMATIONS {
+                    update_external_addresses(&mut inner);
+                }
+            }
+            WakeUpReason::NetworkEvent(service::Event::IdentifyRequestResult {
+                peer_id,
+                response: Err(error),
+                ..
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!("identify-request-error; peer_id={peer_id}; error={error}"),
+                );
+            }
+            WakeUpReason::NetworkEvent(service::Event::RequestInCancel { substream_id }) => {
+                // Only the requests whose response is built by a separate task can be cancelled,
+                // as the other requests are answered immediately.
+                inner
+                    .incoming_requests_tasks
+                    .get_mut(&substream_id)
+                    .unwrap()
+                    .cancelled = true;
+            }
+            WakeUpReason::IncomingRequestResponse(substream_id, response) => {
+                let task = inner.incoming_requests_tasks.remove(&substream_id).unwrap();
+                if task.cancelled {
+                    continue;
+                }
+
+                match response {
+                    IncomingRequestResponse::State(response) => {
+                        inner.network.respond_state(
+                            substream_id,
+                            match &response {
+                                Ok(proof) => Some(&proof[..]),
+                                Err(error) => {
+                                    inner.log_callback.log(
+                                        LogLevel::Debug,
+                                        format!("incoming-state-request-error; error={}", error),
+                                    );
+                                    None
+                                }
+                            },
+                        );
+                    }
+                    IncomingRequestResponse::StorageProof(response) => {
+                        inner.network.respond_storage_proof(
+                            substream_id,
+                            match &response {
+                                Ok(proof) => Some(&proof[..]),
+                                Err(error) => {
+                                    inner.log_callback.log(
+                                        LogLevel::Debug,
+                                        format!(
+                                            "incoming-storage-proof-request-error; error={}",
+                                            error
+                                        ),
+                                    );
+                                    None
+                                }
+                            },
+                        );
+                    }
+                    IncomingRequestResponse::CallProof(response) => {
+                        inner.network.respond_call_proof(
+                            substream_id,
+                            match &response {
+                                Ok(proof) => Some(&proof[..]),
+                                Err(error) => {
+                                    inner.log_callback.log(
+                                        LogLevel::Debug,
+                                        format!(
+                                            "incoming-call-proof-request-error; error={}",
+                                            error
+                                        ),
+                                    );
+                                    None
+                                }
+                            },
+                        );
+                    }
+                }
+            }
+            WakeUpReason::NetworkEvent(service::Event::IdentifyRequestIn {
+                peer_id,
+                substream_id,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!("identify-request; peer_id={}", peer_id),
+                );
+                inner
+                    .network
+                    .respond_identify(substream_id, &inner.identify_agent_version);
+            }
+            WakeUpReason::NetworkEvent(service::Event::BlocksRequestIn {
+                peer_id,
+                chain_id,
+                config,
+                substream_id,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "incoming-blocks-request; peer_id={}; chain={}",
+                        peer_id, inner.network[chain_id].log_name
+                    ),
+                );
+                let mut _jaeger_span = inner.jaeger_service.incoming_block_request_span(
+                    &inner.local_peer_id,
+                    &peer_id,
+                    config.desired_count.get(),
+                    if let (1, codec::BlocksRequestConfigStart::Hash(block_hash)) =
+                        (config.desired_count.get(), &config.start)
+                    {
+                        Some(block_hash)
+                    } else {
+                        None
+                    },
+                );
+
+                // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
+                let response = blocks_request_response(
+                    &inner.network[chain_id].database,
+                    inner.network.block_number_bytes(chain_id),
+                    config,
+                )
+                .await;
+                inner.network.respond_blocks(
+                    substream_id,
+                    match response {
+                        Ok(b) => Some(b),
+                        Err(error) => {
+                            inner.log_callback.log(
+                                LogLevel::Warn,
+                                format!("incoming-blocks-request-error; error={}", error),
+                            );
+                            None
+                        }
+                    },
+                );
+            }
+            WakeUpReason::NetworkEvent(service::Event::CheckpointRequestIn {
+                peer_id,
+                chain_id,
+                substream_id,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "incoming-checkpoint-request; peer_id={}; chain={}",
+                        peer_id, inner.network[chain_id].log_name
+                    ),
+                );
+
+                // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
+                let response = checkpoint_request_response(&inner.network[chain_id].database).await;
+                inner.network.respond_checkpoint(
+                    substream_id,
+                    match &response {
+                        Ok(chain_information) => Some(chain_information.into()),
+                        Err(error) => {
+                            inner.log_callback.log(
+                                LogLevel::Warn,
+                                format!("incoming-checkpoint-request-error; error={}", error),
+                            );
+                            None
+                        }
+                    },
+                );
+            }
+            WakeUpReason::NetworkEvent(service::Event::StateRequestIn {
+                peer_id,
+                chain_id,
+                block_hash,
+                child_trie,
+                start_key,
+                substream_id,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "incoming-state-request; peer_id={}; chain={}; block={}; start_key={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        HashDisplay(&block_hash),
+                        hex::encode(&start_key)
+                    ),
+                );
+
+                // Building the response can take a long time. In order to not freeze the
+                // networking, it is built by a separate task on a read-only database connection.
+                if !start_incoming_request_task(
+                    &mut inner,
+                    substream_id,
+                    IncomingRequestKind::State,
+                ) {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        "incoming-state-request-error; error=too-many-concurrent-requests"
+                            .to_string(),
+                    );
+                    inner.network.respond_state(substream_id, None);
+                    continue;
+                }
+
+                let database = inner.network[chain_id].database.clone();
+                let responses_tx = inner.incoming_requests_responses_tx.clone();
+                (inner.tasks_executor)(Box::pin(async move {
+                    let response = database
+                        .with_database_read(move |database| {
+                            state_response::build_proof(
+                                database,
+                                &block_hash,
+                                child_trie.as_deref(),
+                                &start_key,
+                            )
+                        })
+                        .await;
+                    let _ = responses_tx
+                        .send((substream_id, IncomingRequestResponse::State(response)))
+                        .await;
+                }));
+            }
+            WakeUpReason::NetworkEvent(service::Event::StorageProofRequestIn {
                 peer_id,
+                chain_id,
+                block_hash,
+                child_trie,
+                keys,
                 substream_id,
             }) => {
                 inner.log_callback.log(
                     LogLevel::Debug,
-                    format!("identify-request; peer_id={}", peer_id),
+                    format!(
+                        "incoming-storage-proof-request; peer_id={}; chain={}; block={}; child_trie={}; num_keys={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        HashDisplay(&block_hash),
+                        child_trie.as_ref().map_or("none".to_owned(), hex::encode),
+                        keys.len()
+                    ),
                 );
-                inner
-                    .network
-                    .respond_identify(substream_id, &inner.identify_agent_version);
+
+                // See the remarks about state requests.
+                if !start_incoming_request_task(
+                    &mut inner,
+                    substream_id,
+                    IncomingRequestKind::StorageProof,
+                ) {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        "incoming-storage-proof-request-error; error=too-many-concurrent-requests"
+                            .to_string(),
+                    );
+                    inner.network.respond_storage_proof(substream_id, None);
+                    continue;
+                }
+
+                let database = inner.network[chain_id].database.clone();
+                let responses_tx = inner.incoming_requests_responses_tx.clone();
+                (inner.tasks_executor)(Box::pin(async move {
+                    let response = database
+                        .with_database_read(move |database| {
+                            light_proofs::build_storage_proof(
+                                database,
+                                &block_hash,
+                                child_trie.as_deref(),
+                                &keys,
+                            )
+                        })
+                        .await;
+                    let _ = responses_tx
+                        .send((
+                            substream_id,
+                            IncomingRequestResponse::StorageProof(response),
+                        ))
+                        .await;
+                }));
             }
-            WakeUpReason::NetworkEvent(service::Event::BlocksRequestIn {
+            WakeUpReason::NetworkEvent(service::Event::CallProofRequestIn {
                 peer_id,
                 chain_id,
-                config,
+                block_hash,
+                method,
+                parameter,
                 substream_id,
             }) => {
                 inner.log_callback.log(
                     LogLevel::Debug,
                     format!(
-                        "incoming-blocks-request; peer_id={}; chain={}",
-                        peer_id, inner.network[chain_id].log_name
+                        "incoming-call-proof-request; peer_id={}; chain={}; block={}; method={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        HashDisplay(&block_hash),
+                        method
                     ),
                 );
-                let mut _jaeger_span = inner.jaeger_service.incoming_block_request_span(
-                    &inner.local_peer_id,
-                    &peer_id,
-                    config.desired_count.get(),
-                    if let (1, codec::BlocksRequestConfigStart::Hash(block_hash)) =
-                        (config.desired_count.get(), &config.start)
-                    {
-                        Some(block_hash)
-                    } else {
-                        None
-                    },
-                );
 
-                // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
-                let response = blocks_request_response(
-                    &inner.network[chain_id].database,
-                    inner.network.block_number_bytes(chain_id),
-                    config,
-                )
-                .await;
-                inner.network.respond_blocks(
+                // See the remarks about state requests. In addition, the runtime call is subject
+                // to the runtime calls limiter and to a timeout.
+                if !start_incoming_request_task(
+                    &mut inner,
                     substream_id,
-                    match response {
-                        Ok(b) => Some(b),
-                        Err(error) => {
-                            inner.log_callback.log(
-                                LogLevel::Warn,
-                                format!("incoming-blocks-request-error; error={}", error),
-                            );
-                            None
-                        }
-                    },
-                );
+                    IncomingRequestKind::CallProof,
+                ) {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        "incoming-call-proof-request-error; error=too-many-concurrent-requests"
+                            .to_string(),
+                    );
+                    inner.network.respond_call_proof(substream_id, None);
+                    continue;
+                }
+
+                let database = inner.network[chain_id].database.clone();
+                let runtime_caches_service = inner.network[chain_id].runtime_caches_service.clone();
+                let runtime_calls_limiter = inner.runtime_calls_limiter.clone();
+                let responses_tx = inner.incoming_requests_responses_tx.clone();
+                (inner.tasks_executor)(Box::pin(async move {
+                    let response = async {
+                        let _permit = runtime_calls_limiter.json_rpc_permit().await;
+                        light_proofs::build_call_proof(
+                            &database,
+                            &runtime_caches_service,
+                            block_hash,
+                            &method,
+                            &parameter,
+                        )
+                        .await
+                    }
+                    .or(async {
+                        smol::Timer::after(CALL_PROOF_REQUEST_TIMEOUT).await;
+                        Err(light_proofs::CallProofError::Timeout)
+                    })
+                    .await;
+                    let _ = responses_tx
+                        .send((substream_id, IncomingRequestResponse::CallProof(response)))
+                        .await;
+                }));
             }
             WakeUpReason::NetworkEvent(service::Event::GrandpaNeighborPacket {
                 chain_id,
@@ -2075,9 +4903,58 @@ async fn background_task(mut inner: Inner) {
                 inner.event_pending_send = Some(Event::GrandpaNeighborPacket {
                     chain_id,
                     peer_id,
+                    round_number: state.round_number,
+                    set_id: state.set_id,
                     finalized_block_height: state.commit_finalized_height,
                 });
             }
+            WakeUpReason::NetworkEvent(service::Event::GrandpaCatchUpRequest {
+                chain_id,
+                peer_id,
+                request,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-catch-up-request; peer_id={}; chain={}; round_number={}; set_id={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        request.round_number,
+                        request.set_id,
+                    ),
+                );
+
+                debug_assert!(inner.event_pending_send.is_none());
+                inner.event_pending_send = Some(Event::GrandpaCatchUpRequest {
+                    chain_id,
+                    peer_id,
+                    round_number: request.round_number,
+                    set_id: request.set_id,
+                });
+            }
+            WakeUpReason::NetworkEvent(service::Event::GrandpaCatchUp {
+                chain_id,
+                peer_id,
+                message,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-catch-up; peer_id={}; chain={}; round_number={}; set_id={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        message.decode().round_number,
+                        message.decode().set_id,
+                    ),
+                );
+
+                debug_assert!(inner.event_pending_send.is_none());
+                inner.event_pending_send = Some(Event::GrandpaCatchUp {
+                    chain_id,
+                    peer_id,
+                    message,
+                });
+            }
             WakeUpReason::NetworkEvent(service::Event::GrandpaCommitMessage {
                 chain_id,
                 peer_id,
@@ -2093,6 +4970,53 @@ async fn background_task(mut inner: Inner) {
                     ),
                 );
             }
+            WakeUpReason::NetworkEvent(service::Event::GrandpaVoteMessage {
+                chain_id,
+                peer_id,
+                message,
+            }) => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "grandpa-vote-message; peer_id={}; chain={}; round_number={}; set_id={}",
+                        peer_id,
+                        inner.network[chain_id].log_name,
+                        message.decode().round_number,
+                        message.decode().set_id,
+                    ),
+                );
+
+                debug_assert!(inner.event_pending_send.is_none());
+                inner.event_pending_send = Some(Event::GrandpaVoteMessage {
+                    chain_id,
+                    peer_id,
+                    message,
+                });
+            }
+            WakeUpReason::NetworkEvent(service::Event::ProtocolError {
+                peer_id,
+                error: service::ProtocolError::MessageSizeViolation(violation),
+            }) => {
+                // Sending messages larger than the protocol limit is never accidental, and the
+                // peer is thus banned for longer than in case of other protocol errors.
+                inner.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "message-size-violation; peer_id={}; kind={}; size={}; max_size={}",
+                        peer_id, violation.kind, violation.size, violation.max_size
+                    ),
+                );
+                inner
+                    .peering_strategy
+                    .unassign_slots_and_ban(&peer_id, Instant::now() + Duration::from_secs(40));
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "all-slots-unassigned; reason=message-size-violation; peer_id={}",
+                        peer_id
+                    ),
+                );
+            }
             WakeUpReason::NetworkEvent(service::Event::ProtocolError { peer_id, error }) => {
                 inner.log_callback.log(
                     LogLevel::Warn,
@@ -2111,6 +5035,76 @@ async fn background_task(mut inner: Inner) {
                 );
             }
 
+            WakeUpReason::BootnodesProvided {
+                chain_id,
+                result: Ok(bootnodes),
+            } => {
+                inner.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "bootnodes-provided; chain={}; num_bootnodes={}",
+                        inner.network[chain_id].log_name,
+                        bootnodes.len()
+                    ),
+                );
+
+                for (peer_id, addr) in bootnodes {
+                    // Note that we must call this function before `insert_address`, as
+                    // documented in `basic_peering_strategy`.
+                    inner
+                        .peering_strategy
+                        .insert_chain_peer(chain_id, peer_id.clone(), usize::MAX);
+                    inner
+                        .peering_strategy
+                        .insert_address(&peer_id, addr.into_bytes(), usize::MAX);
+                }
+            }
+
+            WakeUpReason::BootnodesProvided {
+                chain_id,
+                result: Err(error),
+            } => {
+                inner.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "bootnodes-provider-error; chain={}; error={}",
+                        inner.network[chain_id].log_name, error
+                    ),
+                );
+            }
+
+            WakeUpReason::PortMapping {
+                listener_index,
+                result,
+            } => {
+                let (local_addr, is_websocket) = inner.tcp_listeners[listener_index];
+                match result {
+                    Ok(mapping) => {
+                        let external_addr = tcp_multiaddr(
+                            SocketAddr::from((mapping.external_ip, mapping.external_port)),
+                            is_websocket,
+                        );
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "port-mapped; local_addr={local_addr}; external_addr={external_addr}; lifetime={:?}",
+                                mapping.lifetime
+                            ),
+                        );
+                        inner.nat_mapped_addresses[listener_index] = Some(external_addr);
+                    }
+                    Err(error) => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!("port-mapping-error; local_addr={local_addr}; error={error}"),
+                        );
+                        inner.nat_mapped_addresses[listener_index] = None;
+                    }
+                }
+
+                update_external_addresses(&mut inner);
+            }
+
             WakeUpReason::CanAssignSlot(peer_id, chain_id) => {
                 inner.peering_strategy.assign_slot(&chain_id, &peer_id);
 
@@ -2180,9 +5174,75 @@ async fn background_task(mut inner: Inner) {
                     }
                 };
 
+                // Refuse to connect if there are already too many outgoing connections towards
+                // the same subnet. The peer is banned for a short time so that a different peer
+                // is picked instead.
+                let subnet = AddressSubnet::from_multiaddr(&multiaddr);
+                if let (Some(subnet), Some(max)) =
+                    (subnet, inner.max_outbound_connections_per_subnet)
+                {
+                    let num_in_subnet = inner
+                        .outbound_connections_subnets
+                        .values()
+                        .filter(|s| **s == subnet)
+                        .count();
+                    if num_in_subnet >= max.get() {
+                        inner.num_pending_out_attempts -= 1;
+                        inner
+                            .peering_strategy
+                            .decrease_address_connections(&peer_id, multiaddr.as_ref())
+                            .unwrap();
+                        inner.network.gossip_remove_desired_all(
+                            &peer_id,
+                            service::GossipKind::ConsensusTransactions,
+                        );
+                        for (chain_id, what_happened) in
+                            inner.peering_strategy.unassign_slots_and_ban(
+                                &peer_id,
+                                Instant::now() + Duration::from_secs(30),
+                            )
+                        {
+                            if matches!(
+                                what_happened,
+                                basic_peering_strategy::UnassignSlotsAndBan::Banned {
+                                    had_slot: true
+                                }
+                            ) {
+                                inner.log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "slot-unassigned; peer_id={}; chain={}; address={}; reason=subnet-limit",
+                                        peer_id, inner.network[*chain_id].log_name, multiaddr
+                                    ),
+                                );
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                // Connection attempts are only started if the budget of file descriptors isn't
+                // exhausted, so this isn't supposed to fail. It is nonetheless handled gracefully
+                // by giving up on the attempt.
+                let Some(fd_permit) = inner.fd_budget.try_acquire_socket() else {
+                    inner.num_pending_out_attempts -= 1;
+                    inner
+                        .peering_strategy
+                        .decrease_address_connections(&peer_id, multiaddr.as_ref())
+                        .unwrap();
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "start-connecting-postponed; peer_id={}; address={}; reason=file-descriptors-budget",
+                            peer_id, multiaddr
+                        ),
+                    );
+                    continue;
+                };
+
                 // Convert the `multiaddr` (typically of the form `/ip4/a.b.c.d/tcp/d`) into
                 // a `Future<dyn Output = Result<TcpStream, ...>>`.
-                let socket = match tasks::multiaddr_to_socket(&multiaddr) {
+                let socket = match tasks::multiaddr_to_socket(&multiaddr, inner.socks5_proxy) {
                     Ok(socket) => socket,
                     Err(_) => {
                         // Address is in an invalid format or isn't supported.
@@ -2222,8 +5282,20 @@ async fn background_task(mut inner: Inner) {
                     tx,
                 );
 
-                // Handle the connection in a separate task.
-                (inner.tasks_executor)(Box::pin(tasks::connection_task(
+                if let Some(subnet) = subnet {
+                    inner
+                        .outbound_connections_subnets
+                        .insert(connection_id, subnet);
+                }
+
+                let bandwidth = Arc::new(tasks::BandwidthCounters::default());
+                inner
+                    .connections_bandwidth
+                    .insert(connection_id, (Some(peer_id.clone()), bandwidth.clone()));
+
+                // Handle the connection in a separate task. The socket is given back to the
+                // budget of file descriptors when the task ends.
+                let task = tasks::connection_task(
                     inner.log_callback.clone(),
                     multiaddr.to_string(),
                     socket,
@@ -2231,7 +5303,13 @@ async fn background_task(mut inner: Inner) {
                     connection_task,
                     rx,
                     inner.from_connections_tx.clone(),
-                )));
+                    bandwidth,
+                    inner.shared_bandwidth.clone(),
+                );
+                (inner.tasks_executor)(Box::pin(async move {
+                    task.await;
+                    drop(fd_permit);
+                }));
             }
 
             WakeUpReason::CanOpenGossip(peer_id, chain_id) => {
@@ -2271,6 +5349,7 @@ async fn blocks_request_response(
 
             let mut output = Vec::with_capacity(num_blocks);
             let mut next_block = config.start;
+            let mut bodies_size = 0u64;
 
             loop {
                 if output.len() >= num_blocks {
@@ -2293,6 +5372,22 @@ async fn blocks_request_response(
                     None => break,
                 };
 
+                // Check the size of the body before loading it, in order to not load in memory
+                // a body that wouldn't fit in the response anyway. The first block is always
+                // included, so that the remote can make progress even if a single body exceeds
+                // the limit.
+                if config.fields.body {
+                    let Some((_, body_size)) = database.block_body_size(&hash)? else {
+                        break;
+                    };
+                    if !output.is_empty()
+                        && bodies_size.saturating_add(body_size) > MAX_BLOCKS_RESPONSE_BODIES_SIZE
+                    {
+                        break;
+                    }
+                    bodies_size = bodies_size.saturating_add(body_size);
+                }
+
                 next_block = {
                     let decoded = header::decode(&header, block_number_bytes).unwrap();
                     match config.direction {
@@ -2322,8 +5417,17 @@ async fn blocks_request_response(
                         None
                     },
                     justifications: if config.fields.justifications {
-                        // TODO: justifications aren't saved in database at the moment
-                        Some(Vec::new())
+                        Some(
+                            database
+                                .block_justifications(&hash)?
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|(engine_id, justification)| codec::Justification {
+                                    engine_id,
+                                    justification,
+                                })
+                                .collect(),
+                        )
                     } else {
                         None
                     },
@@ -2334,3 +5438,97 @@ async fn blocks_request_response(
         })
         .await
 }
+
+/// Builds the response to a checkpoint request, in other words the information about the latest
+/// finalized block found in the database.
+async fn checkpoint_request_response(
+    database: &database_thread::DatabaseThread,
+) -> Result<chain_information::ValidChainInformation, full_sqlite::StorageAccessError> {
+    database
+        .with_database(move |database| {
+            let finalized_block_hash = database.finalized_block_hash()?;
+            database.to_chain_information(&finalized_block_hash)
+        })
+        .await
+}
+
+/// Compresses a response to a block or state request. See [`service::Config::compress_response`].
+fn compress_response(response: Vec<u8>) -> Vec<u8> {
+    // Level 1 is the fastest level of the reference implementation. Compressing is only worth it
+    // if it doesn't slow down answering requests too much.
+    match zstd::bulk::compress(&response, 1) {
+        Ok(compressed) if compressed.len() < response.len() => compressed,
+        _ => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::iter;
+
+    #[test]
+    fn subnet_limit_ignores_non_global_addresses() {
+        for address in [
+            "/ip4/127.0.0.1/tcp/30333",
+            "/ip4/192.168.1.10/tcp/30333",
+            "/ip4/10.0.0.1/tcp/30333",
+            "/ip6/::1/tcp/30333",
+            "/ip6/fd00::1/tcp/30333",
+            "/dns/localhost/tcp/30333",
+        ] {
+            let multiaddr = address.parse::<Multiaddr>().unwrap();
+            assert_eq!(AddressSubnet::from_multiaddr(&multiaddr), None, "{address}");
+        }
+
+        assert_eq!(
+            AddressSubnet::from_multiaddr(&"/ip4/1.2.3.4/tcp/30333".parse().unwrap()),
+            Some(AddressSubnet::Ipv4([1, 2, 3]))
+        );
+        assert_eq!(
+            AddressSubnet::from_multiaddr(&"/ip4/1.2.3.5/tcp/30333".parse().unwrap()),
+            Some(AddressSubnet::Ipv4([1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn inbound_limit_ignores_non_global_addresses() {
+        let max = NonZeroUsize::new(2);
+
+        for ip in ["127.0.0.1", "192.168.1.10", "::1"] {
+            let ip = ip.parse::<IpAddr>().unwrap();
+            assert!(!inbound_connections_limit_reached(
+                ip,
+                max,
+                &Default::default(),
+                vec![ip; 10].into_iter()
+            ));
+        }
+
+        let ip = "1.2.3.4".parse::<IpAddr>().unwrap();
+        assert!(!inbound_connections_limit_reached(
+            ip,
+            max,
+            &Default::default(),
+            iter::once(ip)
+        ));
+        assert!(inbound_connections_limit_reached(
+            ip,
+            max,
+            &Default::default(),
+            vec![ip; 2].into_iter()
+        ));
+        assert!(!inbound_connections_limit_reached(
+            ip,
+            max,
+            &iter::once(ip).collect(),
+            vec![ip; 2].into_iter()
+        ));
+        assert!(!inbound_connections_limit_reached(
+            ip,
+            None,
+            &Default::default(),
+            vec![ip; 2].into_iter()
+        ));
+    }
+}
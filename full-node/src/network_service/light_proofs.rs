@@ -0,0 +1,384 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Building the responses to incoming storage proof and call proof requests of the light client
+//! protocol.
+//!
+//! Both kinds of responses contain a Merkle proof in the format of Substrate: a SCALE-encoded
+//! list of node values, in no particular order. The proof of a key contains the node values of
+//! all the nodes between the root of the trie and the node of this key. Nodes whose node value
+//! is shorter than 32 bytes are inlined within the node value of their parent and are thus not
+//! part of the list. When the storage value of a proven key is hashed within its node value, the
+//! storage value itself is also added to the list.
+//!
+//! A call proof is the proof of all the keys that the runtime accesses during the call, plus
+//! `:code` and `:heappages`.
+//!
+//! > **Note**: The database doesn't store node values. Instead, each node value is rebuilt from
+//! >           the storage value of the node and the Merkle values of its children. The trie is
+//! >           traversed by following the Merkle values of the children, which requires two
+//! >           database queries per node.
+
+use crate::{database_thread, runtime_caches_service};
+
+use hashbrown::HashSet;
+use smoldot::{
+    database::full_sqlite,
+    executor::{host, runtime_call},
+    trie,
+};
+use std::iter;
+
+/// Prefix of the keys of the main trie that correspond to default child tries.
+const CHILD_STORAGE_DEFAULT_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Builds the Merkle proof to send back in response to a storage proof request.
+///
+/// If `child_trie` is `Some`, the keys belong to the given default child trie, and the proof
+/// also contains the path to this child trie in the main trie.
+pub(super) fn build_storage_proof(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+    child_trie: Option<&[u8]>,
+    keys: &[Vec<u8>],
+) -> Result<Vec<u8>, full_sqlite::StorageAccessError> {
+    let mut proof_nodes = HashSet::new();
+
+    let main_trie_root = database.block_storage_root_merkle_value(block_hash)?;
+    let trie_root = match child_trie {
+        None => main_trie_root,
+        Some(child_trie) => {
+            match add_path(
+                database,
+                &main_trie_root,
+                &child_trie_path(child_trie),
+                &mut proof_nodes,
+            )? {
+                Some(child_trie_root) => child_trie_root,
+                // The proof of the absence of the child trie is enough to prove the absence of
+                // all the keys.
+                None => return Ok(encode_proof(proof_nodes)),
+            }
+        }
+    };
+
+    for key in keys {
+        add_path(database, &trie_root, &nibbles(key), &mut proof_nodes)?;
+    }
+    Ok(encode_proof(proof_nodes))
+}
+
+/// Performs the given runtime call against the storage of the given block, and builds the Merkle
+/// proof to send back in response to a call proof request.
+///
+/// The runtime of the block is obtained from the given cache, and is thus only compiled the
+/// first time it is needed.
+pub(super) async fn build_call_proof(
+    database: &database_thread::DatabaseThread,
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    block_hash: [u8; 32],
+    method: &str,
+    parameter: &[u8],
+) -> Result<Vec<u8>, CallProofError> {
+    // List of `(trie, key)` tuples, where `trie` is the path of the child trie within the main
+    // trie or `None` for the main trie, and `key` the key in nibbles whose path to prove.
+    let mut proven_paths = vec![(None, nibbles(b":code")), (None, nibbles(b":heappages"))];
+
+    let virtual_machine = runtime_caches_service
+        .get(block_hash)
+        .await
+        .map_err(CallProofError::Runtime)?;
+
+    let mut call = runtime_call::run(runtime_call::Config {
+        virtual_machine: (*virtual_machine).clone(),
+        function_to_call: method,
+        parameter: iter::once(&parameter),
+        storage_proof_size_behavior:
+            runtime_call::StorageProofSizeBehavior::proof_recording_disabled(),
+        storage_main_trie_changes: Default::default(),
+        max_log_level: 0,
+        calculate_trie_changes: false,
+    })
+    .map_err(|(err, _)| CallProofError::RuntimeStartError(err))?;
+
+    loop {
+        match call {
+            runtime_call::RuntimeCall::Finished(Ok(_)) => break,
+            runtime_call::RuntimeCall::Finished(Err(error)) => {
+                return Err(CallProofError::RuntimeExecutionError(error.detail));
+            }
+            runtime_call::RuntimeCall::StorageGet(req) => {
+                let trie = req.child_trie().map(|t| child_trie_path(t.as_ref()));
+                let key = nibbles(req.key().as_ref());
+                proven_paths.push((trie.clone(), key.clone()));
+
+                let value = database
+                    .with_database_read(move |database| {
+                        database.block_storage_get(
+                            &block_hash,
+                            trie.into_iter().map(|t| t.into_iter()),
+                            key.into_iter(),
+                        )
+                    })
+                    .await?;
+                let value = match value {
+                    Some((ref value, version)) => Some((
+                        iter::once(&value[..]),
+                        runtime_call::TrieEntryVersion::try_from(version)
+                            .map_err(|_| CallProofError::DatabaseInvalidStateTrieVersion)?,
+                    )),
+                    None => None,
+                };
+                call = req.inject_value(value);
+            }
+            runtime_call::RuntimeCall::ClosestDescendantMerkleValue(req) => {
+                let trie = req.child_trie().map(|t| child_trie_path(t.as_ref()));
+                let key = req.key().map(u8::from).collect::<Vec<_>>();
+                proven_paths.push((trie.clone(), key.clone()));
+
+                let merkle_value = database
+                    .with_database_read(move |database| {
+                        database.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            trie.into_iter().map(|t| t.into_iter()),
+                            key.into_iter(),
+                        )
+                    })
+                    .await?;
+                call = req.inject_merkle_value(merkle_value.as_deref());
+            }
+            runtime_call::RuntimeCall::NextKey(req) => {
+                let trie = req.child_trie().map(|t| child_trie_path(t.as_ref()));
+                let key = req.key().map(u8::from).collect::<Vec<_>>();
+                let prefix = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+                proven_paths.push((trie.clone(), key.clone()));
+
+                let search_start = key
+                    .iter()
+                    .copied()
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let next_key = {
+                    let trie = trie.clone();
+                    database
+                        .with_database_read(move |database| {
+                            database.block_storage_next_key(
+                                &block_hash,
+                                trie.into_iter().map(|t| t.into_iter()),
+                                search_start.into_iter(),
+                                prefix.into_iter(),
+                                branch_nodes,
+                            )
+                        })
+                        .await?
+                };
+
+                // The path to the key that follows must also be proven, in order to prove that
+                // there isn't any other key in between.
+                if let Some(next_key) = &next_key {
+                    proven_paths.push((trie, next_key.clone()));
+                }
+
+                call = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|n| trie::Nibble::try_from(n).unwrap())),
+                );
+            }
+            runtime_call::RuntimeCall::OffchainStorageSet(req) => {
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::LogEmit(req) => {
+                call = req.resume();
+            }
+            runtime_call::RuntimeCall::SignatureVerification(sig) => {
+                call = sig.verify_and_resume();
+            }
+            runtime_call::RuntimeCall::Offchain(_) => {
+                return Err(CallProofError::ForbiddenHostFunction);
+            }
+        }
+    }
+
+    let proof = database
+        .with_database_read(move |database| {
+            let mut proof_nodes = HashSet::new();
+            let main_trie_root = database.block_storage_root_merkle_value(&block_hash)?;
+            for (trie, key) in &proven_paths {
+                // Child tries are only reachable through their root in the main trie.
+                let trie_root = match trie {
+                    None => main_trie_root.clone(),
+                    Some(trie) => {
+                        match add_path(database, &main_trie_root, trie, &mut proof_nodes)? {
+                            Some(child_trie_root) => child_trie_root,
+                            None => continue,
+                        }
+                    }
+                };
+                add_path(database, &trie_root, key, &mut proof_nodes)?;
+            }
+            Ok::<_, full_sqlite::StorageAccessError>(encode_proof(proof_nodes))
+        })
+        .await?;
+
+    Ok(proof)
+}
+
+/// Error potentially returned by [`build_call_proof`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub(super) enum CallProofError {
+    /// Error while accessing the storage of the block in the database.
+    #[display(fmt = "{_0}")]
+    StorageAccess(full_sqlite::StorageAccessError),
+    /// Error while obtaining the runtime of the block.
+    #[display(fmt = "{_0}")]
+    #[from(ignore)]
+    Runtime(runtime_caches_service::GetError),
+    /// Error starting the runtime execution.
+    #[display(fmt = "{_0}")]
+    RuntimeStartError(host::StartErr),
+    /// Error while executing the runtime.
+    #[display(fmt = "{_0}")]
+    RuntimeExecutionError(runtime_call::ErrorDetail),
+    /// State trie version stored in database is invalid.
+    DatabaseInvalidStateTrieVersion,
+    /// Runtime has tried to call a forbidden host function.
+    ForbiddenHostFunction,
+    /// Building the response has taken too long.
+    Timeout,
+}
+
+/// Adds to `proof_nodes` the entries necessary to prove the node values of all the nodes between
+/// the root node of a trie, whose Merkle value is `root_merkle_value`, and the given key.
+///
+/// Returns the storage value of the node whose key is `key`, if any. If `key` is the key of a
+/// child trie within the main trie, this is the Merkle value of the root node of the child trie.
+fn add_path(
+    database: &full_sqlite::SqliteFullDatabase,
+    root_merkle_value: &[u8],
+    key: &[u8],
+    proof_nodes: &mut HashSet<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, full_sqlite::StorageAccessError> {
+    let mut merkle_value = root_merkle_value.to_vec();
+    let mut node_key = Vec::new();
+
+    loop {
+        let full_sqlite::TrieNode {
+            partial_key_nibbles,
+            storage_value,
+            children_merkle_values,
+        } = database.trie_node(&merkle_value)?;
+
+        let is_root = node_key.is_empty();
+        let partial_key_start = node_key.len();
+        node_key.extend_from_slice(&partial_key_nibbles);
+
+        let storage_value_hash = match &storage_value {
+            Some((value, 1)) if value.len() >= 33 => Some(
+                *<&[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], value).as_bytes())
+                    .unwrap(),
+            ),
+            _ => None,
+        };
+
+        // Merkle value of the child to continue the traversal with, if any.
+        let next_child = if node_key != key && key.starts_with(&node_key) {
+            let nibble = key[node_key.len()];
+            children_merkle_values[usize::from(nibble)]
+                .clone()
+                .map(|child| (nibble, child))
+        } else {
+            None
+        };
+
+        let node_value = trie::trie_node::encode_to_vec(trie::trie_node::Decoded {
+            children: children_merkle_values,
+            partial_key: node_key[partial_key_start..]
+                .iter()
+                .map(|n| trie::Nibble::try_from(*n).unwrap()),
+            storage_value: match (&storage_value, &storage_value_hash) {
+                (_, Some(hash)) => trie::trie_node::StorageValue::Hashed(hash),
+                (Some((value, _)), None) => trie::trie_node::StorageValue::Unhashed(value),
+                (None, None) => trie::trie_node::StorageValue::None,
+            },
+        })
+        .unwrap();
+
+        // Nodes whose node value is small enough are inlined within the node value of their
+        // parent. The root node is always part of the proof.
+        if is_root || node_value.len() >= 32 {
+            proof_nodes.insert(node_value);
+        }
+
+        if node_key == key {
+            let Some((value, _)) = storage_value else {
+                return Ok(None);
+            };
+            if storage_value_hash.is_some() {
+                proof_nodes.insert(value.clone());
+            }
+            return Ok(Some(value));
+        }
+
+        let Some((child_nibble, child_merkle_value)) = next_child else {
+            return Ok(None);
+        };
+
+        node_key.push(child_nibble);
+        merkle_value = child_merkle_value;
+    }
+}
+
+/// Encodes the given list of entries into a proof.
+fn encode_proof(proof_nodes: HashSet<Vec<u8>>) -> Vec<u8> {
+    let mut proof =
+        Vec::with_capacity(proof_nodes.iter().fold(0, |sum, node| sum + node.len() + 4) + 4);
+    proof.extend_from_slice(&encode_scale_compact_usize(proof_nodes.len()));
+    for node in proof_nodes {
+        proof.extend_from_slice(&encode_scale_compact_usize(node.len()));
+        proof.extend_from_slice(&node);
+    }
+    proof
+}
+
+/// Returns the path in nibbles, within the main trie, of the given default child trie.
+fn child_trie_path(child_trie: &[u8]) -> Vec<u8> {
+    let mut key = CHILD_STORAGE_DEFAULT_PREFIX.to_vec();
+    key.extend_from_slice(child_trie);
+    nibbles(&key)
+}
+
+/// Turns the given bytes into a list of nibbles, in the format expected by the database.
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    trie::bytes_to_nibbles(bytes.iter().copied())
+        .map(u8::from)
+        .collect()
+}
+
+/// Returns the SCALE-compact encoding of the given number.
+///
+/// Only numbers strictly inferior to 2^30 are supported, which is more than enough for the
+/// lengths found in a proof.
+fn encode_scale_compact_usize(value: usize) -> Vec<u8> {
+    if value < 1 << 6 {
+        vec![(value as u8) << 2]
+    } else if value < 1 << 14 {
+        ((value as u16) << 2 | 0b01).to_le_bytes().to_vec()
+    } else {
+        assert!(value < 1 << 30);
+        ((value as u32) << 2 | 0b10).to_le_bytes().to_vec()
+    }
+}
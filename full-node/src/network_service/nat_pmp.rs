@@ -0,0 +1,214 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Client of the NAT Port Mapping Protocol (NAT-PMP).
+//!
+//! NAT-PMP makes it possible to ask the router of the local network to forward a port of its
+//! public IP address to the local machine. Most home routers that support PCP also support
+//! NAT-PMP.
+//!
+//! See <https://www.rfc-editor.org/rfc/rfc6886>.
+
+use super::PortMapping;
+
+use futures_lite::FutureExt as _;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+/// UDP port the NAT-PMP server of the gateway listens on.
+const SERVER_PORT: u16 = 5351;
+
+/// Number of times a request is sent before giving up. The first retransmission happens after
+/// 250ms, and the delay doubles every time.
+// Note: the RFC recommends 9 attempts, which adds up to more than a minute. Routers that
+// support NAT-PMP answer quickly, so we give up sooner.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Error potentially returned by [`map_tcp_port`].
+#[derive(Debug, derive_more::Display)]
+pub(super) enum NatPmpError {
+    /// The IP address of the gateway couldn't be determined.
+    #[display(fmt = "{_0}")]
+    DefaultGateway(DefaultGatewayError),
+    /// Error while sending or receiving UDP packets.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The gateway didn't answer.
+    Timeout,
+    /// The gateway has sent back a malformed response.
+    InvalidResponse,
+    /// The gateway has refused the request.
+    #[display(fmt = "Request refused with result code {_0}")]
+    Refused(u16),
+}
+
+/// Error potentially returned by [`default_gateway`].
+#[derive(Debug, derive_more::Display)]
+pub(super) enum DefaultGatewayError {
+    /// Finding the default gateway is only implemented on Linux.
+    #[cfg(not(target_os = "linux"))]
+    #[display(fmt = "Finding the default gateway isn't supported on this platform")]
+    UnsupportedPlatform,
+    /// Error while reading the routing table.
+    #[cfg(target_os = "linux")]
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The routing table doesn't contain any default route.
+    #[cfg(target_os = "linux")]
+    NoDefaultRoute,
+}
+
+/// Returns the IPv4 address of the default gateway of the machine.
+///
+/// Only implemented on Linux, where the routing table is found in `/proc/net/route`. Returns
+/// `DefaultGatewayError::UnsupportedPlatform` on other platforms.
+#[cfg(target_os = "linux")]
+pub(super) fn default_gateway() -> Result<Ipv4Addr, DefaultGatewayError> {
+    let routes = std::fs::read_to_string("/proc/net/route").map_err(DefaultGatewayError::Io)?;
+
+    // Each line has the format `Iface Destination Gateway Flags ...`, where the destination and
+    // gateway are IPv4 addresses encoded in hexadecimal in the native endianness.
+    for line in routes.lines().skip(1) {
+        let mut columns = line.split_whitespace();
+        let (Some(_), Some(destination), Some(gateway)) =
+            (columns.next(), columns.next(), columns.next())
+        else {
+            continue;
+        };
+
+        if destination != "00000000" {
+            continue;
+        }
+
+        let Ok(gateway) = u32::from_str_radix(gateway, 16) else {
+            continue;
+        };
+        let gateway = Ipv4Addr::from(gateway.to_ne_bytes());
+        if !gateway.is_unspecified() {
+            return Ok(gateway);
+        }
+    }
+
+    Err(DefaultGatewayError::NoDefaultRoute)
+}
+
+/// Returns the IPv4 address of the default gateway of the machine.
+///
+/// Only implemented on Linux, where the routing table is found in `/proc/net/route`. Returns
+/// `DefaultGatewayError::UnsupportedPlatform` on other platforms.
+#[cfg(not(target_os = "linux"))]
+pub(super) fn default_gateway() -> Result<Ipv4Addr, DefaultGatewayError> {
+    Err(DefaultGatewayError::UnsupportedPlatform)
+}
+
+/// Asks the NAT-PMP server of the default gateway of the machine to forward a TCP port of its
+/// public IP address to the given local port.
+///
+/// The mapping must be renewed by calling this function again before its lifetime expires.
+pub(super) async fn map_tcp_port(
+    internal_port: u16,
+    lifetime: Duration,
+) -> Result<PortMapping, NatPmpError> {
+    let gateway = default_gateway().map_err(NatPmpError::DefaultGateway)?;
+
+    // Public address request.
+    let response = request(gateway, &[0, 0]).await?;
+    if response.len() < 12 || response[1] != 128 {
+        return Err(NatPmpError::InvalidResponse);
+    }
+    check_result_code(&response)?;
+    let external_ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+
+    // TCP mapping request. The external port suggested is the same as the internal port.
+    let mut mapping_request = [0; 12];
+    mapping_request[1] = 2;
+    mapping_request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    mapping_request[6..8].copy_from_slice(&internal_port.to_be_bytes());
+    mapping_request[8..12].copy_from_slice(
+        &u32::try_from(lifetime.as_secs())
+            .unwrap_or(u32::MAX)
+            .to_be_bytes(),
+    );
+
+    let response = request(gateway, &mapping_request).await?;
+    if response.len() < 16 || response[1] != 130 {
+        return Err(NatPmpError::InvalidResponse);
+    }
+    check_result_code(&response)?;
+    if u16::from_be_bytes([response[8], response[9]]) != internal_port {
+        return Err(NatPmpError::InvalidResponse);
+    }
+
+    Ok(PortMapping {
+        external_ip,
+        external_port: u16::from_be_bytes([response[10], response[11]]),
+        lifetime: Duration::from_secs(u64::from(u32::from_be_bytes([
+            response[12],
+            response[13],
+            response[14],
+            response[15],
+        ]))),
+    })
+}
+
+/// Sends the given request to the NAT-PMP server of the gateway and waits for the response,
+/// retransmitting the request if necessary.
+async fn request(gateway: Ipv4Addr, payload: &[u8]) -> Result<Vec<u8>, NatPmpError> {
+    let socket = smol::net::UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .await
+        .map_err(NatPmpError::Io)?;
+    socket
+        .connect(SocketAddr::from((gateway, SERVER_PORT)))
+        .await
+        .map_err(NatPmpError::Io)?;
+
+    let mut timeout = Duration::from_millis(250);
+    for _ in 0..MAX_ATTEMPTS {
+        socket.send(payload).await.map_err(NatPmpError::Io)?;
+
+        let mut buffer = [0; 16];
+        let received = async { Some(socket.recv(&mut buffer).await) }
+            .or(async {
+                smol::Timer::after(timeout).await;
+                None
+            })
+            .await;
+
+        match received {
+            Some(Ok(len)) => return Ok(buffer[..len].to_vec()),
+            Some(Err(err)) => return Err(NatPmpError::Io(err)),
+            None => timeout *= 2,
+        }
+    }
+
+    Err(NatPmpError::Timeout)
+}
+
+/// Checks the version and result code of a response.
+fn check_result_code(response: &[u8]) -> Result<(), NatPmpError> {
+    if response[0] != 0 {
+        return Err(NatPmpError::InvalidResponse);
+    }
+
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => Err(NatPmpError::Refused(code)),
+    }
+}
@@ -0,0 +1,278 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Building the responses to incoming state requests.
+//!
+//! A response to a state request is a compact Merkle proof of the storage entries of a block
+//! that start at the key indicated in the request. The trie is traversed in lexicographic order
+//! starting from its root node, and the proof contains the nodes whose key is an ancestor of the
+//! start key, then all the nodes that follow the start key until the size limit is reached.
+//!
+//! The format of the proof is the one of Substrate: a SCALE-encoded list of node values, in
+//! the order in which the nodes are traversed. When a child of a node is itself part of the
+//! proof, the reference to this child is replaced with an empty inline reference. When the
+//! storage value of a node is hashed and is part of the proof, it is removed from the node
+//! value, the node value is prefixed with a `0x01` byte, and the storage value immediately
+//! follows the node value in the list.
+//!
+//! The nodes of the default child tries found within the range of the proof are added at the
+//! end of the list, in the order in which the child tries are found in the main trie.
+//!
+//! > **Note**: The database doesn't store node values. Instead, each node value is rebuilt from
+//! >           the storage value of the node and the Merkle values of its children. The trie is
+//! >           traversed by following the Merkle values of the children, which requires two
+//! >           database queries per node.
+
+use smoldot::{database::full_sqlite, trie};
+use std::mem;
+
+/// Size, in bytes, after which no more nodes are added to the proof.
+// Note: Substrate limits the size of responses to 2 MiB, and its implementation stops adding
+// entries when half of this limit is reached. We do the same.
+const PROOF_SIZE_LIMIT: usize = 1024 * 1024;
+
+/// Prefix of the keys of the main trie that correspond to default child tries.
+const CHILD_STORAGE_DEFAULT_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Builds the compact Merkle proof to send back in response to a state request.
+///
+/// If `child_trie` is `Some`, the proof starts at `start_key` within the given default child
+/// trie, then continues with the entries of the main trie that follow this child trie.
+pub(super) fn build_proof(
+    database: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+    child_trie: Option<&[u8]>,
+    start_key: &[u8],
+) -> Result<Vec<u8>, full_sqlite::StorageAccessError> {
+    let mut builder = Builder {
+        database,
+        proof_size: 0,
+        child_tries_nodes: Vec::new(),
+    };
+
+    let root_merkle_value = database.block_storage_root_merkle_value(block_hash)?;
+    let main_trie_nodes = match child_trie {
+        None => builder.trie_nodes(&root_merkle_value, true, &nibbles(start_key), None)?,
+        Some(child_trie) => {
+            let mut child_trie_key = CHILD_STORAGE_DEFAULT_PREFIX.to_vec();
+            child_trie_key.extend_from_slice(child_trie);
+            builder.trie_nodes(
+                &root_merkle_value,
+                true,
+                &nibbles(&child_trie_key),
+                Some((&child_trie_key, &nibbles(start_key))),
+            )?
+        }
+    };
+
+    let num_nodes = main_trie_nodes.len() + builder.child_tries_nodes.len();
+    let mut proof = Vec::with_capacity(builder.proof_size + 4 * num_nodes + 4);
+    proof.extend_from_slice(&encode_scale_compact_usize(num_nodes));
+    for node in main_trie_nodes
+        .iter()
+        .chain(builder.child_tries_nodes.iter())
+    {
+        proof.extend_from_slice(&encode_scale_compact_usize(node.len()));
+        proof.extend_from_slice(node);
+    }
+    Ok(proof)
+}
+
+struct Builder<'a> {
+    database: &'a full_sqlite::SqliteFullDatabase,
+    /// Sum of the sizes of all the entries added to the proof so far.
+    proof_size: usize,
+    /// Entries of the proof that concern child tries.
+    child_tries_nodes: Vec<Vec<u8>>,
+}
+
+impl<'a> Builder<'a> {
+    /// Returns the entries of the proof of the trie whose root node has the given Merkle value,
+    /// starting at the given key.
+    ///
+    /// If `requested_child_trie` is `Some`, the child trie at this key starts at the given key
+    /// rather than at its beginning.
+    fn trie_nodes(
+        &mut self,
+        root_merkle_value: &[u8],
+        is_main_trie: bool,
+        start_key: &[u8],
+        requested_child_trie: Option<(&[u8], &[u8])>,
+    ) -> Result<Vec<Vec<u8>>, full_sqlite::StorageAccessError> {
+        let mut output = Vec::new();
+        self.add_node(
+            is_main_trie,
+            root_merkle_value,
+            Vec::new(),
+            start_key,
+            requested_child_trie,
+            &mut output,
+        )?;
+        Ok(output)
+    }
+
+    /// Adds to `output` the node with the given Merkle value, followed with its descendants that
+    /// are part of the proof.
+    ///
+    /// `key_before_partial_key` is the key of the node, in nibbles, without its partial key.
+    fn add_node(
+        &mut self,
+        is_main_trie: bool,
+        merkle_value: &[u8],
+        key_before_partial_key: Vec<u8>,
+        start_key: &[u8],
+        requested_child_trie: Option<(&[u8], &[u8])>,
+        output: &mut Vec<Vec<u8>>,
+    ) -> Result<(), full_sqlite::StorageAccessError> {
+        let full_sqlite::TrieNode {
+            partial_key_nibbles,
+            storage_value,
+            mut children_merkle_values,
+        } = self.database.trie_node(merkle_value)?;
+
+        let partial_key_start = key_before_partial_key.len();
+        let mut key = key_before_partial_key;
+        key.extend_from_slice(&partial_key_nibbles);
+
+        // Nodes that are before the start key are ancestors of the start key, and their storage
+        // value isn't part of the range that is being proven.
+        let in_range = &key[..] >= start_key;
+
+        let storage_value_hash = match &storage_value {
+            Some((value, 1)) if value.len() >= 33 => Some(
+                *<&[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], value).as_bytes())
+                    .unwrap(),
+            ),
+            _ => None,
+        };
+        let omit_storage_value = in_range && storage_value_hash.is_some();
+
+        // The node value can only be built after the children have been traversed. Reserve its
+        // position in the list.
+        let node_index = output.len();
+        output.push(Vec::new());
+        if omit_storage_value {
+            let value = storage_value.as_ref().unwrap().0.clone();
+            self.proof_size += value.len();
+            output.push(value);
+        }
+
+        // If this node is the root of a child trie, the child trie is traversed immediately after
+        // the node, as if it was a descendant of the node. The storage value of the node is the
+        // Merkle value of the root node of the child trie.
+        if let (true, true, Some((value, _))) = (is_main_trie, in_range, &storage_value) {
+            let key_bytes = trie::nibbles_to_bytes_truncate(
+                key.iter().map(|n| trie::Nibble::try_from(*n).unwrap()),
+            )
+            .collect::<Vec<_>>();
+            if key_bytes.starts_with(CHILD_STORAGE_DEFAULT_PREFIX) && value.len() == 32 {
+                let child_start_key = match requested_child_trie {
+                    Some((child_trie, child_start_key)) if child_trie == &key_bytes[..] => {
+                        child_start_key
+                    }
+                    _ => &[][..],
+                };
+                let nodes = self.trie_nodes(value, false, child_start_key, None)?;
+                self.child_tries_nodes.extend(nodes);
+            }
+        }
+
+        for (nibble, child) in children_merkle_values.iter_mut().enumerate() {
+            let Some(child_merkle_value) = child else {
+                continue;
+            };
+
+            // Children whose node value is small enough are inlined within the node value.
+            if child_merkle_value.len() < 32 {
+                continue;
+            }
+
+            // Skip the children that are entirely before the start key.
+            let child_prefix = key
+                .iter()
+                .copied()
+                .chain(Some(nibble as u8))
+                .collect::<Vec<_>>();
+            if &child_prefix[..] < start_key && !start_key.starts_with(&child_prefix) {
+                continue;
+            }
+
+            if self.proof_size >= PROOF_SIZE_LIMIT {
+                break;
+            }
+
+            // The child is part of the proof, and the reference to it is replaced with an empty
+            // inline reference.
+            let child_merkle_value = mem::take(child_merkle_value);
+            self.add_node(
+                is_main_trie,
+                &child_merkle_value,
+                child_prefix,
+                start_key,
+                requested_child_trie,
+                output,
+            )?;
+        }
+
+        let mut node_value = if omit_storage_value {
+            vec![0x01]
+        } else {
+            Vec::new()
+        };
+        node_value.extend_from_slice(
+            &trie::trie_node::encode_to_vec(trie::trie_node::Decoded {
+                children: children_merkle_values,
+                partial_key: key[partial_key_start..]
+                    .iter()
+                    .map(|n| trie::Nibble::try_from(*n).unwrap()),
+                storage_value: match (&storage_value, &storage_value_hash) {
+                    _ if omit_storage_value => trie::trie_node::StorageValue::Unhashed(&[]),
+                    (_, Some(hash)) => trie::trie_node::StorageValue::Hashed(hash),
+                    (Some((value, _)), None) => trie::trie_node::StorageValue::Unhashed(value),
+                    (None, None) => trie::trie_node::StorageValue::None,
+                },
+            })
+            .unwrap(),
+        );
+        self.proof_size += node_value.len();
+        output[node_index] = node_value;
+
+        Ok(())
+    }
+}
+
+/// Turns the given bytes into a list of nibbles, in the format expected by the database.
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    trie::bytes_to_nibbles(bytes.iter().copied())
+        .map(u8::from)
+        .collect()
+}
+
+/// Returns the SCALE-compact encoding of the given number.
+///
+/// Only numbers strictly inferior to 2^30 are supported, which is more than enough for the
+/// lengths found in a proof.
+fn encode_scale_compact_usize(value: usize) -> Vec<u8> {
+    if value < 1 << 6 {
+        vec![(value as u8) << 2]
+    } else if value < 1 << 14 {
+        ((value as u16) << 2 | 0b01).to_le_bytes().to_vec()
+    } else {
+        assert!(value < 1 << 30);
+        ((value as u32) << 2 | 0b10).to_le_bytes().to_vec()
+    }
+}
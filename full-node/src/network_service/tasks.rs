@@ -22,7 +22,7 @@ use futures_util::StreamExt as _;
 use smol::{
     channel,
     future::FutureExt as _,
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
 };
 use smoldot::{
     libp2p::{
@@ -34,14 +34,95 @@ use smoldot::{
 use std::{
     io,
     net::{IpAddr, SocketAddr},
+    num::NonZeroU64,
     pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 pub(super) trait AsyncReadWrite: AsyncRead + AsyncWrite {}
 impl<T> AsyncReadWrite for T where T: AsyncRead + AsyncWrite {}
 
+/// Number of bytes read from and written to one or more sockets.
+#[derive(Debug, Default)]
+pub(super) struct BandwidthCounters {
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+}
+
+impl BandwidthCounters {
+    fn add(&self, bytes_in: usize, bytes_out: usize) {
+        // Conversions from `usize` to `u64` never fail on the platforms we support.
+        self.bytes_in
+            .fetch_add(u64::try_from(bytes_in).unwrap(), Ordering::Relaxed);
+        self.bytes_out
+            .fetch_add(u64::try_from(bytes_out).unwrap(), Ordering::Relaxed);
+    }
+}
+
+/// State shared between all the connection tasks.
+pub(super) struct SharedBandwidth {
+    /// Total number of bytes read from and written to all the sockets.
+    pub total: BandwidthCounters,
+    /// If `Some`, limits the number of bytes written to all the sockets.
+    pub upload_limiter: Option<RateLimiter>,
+    /// If `Some`, limits the number of bytes read from all the sockets.
+    pub download_limiter: Option<RateLimiter>,
+}
+
+/// Token bucket limiting the number of bytes per second that flow through the sockets.
+///
+/// The number of bytes transferred by a socket can't be known ahead of time. Instead, the bytes
+/// are removed from the bucket after they have been transferred, and the connection tasks pause
+/// while the bucket is in deficit. The limit is therefore enforced on average rather than
+/// precisely.
+pub(super) struct RateLimiter {
+    bytes_per_sec: NonZeroU64,
+    /// Number of bytes that can still be transferred, and when this value has last been
+    /// updated. Can be negative, in which case the bucket is in deficit.
+    state: Mutex<(i64, Instant)>,
+}
+
+impl RateLimiter {
+    pub(super) fn new(bytes_per_sec: NonZeroU64) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            state: Mutex::new((
+                i64::try_from(bytes_per_sec.get()).unwrap_or(i64::MAX),
+                Instant::now(),
+            )),
+        }
+    }
+
+    /// Removes `num_bytes` from the bucket. Returns the moment when the bucket will no longer be
+    /// in deficit, or `None` if it isn't in deficit.
+    fn consume(&self, now: Instant, num_bytes: usize) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        let (available, last_refill) = &mut *state;
+
+        // The bucket can't contain more than one second worth of bytes, in order to limit the
+        // size of the bursts.
+        let capacity = i64::try_from(self.bytes_per_sec.get()).unwrap_or(i64::MAX);
+        let refill = now.saturating_duration_since(*last_refill).as_secs_f64()
+            * self.bytes_per_sec.get() as f64;
+        *available = available
+            .saturating_add(refill as i64)
+            .min(capacity)
+            .saturating_sub(i64::try_from(num_bytes).unwrap_or(i64::MAX));
+        *last_refill = now;
+
+        if *available >= 0 {
+            return None;
+        }
+
+        let deficit = available.unsigned_abs() as f64;
+        Some(now + Duration::from_secs_f64(deficit / self.bytes_per_sec.get() as f64))
+    }
+}
+
 /// Asynchronous task managing a specific connection.
 pub(super) async fn connection_task(
     log_callback: Arc<dyn LogCallback + Send + Sync>,
@@ -54,6 +135,8 @@ pub(super) async fn connection_task(
         service::ConnectionId,
         Option<service::ConnectionToCoordinator>,
     )>,
+    bandwidth: Arc<BandwidthCounters>,
+    shared_bandwidth: Arc<SharedBandwidth>,
 ) {
     // The socket future is wrapped around an object containing a read buffer and a write buffer
     // and allowing easier usage.
@@ -66,43 +149,67 @@ pub(super) async fn connection_task(
     // Channel receivers need to be pinned.
     let mut coordinator_to_connection = pin::pin!(coordinator_to_connection);
 
+    // If `Some`, a rate limit has been exceeded and the socket must not be processed again
+    // before this moment.
+    let mut throttled_until = None::<Instant>;
+
     loop {
         // Because only one message should be sent to the coordinator at a time, and that
         // processing the socket might generate a message, we only process the socket if no
         // message is currently being sent.
         if message_sending.is_none() {
-            if let Ok(mut socket_read_write) = socket.as_mut().read_write_access(Instant::now()) {
-                let read_bytes_before = socket_read_write.read_bytes;
-                let written_bytes_before = socket_read_write.write_bytes_queued;
-                let write_closed = socket_read_write.write_bytes_queueable.is_none();
+            // The socket isn't processed while a rate limit is exceeded. Data then accumulates
+            // in the buffers of the operating system, which eventually slows down the remote.
+            if throttled_until.map_or(true, |when| when <= Instant::now()) {
+                throttled_until = None;
 
-                connection_task.read_write(&mut *socket_read_write);
-
-                if socket_read_write.read_bytes != read_bytes_before
-                    || socket_read_write.write_bytes_queued != written_bytes_before
-                    || (!write_closed && socket_read_write.write_bytes_queueable.is_none())
+                if let Ok(mut socket_read_write) = socket.as_mut().read_write_access(Instant::now())
                 {
-                    log_callback.log(
-                        LogLevel::Trace,
-                        format!(
-                            "connection-activity; address={address}; read={}; written={}; wake_up_after={:?}; write_close={:?}",
-                            socket_read_write.read_bytes - read_bytes_before,
-                            socket_read_write.write_bytes_queued - written_bytes_before,
-                            socket_read_write.wake_up_after.map(|w| w
-                                .checked_duration_since(socket_read_write.now)
-                                .unwrap_or(Duration::new(0, 0))),
-                            socket_read_write.write_bytes_queueable.is_none(),
-                        ),
-                    );
-                }
-            } else {
-                // Error on the socket.
-                if !connection_task.is_reset_called() {
-                    log_callback.log(
-                        LogLevel::Trace,
-                        format!("connection-activity; address={}; reset", address),
-                    );
-                    connection_task.reset();
+                    let read_bytes_before = socket_read_write.read_bytes;
+                    let written_bytes_before = socket_read_write.write_bytes_queued;
+                    let write_closed = socket_read_write.write_bytes_queueable.is_none();
+
+                    connection_task.read_write(&mut *socket_read_write);
+
+                    let read = socket_read_write.read_bytes - read_bytes_before;
+                    let written = socket_read_write.write_bytes_queued - written_bytes_before;
+
+                    if read != 0
+                        || written != 0
+                        || (!write_closed && socket_read_write.write_bytes_queueable.is_none())
+                    {
+                        log_callback.log(
+                            LogLevel::Trace,
+                            format!(
+                                "connection-activity; address={address}; read={read}; written={written}; wake_up_after={:?}; write_close={:?}",
+                                socket_read_write.wake_up_after.map(|w| w
+                                    .checked_duration_since(socket_read_write.now)
+                                    .unwrap_or(Duration::new(0, 0))),
+                                socket_read_write.write_bytes_queueable.is_none(),
+                            ),
+                        );
+                    }
+
+                    bandwidth.add(read, written);
+                    shared_bandwidth.total.add(read, written);
+
+                    let now = socket_read_write.now;
+                    throttled_until = [
+                        (&shared_bandwidth.download_limiter, read),
+                        (&shared_bandwidth.upload_limiter, written),
+                    ]
+                    .into_iter()
+                    .filter_map(|(limiter, num_bytes)| limiter.as_ref()?.consume(now, num_bytes))
+                    .max();
+                } else {
+                    // Error on the socket.
+                    if !connection_task.is_reset_called() {
+                        log_callback.log(
+                            LogLevel::Trace,
+                            format!("connection-activity; address={}; reset", address),
+                        );
+                        connection_task.reset();
+                    }
                 }
             }
 
@@ -136,6 +243,7 @@ pub(super) async fn connection_task(
             CoordinatorDead,
             SocketEvent,
             MessageSent,
+            ThrottleEnded,
         }
 
         let wake_up_reason: WakeUpReason = {
@@ -151,7 +259,7 @@ pub(super) async fn connection_task(
                 // must be called. Because we only call `read_write_access` when `message_sending`
                 // is `None`, we also call `wait_read_write_again` only when `message_sending` is
                 // `None`.
-                let fut = if message_sending.is_none() {
+                let fut = if message_sending.is_none() && throttled_until.is_none() {
                     Some(socket.as_mut().wait_read_write_again(|when| async move {
                         smol::Timer::at(when).await;
                     }))
@@ -183,7 +291,20 @@ pub(super) async fn connection_task(
                 }
             };
 
-            coordinator_message.or(socket_event).or(message_sent).await
+            let throttle_ended = async {
+                if let Some(when) = throttled_until {
+                    smol::Timer::at(when).await;
+                    WakeUpReason::ThrottleEnded
+                } else {
+                    future::pending().await
+                }
+            };
+
+            coordinator_message
+                .or(socket_event)
+                .or(message_sent)
+                .or(throttle_ended)
+                .await
         };
 
         match wake_up_reason {
@@ -193,14 +314,19 @@ pub(super) async fn connection_task(
             WakeUpReason::CoordinatorDead => return,
             WakeUpReason::SocketEvent => {}
             WakeUpReason::MessageSent => {}
+            WakeUpReason::ThrottleEnded => {}
         }
     }
 }
 
 /// Builds a future that connects to the given multiaddress. Returns an error if the multiaddress
 /// protocols aren't supported.
+///
+/// If `socks5_proxy` is `Some`, the TCP connection is established through the SOCKS5 proxy found
+/// at this address. Domain names are then resolved by the proxy rather than locally.
 pub(super) fn multiaddr_to_socket(
     addr: &Multiaddr,
+    socks5_proxy: Option<SocketAddr>,
 ) -> Result<impl Future<Output = Result<impl AsyncReadWrite, io::Error>>, ()> {
     let mut iter = addr.iter().fuse();
     let proto1 = iter.next().ok_or(())?;
@@ -251,9 +377,12 @@ pub(super) fn multiaddr_to_socket(
     };
 
     Ok(async move {
-        let tcp_socket = match addr {
-            either::Left(socket_addr) => smol::net::TcpStream::connect(socket_addr).await,
-            either::Right((dns, port)) => smol::net::TcpStream::connect((&dns[..], port)).await,
+        let tcp_socket = match (addr, socks5_proxy) {
+            (addr, Some(proxy)) => socks5_connect(proxy, addr).await,
+            (either::Left(socket_addr), None) => smol::net::TcpStream::connect(socket_addr).await,
+            (either::Right((dns, port)), None) => {
+                smol::net::TcpStream::connect((&dns[..], port)).await
+            }
         };
 
         if let Ok(tcp_socket) = &tcp_socket {
@@ -282,3 +411,94 @@ pub(super) fn multiaddr_to_socket(
         }
     })
 }
+
+/// Connects to the SOCKS5 proxy found at `proxy` and asks it to establish a TCP connection to
+/// the given target. Only the "no authentication required" method is supported.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc1928>.
+async fn socks5_connect(
+    proxy: SocketAddr,
+    target: either::Either<SocketAddr, (String, u16)>,
+) -> Result<smol::net::TcpStream, io::Error> {
+    let mut socket = smol::net::TcpStream::connect(proxy).await?;
+
+    // Method selection. We only offer the "no authentication required" method.
+    socket.write_all(&[0x05, 0x01, 0x00]).await?;
+    let mut method_reply = [0; 2];
+    socket.read_exact(&mut method_reply).await?;
+    if method_reply != [0x05, 0x00] {
+        return Err(io::Error::other(
+            "SOCKS5 proxy refused the authentication method",
+        ));
+    }
+
+    // CONNECT request.
+    let mut request = vec![0x05, 0x01, 0x00];
+    let port = match target {
+        either::Left(SocketAddr::V4(addr)) => {
+            request.push(0x01);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        either::Left(SocketAddr::V6(addr)) => {
+            request.push(0x04);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        either::Right((domain, port)) => {
+            let Ok(domain_len) = u8::try_from(domain.len()) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "domain name too long for SOCKS5",
+                ));
+            };
+            request.push(0x03);
+            request.push(domain_len);
+            request.extend_from_slice(domain.as_bytes());
+            port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    socket.write_all(&request).await?;
+
+    // Reply. Contains the address bound by the proxy, which we read and ignore.
+    let mut reply_header = [0; 4];
+    socket.read_exact(&mut reply_header).await?;
+    if reply_header[0] != 0x05 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid SOCKS5 proxy reply",
+        ));
+    }
+    match reply_header[1] {
+        0x00 => {}
+        0x02 => return Err(io::ErrorKind::PermissionDenied.into()),
+        0x03 | 0x04 => return Err(io::ErrorKind::NotFound.into()),
+        0x05 => return Err(io::ErrorKind::ConnectionRefused.into()),
+        0x06 => return Err(io::ErrorKind::TimedOut.into()),
+        code => {
+            return Err(io::Error::other(format!(
+                "SOCKS5 proxy failure (reply code {code})"
+            )))
+        }
+    }
+    let bound_addr_len = match reply_header[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0; 1];
+            socket.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid SOCKS5 proxy reply",
+            ))
+        }
+    };
+    let mut bound_addr = vec![0; bound_addr_len + 2];
+    socket.read_exact(&mut bound_addr).await?;
+
+    Ok(socket)
+}
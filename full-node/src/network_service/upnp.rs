@@ -0,0 +1,428 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Client of the UPnP Internet Gateway Device (IGD) protocol.
+//!
+//! The gateway is found by multicasting an SSDP search request on the local network. The gateway
+//! answers with the URL of an XML document describing its services, one of which is the
+//! `WANIPConnection` (or `WANPPPConnection`) service. Port mappings are then requested by sending
+//! SOAP requests over HTTP to this service.
+//!
+//! Contrary to NAT-PMP, UPnP doesn't require knowing the IP address of the gateway beforehand.
+//!
+//! See <https://openconnectivity.org/developer/specifications/upnp-resources/upnp/internet-gateway-device-igd-v-2-0/>.
+
+use super::PortMapping;
+
+use futures_lite::FutureExt as _;
+use smol::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str,
+    time::Duration,
+};
+
+/// Multicast address and port SSDP search requests are sent to.
+const SSDP_MULTICAST_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+
+/// Number of times the SSDP search request is sent before giving up. Each attempt waits for
+/// [`SSDP_ATTEMPT_DURATION`].
+const SSDP_MAX_ATTEMPTS: u32 = 3;
+
+/// Duration during which answers to an SSDP search request are waited for.
+const SSDP_ATTEMPT_DURATION: Duration = Duration::from_secs(2);
+
+/// Maximum duration of an HTTP request to the gateway.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum size of an HTTP response of the gateway.
+const HTTP_MAX_RESPONSE_SIZE: usize = 256 * 1024;
+
+/// UPnP error code returned by gateways that only support mappings without a lifetime.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+
+/// Error potentially returned by [`map_tcp_port`].
+#[derive(Debug, derive_more::Display)]
+pub(super) enum UpnpError {
+    /// Error while sending or receiving packets.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// No gateway has answered the SSDP search request.
+    NoGateway,
+    /// The gateway didn't answer an HTTP request in time.
+    Timeout,
+    /// The gateway has sent back a malformed response.
+    InvalidResponse,
+    /// The gateway doesn't provide any of the services that make it possible to map ports.
+    NoWanConnectionService,
+    /// The gateway has answered an HTTP request with an error status code.
+    #[display(fmt = "HTTP error {_0}")]
+    Http(u16),
+    /// The gateway has refused the request.
+    #[display(fmt = "Request refused with UPnP error code {_0}")]
+    Refused(u16),
+}
+
+/// Discovers the UPnP gateway of the local network and asks it to forward a TCP port of its
+/// public IP address to the given local port.
+///
+/// The mapping must be renewed by calling this function again before its lifetime expires.
+/// Gateways that only support permanent mappings are given a permanent mapping, in which case
+/// the returned lifetime is `lifetime` as well, so that the mapping is regularly verified.
+pub(super) async fn map_tcp_port(
+    internal_port: u16,
+    lifetime: Duration,
+) -> Result<PortMapping, UpnpError> {
+    let location = discover_gateway().await?;
+    let service = find_wan_connection_service(&location).await?;
+
+    // Public address request.
+    let response = soap_request(&service, "GetExternalIPAddress", &[]).await?;
+    let external_ip = xml_element(&response, "NewExternalIPAddress")
+        .and_then(|ip| ip.trim().parse::<Ipv4Addr>().ok())
+        .ok_or(UpnpError::InvalidResponse)?;
+
+    // TCP mapping request. The external port requested is the same as the internal port.
+    let local_ip = local_ip_towards(&service).await?;
+    let lease = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX);
+    match add_port_mapping(&service, local_ip, internal_port, lease).await {
+        Ok(()) => {}
+        Err(UpnpError::Refused(ONLY_PERMANENT_LEASES_SUPPORTED)) => {
+            add_port_mapping(&service, local_ip, internal_port, 0).await?;
+        }
+        Err(err) => return Err(err),
+    }
+
+    Ok(PortMapping {
+        external_ip,
+        external_port: internal_port,
+        lifetime,
+    })
+}
+
+/// `WANIPConnection` or `WANPPPConnection` service of a gateway.
+struct WanConnectionService {
+    /// Host and port of the HTTP server of the gateway.
+    host: String,
+    /// Path of the control URL of the service on [`WanConnectionService::host`].
+    control_path: String,
+    /// Type of the service, for example `urn:schemas-upnp-org:service:WANIPConnection:1`.
+    service_type: String,
+}
+
+/// Multicasts an SSDP search request on the local network and returns the URL of the
+/// description of the first Internet gateway device that answers.
+async fn discover_gateway() -> Result<String, UpnpError> {
+    let socket = smol::net::UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .await
+        .map_err(UpnpError::Io)?;
+
+    let request = "M-SEARCH * HTTP/1.1\r\n\
+        HOST: 239.255.255.250:1900\r\n\
+        ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+        MAN: \"ssdp:discover\"\r\n\
+        MX: 2\r\n\r\n";
+
+    for _ in 0..SSDP_MAX_ATTEMPTS {
+        socket
+            .send_to(request.as_bytes(), SSDP_MULTICAST_ADDR)
+            .await
+            .map_err(UpnpError::Io)?;
+
+        let mut deadline = smol::Timer::after(SSDP_ATTEMPT_DURATION);
+        loop {
+            let mut buffer = [0; 2048];
+            let received = async { Some(socket.recv_from(&mut buffer).await) }
+                .or(async {
+                    (&mut deadline).await;
+                    None
+                })
+                .await;
+
+            match received {
+                Some(Ok((len, _))) => {
+                    // Answers that aren't parsable or don't contain a location are ignored, as
+                    // other devices of the network might answer as well.
+                    let Ok(response) = str::from_utf8(&buffer[..len]) else {
+                        continue;
+                    };
+                    if let Some(location) = http_header(response, "location") {
+                        return Ok(location.to_owned());
+                    }
+                }
+                Some(Err(err)) => return Err(UpnpError::Io(err)),
+                None => break,
+            }
+        }
+    }
+
+    Err(UpnpError::NoGateway)
+}
+
+/// Downloads the description of the gateway found at the given URL and finds its
+/// `WANIPConnection` or `WANPPPConnection` service.
+async fn find_wan_connection_service(location: &str) -> Result<WanConnectionService, UpnpError> {
+    let (host, path) = split_http_url(location).ok_or(UpnpError::InvalidResponse)?;
+    let description = http_request(host, "GET", path, &[], "").await?;
+
+    // The services are nested within the devices of the description. Rather than parsing the
+    // XML document, we look for the `service` elements directly.
+    for service in description.split("<service>").skip(1) {
+        let Some(service_type) = xml_element(service, "serviceType") else {
+            continue;
+        };
+        let service_type = service_type.trim();
+        if !service_type.starts_with("urn:schemas-upnp-org:service:WANIPConnection:")
+            && !service_type.starts_with("urn:schemas-upnp-org:service:WANPPPConnection:")
+        {
+            continue;
+        }
+
+        let Some(control_url) = xml_element(service, "controlURL") else {
+            continue;
+        };
+        let control_url = control_url.trim();
+
+        let (host, control_path) = if let Some((host, path)) = split_http_url(control_url) {
+            (host.to_owned(), path.to_owned())
+        } else if control_url.starts_with('/') {
+            (host.to_owned(), control_url.to_owned())
+        } else {
+            // Relative to the directory of the description.
+            let directory = &path[..path.rfind('/').map_or(0, |n| n + 1)];
+            (host.to_owned(), format!("{directory}{control_url}"))
+        };
+
+        return Ok(WanConnectionService {
+            host,
+            control_path,
+            service_type: service_type.to_owned(),
+        });
+    }
+
+    Err(UpnpError::NoWanConnectionService)
+}
+
+/// Returns the local IP address used to reach the HTTP server of the given service.
+async fn local_ip_towards(service: &WanConnectionService) -> Result<Ipv4Addr, UpnpError> {
+    let socket = smol::net::UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .await
+        .map_err(UpnpError::Io)?;
+    // Connecting a UDP socket doesn't send any packet, but makes the operating system choose the
+    // local address.
+    socket
+        .connect(service.host.as_str())
+        .await
+        .map_err(UpnpError::Io)?;
+    match socket.local_addr().map_err(UpnpError::Io)?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(UpnpError::InvalidResponse),
+    }
+}
+
+/// Asks the given service to forward the TCP port of the same number of the public IP address of
+/// the gateway to the given local IP address and port. A `lease` of `0` means that the mapping
+/// never expires.
+async fn add_port_mapping(
+    service: &WanConnectionService,
+    local_ip: Ipv4Addr,
+    port: u16,
+    lease: u32,
+) -> Result<(), UpnpError> {
+    let port = port.to_string();
+    soap_request(
+        service,
+        "AddPortMapping",
+        &[
+            ("NewRemoteHost", ""),
+            ("NewExternalPort", &port),
+            ("NewProtocol", "TCP"),
+            ("NewInternalPort", &port),
+            ("NewInternalClient", &local_ip.to_string()),
+            ("NewEnabled", "1"),
+            ("NewPortMappingDescription", "smoldot"),
+            ("NewLeaseDuration", &lease.to_string()),
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sends a SOAP request to the given service and returns the body of the response.
+async fn soap_request(
+    service: &WanConnectionService,
+    action: &str,
+    arguments: &[(&str, &str)],
+) -> Result<String, UpnpError> {
+    let mut body = format!(
+        "<?xml version=\"1.0\"?>\r\n\
+        <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+        s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+        <s:Body><u:{action} xmlns:u=\"{}\">",
+        service.service_type
+    );
+    for (name, value) in arguments {
+        body.push_str(&format!("<{name}>{value}</{name}>"));
+    }
+    body.push_str(&format!("</u:{action}></s:Body></s:Envelope>\r\n"));
+
+    let soap_action = format!("\"{}#{action}\"", service.service_type);
+    http_request(
+        &service.host,
+        "POST",
+        &service.control_path,
+        &[
+            ("Content-Type", "text/xml; charset=\"utf-8\""),
+            ("SOAPAction", &soap_action),
+        ],
+        &body,
+    )
+    .await
+}
+
+/// Sends an HTTP/1.1 request to the given host and returns the body of the response.
+///
+/// Returns [`UpnpError::Refused`] with the UPnP error code if the gateway answers with a SOAP
+/// fault, and [`UpnpError::Http`] if it answers with another error status code.
+async fn http_request(
+    host: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<String, UpnpError> {
+    let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+
+    let exchange = async {
+        let mut socket = smol::net::TcpStream::connect(host)
+            .await
+            .map_err(UpnpError::Io)?;
+        socket
+            .write_all(request.as_bytes())
+            .await
+            .map_err(UpnpError::Io)?;
+
+        let mut response = Vec::new();
+        (&mut socket)
+            .take(u64::try_from(HTTP_MAX_RESPONSE_SIZE).unwrap())
+            .read_to_end(&mut response)
+            .await
+            .map_err(UpnpError::Io)?;
+        Ok(response)
+    };
+
+    let response = exchange
+        .or(async {
+            smol::Timer::after(HTTP_TIMEOUT).await;
+            Err(UpnpError::Timeout)
+        })
+        .await?;
+
+    let response = String::from_utf8(response).map_err(|_| UpnpError::InvalidResponse)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or(UpnpError::InvalidResponse)?;
+
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or(UpnpError::InvalidResponse)?;
+
+    let body = if http_header(head, "transfer-encoding")
+        .map_or(false, |encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        decode_chunked(body).ok_or(UpnpError::InvalidResponse)?
+    } else {
+        body.to_owned()
+    };
+
+    match status {
+        200..=299 => Ok(body),
+        // Errors of SOAP requests are reported as a SOAP fault containing the UPnP error code.
+        500 => match xml_element(&body, "errorCode").and_then(|code| code.trim().parse().ok()) {
+            Some(code) => Err(UpnpError::Refused(code)),
+            None => Err(UpnpError::Http(status)),
+        },
+        _ => Err(UpnpError::Http(status)),
+    }
+}
+
+/// Decodes an HTTP body encoded with the `chunked` transfer encoding.
+fn decode_chunked(mut body: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(body.len());
+    loop {
+        let (size, rest) = body.split_once("\r\n")?;
+        // Chunk extensions, after a `;`, are ignored.
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.push_str(rest.get(..size)?);
+        body = rest.get(size..)?.strip_prefix("\r\n")?;
+    }
+}
+
+/// Splits an `http://` URL into its host (including the port) and its path.
+fn split_http_url(url: &str) -> Option<(&str, &str)> {
+    let url = url.strip_prefix("http://")?;
+    match url.find('/') {
+        Some(pos) => Some(url.split_at(pos)),
+        None => Some((url, "/")),
+    }
+}
+
+/// Returns the value of the given header, whose name is case-insensitive, in the head of an HTTP
+/// request or response.
+fn http_header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (header_name, value) = line.split_once(':')?;
+        if header_name.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the content of the first element with the given name in an XML document. The
+/// element can be prefixed with a namespace.
+fn xml_element<'a>(document: &'a str, name: &str) -> Option<&'a str> {
+    let mut search_from = 0;
+    loop {
+        let start = search_from + document[search_from..].find('<')? + 1;
+        let tag_end = start + document[start..].find('>')?;
+        let tag = &document[start..tag_end];
+        search_from = tag_end;
+
+        let tag_name = tag.split_whitespace().next()?;
+        let local_name = tag_name.rsplit(':').next()?;
+        if local_name != name {
+            continue;
+        }
+
+        let content = &document[tag_end + 1..];
+        let end = content.find("</")?;
+        return Some(&content[..end]);
+    }
+}
@@ -0,0 +1,337 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! HTTP requests performed by the runtime through the offchain HTTP host functions.
+//!
+//! A [`HttpRequests`] holds the requests of a single runtime call. A request is started, then
+//! its headers are added and its body written, after which it is sent. The body is buffered and
+//! the request is only sent once the runtime indicates that the body is complete or starts
+//! waiting for the response. The response, including its body, is then entirely downloaded in
+//! the background before being handed to the runtime.
+//!
+//! The requests still in progress are cancelled when the [`HttpRequests`] is destroyed.
+
+use smol::future::FutureExt as _;
+use smoldot::executor::runtime_call::{HttpError, HttpRequestStatus};
+use std::{
+    io::Read as _,
+    str,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Maximum number of requests that can exist at the same time.
+const MAX_REQUESTS: usize = 64;
+
+/// Maximum size, in bytes, of the body of a request.
+const MAX_REQUEST_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum size, in bytes, of the body of a response. Responses whose body is larger are
+/// considered as failed.
+const MAX_RESPONSE_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Timeout of each request, from the moment it is sent until its response has been entirely
+/// downloaded.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Collection of HTTP requests started by a runtime call.
+pub struct HttpRequests {
+    /// Agent used to send the requests.
+    agent: ureq::Agent,
+
+    /// List of requests, indexed by their identifier as known by the runtime.
+    requests: hashbrown::HashMap<u16, Request, fnv::FnvBuildHasher>,
+
+    /// Identifier to try to assign to the next request.
+    next_request_id: u16,
+}
+
+enum Request {
+    /// Request has been started but not sent yet.
+    Building {
+        method: String,
+        uri: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    /// Request has been sent, and the response is being downloaded.
+    Sent(smol::Task<Result<Response, ()>>),
+    /// Response has been entirely downloaded.
+    Response(Response),
+}
+
+struct Response {
+    status_code: u16,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+    body: Vec<u8>,
+    /// Number of bytes of `body` that have already been read by the runtime.
+    body_read_offset: usize,
+}
+
+impl HttpRequests {
+    /// Initializes a new empty collection.
+    pub fn new() -> Self {
+        HttpRequests {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            requests: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
+            next_request_id: 0,
+        }
+    }
+
+    /// Starts a new request. Returns `None` if the method or URI aren't valid UTF-8, or if too
+    /// many requests exist at the same time.
+    pub fn start(&mut self, method: &[u8], uri: &[u8]) -> Option<u16> {
+        if self.requests.len() >= MAX_REQUESTS {
+            return None;
+        }
+
+        let method = str::from_utf8(method).ok()?.to_owned();
+        let uri = str::from_utf8(uri).ok()?.to_owned();
+
+        let request_id = loop {
+            let id = self.next_request_id;
+            self.next_request_id = self.next_request_id.wrapping_add(1);
+            if !self.requests.contains_key(&id) {
+                break id;
+            }
+        };
+
+        self.requests.insert(
+            request_id,
+            Request::Building {
+                method,
+                uri,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+        );
+
+        Some(request_id)
+    }
+
+    /// Adds a header to a request that hasn't been sent yet. Returns `false` if the request
+    /// identifier is invalid, if the request has already been sent, or if the name or value
+    /// aren't valid UTF-8.
+    pub fn add_header(&mut self, request_id: u16, name: &[u8], value: &[u8]) -> bool {
+        let Some(Request::Building { headers, .. }) = self.requests.get_mut(&request_id) else {
+            return false;
+        };
+
+        let (Ok(name), Ok(value)) = (str::from_utf8(name), str::from_utf8(value)) else {
+            return false;
+        };
+
+        headers.push((name.to_owned(), value.to_owned()));
+        true
+    }
+
+    /// Writes a chunk of the body of a request that hasn't been sent yet. An empty chunk
+    /// indicates that the body is complete, in which case the request is sent.
+    pub fn write_body(&mut self, request_id: u16, chunk: &[u8]) -> Result<(), HttpError> {
+        let Some(Request::Building { body, .. }) = self.requests.get_mut(&request_id) else {
+            return Err(HttpError::Invalid);
+        };
+
+        if chunk.is_empty() {
+            self.send(request_id);
+            return Ok(());
+        }
+
+        if body.len().saturating_add(chunk.len()) > MAX_REQUEST_BODY_SIZE {
+            self.requests.remove(&request_id);
+            return Err(HttpError::IoError);
+        }
+
+        body.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// Waits until the responses to the given requests have been received or until the
+    /// deadline, in milliseconds since the UNIX epoch, is reached. Requests that haven't been
+    /// sent yet are sent.
+    ///
+    /// Returns the status of each request, in the same order as `request_ids`.
+    pub async fn wait(
+        &mut self,
+        request_ids: &[u16],
+        deadline: Option<u64>,
+    ) -> Vec<HttpRequestStatus> {
+        let deadline = deadline_instant(deadline);
+        let mut statuses = Vec::with_capacity(request_ids.len());
+        for request_id in request_ids {
+            statuses.push(self.wait_response(*request_id, deadline).await);
+        }
+        statuses
+    }
+
+    /// Returns the headers of the response to the given request. Empty if the request
+    /// identifier is invalid or if the response hasn't been received yet.
+    pub fn response_headers(&self, request_id: u16) -> &[(Vec<u8>, Vec<u8>)] {
+        match self.requests.get(&request_id) {
+            Some(Request::Response(response)) => &response.headers,
+            _ => &[],
+        }
+    }
+
+    /// Reads at most `max_size` bytes of the body of the response to the given request,
+    /// waiting for the response until the deadline, in milliseconds since the UNIX epoch, if it
+    /// hasn't been received yet.
+    ///
+    /// Returns an empty chunk once the entire body has been read, after which the request is
+    /// destroyed.
+    pub async fn read_body(
+        &mut self,
+        request_id: u16,
+        max_size: usize,
+        deadline: Option<u64>,
+    ) -> Result<Vec<u8>, HttpError> {
+        match self
+            .wait_response(request_id, deadline_instant(deadline))
+            .await
+        {
+            HttpRequestStatus::Finished(_) => {}
+            HttpRequestStatus::DeadlineReached => return Err(HttpError::DeadlineReached),
+            HttpRequestStatus::IoError => return Err(HttpError::IoError),
+            HttpRequestStatus::Invalid => return Err(HttpError::Invalid),
+        }
+
+        let Some(Request::Response(response)) = self.requests.get_mut(&request_id) else {
+            unreachable!()
+        };
+
+        let remaining = &response.body[response.body_read_offset..];
+        let chunk = remaining[..remaining.len().min(max_size)].to_vec();
+        response.body_read_offset += chunk.len();
+
+        if chunk.is_empty() {
+            self.requests.remove(&request_id);
+        }
+
+        Ok(chunk)
+    }
+
+    /// Sends the given request, which must be in the [`Request::Building`] state.
+    fn send(&mut self, request_id: u16) {
+        let Some(Request::Building {
+            method,
+            uri,
+            headers,
+            body,
+        }) = self.requests.remove(&request_id)
+        else {
+            unreachable!()
+        };
+
+        let agent = self.agent.clone();
+        let task = smol::unblock(move || {
+            let mut request = agent.request(&method, &uri);
+            for (name, value) in &headers {
+                request = request.set(name, value);
+            }
+
+            // Responses with an error status code are reported to the runtime like any other.
+            let response = match request.send_bytes(&body) {
+                Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+                Err(ureq::Error::Transport(_)) => return Err(()),
+            };
+
+            let status_code = response.status();
+            let mut headers = Vec::new();
+            for name in response.headers_names() {
+                for value in response.all(&name) {
+                    headers.push((name.as_bytes().to_vec(), value.as_bytes().to_vec()));
+                }
+            }
+
+            let mut body = Vec::new();
+            response
+                .into_reader()
+                .take(MAX_RESPONSE_BODY_SIZE + 1)
+                .read_to_end(&mut body)
+                .map_err(|_| ())?;
+            if u64::try_from(body.len()).unwrap_or(u64::MAX) > MAX_RESPONSE_BODY_SIZE {
+                return Err(());
+            }
+
+            Ok(Response {
+                status_code,
+                headers,
+                body,
+                body_read_offset: 0,
+            })
+        });
+
+        self.requests.insert(request_id, Request::Sent(task));
+    }
+
+    /// Waits until the response to the given request has been received or until the deadline
+    /// is reached. Sends the request if it hasn't been sent yet.
+    async fn wait_response(
+        &mut self,
+        request_id: u16,
+        deadline: Option<Instant>,
+    ) -> HttpRequestStatus {
+        if matches!(
+            self.requests.get(&request_id),
+            Some(Request::Building { .. })
+        ) {
+            self.send(request_id);
+        }
+
+        let task = match self.requests.get_mut(&request_id) {
+            None => return HttpRequestStatus::Invalid,
+            Some(Request::Response(response)) => {
+                return HttpRequestStatus::Finished(response.status_code)
+            }
+            Some(Request::Sent(task)) => task,
+            Some(Request::Building { .. }) => unreachable!(),
+        };
+
+        let outcome = async { Some(task.await) }
+            .or(async {
+                match deadline {
+                    Some(deadline) => smol::Timer::at(deadline).await,
+                    None => smol::Timer::never().await,
+                };
+                None
+            })
+            .await;
+
+        match outcome {
+            None => HttpRequestStatus::DeadlineReached,
+            Some(Ok(response)) => {
+                let status_code = response.status_code;
+                self.requests
+                    .insert(request_id, Request::Response(response));
+                HttpRequestStatus::Finished(status_code)
+            }
+            Some(Err(())) => {
+                // The request is considered destroyed after an error.
+                self.requests.remove(&request_id);
+                HttpRequestStatus::IoError
+            }
+        }
+    }
+}
+
+/// Converts a deadline in milliseconds since the UNIX epoch into an [`Instant`].
+fn deadline_instant(deadline: Option<u64>) -> Option<Instant> {
+    let deadline = Duration::from_millis(deadline?);
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::new(0, 0));
+    Some(Instant::now() + deadline.saturating_sub(now))
+}
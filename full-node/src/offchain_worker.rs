@@ -0,0 +1,365 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Execution of the offchain workers of the runtime.
+//!
+//! Every time a new best block is imported, the `OffchainWorkerApi_offchain_worker` runtime
+//! function is called against the storage of this block. Contrary to the other runtime calls,
+//! the runtime is allowed to use the offchain host functions: the offchain local storage is
+//! persisted in the database, and the transactions that the runtime submits are validated
+//! against the block then announced to the peers of the node.
+//!
+//! The offchain workers are executed one at a time, in the same order as the blocks are
+//! imported. If the execution is too slow compared to the import of the blocks, the
+//! subscription to the consensus service gets interrupted and the blocks that have been imported
+//! in the meanwhile are skipped.
+//!
+//! The HTTP requests that an offchain worker performs are sent directly from the node. They are
+//! all cancelled once the offchain worker has finished executing. See the [`offchain_http`]
+//! module.
+
+use crate::{
+    consensus_service, database_thread, network_service, offchain_http, runtime_calls_limiter,
+    transactions_pool, LogCallback, LogLevel,
+};
+
+use hashbrown::HashMap;
+use rand::SeedableRng as _;
+use smol::stream::StreamExt as _;
+use smoldot::{executor, header, informant::HashDisplay, transactions::validate};
+use std::{iter, num::NonZeroUsize, sync::Arc};
+
+/// Name of the runtime function of the offchain workers.
+const OFFCHAIN_WORKER_FUNCTION_NAME: &str = "OffchainWorkerApi_offchain_worker";
+
+/// Configuration for [`run`].
+pub struct Config {
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Consensus service of the chain. Used to track the new best blocks.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Database of the chain. Used to access the storage of the blocks and the offchain storage.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Service used to announce the transactions submitted by the offchain workers.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Pool where the transactions submitted by the offchain workers are inserted.
+    pub transactions_pool: Arc<transactions_pool::TransactionsPool>,
+
+    /// Limiter of the runtime executions. The offchain workers aren't consensus-critical and
+    /// are thus treated like JSON-RPC runtime calls.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+
+    /// Seed used to initialize the source of the random seeds that the offchain workers
+    /// request.
+    pub randomness_seed: [u8; 32],
+}
+
+/// Runs the offchain workers of the chain. Never returns.
+pub async fn run(config: Config) {
+    let mut randomness = rand::rngs::StdRng::from_seed(config.randomness_seed);
+
+    loop {
+        let subscribe_all = config
+            .consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Runtime of each block of the subscription, including the finalized block.
+        let mut runtimes = HashMap::<_, _, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+            subscribe_all.non_finalized_blocks_ancestry_order.len() + 1,
+            Default::default(),
+        );
+        let mut finalized_block_hash = subscribe_all.finalized_block_hash;
+        runtimes.insert(
+            subscribe_all.finalized_block_hash,
+            subscribe_all.finalized_block_runtime,
+        );
+        config
+            .consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+
+        // The offchain workers aren't executed for the blocks that were already known, as they
+        // have most likely already been executed before.
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let runtime = match block.runtime_update {
+                Some(runtime) => runtime,
+                None => runtimes.get(&block.parent_hash).unwrap().clone(),
+            };
+            runtimes.insert(block.block_hash, runtime);
+            config
+                .consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        while let Some(notification) = new_blocks.next().await {
+            match notification {
+                consensus_service::Notification::Block { block, .. } => {
+                    let runtime = match block.runtime_update {
+                        Some(runtime) => runtime,
+                        None => runtimes.get(&block.parent_hash).unwrap().clone(),
+                    };
+                    runtimes.insert(block.block_hash, runtime.clone());
+
+                    // The block is unpinned only after the offchain worker has finished, in
+                    // order to guarantee that its storage is still in the database.
+                    if block.is_new_best {
+                        run_offchain_worker(
+                            &config,
+                            &mut randomness,
+                            &block.block_hash,
+                            &block.scale_encoded_header,
+                            &runtime,
+                        )
+                        .await;
+                    }
+
+                    config
+                        .consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks_hashes,
+                    ..
+                } => {
+                    runtimes.remove(&finalized_block_hash);
+                    for hash in finalized_blocks_newest_to_oldest.iter().skip(1) {
+                        runtimes.remove(hash);
+                    }
+                    for hash in &pruned_blocks_hashes {
+                        runtimes.remove(hash);
+                    }
+                    finalized_block_hash = finalized_blocks_newest_to_oldest[0];
+                }
+            }
+        }
+
+        // The consensus service has killed the subscription, most likely because the offchain
+        // workers are too slow. Subscribe again.
+        config
+            .log_callback
+            .log(LogLevel::Debug, "offchain-worker-resubscribe".to_string());
+    }
+}
+
+/// Executes the offchain worker of the given block, then validates and announces the
+/// transactions that it has submitted.
+async fn run_offchain_worker(
+    config: &Config,
+    randomness: &mut rand::rngs::StdRng,
+    block_hash: &[u8; 32],
+    scale_encoded_header: &[u8],
+    runtime: &executor::host::HostVmPrototype,
+) {
+    let block_number = match header::decode(scale_encoded_header, config.block_number_bytes) {
+        Ok(header) => header.number,
+        Err(_) => return,
+    };
+
+    // Version 1 of the runtime API takes the number of the block as parameter, while version 2
+    // takes the header of the block.
+    let parameter = match runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version("OffchainWorkerApi")
+    {
+        Some(1) => block_number.to_le_bytes()[..config.block_number_bytes].to_vec(),
+        Some(2) => scale_encoded_header.to_vec(),
+        // The runtime doesn't have any offchain worker.
+        _ => return,
+    };
+
+    config.log_callback.log(
+        LogLevel::Debug,
+        format!(
+            "offchain-worker-start; block={}; number={}",
+            HashDisplay(block_hash),
+            block_number
+        ),
+    );
+
+    let mut submitted_transactions = Vec::new();
+    let result = {
+        let _permit = config.runtime_calls_limiter.json_rpc_permit().await;
+        let mut http_requests = offchain_http::HttpRequests::new();
+        consensus_service::offchain_runtime_call(
+            &config.database,
+            block_hash,
+            runtime.clone(),
+            OFFCHAIN_WORKER_FUNCTION_NAME,
+            &parameter,
+            consensus_service::OffchainEnvironment {
+                submitted_transactions: &mut submitted_transactions,
+                http_requests: Some(&mut http_requests),
+                randomness,
+            },
+        )
+        .await
+    };
+
+    if let Err(error) = result {
+        config.log_callback.log(
+            LogLevel::Warn,
+            format!(
+                "offchain-worker-error; block={}; error={}",
+                HashDisplay(block_hash),
+                error
+            ),
+        );
+    }
+
+    // Transactions submitted before an error are nonetheless inserted in the pool and announced,
+    // as the runtime has been told that they have been successfully submitted.
+    for transaction in submitted_transactions {
+        match validate_transaction(
+            &config.database,
+            &config.runtime_calls_limiter,
+            block_hash,
+            runtime,
+            &transaction,
+            validate::TransactionSource::Local,
+        )
+        .await
+        {
+            Ok(validity) => {
+                let propagate = validity.propagate;
+                if let Err(error) = config.transactions_pool.insert(
+                    transaction.clone(),
+                    validity,
+                    validate::TransactionSource::Local,
+                ) {
+                    config.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "offchain-worker-transaction-not-pooled; block={}; error={}",
+                            HashDisplay(block_hash),
+                            error
+                        ),
+                    );
+                }
+
+                if propagate {
+                    config
+                        .network_service
+                        .0
+                        .announce_transaction(config.network_service.1, transaction)
+                        .await;
+                }
+            }
+            Err(error) => {
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "offchain-worker-transaction-invalid; block={}; error={}",
+                        HashDisplay(block_hash),
+                        error
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Validates a transaction against the given block, whose runtime must be passed as parameter.
+///
+/// This is used for the transactions submitted by the runtime, for example by an offchain
+/// worker, in which case `source` is [`validate::TransactionSource::Local`], and in order to
+/// validate again the transactions of the pool.
+pub async fn validate_transaction(
+    database: &database_thread::DatabaseThread,
+    runtime_calls_limiter: &Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+    block_hash: &[u8; 32],
+    runtime: &executor::host::HostVmPrototype,
+    scale_encoded_transaction: &[u8],
+    source: validate::TransactionSource,
+) -> Result<validate::ValidTransaction, ValidateError> {
+    let parameters = match runtime
+        .runtime_version()
+        .decode()
+        .apis
+        .find_version("TaggedTransactionQueue")
+    {
+        Some(2) => validate::validate_transaction_runtime_parameters_v2(
+            iter::once(scale_encoded_transaction),
+            source,
+        )
+        .fold(Vec::new(), |mut params, chunk| {
+            params.extend_from_slice(chunk.as_ref());
+            params
+        }),
+        Some(3) => validate::validate_transaction_runtime_parameters_v3(
+            iter::once(scale_encoded_transaction),
+            source,
+            block_hash,
+        )
+        .fold(Vec::new(), |mut params, chunk| {
+            params.extend_from_slice(chunk.as_ref());
+            params
+        }),
+        _ => return Err(ValidateError::UnsupportedRuntime),
+    };
+
+    let _permit = runtime_calls_limiter.json_rpc_permit().await;
+    let output = consensus_service::runtime_call(
+        database,
+        block_hash,
+        runtime.clone(),
+        validate::VALIDATION_FUNCTION_NAME,
+        &parameters,
+        executor::runtime_call::StorageProofSizeBehavior::Unimplemented,
+        executor::runtime_call::StorageChanges::empty(),
+        false,
+    )
+    .await
+    .map_err(ValidateError::RuntimeCall)?
+    .output;
+
+    match validate::decode_validate_transaction_return_value(&output) {
+        Ok(Ok(valid)) => Ok(valid),
+        Ok(Err(error)) => Err(ValidateError::Invalid(error)),
+        Err(_) => Err(ValidateError::OutputDecode),
+    }
+}
+
+/// Error returned by [`validate_transaction`].
+#[derive(Debug, derive_more::Display)]
+pub enum ValidateError {
+    /// The runtime doesn't support validating transactions.
+    UnsupportedRuntime,
+    /// Error while executing the validation function.
+    #[display(fmt = "{_0}")]
+    RuntimeCall(consensus_service::RuntimeCallError),
+    /// Failed to decode the output of the validation function.
+    OutputDecode,
+    /// The runtime has reported the transaction as invalid.
+    #[display(fmt = "{_0}")]
+    Invalid(validate::TransactionValidityError),
+}
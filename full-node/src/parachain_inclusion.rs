@@ -0,0 +1,190 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of the inclusion of parachain candidates in the relay chain.
+//!
+//! Whenever the relay chain includes a candidate of a parachain, the `Paras` pallet of the relay
+//! chain runtime updates the head of this parachain stored in `Paras::Heads`. This module watches
+//! the storage changes of the relay chain blocks that the node verifies in order to detect these
+//! updates.
+//!
+//! The information is only used for display purposes. Since only the storage changes of new
+//! blocks are available, nothing is known until a candidate has been included in a relay chain
+//! block verified after the node has started.
+
+use hashbrown::HashMap;
+use smol::{lock::Mutex, stream::StreamExt as _};
+use smoldot::header;
+use std::{num::NonZeroUsize, sync::Arc};
+
+use crate::consensus_service;
+
+/// `twox128("Paras") ++ twox128("Heads")`. Keys of `Paras::Heads` consist in this prefix
+/// followed with `twox64(para_id) ++ para_id`.
+const PARAS_HEADS_PREFIX: [u8; 32] = [
+    0xcd, 0x71, 0x0b, 0x30, 0xbd, 0x2e, 0xab, 0x03, 0x52, 0xdd, 0xcc, 0x26, 0x41, 0x7a, 0xa1, 0x94,
+    0x1b, 0x3c, 0x25, 0x2f, 0xcb, 0x29, 0xd8, 0x8e, 0xff, 0x4f, 0x3d, 0xe5, 0xde, 0x44, 0x76, 0xc3,
+];
+
+/// Most recent inclusion of a candidate of the parachain in the best chain of the relay chain.
+#[derive(Debug, Clone)]
+pub struct ParachainInclusion {
+    /// Number of the relay chain block where the candidate has been included.
+    pub relay_block_number: u64,
+    /// Hash of the relay chain block where the candidate has been included.
+    pub relay_block_hash: [u8; 32],
+    /// Number of the parachain block that has been included.
+    pub para_block_number: u64,
+}
+
+/// Runs the task that tracks the inclusion of the candidates of the given parachain. Never
+/// returns.
+///
+/// `output` is updated whenever the most recent inclusion in the best chain of the relay chain
+/// changes.
+pub async fn run(
+    relay_chain_consensus_service: Arc<consensus_service::ConsensusService>,
+    para_id: u32,
+    para_block_number_bytes: usize,
+    output: Arc<Mutex<Option<ParachainInclusion>>>,
+) {
+    loop {
+        let subscribe_all = relay_chain_consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Most recent inclusion in the chain of each non-finalized block and of the current
+        // finalized block, or `None` if unknown.
+        let mut blocks: HashMap<[u8; 32], Option<ParachainInclusion>, fnv::FnvBuildHasher> =
+            HashMap::with_capacity_and_hasher(
+                subscribe_all.non_finalized_blocks_ancestry_order.len() + 1,
+                Default::default(),
+            );
+        let mut finalized_block_hash = subscribe_all.finalized_block_hash;
+
+        blocks.insert(finalized_block_hash, None);
+        relay_chain_consensus_service
+            .unpin_block(subscribe_all.id, finalized_block_hash)
+            .await;
+
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let inclusion = blocks.get(&block.parent_hash).cloned().flatten();
+            if block.is_new_best {
+                *output.lock().await = inclusion.clone();
+            }
+            blocks.insert(block.block_hash, inclusion);
+            relay_chain_consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        while let Some(notification) = new_blocks.next().await {
+            match notification {
+                consensus_service::Notification::Block {
+                    block,
+                    storage_changes,
+                } => {
+                    // Look for a modification of the head of the parachain in the storage
+                    // changes of the block.
+                    let new_head = storage_changes
+                        .main_trie_storage_changes_iter_unordered()
+                        .find_map(|(key, value)| {
+                            if key.len() == PARAS_HEADS_PREFIX.len() + 8 + 4
+                                && key.starts_with(&PARAS_HEADS_PREFIX)
+                                && key.ends_with(&para_id.to_le_bytes())
+                            {
+                                value
+                            } else {
+                                None
+                            }
+                        })
+                        .and_then(|head| decode_head_data_number(head, para_block_number_bytes));
+
+                    let inclusion = match (
+                        new_head,
+                        header::decode(
+                            &block.scale_encoded_header,
+                            relay_chain_consensus_service.block_number_bytes(),
+                        ),
+                    ) {
+                        (Some(para_block_number), Ok(relay_header)) => Some(ParachainInclusion {
+                            relay_block_number: relay_header.number,
+                            relay_block_hash: block.block_hash,
+                            para_block_number,
+                        }),
+                        _ => blocks.get(&block.parent_hash).cloned().flatten(),
+                    };
+
+                    if block.is_new_best {
+                        *output.lock().await = inclusion.clone();
+                    }
+                    blocks.insert(block.block_hash, inclusion);
+                    relay_chain_consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    best_block_hash,
+                    pruned_blocks_hashes,
+                } => {
+                    blocks.remove(&finalized_block_hash);
+                    for hash in finalized_blocks_newest_to_oldest
+                        .iter()
+                        .skip(1)
+                        .chain(pruned_blocks_hashes.iter())
+                    {
+                        blocks.remove(hash);
+                    }
+                    finalized_block_hash = finalized_blocks_newest_to_oldest[0];
+
+                    *output.lock().await = blocks.get(&best_block_hash).cloned().flatten();
+                }
+            }
+        }
+
+        // The consensus service has killed the subscription. Subscribe again.
+    }
+}
+
+/// Decodes the value of a `Paras::Heads` entry, and returns the number of the parachain block
+/// it contains.
+///
+/// The value is a SCALE-encoded `Vec<u8>` containing the SCALE-encoded header of the block.
+fn decode_head_data_number(value: &[u8], block_number_bytes: usize) -> Option<u64> {
+    // Decode the SCALE-compact length prefix. Only the modes that can realistically be used for
+    // a block header are supported.
+    let (len, prefix_len) = match value.first()? & 0b11 {
+        0b00 => (usize::from(value[0] >> 2), 1),
+        0b01 => (
+            usize::from(u16::from_le_bytes(<[u8; 2]>::try_from(value.get(..2)?).unwrap()) >> 2),
+            2,
+        ),
+        0b10 => (
+            usize::try_from(u32::from_le_bytes(<[u8; 4]>::try_from(value.get(..4)?).unwrap()) >> 2)
+                .ok()?,
+            4,
+        ),
+        _ => return None,
+    };
+
+    let head_data = value.get(prefix_len..)?.get(..len)?;
+    header::decode_partial(head_data, block_number_bytes)
+        .ok()
+        .map(|(header, _)| header.number)
+}
@@ -0,0 +1,114 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background pruning of the database.
+//!
+//! Periodically removes from the database the blocks that aren't descendants of the finalized
+//! block and, depending on the configuration, the storage and possibly the body of the finalized
+//! blocks that are older than a certain threshold. See [`crate::Pruning`].
+//!
+//! The trie nodes are deduplicated between the storage of all the blocks. When the storage of a
+//! block is pruned, only the trie nodes that are no longer referenced by the storage of any other
+//! block are removed from the database.
+//!
+//! Blocks whose state is pinned (see [`database_thread::DatabaseThread::pin_state`]) are never
+//! pruned.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{database_thread, LogCallback, LogLevel};
+
+/// Delay between two pruning passes.
+const PRUNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of finalized blocks pruned at once. The database is locked while blocks are
+/// pruned, and pruning is done in small batches in order to not block other database accesses
+/// for too long.
+const BLOCKS_PER_BATCH: usize = 64;
+
+/// Runs the pruning task. Never returns.
+///
+/// If `keep_last` is `None`, only the blocks that aren't descendants of the finalized block are
+/// pruned. If `keep_last` is `Some`, the storage and body of the finalized blocks whose height is
+/// inferior to the height of the finalized block minus `keep_last` are additionally pruned. The
+/// body of these blocks is pruned only if `prune_bodies` is `true`.
+pub async fn run(
+    database: Arc<database_thread::DatabaseThread>,
+    keep_last: Option<u64>,
+    prune_bodies: bool,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    loop {
+        smol::Timer::after(PRUNING_INTERVAL).await;
+
+        let state_pins = database.state_pins();
+        let result = database
+            .with_database(move |database| {
+                database.purge_finality_orphans_except(|hash| state_pins.is_pinned(hash))
+            })
+            .await;
+        if let Err(err) = result {
+            log_callback.log(
+                LogLevel::Warn,
+                format!("database-pruning-error; error={}", err),
+            );
+            continue;
+        }
+
+        let Some(keep_last) = keep_last else {
+            continue;
+        };
+
+        let mut num_pruned = 0;
+        loop {
+            let state_pins = database.state_pins();
+            let result = database
+                .with_database(move |database| {
+                    database.prune_finalized_blocks(
+                        keep_last,
+                        prune_bodies,
+                        BLOCKS_PER_BATCH,
+                        |hash| state_pins.is_pinned(hash),
+                    )
+                })
+                .await;
+
+            match result {
+                Ok(n) => {
+                    num_pruned += n;
+                    if n < BLOCKS_PER_BATCH {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    log_callback.log(
+                        LogLevel::Warn,
+                        format!("database-pruning-error; error={}", err),
+                    );
+                    break;
+                }
+            }
+        }
+
+        if num_pruned != 0 {
+            log_callback.log(
+                LogLevel::Debug,
+                format!("database-pruned; num_blocks={}", num_pruned),
+            );
+        }
+    }
+}
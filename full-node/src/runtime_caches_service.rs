@@ -77,7 +77,7 @@ impl RuntimeCachesService {
 
                         let (code, heap_pages) = config
                             .database
-                            .with_database(move |database| {
+                            .with_database_read(move |database| {
                                 let code = database.block_storage_get(
                                     &block_hash,
                                     iter::empty::<iter::Empty<_>>(),
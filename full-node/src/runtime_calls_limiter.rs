@@ -0,0 +1,244 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Scheduling of the runtime executions between the consensus and the JSON-RPC service.
+//!
+//! Every runtime execution must hold a permit obtained from the [`RuntimeCallsLimiter`] for as
+//! long as it runs.
+//!
+//! Consensus-critical executions (block verification and authoring) always obtain a permit
+//! immediately. JSON-RPC-initiated executions, on the other hand, wait until both the number of
+//! JSON-RPC executions and the total number of executions are below their respective limits.
+//! Consequently, a large number of JSON-RPC requests can never delay the import or authoring of
+//! blocks, while executions started by the consensus reduce the number of JSON-RPC executions
+//! that can run in parallel.
+//!
+//! JSON-RPC executions obtain their permit in the order in which they have asked for it.
+
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+/// Configuration for a [`RuntimeCallsLimiter`].
+pub struct Config {
+    /// Maximum number of runtime executions, consensus-critical or not, above which JSON-RPC
+    /// executions are no longer started.
+    pub max_total: NonZeroUsize,
+
+    /// Maximum number of JSON-RPC runtime executions that can run in parallel.
+    pub max_json_rpc: NonZeroUsize,
+}
+
+/// Limits the number of runtime executions running in parallel. See the module-level
+/// documentation for more info.
+pub struct RuntimeCallsLimiter {
+    /// See [`Config::max_total`].
+    max_total: usize,
+
+    /// See [`Config::max_json_rpc`].
+    max_json_rpc: usize,
+
+    state: Mutex<State>,
+
+    /// Notified whenever a permit is released or a JSON-RPC execution leaves the queue.
+    on_state_change: event_listener::Event,
+}
+
+struct State {
+    /// Number of permits of consensus-critical executions currently alive.
+    num_consensus: usize,
+
+    /// Number of permits of JSON-RPC executions currently alive.
+    num_json_rpc: usize,
+
+    /// Identifiers of the JSON-RPC executions waiting for a permit, in order.
+    queue: VecDeque<u64>,
+
+    /// Identifier to assign to the next JSON-RPC execution that asks for a permit.
+    next_queue_id: u64,
+}
+
+impl RuntimeCallsLimiter {
+    /// Initializes a new limiter.
+    pub fn new(config: Config) -> Self {
+        RuntimeCallsLimiter {
+            max_total: config.max_total.get(),
+            max_json_rpc: config.max_json_rpc.get(),
+            state: Mutex::new(State {
+                num_consensus: 0,
+                num_json_rpc: 0,
+                queue: VecDeque::new(),
+                next_queue_id: 0,
+            }),
+            on_state_change: event_listener::Event::new(),
+        }
+    }
+
+    /// Returns a permit for a consensus-critical runtime execution. Never waits.
+    pub fn consensus_permit(self: &Arc<Self>) -> Permit {
+        self.state.lock().unwrap().num_consensus += 1;
+        Permit {
+            limiter: self.clone(),
+            is_consensus: true,
+        }
+    }
+
+    /// Waits until a JSON-RPC runtime execution is allowed to start, then returns a permit.
+    pub async fn json_rpc_permit(self: &Arc<Self>) -> Permit {
+        let queue_entry = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_queue_id;
+            state.next_queue_id += 1;
+            state.queue.push_back(id);
+            QueueEntry { limiter: self, id }
+        };
+
+        loop {
+            let listener = self.on_state_change.listen();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.queue.front() == Some(&queue_entry.id)
+                    && state.num_json_rpc < self.max_json_rpc
+                    && state.num_json_rpc + state.num_consensus < self.max_total
+                {
+                    state.queue.pop_front();
+                    state.num_json_rpc += 1;
+                    drop(state);
+                    // The entry has been removed from the queue above.
+                    core::mem::forget(queue_entry);
+                    // Wake up the next entry in the queue, as it might be able to start as well.
+                    self.on_state_change.notify(usize::MAX);
+                    return Permit {
+                        limiter: self.clone(),
+                        is_consensus: false,
+                    };
+                }
+            }
+
+            listener.await;
+        }
+    }
+}
+
+/// Permit to run a runtime execution. The execution must end before the permit is destroyed.
+#[must_use]
+pub struct Permit {
+    limiter: Arc<RuntimeCallsLimiter>,
+    is_consensus: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        {
+            let mut state = self.limiter.state.lock().unwrap();
+            if self.is_consensus {
+                state.num_consensus -= 1;
+            } else {
+                state.num_json_rpc -= 1;
+            }
+        }
+        self.limiter.on_state_change.notify(usize::MAX);
+    }
+}
+
+/// Entry in [`State::queue`]. Removes itself from the queue if the JSON-RPC execution stops
+/// waiting for its permit, for example because the request has been cancelled.
+struct QueueEntry<'a> {
+    limiter: &'a RuntimeCallsLimiter,
+    id: u64,
+}
+
+impl<'a> Drop for QueueEntry<'a> {
+    fn drop(&mut self) {
+        {
+            let mut state = self.limiter.state.lock().unwrap();
+            let position = state.queue.iter().position(|id| *id == self.id).unwrap();
+            state.queue.remove(position);
+        }
+        self.limiter.on_state_change.notify(usize::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, RuntimeCallsLimiter};
+    use std::{num::NonZeroUsize, sync::Arc};
+
+    fn limiter(max_total: usize, max_json_rpc: usize) -> Arc<RuntimeCallsLimiter> {
+        Arc::new(RuntimeCallsLimiter::new(Config {
+            max_total: NonZeroUsize::new(max_total).unwrap(),
+            max_json_rpc: NonZeroUsize::new(max_json_rpc).unwrap(),
+        }))
+    }
+
+    #[test]
+    fn json_rpc_limit() {
+        smol::block_on(async move {
+            let limiter = limiter(8, 2);
+            let permit1 = limiter.json_rpc_permit().await;
+            let _permit2 = limiter.json_rpc_permit().await;
+
+            let mut waiting = Box::pin(limiter.json_rpc_permit());
+            assert!(smol::future::poll_once(&mut waiting).await.is_none());
+
+            // Dropping a permit releases its slot.
+            drop(permit1);
+            let _permit3 = waiting.await;
+        });
+    }
+
+    #[test]
+    fn consensus_permits_count_towards_total() {
+        smol::block_on(async move {
+            let limiter = limiter(2, 2);
+
+            // Consensus-critical executions are never delayed, even above the total limit.
+            let consensus1 = limiter.consensus_permit();
+            let consensus2 = limiter.consensus_permit();
+            let _consensus3 = limiter.consensus_permit();
+
+            let mut waiting = Box::pin(limiter.json_rpc_permit());
+            assert!(smol::future::poll_once(&mut waiting).await.is_none());
+            drop(consensus1);
+            assert!(smol::future::poll_once(&mut waiting).await.is_none());
+            drop(consensus2);
+            let _permit = waiting.await;
+        });
+    }
+
+    #[test]
+    fn cancelled_request_leaves_queue() {
+        smol::block_on(async move {
+            let limiter = limiter(8, 1);
+            let permit = limiter.json_rpc_permit().await;
+
+            let mut cancelled = Box::pin(limiter.json_rpc_permit());
+            assert!(smol::future::poll_once(&mut cancelled).await.is_none());
+            let mut waiting = Box::pin(limiter.json_rpc_permit());
+            assert!(smol::future::poll_once(&mut waiting).await.is_none());
+
+            // The request that has been cancelled no longer prevents the ones queued after it
+            // from obtaining a permit.
+            drop(cancelled);
+            drop(permit);
+            let _permit = waiting.await;
+        });
+    }
+}
@@ -0,0 +1,125 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Executing a runtime can take a long time without ever yielding back to the asynchronous
+//! executor, which can starve the other tasks (networking, JSON-RPC, etc.) if the machine has
+//! few CPU cores. For this reason, runtime executions can optionally be performed on dedicated
+//! threads.
+
+use crate::{LogCallback, LogLevel};
+
+use core::{future::Future, num::NonZeroUsize, pin::Pin};
+use futures_channel::oneshot;
+use smol::channel;
+use std::thread;
+
+/// Configuration for a [`RuntimeExecutionThreads`].
+pub struct Config<'a> {
+    /// Function called in order to notify of something.
+    pub log_callback: &'a (dyn LogCallback + Send + Sync),
+
+    /// Number of threads to spawn.
+    pub num_threads: NonZeroUsize,
+
+    /// Identifiers of the CPU cores to pin the threads to. Thread number `n` is pinned to
+    /// `cores[n % cores.len()]`. If empty, the threads aren't pinned.
+    pub cores: &'a [usize],
+}
+
+/// Handle to the threads where the runtime executions are performed.
+///
+/// Destroying this object stops the threads once they have finished executing their current
+/// task.
+pub struct RuntimeExecutionThreads {
+    sender: channel::Sender<Exec>,
+}
+
+type Exec = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+impl RuntimeExecutionThreads {
+    /// Spawns the threads.
+    pub fn new(config: Config) -> Self {
+        let (sender, rx) = channel::bounded::<Exec>(config.num_threads.get());
+
+        let available_cores = if !config.cores.is_empty() {
+            core_affinity::get_core_ids().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        for thread_index in 0..config.num_threads.get() {
+            let core_id = if !config.cores.is_empty() {
+                Some(config.cores[thread_index % config.cores.len()])
+            } else {
+                None
+            };
+
+            if let Some(core_id) = core_id {
+                if !available_cores.iter().any(|c| c.id == core_id) {
+                    config.log_callback.log(
+                        LogLevel::Warn,
+                        format!(
+                            "runtime-execution-thread-unknown-core; thread={}; core={}",
+                            thread_index, core_id
+                        ),
+                    );
+                }
+            }
+
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("runtime-execution-{thread_index}"))
+                .spawn(move || {
+                    if let Some(core_id) = core_id {
+                        // Failing to pin the thread isn't a big deal, and a warning has
+                        // already been printed above if the core is unknown.
+                        let _ =
+                            core_affinity::set_for_current(core_affinity::CoreId { id: core_id });
+                    }
+
+                    // When the `RuntimeExecutionThreads` is dropped, the sender will close,
+                    // `rx.recv()` will return an error, and the closure here will finish,
+                    // ending the thread.
+                    while let Ok(task) = rx.recv_blocking() {
+                        smol::block_on(task)
+                    }
+                })
+                .unwrap();
+        }
+
+        RuntimeExecutionThreads { sender }
+    }
+
+    /// Executes the given future on one of the threads, then returns the value that the future
+    /// returned.
+    ///
+    /// The future is expected to be CPU-heavy, such as a runtime call. Any asynchronous operation
+    /// performed by the future blocks the thread it is running on.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> T {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Box::pin(async move {
+                let _ = tx.send(future.await);
+            }))
+            .await
+            .unwrap();
+        rx.await.unwrap()
+    }
+}
@@ -0,0 +1,149 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Snapshots of the state of the finalized block.
+//!
+//! A snapshot contains the chain information of the finalized block, which includes its header,
+//! and all the storage items of this block, in the format of
+//! [`smoldot::database::finalized_serialize`]. A new database can be initialized from a
+//! snapshot instead of from the genesis block of the chain, in which case the node directly
+//! resumes syncing from the finalized block of the snapshot.
+//!
+//! The content of the snapshot is trusted, in the same way as a checkpoint found in a chain
+//! specification. Only the fact that the storage matches the state root of the header is
+//! verified.
+//!
+//! Child tries aren't supported. Only the root hash of each child trie, as found in the main
+//! trie, is included in the snapshot.
+
+use smoldot::{
+    database::{finalized_serialize, full_sqlite},
+    trie,
+};
+use std::{fs, io, iter, path::Path};
+
+use crate::database_thread;
+
+/// Error potentially returned by [`export`].
+#[derive(Debug, derive_more::Display)]
+pub enum ExportStateSnapshotError {
+    /// Error while reading the finalized block from the database.
+    #[display(fmt = "Failed to access the database: {_0}")]
+    Database(full_sqlite::StorageAccessError),
+    /// Error while writing the snapshot file.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+}
+
+/// Error potentially returned by [`load`].
+#[derive(Debug, derive_more::Display)]
+pub enum LoadStateSnapshotError {
+    /// Error while reading the snapshot file.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The content of the snapshot file is invalid.
+    #[display(fmt = "Invalid snapshot: {_0}")]
+    Corrupted(finalized_serialize::CorruptedError),
+    /// The snapshot file doesn't contain the storage of the finalized block.
+    #[display(fmt = "The snapshot doesn't contain the storage of the finalized block")]
+    MissingStorage,
+}
+
+/// Writes a snapshot of the state of the finalized block of the database to the file at the
+/// given path. The file is replaced if it already exists.
+///
+/// Returns the number of the block whose state has been exported.
+pub async fn export(
+    database: &database_thread::DatabaseThread,
+    block_number_bytes: usize,
+    path: &Path,
+) -> Result<u64, ExportStateSnapshotError> {
+    // The storage is read in one go in order to guarantee that the finalized block isn't
+    // pruned in the meanwhile.
+    let (finalized_block_number, snapshot) = database
+        .with_database(move |database| {
+            let finalized_block_hash = database.finalized_block_hash()?;
+            let chain_information = database.to_chain_information(&finalized_block_hash)?;
+
+            let mut storage = Vec::new();
+            let mut key_nibbles = Vec::new();
+            while let Some(key) = database.block_storage_next_key(
+                &finalized_block_hash,
+                iter::empty::<iter::Empty<_>>(),
+                key_nibbles.iter().copied(),
+                iter::empty(),
+                false,
+            )? {
+                let value = database.block_storage_get(
+                    &finalized_block_hash,
+                    iter::empty::<iter::Empty<_>>(),
+                    key.iter().copied(),
+                )?;
+                // Storage values are only ever found at keys with an even number of nibbles.
+                if let (Some((value, _)), 0) = (value, key.len() % 2) {
+                    let key_bytes = trie::nibbles_to_bytes_truncate(
+                        key.iter().map(|n| trie::Nibble::try_from(*n).unwrap()),
+                    )
+                    .collect::<Vec<_>>();
+                    storage.push((key_bytes, value));
+                }
+
+                key_nibbles = key;
+                key_nibbles.push(0);
+            }
+
+            Ok::<_, full_sqlite::StorageAccessError>((
+                chain_information.as_ref().finalized_block_header.number,
+                finalized_serialize::encode_chain_storage(
+                    &chain_information,
+                    block_number_bytes,
+                    Some(storage.into_iter()),
+                ),
+            ))
+        })
+        .await
+        .map_err(ExportStateSnapshotError::Database)?;
+
+    let path = path.to_owned();
+    smol::unblock(move || write_atomically(&path, snapshot.as_bytes()))
+        .await
+        .map_err(ExportStateSnapshotError::Io)?;
+
+    Ok(finalized_block_number)
+}
+
+/// Reads the snapshot found in the file at the given path.
+pub fn load(
+    path: &Path,
+    block_number_bytes: usize,
+) -> Result<finalized_serialize::Decoded, LoadStateSnapshotError> {
+    let encoded = fs::read_to_string(path).map_err(LoadStateSnapshotError::Io)?;
+    let decoded = finalized_serialize::decode_chain(&encoded, block_number_bytes)
+        .map_err(LoadStateSnapshotError::Corrupted)?;
+    if decoded.storage.is_none() {
+        return Err(LoadStateSnapshotError::MissingStorage);
+    }
+    Ok(decoded)
+}
+
+/// Writes the given data to a temporary file then renames it to the given path.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), io::Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
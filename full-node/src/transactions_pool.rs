@@ -0,0 +1,736 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Transactions waiting to be included in a block.
+//!
+//! The transactions submitted through the JSON-RPC server or by the runtime, for example by the
+//! offchain workers, are validated then inserted in a [`TransactionsPool`]. The blocks that the
+//! node builds include the transactions of the pool (see
+//! [`TransactionsPool::block_transactions`]).
+//!
+//! The transactions are removed from the pool by [`run_maintenance`] once they are included in
+//! a block of the best chain. The transactions of the blocks that are retracted from the best
+//! chain because of a re-organization aren't added back to the pool.
+//!
+//! [`run_maintenance`] also validates the transactions of the pool again against each new best
+//! block, and removes the ones that are no longer valid, for example because they have become
+//! outdated. At most [`MAX_REVALIDATIONS_PER_BLOCK`] transactions are validated again per block,
+//! starting with the ones that have been validated the least recently.
+
+use crate::{
+    consensus_service, database_thread, json_rpc_service, offchain_worker, runtime_calls_limiter,
+    util, LogCallback, LogLevel,
+};
+
+use hashbrown::{HashMap, HashSet};
+use smol::stream::StreamExt as _;
+use smoldot::{informant::HashDisplay, transactions::validate};
+use std::{
+    cmp,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of transactions of the pool validated again against each new best block.
+/// Validating a transaction requires a runtime call, and validating the entire pool at each block
+/// could keep the runtime busy for too long.
+const MAX_REVALIDATIONS_PER_BLOCK: usize = 64;
+
+/// Configuration for a [`TransactionsPool`].
+pub struct Config {
+    /// Maximum number of transactions in the pool. Transactions inserted while the pool is full
+    /// are refused.
+    pub max_transactions: usize,
+
+    /// Maximum number of transactions in the pool that have been signed by the same account.
+    /// The transactions submitted by the node itself aren't subject to this limit.
+    pub max_transactions_per_submitter: usize,
+}
+
+/// Collection of validated transactions waiting to be included in a block.
+pub struct TransactionsPool {
+    /// See [`Config::max_transactions`].
+    max_transactions: usize,
+
+    /// See [`Config::max_transactions_per_submitter`].
+    max_transactions_per_submitter: usize,
+
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// Transactions of the pool, indexed by their hash.
+    transactions: HashMap<[u8; 32], Transaction, fnv::FnvBuildHasher>,
+
+    /// Number of transactions of [`Inner::transactions`] for each value of
+    /// [`Transaction::sender`]. Senders without any transaction are absent from the map.
+    num_transactions_per_sender: HashMap<Vec<u8>, usize, fnv::FnvBuildHasher>,
+
+    /// Value of [`Transaction::insertion_index`] of the next transaction to insert.
+    next_insertion_index: u64,
+
+    /// Value of [`Transaction::validation_index`] of the next transaction to validate.
+    next_validation_index: u64,
+}
+
+struct Transaction {
+    /// SCALE-encoded transaction.
+    scale_encoded: Vec<u8>,
+
+    /// Result of the most recent validation of the transaction.
+    validity: validate::ValidTransaction,
+
+    /// Source of the transaction, passed to the runtime when validating it.
+    source: validate::TransactionSource,
+
+    /// Address of the account that has signed the transaction, as returned by
+    /// [`transaction_sender`]. Always `None` for the transactions submitted by the node itself,
+    /// which aren't subject to [`Config::max_transactions_per_submitter`].
+    sender: Option<Vec<u8>>,
+
+    /// Number of transactions inserted in the pool before this one. Used in order to include
+    /// the transactions with the same priority in the order in which they have been inserted.
+    insertion_index: u64,
+
+    /// Number of validations started before the most recent validation of this transaction. Used
+    /// in order to validate again the transactions that have been validated the least recently.
+    validation_index: u64,
+}
+
+impl TransactionsPool {
+    /// Initializes a new empty pool.
+    pub fn new(config: Config) -> Self {
+        TransactionsPool {
+            max_transactions: config.max_transactions,
+            max_transactions_per_submitter: config.max_transactions_per_submitter,
+            inner: Mutex::new(Inner {
+                transactions: HashMap::with_capacity_and_hasher(0, Default::default()),
+                num_transactions_per_sender: HashMap::with_capacity_and_hasher(
+                    0,
+                    Default::default(),
+                ),
+                next_insertion_index: 0,
+                next_validation_index: 0,
+            }),
+        }
+    }
+
+    /// Inserts a transaction in the pool, after it has been successfully validated with the
+    /// given source.
+    ///
+    /// The transactions whose source is [`validate::TransactionSource::Local`] have been
+    /// submitted by the node itself.
+    pub fn insert(
+        &self,
+        scale_encoded_transaction: Vec<u8>,
+        validity: validate::ValidTransaction,
+        source: validate::TransactionSource,
+    ) -> Result<(), InsertError> {
+        let hash = transaction_hash(&scale_encoded_transaction);
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.transactions.contains_key(&hash) {
+            return Err(InsertError::AlreadyInPool);
+        }
+        if inner.transactions.len() >= self.max_transactions {
+            return Err(InsertError::PoolFull);
+        }
+
+        let sender = match source {
+            validate::TransactionSource::Local => None,
+            validate::TransactionSource::External | validate::TransactionSource::InBlock => {
+                transaction_sender(&scale_encoded_transaction).map(|sender| sender.to_vec())
+            }
+        };
+        if let Some(sender) = &sender {
+            if inner
+                .num_transactions_per_sender
+                .get(sender)
+                .map_or(0, |n| *n)
+                >= self.max_transactions_per_submitter
+            {
+                return Err(InsertError::SubmitterLimitReached);
+            }
+            *inner
+                .num_transactions_per_sender
+                .entry(sender.clone())
+                .or_insert(0) += 1;
+        }
+
+        let insertion_index = inner.next_insertion_index;
+        inner.next_insertion_index += 1;
+        let validation_index = inner.next_validation_index;
+        inner.next_validation_index += 1;
+        inner.transactions.insert(
+            hash,
+            Transaction {
+                scale_encoded: scale_encoded_transaction,
+                validity,
+                source,
+                sender,
+                insertion_index,
+                validation_index,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns `true` if the given tag is provided by one of the transactions of the pool, as
+    /// found in [`validate::ValidTransaction::provides`].
+    pub fn is_provided(&self, tag: &[u8]) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .transactions
+            .values()
+            .any(|tx| tx.validity.provides.iter().any(|t| *t == tag))
+    }
+
+    /// Removes the given transaction from the pool, if it is in there.
+    pub fn remove(&self, scale_encoded_transaction: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(transaction) = inner
+            .transactions
+            .remove(&transaction_hash(scale_encoded_transaction))
+        else {
+            return;
+        };
+
+        if let Some(sender) = transaction.sender {
+            let hashbrown::hash_map::Entry::Occupied(mut entry) =
+                inner.num_transactions_per_sender.entry(sender)
+            else {
+                unreachable!()
+            };
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Returns at most `max` transactions of the pool, alongside with their source, starting with
+    /// the ones that have been validated the least recently.
+    ///
+    /// The returned transactions are considered as validated from now on, even if the validation
+    /// fails, so that the transactions that can't be validated don't prevent the other ones from
+    /// being validated again.
+    fn transactions_to_revalidate(
+        &self,
+        max: usize,
+    ) -> Vec<(Vec<u8>, validate::TransactionSource)> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        let mut transactions = inner.transactions.values_mut().collect::<Vec<_>>();
+        transactions.sort_by_key(|tx| tx.validation_index);
+        transactions.truncate(max);
+
+        transactions
+            .into_iter()
+            .map(|tx| {
+                tx.validation_index = inner.next_validation_index;
+                inner.next_validation_index += 1;
+                (tx.scale_encoded.clone(), tx.source)
+            })
+            .collect()
+    }
+
+    /// Updates the validity of the given transaction, if it is still in the pool.
+    fn set_validity(&self, scale_encoded_transaction: &[u8], validity: validate::ValidTransaction) {
+        if let Some(transaction) = self
+            .inner
+            .lock()
+            .unwrap()
+            .transactions
+            .get_mut(&transaction_hash(scale_encoded_transaction))
+        {
+            transaction.validity = validity;
+        }
+    }
+
+    /// Returns the transactions of the pool that can be included in a block, in the order in
+    /// which they must be included, and whose total size doesn't exceed `max_total_size` bytes.
+    ///
+    /// The transactions are ordered by decreasing priority. A transaction is only included
+    /// after all the tags it requires have been provided by the transactions included before it.
+    pub fn block_transactions(&self, max_total_size: usize) -> BlockTransactions {
+        let mut candidates = self
+            .inner
+            .lock()
+            .unwrap()
+            .transactions
+            .values()
+            .map(|tx| Candidate {
+                scale_encoded: tx.scale_encoded.clone(),
+                requires: tx.validity.requires.clone(),
+                provides: tx.validity.provides.clone(),
+                priority: tx.validity.priority,
+                insertion_index: tx.insertion_index,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|c| (cmp::Reverse(c.priority), c.insertion_index));
+
+        BlockTransactions {
+            candidates,
+            provided_tags: HashSet::with_capacity_and_hasher(0, Default::default()),
+            remaining_size: max_total_size,
+            pending: None,
+        }
+    }
+}
+
+/// Error returned by [`TransactionsPool::insert`].
+#[derive(Debug, derive_more::Display)]
+pub enum InsertError {
+    /// The transaction is already in the pool.
+    #[display(fmt = "Transaction is already in the pool")]
+    AlreadyInPool,
+    /// The pool contains the maximum number of transactions.
+    #[display(fmt = "Transactions pool is full")]
+    PoolFull,
+    /// The pool contains the maximum number of transactions signed by the same account.
+    #[display(fmt = "Too many transactions in the pool have been signed by this account")]
+    SubmitterLimitReached,
+}
+
+/// Transactions to include in a block. See [`TransactionsPool::block_transactions`].
+///
+/// This is a snapshot of the content of the pool at the time when it has been created.
+pub struct BlockTransactions {
+    /// Transactions that haven't been returned by [`BlockTransactions::next_transaction`] yet,
+    /// ordered by decreasing priority.
+    candidates: Vec<Candidate>,
+
+    /// Tags provided by the transactions included in the block so far.
+    provided_tags: HashSet<Vec<u8>, fnv::FnvBuildHasher>,
+
+    /// Number of bytes that the transactions included from now on can still use.
+    remaining_size: usize,
+
+    /// Transaction most recently returned by [`BlockTransactions::next_transaction`].
+    /// Considered as included in the block unless [`BlockTransactions::report_not_included`] is
+    /// called.
+    pending: Option<Candidate>,
+}
+
+struct Candidate {
+    scale_encoded: Vec<u8>,
+    requires: Vec<Vec<u8>>,
+    provides: Vec<Vec<u8>>,
+    priority: u64,
+    insertion_index: u64,
+}
+
+impl BlockTransactions {
+    /// Returns the next transaction to include in the block, or `None` if no other transaction
+    /// can be included.
+    ///
+    /// The transaction previously returned by this function is considered as successfully
+    /// included.
+    pub fn next_transaction(&mut self) -> Option<Vec<u8>> {
+        if let Some(included) = self.pending.take() {
+            self.provided_tags.extend(included.provides);
+        }
+
+        // Transactions that are too large will never fit, as the remaining size only decreases.
+        let remaining_size = self.remaining_size;
+        self.candidates
+            .retain(|c| c.scale_encoded.len() <= remaining_size);
+
+        let index = self.candidates.iter().position(|c| {
+            c.requires
+                .iter()
+                .all(|tag| self.provided_tags.contains(tag))
+        })?;
+
+        let candidate = self.candidates.remove(index);
+        self.remaining_size -= candidate.scale_encoded.len();
+        let scale_encoded = candidate.scale_encoded.clone();
+        self.pending = Some(candidate);
+        Some(scale_encoded)
+    }
+
+    /// Indicates that the transaction most recently returned by
+    /// [`BlockTransactions::next_transaction`] couldn't be included in the block. Its size no
+    /// longer counts towards the limit, and the transactions that require the tags it provides
+    /// are no longer returned.
+    pub fn report_not_included(&mut self) {
+        if let Some(candidate) = self.pending.take() {
+            self.remaining_size += candidate.scale_encoded.len();
+        }
+    }
+}
+
+/// Configuration for [`run_maintenance`].
+pub struct MaintenanceConfig {
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Consensus service of the chain. Used to track the new best blocks.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Database of the chain. Used to access the bodies and the storage of the blocks.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Limiter of the runtime executions. Validating transactions isn't consensus-critical and
+    /// is thus treated like JSON-RPC runtime calls.
+    pub runtime_calls_limiter: Arc<runtime_calls_limiter::RuntimeCallsLimiter>,
+
+    /// Pool to maintain.
+    pub transactions_pool: Arc<TransactionsPool>,
+
+    /// Transactions banned by the JSON-RPC service. The transactions of the pool that the runtime
+    /// reports as invalid are added to it, so that submitting them again is refused.
+    pub banned_transactions: Arc<json_rpc_service::BannedTransactions>,
+}
+
+/// Removes from the pool the transactions included in the new best blocks, then validates the
+/// other ones again against these blocks. Never returns.
+pub async fn run_maintenance(config: MaintenanceConfig) {
+    loop {
+        let subscribe_all = config
+            .consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+        let mut new_blocks = Box::pin(subscribe_all.new_blocks);
+
+        // Runtime of each block of the subscription, including the finalized block.
+        let mut runtimes = HashMap::<_, _, fnv::FnvBuildHasher>::with_capacity_and_hasher(
+            subscribe_all.non_finalized_blocks_ancestry_order.len() + 1,
+            Default::default(),
+        );
+        let mut finalized_block_hash = subscribe_all.finalized_block_hash;
+        runtimes.insert(
+            subscribe_all.finalized_block_hash,
+            subscribe_all.finalized_block_runtime,
+        );
+        config
+            .consensus_service
+            .unpin_block(subscribe_all.id, subscribe_all.finalized_block_hash)
+            .await;
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let runtime = match block.runtime_update {
+                Some(runtime) => runtime,
+                None => runtimes.get(&block.parent_hash).unwrap().clone(),
+            };
+            runtimes.insert(block.block_hash, runtime);
+            config
+                .consensus_service
+                .unpin_block(subscribe_all.id, block.block_hash)
+                .await;
+        }
+
+        while let Some(notification) = new_blocks.next().await {
+            match notification {
+                consensus_service::Notification::Block { block, .. } => {
+                    let runtime = match block.runtime_update {
+                        Some(runtime) => runtime,
+                        None => runtimes.get(&block.parent_hash).unwrap().clone(),
+                    };
+                    runtimes.insert(block.block_hash, runtime.clone());
+
+                    // The block is unpinned only after the transactions have been validated, in
+                    // order to guarantee that its storage is still in the database.
+                    if block.is_new_best {
+                        remove_included_transactions(&config, &block.block_hash).await;
+                        revalidate_transactions(&config, &block.block_hash, &runtime).await;
+                    }
+
+                    config
+                        .consensus_service
+                        .unpin_block(subscribe_all.id, block.block_hash)
+                        .await;
+                }
+                consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks_hashes,
+                    ..
+                } => {
+                    runtimes.remove(&finalized_block_hash);
+                    for hash in finalized_blocks_newest_to_oldest.iter().skip(1) {
+                        runtimes.remove(hash);
+                    }
+                    for hash in &pruned_blocks_hashes {
+                        runtimes.remove(hash);
+                    }
+                    finalized_block_hash = finalized_blocks_newest_to_oldest[0];
+                }
+            }
+        }
+
+        // The consensus service has killed the subscription, most likely because the maintenance
+        // is too slow. Subscribe again.
+        config
+            .log_callback
+            .log(LogLevel::Debug, "transactions-pool-resubscribe".to_string());
+    }
+}
+
+/// Removes from the pool the transactions included in the body of the given block.
+async fn remove_included_transactions(config: &MaintenanceConfig, block_hash: &[u8; 32]) {
+    let body = config
+        .database
+        .with_database_read({
+            let block_hash = *block_hash;
+            move |database| database.block_extrinsics(&block_hash)
+        })
+        .await;
+
+    match body {
+        Ok(Some(body)) => {
+            for transaction in body {
+                config.transactions_pool.remove(&transaction);
+            }
+        }
+        Ok(None) => {}
+        Err(error) => {
+            config.log_callback.log(
+                LogLevel::Warn,
+                format!(
+                    "transactions-pool-block-body-error; block={}; error={}",
+                    HashDisplay(block_hash),
+                    error
+                ),
+            );
+        }
+    }
+}
+
+/// Validates the transactions of the pool against the given block, and removes the ones that are
+/// no longer valid. See [`MAX_REVALIDATIONS_PER_BLOCK`].
+async fn revalidate_transactions(
+    config: &MaintenanceConfig,
+    block_hash: &[u8; 32],
+    runtime: &smoldot::executor::host::HostVmPrototype,
+) {
+    for (transaction, source) in config
+        .transactions_pool
+        .transactions_to_revalidate(MAX_REVALIDATIONS_PER_BLOCK)
+    {
+        match offchain_worker::validate_transaction(
+            &config.database,
+            &config.runtime_calls_limiter,
+            block_hash,
+            runtime,
+            &transaction,
+            source,
+        )
+        .await
+        {
+            Ok(validity) => config
+                .transactions_pool
+                .set_validity(&transaction, validity),
+            Err(offchain_worker::ValidateError::Invalid(error)) => {
+                config.transactions_pool.remove(&transaction);
+                // Similar to the JSON-RPC service, only the transactions that are definitely
+                // invalid are banned.
+                if matches!(error, validate::TransactionValidityError::Invalid(_)) {
+                    config
+                        .banned_transactions
+                        .ban(transaction_hash(&transaction));
+                }
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "transactions-pool-transaction-dropped; transaction={}; block={}; error={}",
+                        HashDisplay(&transaction_hash(&transaction)),
+                        HashDisplay(block_hash),
+                        error
+                    ),
+                );
+            }
+            // Failing to validate the transaction says nothing about its validity, and it is
+            // thus kept.
+            Err(error) => {
+                config.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "transactions-pool-revalidation-error; transaction={}; block={}; error={}",
+                        HashDisplay(&transaction_hash(&transaction)),
+                        HashDisplay(block_hash),
+                        error
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the address of the account that has signed the given transaction, or `None` if the
+/// transaction isn't signed or if its format isn't recognized.
+///
+/// The address is assumed to be encoded as a `MultiAddress`, which is the case for the vast
+/// majority of chains, and is returned including the byte indicating its variant.
+fn transaction_sender(scale_encoded_transaction: &[u8]) -> Option<&[u8]> {
+    // Transactions are prefixed with their length, followed with a byte containing the version
+    // of the format, whose highest bit is set if the transaction is signed. The address of the
+    // signer immediately follows.
+    let (_, transaction) = util::decode_scale_compact_usize(scale_encoded_transaction)?;
+    let (0x84, address) = transaction.split_first()? else {
+        return None;
+    };
+
+    let address_len = match address.first()? {
+        // `Id` and `Address32`.
+        0 | 3 => 33,
+        // `Address20`.
+        4 => 21,
+        // `Index`.
+        1 => {
+            let (_, after) = util::decode_scale_compact_usize(&address[1..])?;
+            address.len() - after.len()
+        }
+        // `Raw`.
+        2 => {
+            let (len, after) = util::decode_scale_compact_usize(&address[1..])?;
+            address.len() - after.len() + len
+        }
+        _ => return None,
+    };
+
+    address.get(..address_len)
+}
+
+/// Returns the hash of the given transaction, as reported to the JSON-RPC clients.
+fn transaction_hash(scale_encoded_transaction: &[u8]) -> [u8; 32] {
+    blake2_rfc::blake2b::blake2b(32, &[], scale_encoded_transaction)
+        .as_bytes()
+        .try_into()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, InsertError, TransactionsPool};
+    use smoldot::transactions::validate;
+    use std::num::NonZeroU64;
+
+    fn validity(
+        priority: u64,
+        requires: &[&[u8]],
+        provides: &[&[u8]],
+    ) -> validate::ValidTransaction {
+        validate::ValidTransaction {
+            priority,
+            requires: requires.iter().map(|tag| tag.to_vec()).collect(),
+            provides: provides.iter().map(|tag| tag.to_vec()).collect(),
+            longevity: NonZeroU64::new(64).unwrap(),
+            propagate: true,
+        }
+    }
+
+    /// Builds a signed transaction whose signer is an `Id` address filled with `sender`.
+    fn signed_transaction(sender: u8, nonce: u8) -> Vec<u8> {
+        let mut transaction = vec![0x84, 0];
+        transaction.extend_from_slice(&[sender; 32]);
+        transaction.push(nonce);
+        let mut scale_encoded = vec![u8::try_from(transaction.len() << 2).unwrap()];
+        scale_encoded.extend_from_slice(&transaction);
+        scale_encoded
+    }
+
+    #[test]
+    fn block_transactions_follow_tags() {
+        let pool = TransactionsPool::new(Config {
+            max_transactions: 16,
+            max_transactions_per_submitter: 16,
+        });
+
+        let local = validate::TransactionSource::Local;
+        pool.insert(vec![1], validity(1, &[], &[b"a"]), local)
+            .unwrap();
+        pool.insert(vec![2], validity(10, &[b"a"], &[b"b"]), local)
+            .unwrap();
+        pool.insert(vec![3], validity(5, &[b"b"], &[]), local)
+            .unwrap();
+        pool.insert(vec![4], validity(20, &[b"missing"], &[]), local)
+            .unwrap();
+
+        // Transactions are returned by decreasing priority, but only once the tags they require
+        // have been provided.
+        let mut block_transactions = pool.block_transactions(usize::MAX);
+        assert_eq!(block_transactions.next_transaction(), Some(vec![1]));
+        assert_eq!(block_transactions.next_transaction(), Some(vec![2]));
+        assert_eq!(block_transactions.next_transaction(), Some(vec![3]));
+        assert_eq!(block_transactions.next_transaction(), None);
+
+        // The tags provided by a transaction that isn't included aren't considered as provided.
+        let mut block_transactions = pool.block_transactions(usize::MAX);
+        assert_eq!(block_transactions.next_transaction(), Some(vec![1]));
+        assert_eq!(block_transactions.next_transaction(), Some(vec![2]));
+        block_transactions.report_not_included();
+        assert_eq!(block_transactions.next_transaction(), None);
+    }
+
+    #[test]
+    fn submitter_limit() {
+        let pool = TransactionsPool::new(Config {
+            max_transactions: 16,
+            max_transactions_per_submitter: 2,
+        });
+
+        let external = validate::TransactionSource::External;
+        pool.insert(signed_transaction(1, 0), validity(0, &[], &[]), external)
+            .unwrap();
+        pool.insert(signed_transaction(1, 1), validity(0, &[], &[]), external)
+            .unwrap();
+        assert!(matches!(
+            pool.insert(signed_transaction(1, 2), validity(0, &[], &[]), external),
+            Err(InsertError::SubmitterLimitReached)
+        ));
+
+        // Other submitters and the node itself aren't affected.
+        pool.insert(signed_transaction(2, 0), validity(0, &[], &[]), external)
+            .unwrap();
+        pool.insert(
+            signed_transaction(1, 2),
+            validity(0, &[], &[]),
+            validate::TransactionSource::Local,
+        )
+        .unwrap();
+
+        // Removing a transaction makes room for another one from the same submitter.
+        pool.remove(&signed_transaction(1, 0));
+        pool.insert(signed_transaction(1, 3), validity(0, &[], &[]), external)
+            .unwrap();
+    }
+
+    #[test]
+    fn revalidation_least_recently_validated_first() {
+        let pool = TransactionsPool::new(Config {
+            max_transactions: 16,
+            max_transactions_per_submitter: 16,
+        });
+
+        let local = validate::TransactionSource::Local;
+        for transaction in 1..=3 {
+            pool.insert(vec![transaction], validity(0, &[], &[]), local)
+                .unwrap();
+        }
+
+        let transactions = |max| {
+            pool.transactions_to_revalidate(max)
+                .into_iter()
+                .map(|(transaction, _)| transaction)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(transactions(2), vec![vec![1], vec![2]]);
+        assert_eq!(transactions(2), vec![vec![3], vec![1]]);
+        assert_eq!(transactions(2), vec![vec![2], vec![3]]);
+    }
+}
@@ -45,3 +45,31 @@ pub fn truncated_str<'a>(
 
     Iter(input, limit)
 }
+
+/// Decodes a SCALE-compact-encoded number found at the start of the given slice. Returns the
+/// number and the rest of the slice.
+pub fn decode_scale_compact_usize(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let first_byte = *bytes.first()?;
+    let (value, num_bytes) = match first_byte & 0b11 {
+        0b00 => (u64::from(first_byte >> 2), 1),
+        0b01 => {
+            let value = u16::from_le_bytes(<[u8; 2]>::try_from(bytes.get(..2)?).unwrap());
+            (u64::from(value >> 2), 2)
+        }
+        0b10 => {
+            let value = u32::from_le_bytes(<[u8; 4]>::try_from(bytes.get(..4)?).unwrap());
+            (u64::from(value >> 2), 4)
+        }
+        _ => {
+            let num_value_bytes = usize::from(first_byte >> 2) + 4;
+            if num_value_bytes > 8 {
+                return None;
+            }
+            let mut value = [0; 8];
+            value[..num_value_bytes].copy_from_slice(bytes.get(1..1 + num_value_bytes)?);
+            (u64::from_le_bytes(value), 1 + num_value_bytes)
+        }
+    };
+
+    Some((usize::try_from(value).ok()?, &bytes[num_bytes..]))
+}
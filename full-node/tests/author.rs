@@ -15,32 +15,88 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod common;
+
+use smoldot::header;
+use smoldot::identity::{keystore, seed_phrase};
 use smoldot::json_rpc;
-use std::sync::Arc;
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 #[test]
-#[ignore] // TODO: restore after https://github.com/smol-dot/smoldot/issues/1109
 fn basic_block_generated() {
     smol::block_on(async move {
         let client = smoldot_full_node::start(smoldot_full_node::Config {
             chain: smoldot_full_node::ChainConfig {
                 chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
                 additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
                 keystore_memory: vec![smoldot::identity::seed_phrase::decode_sr25519_private_key(
                     "//Alice",
                 )
                 .unwrap()],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_options: Default::default(),
                 keystore_path: None,
                 json_rpc_listen: None,
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: smoldot_full_node::SyncMode::Warp,
+                cross_check_warp_sync: false,
+                max_reorg_depth: None,
+                slot_drift_tolerance: Duration::from_secs(30),
+                max_slot_lenience: Duration::new(0, 0),
+                pruning: smoldot_full_node::Pruning::Archive,
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: 15,
+                min_out_peers: 4,
+                max_in_peers: 25,
+                legacy_protocol_names: false,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 4 * 1024 * 1024,
+                max_pool_transactions: 8192,
+                max_pool_transactions_per_submitter: 512,
+                sync_limits: Default::default(),
             },
             relay_chain: None,
-            libp2p_key: Box::new([0; 32]),
+            libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
             listen_addresses: Vec::new(),
+            max_outbound_connections_per_subnet: None,
+            max_inbound_connections_per_ip: None,
+            inbound_connections_ip_allowlist: Vec::new(),
+            socks5_proxy: None,
+            nat_port_mapping: false,
+            max_upload_bytes_per_sec: None,
+            max_download_bytes_per_sec: None,
+            max_file_descriptors: None,
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
+            progress_callback: Arc::new(|_| {}),
             jaeger_agent: None,
+            jaeger_sampling: Default::default(),
+            runtime_execution_threads: None,
+            max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+            genesis_build_threads: None,
+            quarantine_corrupted_database: false,
+            compiled_runtimes_cache_path: None,
+            block_export: None,
+            block_import_hook: None,
+            randomness_seed: None,
         })
         .await
         .unwrap();
@@ -74,3 +130,145 @@ fn basic_block_generated() {
         }
     });
 }
+
+#[test]
+fn block_template_built() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        // The node template uses Aura with slots of 6 seconds.
+        let slot_number = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            / 6000;
+        let template = client
+            .build_block_template(smoldot_full_node::BlockTemplateSlotClaim::Aura {
+                slot_number: u64::try_from(slot_number).unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let decoded = header::decode(&template.scale_encoded_header, 4).unwrap();
+        assert_eq!(decoded.number, 1);
+        assert_eq!(*decoded.parent_hash, template.parent_hash);
+        assert_eq!(*decoded.state_root, template.state_root);
+        assert!(decoded.digest.aura_seal().is_none());
+        assert!(!template.body.is_empty());
+    });
+}
+
+#[test]
+fn block_template_transactions_size_limited() {
+    smol::block_on(async move {
+        // Each of the transactions submitted below is slightly more than 200 bytes, meaning that
+        // only two of them fit in the limit.
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                keystore_memory: Vec::new(),
+                max_authored_block_transactions_size: 500,
+                ..common::chain_config()
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        let genesis_hash = json_rpc_call(&client, "chain_getBlockHash", "[0]").await;
+        let genesis_hash = <[u8; 32]>::try_from(
+            hex::decode(genesis_hash.as_str().unwrap().trim_start_matches("0x")).unwrap(),
+        )
+        .unwrap();
+        let runtime_version = json_rpc_call(&client, "state_getRuntimeVersion", "[]").await;
+
+        let mut keystore = keystore::Keystore::new(None, [0; 32]).await.unwrap();
+        let alice = keystore.insert_sr25519_memory(
+            keystore::KeyNamespace::all(),
+            &seed_phrase::decode_sr25519_private_key("//Alice").unwrap(),
+        );
+
+        let mut transactions = Vec::new();
+        for nonce in 0..3 {
+            // `System::remark` call, followed by the immortal era, the nonce, and the tip.
+            let call = [&[0, 1][..], &encode_compact(100), &[0xaa; 100]].concat();
+            let extra = [0, nonce * 4, 0];
+            let payload = [
+                &call[..],
+                &extra[..],
+                &u32::try_from(runtime_version["specVersion"].as_u64().unwrap())
+                    .unwrap()
+                    .to_le_bytes(),
+                &u32::try_from(runtime_version["transactionVersion"].as_u64().unwrap())
+                    .unwrap()
+                    .to_le_bytes(),
+                &genesis_hash,
+                &genesis_hash,
+            ]
+            .concat();
+            let signature = keystore
+                .sign(keystore::KeyNamespace::Aura, &alice, &payload)
+                .await
+                .unwrap();
+
+            let transaction = [&[0x84, 0][..], &alice, &[1], &signature, &extra, &call].concat();
+            transactions.push([encode_compact(transaction.len()), transaction].concat());
+        }
+
+        for transaction in &transactions {
+            json_rpc_call(
+                &client,
+                "author_submitExtrinsic",
+                &format!("[\"0x{}\"]", hex::encode(transaction)),
+            )
+            .await;
+        }
+
+        // The node template uses Aura with slots of 6 seconds.
+        let slot_number = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            / 6000;
+        let template = client
+            .build_block_template(smoldot_full_node::BlockTemplateSlotClaim::Aura {
+                slot_number: u64::try_from(slot_number).unwrap(),
+            })
+            .await
+            .unwrap();
+
+        // The first item of the body is the timestamp inherent.
+        assert_eq!(template.body.len(), 3);
+        assert_eq!(template.body[1..], transactions[..2]);
+    });
+}
+
+/// Sends a JSON-RPC request to the node and returns the result of the response.
+async fn json_rpc_call(
+    client: &smoldot_full_node::Client,
+    method: &str,
+    params: &str,
+) -> serde_json::Value {
+    client.send_json_rpc_request(format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"{method}","params":{params}}}"#
+    ));
+    let response = client.next_json_rpc_response().await;
+    serde_json::from_str(
+        json_rpc::parse::parse_response(&response)
+            .unwrap()
+            .into_success()
+            .unwrap_or_else(|| panic!("{response}"))
+            .1,
+    )
+    .unwrap()
+}
+
+/// SCALE-encodes the given number, which must be lower than `2^14`, as a compact.
+fn encode_compact(value: usize) -> Vec<u8> {
+    if value < 64 {
+        vec![u8::try_from(value << 2).unwrap()]
+    } else {
+        (u16::try_from(value << 2).unwrap() | 1)
+            .to_le_bytes()
+            .to_vec()
+    }
+}
@@ -0,0 +1,97 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Configuration shared by the tests. Tests override the fields they need through the struct
+//! update syntax.
+
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+
+/// Returns the configuration of a node that runs `substrate-node-template.json` with an
+/// in-memory database, doesn't listen on any address, and has no JSON-RPC server.
+pub fn config() -> smoldot_full_node::Config<'static> {
+    smoldot_full_node::Config {
+        chain: chain_config(),
+        relay_chain: None,
+        libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
+        listen_addresses: Vec::new(),
+        max_outbound_connections_per_subnet: None,
+        max_inbound_connections_per_ip: None,
+        inbound_connections_ip_allowlist: Vec::new(),
+        socks5_proxy: None,
+        nat_port_mapping: false,
+        max_upload_bytes_per_sec: None,
+        max_download_bytes_per_sec: None,
+        max_file_descriptors: None,
+        tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+        log_callback: Arc::new(move |_, _| {}),
+        progress_callback: Arc::new(|_| {}),
+        jaeger_agent: None,
+        jaeger_sampling: Default::default(),
+        runtime_execution_threads: None,
+        max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+        genesis_build_threads: None,
+        quarantine_corrupted_database: false,
+        compiled_runtimes_cache_path: None,
+        block_export: None,
+        block_import_hook: None,
+        randomness_seed: None,
+    }
+}
+
+/// Returns the value of [`smoldot_full_node::Config::chain`] in [`config`].
+pub fn chain_config() -> smoldot_full_node::ChainConfig<'static> {
+    smoldot_full_node::ChainConfig {
+        chain_spec: (&include_bytes!("../substrate-node-template.json")[..]).into(),
+        additional_bootnodes: Vec::new(),
+        bootnodes_providers: Vec::new(),
+        keystore_memory: vec![],
+        sqlite_database_path: None,
+        sqlite_cache_size: 256 * 1024 * 1024,
+        sqlite_options: Default::default(),
+        keystore_path: None,
+        json_rpc_listen: None,
+        finalized_chain_only: false,
+        block_execution_profiling: false,
+        sync_mode: smoldot_full_node::SyncMode::Warp,
+        cross_check_warp_sync: false,
+        max_reorg_depth: None,
+        slot_drift_tolerance: Duration::from_secs(30),
+        max_slot_lenience: Duration::new(0, 0),
+        pruning: smoldot_full_node::Pruning::Archive,
+        peer_rotation_interval: None,
+        bootstrap_fallback_delay: None,
+        fallback_bootnodes: Vec::new(),
+        max_out_peers: 15,
+        min_out_peers: 4,
+        max_in_peers: 25,
+        legacy_protocol_names: false,
+        grandpa_voter: false,
+        offchain_worker: false,
+        offchain_indexing: false,
+        checkpoint_export: None,
+        database_backup: None,
+        state_snapshot: None,
+        database_compaction_interval: None,
+        inherent_data_providers: Vec::new(),
+        report_equivocations: false,
+        authoring_slot_proportion: 2.0 / 3.0,
+        max_authored_block_transactions_size: 4 * 1024 * 1024,
+        max_pool_transactions: 8192,
+        max_pool_transactions_per_submitter: 512,
+        sync_limits: Default::default(),
+    }
+}
@@ -0,0 +1,416 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[test]
+fn corrupted_database_quarantined() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-quarantine-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let database_path = directory.join("database");
+        fs::write(&database_path, [0xde; 4096]).unwrap();
+
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                sqlite_database_path: Some(database_path.clone()),
+                ..common::chain_config()
+            },
+            quarantine_corrupted_database: true,
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        // The corrupted file has been moved aside and contains the original content.
+        let quarantined = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("database.corrupted-")
+            })
+            .unwrap();
+        assert_eq!(fs::read(quarantined).unwrap(), [0xde; 4096]);
+
+        drop(client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn database_read_only_while_running() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-read-only-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let database_path = directory.join("database");
+
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                sqlite_database_path: Some(database_path.clone()),
+                sqlite_options: smoldot_full_node::SqliteOptions {
+                    external_read_access: true,
+                    ..Default::default()
+                },
+                ..common::chain_config()
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        // The database can be read while the node is running.
+        let read_only_database =
+            smoldot_full_node::open_database_read_only(&database_path, 4, 16 * 1024 * 1024)
+                .unwrap();
+        let finalized_block_hash = read_only_database.finalized_block_hash().unwrap();
+        assert_eq!(
+            read_only_database
+                .block_hash_by_number(0)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![finalized_block_hash]
+        );
+
+        // Modifications are refused.
+        assert!(read_only_database
+            .set_authoring_stats(&Default::default())
+            .is_err());
+
+        drop(read_only_database);
+        drop(client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn startup_report_includes_genesis_build() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        let report = client.startup_report().await;
+        assert!(report.genesis_build.unwrap() <= report.database_open);
+        assert!(report.database_open <= report.initialization);
+        assert!(report.first_peer.is_none());
+    });
+}
+
+#[test]
+fn initialization_progress_reported() {
+    smol::block_on(async move {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let _client = smoldot_full_node::start(smoldot_full_node::Config {
+            progress_callback: {
+                let reports = reports.clone();
+                Arc::new(move |progress| reports.lock().unwrap().push(progress))
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert!(matches!(
+            reports.first(),
+            Some(smoldot_full_node::Progress::StartupPhase {
+                phase: smoldot_full_node::StartupPhase::ChainSpecParse,
+                ..
+            })
+        ));
+        assert!(reports
+            .iter()
+            .any(|p| matches!(p, smoldot_full_node::Progress::DatabaseOpen { .. })));
+        assert!(reports.iter().any(|p| matches!(
+            p,
+            smoldot_full_node::Progress::GenesisBuild {
+                step: smoldot_full_node::GenesisBuildStep::MerkleValues,
+                ..
+            }
+        )));
+        assert!(reports.iter().any(|p| matches!(
+            p,
+            smoldot_full_node::Progress::StartupPhase {
+                phase: smoldot_full_node::StartupPhase::Initialization,
+                ..
+            }
+        )));
+    });
+}
+
+#[test]
+fn checkpoint_exported() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-checkpoint-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let checkpoint_path = directory.join("checkpoint.json");
+
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                checkpoint_export: Some(smoldot_full_node::CheckpointExportConfig {
+                    path: checkpoint_path.clone(),
+                    interval: Duration::from_millis(100),
+                }),
+                ..common::chain_config()
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        // Wait for the checkpoint to be written.
+        let checkpoint = loop {
+            if let Ok(checkpoint) = fs::read_to_string(&checkpoint_path) {
+                break checkpoint;
+            }
+            smol::Timer::after(Duration::from_millis(50)).await;
+        };
+
+        // The database is empty, and the checkpoint is thus the genesis block.
+        let decoded = smoldot::database::finalized_serialize::decode_chain(&checkpoint, 4).unwrap();
+        assert!(decoded.storage.is_none());
+        assert_eq!(
+            decoded
+                .chain_information
+                .as_ref()
+                .finalized_block_header
+                .number,
+            0
+        );
+
+        drop(client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn force_finalize_unknown_block() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        assert!(matches!(
+            client.force_finalize([0xff; 32]).await,
+            Err(smoldot_full_node::ForceFinalizeError::UnknownBlock)
+        ));
+    });
+}
+
+#[test]
+fn import_blocks_invalid_file() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-import-blocks-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        // File whose only block can't be decoded.
+        let undecodable_path = directory.join("undecodable.bin");
+        fs::write(
+            &undecodable_path,
+            [&1u64.to_le_bytes()[..], &[1, 2, 3][..]].concat(),
+        )
+        .unwrap();
+        assert!(matches!(
+            client.import_blocks(undecodable_path).await,
+            Err(smoldot_full_node::ImportBlocksError::InvalidBlock { index: 0 })
+        ));
+
+        // File whose only block isn't a child of a known block.
+        let unknown_parent_path = directory.join("unknown-parent.bin");
+        let header = smoldot::header::HeaderRef {
+            parent_hash: &[0xff; 32],
+            number: 1,
+            state_root: &[0; 32],
+            extrinsics_root: &[0; 32],
+            digest: smoldot::header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(4);
+        fs::write(
+            &unknown_parent_path,
+            [&1u64.to_le_bytes()[..], &header, &[0, 0][..]].concat(),
+        )
+        .unwrap();
+        assert!(matches!(
+            client.import_blocks(unknown_parent_path).await,
+            Err(smoldot_full_node::ImportBlocksError::UnknownParent { index: 0 })
+        ));
+
+        // Empty file.
+        let empty_path = directory.join("empty.bin");
+        fs::write(&empty_path, 0u64.to_le_bytes()).unwrap();
+        let report = client.import_blocks(empty_path).await.unwrap();
+        assert_eq!(report.num_queued, 0);
+        assert_eq!(report.num_already_known, 0);
+
+        drop(client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn export_blocks_then_import() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-export-blocks-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("blocks.bin");
+
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        // Only the genesis block is finalized.
+        assert_eq!(client.export_blocks(path.clone(), 0..=10).await.unwrap(), 1);
+        let exported = fs::read(&path).unwrap();
+        assert_eq!(exported[..8], 1u64.to_le_bytes());
+        let (header, remain) = smoldot::header::decode_partial(&exported[8..], 4).unwrap();
+        assert_eq!(header.number, 0);
+        // Empty body and no justification.
+        assert_eq!(remain, &[0, 0]);
+
+        assert!(matches!(
+            client.export_blocks(path.clone(), 1..=10).await,
+            Err(smoldot_full_node::ExportBlocksError::NotFinalized(1))
+        ));
+
+        let report = client.import_blocks(path).await.unwrap();
+        assert_eq!(report.num_queued, 0);
+        assert_eq!(report.num_already_known, 1);
+
+        drop(client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn state_snapshot_export_then_bootstrap() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-state-snapshot-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("snapshot.json");
+        let reexported_path = directory.join("snapshot-reexported.json");
+
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        // Only the genesis block is finalized.
+        assert_eq!(client.export_state_snapshot(path.clone()).await.unwrap(), 0);
+        let decoded = smoldot::database::finalized_serialize::decode_chain(
+            &fs::read_to_string(&path).unwrap(),
+            4,
+        )
+        .unwrap();
+        assert!(decoded.storage.unwrap().contains_key(&b":code"[..]));
+        drop(client);
+
+        let bootstrapped_client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                state_snapshot: Some(path.clone()),
+                ..common::chain_config()
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            bootstrapped_client
+                .export_state_snapshot(reexported_path.clone())
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            fs::read(&reexported_path).unwrap()
+        );
+
+        drop(bootstrapped_client);
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
+
+#[test]
+fn compact_database() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        client.compact_database().await.unwrap();
+
+        // Everything has been given back by the previous call.
+        assert_eq!(client.compact_database().await.unwrap(), 0);
+    });
+}
+
+#[test]
+fn database_metrics() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(common::config()).await.unwrap();
+
+        // Starting the client requires accessing the database.
+        let metrics = client.database_metrics().await;
+        assert!(metrics.write_connection.num_operations > 0);
+        assert_eq!(
+            metrics
+                .write_connection
+                .duration_histogram
+                .iter()
+                .sum::<u64>(),
+            metrics.write_connection.num_operations
+        );
+        assert_eq!(
+            metrics.write_connection.duration_histogram.len(),
+            smoldot_full_node::DATABASE_LATENCY_BUCKETS.len() + 1
+        );
+
+        assert!(!metrics.slowest_operations.is_empty());
+        assert!(metrics
+            .slowest_operations
+            .windows(2)
+            .all(|ops| ops[0].duration >= ops[1].duration));
+
+        // Operations that are performed later are counted as well.
+        client.compact_database().await.unwrap();
+        let after = client.database_metrics().await;
+        assert!(
+            after.write_connection.num_operations + after.read_connections.num_operations
+                > metrics.write_connection.num_operations + metrics.read_connections.num_operations
+        );
+    });
+}
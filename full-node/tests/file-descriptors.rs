@@ -0,0 +1,57 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::{io::Read as _, net, num::NonZeroUsize, time::Duration};
+
+#[test]
+fn incoming_connection_refused_when_budget_exhausted() {
+    smol::block_on(async move {
+        // Find a free port by binding to port 0.
+        let port = net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            listen_addresses: vec![format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()],
+            max_file_descriptors: Some(NonZeroUsize::new(1).unwrap()),
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        // The budget is entirely used by the listening socket.
+        let usage = client.file_descriptors_usage();
+        assert_eq!(usage.max, Some(NonZeroUsize::new(1).unwrap()));
+        assert_eq!(usage.sockets, 1);
+        assert_eq!(usage.database, 0);
+
+        // The node accepts then immediately closes the connection.
+        let mut socket = net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        assert_eq!(socket.read(&mut [0; 64]).unwrap(), 0);
+
+        let usage = client.file_descriptors_usage();
+        assert_eq!(usage.sockets, 1);
+        assert_eq!(usage.num_refused_sockets, 1);
+    });
+}
@@ -15,8 +15,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod common;
+
+use smol::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use smoldot::json_rpc;
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
 #[test]
 fn send_request_errs_if_malformed() {
@@ -25,18 +28,67 @@ fn send_request_errs_if_malformed() {
             chain: smoldot_full_node::ChainConfig {
                 chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
                 additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
                 keystore_memory: vec![],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_options: Default::default(),
                 keystore_path: None,
                 json_rpc_listen: None,
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: smoldot_full_node::SyncMode::Warp,
+                cross_check_warp_sync: false,
+                max_reorg_depth: None,
+                slot_drift_tolerance: Duration::from_secs(30),
+                max_slot_lenience: Duration::new(0, 0),
+                pruning: smoldot_full_node::Pruning::Archive,
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: 15,
+                min_out_peers: 4,
+                max_in_peers: 25,
+                legacy_protocol_names: false,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 4 * 1024 * 1024,
+                max_pool_transactions: 8192,
+                max_pool_transactions_per_submitter: 512,
+                sync_limits: Default::default(),
             },
             relay_chain: None,
-            libp2p_key: Box::new([0; 32]),
+            libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
             listen_addresses: Vec::new(),
+            max_outbound_connections_per_subnet: None,
+            max_inbound_connections_per_ip: None,
+            inbound_connections_ip_allowlist: Vec::new(),
+            socks5_proxy: None,
+            nat_port_mapping: false,
+            max_upload_bytes_per_sec: None,
+            max_download_bytes_per_sec: None,
+            max_file_descriptors: None,
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
+            progress_callback: Arc::new(|_| {}),
             jaeger_agent: None,
+            jaeger_sampling: Default::default(),
+            runtime_execution_threads: None,
+            max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+            genesis_build_threads: None,
+            quarantine_corrupted_database: false,
+            compiled_runtimes_cache_path: None,
+            block_export: None,
+            block_import_hook: None,
+            randomness_seed: None,
         })
         .await
         .unwrap();
@@ -57,18 +109,67 @@ fn send_request_works_if_unknown_request() {
             chain: smoldot_full_node::ChainConfig {
                 chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
                 additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
                 keystore_memory: vec![],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_options: Default::default(),
                 keystore_path: None,
                 json_rpc_listen: None,
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: smoldot_full_node::SyncMode::Warp,
+                cross_check_warp_sync: false,
+                max_reorg_depth: None,
+                slot_drift_tolerance: Duration::from_secs(30),
+                max_slot_lenience: Duration::new(0, 0),
+                pruning: smoldot_full_node::Pruning::Archive,
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: 15,
+                min_out_peers: 4,
+                max_in_peers: 25,
+                legacy_protocol_names: false,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 4 * 1024 * 1024,
+                max_pool_transactions: 8192,
+                max_pool_transactions_per_submitter: 512,
+                sync_limits: Default::default(),
             },
             relay_chain: None,
-            libp2p_key: Box::new([0; 32]),
+            libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
             listen_addresses: Vec::new(),
+            max_outbound_connections_per_subnet: None,
+            max_inbound_connections_per_ip: None,
+            inbound_connections_ip_allowlist: Vec::new(),
+            socks5_proxy: None,
+            nat_port_mapping: false,
+            max_upload_bytes_per_sec: None,
+            max_download_bytes_per_sec: None,
+            max_file_descriptors: None,
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
+            progress_callback: Arc::new(|_| {}),
             jaeger_agent: None,
+            jaeger_sampling: Default::default(),
+            runtime_execution_threads: None,
+            max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+            genesis_build_threads: None,
+            quarantine_corrupted_database: false,
+            compiled_runtimes_cache_path: None,
+            block_export: None,
+            block_import_hook: None,
+            randomness_seed: None,
         })
         .await
         .unwrap();
@@ -86,3 +187,284 @@ fn send_request_works_if_unknown_request() {
         }
     });
 }
+
+#[test]
+fn json_rpc_metrics_count_calls_and_errors() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_options: Default::default(),
+                keystore_path: None,
+                json_rpc_listen: None,
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: smoldot_full_node::SyncMode::Warp,
+                cross_check_warp_sync: false,
+                max_reorg_depth: None,
+                slot_drift_tolerance: Duration::from_secs(30),
+                max_slot_lenience: Duration::new(0, 0),
+                pruning: smoldot_full_node::Pruning::Archive,
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: 15,
+                min_out_peers: 4,
+                max_in_peers: 25,
+                legacy_protocol_names: false,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 4 * 1024 * 1024,
+                max_pool_transactions: 8192,
+                max_pool_transactions_per_submitter: 512,
+                sync_limits: Default::default(),
+            },
+            relay_chain: None,
+            libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
+            listen_addresses: Vec::new(),
+            max_outbound_connections_per_subnet: None,
+            max_inbound_connections_per_ip: None,
+            inbound_connections_ip_allowlist: Vec::new(),
+            socks5_proxy: None,
+            nat_port_mapping: false,
+            max_upload_bytes_per_sec: None,
+            max_download_bytes_per_sec: None,
+            max_file_descriptors: None,
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            progress_callback: Arc::new(|_| {}),
+            jaeger_agent: None,
+            jaeger_sampling: Default::default(),
+            runtime_execution_threads: None,
+            max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+            genesis_build_threads: None,
+            quarantine_corrupted_database: false,
+            compiled_runtimes_cache_path: None,
+            block_export: None,
+            block_import_hook: None,
+            randomness_seed: None,
+        })
+        .await
+        .unwrap();
+
+        for id in 0..2 {
+            client.send_json_rpc_request(format!(
+                r#"{{"jsonrpc":"2.0","id":{id},"method":"system_name","params":[]}}"#
+            ));
+            let _ = client.next_json_rpc_response().await;
+        }
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":2,"method":"thisjsonrpcmethoddoesntexist","params":[]}"#
+                .to_owned(),
+        );
+        let _ = client.next_json_rpc_response().await;
+
+        let metrics = client.json_rpc_metrics();
+
+        let system_name = metrics.iter().find(|m| m.method == "system_name").unwrap();
+        assert_eq!(system_name.num_calls, 2);
+        assert_eq!(system_name.num_errors, 0);
+        assert_eq!(system_name.latency_histogram.iter().sum::<u64>(), 2);
+
+        let unknown = metrics.iter().find(|m| m.method == "unknown").unwrap();
+        assert_eq!(unknown.num_calls, 1);
+        assert_eq!(unknown.num_errors, 1);
+    });
+}
+
+#[test]
+fn health_endpoint_served_on_json_rpc_port() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                bootnodes_providers: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_options: Default::default(),
+                keystore_path: None,
+                json_rpc_listen: Some(smoldot_full_node::JsonRpcListenConfig {
+                    address: "127.0.0.1:0".parse().unwrap(),
+                    admin_address: None,
+                    health_address: None,
+                    max_json_rpc_clients: 2,
+                    max_json_rpc_clients_per_ip: 2,
+                    max_requests_per_second_per_client: u32::MAX,
+                    max_subscriptions_per_client: 1,
+                    max_pending_notifications_per_client: NonZeroUsize::new(1).unwrap(),
+                    slow_subscriber_policy: smoldot_full_node::SlowSubscriberPolicy::Disconnect,
+                    multiplexed_subscriptions_buffer: None,
+                    tls: None,
+                    allowed_origins: None,
+                    transactions_ban_duration: Duration::from_secs(30 * 60),
+                }),
+                finalized_chain_only: false,
+                block_execution_profiling: false,
+                sync_mode: smoldot_full_node::SyncMode::Warp,
+                cross_check_warp_sync: false,
+                max_reorg_depth: None,
+                slot_drift_tolerance: Duration::from_secs(30),
+                max_slot_lenience: Duration::new(0, 0),
+                pruning: smoldot_full_node::Pruning::Archive,
+                peer_rotation_interval: None,
+                bootstrap_fallback_delay: None,
+                fallback_bootnodes: Vec::new(),
+                max_out_peers: 15,
+                min_out_peers: 4,
+                max_in_peers: 25,
+                legacy_protocol_names: false,
+                grandpa_voter: false,
+                offchain_worker: false,
+                offchain_indexing: false,
+                checkpoint_export: None,
+                database_backup: None,
+                state_snapshot: None,
+                database_compaction_interval: None,
+                inherent_data_providers: Vec::new(),
+                report_equivocations: false,
+                authoring_slot_proportion: 2.0 / 3.0,
+                max_authored_block_transactions_size: 4 * 1024 * 1024,
+                max_pool_transactions: 8192,
+                max_pool_transactions_per_submitter: 512,
+                sync_limits: Default::default(),
+            },
+            relay_chain: None,
+            libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
+            listen_addresses: Vec::new(),
+            max_outbound_connections_per_subnet: None,
+            max_inbound_connections_per_ip: None,
+            inbound_connections_ip_allowlist: Vec::new(),
+            socks5_proxy: None,
+            nat_port_mapping: false,
+            max_upload_bytes_per_sec: None,
+            max_download_bytes_per_sec: None,
+            max_file_descriptors: None,
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            progress_callback: Arc::new(|_| {}),
+            jaeger_agent: None,
+            jaeger_sampling: Default::default(),
+            runtime_execution_threads: None,
+            max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+            genesis_build_threads: None,
+            quarantine_corrupted_database: false,
+            compiled_runtimes_cache_path: None,
+            block_export: None,
+            block_import_hook: None,
+            randomness_seed: None,
+        })
+        .await
+        .unwrap();
+
+        let mut socket = smol::net::TcpStream::connect(client.json_rpc_server_addr().unwrap())
+            .await
+            .unwrap();
+        socket
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\"isSyncing\""));
+        assert!(response.contains("\"genesisMismatches\":0"));
+
+        // The request line is split between multiple TCP packets.
+        let mut socket = smol::net::TcpStream::connect(client.json_rpc_server_addr().unwrap())
+            .await
+            .unwrap();
+        socket.write_all(b"GET /hea").await.unwrap();
+        socket.flush().await.unwrap();
+        smol::Timer::after(Duration::from_millis(200)).await;
+        socket
+            .write_all(b"lth HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    });
+}
+
+#[test]
+fn websocket_handshake_rejected_if_origin_not_allowed() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                json_rpc_listen: Some(smoldot_full_node::JsonRpcListenConfig {
+                    address: "127.0.0.1:0".parse().unwrap(),
+                    admin_address: None,
+                    health_address: None,
+                    max_json_rpc_clients: 2,
+                    max_json_rpc_clients_per_ip: 2,
+                    max_requests_per_second_per_client: u32::MAX,
+                    max_subscriptions_per_client: 1,
+                    max_pending_notifications_per_client: NonZeroUsize::new(1).unwrap(),
+                    slow_subscriber_policy: smoldot_full_node::SlowSubscriberPolicy::Disconnect,
+                    multiplexed_subscriptions_buffer: None,
+                    tls: None,
+                    allowed_origins: Some(vec!["https://allowed.example".to_owned()]),
+                    transactions_ban_duration: Duration::from_secs(30 * 60),
+                }),
+                ..common::chain_config()
+            },
+            ..common::config()
+        })
+        .await
+        .unwrap();
+
+        let handshake = |origin: &str| {
+            format!(
+                "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                 Sec-WebSocket-Version: 13\r\nOrigin: {origin}\r\n\r\n"
+            )
+        };
+
+        // The connection is closed after the handshake has been rejected.
+        let mut socket = smol::net::TcpStream::connect(client.json_rpc_server_addr().unwrap())
+            .await
+            .unwrap();
+        socket
+            .write_all(handshake("https://disallowed.example").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+        // The connection stays open after the handshake has been accepted, and the response is
+        // thus read until the end of its headers.
+        let mut socket = smol::net::TcpStream::connect(client.json_rpc_server_addr().unwrap())
+            .await
+            .unwrap();
+        socket
+            .write_all(handshake("https://allowed.example").as_bytes())
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            socket.read_exact(&mut byte).await.unwrap();
+            response.push(byte[0]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+    });
+}
@@ -16,25 +16,74 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use smoldot::json_rpc;
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
 async fn start_client() -> smoldot_full_node::Client {
     smoldot_full_node::start(smoldot_full_node::Config {
         chain: smoldot_full_node::ChainConfig {
             chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
             additional_bootnodes: Vec::new(),
+            bootnodes_providers: Vec::new(),
             keystore_memory: vec![],
             sqlite_database_path: None,
             sqlite_cache_size: 256 * 1024 * 1024,
+            sqlite_options: Default::default(),
             keystore_path: None,
             json_rpc_listen: None,
+            finalized_chain_only: false,
+            block_execution_profiling: false,
+            sync_mode: smoldot_full_node::SyncMode::Warp,
+            cross_check_warp_sync: false,
+            max_reorg_depth: None,
+            slot_drift_tolerance: Duration::from_secs(30),
+            max_slot_lenience: Duration::new(0, 0),
+            pruning: smoldot_full_node::Pruning::Archive,
+            peer_rotation_interval: None,
+            bootstrap_fallback_delay: None,
+            fallback_bootnodes: Vec::new(),
+            max_out_peers: 15,
+            min_out_peers: 4,
+            max_in_peers: 25,
+            legacy_protocol_names: false,
+            grandpa_voter: false,
+            offchain_worker: false,
+            offchain_indexing: false,
+            checkpoint_export: None,
+            database_backup: None,
+            state_snapshot: None,
+            database_compaction_interval: None,
+            inherent_data_providers: Vec::new(),
+            report_equivocations: false,
+            authoring_slot_proportion: 2.0 / 3.0,
+            max_authored_block_transactions_size: 4 * 1024 * 1024,
+            max_pool_transactions: 8192,
+            max_pool_transactions_per_submitter: 512,
+            sync_limits: Default::default(),
         },
         relay_chain: None,
-        libp2p_key: Box::new([0; 32]),
+        libp2p_key: smoldot_full_node::Libp2pKey::Memory(Box::new([0; 32])),
         listen_addresses: Vec::new(),
+        max_outbound_connections_per_subnet: None,
+        max_inbound_connections_per_ip: None,
+        inbound_connections_ip_allowlist: Vec::new(),
+        socks5_proxy: None,
+        nat_port_mapping: false,
+        max_upload_bytes_per_sec: None,
+        max_download_bytes_per_sec: None,
+        max_file_descriptors: None,
         tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
         log_callback: Arc::new(move |_, _| {}),
+        progress_callback: Arc::new(|_| {}),
         jaeger_agent: None,
+        jaeger_sampling: Default::default(),
+        runtime_execution_threads: None,
+        max_json_rpc_runtime_calls: NonZeroUsize::new(4).unwrap(),
+        genesis_build_threads: None,
+        quarantine_corrupted_database: false,
+        compiled_runtimes_cache_path: None,
+        block_export: None,
+        block_import_hook: None,
+        randomness_seed: None,
     })
     .await
     .unwrap()
@@ -457,6 +506,75 @@ fn state_get_keys_paged_unknown_block() {
     });
 }
 
+#[test]
+fn state_query_storage_genesis() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_queryStorage","params":[["0xf0c365c3cf59d671eb72da0e7a4113c44e7b9012096b41c4eb3aaf947f6ea429", "0xdead"], "0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let decoded =
+            serde_json::from_str::<Vec<json_rpc::methods::StorageChangeSet>>(result_json).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(
+            decoded[0].block.0,
+            [
+                107, 243, 13, 4, 73, 92, 22, 239, 5, 61, 228, 172, 116, 234, 195, 93, 253, 100,
+                115, 228, 144, 120, 16, 244, 80, 190, 161, 185, 118, 172, 81, 143
+            ]
+        );
+        assert_eq!(decoded[0].changes.len(), 2);
+        assert_eq!(decoded[0].changes[0].1.as_ref().unwrap().0, vec![0, 0]);
+        assert!(decoded[0].changes[1].1.is_none());
+    });
+}
+
+#[test]
+fn state_query_storage_unknown_block() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_queryStorage","params":[["0x"], "0x0000000000000000000000000000000000000000000000000000000000000000"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::Error {
+                error_code: -32602, // Invalid parameter error code.
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn state_query_storage_too_many_keys() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        let keys = vec!["\"0x\""; 1025].join(",");
+        client.send_json_rpc_request(format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"state_queryStorage","params":[[{keys}], "0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f"]}}"#
+        ));
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::Error {
+                error_code: -32602, // Invalid parameter error code.
+                ..
+            }
+        ));
+    });
+}
+
 #[test]
 fn system_chain() {
     smol::block_on(async move {
@@ -540,6 +658,67 @@ fn system_local_peer_id() {
     });
 }
 
+#[test]
+fn system_add_reserved_peer_invalid_address() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // The address doesn't end with `/p2p/...`.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_addReservedPeer","params":["/ip4/127.0.0.1/tcp/30333"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::Error {
+                error_code: -32602, // Invalid parameter error code.
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn system_add_and_remove_reserved_peer() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_addReservedPeer","params":["/ip4/127.0.0.1/tcp/1/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .is_some());
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":2,"method":"system_removeReservedPeer","params":["12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .is_some());
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":3,"method":"system_removeReservedPeer","params":["foo"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::Error {
+                error_code: -32602, // Invalid parameter error code.
+                ..
+            }
+        ));
+    });
+}
+
 #[test]
 fn system_name() {
     smol::block_on(async move {
@@ -561,6 +740,25 @@ fn system_name() {
     });
 }
 
+#[test]
+fn system_peers_empty() {
+    smol::block_on(async move {
+        let client = start_client().await;
+        assert!(client.peers().await.is_empty());
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_peers","params":[]}"#.to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "[]");
+    });
+}
+
 #[test]
 fn system_properties() {
     smol::block_on(async move {
@@ -597,6 +795,239 @@ fn system_version() {
     });
 }
 
+#[test]
+fn sudo_unstable_block_execution_profile_disabled() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // Profiling is disabled in the configuration, and the block is unknown anyway.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"sudo_unstable_blockExecutionProfile","params":["0x1111111111111111111111111111111111111111111111111111111111111111"]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "null");
+        assert!(client.block_execution_profile([0x11; 32]).await.is_none());
+    });
+}
+
+#[test]
+fn sudo_unstable_database_statistics() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"sudo_unstable_databaseStatistics","params":[]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let result = serde_json::from_str::<serde_json::Value>(result_json).unwrap();
+        assert_eq!(result["finalizedBlockNumber"], 0);
+        assert_eq!(result["oldestBlockWithState"], 0);
+        assert_eq!(result["numJustifications"], 0);
+        assert!(result["tables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|table| table["name"] == "blocks" && table["numRows"] == 1));
+    });
+}
+
+#[test]
+fn sudo_unstable_set_authoring_enabled() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"sudo_unstable_setAuthoringEnabled","params":[false]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "null");
+
+        // The node keeps answering requests while the authoring is paused.
+        client.set_authoring_enabled(true).await;
+        assert!(client.parachain_inclusion().await.is_none());
+    });
+}
+
+#[test]
+fn sudo_unstable_storage_diff_genesis() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainSpec_v1_genesisHash","params":[]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let genesis_hash =
+            serde_json::from_str::<json_rpc::methods::HashHexString>(result_json).unwrap();
+
+        // A block has no difference with itself.
+        assert!(client
+            .storage_diff(genesis_hash.0, genesis_hash.0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            client.storage_diff(genesis_hash.0, [0x11; 32]).await,
+            Err(smoldot_full_node::StorageAccessError::UnknownBlock)
+        ));
+
+        client.send_json_rpc_request(
+            format!(
+                r#"{{"jsonrpc":"2.0","id":2,"method":"sudo_unstable_storageDiff","params":["0x{}","0x{}"]}}"#,
+                hex::encode(genesis_hash.0),
+                hex::encode(genesis_hash.0)
+            ),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "[]");
+    });
+}
+
+#[test]
+fn state_trace_block_genesis_and_unknown() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainSpec_v1_genesisHash","params":[]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let genesis_hash =
+            serde_json::from_str::<json_rpc::methods::HashHexString>(result_json).unwrap();
+
+        // The genesis block has no parent and can thus not be re-executed.
+        assert!(matches!(
+            client.trace_block(genesis_hash.0).await,
+            Err(smoldot_full_node::TraceBlockError::GenesisBlock)
+        ));
+        assert!(matches!(
+            client.trace_block([0x11; 32]).await,
+            Err(smoldot_full_node::TraceBlockError::UnknownBlock)
+        ));
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":2,"method":"state_traceBlock","params":["0x1111111111111111111111111111111111111111111111111111111111111111"]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .is_none());
+    });
+}
+
+#[test]
+fn sudo_unstable_block_authorities_genesis() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainSpec_v1_genesisHash","params":[]}"#
+                .to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let genesis_hash =
+            serde_json::from_str::<json_rpc::methods::HashHexString>(result_json).unwrap();
+
+        // The chain uses Aura and GrandPa.
+        let authorities = client.block_authorities(genesis_hash.0).await.unwrap();
+        assert_eq!(authorities.block_number, 0);
+        let (set_id, grandpa_authorities) = authorities.grandpa.unwrap();
+        assert_eq!(set_id, 0);
+        assert!(!grandpa_authorities.is_empty());
+        assert!(authorities.babe.is_none());
+
+        assert!(matches!(
+            client.block_authorities([0x11; 32]).await,
+            Err(smoldot_full_node::BlockAuthoritiesError::UnknownBlock)
+        ));
+    });
+}
+
+#[test]
+fn runtime_version_updates_starts_with_current() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        let mut updates = client.runtime_version_updates();
+        let runtime_version = smol::stream::StreamExt::next(&mut updates).await.unwrap();
+        let decoded = runtime_version.decode();
+        assert_eq!(decoded.impl_name, "node-template");
+        assert_eq!(decoded.spec_version, 100);
+    });
+}
+
+#[test]
+fn system_account_next_index() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // Alice hasn't sent any transaction at genesis.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_accountNextIndex","params":["5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(serde_json::from_str::<u64>(result_json).unwrap(), 0);
+
+        let alice = hex::decode("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d")
+            .unwrap();
+        assert_eq!(client.account_next_index(&alice).await.unwrap(), 0);
+    });
+}
+
+#[test]
+fn parachain_inclusion_none_without_relay_chain() {
+    smol::block_on(async move {
+        let client = start_client().await;
+        assert!(client.parachain_inclusion().await.is_none());
+    });
+}
+
 // TODO: add tests for `chain_subscribeAllHeads`
 // TODO: add tests for `chain_subscribeFinalizedHeads`
 // TODO: add tests for `chain_subscribeNewHeads`
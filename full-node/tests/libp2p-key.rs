@@ -0,0 +1,62 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+async fn start_client(libp2p_key_path: PathBuf) -> smoldot_full_node::Client {
+    smoldot_full_node::start(smoldot_full_node::Config {
+        libp2p_key: smoldot_full_node::Libp2pKey::File(libp2p_key_path),
+        ..common::config()
+    })
+    .await
+    .unwrap()
+}
+
+#[test]
+fn libp2p_key_file_persisted() {
+    smol::block_on(async move {
+        let directory = std::env::temp_dir().join(format!(
+            "smoldot-full-node-test-libp2p-key-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let key_path = directory.join("libp2p_ed25519_secret_key.secret");
+
+        // The key file doesn't exist yet and is generated.
+        let client = start_client(key_path.clone()).await;
+        let peer_id = client.local_peer_id().clone();
+        drop(client);
+
+        assert_eq!(fs::read_to_string(&key_path).unwrap().len(), 64);
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(
+                &fs::metadata(&key_path).unwrap().permissions()
+            ) & 0o777,
+            0o600
+        );
+
+        // The same key is loaded when restarting.
+        let client = start_client(key_path.clone()).await;
+        assert_eq!(*client.local_peer_id(), peer_id);
+        drop(client);
+
+        let _ = fs::remove_dir_all(&directory);
+    });
+}
@@ -0,0 +1,592 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Claiming of Babe slots.
+//!
+//! Contrary to Aura, whether a Babe slot can be claimed by an authority depends on the output
+//! of a VRF (Verifiable Random Function) computed with the private key of that authority. See
+//! the [`crate::verify::babe`] module for an overview of Babe.
+//!
+//! Claiming a slot is done in two steps:
+//!
+//! - Call [`next_slot`] in order to determine the next slot and which of the local authorities
+//!   are part of the epoch this slot belongs to.
+//! - Generate, for each of the authorities returned by [`SlotCandidate::local_authorities`], a
+//!   VRF output and proof from the transcript described by [`SlotCandidate::vrf_transcript`],
+//!   then pass them to [`SlotCandidate::claim`].
+//!
+//! The VRF is computed outside of this module, as the private keys are typically held by a
+//! keystore.
+
+use crate::{
+    chain::chain_information,
+    header,
+    verify::babe::{calculate_primary_threshold, secondary_slot_author, vrf_transcript},
+};
+
+use alloc::vec::Vec;
+use core::{num::NonZeroU64, time::Duration};
+
+/// Configuration for [`next_slot`].
+pub struct Config<'a, TLocAuth> {
+    /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+    /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+    pub now_from_unix_epoch: Duration,
+
+    /// Duration, in milliseconds, of a Babe slot.
+    pub slot_duration: NonZeroU64,
+
+    /// Number of slots per epoch in the Babe configuration.
+    pub slots_per_epoch: NonZeroU64,
+
+    /// Slot number of the block to build upon, or `None` if this block is the genesis block.
+    pub parent_slot_number: Option<u64>,
+
+    /// Epoch the block to build upon belongs to. Must be `None` if and only if this block is the
+    /// genesis block. See [`crate::verify::babe::VerifyConfig::parent_block_epoch`].
+    pub parent_block_epoch: Option<chain_information::BabeEpochInformationRef<'a>>,
+
+    /// Epoch that follows the epoch the block to build upon belongs to. See
+    /// [`crate::verify::babe::VerifyConfig::parent_block_next_epoch`].
+    pub parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+    /// Iterator to the list of Sr25519 public keys available locally.
+    ///
+    /// Must implement `Iterator<Item = &[u8; 32]>`.
+    pub local_authorities: TLocAuth,
+}
+
+/// Determines the next slot that a child of the given block could be authored on, and which of
+/// the authorities in [`Config::local_authorities`] might be allowed to claim it.
+///
+/// The slot is the one happening now, or the one following the slot of the parent block if the
+/// slot happening now isn't strictly superior to it.
+///
+/// Returns `None` if none of the local authorities belong to the epoch of that slot.
+pub fn next_slot<'a>(
+    config: Config<'a, impl Iterator<Item = &'a [u8; 32]>>,
+) -> Option<SlotCandidate> {
+    // Note that this calculation can overflow in the very distant future. This is considered
+    // acceptable.
+    let current_slot = u64::try_from(
+        config.now_from_unix_epoch.as_millis() / u128::from(config.slot_duration.get()),
+    )
+    .unwrap();
+    let slot_number = match config.parent_slot_number {
+        Some(parent) if parent >= current_slot => parent.checked_add(1)?,
+        _ => current_slot,
+    };
+
+    // Determine the epoch the slot belongs to, the same way as the verification code does.
+    // The block transitions to the next epoch if its slot is past the start of that next epoch,
+    // in which case the runtime adds the epoch change digest item to the header.
+    let epoch = match &config.parent_block_epoch {
+        Some(parent_epoch)
+            if config
+                .parent_block_next_epoch
+                .start_slot_number
+                .is_some_and(|n| n > slot_number) =>
+        {
+            parent_epoch
+        }
+        _ => &config.parent_block_next_epoch,
+    };
+
+    // If no block has been produced for entire epochs, the epoch index must be adjusted.
+    let epoch_index = match epoch.start_slot_number {
+        Some(start) => epoch
+            .epoch_index
+            .checked_add(slot_number.checked_sub(start)? / config.slots_per_epoch)?,
+        None => epoch.epoch_index,
+    };
+
+    let num_authorities = epoch.authorities.len();
+    let authorities_weights_sum = epoch
+        .authorities
+        .clone()
+        .fold(0u64, |sum, a| sum.saturating_add(a.weight));
+
+    let mut local_authorities = Vec::new();
+    for (local_authorities_index, local_pub_key) in config.local_authorities.enumerate() {
+        // TODO: O(n) complexity
+        let Some((authority_index, authority)) = epoch
+            .authorities
+            .clone()
+            .enumerate()
+            .find(|(_, a)| a.public_key == local_pub_key)
+        else {
+            continue;
+        };
+
+        // An authority with a weight of 0 can never claim a primary slot.
+        let primary_threshold = if authority.weight != 0 && authorities_weights_sum != 0 {
+            Some(calculate_primary_threshold(
+                epoch.c,
+                epoch.authorities.clone().map(|a| a.weight),
+                authority.weight,
+            ))
+        } else {
+            None
+        };
+
+        local_authorities.push(LocalAuthority {
+            local_authorities_index,
+            authority_index: u32::try_from(authority_index).ok()?,
+            public_key: *local_pub_key,
+            primary_threshold,
+        });
+    }
+
+    if local_authorities.is_empty() {
+        return None;
+    }
+
+    let slot_start_from_unix_epoch =
+        Duration::from_millis(slot_number.checked_mul(config.slot_duration.get())?);
+    let slot_end_from_unix_epoch =
+        slot_start_from_unix_epoch + Duration::from_millis(config.slot_duration.get());
+
+    Some(SlotCandidate {
+        slot_start_from_unix_epoch,
+        slot_end_from_unix_epoch,
+        slot_number,
+        epoch_index,
+        randomness: *epoch.randomness,
+        allowed_slots: epoch.allowed_slots,
+        secondary_slot_author: secondary_slot_author(
+            epoch.randomness,
+            slot_number,
+            num_authorities,
+        ),
+        local_authorities,
+    })
+}
+
+/// Item of the transcript returned by [`SlotCandidate::vrf_transcript`]: a label and either
+/// bytes or a `u64` to append to the transcript.
+pub type VrfTranscriptItem<'a> = (&'static [u8], either::Either<&'a [u8], u64>);
+
+/// Slot happening now or in the future, and that one of the authorities in
+/// [`Config::local_authorities`] might be allowed to claim.
+///
+/// See also [`next_slot`].
+#[derive(Debug, Clone)]
+pub struct SlotCandidate {
+    slot_start_from_unix_epoch: Duration,
+    slot_end_from_unix_epoch: Duration,
+    slot_number: u64,
+    epoch_index: u64,
+    randomness: [u8; 32],
+    allowed_slots: header::BabeAllowedSlots,
+    /// Index within the list of authorities of the epoch of the authority allowed to claim the
+    /// slot as a secondary slot.
+    secondary_slot_author: Option<u32>,
+    /// Local authorities that belong to the epoch of the slot.
+    local_authorities: Vec<LocalAuthority>,
+}
+
+#[derive(Debug, Clone)]
+struct LocalAuthority {
+    local_authorities_index: usize,
+    authority_index: u32,
+    public_key: [u8; 32],
+    /// Threshold the VRF output must be inferior to in order to claim a primary slot. `None` if
+    /// the authority can't claim primary slots.
+    primary_threshold: Option<u128>,
+}
+
+impl SlotCandidate {
+    /// UNIX time when the slot starts. Can be inferior to the value passed to
+    /// [`Config::now_from_unix_epoch`] if the slot has already started.
+    pub fn slot_start_from_unix_epoch(&self) -> Duration {
+        self.slot_start_from_unix_epoch
+    }
+
+    /// UNIX time when the slot ends.
+    pub fn slot_end_from_unix_epoch(&self) -> Duration {
+        self.slot_end_from_unix_epoch
+    }
+
+    /// Number of the slot.
+    pub fn slot_number(&self) -> u64 {
+        self.slot_number
+    }
+
+    /// Index of the epoch the slot belongs to.
+    pub fn epoch_index(&self) -> u64 {
+        self.epoch_index
+    }
+
+    /// Returns the indices within [`Config::local_authorities`] of the authorities that belong
+    /// to the epoch of this slot, and for which a VRF output and proof should be generated.
+    pub fn local_authorities(&self) -> impl ExactSizeIterator<Item = usize> + '_ {
+        self.local_authorities
+            .iter()
+            .map(|a| a.local_authorities_index)
+    }
+
+    /// Returns the label and the items of the transcript that the VRF output and proof must be
+    /// generated from. The items are either bytes or a `u64`, and must be appended to the
+    /// transcript in order.
+    pub fn vrf_transcript(
+        &self,
+    ) -> (
+        &'static [u8],
+        impl Iterator<Item = VrfTranscriptItem<'_>> + '_,
+    ) {
+        (
+            b"BABE",
+            [
+                (&b"slot number"[..], either::Right(self.slot_number)),
+                (&b"current epoch"[..], either::Right(self.epoch_index)),
+                (&b"chain randomness"[..], either::Left(&self.randomness[..])),
+            ]
+            .into_iter(),
+        )
+    }
+
+    /// Tries to claim the slot using the given VRF outputs and proofs.
+    ///
+    /// `vrf_outputs` must yield, for some or all of the values returned by
+    /// [`SlotCandidate::local_authorities`], the VRF output and proof generated by the
+    /// corresponding authority. Authorities that are missing, for example because the keystore
+    /// failed to generate the VRF, are ignored.
+    ///
+    /// Primary slot claims are preferred over secondary slot claims. Returns `None` if none of
+    /// the local authorities are allowed to claim the slot.
+    pub fn claim(
+        &self,
+        vrf_outputs: impl Iterator<Item = (usize, [u8; 32], [u8; 64])>,
+    ) -> Option<SlotClaim> {
+        let mut secondary_claim = None;
+
+        for (local_authorities_index, vrf_output, vrf_proof) in vrf_outputs {
+            let Some(authority) = self
+                .local_authorities
+                .iter()
+                .find(|a| a.local_authorities_index == local_authorities_index)
+            else {
+                continue;
+            };
+
+            // Verifying the VRF proof is necessary in order to obtain the value to compare
+            // with the threshold, and additionally protects against a misbehaving VRF generator.
+            let Some(vrf_value) = self.vrf_value(&authority.public_key, &vrf_output, &vrf_proof)
+            else {
+                continue;
+            };
+
+            if authority
+                .primary_threshold
+                .is_some_and(|threshold| vrf_value < threshold)
+            {
+                return Some(self.build_claim(
+                    authority,
+                    header::BabePreDigest::Primary(header::BabePrimaryPreDigest {
+                        authority_index: authority.authority_index,
+                        slot_number: self.slot_number,
+                        vrf_output,
+                        vrf_proof,
+                    }),
+                ));
+            }
+
+            if self.secondary_slot_author == Some(authority.authority_index)
+                && matches!(
+                    self.allowed_slots,
+                    header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
+                )
+            {
+                secondary_claim = Some(self.build_claim(
+                    authority,
+                    header::BabePreDigest::SecondaryVRF(header::BabeSecondaryVRFPreDigest {
+                        authority_index: authority.authority_index,
+                        slot_number: self.slot_number,
+                        vrf_output,
+                        vrf_proof,
+                    }),
+                ));
+            }
+        }
+
+        if secondary_claim.is_some() {
+            return secondary_claim;
+        }
+
+        // Secondary plain slots don't require any VRF output.
+        if matches!(
+            self.allowed_slots,
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots
+        ) {
+            if let Some(authority) = self
+                .local_authorities
+                .iter()
+                .find(|a| Some(a.authority_index) == self.secondary_slot_author)
+            {
+                return Some(self.build_claim(
+                    authority,
+                    header::BabePreDigest::SecondaryPlain(header::BabeSecondaryPlainPreDigest {
+                        authority_index: authority.authority_index,
+                        slot_number: self.slot_number,
+                    }),
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Verifies the given VRF output and proof and returns the value to compare with the primary
+    /// slot threshold, or `None` if the proof is invalid.
+    fn vrf_value(
+        &self,
+        public_key: &[u8; 32],
+        vrf_output: &[u8; 32],
+        vrf_proof: &[u8; 64],
+    ) -> Option<u128> {
+        let public_key = schnorrkel::PublicKey::from_bytes(public_key).ok()?;
+        let vrf_output = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf_output[..]).ok()?;
+        let vrf_proof = schnorrkel::vrf::VRFProof::from_bytes(&vrf_proof[..]).ok()?;
+        let transcript = vrf_transcript(self.slot_number, self.epoch_index, &self.randomness);
+        let (vrf_in_out, _) = public_key
+            .vrf_verify(transcript, &vrf_output, &vrf_proof)
+            .ok()?;
+        Some(u128::from_le_bytes(
+            vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"),
+        ))
+    }
+
+    fn build_claim(
+        &self,
+        authority: &LocalAuthority,
+        pre_digest: header::BabePreDigest,
+    ) -> SlotClaim {
+        SlotClaim {
+            slot_start_from_unix_epoch: self.slot_start_from_unix_epoch,
+            slot_end_from_unix_epoch: self.slot_end_from_unix_epoch,
+            slot_number: self.slot_number,
+            local_authorities_index: authority.local_authorities_index,
+            pre_digest,
+        }
+    }
+}
+
+/// Slot happening now or in the future and that has been claimed by one of the authorities in
+/// [`Config::local_authorities`].
+///
+/// See also [`SlotCandidate::claim`].
+#[derive(Debug, Clone)]
+pub struct SlotClaim {
+    /// UNIX time when the slot starts. Can be inferior to the value passed to
+    /// [`Config::now_from_unix_epoch`] if the slot has already started.
+    pub slot_start_from_unix_epoch: Duration,
+    /// UNIX time when the slot ends.
+    pub slot_end_from_unix_epoch: Duration,
+    /// Slot number of the claim.
+    pub slot_number: u64,
+    /// Index within [`Config::local_authorities`] of the authority that can produce the block.
+    pub local_authorities_index: usize,
+    /// Pre-runtime digest item to put in the header of the block. Indicates whether the slot
+    /// is a primary or secondary slot claim.
+    pub pre_digest: header::BabePreDigest,
+}
+
+impl SlotClaim {
+    /// Returns `true` if the slot has been claimed as a primary slot.
+    pub fn is_primary(&self) -> bool {
+        matches!(self.pre_digest, header::BabePreDigest::Primary(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{chain::chain_information, header};
+    use core::{iter, num::NonZeroU64, time::Duration};
+
+    fn keypair(seed: u8) -> schnorrkel::Keypair {
+        schnorrkel::MiniSecretKey::from_bytes(&[seed; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+    }
+
+    fn vrf_sign(
+        candidate: &super::SlotCandidate,
+        keypair: &schnorrkel::Keypair,
+    ) -> ([u8; 32], [u8; 64]) {
+        let (label, items) = candidate.vrf_transcript();
+        let mut transcript = merlin::Transcript::new(label);
+        for (label, item) in items {
+            match item {
+                either::Left(bytes) => transcript.append_message(label, bytes),
+                either::Right(value) => transcript.append_u64(label, value),
+            }
+        }
+        let (in_out, proof, _) = keypair.vrf_sign(transcript);
+        (in_out.to_preout().to_bytes(), proof.to_bytes())
+    }
+
+    fn epoch(
+        authorities: &[header::BabeAuthority],
+        c: (u64, u64),
+        allowed_slots: header::BabeAllowedSlots,
+    ) -> chain_information::BabeEpochInformationRef<'_> {
+        chain_information::BabeEpochInformationRef {
+            epoch_index: 0,
+            start_slot_number: None,
+            authorities: header::BabeAuthoritiesIter::from_slice(authorities),
+            randomness: &[7; 32],
+            c,
+            allowed_slots,
+        }
+    }
+
+    #[test]
+    fn primary_claim_verifies() {
+        let keypair = keypair(1);
+        let authorities = [header::BabeAuthority {
+            public_key: keypair.public.to_bytes(),
+            weight: 1,
+        }];
+
+        // With `c` very close to 1, nearly all slots are primary slots.
+        let candidate = super::next_slot(super::Config {
+            now_from_unix_epoch: Duration::from_secs(60),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            slots_per_epoch: NonZeroU64::new(10).unwrap(),
+            parent_slot_number: None,
+            parent_block_epoch: None,
+            parent_block_next_epoch: epoch(
+                &authorities,
+                (999_999, 1_000_000),
+                header::BabeAllowedSlots::PrimarySlots,
+            ),
+            local_authorities: iter::once(&authorities[0].public_key),
+        })
+        .unwrap();
+        assert_eq!(candidate.slot_number(), 10);
+        assert_eq!(candidate.local_authorities().collect::<Vec<_>>(), vec![0]);
+
+        let (output, proof) = vrf_sign(&candidate, &keypair);
+        let claim = candidate.claim(iter::once((0, output, proof))).unwrap();
+        assert!(claim.is_primary());
+        assert_eq!(claim.slot_start_from_unix_epoch, Duration::from_secs(60));
+        assert_eq!(claim.slot_end_from_unix_epoch, Duration::from_secs(66));
+
+        // A bad proof is ignored.
+        assert!(candidate.claim(iter::once((0, output, [0; 64]))).is_none());
+    }
+
+    #[test]
+    fn secondary_plain_claim() {
+        let keypair = keypair(2);
+        let authorities = [header::BabeAuthority {
+            public_key: keypair.public.to_bytes(),
+            weight: 1,
+        }];
+
+        // With `c` equal to 0, no primary slot can be claimed, but the only authority is always
+        // the secondary slot author.
+        let candidate = super::next_slot(super::Config {
+            now_from_unix_epoch: Duration::from_secs(60),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            slots_per_epoch: NonZeroU64::new(10).unwrap(),
+            parent_slot_number: Some(12),
+            parent_block_epoch: Some(chain_information::BabeEpochInformationRef {
+                start_slot_number: Some(5),
+                ..epoch(
+                    &authorities,
+                    (0, 1),
+                    header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+                )
+            }),
+            parent_block_next_epoch: chain_information::BabeEpochInformationRef {
+                epoch_index: 1,
+                start_slot_number: Some(15),
+                ..epoch(
+                    &authorities,
+                    (0, 1),
+                    header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+                )
+            },
+            local_authorities: iter::once(&authorities[0].public_key),
+        })
+        .unwrap();
+
+        // The slot must be strictly superior to the one of the parent.
+        assert_eq!(candidate.slot_number(), 13);
+        assert_eq!(candidate.epoch_index(), 0);
+
+        let claim = candidate.claim(iter::empty()).unwrap();
+        assert!(matches!(
+            claim.pre_digest,
+            header::BabePreDigest::SecondaryPlain(header::BabeSecondaryPlainPreDigest {
+                authority_index: 0,
+                slot_number: 13,
+            })
+        ));
+    }
+
+    #[test]
+    fn primary_only_no_claim() {
+        let keypair = keypair(3);
+        let authorities = [header::BabeAuthority {
+            public_key: keypair.public.to_bytes(),
+            weight: 1,
+        }];
+
+        let candidate = super::next_slot(super::Config {
+            now_from_unix_epoch: Duration::from_secs(60),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            slots_per_epoch: NonZeroU64::new(10).unwrap(),
+            parent_slot_number: None,
+            parent_block_epoch: None,
+            parent_block_next_epoch: epoch(
+                &authorities,
+                (0, 1),
+                header::BabeAllowedSlots::PrimarySlots,
+            ),
+            local_authorities: iter::once(&authorities[0].public_key),
+        })
+        .unwrap();
+
+        let (output, proof) = vrf_sign(&candidate, &keypair);
+        assert!(candidate.claim(iter::once((0, output, proof))).is_none());
+    }
+
+    #[test]
+    fn unknown_local_authority() {
+        let authorities = [header::BabeAuthority {
+            public_key: keypair(4).public.to_bytes(),
+            weight: 1,
+        }];
+
+        assert!(super::next_slot(super::Config {
+            now_from_unix_epoch: Duration::from_secs(60),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            slots_per_epoch: NonZeroU64::new(10).unwrap(),
+            parent_slot_number: None,
+            parent_block_epoch: None,
+            parent_block_next_epoch: epoch(
+                &authorities,
+                (1, 4),
+                header::BabeAllowedSlots::PrimarySlots,
+            ),
+            local_authorities: iter::once(&keypair(5).public.to_bytes()),
+        })
+        .is_none());
+    }
+}
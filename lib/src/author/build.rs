@@ -18,7 +18,7 @@
 // TODO: docs
 
 use crate::{
-    author::{aura, runtime},
+    author::{aura, babe, runtime},
     executor::host,
     header,
     verify::inherents,
@@ -56,7 +56,20 @@ pub enum ConfigConsensus<'a, TLocAuth> {
         /// Must implement `Iterator<Item = &[u8; 32]>`.
         local_authorities: TLocAuth,
     },
-    // TODO: Babe isn't supported yet
+
+    /// Chain is using the Babe consensus algorithm.
+    ///
+    /// Contrary to Aura, the slot must have been claimed beforehand, as claiming a Babe slot
+    /// requires generating a VRF output with the private key of the authority. See the
+    /// [`babe`] module.
+    Babe {
+        /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+        /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+        now_from_unix_epoch: Duration,
+
+        /// Slot that has been claimed by one of the local authorities.
+        slot_claim: babe::SlotClaim,
+    },
 }
 
 /// Current state of the block building process.
@@ -101,6 +114,17 @@ impl Builder {
 
                 (WaitSlotConsensus::Aura(consensus), ready)
             }
+            ConfigConsensus::Babe {
+                now_from_unix_epoch,
+                slot_claim,
+            } => {
+                if now_from_unix_epoch >= slot_claim.slot_end_from_unix_epoch {
+                    return Builder::Idle;
+                }
+
+                let ready = now_from_unix_epoch >= slot_claim.slot_start_from_unix_epoch;
+                (WaitSlotConsensus::Babe(slot_claim), ready)
+            }
         };
 
         if ready {
@@ -169,6 +193,7 @@ pub struct WaitSlot {
 #[derive(Debug)]
 enum WaitSlotConsensus {
     Aura(aura::SlotClaim),
+    Babe(babe::SlotClaim),
 }
 
 impl WaitSlot {
@@ -176,8 +201,9 @@ impl WaitSlot {
     /// the UNIX epoch, ignoring leap seconds).
     pub fn when(&self) -> Duration {
         // TODO: we can actually start building the block before our slot in some situations?
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -202,8 +228,9 @@ impl AuthoringStart {
     /// Returns when the authoring slot start, as a UNIX timestamp (i.e. number of seconds since
     /// the UNIX epoch, ignoring leap seconds).
     pub fn slot_start_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -215,8 +242,9 @@ impl AuthoringStart {
     /// authored **and** propagated throughout the entire peer-to-peer network before the slot
     /// ends.
     pub fn slot_end_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_end_from_unix_epoch,
+            WaitSlotConsensus::Babe(claim) => claim.slot_end_from_unix_epoch,
         }
     }
 
@@ -228,12 +256,15 @@ impl AuthoringStart {
             parent_number: config.parent_number,
             parent_runtime: config.parent_runtime,
             block_body_capacity: config.block_body_capacity,
-            consensus_digest_log_item: match self.consensus {
+            consensus_digest_log_item: match &self.consensus {
                 WaitSlotConsensus::Aura(slot) => {
                     runtime::ConfigPreRuntime::Aura(header::AuraPreDigest {
                         slot_number: slot.slot_number,
                     })
                 }
+                WaitSlotConsensus::Babe(slot) => {
+                    runtime::ConfigPreRuntime::Babe((&slot.pre_digest).into())
+                }
             },
             max_log_level: config.max_log_level,
             calculate_trie_changes: config.calculate_trie_changes,
@@ -242,6 +273,11 @@ impl AuthoringStart {
         let inherent_data = inherents::InherentData {
             timestamp: u64::try_from(config.now_from_unix_epoch.as_millis()).unwrap_or(u64::MAX),
         };
+        let inherent_data = inherent_data
+            .into_raw_list()
+            .map(|(id, value)| (id, value.as_ref().to_vec()))
+            .chain(config.additional_inherents)
+            .collect::<Vec<_>>();
 
         (Shared {
             inherent_data: Some(inherent_data),
@@ -271,6 +307,14 @@ pub struct AuthoringStartConfig<'a> {
     /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
     pub now_from_unix_epoch: Duration,
 
+    /// Identifiers and SCALE-encoded values of the inherents to pass to the runtime in addition
+    /// to the ones of [`inherents::InherentData`], for example parachain-related inherents or
+    /// the inherents of custom pallets.
+    ///
+    /// If the same identifier is found multiple times, the way the runtime handles it is
+    /// unspecified.
+    pub additional_inherents: Vec<([u8; 8], Vec<u8>)>,
+
     /// Runtime used to check the new block. Must be built using the Wasm code found at the
     /// `:code` key of the parent block storage.
     pub parent_runtime: host::HostVmPrototype,
@@ -462,10 +506,12 @@ impl Seal {
     /// Returns the index within the list of authorities of the authority that must sign the
     /// block.
     ///
-    /// See [`ConfigConsensus::Aura::local_authorities`].
+    /// See [`ConfigConsensus::Aura::local_authorities`] and
+    /// [`babe::Config::local_authorities`].
     pub fn authority_index(&self) -> usize {
-        match self.shared.slot_claim {
+        match &self.shared.slot_claim {
             WaitSlotConsensus::Aura(slot) => slot.local_authorities_index,
+            WaitSlotConsensus::Babe(slot) => slot.local_authorities_index,
         }
     }
 
@@ -483,7 +529,10 @@ impl Seal {
         self.block.scale_encoded_header = header
             .scale_encoding_with_extra_digest_item(
                 self.shared.block_number_bytes,
-                header::DigestItemRef::AuraSeal(&signature),
+                match self.shared.slot_claim {
+                    WaitSlotConsensus::Aura(_) => header::DigestItemRef::AuraSeal(&signature),
+                    WaitSlotConsensus::Babe(_) => header::DigestItemRef::BabeSeal(&signature),
+                },
             )
             .fold(Vec::with_capacity(8192), |mut a, b| {
                 a.extend_from_slice(b.as_ref());
@@ -508,9 +557,9 @@ pub enum Error {
 /// Extra information maintained in all variants of the [`Builder`].
 #[derive(Debug)]
 struct Shared {
-    /// Inherent data waiting to be injected. Will be extracted from its `Option` when the inner
-    /// block builder requests it.
-    inherent_data: Option<inherents::InherentData>,
+    /// Inherent data waiting to be injected, as a list of identifiers and values. Will be
+    /// extracted from its `Option` when the inner block builder requests it.
+    inherent_data: Option<Vec<([u8; 8], Vec<u8>)>>,
 
     /// Number of bytes used to encode the block number in the header.
     block_number_bytes: usize,
@@ -563,7 +612,8 @@ impl Shared {
                 }
                 runtime::BlockBuild::InherentExtrinsics(a) => {
                     // Injecting the inherent is guaranteed to be done only once per block.
-                    inner = a.inject_inherents(self.inherent_data.take().unwrap());
+                    inner =
+                        a.inject_raw_inherents_list(self.inherent_data.take().unwrap().into_iter());
                 }
                 runtime::BlockBuild::ApplyExtrinsic(a) => {
                     inner = a.finish();
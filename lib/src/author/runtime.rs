@@ -290,6 +290,14 @@ impl BlockBuild {
                 (Inner::Runtime(runtime_call::RuntimeCall::OffchainStorageSet(inner)), _) => {
                     return BlockBuild::OffchainStorageSet(OffchainStorageSet(inner, shared))
                 }
+                (Inner::Runtime(runtime_call::RuntimeCall::SignatureVerification(sig)), _) => {
+                    // Signatures are verified when applying the transactions.
+                    inner = Inner::Runtime(sig.verify_and_resume());
+                }
+                (Inner::Runtime(runtime_call::RuntimeCall::LogEmit(log)), _) => {
+                    // Logs are ignored.
+                    inner = Inner::Runtime(log.resume());
+                }
 
                 (
                     Inner::Runtime(runtime_call::RuntimeCall::Finished(Ok(success))),
@@ -461,7 +469,6 @@ impl BlockBuild {
                     }));
                 }
 
-                // TODO: what about SignatureVerification and EmitLog? at the time of writing of this comment, it's not worth fixing as this code would get removed by <https://github.com/smol-dot/smoldot/issues/1517>
                 (_, s) => unreachable!("{:?}", s),
             }
         }
@@ -108,6 +108,12 @@ pub struct Config {
     /// However, since a recognized consensus engine must always be present, both `true` and
     /// `false` guarantee that the number of authorable blocks over the network is bounded.
     pub allow_unknown_consensus_engines: bool,
+
+    /// Maximum duration by which the slot of a block is allowed to start in the future. Blocks
+    /// whose slot starts later are considered as invalid.
+    ///
+    /// See [`crate::verify::aura::VerifyConfig::slot_drift_tolerance`].
+    pub slot_drift_tolerance: Duration,
 }
 
 /// Holds state about the current state of the chain for the purpose of verifying headers.
@@ -151,6 +157,8 @@ pub struct NonFinalizedTree<T> {
     block_number_bytes: usize,
     /// See [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// See [`Config::slot_drift_tolerance`].
+    slot_drift_tolerance: Duration,
 }
 
 impl<T> NonFinalizedTree<T> {
@@ -223,6 +231,7 @@ impl<T> NonFinalizedTree<T> {
             blocks_trigger_gp_change: BTreeSet::new(),
             block_number_bytes: config.block_number_bytes,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            slot_drift_tolerance: config.slot_drift_tolerance,
         }
     }
 
@@ -375,6 +384,34 @@ impl<T> NonFinalizedTree<T> {
         }
     }
 
+    /// Returns the number of blocks of the current best chain that aren't ancestors of the given
+    /// block, in other words the number of blocks that would be reverted if a child of the given
+    /// block became the new best block.
+    ///
+    /// Returns `None` if the block is neither the finalized block nor in the
+    /// [`NonFinalizedTree`].
+    pub fn best_block_reorg_depth(&self, hash: &[u8; 32]) -> Option<u64> {
+        let best_block_height = self.best_block_height();
+
+        if *hash == self.finalized_block_hash {
+            return Some(best_block_height - self.finalized_block_number);
+        }
+
+        let node_index = *self.blocks_by_hash.get(hash)?;
+        let Some((_, best_node_index)) = self.blocks_by_best_score.last_key_value() else {
+            // The best block is the finalized block, which is an ancestor of all blocks.
+            return Some(0);
+        };
+
+        let common_ancestor_height = match self.blocks.common_ancestor(node_index, *best_node_index)
+        {
+            Some(ancestor) => self.blocks.get(ancestor).unwrap().number,
+            None => self.finalized_block_number,
+        };
+
+        Some(best_block_height - common_ancestor_height)
+    }
+
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         match (
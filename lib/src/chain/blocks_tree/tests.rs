@@ -169,6 +169,7 @@ fn polkadot_blocks_0_to_2() {
         blocks_capacity: 8,
         block_number_bytes: 4,
         allow_unknown_consensus_engines: false,
+        slot_drift_tolerance: crate::verify::aura::DEFAULT_SLOT_DRIFT_TOLERANCE,
     });
 
     let block1 = vec![
@@ -382,6 +383,7 @@ fn kusama_blocks_0_to_2() {
         blocks_capacity: 8,
         block_number_bytes: 4,
         allow_unknown_consensus_engines: false,
+        slot_drift_tolerance: crate::verify::aura::DEFAULT_SLOT_DRIFT_TOLERANCE,
     });
 
     let block1 = vec![
@@ -440,3 +442,113 @@ fn kusama_blocks_0_to_2() {
 
     tree.insert_verified_header(verified_header2, ());
 }
+
+/// Builds a [`NonFinalizedTree`] using the Aura consensus with a single authority, whose
+/// finalized block is a genesis block.
+fn aura_tree(keypair: &schnorrkel::Keypair) -> NonFinalizedTree<()> {
+    NonFinalizedTree::new(Config {
+        chain_information: chain_information::ChainInformation {
+            finalized_block_header: Box::new(header::Header {
+                parent_hash: [0; 32],
+                number: 0,
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                digest: header::Digest::from(header::DigestRef::empty()),
+            }),
+            consensus: chain_information::ChainInformationConsensus::Aura {
+                finalized_authorities_list: vec![header::AuraAuthority {
+                    public_key: keypair.public.to_bytes(),
+                }],
+                slot_duration: NonZeroU64::new(1000).unwrap(),
+            },
+            finality: chain_information::ChainInformationFinality::Outsourced,
+        }
+        .try_into()
+        .unwrap(),
+        blocks_capacity: 8,
+        block_number_bytes: 4,
+        allow_unknown_consensus_engines: false,
+        slot_drift_tolerance: crate::verify::aura::DEFAULT_SLOT_DRIFT_TOLERANCE,
+    })
+}
+
+/// Builds a block on top of the given parent, verifies it, and inserts it in the tree. Returns
+/// the hash of the new block.
+fn insert_aura_block(
+    tree: &mut NonFinalizedTree<()>,
+    keypair: &schnorrkel::Keypair,
+    parent_hash: [u8; 32],
+    number: u64,
+    slot_number: u64,
+) -> [u8; 32] {
+    let pre_digest = [header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+        slot_number,
+    })];
+    let mut block_header = header::Header {
+        parent_hash,
+        number,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::from_slice(&pre_digest).unwrap().into(),
+    };
+
+    let signature = keypair
+        .sign_simple(b"substrate", &block_header.hash(4))
+        .to_bytes();
+    let sealed_digest = [
+        pre_digest[0].clone(),
+        header::DigestItem::AuraSeal(signature),
+    ];
+    block_header.digest = header::DigestRef::from_slice(&sealed_digest)
+        .unwrap()
+        .into();
+
+    let verified_header = match tree
+        .verify_header(
+            block_header.scale_encoding_vec(4),
+            Duration::from_secs(1000),
+        )
+        .unwrap()
+    {
+        HeaderVerifySuccess::Verified {
+            verified_header, ..
+        } => verified_header,
+        _ => panic!(),
+    };
+
+    let hash = block_header.hash(4);
+    tree.insert_verified_header(verified_header, ());
+    hash
+}
+
+#[test]
+fn best_block_reorg_depth() {
+    let keypair = schnorrkel::MiniSecretKey::from_bytes(&[1; 32])
+        .unwrap()
+        .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+    let mut tree = aura_tree(&keypair);
+    let genesis_hash = *tree.finalized_block_hash();
+
+    // The best block is the finalized block.
+    assert_eq!(tree.best_block_reorg_depth(&genesis_hash), Some(0));
+    assert_eq!(tree.best_block_reorg_depth(&[0xff; 32]), None);
+
+    // Best chain: genesis <- 1 <- 2 <- 3
+    let block1 = insert_aura_block(&mut tree, &keypair, genesis_hash, 1, 1);
+    let block2 = insert_aura_block(&mut tree, &keypair, block1, 2, 2);
+    let block3 = insert_aura_block(&mut tree, &keypair, block2, 3, 3);
+    assert_eq!(*tree.best_block_hash(), block3);
+
+    // Fork: 1 <- 2' and genesis <- 1'
+    let block2_fork = insert_aura_block(&mut tree, &keypair, block1, 2, 4);
+    let block1_fork = insert_aura_block(&mut tree, &keypair, genesis_hash, 1, 5);
+    assert_eq!(*tree.best_block_hash(), block3);
+
+    assert_eq!(tree.best_block_reorg_depth(&block3), Some(0));
+    assert_eq!(tree.best_block_reorg_depth(&block2), Some(1));
+    assert_eq!(tree.best_block_reorg_depth(&block1), Some(2));
+    assert_eq!(tree.best_block_reorg_depth(&genesis_hash), Some(3));
+    assert_eq!(tree.best_block_reorg_depth(&block2_fork), Some(2));
+    assert_eq!(tree.best_block_reorg_depth(&block1_fork), Some(3));
+    assert_eq!(tree.best_block_reorg_depth(&[0xff; 32]), None);
+}
@@ -143,6 +143,7 @@ impl<T> NonFinalizedTree<T> {
                     current_authorities: header::AuraAuthoritiesIter::from_slice(authorities_list),
                     now_from_unix_epoch,
                     slot_duration: *slot_duration,
+                    slot_drift_tolerance: self.slot_drift_tolerance,
                 },
                 (
                     FinalizedConsensus::Babe {
@@ -196,6 +197,19 @@ impl<T> NonFinalizedTree<T> {
             }
         };
 
+        let (slot_number, author_public_key) = match &header_verify_result {
+            verify::header_only::Success::Aura {
+                slot_number,
+                authority_public_key,
+                ..
+            }
+            | verify::header_only::Success::Babe {
+                slot_number,
+                authority_public_key,
+                ..
+            } => (*slot_number, *authority_public_key),
+        };
+
         // Updated consensus information for the block being verified.
         let (best_score_num_primary_slots, best_score_num_secondary_slots, consensus_update) =
             match (
@@ -208,6 +222,7 @@ impl<T> NonFinalizedTree<T> {
                 (
                     verify::header_only::Success::Aura {
                         authorities_change: None,
+                        ..
                     },
                     Some(BlockConsensus::Aura {
                         authorities_list: parent_authorities,
@@ -226,6 +241,7 @@ impl<T> NonFinalizedTree<T> {
                 (
                     verify::header_only::Success::Aura {
                         authorities_change: Some(new_authorities_list),
+                        ..
                     },
                     Some(BlockConsensus::Aura { .. }),
                     FinalizedConsensus::Aura { .. },
@@ -474,6 +490,8 @@ impl<T> NonFinalizedTree<T> {
                 best_score_num_primary_slots,
                 best_score_num_secondary_slots,
                 hash,
+                slot_number,
+                author_public_key,
             },
             is_new_best,
         })
@@ -563,9 +581,21 @@ pub struct VerifiedHeader {
     best_score_num_secondary_slots: u64,
     hash: [u8; 32],
     number: u64,
+    slot_number: u64,
+    author_public_key: [u8; 32],
 }
 
 impl VerifiedHeader {
+    /// Returns the slot number the block belongs to.
+    pub fn slot_number(&self) -> u64 {
+        self.slot_number
+    }
+
+    /// Returns the Sr25519 public key of the authority that has authored the block.
+    pub fn author_public_key(&self) -> &[u8; 32] {
+        &self.author_public_key
+    }
+
     /// Returns the block header.
     pub fn scale_encoded_header(&self) -> &[u8] {
         &self.scale_encoded_header
@@ -43,6 +43,7 @@ use crate::header;
 use alloc::{boxed::Box, vec::Vec};
 use core::num::NonZeroU64;
 
+pub mod authorities_history;
 pub mod build;
 
 /// Information about the latest finalized block and state found in its ancestors.
@@ -0,0 +1,321 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Determining the GrandPa authorities and Babe epochs of past blocks.
+//!
+//! A [`ChainInformation`](super::ChainInformation) only contains the consensus-related
+//! information of one specific block. The changes to this information (authority set changes
+//! and epoch transitions) are, however, all reported in the digests of the headers of the chain.
+//!
+//! The [`AuthoritiesHistory`] starts from a chain information, typically the one of the genesis
+//! block, and is then fed with the headers of the descendants of this block, one by one and in
+//! order. After each header, it indicates the GrandPa authorities and Babe epoch of this block.
+//!
+//! > **Note**: No verification of the headers is performed other than checking that they are
+//! >           each the child of the previous one. The headers are assumed to come from a
+//! >           trusted source, such as a local database of blocks that have been verified.
+
+use crate::{chain::chain_information, header};
+
+use alloc::vec::Vec;
+use core::num::NonZeroU64;
+
+/// See the module-level documentation.
+#[derive(Debug, Clone)]
+pub struct AuthoritiesHistory {
+    /// Number of bytes used to encode the block number in headers.
+    block_number_bytes: usize,
+
+    /// Number of the latest block passed to [`AuthoritiesHistory::apply_header`], or of the
+    /// block of the chain information if no header has been applied yet.
+    block_number: u64,
+
+    /// Hash of the block whose number is [`AuthoritiesHistory::block_number`].
+    block_hash: [u8; 32],
+
+    /// State of the GrandPa authorities. `None` if the chain doesn't use GrandPa.
+    grandpa: Option<GrandpaState>,
+
+    /// State of the Babe epochs. `None` if the chain doesn't use Babe.
+    babe: Option<BabeState>,
+}
+
+#[derive(Debug, Clone)]
+struct GrandpaState {
+    /// Identifier of the set of authorities that finalize the children of the current block.
+    after_block_set_id: u64,
+    /// Authorities that finalize the children of the current block.
+    after_block_authorities: Vec<header::GrandpaAuthority>,
+    /// If the current block has triggered an authorities change, contains the set that
+    /// finalizes the current block itself, in other words the set before the change.
+    previous_set: Option<(u64, Vec<header::GrandpaAuthority>)>,
+    /// Change that has been scheduled but not triggered yet, with the number of the block that
+    /// triggers it.
+    scheduled_change: Option<(u64, Vec<header::GrandpaAuthority>)>,
+}
+
+#[derive(Debug, Clone)]
+struct BabeState {
+    /// See [`chain_information::ChainInformationConsensus::Babe::slots_per_epoch`].
+    slots_per_epoch: NonZeroU64,
+    /// Epoch the current block belongs to, and its actual index. The index can be higher than
+    /// the one found in the [`chain_information::BabeEpochInformation`] if entire epochs have
+    /// been skipped. `None` if the current block is the genesis block.
+    current_epoch: Option<(u64, chain_information::BabeEpochInformation)>,
+    /// Epoch following the one of the current block.
+    next_epoch: chain_information::BabeEpochInformation,
+}
+
+/// GrandPa authorities set. See [`AuthoritiesHistory::grandpa_authorities`].
+#[derive(Debug, Copy, Clone)]
+pub struct GrandpaAuthorities<'a> {
+    /// Identifier of the authorities set. Starts at 0 at the genesis block and is incremented
+    /// by one at each change.
+    pub set_id: u64,
+    /// List of authorities.
+    pub authorities: &'a [header::GrandpaAuthority],
+}
+
+/// Babe epoch. See [`AuthoritiesHistory::babe_epoch`].
+#[derive(Debug, Copy, Clone)]
+pub struct BabeEpoch<'a> {
+    /// Index of the epoch.
+    ///
+    /// Might be higher than [`chain_information::BabeEpochInformation::epoch_index`] of
+    /// [`BabeEpoch::information`], in the situation where no block has been authored during
+    /// entire epochs.
+    pub epoch_index: u64,
+    /// Information about the epoch, including its list of authorities.
+    pub information: &'a chain_information::BabeEpochInformation,
+}
+
+impl AuthoritiesHistory {
+    /// Initializes a new [`AuthoritiesHistory`] whose current block is the finalized block of
+    /// the given chain information.
+    pub fn new(
+        chain_information: chain_information::ValidChainInformationRef,
+        block_number_bytes: usize,
+    ) -> Self {
+        let chain_information = chain_information.as_ref();
+
+        let grandpa = match chain_information.finality {
+            chain_information::ChainInformationFinalityRef::Outsourced => None,
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change,
+            } => Some(GrandpaState {
+                after_block_set_id: after_finalized_block_authorities_set_id,
+                after_block_authorities: finalized_triggered_authorities.to_vec(),
+                previous_set: None,
+                scheduled_change: finalized_scheduled_change
+                    .map(|(number, list)| (number, list.to_vec())),
+            }),
+        };
+
+        let babe = match chain_information.consensus {
+            chain_information::ChainInformationConsensusRef::Babe {
+                slots_per_epoch,
+                finalized_block_epoch_information,
+                finalized_next_epoch_transition,
+            } => Some(BabeState {
+                slots_per_epoch,
+                current_epoch: finalized_block_epoch_information.map(|epoch| {
+                    let epoch = chain_information::BabeEpochInformation::from(epoch);
+                    (epoch.epoch_index, epoch)
+                }),
+                next_epoch: finalized_next_epoch_transition.into(),
+            }),
+            _ => None,
+        };
+
+        AuthoritiesHistory {
+            block_number_bytes,
+            block_number: chain_information.finalized_block_header.number,
+            block_hash: chain_information
+                .finalized_block_header
+                .hash(block_number_bytes),
+            grandpa,
+            babe,
+        }
+    }
+
+    /// Returns the number of the current block.
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    /// Returns the hash of the current block.
+    pub fn block_hash(&self) -> &[u8; 32] {
+        &self.block_hash
+    }
+
+    /// Returns the GrandPa authorities set responsible for finalizing the current block, or
+    /// `None` if the chain doesn't use GrandPa.
+    ///
+    /// If the current block is the one of the chain information passed to
+    /// [`AuthoritiesHistory::new`], this is the set that finalizes its children instead.
+    pub fn grandpa_authorities(&self) -> Option<GrandpaAuthorities> {
+        let grandpa = self.grandpa.as_ref()?;
+        Some(match &grandpa.previous_set {
+            Some((set_id, authorities)) => GrandpaAuthorities {
+                set_id: *set_id,
+                authorities,
+            },
+            None => GrandpaAuthorities {
+                set_id: grandpa.after_block_set_id,
+                authorities: &grandpa.after_block_authorities,
+            },
+        })
+    }
+
+    /// Returns the Babe epoch the current block belongs to, or `None` if the chain doesn't use
+    /// Babe or if the current block is the genesis block.
+    pub fn babe_epoch(&self) -> Option<BabeEpoch> {
+        let (epoch_index, information) = self.babe.as_ref()?.current_epoch.as_ref()?;
+        Some(BabeEpoch {
+            epoch_index: *epoch_index,
+            information,
+        })
+    }
+
+    /// Updates the state with the given header, which becomes the current block.
+    ///
+    /// The header must be the one of a child of the current block.
+    pub fn apply_header(&mut self, scale_encoded_header: &[u8]) -> Result<(), ApplyHeaderError> {
+        let decoded = header::decode(scale_encoded_header, self.block_number_bytes)
+            .map_err(ApplyHeaderError::InvalidHeader)?;
+
+        if *decoded.parent_hash != self.block_hash
+            || Some(decoded.number) != self.block_number.checked_add(1)
+        {
+            return Err(ApplyHeaderError::NotChild);
+        }
+
+        if let Some(grandpa) = &mut self.grandpa {
+            grandpa.previous_set = None;
+
+            // A forced change takes precedence over the scheduled changes of the same block.
+            let mut forced_change = None;
+            for item in decoded.digest.logs() {
+                let header::DigestItemRef::GrandpaConsensus(item) = item else {
+                    continue;
+                };
+
+                match item {
+                    header::GrandpaConsensusLogRef::ScheduledChange(change)
+                        if grandpa.scheduled_change.is_none() =>
+                    {
+                        grandpa.scheduled_change = Some((
+                            decoded.number.saturating_add(change.delay),
+                            change.next_authorities.map(Into::into).collect(),
+                        ));
+                    }
+                    header::GrandpaConsensusLogRef::ForcedChange { change, .. }
+                        if forced_change.is_none() =>
+                    {
+                        forced_change = Some((
+                            decoded.number.saturating_add(change.delay),
+                            change.next_authorities.map(Into::into).collect(),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            if forced_change.is_some() {
+                grandpa.scheduled_change = forced_change;
+            }
+
+            if grandpa
+                .scheduled_change
+                .as_ref()
+                .map_or(false, |(trigger, _)| *trigger == decoded.number)
+            {
+                let (_, new_authorities) = grandpa.scheduled_change.take().unwrap();
+                grandpa.previous_set = Some((
+                    grandpa.after_block_set_id,
+                    core::mem::replace(&mut grandpa.after_block_authorities, new_authorities),
+                ));
+                grandpa.after_block_set_id += 1;
+            }
+        }
+
+        if let Some(babe) = &mut self.babe {
+            let slot_number = decoded
+                .digest
+                .babe_pre_runtime()
+                .ok_or(ApplyHeaderError::MissingBabePreRuntimeDigest)?
+                .slot_number();
+
+            if let Some((info, maybe_config)) = decoded.digest.babe_epoch_information() {
+                // The block is the first block of the epoch that was the next epoch of its
+                // parent.
+                let mut block_epoch = babe.next_epoch.clone();
+                if block_epoch.start_slot_number.is_none() {
+                    block_epoch.start_slot_number = Some(slot_number);
+                }
+                let block_epoch_start = block_epoch.start_slot_number.unwrap();
+                let skipped_epochs =
+                    slot_number.saturating_sub(block_epoch_start) / babe.slots_per_epoch.get();
+                let block_epoch_index = block_epoch.epoch_index.saturating_add(skipped_epochs);
+
+                babe.next_epoch = chain_information::BabeEpochInformation {
+                    epoch_index: block_epoch_index.saturating_add(1),
+                    start_slot_number: Some(
+                        block_epoch_start.saturating_add(
+                            babe.slots_per_epoch
+                                .get()
+                                .saturating_mul(skipped_epochs.saturating_add(1)),
+                        ),
+                    ),
+                    authorities: info.authorities.map(Into::into).collect(),
+                    randomness: *info.randomness,
+                    c: maybe_config.map_or(block_epoch.c, |config| config.c),
+                    allowed_slots: maybe_config
+                        .map_or(block_epoch.allowed_slots, |config| config.allowed_slots),
+                };
+                babe.current_epoch = Some((block_epoch_index, block_epoch));
+            } else if let Some((epoch_index, epoch)) = &mut babe.current_epoch {
+                if let Some(start_slot_number) = epoch.start_slot_number {
+                    let skipped_epochs =
+                        slot_number.saturating_sub(start_slot_number) / babe.slots_per_epoch.get();
+                    *epoch_index = epoch.epoch_index.saturating_add(skipped_epochs);
+                }
+            } else {
+                return Err(ApplyHeaderError::MissingBabeEpochChange);
+            }
+        }
+
+        self.block_number = decoded.number;
+        self.block_hash = header::hash_from_scale_encoded_header(scale_encoded_header);
+        Ok(())
+    }
+}
+
+/// Error potentially returned by [`AuthoritiesHistory::apply_header`].
+#[derive(Debug, derive_more::Display)]
+pub enum ApplyHeaderError {
+    /// Failed to decode the header.
+    #[display(fmt = "{_0}")]
+    InvalidHeader(header::Error),
+    /// Header isn't the one of a child of the current block.
+    NotChild,
+    /// Chain uses Babe but the header doesn't contain any Babe pre-runtime digest.
+    MissingBabePreRuntimeDigest,
+    /// First block after the genesis block doesn't contain any Babe epoch change.
+    MissingBabeEpochChange,
+}
@@ -39,7 +39,9 @@ use crate::{
         build, BabeEpochInformation, ChainInformation, ChainInformationConsensus,
         ChainInformationFinality, ValidChainInformation, ValidityError,
     },
-    executor, libp2p, trie,
+    executor,
+    identity::ss58,
+    libp2p, trie,
 };
 
 use alloc::{
@@ -332,6 +334,23 @@ impl ChainSpec {
             .map_or("{}", |p| p.get())
     }
 
+    /// Returns the SS58 prefix of the addresses of the chain, found under the `ss58Format` key of
+    /// the properties of the chain specification. See [`ChainSpec::properties`].
+    ///
+    /// Returns `None` if the properties don't contain any valid SS58 prefix, in which case
+    /// [`ss58::ChainPrefix::GENERIC_SUBSTRATE`] is typically used.
+    pub fn ss58_prefix(&self) -> Option<ss58::ChainPrefix> {
+        #[derive(serde::Deserialize)]
+        struct Properties {
+            #[serde(rename = "ss58Format")]
+            ss58_format: Option<u16>,
+        }
+
+        let properties = self.client_spec.properties.as_ref()?;
+        let properties = serde_json::from_str::<Properties>(properties.get()).ok()?;
+        ss58::ChainPrefix::try_from(properties.ss58_format?).ok()
+    }
+
     pub fn light_sync_state(&self) -> Option<LightSyncState> {
         self.client_spec
             .light_sync_state
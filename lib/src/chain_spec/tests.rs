@@ -24,6 +24,7 @@ fn can_decode_polkadot_genesis() {
     let spec = &include_bytes!("./tests/example.json")[..];
     let specs = ChainSpec::from_json_bytes(spec).unwrap();
     assert_eq!(specs.id(), "polkadot");
+    assert_eq!(specs.ss58_prefix().map(u16::from), Some(0));
 
     // code_substitutes field
     assert_eq!(specs.client_spec.code_substitutes.get(&1), None);
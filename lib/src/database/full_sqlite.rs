@@ -78,11 +78,13 @@ use crate::{
 };
 
 use alloc::borrow::Cow;
-use core::{fmt, iter};
+use core::{fmt, iter, num::NonZeroU32};
 use parking_lot::Mutex;
 use rusqlite::OptionalExtension as _;
 
-pub use open::{open, Config, ConfigTy, DatabaseEmpty, DatabaseOpen};
+pub use open::{
+    open, open_read_only, Config, ConfigTy, DatabaseEmpty, DatabaseOpen, JournalMode, Synchronous,
+};
 
 mod open;
 mod tests;
@@ -105,6 +107,13 @@ pub struct SqliteFullDatabase {
 
     /// Number of bytes used to encode the block number.
     block_number_bytes: usize,
+
+    /// File on which an advisory lock is held for as long as the database is open, if the
+    /// database is stored on disk and writable. See [`ConfigTy::Disk::shared_access`].
+    ///
+    /// Declared after [`SqliteFullDatabase::database`] so that the lock is released only after
+    /// the connection has been closed.
+    _lock_file: Option<std::fs::File>,
 }
 
 impl SqliteFullDatabase {
@@ -160,14 +169,17 @@ impl SqliteFullDatabase {
         let out = connection
             .prepare_cached(r#"SELECT parent_hash FROM blocks WHERE hash = ?"#)
             .map_err(|err| CorruptedError::Internal(InternalError(err)))?
-            .query_row((&block_hash[..],), |row| row.get::<_, [u8; 32]>(0))
+            .query_row((&block_hash[..],), |row| row.get::<_, Option<[u8; 32]>>(0))
             .optional()
             .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
-        Ok(out)
+        // The parent of the genesis block is stored as `NULL`. Its header contains a parent hash
+        // made of zeroes, which is what is returned here.
+        Ok(out.map(|parent| parent.unwrap_or([0; 32])))
     }
 
-    /// Returns the list of extrinsics of the given block, or `None` if the block is unknown.
+    /// Returns the list of extrinsics of the given block, or `None` if the block is unknown or
+    /// if its body has been pruned.
     ///
     /// > **Note**: The list of extrinsics of a block is also known as its *body*.
     ///
@@ -180,7 +192,9 @@ impl SqliteFullDatabase {
     ) -> Result<Option<impl ExactSizeIterator<Item = Vec<u8>>>, CorruptedError> {
         let connection = self.database.lock();
 
-        // TODO: doesn't detect if block is absent
+        if block_body_pruned(&connection, block_hash)?.unwrap_or(true) {
+            return Ok(None);
+        }
 
         let result = connection
             .prepare_cached(r#"SELECT extrinsic FROM blocks_body WHERE hash = ? ORDER BY idx ASC"#)
@@ -193,6 +207,161 @@ impl SqliteFullDatabase {
         Ok(Some(result.into_iter()))
     }
 
+    /// Returns a chunk of the list of extrinsics of the given block, starting at the extrinsic
+    /// whose index is `start_index`.
+    ///
+    /// Extrinsics are added to the chunk until their total size reaches `max_chunk_size`. The
+    /// chunk always contains at least one extrinsic, unless `start_index` is superior or equal
+    /// to the number of extrinsics of the block. In other words, an empty chunk indicates that
+    /// the end of the body has been reached.
+    ///
+    /// Contrary to [`SqliteFullDatabase::block_extrinsics`], this function makes it possible to
+    /// process the body of a very large block without loading it entirely in memory.
+    ///
+    /// Returns `Ok(None)` if the block is unknown or if its body has been pruned.
+    pub fn block_extrinsics_chunk(
+        &self,
+        block_hash: &[u8; 32],
+        start_index: usize,
+        max_chunk_size: usize,
+    ) -> Result<Option<Vec<Vec<u8>>>, CorruptedError> {
+        let connection = self.database.lock();
+
+        if block_body_pruned(&connection, block_hash)?.unwrap_or(true) {
+            return Ok(None);
+        }
+
+        let Ok(start_index) = i64::try_from(start_index) else {
+            return Ok(Some(Vec::new()));
+        };
+
+        let mut statement = connection
+            .prepare_cached(
+                r#"SELECT extrinsic FROM blocks_body WHERE hash = ? AND idx >= ? ORDER BY idx ASC"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        // Rows are read lazily, meaning that the extrinsics after the end of the chunk are
+        // never loaded.
+        let rows = statement
+            .query_map((&block_hash[..], start_index), |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let mut chunk = Vec::new();
+        let mut chunk_size = 0;
+        for extrinsic in rows {
+            if chunk_size >= max_chunk_size && !chunk.is_empty() {
+                break;
+            }
+
+            let extrinsic =
+                extrinsic.map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            chunk_size += extrinsic.len();
+            chunk.push(extrinsic);
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// Returns the number of extrinsics in the body of the given block and their total size in
+    /// bytes, without loading the body in memory.
+    ///
+    /// Returns `Ok(None)` if the block is unknown or if its body has been pruned.
+    pub fn block_body_size(
+        &self,
+        block_hash: &[u8; 32],
+    ) -> Result<Option<(usize, u64)>, CorruptedError> {
+        let connection = self.database.lock();
+
+        if block_body_pruned(&connection, block_hash)?.unwrap_or(true) {
+            return Ok(None);
+        }
+
+        let (num_extrinsics, total_size) = connection
+            .prepare_cached(
+                r#"SELECT COUNT(*), COALESCE(SUM(LENGTH(extrinsic)), 0) FROM blocks_body WHERE hash = ?"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(Some((
+            usize::try_from(num_extrinsics).map_err(|_| CorruptedError::InvalidNumber)?,
+            u64::try_from(total_size).map_err(|_| CorruptedError::InvalidNumber)?,
+        )))
+    }
+
+    /// Returns the justifications of the given block, as a list of consensus engine identifiers
+    /// and SCALE-encoded justifications, or `None` if the block is unknown.
+    ///
+    /// The list is empty if no justification has been stored for this block.
+    pub fn block_justifications(
+        &self,
+        block_hash: &[u8; 32],
+    ) -> Result<Option<Vec<([u8; 4], Vec<u8>)>>, CorruptedError> {
+        let connection = self.database.lock();
+
+        let justifications = connection
+            .prepare_cached(r#"SELECT justification FROM blocks WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| row.get::<_, Option<Vec<u8>>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        match justifications {
+            None => Ok(None),
+            Some(None) => Ok(Some(Vec::new())),
+            Some(Some(encoded)) => Ok(Some(decode_justifications(&encoded)?)),
+        }
+    }
+
+    /// Stores a justification of the given block, replacing the one of the same consensus engine
+    /// that might already be stored.
+    ///
+    /// Justifications are never pruned, even when the body or the storage of the block is.
+    pub fn set_block_justification(
+        &self,
+        block_hash: &[u8; 32],
+        consensus_engine_id: [u8; 4],
+        justification: &[u8],
+    ) -> Result<(), SetJustificationError> {
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let mut justifications = match transaction
+            .prepare_cached(r#"SELECT justification FROM blocks WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| row.get::<_, Option<Vec<u8>>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        {
+            None => return Err(SetJustificationError::UnknownBlock),
+            Some(None) => Vec::new(),
+            Some(Some(encoded)) => decode_justifications(&encoded)?,
+        };
+
+        justifications.retain(|(engine_id, _)| *engine_id != consensus_engine_id);
+        justifications.push((consensus_engine_id, justification.to_vec()));
+
+        transaction
+            .prepare_cached(r#"UPDATE blocks SET justification = ? WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((encode_justifications(&justifications), &block_hash[..]))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
     /// Returns the hashes of the blocks given a block number.
     pub fn block_hash_by_number(
         &self,
@@ -435,72 +604,7 @@ impl SqliteFullDatabase {
         trie_entries_version: u8,
     ) -> Result<(), CorruptedError> {
         let mut database = self.database.lock();
-
-        let transaction = database
-            .transaction()
-            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-
-        {
-            // TODO: should check whether the existing merkle values that are referenced from inserted nodes exist in the parent's storage
-            // TODO: is it correct to have OR IGNORE everywhere?
-            let mut insert_node_statement = transaction
-                .prepare_cached("INSERT OR IGNORE INTO trie_node(hash, partial_key) VALUES(?, ?)")
-                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-            let mut insert_node_storage_statement = transaction
-                .prepare_cached("INSERT OR IGNORE INTO trie_node_storage(node_hash, value, trie_root_ref, trie_entry_version) VALUES(?, ?, ?, ?)")
-                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-            let mut insert_child_statement = transaction
-                .prepare_cached(
-                    "INSERT OR IGNORE INTO trie_node_child(hash, child_num, child_hash) VALUES(?, ?, ?)",
-                )
-                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-            // TODO: if the iterator's `next()` function accesses the database, we deadlock
-            for trie_node in new_trie_nodes {
-                assert!(trie_node.partial_key_nibbles.iter().all(|n| *n < 16)); // TODO: document
-                insert_node_statement
-                    .execute((&trie_node.merkle_value, trie_node.partial_key_nibbles))
-                    .map_err(|err: rusqlite::Error| CorruptedError::Internal(InternalError(err)))?;
-                match trie_node.storage_value {
-                    InsertTrieNodeStorageValue::Value {
-                        value,
-                        references_merkle_value,
-                    } => {
-                        insert_node_storage_statement
-                            .execute((
-                                &trie_node.merkle_value,
-                                if !references_merkle_value {
-                                    Some(&value)
-                                } else {
-                                    None
-                                },
-                                if references_merkle_value {
-                                    Some(&value)
-                                } else {
-                                    None
-                                },
-                                trie_entries_version,
-                            ))
-                            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-                    }
-                    InsertTrieNodeStorageValue::NoValue => {}
-                }
-                for (child_num, child) in trie_node.children_merkle_values.iter().enumerate() {
-                    if let Some(child) = child {
-                        let child_num =
-                            vec![u8::try_from(child_num).unwrap_or_else(|_| unreachable!())];
-                        insert_child_statement
-                            .execute((&trie_node.merkle_value, child_num, child))
-                            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-                    }
-                }
-            }
-        }
-
-        transaction
-            .commit()
-            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-
-        Ok(())
+        insert_trie_nodes(&mut database, new_trie_nodes, trie_entries_version)
     }
 
     /// Returns a list of trie nodes that are missing from the database and that belong to the
@@ -728,9 +832,19 @@ impl SqliteFullDatabase {
     /// Removes from the database all blocks that aren't a descendant of the current finalized
     /// block.
     pub fn purge_finality_orphans(&self) -> Result<(), CorruptedError> {
-        let mut database = self.database.lock();
+        self.purge_finality_orphans_except(|_| false)
+    }
 
-        // TODO: untested
+    /// Similar to [`SqliteFullDatabase::purge_finality_orphans`], but doesn't remove the blocks
+    /// whose hash is passed to `keep` and for which `keep` returns `true`.
+    ///
+    /// The blocks that are kept are removed by later calls to this function, once `keep` no
+    /// longer returns `true` for them.
+    pub fn purge_finality_orphans_except(
+        &self,
+        mut keep: impl FnMut(&[u8; 32]) -> bool,
+    ) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
 
         let transaction = database
             .transaction()
@@ -757,6 +871,9 @@ impl SqliteFullDatabase {
             .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
         for block in blocks {
+            if <&[u8; 32]>::try_from(&block[..]).is_ok_and(&mut keep) {
+                continue;
+            }
             purge_block(&transaction, &block)?;
         }
 
@@ -768,6 +885,90 @@ impl SqliteFullDatabase {
         Ok(())
     }
 
+    /// Removes from the database the storage of the blocks of the finalized chain whose height is
+    /// strictly inferior to the height of the finalized block minus `keep_last`. If
+    /// `prune_bodies` is `true`, the bodies of these blocks are removed as well. Their headers
+    /// and justifications are always kept.
+    ///
+    /// The blocks whose hash is passed to `keep` and for which `keep` returns `true` are skipped.
+    /// They are pruned by later calls to this function, once `keep` no longer returns `true` for
+    /// them.
+    ///
+    /// At most `max_blocks` blocks are pruned, in order to not hold the database for too long.
+    /// Returns the number of blocks that have been pruned. If this number is equal to
+    /// `max_blocks`, there are likely more blocks left to prune.
+    pub fn prune_finalized_blocks(
+        &self,
+        keep_last: u64,
+        prune_bodies: bool,
+        max_blocks: usize,
+        mut keep: impl FnMut(&[u8; 32]) -> bool,
+    ) -> Result<usize, CorruptedError> {
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let Some(threshold) = finalized_num(&transaction)?.checked_sub(keep_last) else {
+            return Ok(0);
+        };
+
+        // The blocks for which `keep` returns `true` don't count towards `max_blocks`. The
+        // candidates are thus fetched page by page, in order to not stall if the oldest blocks
+        // are all kept.
+        let mut num_pruned = 0;
+        let mut after_number = -1i64;
+        while num_pruned < max_blocks {
+            let blocks = transaction
+                .prepare_cached(
+                    r#"
+                    SELECT hash, number FROM blocks
+                    WHERE number < :threshold AND number > :after_number AND is_best_chain = TRUE
+                        AND (state_trie_root_hash IS NOT NULL OR (:prune_bodies AND body_pruned = FALSE))
+                    ORDER BY number ASC
+                    LIMIT :max_blocks
+                "#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .query_map(
+                    rusqlite::named_params! {
+                        ":threshold": i64::try_from(threshold).unwrap_or(i64::MAX),
+                        ":after_number": after_number,
+                        ":prune_bodies": prune_bodies,
+                        ":max_blocks": i64::try_from(max_blocks - num_pruned).unwrap_or(i64::MAX),
+                    },
+                    |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?)),
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            let Some((_, last_number)) = blocks.last() else {
+                break;
+            };
+            after_number = *last_number;
+
+            for (block, _) in blocks {
+                if <&[u8; 32]>::try_from(&block[..]).is_ok_and(&mut keep) {
+                    continue;
+                }
+                purge_block_storage(&transaction, &block)?;
+                if prune_bodies {
+                    purge_block_body(&transaction, &block)?;
+                }
+                num_pruned += 1;
+            }
+        }
+
+        // If everything went well up to this point, commit the transaction.
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(num_pruned)
+    }
+
     /// Returns the value associated with a node of the trie of the given block.
     ///
     /// `parent_tries_paths_nibbles` is a list of keys to follow in order to find the root of the
@@ -1220,6 +1421,243 @@ impl SqliteFullDatabase {
         Ok(next_key)
     }
 
+    /// Returns the storage entries of the given block whose key starts with the given prefix
+    /// and is superior or equal to the given start key, in lexicographic order.
+    ///
+    /// `prefix_nibbles` and `start_key_nibbles` must be iterators to the **nibbles** of the
+    /// prefix and start key. Contrary to [`SqliteFullDatabase::block_storage_next_key`], the
+    /// start key doesn't have to start with the prefix. If it is inferior to the prefix, the
+    /// iteration starts at the first key that starts with the prefix.
+    ///
+    /// `parent_tries_paths_nibbles` is a list of keys to follow in order to find the root of the
+    /// trie into which the entries should be searched.
+    ///
+    /// At most `limit` entries are returned. In order to continue iterating, call this function
+    /// again with a start key equal to the key of the last entry with a `0` appended at the end.
+    ///
+    /// If `with_values` is `false`, then [`StorageEntry::value`] is always `None` and the values
+    /// aren't read from the database. Branch nodes (i.e. nodes with no value associated to them)
+    /// are never returned.
+    ///
+    /// The keys are returned in nibbles. An empty list is returned if
+    /// `parent_tries_paths_nibbles` didn't lead to any trie.
+    ///
+    /// Contrary to repeatedly calling [`SqliteFullDatabase::block_storage_next_key`], the trie is
+    /// walked only once and the sub-trees that can't contain any matching entry aren't visited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the values yielded by `parent_tries_paths_nibbles`, `prefix_nibbles`, or
+    /// `start_key_nibbles` is superior or equal to 16.
+    ///
+    pub fn block_storage_entries(
+        &self,
+        block_hash: &[u8; 32],
+        parent_tries_paths_nibbles: impl Iterator<Item = impl Iterator<Item = u8>>,
+        prefix_nibbles: impl Iterator<Item = u8>,
+        start_key_nibbles: impl Iterator<Item = u8>,
+        limit: usize,
+        with_values: bool,
+    ) -> Result<Vec<StorageEntry>, StorageAccessError> {
+        // Process the iterators at the very beginning and before locking the database, in order
+        // to avoid a deadlock in case the `next()` function of one of the iterators accesses
+        // the database as well.
+        let parent_tries_paths_nibbles = parent_tries_paths_nibbles
+            .map(|t| t.inspect(|n| assert!(*n < 16)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let prefix_nibbles = prefix_nibbles
+            .inspect(|n| assert!(*n < 16))
+            .collect::<Vec<_>>();
+        let start_key_nibbles = start_key_nibbles
+            .inspect(|n| assert!(*n < 16))
+            .collect::<Vec<_>>();
+
+        let connection = self.database.lock();
+
+        let internal_error =
+            |err| StorageAccessError::Corrupted(CorruptedError::Internal(InternalError(err)));
+
+        let mut root_hash = state_trie_root_hash(&connection, block_hash)?;
+
+        let mut node_statement = connection
+            .prepare_cached(
+                r#"
+            SELECT
+                trie_node.partial_key,
+                trie_node_storage.value IS NOT NULL OR trie_node_storage.trie_root_ref IS NOT NULL,
+                IIF(:with_values, COALESCE(trie_node_storage.value, trie_node_storage.trie_root_ref), NULL),
+                trie_node_storage.trie_entry_version,
+                trie_node_storage.trie_root_ref
+            FROM trie_node
+            LEFT JOIN trie_node_storage ON trie_node_storage.node_hash = trie_node.hash
+            WHERE trie_node.hash = :node_hash
+            "#,
+            )
+            .map_err(internal_error)?;
+        let mut child_statement = connection
+            .prepare_cached(
+                r#"SELECT child_hash FROM trie_node_child WHERE hash = ? AND child_num = ?"#,
+            )
+            .map_err(internal_error)?;
+        let mut children_statement = connection
+            .prepare_cached(
+                r#"SELECT child_num, child_hash FROM trie_node_child WHERE hash = ? ORDER BY child_num DESC"#,
+            )
+            .map_err(internal_error)?;
+
+        // Loads the node with the given hash. Returns an error if it is missing from the
+        // database, as the storage of the block is then incomplete.
+        let mut load_node = |node_hash: &[u8], with_values: bool| {
+            node_statement
+                .query_row(
+                    rusqlite::named_params! {
+                        ":node_hash": node_hash,
+                        ":with_values": with_values,
+                    },
+                    |row| {
+                        let partial_key = row.get::<_, Vec<u8>>(0)?;
+                        let has_storage_value = row.get::<_, i64>(1)? != 0;
+                        let value = row.get::<_, Option<Vec<u8>>>(2)?;
+                        let trie_entry_version = row.get::<_, Option<i64>>(3)?;
+                        let trie_root_ref = row.get::<_, Option<Vec<u8>>>(4)?;
+                        Ok((
+                            partial_key,
+                            has_storage_value,
+                            value,
+                            trie_entry_version,
+                            trie_root_ref,
+                        ))
+                    },
+                )
+                .optional()
+                .map_err(internal_error)?
+                .ok_or(StorageAccessError::IncompleteStorage)
+        };
+
+        // Follow `parent_tries_paths_nibbles` in order to find the root of the trie to iterate.
+        for parent_trie_path in &parent_tries_paths_nibbles {
+            let mut node_hash = root_hash;
+            let mut key_remain = &parent_trie_path[..];
+            root_hash = loop {
+                let (partial_key, _, _, _, trie_root_ref) = load_node(&node_hash, false)?;
+                let Some(after_partial_key) = key_remain.strip_prefix(&partial_key[..]) else {
+                    return Ok(Vec::new());
+                };
+                let Some((child_num, after_child_num)) = after_partial_key.split_first() else {
+                    match trie_root_ref {
+                        Some(trie_root_ref) => break trie_root_ref,
+                        None => return Ok(Vec::new()),
+                    }
+                };
+                let child_hash = child_statement
+                    .query_row((&node_hash[..], &[*child_num][..]), |row| {
+                        row.get::<_, Vec<u8>>(0)
+                    })
+                    .optional()
+                    .map_err(internal_error)?;
+                let Some(child_hash) = child_hash else {
+                    return Ok(Vec::new());
+                };
+                node_hash = child_hash;
+                key_remain = after_child_num;
+            };
+        }
+
+        // Returns `true` if the sub-trie whose keys all start with `key` might contain an entry
+        // that matches the prefix and the start key.
+        let subtree_might_match = |key: &[u8]| {
+            let common = key.len().min(prefix_nibbles.len());
+            key[..common] == prefix_nibbles[..common]
+                && (start_key_nibbles.starts_with(key) || key >= &start_key_nibbles[..])
+        };
+
+        // Depth-first traversal of the trie. Children are pushed in reverse order, so that the
+        // entries are visited in lexicographic order. Each element of the stack contains the
+        // hash of a node and the key that precedes its partial key.
+        let mut output = Vec::new();
+        let mut stack = vec![(root_hash, Vec::new())];
+        while let Some((node_hash, mut key)) = stack.pop() {
+            if output.len() >= limit {
+                break;
+            }
+
+            let (partial_key, has_storage_value, value, trie_entry_version, _) =
+                load_node(&node_hash, with_values)?;
+            key.extend_from_slice(&partial_key);
+            if !subtree_might_match(&key) {
+                continue;
+            }
+
+            if has_storage_value && key.starts_with(&prefix_nibbles) && key >= start_key_nibbles {
+                let value = match value {
+                    Some(value) => {
+                        let trie_entry_version = trie_entry_version
+                            .and_then(|v| u8::try_from(v).ok())
+                            .ok_or(StorageAccessError::Corrupted(
+                                CorruptedError::InvalidTrieEntryVersion,
+                            ))?;
+                        Some((value, trie_entry_version))
+                    }
+                    None => None,
+                };
+                output.push(StorageEntry {
+                    key_nibbles: key.clone(),
+                    value,
+                });
+            }
+
+            for child in children_statement
+                .query_map((&node_hash[..],), |row| {
+                    Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+                })
+                .map_err(internal_error)?
+            {
+                let (child_num, child_hash) = child.map_err(internal_error)?;
+                let mut child_key = key.clone();
+                child_key.extend_from_slice(&child_num);
+                if subtree_might_match(&child_key) {
+                    stack.push((child_hash, child_key));
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Returns the storage entries whose value differs between the storage of `old_block_hash`
+    /// and the storage of `new_block_hash`, in the lexicographic order of their keys.
+    ///
+    /// An entry is returned if its key is found in only one of the two blocks, or if its value
+    /// or its trie entry version differs between the two blocks.
+    ///
+    /// The two tries are compared node by node. Since the nodes are shared between blocks in
+    /// the database, the sub-tries that are identical in both blocks are detected by comparing
+    /// their Merkle values and aren't visited. Comparing a block with its parent is thus
+    /// proportional to the number of storage modifications performed by the block.
+    ///
+    /// Only the main trie is compared. A modification of a child trie shows up as a
+    /// modification of the entry of the main trie that references the root of the child trie.
+    ///
+    /// Returns an error if one of the two blocks or its storage can't be found in the database.
+    pub fn block_storage_diff(
+        &self,
+        old_block_hash: &[u8; 32],
+        new_block_hash: &[u8; 32],
+    ) -> Result<Vec<StorageDiffEntry>, StorageAccessError> {
+        let connection = self.database.lock();
+
+        let old_root_hash = state_trie_root_hash(&connection, old_block_hash)?;
+        let new_root_hash = state_trie_root_hash(&connection, new_block_hash)?;
+
+        let mut output = Vec::new();
+        if old_root_hash != new_root_hash {
+            let old_root = load_trie_node(&connection, old_root_hash, Vec::new())?;
+            let new_root = load_trie_node(&connection, new_root_hash, Vec::new())?;
+            trie_diff(&connection, Some(old_root), Some(new_root), &mut output)?;
+        }
+        Ok(output)
+    }
+
     /// Returns the Merkle value of the trie node in the storage that is the closest descendant
     /// of the provided key.
     ///
@@ -1384,31 +1822,479 @@ impl SqliteFullDatabase {
         Ok(merkle_value)
     }
 
-    /// Inserts a block in the database and sets it as the finalized block.
-    ///
-    /// The parent of the block doesn't need to be present in the database.
+    /// Returns the Merkle value of the root node of the main storage trie of the given block.
     ///
-    /// If the block is already in the database, it is replaced by the one provided.
-    pub fn reset<'a>(
+    /// Combined with [`SqliteFullDatabase::trie_node`], this makes it possible to traverse the
+    /// storage trie node by node, which is much cheaper than looking up each node by its key.
+    pub fn block_storage_root_merkle_value(
         &self,
-        finalized_block_header: &[u8],
-        finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
-        finalized_block_justification: Option<Vec<u8>>,
-    ) -> Result<(), CorruptedError> {
-        // Start a transaction to insert everything in one go.
-        let mut database = self.database.lock();
-        let transaction = database
-            .transaction()
-            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        block_hash: &[u8; 32],
+    ) -> Result<Vec<u8>, StorageAccessError> {
+        let connection = self.database.lock();
+        state_trie_root_hash(&connection, block_hash)
+    }
 
-        // Temporarily disable foreign key checks in order to make the initial insertion easier,
-        // as we don't have to make sure that trie nodes are sorted.
-        // Note that this is immediately disabled again when we `COMMIT`.
-        transaction
-            .execute("PRAGMA defer_foreign_keys = ON", ())
-            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    /// Returns the trie node whose Merkle value is `merkle_value`.
+    ///
+    /// Trie nodes are shared between the storage tries of all the blocks. Use
+    /// [`SqliteFullDatabase::block_storage_root_merkle_value`] to obtain the root node of the
+    /// storage of a block.
+    ///
+    /// Returns [`StorageAccessError::IncompleteStorage`] if the node isn't in the database.
+    pub fn trie_node(&self, merkle_value: &[u8]) -> Result<TrieNode, StorageAccessError> {
+        let connection = self.database.lock();
+        let node = load_trie_node(&connection, merkle_value.to_vec(), Vec::new())?;
 
-        let finalized_block_hash = header::hash_from_scale_encoded_header(finalized_block_header);
+        let mut children_merkle_values = [(); 16].map(|()| None);
+        for (child_num, child_merkle_value) in node.children {
+            let slot = match &child_num[..] {
+                [n] => children_merkle_values.get_mut(usize::from(*n)),
+                _ => None,
+            }
+            .ok_or(StorageAccessError::Corrupted(
+                CorruptedError::InvalidChildNum,
+            ))?;
+            *slot = Some(child_merkle_value);
+        }
+
+        Ok(TrieNode {
+            partial_key_nibbles: node.full_key,
+            storage_value: node.value,
+            children_merkle_values,
+        })
+    }
+
+    /// Returns the list of peers saved with [`SqliteFullDatabase::set_known_peers`].
+    pub fn known_peers(&self) -> Result<Vec<KnownPeer>, CorruptedError> {
+        let database = self.database.lock();
+
+        let mut peers = database
+            .prepare_cached(r#"SELECT peer_id, reputation FROM peers"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_map((), |row| {
+                Ok(KnownPeer {
+                    peer_id: row.get::<_, Vec<u8>>(0)?,
+                    addresses: Vec::new(),
+                    reputation: row.get::<_, i32>(1)?,
+                })
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let mut statement = database
+            .prepare_cached(r#"SELECT address FROM peers_addresses WHERE peer_id = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        for peer in &mut peers {
+            peer.addresses = statement
+                .query_map((&peer.peer_id,), |row| row.get::<_, Vec<u8>>(0))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        }
+
+        Ok(peers)
+    }
+
+    /// Replaces the list of peers stored in the database with the given one.
+    ///
+    /// The database doesn't interpret the peers in any way. It only stores them so that they can
+    /// later be retrieved with [`SqliteFullDatabase::known_peers`], typically after a restart.
+    pub fn set_known_peers(
+        &self,
+        peers: impl Iterator<Item = KnownPeer>,
+    ) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // Addresses are removed through the `ON DELETE CASCADE`.
+        transaction
+            .execute(r#"DELETE FROM peers"#, ())
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        {
+            let mut insert_peer = transaction
+                .prepare_cached(
+                    r#"INSERT OR REPLACE INTO peers(peer_id, reputation) VALUES (?, ?)"#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            let mut insert_address = transaction
+                .prepare_cached(
+                    r#"INSERT OR IGNORE INTO peers_addresses(peer_id, address) VALUES (?, ?)"#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            for peer in peers {
+                insert_peer
+                    .execute((&peer.peer_id, peer.reputation))
+                    .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+                for address in &peer.addresses {
+                    insert_address
+                        .execute((&peer.peer_id, address))
+                        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+                }
+            }
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
+    /// Returns the statistics saved with [`SqliteFullDatabase::set_authoring_stats`]. All the
+    /// counters are zero if they have never been saved.
+    pub fn authoring_stats(&self) -> Result<AuthoringStats, CorruptedError> {
+        let database = self.database.lock();
+        let get = |key| Ok::<_, CorruptedError>(meta_get_number(&database, key)?.unwrap_or(0));
+        Ok(AuthoringStats {
+            eligible_slots: get("authoring_eligible_slots")?,
+            authored_blocks: get("authoring_authored_blocks")?,
+            authored_blocks_best: get("authoring_authored_blocks_best")?,
+            authored_blocks_finalized: get("authoring_authored_blocks_finalized")?,
+            missed_slots_late: get("authoring_missed_slots_late")?,
+            missed_slots_no_peers: get("authoring_missed_slots_no_peers")?,
+            missed_slots_error: get("authoring_missed_slots_error")?,
+        })
+    }
+
+    /// Replaces the block authoring statistics stored in the database with the given ones.
+    ///
+    /// The database doesn't interpret these statistics in any way. It only stores them so that
+    /// they can later be retrieved with [`SqliteFullDatabase::authoring_stats`], typically after
+    /// a restart.
+    pub fn set_authoring_stats(&self, stats: &AuthoringStats) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        for (key, value) in [
+            ("authoring_eligible_slots", stats.eligible_slots),
+            ("authoring_authored_blocks", stats.authored_blocks),
+            ("authoring_authored_blocks_best", stats.authored_blocks_best),
+            (
+                "authoring_authored_blocks_finalized",
+                stats.authored_blocks_finalized,
+            ),
+            ("authoring_missed_slots_late", stats.missed_slots_late),
+            (
+                "authoring_missed_slots_no_peers",
+                stats.missed_slots_no_peers,
+            ),
+            ("authoring_missed_slots_error", stats.missed_slots_error),
+        ] {
+            meta_set_number(&transaction, key, value)?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
+    /// Returns the GrandPa voter state saved with [`SqliteFullDatabase::set_grandpa_voter_state`],
+    /// or `None` if it has never been saved.
+    pub fn grandpa_voter_state(&self) -> Result<Option<GrandpaVoterState>, CorruptedError> {
+        let database = self.database.lock();
+        let set_id = meta_get_number(&database, "grandpa_voter_set_id")?;
+        let round_number = meta_get_number(&database, "grandpa_voter_round_number")?;
+        let (Some(set_id), Some(round_number)) = (set_id, round_number) else {
+            return Ok(None);
+        };
+
+        let mut votes = Vec::new();
+        let encoded_votes = meta_get_blob(&database, "grandpa_voter_votes")?.unwrap_or_default();
+        let mut remain = &encoded_votes[..];
+        while !remain.is_empty() {
+            let (rest, vote) = crate::util::nom_bytes_decode::<nom::error::Error<&[u8]>>(remain)
+                .map_err(|_| CorruptedError::InvalidGrandpaVoterVotes)?;
+            votes.push(vote.to_vec());
+            remain = rest;
+        }
+
+        Ok(Some(GrandpaVoterState {
+            set_id,
+            round_number,
+            votes,
+        }))
+    }
+
+    /// Stores in the database the state of the GrandPa voter of the local node.
+    ///
+    /// Voting twice in the same round is considered as misbehavior. This state is stored in
+    /// order to make sure that the local node never casts a different vote in a round after a
+    /// restart. It must therefore be stored before any vote is sent out.
+    pub fn set_grandpa_voter_state(&self, state: &GrandpaVoterState) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let encoded_votes = state.votes.iter().fold(Vec::new(), |mut encoded, vote| {
+            encoded.extend_from_slice(crate::util::encode_scale_compact_usize(vote.len()).as_ref());
+            encoded.extend_from_slice(vote);
+            encoded
+        });
+
+        meta_set_number(&transaction, "grandpa_voter_set_id", state.set_id)?;
+        meta_set_number(
+            &transaction,
+            "grandpa_voter_round_number",
+            state.round_number,
+        )?;
+        meta_set_blob(&transaction, "grandpa_voter_votes", &encoded_votes)?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
+    /// Returns the value associated to the given key in the offchain storage, or `None` if there
+    /// is no such value.
+    ///
+    /// The offchain storage is a key-value storage that isn't part of the consensus, and that is
+    /// shared between all the blocks.
+    pub fn offchain_storage_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, CorruptedError> {
+        let database = self.database.lock();
+        let value = database
+            .prepare_cached(r#"SELECT value FROM offchain_storage WHERE key = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((key,), |row| row.get::<_, Vec<u8>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        Ok(value)
+    }
+
+    /// Sets or removes the value associated to the given key in the offchain storage.
+    ///
+    /// If `old_value` is `Some`, the value is only modified if the current value is equal to
+    /// `old_value`, where `Some(None)` means that there must not be any value at the moment.
+    ///
+    /// Returns `true` if the value has been modified.
+    pub fn offchain_storage_compare_set(
+        &self,
+        key: &[u8],
+        new_value: Option<&[u8]>,
+        old_value: Option<Option<&[u8]>>,
+    ) -> Result<bool, CorruptedError> {
+        let mut database = self.database.lock();
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        if let Some(old_value) = old_value {
+            let current_value = transaction
+                .prepare_cached(r#"SELECT value FROM offchain_storage WHERE key = ?"#)
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .query_row((key,), |row| row.get::<_, Vec<u8>>(0))
+                .optional()
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            if current_value.as_deref() != old_value {
+                return Ok(false);
+            }
+        }
+
+        offchain_storage_set(&transaction, key, new_value)?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(true)
+    }
+
+    /// Returns statistics about the content of the database, such as the number of rows and the
+    /// space used by each table.
+    ///
+    /// This function counts all the rows of all the tables, and is thus relatively expensive.
+    pub fn statistics(&self) -> Result<DatabaseStatistics, CorruptedError> {
+        let database = self.database.lock();
+
+        // The sizes are obtained through the `dbstat` virtual table, which is an optional
+        // feature of SQLite. The sizes of the indices are added to the size of their table.
+        let sizes = database
+            .prepare(
+                r#"
+                SELECT sqlite_schema.tbl_name, SUM(dbstat.pgsize)
+                FROM dbstat
+                JOIN sqlite_schema ON sqlite_schema.name = dbstat.name
+                GROUP BY sqlite_schema.tbl_name
+            "#,
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map((), |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .ok();
+
+        let mut tables = Vec::new();
+        for table in [
+            "meta",
+            "trie_node",
+            "trie_node_storage",
+            "trie_node_child",
+            "blocks",
+            "blocks_body",
+            "peers",
+            "peers_addresses",
+            "offchain_storage",
+        ] {
+            let num_rows = database
+                .prepare(&format!("SELECT COUNT(*) FROM {table}"))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .query_row((), |row| row.get::<_, i64>(0))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            tables.push(TableStatistics {
+                name: table,
+                num_rows: u64::try_from(num_rows).unwrap_or(0),
+                size_bytes: sizes.as_ref().map(|sizes| {
+                    sizes
+                        .iter()
+                        .find(|(name, _)| name == table)
+                        .map_or(0, |(_, size)| u64::try_from(*size).unwrap_or(0))
+                }),
+            });
+        }
+
+        let (oldest_block_with_state, oldest_block_with_body, num_justifications) = database
+            .prepare_cached(
+                r#"
+                SELECT
+                    MIN(CASE WHEN state_trie_root_hash IS NOT NULL THEN number END),
+                    MIN(CASE WHEN body_pruned = FALSE THEN number END),
+                    COUNT(justification)
+                FROM blocks
+            "#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((), |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(DatabaseStatistics {
+            tables,
+            finalized_block_number: finalized_num(&database)?,
+            oldest_block_with_state: oldest_block_with_state
+                .map(|n| u64::from_ne_bytes(n.to_ne_bytes())),
+            oldest_block_with_body: oldest_block_with_body
+                .map(|n| u64::from_ne_bytes(n.to_ne_bytes())),
+            num_justifications: u64::try_from(num_justifications).unwrap_or(0),
+        })
+    }
+
+    /// Copies the content of the database to the database file found at the given path, using
+    /// the online backup API of SQLite. The file is created if it doesn't exist, and its content
+    /// is overwritten otherwise.
+    ///
+    /// The copy reflects the state of the database at the time when this function is called,
+    /// and is never partially written in case of concurrent modifications. The other accesses to
+    /// the database are blocked while the copy is in progress.
+    pub fn backup(&self, path: &std::path::Path) -> Result<(), InternalError> {
+        let database = self.database.lock();
+        database
+            .backup(rusqlite::DatabaseName::Main, path, None)
+            .map_err(InternalError)
+    }
+
+    /// Gives back to the file system up to `max_pages` of the pages of the database file that
+    /// are no longer used, for example because blocks have been pruned.
+    ///
+    /// SQLite doesn't shrink the database file when data is removed, and instead reuses the
+    /// freed pages for later insertions. This function moves pages from the end of the file to
+    /// the free pages, then truncates the file. The other accesses to the database are blocked
+    /// while this function runs, and `max_pages` should be small enough in order for this to
+    /// not last too long.
+    ///
+    /// In WAL mode, the write-ahead log is checkpointed afterwards in order for the database
+    /// file to actually shrink. The checkpoint doesn't wait for the connections that are
+    /// reading the database, and the file might only shrink during a later checkpoint.
+    pub fn incremental_vacuum(
+        &self,
+        max_pages: NonZeroU32,
+    ) -> Result<IncrementalVacuumOutcome, InternalError> {
+        let database = self.database.lock();
+
+        let page_size = database
+            .query_row("PRAGMA page_size", (), |row| row.get::<_, i64>(0))
+            .map_err(InternalError)?;
+        let freelist_count = || {
+            database
+                .query_row("PRAGMA freelist_count", (), |row| row.get::<_, i64>(0))
+                .map_err(InternalError)
+        };
+
+        let free_pages_before = freelist_count()?;
+        // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
+        // SQLite gives back one page every time the statement is stepped, so all the rows must
+        // be iterated over.
+        let mut statement = database
+            .prepare(&format!("PRAGMA incremental_vacuum({max_pages})"))
+            .map_err(InternalError)?;
+        let mut rows = statement.query(()).map_err(InternalError)?;
+        while rows.next().map_err(InternalError)?.is_some() {}
+        drop(rows);
+        drop(statement);
+        let free_pages_after = freelist_count()?;
+
+        // Returns a row even if the database isn't in WAL mode, in which case it does nothing.
+        database
+            .query_row("PRAGMA wal_checkpoint(PASSIVE)", (), |_| Ok(()))
+            .map_err(InternalError)?;
+
+        let page_size = u64::try_from(page_size).unwrap_or(0);
+        Ok(IncrementalVacuumOutcome {
+            freed_bytes: u64::try_from(free_pages_before.saturating_sub(free_pages_after))
+                .unwrap_or(0)
+                .saturating_mul(page_size),
+            remaining_free_bytes: u64::try_from(free_pages_after)
+                .unwrap_or(0)
+                .saturating_mul(page_size),
+        })
+    }
+
+    /// Inserts a block in the database and sets it as the finalized block.
+    ///
+    /// The parent of the block doesn't need to be present in the database.
+    ///
+    /// If the block is already in the database, it is replaced by the one provided.
+    ///
+    /// If any, `finalized_block_justification` must be the SCALE encoding of the list of
+    /// consensus engine identifiers and justifications of the block.
+    pub fn reset<'a>(
+        &self,
+        finalized_block_header: &[u8],
+        finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+        finalized_block_justification: Option<Vec<u8>>,
+    ) -> Result<(), CorruptedError> {
+        // Start a transaction to insert everything in one go.
+        let mut database = self.database.lock();
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // Temporarily disable foreign key checks in order to make the initial insertion easier,
+        // as we don't have to make sure that trie nodes are sorted.
+        // Note that this is immediately disabled again when we `COMMIT`.
+        transaction
+            .execute("PRAGMA defer_foreign_keys = ON", ())
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let finalized_block_hash = header::hash_from_scale_encoded_header(finalized_block_header);
         // TODO: this module shouldn't decode blocks
         let decoded = header::decode(finalized_block_header, self.block_number_bytes).unwrap();
 
@@ -1481,6 +2367,129 @@ impl Drop for SqliteFullDatabase {
     }
 }
 
+/// Peer of the peer-to-peer network. See [`SqliteFullDatabase::known_peers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPeer {
+    /// Identity of the peer, in its binary encoding.
+    pub peer_id: Vec<u8>,
+    /// Addresses of the peer, encoded as multiaddresses.
+    pub addresses: Vec<Vec<u8>>,
+    /// Reputation of the peer. The meaning of this value is up to the API user.
+    pub reputation: i32,
+}
+
+/// Statistics about the blocks authored by the local node. See
+/// [`SqliteFullDatabase::authoring_stats`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuthoringStats {
+    /// Number of slots during which the local node was allowed to author a block.
+    pub eligible_slots: u64,
+    /// Number of blocks that the local node has authored.
+    pub authored_blocks: u64,
+    /// Number of blocks authored by the local node that have become the best block.
+    pub authored_blocks_best: u64,
+    /// Number of blocks authored by the local node that have been finalized.
+    pub authored_blocks_finalized: u64,
+    /// Number of eligible slots during which the block was authored too late to be accepted by
+    /// the rest of the network.
+    pub missed_slots_late: u64,
+    /// Number of eligible slots during which the node wasn't connected to any peer, and thus
+    /// couldn't propagate its block.
+    pub missed_slots_no_peers: u64,
+    /// Number of eligible slots during which authoring the block has failed.
+    pub missed_slots_error: u64,
+}
+
+/// State of the GrandPa voter of the local node. See
+/// [`SqliteFullDatabase::grandpa_voter_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrandpaVoterState {
+    /// Id of the GrandPa authorities set of the latest round the local node has voted in.
+    pub set_id: u64,
+    /// Number of the latest round the local node has voted in.
+    pub round_number: u64,
+    /// Votes that the local node has cast in that round. The database doesn't interpret them in
+    /// any way.
+    pub votes: Vec<Vec<u8>>,
+}
+
+/// Statistics about the content of the database. See [`SqliteFullDatabase::statistics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseStatistics {
+    /// Statistics of each table of the database.
+    pub tables: Vec<TableStatistics>,
+    /// Height of the latest finalized block.
+    pub finalized_block_number: u64,
+    /// Height of the oldest block whose storage is still available, or `None` if no block has
+    /// its storage available.
+    ///
+    /// Blocks below this height have had their storage pruned, or have an empty storage.
+    pub oldest_block_with_state: Option<u64>,
+    /// Height of the oldest block whose body is still available, or `None` if no block has its
+    /// body available.
+    pub oldest_block_with_body: Option<u64>,
+    /// Number of blocks whose justification is stored in the database.
+    pub num_justifications: u64,
+}
+
+/// See [`SqliteFullDatabase::block_storage_entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageEntry {
+    /// Key of the entry, in nibbles.
+    pub key_nibbles: Vec<u8>,
+    /// Storage value of the entry and its trie entry version. Always `None` if the values
+    /// haven't been requested.
+    pub value: Option<(Vec<u8>, u8)>,
+}
+
+/// See [`SqliteFullDatabase::block_storage_diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageDiffEntry {
+    /// Key of the entry, in nibbles.
+    pub key_nibbles: Vec<u8>,
+    /// Storage value of the entry and its trie entry version in the old block, or `None` if
+    /// the entry doesn't exist in the old block.
+    pub old_value: Option<(Vec<u8>, u8)>,
+    /// Storage value of the entry and its trie entry version in the new block, or `None` if
+    /// the entry doesn't exist in the new block.
+    pub new_value: Option<(Vec<u8>, u8)>,
+}
+
+/// Outcome of [`SqliteFullDatabase::incremental_vacuum`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncrementalVacuumOutcome {
+    /// Number of bytes that have been given back to the file system.
+    pub freed_bytes: u64,
+    /// Number of bytes of the database file that are still unused. Further calls to
+    /// [`SqliteFullDatabase::incremental_vacuum`] can give them back to the file system.
+    pub remaining_free_bytes: u64,
+}
+
+/// Statistics about a table of the database. See [`DatabaseStatistics::tables`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStatistics {
+    /// Name of the table.
+    pub name: &'static str,
+    /// Number of rows in the table.
+    pub num_rows: u64,
+    /// Number of bytes that the table and its indices occupy on disk. `None` if the version of
+    /// SQLite doesn't support reporting this information.
+    pub size_bytes: Option<u64>,
+}
+
+/// See [`SqliteFullDatabase::trie_node`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrieNode {
+    /// Partial key of the node, in nibbles.
+    pub partial_key_nibbles: Vec<u8>,
+    /// Storage value of the node and its trie entry version, or `None` for branch nodes. If the
+    /// node is the root of a child trie, the value is the Merkle value of the root node of that
+    /// child trie.
+    pub storage_value: Option<(Vec<u8>, u8)>,
+    /// Merkle values of the children of the node, indexed by nibble.
+    pub children_merkle_values: [Option<Vec<u8>>; 16],
+}
+
 /// See [`SqliteFullDatabase::finalized_and_above_missing_trie_nodes_unordered`].
 #[derive(Debug)]
 pub struct MissingTrieNode {
@@ -1552,6 +2561,15 @@ pub enum SetFinalizedError {
     RevertForbidden,
 }
 
+/// Error while calling [`SqliteFullDatabase::set_block_justification`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum SetJustificationError {
+    /// Error accessing the database.
+    Corrupted(CorruptedError),
+    /// Block isn't in the database.
+    UnknownBlock,
+}
+
 /// Error while accessing the storage of the finalized block.
 #[derive(Debug, derive_more::Display, derive_more::From)]
 pub enum StorageAccessError {
@@ -1588,6 +2606,12 @@ pub enum CorruptedError {
     BlockHeaderCorrupted(header::Error),
     /// The version information about a storage entry has failed to decode.
     InvalidTrieEntryVersion,
+    /// The index of a child of a trie node isn't a single nibble.
+    InvalidChildNum,
+    /// The GrandPa votes stored in the `meta` table have failed to decode.
+    InvalidGrandpaVoterVotes,
+    /// The justifications of a block have failed to decode.
+    InvalidJustifications,
     #[display(fmt = "Internal error: {_0}")]
     Internal(InternalError),
 }
@@ -1596,6 +2620,89 @@ pub enum CorruptedError {
 #[derive(Debug, derive_more::Display)]
 pub struct InternalError(rusqlite::Error);
 
+impl InternalError {
+    /// Returns `true` if the error indicates that the database file is corrupted or isn't a
+    /// database at all, as opposed to for example a file system access error.
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self.0.sqlite_error_code(),
+            Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+        )
+    }
+}
+
+fn insert_trie_nodes<'a>(
+    database: &mut rusqlite::Connection,
+    new_trie_nodes: impl Iterator<Item = InsertTrieNode<'a>>,
+    trie_entries_version: u8,
+) -> Result<(), CorruptedError> {
+    let transaction = database
+        .transaction()
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+    {
+        // TODO: should check whether the existing merkle values that are referenced from inserted nodes exist in the parent's storage
+        // TODO: is it correct to have OR IGNORE everywhere?
+        let mut insert_node_statement = transaction
+            .prepare_cached("INSERT OR IGNORE INTO trie_node(hash, partial_key) VALUES(?, ?)")
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let mut insert_node_storage_statement = transaction
+            .prepare_cached("INSERT OR IGNORE INTO trie_node_storage(node_hash, value, trie_root_ref, trie_entry_version) VALUES(?, ?, ?, ?)")
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let mut insert_child_statement = transaction
+            .prepare_cached(
+                "INSERT OR IGNORE INTO trie_node_child(hash, child_num, child_hash) VALUES(?, ?, ?)",
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        // TODO: if the iterator's `next()` function accesses the database, we deadlock
+        for trie_node in new_trie_nodes {
+            assert!(trie_node.partial_key_nibbles.iter().all(|n| *n < 16)); // TODO: document
+            insert_node_statement
+                .execute((&trie_node.merkle_value, trie_node.partial_key_nibbles))
+                .map_err(|err: rusqlite::Error| CorruptedError::Internal(InternalError(err)))?;
+            match trie_node.storage_value {
+                InsertTrieNodeStorageValue::Value {
+                    value,
+                    references_merkle_value,
+                } => {
+                    insert_node_storage_statement
+                        .execute((
+                            &trie_node.merkle_value,
+                            if !references_merkle_value {
+                                Some(&value)
+                            } else {
+                                None
+                            },
+                            if references_merkle_value {
+                                Some(&value)
+                            } else {
+                                None
+                            },
+                            trie_entries_version,
+                        ))
+                        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+                }
+                InsertTrieNodeStorageValue::NoValue => {}
+            }
+            for (child_num, child) in trie_node.children_merkle_values.iter().enumerate() {
+                if let Some(child) = child {
+                    let child_num =
+                        vec![u8::try_from(child_num).unwrap_or_else(|_| unreachable!())];
+                    insert_child_statement
+                        .execute((&trie_node.merkle_value, child_num, child))
+                        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+                }
+            }
+        }
+    }
+
+    transaction
+        .commit()
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+    Ok(())
+}
+
 fn meta_get_blob(
     database: &rusqlite::Connection,
     key: &str,
@@ -1648,6 +2755,304 @@ fn meta_set_number(
     Ok(())
 }
 
+fn offchain_storage_set(
+    database: &rusqlite::Connection,
+    key: &[u8],
+    value: Option<&[u8]>,
+) -> Result<(), CorruptedError> {
+    if let Some(value) = value {
+        database
+            .prepare_cached(r#"INSERT OR REPLACE INTO offchain_storage(key, value) VALUES (?, ?)"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((key, value))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    } else {
+        database
+            .prepare_cached(r#"DELETE FROM offchain_storage WHERE key = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((key,))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    }
+    Ok(())
+}
+
+/// Returns the hash of the root node of the state trie of the given block.
+fn state_trie_root_hash(
+    database: &rusqlite::Connection,
+    block_hash: &[u8; 32],
+) -> Result<Vec<u8>, StorageAccessError> {
+    let internal_error =
+        |err| StorageAccessError::Corrupted(CorruptedError::Internal(InternalError(err)));
+
+    database
+        .prepare_cached(r#"SELECT state_trie_root_hash FROM blocks WHERE hash = ?"#)
+        .map_err(internal_error)?
+        .query_row((&block_hash[..],), |row| row.get::<_, Option<Vec<u8>>>(0))
+        .optional()
+        .map_err(internal_error)?
+        .ok_or(StorageAccessError::UnknownBlock)?
+        .ok_or(StorageAccessError::IncompleteStorage)
+}
+
+/// Trie node loaded from the database. See [`load_trie_node`].
+struct LoadedTrieNode {
+    /// Merkle value of the node.
+    hash: Vec<u8>,
+    /// Key of the node, in nibbles, including its partial key.
+    full_key: Vec<u8>,
+    /// Storage value of the node and its trie entry version, or `None` for branch nodes.
+    value: Option<(Vec<u8>, u8)>,
+    /// Index, as a single nibble, and Merkle value of each child of the node, ordered by
+    /// index.
+    children: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Loads the trie node with the given hash. `key_before_partial_key` is the key of the node
+/// without its partial key.
+///
+/// Returns [`StorageAccessError::IncompleteStorage`] if the node is missing from the database.
+fn load_trie_node(
+    database: &rusqlite::Connection,
+    hash: Vec<u8>,
+    key_before_partial_key: Vec<u8>,
+) -> Result<LoadedTrieNode, StorageAccessError> {
+    let internal_error =
+        |err| StorageAccessError::Corrupted(CorruptedError::Internal(InternalError(err)));
+
+    let (partial_key, value, trie_entry_version) = database
+        .prepare_cached(
+            r#"
+            SELECT
+                trie_node.partial_key,
+                COALESCE(trie_node_storage.value, trie_node_storage.trie_root_ref),
+                trie_node_storage.trie_entry_version
+            FROM trie_node
+            LEFT JOIN trie_node_storage ON trie_node_storage.node_hash = trie_node.hash
+            WHERE trie_node.hash = ?
+            "#,
+        )
+        .map_err(internal_error)?
+        .query_row((&hash,), |row| {
+            let partial_key = row.get::<_, Vec<u8>>(0)?;
+            let value = row.get::<_, Option<Vec<u8>>>(1)?;
+            let trie_entry_version = row.get::<_, Option<i64>>(2)?;
+            Ok((partial_key, value, trie_entry_version))
+        })
+        .optional()
+        .map_err(internal_error)?
+        .ok_or(StorageAccessError::IncompleteStorage)?;
+
+    let value = match value {
+        Some(value) => {
+            let trie_entry_version = trie_entry_version
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or(StorageAccessError::Corrupted(
+                    CorruptedError::InvalidTrieEntryVersion,
+                ))?;
+            Some((value, trie_entry_version))
+        }
+        None => None,
+    };
+
+    let children = database
+        .prepare_cached(
+            r#"SELECT child_num, child_hash FROM trie_node_child WHERE hash = ? ORDER BY child_num"#,
+        )
+        .map_err(internal_error)?
+        .query_map((&hash,), |row| {
+            Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })
+        .map_err(internal_error)?
+        .map(|child| child.map_err(internal_error))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut full_key = key_before_partial_key;
+    full_key.extend_from_slice(&partial_key);
+
+    Ok(LoadedTrieNode {
+        hash,
+        full_key,
+        value,
+        children,
+    })
+}
+
+/// Pushes to `output` the storage entries that differ between the sub-trie starting at `old`
+/// and the sub-trie starting at `new`, in lexicographic order. A value of `None` designates an
+/// empty sub-trie.
+///
+/// See [`SqliteFullDatabase::block_storage_diff`].
+fn trie_diff(
+    database: &rusqlite::Connection,
+    old: Option<LoadedTrieNode>,
+    new: Option<LoadedTrieNode>,
+    output: &mut Vec<StorageDiffEntry>,
+) -> Result<(), StorageAccessError> {
+    match (old, new) {
+        (None, None) => {}
+
+        // Only one of the two sub-tries is non-empty, in which case all of its entries differ.
+        (Some(old), None) => {
+            if let Some(old_value) = old.value {
+                output.push(StorageDiffEntry {
+                    key_nibbles: old.full_key.clone(),
+                    old_value: Some(old_value),
+                    new_value: None,
+                });
+            }
+            for (child_num, child_hash) in old.children {
+                let child = load_trie_node(
+                    database,
+                    child_hash,
+                    [&old.full_key[..], &child_num[..]].concat(),
+                )?;
+                trie_diff(database, Some(child), None, output)?;
+            }
+        }
+        (None, Some(new)) => {
+            if let Some(new_value) = new.value {
+                output.push(StorageDiffEntry {
+                    key_nibbles: new.full_key.clone(),
+                    old_value: None,
+                    new_value: Some(new_value),
+                });
+            }
+            for (child_num, child_hash) in new.children {
+                let child = load_trie_node(
+                    database,
+                    child_hash,
+                    [&new.full_key[..], &child_num[..]].concat(),
+                )?;
+                trie_diff(database, None, Some(child), output)?;
+            }
+        }
+
+        (Some(old), Some(new)) if old.full_key == new.full_key => {
+            // Identical nodes at the same key necessarily have identical descendants.
+            if old.hash == new.hash {
+                return Ok(());
+            }
+
+            if old.value != new.value {
+                output.push(StorageDiffEntry {
+                    key_nibbles: old.full_key.clone(),
+                    old_value: old.value,
+                    new_value: new.value,
+                });
+            }
+
+            for child_num in 0..16u8 {
+                let old_child = old.children.iter().find(|(n, _)| n[..] == [child_num]);
+                let new_child = new.children.iter().find(|(n, _)| n[..] == [child_num]);
+                if let (Some((_, old_child_hash)), Some((_, new_child_hash))) =
+                    (old_child, new_child)
+                {
+                    if old_child_hash == new_child_hash {
+                        continue;
+                    }
+                }
+
+                let old_child = old_child
+                    .map(|(n, hash)| {
+                        let key = [&old.full_key[..], &n[..]].concat();
+                        load_trie_node(database, hash.clone(), key)
+                    })
+                    .transpose()?;
+                let new_child = new_child
+                    .map(|(n, hash)| {
+                        let key = [&new.full_key[..], &n[..]].concat();
+                        load_trie_node(database, hash.clone(), key)
+                    })
+                    .transpose()?;
+                trie_diff(database, old_child, new_child, output)?;
+            }
+        }
+
+        (Some(old), Some(new)) if new.full_key.starts_with(&old.full_key) => {
+            trie_diff_ancestor(database, old, new, true, output)?;
+        }
+        (Some(old), Some(new)) if old.full_key.starts_with(&new.full_key) => {
+            trie_diff_ancestor(database, new, old, false, output)?;
+        }
+
+        // The keys of the two sub-tries don't overlap.
+        (Some(old), Some(new)) => {
+            if old.full_key < new.full_key {
+                trie_diff(database, Some(old), None, output)?;
+                trie_diff(database, None, Some(new), output)?;
+            } else {
+                trie_diff(database, None, Some(new), output)?;
+                trie_diff(database, Some(old), None, output)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Same as [`trie_diff`], where the key of `ancestor` is a strict prefix of the key of
+/// `descendant`. `ancestor_is_old` indicates whether `ancestor` belongs to the old trie.
+fn trie_diff_ancestor(
+    database: &rusqlite::Connection,
+    ancestor: LoadedTrieNode,
+    descendant: LoadedTrieNode,
+    ancestor_is_old: bool,
+    output: &mut Vec<StorageDiffEntry>,
+) -> Result<(), StorageAccessError> {
+    let diff = |ancestor_side: Option<LoadedTrieNode>,
+                descendant_side: Option<LoadedTrieNode>,
+                output: &mut Vec<StorageDiffEntry>| {
+        if ancestor_is_old {
+            trie_diff(database, ancestor_side, descendant_side, output)
+        } else {
+            trie_diff(database, descendant_side, ancestor_side, output)
+        }
+    };
+
+    // The storage value of `ancestor` isn't in the other trie.
+    if let Some(value) = ancestor.value {
+        let (old_value, new_value) = if ancestor_is_old {
+            (Some(value), None)
+        } else {
+            (None, Some(value))
+        };
+        output.push(StorageDiffEntry {
+            key_nibbles: ancestor.full_key.clone(),
+            old_value,
+            new_value,
+        });
+    }
+
+    // All the keys of the sub-trie of `descendant` continue with this nibble after the key
+    // of `ancestor`.
+    let nibble = [descendant.full_key[ancestor.full_key.len()]];
+    let mut descendant = Some(descendant);
+
+    for (child_num, child_hash) in ancestor.children {
+        if descendant.is_some() && child_num[..] > nibble[..] {
+            diff(None, descendant.take(), output)?;
+        }
+
+        let child = load_trie_node(
+            database,
+            child_hash,
+            [&ancestor.full_key[..], &child_num[..]].concat(),
+        )?;
+        let other = if child_num[..] == nibble[..] {
+            descendant.take()
+        } else {
+            None
+        };
+        diff(Some(child), other, output)?;
+    }
+
+    if descendant.is_some() {
+        diff(None, descendant, output)?;
+    }
+
+    Ok(())
+}
+
 fn has_block(database: &rusqlite::Connection, hash: &[u8]) -> Result<bool, CorruptedError> {
     database
         .prepare_cached(r#"SELECT COUNT(*) FROM blocks WHERE hash = ?"#)
@@ -1656,6 +3061,20 @@ fn has_block(database: &rusqlite::Connection, hash: &[u8]) -> Result<bool, Corru
         .map_err(|err| CorruptedError::Internal(InternalError(err)))
 }
 
+/// Returns whether the body of the given block has been pruned, or `None` if the block is
+/// unknown.
+fn block_body_pruned(
+    database: &rusqlite::Connection,
+    hash: &[u8],
+) -> Result<Option<bool>, CorruptedError> {
+    database
+        .prepare_cached(r#"SELECT body_pruned FROM blocks WHERE hash = ?"#)
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        .query_row((hash,), |row| row.get::<_, bool>(0))
+        .optional()
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))
+}
+
 // TODO: the fact that the meta table stores blobs makes it impossible to use joins ; fix that
 fn finalized_num(database: &rusqlite::Connection) -> Result<u64, CorruptedError> {
     meta_get_number(database, "finalized")?.ok_or(CorruptedError::MissingMetaKey)
@@ -1712,6 +3131,43 @@ fn block_header(
         .map_err(|err| CorruptedError::Internal(InternalError(err)))
 }
 
+/// Decodes the content of the `justification` column of the `blocks` table, which contains the
+/// SCALE encoding of a list of consensus engine identifiers and justifications.
+fn decode_justifications(encoded: &[u8]) -> Result<Vec<([u8; 4], Vec<u8>)>, CorruptedError> {
+    let result: nom::IResult<_, _, nom::error::Error<&[u8]>> =
+        nom::combinator::all_consuming(nom::multi::length_count(
+            crate::util::nom_scale_compact_usize,
+            nom::sequence::tuple((
+                nom::combinator::map(nom::bytes::streaming::take(4u32), |engine_id: &[u8]| {
+                    <[u8; 4]>::try_from(engine_id).unwrap()
+                }),
+                nom::combinator::map(crate::util::nom_bytes_decode, |justification: &[u8]| {
+                    justification.to_vec()
+                }),
+            )),
+        ))(encoded);
+
+    match result {
+        Ok((_, justifications)) => Ok(justifications),
+        Err(_) => Err(CorruptedError::InvalidJustifications),
+    }
+}
+
+/// Opposite of [`decode_justifications`].
+fn encode_justifications(justifications: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut encoded = crate::util::encode_scale_compact_usize(justifications.len())
+        .as_ref()
+        .to_vec();
+    for (engine_id, justification) in justifications {
+        encoded.extend_from_slice(engine_id);
+        encoded.extend_from_slice(
+            crate::util::encode_scale_compact_usize(justification.len()).as_ref(),
+        );
+        encoded.extend_from_slice(justification);
+    }
+    encoded
+}
+
 fn set_best_chain(
     database: &rusqlite::Connection,
     new_best_block_hash: &[u8],
@@ -1789,13 +3245,25 @@ fn purge_block(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), Corru
     Ok(())
 }
 
-fn purge_block_storage(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), CorruptedError> {
-    // TODO: untested
+fn purge_block_body(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), CorruptedError> {
+    database
+        .prepare_cached("UPDATE blocks SET body_pruned = TRUE WHERE hash = ?")
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        .execute((hash,))
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    database
+        .prepare_cached("DELETE FROM blocks_body WHERE hash = ?")
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        .execute((hash,))
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    Ok(())
+}
 
+fn purge_block_storage(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), CorruptedError> {
     let state_trie_root_hash = database
         .prepare_cached(r#"SELECT state_trie_root_hash FROM blocks WHERE hash = ?"#)
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?
-        .query_row((hash,), |row| row.get::<_, Vec<u8>>(0))
+        .query_row((hash,), |row| row.get::<_, Option<Vec<u8>>>(0))
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
     database
@@ -1811,33 +3279,74 @@ fn purge_block_storage(database: &rusqlite::Connection, hash: &[u8]) -> Result<(
         })
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
-    // TODO: doesn't delete everything in the situation where a single node with a merkle value is referenced multiple times from the same trie
-    // TODO: currently doesn't follow `trie_root_ref`
-    database
-        .prepare_cached(r#"
-            WITH RECURSIVE
-                to_delete(node_hash) AS (
-                    SELECT trie_node.hash
-                        FROM trie_node
-                        LEFT JOIN blocks ON blocks.hash != :block_hash AND blocks.state_trie_root_hash = trie_node.hash
-                        LEFT JOIN trie_node_storage ON trie_node_storage.trie_root_ref = trie_node.hash
-                        WHERE trie_node.hash = :state_trie_root_hash AND blocks.hash IS NULL AND trie_node_storage.node_hash IS NULL
-                    UNION ALL
-                    SELECT trie_node_child.child_hash
-                        FROM to_delete
-                        JOIN trie_node_child ON trie_node_child.hash = to_delete.node_hash
-                        LEFT JOIN blocks ON blocks.state_trie_root_hash = trie_node_child.child_hash
-                        LEFT JOIN trie_node_storage ON trie_node_storage.trie_root_ref = to_delete.node_hash
-                        WHERE blocks.hash IS NULL AND trie_node_storage.node_hash IS NULL
-                )
-            DELETE FROM trie_node
-            WHERE hash IN (SELECT node_hash FROM to_delete)
-        "#)
-        .map_err(|err| CorruptedError::Internal(InternalError(err)))?
-        .execute(rusqlite::named_params! {
-            ":state_trie_root_hash": &state_trie_root_hash,
-            ":block_hash": hash,
-        })
-        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    if let Some(state_trie_root_hash) = state_trie_root_hash {
+        purge_unreferenced_trie_nodes(database, vec![state_trie_root_hash])?;
+    }
+
+    Ok(())
+}
+
+/// Removes from the database the trie nodes of `to_check` that are no longer referenced by any
+/// block, any other trie node, or any storage value, then does the same for their children,
+/// recursively.
+fn purge_unreferenced_trie_nodes(
+    database: &rusqlite::Connection,
+    mut to_check: Vec<Vec<u8>>,
+) -> Result<(), CorruptedError> {
+    // Since the trie nodes are deduplicated, a node can be shared between the tries of multiple
+    // blocks, and between multiple locations within the same trie. A node is only removed once
+    // nothing references it anymore, and removing a node decreases the number of references of
+    // its children, which are then checked in turn.
+    while let Some(node_hash) = to_check.pop() {
+        let is_referenced = database
+            .prepare_cached(
+                r#"
+                SELECT
+                    EXISTS(SELECT 1 FROM blocks WHERE state_trie_root_hash = :node_hash)
+                    OR EXISTS(SELECT 1 FROM trie_node_child WHERE child_hash = :node_hash)
+                    OR EXISTS(SELECT 1 FROM trie_node_storage WHERE trie_root_ref = :node_hash)
+            "#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row(
+                rusqlite::named_params! { ":node_hash": &node_hash },
+                |row| row.get::<_, bool>(0),
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        if is_referenced {
+            continue;
+        }
+
+        // The children and the root of the child trie, if any, must be fetched before the node
+        // is removed, as removing the node also removes these references.
+        let children = database
+            .prepare_cached(
+                r#"
+                SELECT child_hash FROM trie_node_child WHERE hash = :node_hash
+                UNION ALL
+                SELECT trie_root_ref FROM trie_node_storage
+                    WHERE node_hash = :node_hash AND trie_root_ref IS NOT NULL
+            "#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_map(
+                rusqlite::named_params! { ":node_hash": &node_hash },
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // The entries of `trie_node_child` and `trie_node_storage` are removed through
+        // `ON DELETE CASCADE`.
+        database
+            .prepare_cached("DELETE FROM trie_node WHERE hash = ?")
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((&node_hash,))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        to_check.extend(children);
+    }
+
     Ok(())
 }
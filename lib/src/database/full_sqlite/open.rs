@@ -21,7 +21,8 @@
 
 use super::{CorruptedError, InternalError, SqliteFullDatabase};
 
-use std::path::Path;
+use fs2::FileExt as _;
+use std::{fs, path::Path};
 
 /// Opens the database using the given [`Config`].
 ///
@@ -37,6 +38,15 @@ pub fn open(config: Config) -> Result<DatabaseOpen, InternalError> {
         // See https://www.sqlite.org/threadsafe.html
         rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX;
 
+    // Taken before opening the database, in order to not touch a database in use by someone
+    // else. The SQLite lock alone isn't enough, as it isn't held by the connections opened in
+    // shared mode, and a connection opened in exclusive mode only takes it when first accessing
+    // the database.
+    let lock_file = match config.ty {
+        ConfigTy::Disk { path, .. } => Some(lock_database(path)?),
+        ConfigTy::Memory => None,
+    };
+
     let database = match config.ty {
         ConfigTy::Disk { path, .. } => rusqlite::Connection::open_with_flags(path, flags),
         ConfigTy::Memory => rusqlite::Connection::open_in_memory_with_flags(flags),
@@ -47,14 +57,65 @@ pub fn open(config: Config) -> Result<DatabaseOpen, InternalError> {
     // value superior to the number of different queries we make.
     database.set_prepared_statement_cache_capacity(64);
 
+    // The page size can only be modified before the database is switched to the WAL mode, and
+    // is ignored by SQLite if the database already exists, unless it is vacuumed.
+    // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
+    if let ConfigTy::Disk {
+        page_size: Some(page_size),
+        ..
+    } = config.ty
+    {
+        database
+            .execute_batch(&format!("PRAGMA page_size = {}", page_size))
+            .map_err(InternalError)?;
+    }
+
+    // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
+    let (journal_mode, synchronous) = match config.ty {
+        ConfigTy::Disk {
+            journal_mode,
+            synchronous,
+            ..
+        } => (journal_mode, synchronous),
+        ConfigTy::Memory => (JournalMode::Wal, Synchronous::Normal),
+    };
+    database
+        .execute_batch(&format!(
+            "PRAGMA journal_mode = {}; PRAGMA synchronous = {};",
+            match journal_mode {
+                JournalMode::Wal => "WAL",
+                JournalMode::Delete => "DELETE",
+                JournalMode::Truncate => "TRUNCATE",
+                JournalMode::Persist => "PERSIST",
+            },
+            match synchronous {
+                Synchronous::Off => "OFF",
+                Synchronous::Normal => "NORMAL",
+                Synchronous::Full => "FULL",
+                Synchronous::Extra => "EXTRA",
+            }
+        ))
+        .map_err(InternalError)?;
+
+    // Unless other connections need to access the database, the database is locked for the
+    // entire lifetime of the connection rather than for each transaction, which is faster.
+    if !matches!(
+        config.ty,
+        ConfigTy::Disk {
+            shared_access: true,
+            ..
+        }
+    ) {
+        database
+            .execute_batch("PRAGMA locking_mode = EXCLUSIVE")
+            .map_err(InternalError)?;
+    }
+
     // Configure the database connection.
     database
         .execute_batch(
             r#"
 -- See https://sqlite.org/pragma.html and https://www.sqlite.org/wal.html
-PRAGMA journal_mode = WAL;
-PRAGMA synchronous = NORMAL;
-PRAGMA locking_mode = EXCLUSIVE;
 PRAGMA encoding = 'UTF-8';
 PRAGMA trusted_schema = false;
 PRAGMA foreign_keys = ON;
@@ -62,19 +123,7 @@ PRAGMA foreign_keys = ON;
         )
         .map_err(InternalError)?;
 
-    // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
-    database
-        .execute(
-            &format!(
-                "PRAGMA cache_size = {}",
-                0i64.saturating_sub_unsigned(
-                    u64::try_from((config.cache_size.saturating_sub(1) / 1024).saturating_add(1))
-                        .unwrap_or(u64::MAX),
-                )
-            ),
-            (),
-        )
-        .map_err(InternalError)?;
+    set_cache_size(&database, config.cache_size)?;
 
     // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
     if let ConfigTy::Disk {
@@ -200,6 +249,76 @@ PRAGMA user_version = 1;
             .map_err(InternalError)?
     }
 
+    if user_version <= 1 {
+        database
+            .execute_batch(
+                r#"
+/*
+Peers of the peer-to-peer network known by the node. Saved in order for the node to quickly find
+peers again after a restart.
+*/
+CREATE TABLE peers(
+    peer_id BLOB NOT NULL PRIMARY KEY,
+    reputation INTEGER NOT NULL
+);
+
+/*
+Addresses of the peers in `peers`. Each address is an encoded multiaddress.
+*/
+CREATE TABLE peers_addresses(
+    peer_id BLOB NOT NULL,
+    address BLOB NOT NULL,
+    UNIQUE(peer_id, address),
+    FOREIGN KEY (peer_id) REFERENCES peers(peer_id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE INDEX peers_addresses_by_peer ON peers_addresses(peer_id);
+
+PRAGMA user_version = 2;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
+    if user_version <= 2 {
+        database
+            .execute_batch(
+                r#"
+/*
+Set to `TRUE` when the body of the block has been removed from the database by the pruning, in
+order to distinguish a pruned body from an empty body.
+*/
+ALTER TABLE blocks ADD COLUMN body_pruned BOOLEAN NOT NULL DEFAULT FALSE;
+
+PRAGMA user_version = 3;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
+    if user_version <= 3 {
+        database
+            .execute_batch(
+                r#"
+/*
+Local storage of the offchain workers. Contains the values written by the offchain workers
+through the `ext_offchain_local_storage_*` host functions, and the values written by the blocks
+through the offchain indexing host functions.
+This storage isn't part of the consensus and is never pruned.
+*/
+CREATE TABLE offchain_storage(
+    key BLOB NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+
+PRAGMA user_version = 4;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
     let is_empty = database
         .prepare_cached("SELECT COUNT(*) FROM meta WHERE key = ?")
         .map_err(InternalError)?
@@ -211,15 +330,138 @@ PRAGMA user_version = 1;
         DatabaseOpen::Open(SqliteFullDatabase {
             database: parking_lot::Mutex::new(database),
             block_number_bytes: config.block_number_bytes, // TODO: consider storing this value in the DB and check it when opening
+            _lock_file: lock_file,
         })
     } else {
         DatabaseOpen::Empty(DatabaseEmpty {
             database,
             block_number_bytes: config.block_number_bytes,
+            lock_file,
         })
     })
 }
 
+/// Opens the file found next to the database at the given path and holds an advisory lock on
+/// it, in order to prevent two connections opened with [`open`] from using the same database
+/// at the same time.
+///
+/// The lock is held for as long as the returned file is alive. The file is created if it
+/// doesn't exist yet, and is never removed.
+fn lock_database(database_path: &Path) -> Result<fs::File, InternalError> {
+    let mut lock_path = database_path.as_os_str().to_owned();
+    lock_path.push("-lock");
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .map_err(|err| {
+            InternalError(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+                Some(format!("Failed to open the lock file: {err}")),
+            ))
+        })?;
+
+    // The same error as the one SQLite reports when trying to access a database locked
+    // exclusively by another connection is returned.
+    if file.try_lock_exclusive().is_err() {
+        return Err(InternalError(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("The database is already in use".to_owned()),
+        )));
+    }
+
+    Ok(file)
+}
+
+/// Opens an additional connection to a database stored on disk, through which the database can
+/// only be read.
+///
+/// The database must have been opened with [`open`] with `shared_access` set to `true` in
+/// [`ConfigTy::Disk`], and must have been initialized. The returned [`SqliteFullDatabase`] can
+/// be accessed from a different thread at the same time as the one returned by [`open`]. All the
+/// functions that modify the database return an error.
+///
+/// The database can also be opened from a different process than the one that has called
+/// [`open`], for example in order to analyze the content of the database of a running node.
+///
+/// If the journal mode of the database is [`JournalMode::Wal`], reading the database never
+/// delays the modifications performed through the connection returned by [`open`], and vice
+/// versa. Each query sees the database as it was when the query started. With the other
+/// journal modes, the database can't be modified while a query is in progress.
+///
+/// The `journal_mode`, `synchronous`, `page_size` and `shared_access` fields of the
+/// configuration are ignored, as they are properties of the database set by [`open`].
+///
+/// # Panic
+///
+/// Panics if [`Config::ty`] is [`ConfigTy::Memory`], as in-memory databases can't be accessed
+/// through multiple connections.
+///
+pub fn open_read_only(config: Config) -> Result<SqliteFullDatabase, InternalError> {
+    let ConfigTy::Disk {
+        path,
+        memory_map_size,
+        ..
+    } = config.ty
+    else {
+        panic!("In-memory databases can't be opened in read-only mode")
+    };
+
+    // See the comment in `open` about the "no mutex" option.
+    let database = rusqlite::Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(InternalError)?;
+
+    database.set_prepared_statement_cache_capacity(64);
+
+    database
+        .execute_batch(
+            r#"
+PRAGMA query_only = true;
+PRAGMA trusted_schema = false;
+            "#,
+        )
+        .map_err(InternalError)?;
+
+    set_cache_size(&database, config.cache_size)?;
+
+    // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
+    database
+        .execute_batch(&format!("PRAGMA mmap_size = {}", memory_map_size))
+        .map_err(InternalError)?;
+
+    // The lock file isn't locked, as reading the database doesn't prevent others from writing
+    // it.
+    Ok(SqliteFullDatabase {
+        database: parking_lot::Mutex::new(database),
+        block_number_bytes: config.block_number_bytes,
+        _lock_file: None,
+    })
+}
+
+/// Sets the maximum size, in bytes, of the cache of the given connection.
+fn set_cache_size(database: &rusqlite::Connection, cache_size: usize) -> Result<(), InternalError> {
+    // `PRAGMA` queries can't be parametrized, and thus we have to use `format!`.
+    // A negative value indicates a number of kibibytes.
+    database
+        .execute(
+            &format!(
+                "PRAGMA cache_size = {}",
+                0i64.saturating_sub_unsigned(
+                    u64::try_from((cache_size.saturating_sub(1) / 1024).saturating_add(1))
+                        .unwrap_or(u64::MAX),
+                )
+            ),
+            (),
+        )
+        .map_err(InternalError)?;
+    Ok(())
+}
+
 /// Configuration for the database.
 #[derive(Debug)]
 pub struct Config<'a> {
@@ -243,11 +485,63 @@ pub enum ConfigTy<'a> {
         /// Maximum allowed amount of memory, in bytes, that SQLite will reserve to memory-map
         /// files.
         memory_map_size: usize,
+        /// How SQLite guarantees the atomicity of transactions.
+        journal_mode: JournalMode,
+        /// How often SQLite waits for the data to be physically written to the disk.
+        synchronous: Synchronous,
+        /// Size, in bytes, of the pages of the database. Must be a power of two between 512 and
+        /// 65536, otherwise it is ignored. Only applies to newly-created databases. If `None`,
+        /// the default of SQLite is used.
+        page_size: Option<u32>,
+        /// If `true`, the database can be accessed at the same time through the connections
+        /// opened with [`open_read_only`]. If `false`, the database is locked exclusively for as
+        /// long as it is open, which is slightly faster and prevents any other connection,
+        /// including from other processes, from accessing it.
+        ///
+        /// In both cases, an advisory lock is also held on a file named after the database
+        /// followed with `-lock`, which prevents the database from being opened by [`open`]
+        /// multiple times at the same time, but not by [`open_read_only`].
+        shared_access: bool,
     },
     /// Store the database in memory. The database is discarded on destruction.
     Memory,
 }
 
+/// See [`ConfigTy::Disk`].
+///
+/// See also <https://www.sqlite.org/pragma.html#pragma_journal_mode>.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JournalMode {
+    /// Changes are appended to a write-ahead log that is regularly merged into the database.
+    /// Generally the fastest mode, but requires support for shared memory from the file system,
+    /// which network file systems might lack.
+    Wal,
+    /// Rollback journal, deleted at the end of each transaction.
+    Delete,
+    /// Rollback journal, truncated to a size of zero at the end of each transaction.
+    Truncate,
+    /// Rollback journal, whose header is overwritten with zeroes at the end of each transaction.
+    Persist,
+}
+
+/// See [`ConfigTy::Disk`].
+///
+/// See also <https://www.sqlite.org/pragma.html#pragma_synchronous>.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Synchronous {
+    /// Never wait for the data to be written. A crash of the operating system or a power loss
+    /// might corrupt the database.
+    Off,
+    /// Wait at critical moments. In [`JournalMode::Wal`], a power loss might roll back the most
+    /// recent transactions but can't corrupt the database.
+    Normal,
+    /// Wait for the data of each transaction to be written.
+    Full,
+    /// Same as [`Synchronous::Full`], but additionally wait for the directory containing the
+    /// rollback journal to be written after the journal has been deleted.
+    Extra,
+}
+
 /// Either existing database or database prototype.
 pub enum DatabaseOpen {
     /// A database already existed and has now been opened.
@@ -268,11 +562,33 @@ pub struct DatabaseEmpty {
 
     /// See the similar field in [`SqliteFullDatabase`].
     block_number_bytes: usize,
+
+    /// See the similar field in [`SqliteFullDatabase`].
+    lock_file: Option<fs::File>,
 }
 
 impl DatabaseEmpty {
+    /// Inserts trie nodes in the database prototype, before it is turned into an actual
+    /// database with [`DatabaseEmpty::initialize`].
+    ///
+    /// Contrary to [`SqliteFullDatabase::insert_trie_nodes`], the database is still considered
+    /// as empty by [`open`] afterwards. This makes it possible to insert a large state in
+    /// multiple transactions without leaving a database with an incomplete state behind if the
+    /// process is interrupted: only [`DatabaseEmpty::initialize`] marks the database as
+    /// initialized.
+    pub fn insert_trie_nodes<'a>(
+        &mut self,
+        new_trie_nodes: impl Iterator<Item = super::InsertTrieNode<'a>>,
+        trie_entries_version: u8,
+    ) -> Result<(), CorruptedError> {
+        super::insert_trie_nodes(&mut self.database, new_trie_nodes, trie_entries_version)
+    }
+
     /// Inserts the given finalized block in the database prototype in order to turn it into
     /// an actual database.
+    ///
+    /// If any, `finalized_block_justification` must be the SCALE encoding of the list of
+    /// consensus engine identifiers and justifications of the block.
     // TODO: can a database not be empty?
     pub fn initialize<'a>(
         self,
@@ -283,6 +599,7 @@ impl DatabaseEmpty {
         let database = SqliteFullDatabase {
             database: parking_lot::Mutex::new(self.database),
             block_number_bytes: self.block_number_bytes,
+            _lock_file: self.lock_file,
         };
 
         database.reset(
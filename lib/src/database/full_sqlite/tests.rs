@@ -18,13 +18,14 @@
 #![cfg(test)]
 
 use super::{
-    open, Config, ConfigTy, DatabaseOpen, InsertTrieNode, InsertTrieNodeStorageValue,
-    StorageAccessError,
+    open, open_read_only, AuthoringStats, Config, ConfigTy, DatabaseOpen, GrandpaVoterState,
+    InsertTrieNode, InsertTrieNodeStorageValue, JournalMode, KnownPeer, SetJustificationError,
+    StorageAccessError, StorageDiffEntry, StorageEntry, Synchronous,
 };
 use crate::{header, trie};
 
 use alloc::borrow::Cow;
-use core::{array, iter};
+use core::{array, iter, num::NonZeroU32};
 use rand::distributions::{Distribution as _, Uniform};
 
 #[test]
@@ -246,6 +247,62 @@ fn empty_database_fill_then_query() {
             );
         }
 
+        // Ask random pages of entries.
+        for _ in 0..1024 {
+            let start_key = (0..uniform_sample(0, 8))
+                .map(|_| trie::Nibble::try_from(uniform_sample(0u8, 15)).unwrap())
+                .collect::<Vec<_>>();
+            let prefix = (0..uniform_sample(0, 4))
+                .map(|_| trie::Nibble::try_from(uniform_sample(0u8, 15)).unwrap())
+                .collect::<Vec<_>>();
+            let limit = usize::from(uniform_sample(0, 16));
+            let with_values = rand::random::<bool>();
+            let actual = open_db
+                .block_storage_entries(
+                    &block0_hash,
+                    iter::empty::<iter::Empty<_>>(),
+                    prefix.iter().copied().map(u8::from),
+                    start_key.iter().copied().map(u8::from),
+                    limit,
+                    with_values,
+                )
+                .unwrap();
+            let expected = trie
+                .iter_ordered()
+                .filter_map(|n| {
+                    let value = trie[n].0.as_ref()?;
+                    let key = trie.node_full_key_by_index(n).unwrap().collect::<Vec<_>>();
+                    if key < start_key || !key.starts_with(&prefix) {
+                        return None;
+                    }
+                    Some(StorageEntry {
+                        key_nibbles: key.iter().copied().map(u8::from).collect(),
+                        value: if with_values {
+                            Some((value.clone(), 0))
+                        } else {
+                            None
+                        },
+                    })
+                })
+                .take(limit)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                actual,
+                expected,
+                "\nstart_key = {:?}\nprefix = {:?}\nlimit = {:?}\ntrie = {:?}",
+                start_key
+                    .iter()
+                    .map(|n| format!("{:x}", n))
+                    .collect::<String>(),
+                prefix
+                    .iter()
+                    .map(|n| format!("{:x}", n))
+                    .collect::<String>(),
+                limit,
+                trie
+            );
+        }
+
         // Ask random closest descendant Merkle values.
         for _ in 0..1024 {
             let key = (0..uniform_sample(0, 8))
@@ -336,6 +393,80 @@ fn unknown_block() {
         ),
         Err(StorageAccessError::UnknownBlock)
     ));
+
+    assert!(matches!(
+        db.block_storage_entries(
+            &[0xff; 32],
+            iter::empty::<iter::Empty<_>>(),
+            [].into_iter(),
+            [].into_iter(),
+            16,
+            true
+        ),
+        Err(StorageAccessError::UnknownBlock)
+    ));
+}
+
+#[test]
+fn finality_orphans_purged() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, iter::empty(), None)
+        .unwrap();
+
+    // Two competing children of the genesis block.
+    let block1a_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1a_hash = header::hash_from_scale_encoded_header(&block1a_header);
+    let block1b_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[1; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1b_hash = header::hash_from_scale_encoded_header(&block1b_header);
+
+    db.insert(&block1a_header, true, iter::empty::<Vec<u8>>())
+        .unwrap();
+    db.insert(&block1b_header, false, iter::empty::<Vec<u8>>())
+        .unwrap();
+
+    // Purging before finalizing doesn't remove anything.
+    db.purge_finality_orphans().unwrap();
+    assert!(db.block_parent(&block1a_hash).unwrap().is_some());
+    assert!(db.block_parent(&block1b_hash).unwrap().is_some());
+
+    db.set_finalized(&block1a_hash).unwrap();
+    db.purge_finality_orphans().unwrap();
+    assert_eq!(db.block_parent(&block1a_hash).unwrap(), Some(genesis_hash));
+    assert!(db.block_parent(&block1b_hash).unwrap().is_none());
+    assert_eq!(db.block_parent(&genesis_hash).unwrap(), Some([0; 32]));
 }
 
 #[test]
@@ -1064,3 +1195,1311 @@ fn storage_closest_descendant_merkle_value_partial() {
         None
     );
 }
+
+#[test]
+fn known_peers_round_trip() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    assert!(db.known_peers().unwrap().is_empty());
+
+    let peer1 = KnownPeer {
+        peer_id: vec![1, 2, 3],
+        addresses: vec![vec![4, 5], vec![6]],
+        reputation: 0,
+    };
+    let peer2 = KnownPeer {
+        peer_id: vec![7],
+        addresses: Vec::new(),
+        reputation: -50,
+    };
+    db.set_known_peers([peer1.clone(), peer2.clone()].into_iter())
+        .unwrap();
+
+    let mut peers = db.known_peers().unwrap();
+    peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+    for peer in &mut peers {
+        peer.addresses.sort();
+    }
+    assert_eq!(peers, vec![peer1, peer2.clone()]);
+
+    // Setting the list of peers again replaces the previous list.
+    db.set_known_peers(iter::once(peer2.clone())).unwrap();
+    assert_eq!(db.known_peers().unwrap(), vec![peer2]);
+}
+
+#[test]
+fn offchain_storage_compare_set() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(db.offchain_storage_get(b"foo").unwrap(), None);
+
+    assert!(db
+        .offchain_storage_compare_set(b"foo", Some(&b"bar"[..]), None)
+        .unwrap());
+    assert_eq!(
+        db.offchain_storage_get(b"foo").unwrap(),
+        Some(b"bar".to_vec())
+    );
+
+    // Wrong old value.
+    assert!(!db
+        .offchain_storage_compare_set(b"foo", Some(&b"baz"[..]), Some(None))
+        .unwrap());
+    assert!(!db
+        .offchain_storage_compare_set(b"foo", Some(&b"baz"[..]), Some(Some(&b"qux"[..])))
+        .unwrap());
+    assert_eq!(
+        db.offchain_storage_get(b"foo").unwrap(),
+        Some(b"bar".to_vec())
+    );
+
+    assert!(db
+        .offchain_storage_compare_set(b"foo", Some(&b"baz"[..]), Some(Some(&b"bar"[..])))
+        .unwrap());
+    assert_eq!(
+        db.offchain_storage_get(b"foo").unwrap(),
+        Some(b"baz".to_vec())
+    );
+
+    assert!(db.offchain_storage_compare_set(b"foo", None, None).unwrap());
+    assert_eq!(db.offchain_storage_get(b"foo").unwrap(), None);
+}
+
+#[test]
+fn statistics() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            [&b"foo"[..], &b"bar"[..]].into_iter(),
+            Some(vec![1, 2, 3]),
+        )
+        .unwrap();
+
+    let stats = db.statistics().unwrap();
+    assert_eq!(stats.finalized_block_number, 0);
+    assert_eq!(stats.oldest_block_with_state, Some(0));
+    assert_eq!(stats.oldest_block_with_body, Some(0));
+    assert_eq!(stats.num_justifications, 1);
+
+    let num_rows = |name| {
+        stats
+            .tables
+            .iter()
+            .find(|table| table.name == name)
+            .unwrap()
+            .num_rows
+    };
+    assert_eq!(num_rows("blocks"), 1);
+    assert_eq!(num_rows("blocks_body"), 2);
+    assert_eq!(num_rows("trie_node"), 0);
+    assert_eq!(num_rows("peers"), 0);
+}
+
+#[test]
+fn backup_then_open() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            [&b"foo"[..], &b"bar"[..]].into_iter(),
+            None,
+        )
+        .unwrap();
+
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("backup.sqlite");
+    db.backup(&path).unwrap();
+
+    let DatabaseOpen::Open(backup) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access: false,
+        },
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let finalized_block_hash = db.finalized_block_hash().unwrap();
+    assert_eq!(backup.finalized_block_hash().unwrap(), finalized_block_hash);
+    assert_eq!(
+        backup
+            .block_extrinsics(&finalized_block_hash)
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![b"foo".to_vec(), b"bar".to_vec()]
+    );
+}
+
+#[test]
+fn block_extrinsics_chunks() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let body = [vec![1; 10], vec![2; 5], vec![3; 20], vec![4; 1]];
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, body.iter().map(|e| &e[..]), None)
+        .unwrap();
+
+    assert_eq!(db.block_body_size(&genesis_hash).unwrap(), Some((4, 36)));
+    assert_eq!(db.block_body_size(&[0xff; 32]).unwrap(), None);
+    assert_eq!(db.block_extrinsics_chunk(&[0xff; 32], 0, 16).unwrap(), None);
+
+    // Reading the body chunk by chunk yields the same extrinsics as reading it at once.
+    let mut read = Vec::new();
+    loop {
+        let chunk = db
+            .block_extrinsics_chunk(&genesis_hash, read.len(), 12)
+            .unwrap()
+            .unwrap();
+        if chunk.is_empty() {
+            break;
+        }
+        read.extend(chunk);
+    }
+    assert_eq!(read, body);
+
+    // A chunk contains at least one extrinsic, even if it is larger than the maximum size.
+    assert_eq!(
+        db.block_extrinsics_chunk(&genesis_hash, 2, 1)
+            .unwrap()
+            .unwrap(),
+        vec![vec![3; 20]]
+    );
+    assert_eq!(
+        db.block_extrinsics_chunk(&genesis_hash, 0, 15)
+            .unwrap()
+            .unwrap(),
+        vec![vec![1; 10], vec![2; 5]]
+    );
+}
+
+#[test]
+fn authoring_stats_round_trip() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(db.authoring_stats().unwrap(), AuthoringStats::default());
+
+    let stats = AuthoringStats {
+        eligible_slots: 10,
+        authored_blocks: 8,
+        authored_blocks_best: 7,
+        authored_blocks_finalized: 6,
+        missed_slots_late: 1,
+        missed_slots_no_peers: 0,
+        missed_slots_error: 1,
+    };
+    db.set_authoring_stats(&stats).unwrap();
+    assert_eq!(db.authoring_stats().unwrap(), stats);
+
+    // The finalized block is stored in the same table and must not be affected.
+    assert_eq!(
+        db.finalized_block_hash().unwrap(),
+        db.best_block_hash().unwrap()
+    );
+}
+
+#[test]
+fn grandpa_voter_state_round_trip() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(db.grandpa_voter_state().unwrap(), None);
+
+    let state = GrandpaVoterState {
+        set_id: 3,
+        round_number: 12,
+        votes: vec![vec![1, 2, 3], Vec::new(), vec![0xff; 300]],
+    };
+    db.set_grandpa_voter_state(&state).unwrap();
+    assert_eq!(db.grandpa_voter_state().unwrap(), Some(state));
+
+    let state = GrandpaVoterState {
+        set_id: 3,
+        round_number: 13,
+        votes: Vec::new(),
+    };
+    db.set_grandpa_voter_state(&state).unwrap();
+    assert_eq!(db.grandpa_voter_state().unwrap(), Some(state));
+}
+
+#[test]
+fn storage_diff() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, iter::empty(), None)
+        .unwrap();
+
+    let block1_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[3; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1_hash = header::hash_from_scale_encoded_header(&block1_header);
+    db.insert(&block1_header, true, iter::empty::<Vec<u8>>())
+        .unwrap();
+
+    // Genesis block: `0x11` => `hello`, `0x112` => `foo`, `0x11111` => `world`.
+    // Block 1: `0x11` => `hello2`, `0x130` => `new`, `0x11111` => `world`. The root of the trie
+    // of block 1 is a branch node at `0x1`, and the node at `0x11111` is shared.
+    let mut genesis_root_children = array::from_fn(|_| None);
+    genesis_root_children[1] = Some(Cow::Borrowed(&[2; 32][..]));
+    genesis_root_children[2] = Some(Cow::Borrowed(&[4; 32][..]));
+    let mut block1_root_children = array::from_fn(|_| None);
+    block1_root_children[1] = Some(Cow::Borrowed(&[5; 32][..]));
+    block1_root_children[3] = Some(Cow::Borrowed(&[6; 32][..]));
+    let mut block1_node_5_children = array::from_fn(|_| None);
+    block1_node_5_children[1] = Some(Cow::Borrowed(&[2; 32][..]));
+    db.insert_trie_nodes(
+        [
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[1; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+                children_merkle_values: genesis_root_children,
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[2; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"world"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[4; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"foo"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[3; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1]),
+                children_merkle_values: block1_root_children,
+                storage_value: InsertTrieNodeStorageValue::NoValue,
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[5; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[]),
+                children_merkle_values: block1_node_5_children,
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello2"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[6; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[0]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"new"),
+                    references_merkle_value: false,
+                },
+            },
+        ]
+        .into_iter(),
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(
+        db.block_storage_diff(&genesis_hash, &block1_hash).unwrap(),
+        vec![
+            StorageDiffEntry {
+                key_nibbles: vec![1, 1],
+                old_value: Some((b"hello".to_vec(), 0)),
+                new_value: Some((b"hello2".to_vec(), 0)),
+            },
+            StorageDiffEntry {
+                key_nibbles: vec![1, 1, 2],
+                old_value: Some((b"foo".to_vec(), 0)),
+                new_value: None,
+            },
+            StorageDiffEntry {
+                key_nibbles: vec![1, 3, 0],
+                old_value: None,
+                new_value: Some((b"new".to_vec(), 0)),
+            },
+        ]
+    );
+
+    // Swapping the two blocks swaps the values.
+    assert_eq!(
+        db.block_storage_diff(&block1_hash, &genesis_hash).unwrap(),
+        db.block_storage_diff(&genesis_hash, &block1_hash)
+            .unwrap()
+            .into_iter()
+            .map(|entry| StorageDiffEntry {
+                key_nibbles: entry.key_nibbles,
+                old_value: entry.new_value,
+                new_value: entry.old_value,
+            })
+            .collect::<Vec<_>>()
+    );
+
+    assert!(db
+        .block_storage_diff(&block1_hash, &block1_hash)
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        db.block_storage_diff(&genesis_hash, &[0xff; 32]),
+        Err(StorageAccessError::UnknownBlock)
+    ));
+}
+
+#[test]
+fn trie_node_traversal() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, iter::empty(), None)
+        .unwrap();
+
+    let mut root_children = array::from_fn(|_| None);
+    root_children[3] = Some(Cow::Borrowed(&[2; 32][..]));
+    db.insert_trie_nodes(
+        [
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[1; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 2]),
+                children_merkle_values: root_children,
+                storage_value: InsertTrieNodeStorageValue::NoValue,
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[2; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[4]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello"),
+                    references_merkle_value: false,
+                },
+            },
+        ]
+        .into_iter(),
+        1,
+    )
+    .unwrap();
+
+    assert_eq!(
+        db.block_storage_root_merkle_value(&genesis_hash).unwrap(),
+        vec![1; 32]
+    );
+    assert!(matches!(
+        db.block_storage_root_merkle_value(&[0xff; 32]),
+        Err(StorageAccessError::UnknownBlock)
+    ));
+
+    let root = db.trie_node(&[1; 32]).unwrap();
+    assert_eq!(root.partial_key_nibbles, vec![1, 2]);
+    assert_eq!(root.storage_value, None);
+    assert_eq!(
+        root.children_merkle_values,
+        array::from_fn(|n| (n == 3).then(|| vec![2; 32]))
+    );
+
+    let child = db.trie_node(&[2; 32]).unwrap();
+    assert_eq!(child.partial_key_nibbles, vec![4]);
+    assert_eq!(child.storage_value, Some((b"hello".to_vec(), 1)));
+    assert!(child.children_merkle_values.iter().all(|c| c.is_none()));
+
+    assert!(matches!(
+        db.trie_node(&[3; 32]),
+        Err(StorageAccessError::IncompleteStorage)
+    ));
+}
+
+#[test]
+fn finalized_blocks_pruned() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, [&b"genesis"[..]].into_iter(), None)
+        .unwrap();
+
+    let block1_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[3; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1_hash = header::hash_from_scale_encoded_header(&block1_header);
+    db.insert(&block1_header, true, [b"block1".to_vec()].into_iter())
+        .unwrap();
+
+    // The roots of the tries of the two blocks differ, but share the same child.
+    let mut children_merkle_values = array::from_fn(|_| None);
+    children_merkle_values[1] = Some(Cow::Borrowed(&[2; 32][..]));
+    db.insert_trie_nodes(
+        [
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[1; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+                children_merkle_values: children_merkle_values.clone(),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[3; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+                children_merkle_values,
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello2"),
+                    references_merkle_value: false,
+                },
+            },
+            InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[2; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"world"),
+                    references_merkle_value: false,
+                },
+            },
+        ]
+        .into_iter(),
+        0,
+    )
+    .unwrap();
+
+    // Nothing is pruned as long as the blocks aren't old enough.
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |_| false).unwrap(),
+        0
+    );
+    db.set_finalized(&block1_hash).unwrap();
+    assert_eq!(
+        db.prune_finalized_blocks(1, true, 16, |_| false).unwrap(),
+        0
+    );
+
+    // Blocks for which `keep` returns `true` are skipped.
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |hash| *hash == genesis_hash)
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |_| false).unwrap(),
+        1
+    );
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |_| false).unwrap(),
+        0
+    );
+
+    assert!(matches!(
+        db.block_storage_get(
+            &genesis_hash,
+            iter::empty::<iter::Empty<_>>(),
+            [1, 1].into_iter(),
+        ),
+        Err(StorageAccessError::IncompleteStorage)
+    ));
+    assert!(db.block_extrinsics(&genesis_hash).unwrap().is_none());
+    assert_eq!(db.block_body_size(&genesis_hash).unwrap(), None);
+    assert!(db
+        .block_scale_encoded_header(&genesis_hash)
+        .unwrap()
+        .is_some());
+
+    // The storage of the finalized block, including the node that was shared with the pruned
+    // block, is still there.
+    assert_eq!(
+        db.block_storage_get(
+            &block1_hash,
+            iter::empty::<iter::Empty<_>>(),
+            [1, 1].into_iter(),
+        )
+        .unwrap()
+        .unwrap()
+        .0,
+        b"hello2"
+    );
+    assert_eq!(
+        db.block_storage_get(
+            &block1_hash,
+            iter::empty::<iter::Empty<_>>(),
+            [1, 1, 1, 1, 1].into_iter(),
+        )
+        .unwrap()
+        .unwrap()
+        .0,
+        b"world"
+    );
+    assert_eq!(
+        db.block_extrinsics(&block1_hash)
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![b"block1".to_vec()]
+    );
+}
+
+#[test]
+fn finalized_blocks_pruned_beyond_kept_blocks() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, [&b"genesis"[..]].into_iter(), None)
+        .unwrap();
+
+    let mut hashes = vec![genesis_hash];
+    for number in 1..=3 {
+        let header = header::HeaderRef {
+            number,
+            extrinsics_root: &[0; 32],
+            parent_hash: hashes.last().unwrap(),
+            state_root: &[1; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(4);
+        db.insert(&header, true, [b"body".to_vec()].into_iter())
+            .unwrap();
+        hashes.push(header::hash_from_scale_encoded_header(&header));
+    }
+    db.set_finalized(&hashes[3]).unwrap();
+
+    // The two oldest blocks are kept. Even though they are the first candidates, the block that
+    // follows them is still pruned.
+    let kept = [hashes[0], hashes[1]];
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 1, |hash| kept.contains(hash))
+            .unwrap(),
+        1
+    );
+    assert!(db.block_extrinsics(&hashes[2]).unwrap().is_none());
+    assert!(db.block_extrinsics(&hashes[0]).unwrap().is_some());
+    assert!(db.block_extrinsics(&hashes[1]).unwrap().is_some());
+
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |hash| kept.contains(hash))
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |_| false).unwrap(),
+        2
+    );
+}
+
+#[test]
+fn finalized_states_pruned_bodies_kept() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    let db = empty_db
+        .initialize(&genesis_header, [&b"genesis"[..]].into_iter(), None)
+        .unwrap();
+
+    db.insert_trie_nodes(
+        [InsertTrieNode {
+            merkle_value: Cow::Borrowed(&[1; 32]),
+            partial_key_nibbles: Cow::Borrowed(&[1, 1]),
+            children_merkle_values: array::from_fn(|_| None),
+            storage_value: InsertTrieNodeStorageValue::Value {
+                value: Cow::Borrowed(b"hello"),
+                references_merkle_value: false,
+            },
+        }]
+        .into_iter(),
+        0,
+    )
+    .unwrap();
+
+    let block1_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[2; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1_hash = header::hash_from_scale_encoded_header(&block1_header);
+    db.insert(&block1_header, true, [b"block1".to_vec()].into_iter())
+        .unwrap();
+    db.set_finalized(&block1_hash).unwrap();
+
+    assert_eq!(
+        db.prune_finalized_blocks(0, false, 16, |_| false).unwrap(),
+        1
+    );
+    assert_eq!(
+        db.prune_finalized_blocks(0, false, 16, |_| false).unwrap(),
+        0
+    );
+
+    // The storage of the genesis block is gone, including its trie nodes, but its body is kept.
+    assert!(matches!(
+        db.block_storage_get(
+            &genesis_hash,
+            iter::empty::<iter::Empty<_>>(),
+            [1, 1].into_iter(),
+        ),
+        Err(StorageAccessError::IncompleteStorage)
+    ));
+    assert_eq!(
+        db.statistics()
+            .unwrap()
+            .tables
+            .iter()
+            .find(|t| t.name == "trie_node")
+            .unwrap()
+            .num_rows,
+        0
+    );
+    assert_eq!(
+        db.block_extrinsics(&genesis_hash)
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![b"genesis".to_vec()]
+    );
+}
+
+#[test]
+fn rollback_journal_and_page_size() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("database.sqlite");
+
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Delete,
+            synchronous: Synchronous::Full,
+            page_size: Some(8192),
+            shared_access: false,
+        },
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+    let finalized_block_hash = db.finalized_block_hash().unwrap();
+    drop(db);
+
+    // The page size is found at offset 16 of the header of the database file, in big endian.
+    let file = std::fs::read(&path).unwrap();
+    assert_eq!(u16::from_be_bytes([file[16], file[17]]), 8192);
+    assert!(!directory.path().join("database.sqlite-wal").exists());
+
+    // Switching back to the WAL mode keeps the content of the database.
+    let DatabaseOpen::Open(db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access: false,
+        },
+    })
+    .unwrap() else {
+        panic!()
+    };
+    assert_eq!(db.finalized_block_hash().unwrap(), finalized_block_hash);
+}
+
+#[test]
+fn read_only_connection() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("database.sqlite");
+    let config = || Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access: true,
+        },
+    };
+
+    let DatabaseOpen::Empty(empty_db) = open(config()).unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+    let db = empty_db
+        .initialize(&genesis_header, iter::empty(), None)
+        .unwrap();
+
+    let read_only_db = open_read_only(config()).unwrap();
+    assert_eq!(read_only_db.finalized_block_hash().unwrap(), genesis_hash);
+
+    // Blocks inserted through the writing connection are visible through the read-only one.
+    let block1_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1_hash = header::hash_from_scale_encoded_header(&block1_header);
+    db.insert(&block1_header, true, iter::empty::<Vec<u8>>())
+        .unwrap();
+    assert_eq!(read_only_db.best_block_hash().unwrap(), block1_hash);
+
+    // Modifications through the read-only connection fail.
+    assert!(read_only_db.set_finalized(&block1_hash).is_err());
+    assert_eq!(db.finalized_block_hash().unwrap(), genesis_hash);
+}
+
+#[test]
+fn shared_access_single_writer() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("database.sqlite");
+    let config = || Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access: true,
+        },
+    };
+
+    let DatabaseOpen::Empty(empty_db) = open(config()).unwrap() else {
+        panic!()
+    };
+
+    // The database can't be opened a second time for writing, even before being initialized.
+    assert!(open(config()).is_err());
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+    assert!(open(config()).is_err());
+
+    // Reading the database is still possible.
+    let read_only_db = open_read_only(config()).unwrap();
+    assert_eq!(
+        read_only_db.finalized_block_hash().unwrap(),
+        db.finalized_block_hash().unwrap()
+    );
+
+    // The lock is released when the database is closed.
+    drop(db);
+    assert!(matches!(open(config()).unwrap(), DatabaseOpen::Open(_)));
+}
+
+#[test]
+fn shared_and_exclusive_access_conflict() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("database.sqlite");
+    let config = |shared_access| Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access,
+        },
+    };
+
+    let DatabaseOpen::Empty(empty_db) = open(config(true)).unwrap() else {
+        panic!()
+    };
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    // A database opened in shared mode can't be opened in exclusive mode.
+    assert!(open(config(false)).is_err());
+    drop(db);
+
+    // A database opened in exclusive mode can't be opened in shared mode.
+    let DatabaseOpen::Open(db) = open(config(false)).unwrap() else {
+        panic!()
+    };
+    assert!(open(config(true)).is_err());
+    drop(db);
+}
+
+#[test]
+fn incremental_vacuum_after_pruning() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let genesis_header = header::HeaderRef {
+        number: 0,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0; 32],
+        state_root: &[1; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let genesis_hash = header::hash_from_scale_encoded_header(&genesis_header);
+
+    // The body of the genesis block is large, in order for its pruning to free many pages.
+    let genesis_body = (0..64u8).map(|n| vec![n; 16 * 1024]).collect::<Vec<_>>();
+    let db = empty_db
+        .initialize(&genesis_header, genesis_body.iter().map(|e| &e[..]), None)
+        .unwrap();
+
+    // Nothing to give back yet.
+    let outcome = db
+        .incremental_vacuum(NonZeroU32::new(u32::MAX).unwrap())
+        .unwrap();
+    assert_eq!(outcome.freed_bytes, 0);
+    assert_eq!(outcome.remaining_free_bytes, 0);
+
+    let block1_header = header::HeaderRef {
+        number: 1,
+        extrinsics_root: &[0; 32],
+        parent_hash: &genesis_hash,
+        state_root: &[2; 32],
+        digest: header::DigestRef::empty(),
+    }
+    .scale_encoding_vec(4);
+    let block1_hash = header::hash_from_scale_encoded_header(&block1_header);
+    db.insert(&block1_header, true, [b"block1".to_vec()].into_iter())
+        .unwrap();
+    db.set_finalized(&block1_hash).unwrap();
+    assert_eq!(
+        db.prune_finalized_blocks(0, true, 16, |_| false).unwrap(),
+        1
+    );
+
+    // The pages are given back progressively.
+    let first = db.incremental_vacuum(NonZeroU32::new(1).unwrap()).unwrap();
+    assert!(first.freed_bytes > 0);
+    assert!(first.freed_bytes + first.remaining_free_bytes >= 64 * 16 * 1024);
+
+    let second = db
+        .incremental_vacuum(NonZeroU32::new(u32::MAX).unwrap())
+        .unwrap();
+    assert_eq!(second.freed_bytes, first.remaining_free_bytes);
+    assert_eq!(second.remaining_free_bytes, 0);
+}
+
+#[test]
+fn trie_nodes_inserted_before_initialization() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("database.sqlite");
+    let config = || Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Disk {
+            path: &path,
+            memory_map_size: 0,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            page_size: None,
+            shared_access: false,
+        },
+    };
+
+    let DatabaseOpen::Empty(mut empty_db) = open(config()).unwrap() else {
+        panic!()
+    };
+
+    empty_db
+        .insert_trie_nodes(
+            [InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[1; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"hello"),
+                    references_merkle_value: false,
+                },
+            }]
+            .into_iter(),
+            0,
+        )
+        .unwrap();
+
+    // Simulates the node being interrupted before the database is initialized.
+    drop(empty_db);
+    let DatabaseOpen::Empty(empty_db) = open(config()).unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(
+        db.block_storage_get(
+            &db.block_hash_by_number(0).unwrap().next().unwrap(),
+            iter::empty::<iter::Empty<_>>(),
+            [].into_iter(),
+        )
+        .unwrap()
+        .unwrap()
+        .0,
+        b"hello"
+    );
+}
+
+#[test]
+fn block_justifications() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let db = empty_db
+        .initialize(
+            &header::HeaderRef {
+                number: 0,
+                extrinsics_root: &[0; 32],
+                parent_hash: &[0; 32],
+                state_root: &[1; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec(4),
+            iter::empty(),
+            None,
+        )
+        .unwrap();
+
+    let block_hash = db.block_hash_by_number(0).unwrap().next().unwrap();
+    assert_eq!(
+        db.block_justifications(&block_hash).unwrap(),
+        Some(Vec::new())
+    );
+    assert!(db.block_justifications(&[0xff; 32]).unwrap().is_none());
+
+    db.set_block_justification(&block_hash, *b"FRNK", b"foo")
+        .unwrap();
+    db.set_block_justification(&block_hash, *b"BEEF", b"bar")
+        .unwrap();
+    // Replaces the previous justification of the same consensus engine.
+    db.set_block_justification(&block_hash, *b"FRNK", b"baz")
+        .unwrap();
+
+    assert_eq!(
+        db.block_justifications(&block_hash).unwrap(),
+        Some(vec![
+            (*b"BEEF", b"bar".to_vec()),
+            (*b"FRNK", b"baz".to_vec())
+        ])
+    );
+    assert_eq!(db.statistics().unwrap().num_justifications, 1);
+
+    assert!(matches!(
+        db.set_block_justification(&[0xff; 32], *b"FRNK", b"foo"),
+        Err(SetJustificationError::UnknownBlock)
+    ));
+}
@@ -309,6 +309,17 @@ struct VmCommon {
 impl HostVmPrototype {
     /// Creates a new [`HostVmPrototype`]. Parses and potentially JITs the module.
     pub fn new(config: Config<impl AsRef<[u8]>>) -> Result<Self, NewErr> {
+        Self::new_with_compiled_modules_cache(config, None)
+    }
+
+    /// Same as [`HostVmPrototype::new`], but the module is loaded from the given cache of
+    /// compiled modules if possible, and stored in it after it has been compiled otherwise.
+    ///
+    /// See [`vm::CompiledModulesCache`].
+    pub fn new_with_compiled_modules_cache(
+        config: Config<impl AsRef<[u8]>>,
+        compiled_modules_cache: Option<&dyn vm::CompiledModulesCache>,
+    ) -> Result<Self, NewErr> {
         // The maximum allowed size for the decompressed Wasm code needs to be the same amongst
         // all implementations.
         // See <https://github.com/paritytech/substrate/blob/f9d10fabe04d598d68f8b097cc4905adbb1ad630/primitives/maybe-compressed-blob/src/lib.rs#L37>.
@@ -353,30 +364,33 @@ impl HostVmPrototype {
         // array.
         let (mut vm_proto, registered_functions) = {
             let mut registered_functions = Vec::new();
-            let vm_proto = vm::VirtualMachinePrototype::new(vm::Config {
-                module_bytes: &module_bytes[..],
-                exec_hint: config.exec_hint,
-                // This closure is called back for each function that the runtime imports.
-                symbols: &mut |mod_name, f_name, signature| {
-                    if mod_name != "env" {
-                        return Err(());
-                    }
-
-                    let id = registered_functions.len();
-                    registered_functions.push(match HostFunction::by_name(f_name) {
-                        Some(f) if f.signature() == *signature => FunctionImport::Resolved(f),
-                        Some(_) | None if !config.allow_unresolved_imports => {
-                            // TODO: return a better error if there is a signature mismatch
+            let vm_proto = vm::VirtualMachinePrototype::new_with_compiled_modules_cache(
+                vm::Config {
+                    module_bytes: &module_bytes[..],
+                    exec_hint: config.exec_hint,
+                    // This closure is called back for each function that the runtime imports.
+                    symbols: &mut |mod_name, f_name, signature| {
+                        if mod_name != "env" {
                             return Err(());
                         }
-                        Some(_) | None => FunctionImport::Unresolved {
-                            name: f_name.to_owned(),
-                            module: mod_name.to_owned(),
-                        },
-                    });
-                    Ok(id)
+
+                        let id = registered_functions.len();
+                        registered_functions.push(match HostFunction::by_name(f_name) {
+                            Some(f) if f.signature() == *signature => FunctionImport::Resolved(f),
+                            Some(_) | None if !config.allow_unresolved_imports => {
+                                // TODO: return a better error if there is a signature mismatch
+                                return Err(());
+                            }
+                            Some(_) | None => FunctionImport::Unresolved {
+                                name: f_name.to_owned(),
+                                module: mod_name.to_owned(),
+                            },
+                        });
+                        Ok(id)
+                    },
                 },
-            })?;
+                compiled_modules_cache,
+            )?;
             (vm_proto, registered_functions.into())
         };
 
@@ -667,6 +681,24 @@ pub enum HostVm {
     /// Submit a transaction from offchain worker.
     #[from]
     OffchainSubmitTransaction(OffchainSubmitTransaction),
+    /// Must start an HTTP request from offchain worker.
+    #[from]
+    OffchainHttpRequestStart(OffchainHttpRequestStart),
+    /// Must add a header to an HTTP request that hasn't been sent yet.
+    #[from]
+    OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader),
+    /// Must write a chunk of the body of an HTTP request.
+    #[from]
+    OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody),
+    /// Must wait for the responses to a list of HTTP requests.
+    #[from]
+    OffchainHttpResponseWait(OffchainHttpResponseWait),
+    /// Must provide the headers of the response to an HTTP request.
+    #[from]
+    OffchainHttpResponseHeaders(OffchainHttpResponseHeaders),
+    /// Must read a chunk of the body of the response to an HTTP request.
+    #[from]
+    OffchainHttpResponseReadBody(OffchainHttpResponseReadBody),
     /// Need to verify whether a signature is valid.
     #[from]
     SignatureVerification(SignatureVerification),
@@ -717,6 +749,12 @@ impl HostVm {
             HostVm::OffchainTimestamp(inner) => inner.inner.into_prototype(),
             HostVm::OffchainRandomSeed(inner) => inner.inner.into_prototype(),
             HostVm::OffchainSubmitTransaction(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestStart(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestAddHeader(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpRequestWriteBody(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseWait(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseHeaders(inner) => inner.inner.into_prototype(),
+            HostVm::OffchainHttpResponseReadBody(inner) => inner.inner.into_prototype(),
             HostVm::SignatureVerification(inner) => inner.inner.into_prototype(),
             HostVm::CallRuntimeVersion(inner) => inner.inner.into_prototype(),
             HostVm::StartStorageTransaction(inner) => inner.inner.into_prototype(),
@@ -1013,6 +1051,38 @@ impl ReadyToRun {
             }};
         }
 
+        // Passed a parameter index. Produces the `u16` identifier of an offchain HTTP request.
+        macro_rules! expect_http_request_id {
+            ($num:expr) => {{
+                match u16::try_from(expect_u32!($num)) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
+        // Passed a parameter index. Produces the deadline of an offchain HTTP operation, in
+        // milliseconds since the UNIX epoch.
+        macro_rules! expect_http_deadline {
+            ($num:expr) => {{
+                let deadline = decode_http_deadline(expect_pointer_size!($num).as_ref());
+                match deadline {
+                    Ok(deadline) => deadline,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                }
+            }};
+        }
+
         // TODO: implement all functions and remove this macro
         macro_rules! host_fn_not_implemented {
             () => {{
@@ -1963,19 +2033,96 @@ impl ReadyToRun {
                     })
                 }
             }
-            HostFunction::ext_offchain_http_request_start_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_offchain_http_request_start_version_1 => {
+                let (method_ptr, method_size) = expect_pointer_size_raw!(0);
+                let (uri_ptr, uri_size) = expect_pointer_size_raw!(1);
+                // The third parameter, the request metadata, is unused in Substrate and is
+                // thus ignored.
+                let _ = expect_pointer_size_raw!(2);
+                HostVm::OffchainHttpRequestStart(OffchainHttpRequestStart {
+                    inner: self.inner,
+                    calling: id,
+                    method_ptr,
+                    method_size,
+                    uri_ptr,
+                    uri_size,
+                })
+            }
             HostFunction::ext_offchain_http_request_add_header_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (name_ptr, name_size) = expect_pointer_size_raw!(1);
+                let (value_ptr, value_size) = expect_pointer_size_raw!(2);
+                HostVm::OffchainHttpRequestAddHeader(OffchainHttpRequestAddHeader {
+                    inner: self.inner,
+                    calling: id,
+                    request_id,
+                    name_ptr,
+                    name_size,
+                    value_ptr,
+                    value_size,
+                })
             }
             HostFunction::ext_offchain_http_request_write_body_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (chunk_ptr, chunk_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_http_deadline!(2);
+                HostVm::OffchainHttpRequestWriteBody(OffchainHttpRequestWriteBody {
+                    inner: self.inner,
+                    calling: id,
+                    request_id,
+                    chunk_ptr,
+                    chunk_size,
+                    deadline,
+                })
+            }
+            HostFunction::ext_offchain_http_response_wait_version_1 => {
+                let request_ids = {
+                    let input = expect_pointer_size!(0);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(nom::multi::length_count(
+                            crate::util::nom_scale_compact_usize,
+                            nom::number::streaming::le_u16,
+                        ))(input.as_ref())
+                        .map(|(_, ids)| ids);
+                    parsing_result.map_err(|_| ())
+                };
+                let request_ids = match request_ids {
+                    Ok(ids) => ids,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        }
+                    }
+                };
+                let deadline = expect_http_deadline!(1);
+                HostVm::OffchainHttpResponseWait(OffchainHttpResponseWait {
+                    inner: self.inner,
+                    calling: id,
+                    request_ids,
+                    deadline,
+                })
             }
-            HostFunction::ext_offchain_http_response_wait_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_offchain_http_response_headers_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                HostVm::OffchainHttpResponseHeaders(OffchainHttpResponseHeaders {
+                    inner: self.inner,
+                    calling: id,
+                    request_id,
+                })
             }
             HostFunction::ext_offchain_http_response_read_body_version_1 => {
-                host_fn_not_implemented!()
+                let request_id = expect_http_request_id!(0);
+                let (buffer_ptr, buffer_size) = expect_pointer_size_raw!(1);
+                let deadline = expect_http_deadline!(2);
+                HostVm::OffchainHttpResponseReadBody(OffchainHttpResponseReadBody {
+                    inner: self.inner,
+                    calling: id,
+                    request_id,
+                    buffer_ptr,
+                    buffer_size,
+                    deadline,
+                })
             }
             HostFunction::ext_trie_blake2_256_root_version_1
             | HostFunction::ext_trie_blake2_256_root_version_2
@@ -3511,6 +3658,455 @@ impl fmt::Debug for OffchainSubmitTransaction {
     }
 }
 
+/// Must start an HTTP request.
+///
+/// The request isn't supposed to be sent yet, as headers might still be added with
+/// [`HostVm::OffchainHttpRequestAddHeader`].
+pub struct OffchainHttpRequestStart {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Pointer to the HTTP method. Guaranteed to be in range.
+    method_ptr: u32,
+    /// Size of the HTTP method. Guaranteed to be in range.
+    method_size: u32,
+    /// Pointer to the URI of the request. Guaranteed to be in range.
+    uri_ptr: u32,
+    /// Size of the URI of the request. Guaranteed to be in range.
+    uri_size: u32,
+}
+
+impl OffchainHttpRequestStart {
+    /// Returns the HTTP method of the request, such as `GET` or `POST`.
+    ///
+    /// The runtime is supposed to pass a UTF-8 string, but this isn't guaranteed.
+    pub fn method(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.method_ptr, self.method_size)
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Returns the URI of the request.
+    ///
+    /// The runtime is supposed to pass a UTF-8 string, but this isn't guaranteed.
+    pub fn uri(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.uri_ptr, self.uri_size)
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Resumes execution after having started the request. Must be passed the identifier of the
+    /// newly-created request, or `None` if the request couldn't be started.
+    pub fn resume(self, request_id: Option<u16>) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Result<u16, ()>`.
+        match request_id {
+            Some(request_id) => self.inner.alloc_write_and_return_pointer_size(
+                host_fn.name(),
+                [&[0x00][..], &request_id.to_le_bytes()[..]].into_iter(),
+            ),
+            None => self
+                .inner
+                .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&[0x01])),
+        }
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestStart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestStart").finish()
+    }
+}
+
+/// Must add a header to an HTTP request that has been started with
+/// [`HostVm::OffchainHttpRequestStart`].
+pub struct OffchainHttpRequestAddHeader {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Identifier of the request.
+    request_id: u16,
+    /// Pointer to the name of the header. Guaranteed to be in range.
+    name_ptr: u32,
+    /// Size of the name of the header. Guaranteed to be in range.
+    name_size: u32,
+    /// Pointer to the value of the header. Guaranteed to be in range.
+    value_ptr: u32,
+    /// Size of the value of the header. Guaranteed to be in range.
+    value_size: u32,
+}
+
+impl OffchainHttpRequestAddHeader {
+    /// Returns the identifier of the request, as passed to
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the name of the header to add.
+    pub fn name(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.name_ptr, self.name_size)
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Returns the value of the header to add.
+    pub fn value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.value_ptr, self.value_size)
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Resumes execution. Must indicate whether the header has been added, which isn't the case
+    /// if the request identifier is invalid or if the request has already been sent.
+    pub fn resume(self, success: bool) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Result<(), ()>`.
+        self.inner.alloc_write_and_return_pointer_size(
+            host_fn.name(),
+            if success {
+                iter::once(&[0x00])
+            } else {
+                iter::once(&[0x01])
+            },
+        )
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestAddHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestAddHeader").finish()
+    }
+}
+
+/// Must write a chunk of the body of an HTTP request.
+///
+/// An empty chunk indicates that the body is complete and that the request can be sent.
+pub struct OffchainHttpRequestWriteBody {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Identifier of the request.
+    request_id: u16,
+    /// Pointer to the chunk to write. Guaranteed to be in range.
+    chunk_ptr: u32,
+    /// Size of the chunk to write. Guaranteed to be in range.
+    chunk_size: u32,
+    /// Deadline, in milliseconds since the UNIX epoch.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpRequestWriteBody {
+    /// Returns the identifier of the request, as passed to
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the chunk of body to write. Empty if the body is complete.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        self.inner
+            .vm
+            .read_memory(self.chunk_ptr, self.chunk_size)
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which the operation must
+    /// be interrupted with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having written the chunk.
+    pub fn resume(self, result: Result<(), HttpError>) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Result<(), HttpError>`.
+        match result {
+            Ok(()) => self
+                .inner
+                .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&[0x00])),
+            Err(error) => self.inner.alloc_write_and_return_pointer_size(
+                host_fn.name(),
+                iter::once(&[0x01, error.scale_encoding()]),
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for OffchainHttpRequestWriteBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpRequestWriteBody").finish()
+    }
+}
+
+/// Must wait until the responses to a list of HTTP requests have been received, or until a
+/// deadline is reached.
+///
+/// Requests that haven't been sent yet must be sent.
+pub struct OffchainHttpResponseWait {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Identifiers of the requests to wait for.
+    request_ids: Vec<u16>,
+    /// Deadline, in milliseconds since the UNIX epoch.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpResponseWait {
+    /// Returns the identifiers of the requests whose response must be waited for.
+    pub fn request_ids(&self) -> &[u16] {
+        &self.request_ids
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which waiting must be
+    /// interrupted. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having waited. Must be passed the status of each request, in
+    /// the same order as [`OffchainHttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses isn't equal to the number of requests.
+    ///
+    pub fn resume(self, statuses: &[HttpRequestStatus]) -> HostVm {
+        assert_eq!(statuses.len(), self.request_ids.len());
+
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Vec<HttpRequestStatus>`.
+        let mut encoded = util::encode_scale_compact_usize(statuses.len())
+            .as_ref()
+            .to_vec();
+        for status in statuses {
+            match status {
+                HttpRequestStatus::DeadlineReached => encoded.push(0),
+                HttpRequestStatus::IoError => encoded.push(1),
+                HttpRequestStatus::Invalid => encoded.push(2),
+                HttpRequestStatus::Finished(status_code) => {
+                    encoded.push(3);
+                    encoded.extend_from_slice(&status_code.to_le_bytes());
+                }
+            }
+        }
+
+        self.inner
+            .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&encoded))
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseWait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseWait").finish()
+    }
+}
+
+/// Must provide the headers of the response to an HTTP request.
+pub struct OffchainHttpResponseHeaders {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Identifier of the request.
+    request_id: u16,
+}
+
+impl OffchainHttpResponseHeaders {
+    /// Returns the identifier of the request, as passed to
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Resumes execution after having obtained the headers of the response, as a list of names
+    /// and values.
+    ///
+    /// The list must be empty if the identifier is invalid or if the response hasn't been
+    /// received yet.
+    pub fn resume<'a>(
+        self,
+        headers: impl ExactSizeIterator<Item = (&'a [u8], &'a [u8])>,
+    ) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Vec<(Vec<u8>, Vec<u8>)>`.
+        let mut encoded = util::encode_scale_compact_usize(headers.len())
+            .as_ref()
+            .to_vec();
+        for (name, value) in headers {
+            encoded.extend_from_slice(util::encode_scale_compact_usize(name.len()).as_ref());
+            encoded.extend_from_slice(name);
+            encoded.extend_from_slice(util::encode_scale_compact_usize(value.len()).as_ref());
+            encoded.extend_from_slice(value);
+        }
+
+        self.inner
+            .alloc_write_and_return_pointer_size(host_fn.name(), iter::once(&encoded))
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseHeaders").finish()
+    }
+}
+
+/// Must read a chunk of the body of the response to an HTTP request.
+///
+/// If the response hasn't been received yet, it must be waited for until the deadline.
+pub struct OffchainHttpResponseReadBody {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Identifier of the request.
+    request_id: u16,
+    /// Pointer to the buffer where to write the body. Guaranteed to be in range.
+    buffer_ptr: u32,
+    /// Size of the buffer where to write the body. Guaranteed to be in range.
+    buffer_size: u32,
+    /// Deadline, in milliseconds since the UNIX epoch.
+    deadline: Option<u64>,
+}
+
+impl OffchainHttpResponseReadBody {
+    /// Returns the identifier of the request, as passed to
+    /// [`OffchainHttpRequestStart::resume`].
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Returns the maximum number of bytes that can be passed to
+    /// [`OffchainHttpResponseReadBody::resume`].
+    pub fn buffer_size(&self) -> usize {
+        usize::try_from(self.buffer_size).unwrap_or(usize::MAX)
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which the operation must
+    /// be interrupted with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Resumes execution after having read a chunk of the body. An empty chunk indicates that
+    /// the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is larger than [`OffchainHttpResponseReadBody::buffer_size`].
+    ///
+    pub fn resume(mut self, result: Result<&[u8], HttpError>) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+
+        // Write a SCALE-encoded `Result<u32, HttpError>`.
+        match result {
+            Ok(chunk) => {
+                assert!(chunk.len() <= self.buffer_size());
+                self.inner
+                    .vm
+                    .write_memory(self.buffer_ptr, chunk)
+                    .unwrap_or_else(|_| unreachable!());
+                let chunk_len = u32::try_from(chunk.len()).unwrap_or_else(|_| unreachable!());
+                self.inner.alloc_write_and_return_pointer_size(
+                    host_fn.name(),
+                    [&[0x00][..], &chunk_len.to_le_bytes()[..]].into_iter(),
+                )
+            }
+            Err(error) => self.inner.alloc_write_and_return_pointer_size(
+                host_fn.name(),
+                iter::once(&[0x01, error.scale_encoding()]),
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for OffchainHttpResponseReadBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OffchainHttpResponseReadBody").finish()
+    }
+}
+
+/// Error that can happen during an offchain HTTP operation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HttpError {
+    /// The deadline has been reached before the operation could finish.
+    DeadlineReached,
+    /// An error has occurred during the request, for example a timeout or the remote having
+    /// closed the connection. The request must then be considered as destroyed.
+    IoError,
+    /// The request identifier is invalid in this context.
+    Invalid,
+}
+
+impl HttpError {
+    /// Returns the SCALE encoding of the error, as expected by the runtime.
+    fn scale_encoding(self) -> u8 {
+        match self {
+            HttpError::DeadlineReached => 1,
+            HttpError::IoError => 2,
+            HttpError::Invalid => 3,
+        }
+    }
+}
+
+/// Status of an offchain HTTP request. See [`OffchainHttpResponseWait::resume`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HttpRequestStatus {
+    /// The deadline has been reached before the response could be received.
+    DeadlineReached,
+    /// An error has occurred during the request, for example a timeout or the remote having
+    /// closed the connection. The request must then be considered as destroyed.
+    IoError,
+    /// The request identifier is invalid in this context.
+    Invalid,
+    /// The response has been received. Contains its status code.
+    Finished(u16),
+}
+
 /// Report about a log entry being emitted.
 ///
 /// Use [`LogEmit::info`] to obtain what must be printed.
@@ -4126,6 +4722,17 @@ pub enum Error {
     },
 }
 
+/// Decodes the SCALE-encoded `Option<u64>` deadline passed to the offchain HTTP host functions.
+fn decode_http_deadline(encoded: &[u8]) -> Result<Option<u64>, ()> {
+    match encoded {
+        [0] => Ok(None),
+        [1, rest @ ..] => Ok(Some(u64::from_le_bytes(
+            <[u8; 8]>::try_from(rest).map_err(|_| ())?,
+        ))),
+        _ => Err(()),
+    }
+}
+
 // Glue between the `allocator` module and the `vm` module.
 //
 // The allocator believes that there are `memory_total_pages` pages available and allocated, where
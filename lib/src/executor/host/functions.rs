@@ -388,7 +388,7 @@ impl HostFunction {
                 crate::signature!((vm::ValueType::I64, vm::ValueType::I64) => vm::ValueType::I64)
             }
             HostFunction::ext_offchain_http_response_headers_version_1 => {
-                crate::signature!((vm::ValueType::I64) => vm::ValueType::I64)
+                crate::signature!((vm::ValueType::I32) => vm::ValueType::I64)
             }
             HostFunction::ext_offchain_http_response_read_body_version_1 => {
                 crate::signature!((vm::ValueType::I32, vm::ValueType::I64, vm::ValueType::I64) => vm::ValueType::I64)
@@ -21,6 +21,7 @@ use super::{vm::ExecHint, Config, HeapPages, HostVm, HostVmPrototype, StoragePro
 
 mod hash_algorithms;
 mod initialization;
+mod offchain_http;
 mod run;
 
 /*
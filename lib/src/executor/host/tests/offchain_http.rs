@@ -0,0 +1,140 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::super::{
+    vm::ExecHint, Config, HeapPages, HostVm, HostVmPrototype, HttpRequestStatus,
+    StorageProofSizeBehavior,
+};
+use super::with_core_version_custom_sections;
+
+/// Module that starts a `GET` request to `http://example.com`, then either reads 16 bytes of
+/// its body or waits for it.
+///
+/// The `read_body` function returns the output of `ext_offchain_http_response_read_body_version_1`,
+/// the `read_body_buffer` function returns the buffer that has been filled, and the `wait`
+/// function returns the output of `ext_offchain_http_response_wait_version_1`.
+fn module_bytes() -> Vec<u8> {
+    with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (import "env" "ext_offchain_http_request_start_version_1"
+            (func $start (param i64 i64 i64) (result i64)))
+        (import "env" "ext_offchain_http_response_read_body_version_1"
+            (func $read_body (param i32 i64 i64) (result i64)))
+        (import "env" "ext_offchain_http_response_wait_version_1"
+            (func $wait (param i64 i64) (result i64)))
+        (memory (export "memory") 17)
+        (global (export "__heap_base") i32 (i32.const 4096))
+        (data (i32.const 1000) "GET")
+        (data (i32.const 1100) "http://example.com")
+        (data (i32.const 1200) "\00")
+        (data (i32.const 1300) "\04\00\00")
+        (func (export "read_body") (param i32 i32) (result i64)
+            (drop (call $start (i64.const 12884902888) (i64.const 77309412428) (i64.const 0)))
+            (call $read_body (i32.const 0) (i64.const 68719478784) (i64.const 4294968496)))
+        (func (export "read_body_buffer") (param i32 i32) (result i64)
+            (drop (call $start (i64.const 12884902888) (i64.const 77309412428) (i64.const 0)))
+            (drop (call $read_body (i32.const 0) (i64.const 68719478784) (i64.const 4294968496)))
+            i64.const 21474838528)
+        (func (export "wait") (param i32 i32) (result i64)
+            (drop (call $start (i64.const 12884902888) (i64.const 77309412428) (i64.const 0)))
+            (call $wait (i64.const 12884903188) (i64.const 4294968496)))
+    )
+    "#,
+        )
+        .unwrap(),
+    )
+}
+
+/// Runs the given function of [`module_bytes`], answering the offchain HTTP host functions,
+/// and returns its output with each of the available execution engines.
+fn run(function: &str) -> Vec<Vec<u8>> {
+    let module_bytes = module_bytes();
+    let mut outputs = Vec::new();
+
+    for exec_hint in ExecHint::available_engines() {
+        let proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(
+            proto
+                .run(
+                    function,
+                    StorageProofSizeBehavior::proof_recording_disabled(),
+                    &[],
+                )
+                .unwrap(),
+        );
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::OffchainHttpRequestStart(req) => {
+                    assert_eq!(req.method().as_ref(), b"GET");
+                    assert_eq!(req.uri().as_ref(), b"http://example.com");
+                    vm = req.resume(Some(0));
+                }
+                HostVm::OffchainHttpResponseReadBody(req) => {
+                    assert_eq!(req.request_id(), 0);
+                    assert_eq!(req.buffer_size(), 16);
+                    assert_eq!(req.deadline(), None);
+                    vm = req.resume(Ok(b"hello"));
+                }
+                HostVm::OffchainHttpResponseWait(req) => {
+                    assert_eq!(req.request_ids(), &[0]);
+                    assert_eq!(req.deadline(), None);
+                    vm = req.resume(&[HttpRequestStatus::Finished(200)]);
+                }
+                HostVm::Finished(out) => {
+                    outputs.push(out.value().as_ref().to_vec());
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    outputs
+}
+
+#[test]
+fn read_body_output() {
+    for output in run("read_body") {
+        // SCALE-encoded `Ok(5u32)`.
+        assert_eq!(output, [0, 5, 0, 0, 0]);
+    }
+}
+
+#[test]
+fn read_body_writes_buffer() {
+    for output in run("read_body_buffer") {
+        assert_eq!(output, b"hello");
+    }
+}
+
+#[test]
+fn response_wait_output() {
+    for output in run("wait") {
+        // SCALE-encoded `vec![HttpRequestStatus::Finished(200)]`.
+        assert_eq!(output, [4, 3, 200, 0]);
+    }
+}
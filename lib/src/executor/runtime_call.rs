@@ -52,7 +52,8 @@ use alloc::{
 use core::{fmt, iter, ops};
 
 pub use host::{
-    Error as ErrorDetail, LogEmitInfo, LogEmitInfoHex, LogEmitInfoStr, StorageProofSizeBehavior,
+    Error as ErrorDetail, HttpError, HttpRequestStatus, LogEmitInfo, LogEmitInfoHex,
+    LogEmitInfoStr, StorageProofSizeBehavior,
 };
 pub use trie::{Nibble, TrieEntryVersion};
 
@@ -554,6 +555,18 @@ pub enum OffchainContext {
     RandomSeed(OffchainRandomSeed),
     /// Submit transaction from offchain worker.
     SubmitTransaction(OffchainSubmitTransaction),
+    /// Start an HTTP request from offchain worker.
+    HttpRequestStart(OffchainHttpRequestStart),
+    /// Add a header to an HTTP request that hasn't been sent yet.
+    HttpRequestAddHeader(OffchainHttpRequestAddHeader),
+    /// Write a chunk of the body of an HTTP request.
+    HttpRequestWriteBody(OffchainHttpRequestWriteBody),
+    /// Wait for the responses to a list of HTTP requests.
+    HttpResponseWait(OffchainHttpResponseWait),
+    /// Obtain the headers of the response to an HTTP request.
+    HttpResponseHeaders(OffchainHttpResponseHeaders),
+    /// Read a chunk of the body of the response to an HTTP request.
+    HttpResponseReadBody(OffchainHttpResponseReadBody),
 }
 
 impl OffchainContext {
@@ -564,6 +577,12 @@ impl OffchainContext {
             OffchainContext::Timestamp(inner) => inner.inner.vm.into_prototype(),
             OffchainContext::RandomSeed(inner) => inner.inner.vm.into_prototype(),
             OffchainContext::SubmitTransaction(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestStart(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestAddHeader(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpRequestWriteBody(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseWait(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseHeaders(inner) => inner.inner.vm.into_prototype(),
+            OffchainContext::HttpResponseReadBody(inner) => inner.inner.vm.into_prototype(),
         }
     }
 }
@@ -1257,6 +1276,286 @@ impl OffchainSubmitTransaction {
     }
 }
 
+/// The runtime requests starting an HTTP request.
+#[must_use]
+pub struct OffchainHttpRequestStart {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestStart {
+    /// Returns the HTTP method of the request, such as `GET` or `POST`.
+    ///
+    /// The runtime is supposed to pass a UTF-8 string, but this isn't guaranteed.
+    pub fn method(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => req.method(),
+            // We only create a `OffchainHttpRequestStart` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the URI of the request.
+    ///
+    /// The runtime is supposed to pass a UTF-8 string, but this isn't guaranteed.
+    pub fn uri(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => req.uri(),
+            // We only create a `OffchainHttpRequestStart` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution. Must be passed the identifier of the newly-created request, or `None`
+    /// if the request couldn't be started.
+    pub fn resume(mut self, request_id: Option<u16>) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestStart(req) => {
+                self.inner.vm = req.resume(request_id);
+            }
+            // We only create a `OffchainHttpRequestStart` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
+/// The runtime requests adding a header to an HTTP request.
+#[must_use]
+pub struct OffchainHttpRequestAddHeader {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestAddHeader {
+    /// Returns the identifier of the request.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.request_id(),
+            // We only create a `OffchainHttpRequestAddHeader` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the name of the header to add.
+    pub fn name(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.name(),
+            // We only create a `OffchainHttpRequestAddHeader` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value of the header to add.
+    pub fn value(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => req.value(),
+            // We only create a `OffchainHttpRequestAddHeader` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution. Must indicate whether the header has been added.
+    pub fn resume(mut self, success: bool) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestAddHeader(req) => {
+                self.inner.vm = req.resume(success);
+            }
+            // We only create a `OffchainHttpRequestAddHeader` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
+/// The runtime requests writing a chunk of the body of an HTTP request.
+#[must_use]
+pub struct OffchainHttpRequestWriteBody {
+    inner: Inner,
+}
+
+impl OffchainHttpRequestWriteBody {
+    /// Returns the identifier of the request.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.request_id(),
+            // We only create a `OffchainHttpRequestWriteBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the chunk of body to write. Empty if the body is complete, in which case the
+    /// request can be sent.
+    pub fn chunk(&'_ self) -> impl AsRef<[u8]> + '_ {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.chunk(),
+            // We only create a `OffchainHttpRequestWriteBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which the operation must
+    /// be interrupted with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => req.deadline(),
+            // We only create a `OffchainHttpRequestWriteBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution after having written the chunk.
+    pub fn resume(mut self, result: Result<(), HttpError>) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpRequestWriteBody(req) => {
+                self.inner.vm = req.resume(result);
+            }
+            // We only create a `OffchainHttpRequestWriteBody` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
+/// The runtime requests waiting for the responses to a list of HTTP requests.
+#[must_use]
+pub struct OffchainHttpResponseWait {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseWait {
+    /// Returns the identifiers of the requests whose response must be waited for.
+    pub fn request_ids(&self) -> &[u16] {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => req.request_ids(),
+            // We only create a `OffchainHttpResponseWait` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which waiting must be
+    /// interrupted. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => req.deadline(),
+            // We only create a `OffchainHttpResponseWait` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution. Must be passed the status of each request, in the same order as
+    /// [`OffchainHttpResponseWait::request_ids`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number of statuses isn't equal to the number of requests.
+    ///
+    pub fn resume(mut self, statuses: &[HttpRequestStatus]) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseWait(req) => {
+                self.inner.vm = req.resume(statuses);
+            }
+            // We only create a `OffchainHttpResponseWait` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
+/// The runtime requests the headers of the response to an HTTP request.
+#[must_use]
+pub struct OffchainHttpResponseHeaders {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseHeaders {
+    /// Returns the identifier of the request.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseHeaders(req) => req.request_id(),
+            // We only create a `OffchainHttpResponseHeaders` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution by providing the names and values of the headers of the response.
+    ///
+    /// The list must be empty if the identifier is invalid or if the response hasn't been
+    /// received yet.
+    pub fn resume<'a>(
+        mut self,
+        headers: impl ExactSizeIterator<Item = (&'a [u8], &'a [u8])>,
+    ) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseHeaders(req) => {
+                self.inner.vm = req.resume(headers);
+            }
+            // We only create a `OffchainHttpResponseHeaders` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
+/// The runtime requests reading a chunk of the body of the response to an HTTP request.
+#[must_use]
+pub struct OffchainHttpResponseReadBody {
+    inner: Inner,
+}
+
+impl OffchainHttpResponseReadBody {
+    /// Returns the identifier of the request.
+    pub fn request_id(&self) -> u16 {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.request_id(),
+            // We only create a `OffchainHttpResponseReadBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the maximum number of bytes that can be passed to
+    /// [`OffchainHttpResponseReadBody::resume`].
+    pub fn buffer_size(&self) -> usize {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.buffer_size(),
+            // We only create a `OffchainHttpResponseReadBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the moment, in milliseconds since the UNIX epoch, after which the operation must
+    /// be interrupted with [`HttpError::DeadlineReached`]. `None` if there is no deadline.
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => req.deadline(),
+            // We only create a `OffchainHttpResponseReadBody` if the state is one of the above.
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution after having read a chunk of the body. An empty chunk indicates that
+    /// the end of the body has been reached.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the chunk is larger than [`OffchainHttpResponseReadBody::buffer_size`].
+    ///
+    pub fn resume(mut self, result: Result<&[u8], HttpError>) -> RuntimeCall {
+        match self.inner.vm {
+            host::HostVm::OffchainHttpResponseReadBody(req) => {
+                self.inner.vm = req.resume(result);
+            }
+            // We only create a `OffchainHttpResponseReadBody` if the state is one of the above.
+            _ => unreachable!(),
+        };
+
+        self.inner.run()
+    }
+}
+
 /// Report about a log entry being emitted.
 ///
 /// Use [`LogEmit::info`] to obtain what must be printed.
@@ -1855,6 +2154,42 @@ impl Inner {
                         OffchainSubmitTransaction { inner: self },
                     ));
                 }
+                host::HostVm::OffchainHttpRequestStart(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpRequestStart(
+                        OffchainHttpRequestStart { inner: self },
+                    ));
+                }
+                host::HostVm::OffchainHttpRequestAddHeader(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpRequestAddHeader(
+                        OffchainHttpRequestAddHeader { inner: self },
+                    ));
+                }
+                host::HostVm::OffchainHttpRequestWriteBody(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpRequestWriteBody(
+                        OffchainHttpRequestWriteBody { inner: self },
+                    ));
+                }
+                host::HostVm::OffchainHttpResponseWait(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpResponseWait(
+                        OffchainHttpResponseWait { inner: self },
+                    ));
+                }
+                host::HostVm::OffchainHttpResponseHeaders(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpResponseHeaders(
+                        OffchainHttpResponseHeaders { inner: self },
+                    ));
+                }
+                host::HostVm::OffchainHttpResponseReadBody(req) => {
+                    self.vm = req.into();
+                    return RuntimeCall::Offchain(OffchainContext::HttpResponseReadBody(
+                        OffchainHttpResponseReadBody { inner: self },
+                    ));
+                }
             }
         }
     }
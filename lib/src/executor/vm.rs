@@ -103,6 +103,32 @@ pub struct Config<'a> {
     pub symbols: &'a mut dyn FnMut(&str, &str, &Signature) -> Result<usize, ()>,
 }
 
+/// Storage for the WebAssembly modules compiled ahead of time, for example on disk, so that
+/// compiling the same module again can be skipped.
+///
+/// Only the modules that are compiled ahead of time by the JIT backend (see
+/// [`ExecHint::ValidateAndCompile`]) are stored in the cache. The keys are derived from the
+/// module bytecode, the version of smoldot, and the configuration of the compiler.
+///
+/// # Safety
+///
+/// [`CompiledModulesCache::load`] must return either `None`, or exactly the bytes that have
+/// previously been passed to [`CompiledModulesCache::store`] with the same key.
+///
+/// The bytes returned by [`CompiledModulesCache::load`] contain machine code that is executed
+/// without being verified. Returning bytes that haven't been produced by smoldot, for example
+/// because the storage has been corrupted or tampered with, is undefined behavior.
+/// Implementations that store the modules in a location that isn't fully trusted must detect
+/// modifications, for example by authenticating the modules, and return `None` if that happens.
+pub unsafe trait CompiledModulesCache {
+    /// Returns the compiled module previously stored with the given key, if any.
+    fn load(&self, key: &[u8; 32]) -> Option<Vec<u8>>;
+
+    /// Stores a compiled module under the given key. Errors, if any, must be handled by the
+    /// implementation, as failing to store a module in the cache is never fatal.
+    fn store(&self, key: &[u8; 32], compiled_module: &[u8]);
+}
+
 /// Virtual machine ready to start executing a function.
 ///
 /// > **Note**: This struct implements `Clone`. Cloning a [`VirtualMachinePrototype`] allocates
@@ -140,6 +166,18 @@ impl VirtualMachinePrototype {
     ///
     /// See [the module-level documentation](..) for an explanation of the parameters.
     pub fn new(config: Config) -> Result<Self, NewErr> {
+        Self::new_with_compiled_modules_cache(config, None)
+    }
+
+    /// Same as [`VirtualMachinePrototype::new`], but loads the compiled module from the given
+    /// cache if possible, and stores it in the cache after it has been compiled otherwise.
+    pub fn new_with_compiled_modules_cache(
+        config: Config,
+        compiled_modules_cache: Option<&dyn CompiledModulesCache>,
+    ) -> Result<Self, NewErr> {
+        // Only the JIT backend makes use of the cache, and it might not be compiled in.
+        let _ = compiled_modules_cache;
+
         Ok(VirtualMachinePrototype {
             inner: match config.exec_hint {
                 #[cfg(all(
@@ -157,9 +195,13 @@ impl VirtualMachinePrototype {
                     ),
                     feature = "wasmtime"
                 ))]
-                ExecHint::ValidateAndCompile => VirtualMachinePrototypeInner::Jit(
-                    jit::JitPrototype::new(config.module_bytes, config.symbols)?,
-                ),
+                ExecHint::ValidateAndCompile => {
+                    VirtualMachinePrototypeInner::Jit(jit::JitPrototype::new(
+                        config.module_bytes,
+                        config.symbols,
+                        compiled_modules_cache,
+                    )?)
+                }
                 #[cfg(not(all(
                     any(
                         all(
@@ -230,9 +272,13 @@ impl VirtualMachinePrototype {
                     ),
                     feature = "wasmtime"
                 ))]
-                ExecHint::ForceWasmtime => VirtualMachinePrototypeInner::Jit(
-                    jit::JitPrototype::new(config.module_bytes, config.symbols)?,
-                ),
+                ExecHint::ForceWasmtime => {
+                    VirtualMachinePrototypeInner::Jit(jit::JitPrototype::new(
+                        config.module_bytes,
+                        config.symbols,
+                        compiled_modules_cache,
+                    )?)
+                }
             },
         })
     }
@@ -18,8 +18,8 @@
 //! Implements the API documented [in the parent module](..).
 
 use super::{
-    ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, RunErr, Signature, StartErr,
-    Trap, ValueType, WasmValue,
+    CompiledModulesCache, ExecOutcome, GlobalValueErr, HeapPages, NewErr, OutOfBoundsError, RunErr,
+    Signature, StartErr, Trap, ValueType, WasmValue,
 };
 
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
@@ -60,6 +60,7 @@ impl JitPrototype {
     pub fn new(
         module_bytes: &[u8],
         symbols: &mut dyn FnMut(&str, &str, &Signature) -> Result<usize, ()>,
+        compiled_modules_cache: Option<&dyn CompiledModulesCache>,
     ) -> Result<Self, NewErr> {
         let mut config = wasmtime::Config::new();
         config.cranelift_nan_canonicalization(true);
@@ -90,8 +91,42 @@ impl JitPrototype {
         let engine =
             wasmtime::Engine::new(&config).map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
 
-        let module = wasmtime::Module::from_binary(&engine, module_bytes)
-            .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
+        // The key under which the compiled module is found in the cache. Any change to the
+        // configuration above must be accompanied with a change to the version of smoldot.
+        let cache_key = compiled_modules_cache.map(|_| {
+            let mut hasher = blake2_rfc::blake2b::Blake2b::new(32);
+            hasher.update(b"smoldot-jit-");
+            hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+            hasher.update(b"-");
+            hasher.update(module_bytes);
+            <[u8; 32]>::try_from(hasher.finalize().as_bytes()).unwrap()
+        });
+
+        let cached_module = compiled_modules_cache
+            .zip(cache_key.as_ref())
+            .and_then(|(cache, key)| cache.load(key))
+            .and_then(|compiled_module| {
+                // SAFETY: `deserialize` must only be passed bytes that have been generated by
+                // `wasmtime::Module::serialize`. This is guaranteed by the safety contract of
+                // the `CompiledModulesCache` trait. Wasmtime verifies by itself that the bytes
+                // have been generated by a compatible engine. If the bytes can't be loaded, we
+                // simply compile the module again.
+                unsafe { wasmtime::Module::deserialize(&engine, compiled_module) }.ok()
+            });
+
+        let module = match cached_module {
+            Some(module) => module,
+            None => {
+                let module = wasmtime::Module::from_binary(&engine, module_bytes)
+                    .map_err(|err| NewErr::InvalidWasm(err.to_string()))?;
+                if let Some((cache, key)) = compiled_modules_cache.zip(cache_key.as_ref()) {
+                    if let Ok(compiled_module) = module.serialize() {
+                        cache.store(key, &compiled_module);
+                    }
+                }
+                module
+            }
+        };
 
         // Building the list of imports that the Wasm VM is able to use.
         let resolved_imports = {
@@ -63,6 +63,64 @@ fn basic_seems_to_work() {
     }
 }
 
+#[test]
+fn compiled_modules_cache() {
+    struct Cache {
+        modules: core::cell::RefCell<alloc::vec::Vec<([u8; 32], alloc::vec::Vec<u8>)>>,
+        num_hits: core::cell::Cell<usize>,
+    }
+
+    // SAFETY: the modules are kept in memory and returned unmodified.
+    unsafe impl super::CompiledModulesCache for Cache {
+        fn load(&self, key: &[u8; 32]) -> Option<alloc::vec::Vec<u8>> {
+            let modules = self.modules.borrow();
+            let (_, module) = modules.iter().find(|(k, _)| k == key)?;
+            self.num_hits.set(self.num_hits.get() + 1);
+            Some(module.clone())
+        }
+
+        fn store(&self, key: &[u8; 32], compiled_module: &[u8]) {
+            self.modules
+                .borrow_mut()
+                .push((*key, compiled_module.to_vec()));
+        }
+    }
+
+    for exec_hint in super::ExecHint::available_engines() {
+        let cache = Cache {
+            modules: core::cell::RefCell::new(alloc::vec::Vec::new()),
+            num_hits: core::cell::Cell::new(0),
+        };
+
+        for _ in 0..2 {
+            let prototype = super::VirtualMachinePrototype::new_with_compiled_modules_cache(
+                super::Config {
+                    module_bytes: &include_bytes!("./test-polkadot-runtime-v9160.wasm")[..],
+                    exec_hint,
+                    symbols: &mut |_, _, _| Ok(0),
+                },
+                Some(&cache),
+            )
+            .unwrap();
+
+            let mut vm = prototype
+                .prepare()
+                .start(
+                    "Core_version",
+                    &[super::WasmValue::I32(0), super::WasmValue::I32(0)],
+                )
+                .unwrap();
+            assert!(vm.run(None).is_ok());
+        }
+
+        // Only the modules compiled ahead of time are stored, and the second instantiation must
+        // then have loaded the module from the cache.
+        let num_stored = cache.modules.borrow().len();
+        assert!(num_stored <= 1);
+        assert_eq!(cache.num_hits.get(), num_stored);
+    }
+}
+
 #[test]
 fn wat_not_accepted() {
     for exec_hint in super::ExecHint::available_engines() {
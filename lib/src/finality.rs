@@ -27,3 +27,4 @@
 
 pub mod decode;
 pub mod verify;
+pub mod voter;
@@ -0,0 +1,1023 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! State of a GrandPa voting round.
+//!
+//! A GrandPa round consists in two voting phases. During the first phase, each authority casts
+//! a *prevote* for the best block it knows of. During the second phase, each authority casts a
+//! *precommit* for the *prevote GHOST*, which is the highest block that has been prevoted for,
+//! directly or through one of its descendants, by a supermajority of the authorities. Once a
+//! supermajority of authorities has precommitted for a block or its descendants, this block can
+//! be finalized, and a *commit* containing these precommits is gossiped to the network.
+//!
+//! The [`Round`] struct in this module stores the votes of a single round, detects
+//! equivocations (i.e. authorities that cast two different votes of the same kind), and
+//! determines the GHOSTs, the estimate, and whether the round is completable, as described in
+//! the GrandPa paper.
+//!
+//! Because the votes refer to blocks by hash, most methods require a function that returns the
+//! hash of the parent of a block. This function must return `None` if the block is unknown.
+//! Votes that target unknown blocks or blocks that aren't descendants of the base of the round
+//! are ignored when determining the GHOSTs.
+//!
+//! The supermajorities are computed using the weights of the authorities. A supermajority is
+//! reached when the total weight of the authorities that agree is strictly more than two thirds
+//! of the total weight of the set.
+//!
+//! Each round has a *primary*, which is one of the authorities of the set determined from the
+//! round number. The primary can broadcast a *primary proposal* at the start of the round, which
+//! the other authorities take into account when choosing the target of their prevote. See
+//! [`Round::primary`] and [`Round::primary_proposal`].
+
+use crate::{header, network::codec};
+
+use alloc::{boxed::Box, vec::Vec};
+use hashbrown::HashMap;
+
+/// Configuration for [`Round::new`].
+#[derive(Debug)]
+pub struct Config<TAuth> {
+    /// Number of the round.
+    pub round_number: u64,
+
+    /// Identifier of the authorities set the round belongs to.
+    pub set_id: u64,
+
+    /// Ed25519 public keys and weights of the authorities of the set.
+    pub authorities: TAuth,
+
+    /// Hash and number of the block that all the votes must be a descendant of. This is
+    /// typically the latest finalized block.
+    pub base: ([u8; 32], u64),
+
+    /// Number of bytes used to encode the block number in the header.
+    pub block_number_bytes: usize,
+}
+
+/// State of a GrandPa round. See [the module-level documentation](..).
+#[derive(Debug, Clone)]
+pub struct Round {
+    /// See [`Config::round_number`].
+    round_number: u64,
+    /// See [`Config::set_id`].
+    set_id: u64,
+    /// See [`Config::base`].
+    base: ([u8; 32], u64),
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+    /// Votes of each authority of the set.
+    authorities: HashMap<[u8; 32], AuthorityVotes, fnv::FnvBuildHasher>,
+    /// Sum of the weights of all the authorities of the set.
+    total_weight: u64,
+    /// Public key of the primary of the round.
+    primary: [u8; 32],
+    /// Hash and number of the block proposed by the primary, if any.
+    primary_proposal: Option<([u8; 32], u64)>,
+}
+
+#[derive(Debug, Clone)]
+struct AuthorityVotes {
+    weight: u64,
+    prevote: VoteState,
+    precommit: VoteState,
+}
+
+#[derive(Debug, Clone, Default)]
+enum VoteState {
+    #[default]
+    None,
+    Single(Vote),
+    Equivocated(Vote, Vote),
+}
+
+/// Vote of an authority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vote {
+    /// Hash of the block that has been voted for.
+    pub target_hash: [u8; 32],
+    /// Number of the block that has been voted for.
+    pub target_number: u64,
+    /// Ed25519 signature of the vote.
+    pub signature: [u8; 64],
+}
+
+/// Kind of a [`Vote`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+/// Outcome of [`Round::import_vote`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoteImportOutcome {
+    /// Vote has been added to the round.
+    Imported,
+    /// Same vote had already been imported. Nothing has changed.
+    Duplicate,
+    /// The authority had already cast a different vote of the same kind. The authority is now
+    /// considered as equivocating.
+    ///
+    /// The two conflicting votes form a proof of the misbehavior of the authority.
+    Equivocation {
+        /// Kind of the two votes.
+        kind: VoteKind,
+        /// Authority that has equivocated.
+        authority_public_key: [u8; 32],
+        /// Vote that had been imported first.
+        first: Box<Vote>,
+        /// Vote that has just been imported.
+        second: Box<Vote>,
+    },
+    /// The vote is a primary proposal that doesn't come from the primary of the round, or the
+    /// primary has already proposed a different block. It has been ignored.
+    Ignored,
+}
+
+/// Error potentially returned by [`Round::import_vote`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum VoteImportError {
+    /// Round number of the vote doesn't match the round.
+    #[display(fmt = "Round number of the vote doesn't match the round")]
+    BadRoundNumber,
+    /// Authorities set id of the vote doesn't match the round.
+    #[display(fmt = "Authorities set id of the vote doesn't match the round")]
+    BadSetId,
+    /// Author of the vote isn't part of the authorities set.
+    #[display(fmt = "Author of the vote isn't part of the authorities set")]
+    UnknownAuthority,
+    /// Signature of the vote is invalid.
+    #[display(fmt = "Signature of the vote is invalid")]
+    BadSignature,
+}
+
+/// Commit assembled by [`Round::commit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// Number of the round.
+    pub round_number: u64,
+    /// Identifier of the authorities set.
+    pub set_id: u64,
+    /// Hash of the block that the commit finalizes.
+    pub target_hash: [u8; 32],
+    /// Number of the block that the commit finalizes.
+    pub target_number: u64,
+    /// List of precommits that the commit consists of, and their authors.
+    pub precommits: Vec<([u8; 32], Vote)>,
+}
+
+impl Commit {
+    /// Returns the SCALE encoding of the commit, in the format expected by
+    /// [`crate::finality::decode::decode_grandpa_commit`].
+    pub fn scale_encoding_vec(&self, block_number_bytes: usize) -> Vec<u8> {
+        codec::commit_message_scale_encoding_vec(
+            &codec::CommitMessageRef {
+                round_number: self.round_number,
+                set_id: self.set_id,
+                target_hash: &self.target_hash,
+                target_number: self.target_number,
+                precommits: self
+                    .precommits
+                    .iter()
+                    .map(|(_, vote)| codec::UnsignedPrecommitRef {
+                        target_hash: &vote.target_hash,
+                        target_number: vote.target_number,
+                    })
+                    .collect(),
+                auth_data: self
+                    .precommits
+                    .iter()
+                    .map(|(authority, vote)| (&vote.signature, authority))
+                    .collect(),
+            },
+            block_number_bytes,
+        )
+    }
+}
+
+impl Round {
+    /// Initializes a new round with no vote.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the list of authorities is empty.
+    ///
+    pub fn new(config: Config<impl IntoIterator<Item = header::GrandpaAuthority>>) -> Self {
+        let mut authorities = HashMap::with_capacity_and_hasher(0, fnv::FnvBuildHasher::default());
+        for authority in config.authorities {
+            // If the same public key appears multiple times, the weights are summed.
+            authorities
+                .entry(authority.public_key)
+                .or_insert(AuthorityVotes {
+                    weight: 0,
+                    prevote: VoteState::None,
+                    precommit: VoteState::None,
+                })
+                .weight += authority.weight.get();
+        }
+
+        // The primary is determined the same way as Substrate: the authorities are sorted by
+        // public key, and the primary is the one at the index equal to the round number modulo
+        // the number of authorities.
+        let primary = {
+            let mut public_keys = authorities.keys().copied().collect::<Vec<_>>();
+            assert!(!public_keys.is_empty());
+            public_keys.sort_unstable();
+            let index =
+                usize::try_from(config.round_number % u64::try_from(public_keys.len()).unwrap())
+                    .unwrap();
+            public_keys[index]
+        };
+
+        Round {
+            round_number: config.round_number,
+            set_id: config.set_id,
+            base: config.base,
+            block_number_bytes: config.block_number_bytes,
+            total_weight: authorities
+                .values()
+                .fold(0u64, |sum, votes| sum.saturating_add(votes.weight)),
+            authorities,
+            primary,
+            primary_proposal: None,
+        }
+    }
+
+    /// Returns the number of the round.
+    pub fn round_number(&self) -> u64 {
+        self.round_number
+    }
+
+    /// Returns the identifier of the authorities set the round belongs to.
+    pub fn set_id(&self) -> u64 {
+        self.set_id
+    }
+
+    /// Returns the hash and number of the block all the votes must be a descendant of.
+    pub fn base(&self) -> ([u8; 32], u64) {
+        self.base
+    }
+
+    /// Returns the number of authorities in the authorities set of the round.
+    pub fn num_authorities(&self) -> usize {
+        self.authorities.len()
+    }
+
+    /// Returns the weight of the given authority, or `None` if it isn't part of the authorities
+    /// set of the round.
+    pub fn authority_weight(&self, public_key: &[u8; 32]) -> Option<u64> {
+        self.authorities.get(public_key).map(|votes| votes.weight)
+    }
+
+    /// Returns `true` if the given authority is part of the authorities set of the round.
+    pub fn is_authority(&self, public_key: &[u8; 32]) -> bool {
+        self.authorities.contains_key(public_key)
+    }
+
+    /// Returns `true` if the total weight of the given authorities is strictly more than the
+    /// maximum weight of faulty authorities that the set can tolerate, in other words if at
+    /// least one of them is necessarily honest.
+    ///
+    /// Authorities that aren't part of the set, and duplicate entries, are ignored.
+    pub fn is_more_than_faulty<'a>(
+        &self,
+        public_keys: impl IntoIterator<Item = &'a [u8; 32]>,
+    ) -> bool {
+        let mut public_keys = public_keys
+            .into_iter()
+            .filter(|key| self.authorities.contains_key(*key))
+            .collect::<Vec<_>>();
+        public_keys.sort_unstable();
+        public_keys.dedup();
+        let weight = public_keys.into_iter().fold(0u64, |sum, key| {
+            sum.saturating_add(self.authorities[key].weight)
+        });
+        weight > self.total_weight - self.threshold()
+    }
+
+    /// Returns the public key of the primary of the round.
+    ///
+    /// Only the primary proposal of this authority is taken into account.
+    pub fn primary(&self) -> &[u8; 32] {
+        &self.primary
+    }
+
+    /// Returns the hash and number of the block proposed by the primary of the round, if any.
+    pub fn primary_proposal(&self) -> Option<([u8; 32], u64)> {
+        self.primary_proposal
+    }
+
+    /// Returns the vote of the given kind that the given authority has cast, if any.
+    ///
+    /// If the authority has equivocated, the vote that has been imported first is returned.
+    pub fn vote(&self, kind: VoteKind, public_key: &[u8; 32]) -> Option<&Vote> {
+        let votes = self.authorities.get(public_key)?;
+        let state = match kind {
+            VoteKind::Prevote => &votes.prevote,
+            VoteKind::Precommit => &votes.precommit,
+        };
+
+        match state {
+            VoteState::None => None,
+            VoteState::Single(vote) | VoteState::Equivocated(vote, _) => Some(vote),
+        }
+    }
+
+    /// Returns all the votes of the given kind that have been imported, and their authors.
+    ///
+    /// If an authority has equivocated, both of its votes are returned.
+    pub fn votes(&self, kind: VoteKind) -> impl Iterator<Item = (&[u8; 32], &Vote)> + '_ {
+        self.authorities
+            .iter()
+            .flat_map(move |(public_key, votes)| {
+                let state = match kind {
+                    VoteKind::Prevote => &votes.prevote,
+                    VoteKind::Precommit => &votes.precommit,
+                };
+
+                let (first, second) = match state {
+                    VoteState::None => (None, None),
+                    VoteState::Single(vote) => (Some(vote), None),
+                    VoteState::Equivocated(first, second) => (Some(first), Some(second)),
+                };
+
+                first
+                    .into_iter()
+                    .chain(second)
+                    .map(move |vote| (public_key, vote))
+            })
+    }
+
+    /// Verifies the signature of the given vote and adds it to the round.
+    pub fn import_vote(
+        &mut self,
+        vote: &codec::VoteMessageRef,
+    ) -> Result<VoteImportOutcome, VoteImportError> {
+        if vote.round_number != self.round_number {
+            return Err(VoteImportError::BadRoundNumber);
+        }
+        if vote.set_id != self.set_id {
+            return Err(VoteImportError::BadSetId);
+        }
+
+        let Some(votes) = self.authorities.get_mut(vote.authority_public_key) else {
+            return Err(VoteImportError::UnknownAuthority);
+        };
+
+        if matches!(vote.message, codec::MessageRef::PrimaryPropose(_))
+            && *vote.authority_public_key != self.primary
+        {
+            return Ok(VoteImportOutcome::Ignored);
+        }
+
+        let payload =
+            vote.message
+                .signature_payload(self.round_number, self.set_id, self.block_number_bytes);
+        ed25519_zebra::VerificationKey::try_from(*vote.authority_public_key)
+            .and_then(|key| key.verify(&ed25519_zebra::Signature::from(*vote.signature), &payload))
+            .map_err(|_| VoteImportError::BadSignature)?;
+
+        let (kind, target_hash, target_number) = match &vote.message {
+            codec::MessageRef::Prevote(v) => (VoteKind::Prevote, v.target_hash, v.target_number),
+            codec::MessageRef::Precommit(v) => {
+                (VoteKind::Precommit, v.target_hash, v.target_number)
+            }
+            codec::MessageRef::PrimaryPropose(v) => {
+                // Only the first proposal of the primary is kept. Proposing multiple blocks
+                // isn't punishable.
+                return Ok(match self.primary_proposal {
+                    None => {
+                        self.primary_proposal = Some((*v.target_hash, v.target_number));
+                        VoteImportOutcome::Imported
+                    }
+                    Some((hash, _)) if hash == *v.target_hash => VoteImportOutcome::Duplicate,
+                    Some(_) => VoteImportOutcome::Ignored,
+                });
+            }
+        };
+
+        let new_vote = Vote {
+            target_hash: *target_hash,
+            target_number,
+            signature: *vote.signature,
+        };
+
+        let state = match kind {
+            VoteKind::Prevote => &mut votes.prevote,
+            VoteKind::Precommit => &mut votes.precommit,
+        };
+
+        match &*state {
+            VoteState::None => {
+                *state = VoteState::Single(new_vote);
+                Ok(VoteImportOutcome::Imported)
+            }
+            VoteState::Single(existing) | VoteState::Equivocated(existing, _)
+                if existing.target_hash == new_vote.target_hash =>
+            {
+                Ok(VoteImportOutcome::Duplicate)
+            }
+            VoteState::Equivocated(_, existing) if existing.target_hash == new_vote.target_hash => {
+                Ok(VoteImportOutcome::Duplicate)
+            }
+            VoteState::Single(existing) => {
+                let first = existing.clone();
+                *state = VoteState::Equivocated(first.clone(), new_vote.clone());
+                Ok(VoteImportOutcome::Equivocation {
+                    kind,
+                    authority_public_key: *vote.authority_public_key,
+                    first: Box::new(first),
+                    second: Box::new(new_vote),
+                })
+            }
+            VoteState::Equivocated(first, _) => {
+                // Only the two first votes are kept, as they are enough to prove the
+                // equivocation.
+                Ok(VoteImportOutcome::Equivocation {
+                    kind,
+                    authority_public_key: *vote.authority_public_key,
+                    first: Box::new(first.clone()),
+                    second: Box::new(new_vote),
+                })
+            }
+        }
+    }
+
+    /// Returns the hash and number of the prevote GHOST, in other words the highest block that
+    /// a supermajority (by weight) of authorities has prevoted for, directly or through one of its
+    /// descendants.
+    ///
+    /// Returns `None` if there isn't any such block.
+    pub fn prevote_ghost(
+        &self,
+        parent_of: impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+    ) -> Option<([u8; 32], u64)> {
+        self.tally(VoteKind::Prevote, &parent_of)
+            .ghost(self.threshold())
+    }
+
+    /// Returns the hash and number of the precommit GHOST, in other words the highest block
+    /// that a supermajority of authorities has precommitted for, directly or through one of its
+    /// descendants. This block can be finalized.
+    ///
+    /// Returns `None` if there isn't any such block.
+    pub fn precommit_ghost(
+        &self,
+        parent_of: impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+    ) -> Option<([u8; 32], u64)> {
+        self.tally(VoteKind::Precommit, &parent_of)
+            .ghost(self.threshold())
+    }
+
+    /// Returns the hash and number of the estimate of the round, in other words the highest
+    /// ancestor of the prevote GHOST (inclusive) that could still be finalized by this round.
+    ///
+    /// Returns `None` if there isn't any prevote GHOST.
+    pub fn estimate(
+        &self,
+        parent_of: impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+    ) -> Option<([u8; 32], u64)> {
+        let prevote_ghost = self.prevote_ghost(&parent_of)?;
+        let precommits = self.tally(VoteKind::Precommit, &parent_of);
+        Some(self.estimate_inner(prevote_ghost, &precommits, &parent_of))
+    }
+
+    /// Returns `true` if the round is completable, in other words if a supermajority (by weight)
+    /// of the authorities has precommitted and it is no longer possible for the estimate of the round
+    /// to change.
+    ///
+    /// The next round can start once the current round is completable.
+    pub fn is_completable(&self, parent_of: impl Fn(&[u8; 32]) -> Option<[u8; 32]>) -> bool {
+        let Some(prevote_ghost) = self.prevote_ghost(&parent_of) else {
+            return false;
+        };
+
+        let precommits = self.tally(VoteKind::Precommit, &parent_of);
+        if precommits.voters_weight < self.threshold() {
+            return false;
+        }
+
+        if self.estimate_inner(prevote_ghost, &precommits, &parent_of) != prevote_ghost {
+            return true;
+        }
+
+        // The estimate is equal to the prevote GHOST. The round is completable only if it is
+        // impossible for any of the children of the prevote GHOST to gather a supermajority of
+        // precommits. Children that no authority has precommitted for can still gather the
+        // votes of the authorities that haven't voted yet.
+        let best_child_votes = precommits
+            .blocks
+            .values()
+            .filter(|block| block.parent.as_ref() == Some(&prevote_ghost.0))
+            .map(|block| block.votes_weight)
+            .max()
+            .unwrap_or(0);
+        precommits.possible_weight(best_child_votes, self.total_weight) < self.threshold()
+    }
+
+    /// Assembles a commit finalizing the precommit GHOST.
+    ///
+    /// Returns `None` if there isn't any precommit GHOST, or if the weight of the
+    /// non-equivocating precommits isn't enough to build a valid commit.
+    pub fn commit(&self, parent_of: impl Fn(&[u8; 32]) -> Option<[u8; 32]>) -> Option<Commit> {
+        let target = self.precommit_ghost(&parent_of)?;
+
+        let mut precommits = Vec::with_capacity(self.authorities.len());
+        let mut precommits_weight = 0u64;
+        for (authority, votes) in &self.authorities {
+            // Only one precommit per authority can be included, otherwise the commit is
+            // considered invalid.
+            let candidates = match &votes.precommit {
+                VoteState::None => continue,
+                VoteState::Single(vote) => [Some(vote), None],
+                VoteState::Equivocated(first, second) => [Some(first), Some(second)],
+            };
+
+            if let Some(vote) = candidates.into_iter().flatten().find(|vote| {
+                is_descendant_or_equal(
+                    (&vote.target_hash, vote.target_number),
+                    (&target.0, target.1),
+                    &parent_of,
+                )
+            }) {
+                precommits.push((*authority, vote.clone()));
+                precommits_weight = precommits_weight.saturating_add(votes.weight);
+            }
+        }
+
+        if precommits_weight < self.threshold() {
+            return None;
+        }
+
+        // Sort the precommits in order to make the output deterministic.
+        precommits.sort_unstable_by_key(|(authority, _)| *authority);
+
+        Some(Commit {
+            round_number: self.round_number,
+            set_id: self.set_id,
+            target_hash: target.0,
+            target_number: target.1,
+            precommits,
+        })
+    }
+
+    /// Minimum total weight of votes necessary to reach a supermajority.
+    ///
+    /// Same formula as Substrate: the total weight minus the maximum weight of faulty
+    /// authorities, the latter being `(total - 1) / 3`. When all the authorities have the same
+    /// weight, this is equivalent to `(expected * 2 / 3) + 1` votes.
+    fn threshold(&self) -> u64 {
+        self.total_weight - self.total_weight.saturating_sub(1) / 3
+    }
+
+    fn estimate_inner(
+        &self,
+        prevote_ghost: ([u8; 32], u64),
+        precommits: &Tally,
+        parent_of: &impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+    ) -> ([u8; 32], u64) {
+        let mut current = prevote_ghost;
+        loop {
+            if current.1 <= self.base.1 {
+                return self.base;
+            }
+
+            let votes = precommits
+                .blocks
+                .get(&current.0)
+                .map_or(0, |block| block.votes_weight);
+            if precommits.possible_weight(votes, self.total_weight) >= self.threshold() {
+                return current;
+            }
+
+            match parent_of(&current.0) {
+                Some(parent) => current = (parent, current.1 - 1),
+                None => return self.base,
+            }
+        }
+    }
+
+    fn tally(&self, kind: VoteKind, parent_of: &impl Fn(&[u8; 32]) -> Option<[u8; 32]>) -> Tally {
+        let mut tally = Tally {
+            blocks: HashMap::with_capacity_and_hasher(0, Default::default()),
+            voters_weight: 0,
+            equivocators_weight: 0,
+        };
+
+        tally.blocks.insert(
+            self.base.0,
+            TallyBlock {
+                number: self.base.1,
+                parent: None,
+                votes_weight: 0,
+            },
+        );
+
+        for votes in self.authorities.values() {
+            let vote = match kind {
+                VoteKind::Prevote => &votes.prevote,
+                VoteKind::Precommit => &votes.precommit,
+            };
+
+            let vote = match vote {
+                VoteState::None => continue,
+                VoteState::Single(vote) => vote,
+                VoteState::Equivocated(..) => {
+                    tally.voters_weight += votes.weight;
+                    tally.equivocators_weight += votes.weight;
+                    continue;
+                }
+            };
+
+            tally.voters_weight += votes.weight;
+
+            // Walk the ancestry of the target of the vote down to the base. Votes for blocks
+            // that are unknown or that aren't descendants of the base are ignored.
+            let mut path = Vec::new();
+            let mut current = (vote.target_hash, vote.target_number);
+            let is_valid = loop {
+                if current.1 == self.base.1 {
+                    break current.0 == self.base.0;
+                }
+                if current.1 < self.base.1 {
+                    break false;
+                }
+                let Some(parent) = parent_of(&current.0) else {
+                    break false;
+                };
+                path.push((current.0, current.1, parent));
+                current = (parent, current.1 - 1);
+            };
+            if !is_valid {
+                continue;
+            }
+
+            tally.blocks.get_mut(&self.base.0).unwrap().votes_weight += votes.weight;
+            for (hash, number, parent) in path {
+                tally
+                    .blocks
+                    .entry(hash)
+                    .or_insert(TallyBlock {
+                        number,
+                        parent: Some(parent),
+                        votes_weight: 0,
+                    })
+                    .votes_weight += votes.weight;
+            }
+        }
+
+        tally
+    }
+}
+
+/// Weight of the votes cast for each block, directly or through one of its descendants.
+struct Tally {
+    blocks: HashMap<[u8; 32], TallyBlock, fnv::FnvBuildHasher>,
+    /// Total weight of the authorities that have voted, including equivocators.
+    voters_weight: u64,
+    /// Total weight of the authorities that have equivocated. Equivocators are considered as
+    /// having voted for every block.
+    equivocators_weight: u64,
+}
+
+struct TallyBlock {
+    number: u64,
+    parent: Option<[u8; 32]>,
+    /// Total weight of the non-equivocating authorities that have voted for this block or one
+    /// of its descendants.
+    votes_weight: u64,
+}
+
+impl Tally {
+    fn ghost(&self, threshold: u64) -> Option<([u8; 32], u64)> {
+        self.blocks
+            .iter()
+            .filter(|(_, block)| block.votes_weight + self.equivocators_weight >= threshold)
+            .max_by_key(|(_, block)| block.number)
+            .map(|(hash, block)| (*hash, block.number))
+    }
+
+    /// Returns the maximum weight of votes that a block that has currently `votes_weight` could
+    /// possibly gather.
+    fn possible_weight(&self, votes_weight: u64, total_weight: u64) -> u64 {
+        votes_weight + self.equivocators_weight + (total_weight - self.voters_weight)
+    }
+}
+
+/// Returns `true` if `block` is a descendant of `ancestor` or equal to `ancestor`.
+fn is_descendant_or_equal(
+    block: (&[u8; 32], u64),
+    ancestor: (&[u8; 32], u64),
+    parent_of: &impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+) -> bool {
+    let mut current = (*block.0, block.1);
+    loop {
+        if current.1 == ancestor.1 {
+            return current.0 == *ancestor.0;
+        }
+        if current.1 < ancestor.1 {
+            return false;
+        }
+        match parent_of(&current.0) {
+            Some(parent) => current = (parent, current.1 - 1),
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Round, VoteImportError, VoteImportOutcome, VoteKind};
+    use crate::{header, network::codec};
+    use core::num::NonZeroU64;
+
+    fn authorities() -> Vec<ed25519_zebra::SigningKey> {
+        (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n + 1; 32]))
+            .collect()
+    }
+
+    fn public_key(authority: usize) -> [u8; 32] {
+        <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(
+            &authorities()[authority],
+        ))
+    }
+
+    fn new_round() -> Round {
+        new_round_with_weights([1, 1, 1, 1])
+    }
+
+    fn new_round_with_weights(weights: [u64; 4]) -> Round {
+        Round::new(Config {
+            round_number: 5,
+            set_id: 2,
+            authorities: (0..4).map(|n| header::GrandpaAuthority {
+                public_key: public_key(n),
+                weight: NonZeroU64::new(weights[n]).unwrap(),
+            }),
+            base: ([0; 32], 10),
+            block_number_bytes: 4,
+        })
+    }
+
+    // Chain used in the tests: `0` (base, #10) <- `1` (#11) <- `2` (#12), and `1` <- `3` (#12).
+    fn parent_of(hash: &[u8; 32]) -> Option<[u8; 32]> {
+        match hash[0] {
+            1 => Some([0; 32]),
+            2 | 3 => Some([1; 32]),
+            _ => None,
+        }
+    }
+
+    fn vote(
+        round: &mut Round,
+        authority: usize,
+        kind: VoteKind,
+        target: u8,
+    ) -> Result<VoteImportOutcome, VoteImportError> {
+        import_encoded_vote(round, &encoded_vote(authority, kind, target))
+    }
+
+    fn import_encoded_vote(
+        round: &mut Round,
+        encoded: &[u8],
+    ) -> Result<VoteImportOutcome, VoteImportError> {
+        let Ok(codec::GrandpaNotificationRef::Vote(vote)) =
+            codec::decode_grandpa_notification(encoded, 4)
+        else {
+            panic!()
+        };
+        round.import_vote(&vote)
+    }
+
+    /// Returns the SCALE-encoded GrandPa notification containing the given signed vote.
+    fn encoded_vote(authority: usize, kind: VoteKind, target: u8) -> Vec<u8> {
+        let key = &authorities()[authority];
+        let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key));
+        let target_hash = [target; 32];
+        let target_number = if target == 0 {
+            10
+        } else {
+            10 + u64::from(target.min(2))
+        };
+        let message = match kind {
+            VoteKind::Prevote => codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+                target_hash: &target_hash,
+                target_number,
+            }),
+            VoteKind::Precommit => codec::MessageRef::Precommit(codec::UnsignedPrecommitRef {
+                target_hash: &target_hash,
+                target_number,
+            }),
+        };
+        let signature = <[u8; 64]>::from(key.sign(&message.signature_payload(5, 2, 4)));
+        codec::GrandpaNotificationRef::Vote(codec::VoteMessageRef {
+            round_number: 5,
+            set_id: 2,
+            message,
+            signature: &signature,
+            authority_public_key: &public_key,
+        })
+        .scale_encoding(4)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+    }
+
+    #[test]
+    fn ghosts_and_commit() {
+        let mut round = new_round();
+        assert!(round.prevote_ghost(parent_of).is_none());
+
+        vote(&mut round, 0, VoteKind::Prevote, 2).unwrap();
+        vote(&mut round, 1, VoteKind::Prevote, 2).unwrap();
+        vote(&mut round, 2, VoteKind::Prevote, 3).unwrap();
+        assert_eq!(round.prevote_ghost(parent_of), Some(([1; 32], 11)));
+
+        vote(&mut round, 3, VoteKind::Prevote, 2).unwrap();
+        assert_eq!(round.prevote_ghost(parent_of), Some(([2; 32], 12)));
+        assert!(!round.is_completable(parent_of));
+
+        vote(&mut round, 0, VoteKind::Precommit, 2).unwrap();
+        vote(&mut round, 1, VoteKind::Precommit, 2).unwrap();
+        assert!(round.precommit_ghost(parent_of).is_none());
+        assert!(!round.is_completable(parent_of));
+
+        vote(&mut round, 2, VoteKind::Precommit, 1).unwrap();
+        assert_eq!(round.precommit_ghost(parent_of), Some(([1; 32], 11)));
+        assert!(round.is_completable(parent_of));
+        // The last authority could still precommit for `2`.
+        assert_eq!(round.estimate(parent_of), Some(([2; 32], 12)));
+
+        let commit = round.commit(parent_of).unwrap();
+        assert_eq!(commit.target_hash, [1; 32]);
+        assert_eq!(commit.precommits.len(), 3);
+
+        let encoded = commit.scale_encoding_vec(4);
+        let decoded = crate::finality::decode::decode_grandpa_commit(&encoded, 4).unwrap();
+        assert_eq!(decoded.round_number, 5);
+        assert_eq!(decoded.set_id, 2);
+        assert_eq!(*decoded.target_hash, [1; 32]);
+        assert_eq!(decoded.precommits.len(), 3);
+    }
+
+    #[test]
+    fn equivocation_detected() {
+        let mut round = new_round();
+        assert_eq!(
+            vote(&mut round, 0, VoteKind::Prevote, 2).unwrap(),
+            VoteImportOutcome::Imported
+        );
+        assert_eq!(
+            vote(&mut round, 0, VoteKind::Prevote, 2).unwrap(),
+            VoteImportOutcome::Duplicate
+        );
+        assert!(matches!(
+            vote(&mut round, 0, VoteKind::Prevote, 3).unwrap(),
+            VoteImportOutcome::Equivocation {
+                kind: VoteKind::Prevote,
+                ..
+            }
+        ));
+
+        // The equivocator counts as voting for every block.
+        vote(&mut round, 1, VoteKind::Prevote, 3).unwrap();
+        vote(&mut round, 2, VoteKind::Prevote, 3).unwrap();
+        assert_eq!(round.prevote_ghost(parent_of), Some(([3; 32], 12)));
+    }
+
+    #[test]
+    fn restored_vote_after_restart() {
+        // The local node prevotes. The vote is persisted before being sent out.
+        let persisted = encoded_vote(0, VoteKind::Prevote, 2);
+        let mut round = new_round();
+        import_encoded_vote(&mut round, &persisted).unwrap();
+
+        // After a restart, the round is rebuilt and the persisted vote imported again. The vote
+        // of the local node must be found, so that it doesn't prevote a second time.
+        let mut round = new_round();
+        assert!(round.vote(VoteKind::Prevote, &public_key(0)).is_none());
+        assert_eq!(
+            import_encoded_vote(&mut round, &persisted).unwrap(),
+            VoteImportOutcome::Imported
+        );
+        assert_eq!(
+            round
+                .vote(VoteKind::Prevote, &public_key(0))
+                .unwrap()
+                .target_hash,
+            [2; 32]
+        );
+        assert!(round.vote(VoteKind::Precommit, &public_key(0)).is_none());
+
+        // Had the vote not been restored, prevoting for a different block would have been an
+        // equivocation.
+        assert!(matches!(
+            vote(&mut round, 0, VoteKind::Prevote, 3).unwrap(),
+            VoteImportOutcome::Equivocation {
+                kind: VoteKind::Prevote,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn weighted_supermajority() {
+        // The first authority alone weighs more than a third of the set, meaning that no
+        // supermajority can be reached without it.
+        let mut round = new_round_with_weights([4, 1, 1, 1]);
+
+        vote(&mut round, 1, VoteKind::Prevote, 2).unwrap();
+        vote(&mut round, 2, VoteKind::Prevote, 2).unwrap();
+        vote(&mut round, 3, VoteKind::Prevote, 2).unwrap();
+        assert!(round.prevote_ghost(parent_of).is_none());
+        assert!(!round.is_more_than_faulty([&public_key(1), &public_key(2)]));
+        assert!(round.is_more_than_faulty([&public_key(0)]));
+
+        vote(&mut round, 0, VoteKind::Prevote, 3).unwrap();
+        assert_eq!(round.prevote_ghost(parent_of), Some(([1; 32], 11)));
+
+        vote(&mut round, 0, VoteKind::Precommit, 1).unwrap();
+        vote(&mut round, 1, VoteKind::Precommit, 1).unwrap();
+        assert_eq!(round.precommit_ghost(parent_of), Some(([1; 32], 11)));
+        assert_eq!(round.commit(parent_of).unwrap().precommits.len(), 2);
+    }
+
+    #[test]
+    fn primary_proposal() {
+        let mut round = new_round();
+
+        // The authorities are sorted by public key, and the primary is the one at the index
+        // equal to the round number modulo the number of authorities.
+        let mut public_keys = (0..4).map(public_key).collect::<Vec<_>>();
+        public_keys.sort_unstable();
+        assert_eq!(*round.primary(), public_keys[5 % 4]);
+        let primary = (0..4).find(|n| public_key(*n) == public_keys[1]).unwrap();
+        let not_primary = (0..4).find(|n| public_key(*n) != public_keys[1]).unwrap();
+
+        let propose = |round: &mut Round, authority: usize, target: u8| {
+            let key = &authorities()[authority];
+            let message = codec::MessageRef::PrimaryPropose(codec::PrimaryProposeRef {
+                target_hash: &[target; 32],
+                target_number: 11,
+            });
+            let signature = <[u8; 64]>::from(key.sign(&message.signature_payload(5, 2, 4)));
+            round.import_vote(&codec::VoteMessageRef {
+                round_number: 5,
+                set_id: 2,
+                message,
+                signature: &signature,
+                authority_public_key: &public_key(authority),
+            })
+        };
+
+        assert_eq!(
+            propose(&mut round, not_primary, 1).unwrap(),
+            VoteImportOutcome::Ignored
+        );
+        assert!(round.primary_proposal().is_none());
+        assert_eq!(
+            propose(&mut round, primary, 1).unwrap(),
+            VoteImportOutcome::Imported
+        );
+        assert_eq!(round.primary_proposal(), Some(([1; 32], 11)));
+        assert_eq!(
+            propose(&mut round, primary, 1).unwrap(),
+            VoteImportOutcome::Duplicate
+        );
+    }
+
+    #[test]
+    fn bad_signature_rejected() {
+        let mut round = new_round();
+        let public_key = <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(&authorities()[0]));
+        assert!(matches!(
+            round.import_vote(&codec::VoteMessageRef {
+                round_number: 5,
+                set_id: 2,
+                message: codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+                    target_hash: &[2; 32],
+                    target_number: 12,
+                }),
+                signature: &[0; 64],
+                authority_public_key: &public_key,
+            }),
+            Err(VoteImportError::BadSignature)
+        ));
+    }
+}
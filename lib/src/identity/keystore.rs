@@ -475,9 +475,9 @@ impl Keystore {
                         }
                     }
 
-                    let (_in_out, proof, _) = key.vrf_sign(transcript);
+                    let (in_out, proof, _) = key.vrf_sign(transcript);
                     Ok(VrfSignature {
-                        // TODO: should probably output the `_in_out` as well
+                        output: in_out.to_preout().to_bytes(),
                         proof: proof.to_bytes(),
                     })
                 }
@@ -609,6 +609,9 @@ struct Guarded {
 }
 
 pub struct VrfSignature {
+    /// VRF output, also known as the "pre-output".
+    pub output: [u8; 32],
+    /// Proof that [`VrfSignature::output`] has been correctly generated.
     pub proof: [u8; 64],
 }
 
@@ -43,6 +43,12 @@ pub struct Decoded<P> {
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct ChainPrefix(u16);
 
+impl ChainPrefix {
+    /// Prefix used by chains that haven't registered any specific prefix. Corresponds to the
+    /// "Substrate" entry of the registry.
+    pub const GENERIC_SUBSTRATE: ChainPrefix = ChainPrefix(42);
+}
+
 impl fmt::Debug for ChainPrefix {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(&self.0, f)
@@ -145,6 +151,39 @@ pub fn decode(encoded: &'_ str) -> Result<Decoded<impl AsRef<[u8]>>, DecodeError
     })
 }
 
+/// Decodes an SS58 address from a string, and checks that its prefix matches the expected one.
+///
+/// On success, returns the public key of the account.
+pub fn decode_with_prefix(
+    encoded: &'_ str,
+    expected_prefix: ChainPrefix,
+) -> Result<impl AsRef<[u8]>, DecodeWithPrefixError> {
+    let decoded = decode(encoded).map_err(DecodeWithPrefixError::Decode)?;
+    if decoded.chain_prefix != expected_prefix {
+        return Err(DecodeWithPrefixError::PrefixMismatch {
+            expected: expected_prefix,
+            actual: decoded.chain_prefix,
+        });
+    }
+    Ok(decoded.public_key)
+}
+
+/// Error while decoding an SS58 address with [`decode_with_prefix`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum DecodeWithPrefixError {
+    /// Failed to decode the address.
+    #[display(fmt = "{_0}")]
+    Decode(DecodeError),
+    /// The address is valid but belongs to a different chain.
+    #[display(fmt = "Address prefix {actual:?} doesn't match expected prefix {expected:?}")]
+    PrefixMismatch {
+        /// Prefix that was expected.
+        expected: ChainPrefix,
+        /// Prefix found in the address.
+        actual: ChainPrefix,
+    },
+}
+
 /// Error while decoding an SS58 address.
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum DecodeError {
@@ -207,4 +246,15 @@ mod tests {
 
         assert_eq!(super::encode(decoded), encoded);
     }
+
+    #[test]
+    fn decode_with_prefix() {
+        let encoded = "15oF4uVJwmo4TdGW7VfQxNLavjCXviqxT9S1MgbjMNHr6Sp5";
+
+        assert!(super::decode_with_prefix(encoded, super::ChainPrefix::from(0)).is_ok());
+        assert!(matches!(
+            super::decode_with_prefix(encoded, super::ChainPrefix::GENERIC_SUBSTRATE),
+            Err(super::DecodeWithPrefixError::PrefixMismatch { .. })
+        ));
+    }
 }
@@ -45,7 +45,7 @@
 //! });
 //! ```
 
-use alloc::format;
+use alloc::{format, string::String};
 use core::{cmp, fmt};
 
 /// Values used to build the informant line. Implements the [`core::fmt::Display`] trait.
@@ -86,6 +86,12 @@ pub struct RelayChain<'a> {
     pub chain_name: &'a str,
     /// Number of the best block that we have locally.
     pub best_number: u64,
+    /// Number of the most recent relay chain block of the best chain that includes a candidate
+    /// of the parachain. `None` if unknown.
+    pub last_inclusion_number: Option<u64>,
+    /// Number of blocks of the parachain that are above the most recently included candidate,
+    /// in other words that are waiting to be included in the relay chain. `None` if unknown.
+    pub candidates_backlog: Option<u64>,
 }
 
 impl<'a> fmt::Display for InformantLine<'a> {
@@ -100,8 +106,20 @@ impl<'a> fmt::Display for InformantLine<'a> {
         let reset = if self.enable_colors { "\x1b[0m" } else { "" };
 
         let (header, header_len) = if let Some(relay_chain) = &self.relay_chain {
+            let inclusion = match (
+                relay_chain.last_inclusion_number,
+                relay_chain.candidates_backlog,
+            ) {
+                (Some(last_inclusion), Some(backlog)) => format!(
+                    ", incl {}, backlog {}",
+                    BlockNumberDisplay(last_inclusion),
+                    backlog
+                ),
+                _ => String::new(),
+            };
+
             let header = format!(
-                "    {cyan}{chain_name}{reset}   {white_bold}{local_best:<10}{reset} {light_gray}({relay_chain_name} {relay_best}){reset} [",
+                "    {cyan}{chain_name}{reset}   {white_bold}{local_best:<10}{reset} {light_gray}({relay_chain_name} {relay_best}{inclusion}){reset} [",
                 cyan = cyan,
                 reset = reset,
                 white_bold = white_bold,
@@ -112,7 +130,10 @@ impl<'a> fmt::Display for InformantLine<'a> {
                 relay_best = BlockNumberDisplay(relay_chain.best_number),
             );
 
-            let header_len = self.chain_name.chars().count() + relay_chain.chain_name.len() + 29; // TODO: ? it's easier to do that than deal with unicode
+            let header_len = self.chain_name.chars().count()
+                + relay_chain.chain_name.len()
+                + inclusion.len()
+                + 29; // TODO: ? it's easier to do that than deal with unicode
             (header, header_len)
         } else {
             let header = format!(
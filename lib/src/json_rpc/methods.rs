@@ -426,17 +426,24 @@ define_methods! {
     state_getStorage(key: HexString, hash: Option<HashHexString>) -> HexString [state_getStorageAt],
     state_getStorageHash() -> () [state_getStorageHashAt], // TODO:
     state_getStorageSize() -> () [state_getStorageSizeAt], // TODO:
-    state_queryStorage() -> (), // TODO:
+    state_queryStorage(keys: Vec<HexString>, #[rename = "block"] from_block: HashHexString, #[rename = "hash"] to_block: Option<HashHexString>) -> Vec<StorageChangeSet>,
     state_queryStorageAt(keys: Vec<HexString>, at: Option<HashHexString>) -> Vec<StorageChangeSet>, // TODO:
     state_subscribeRuntimeVersion() -> Cow<'a, str> [chain_subscribeRuntimeVersion],
     state_subscribeStorage(list: Vec<HexString>) -> Cow<'a, str>,
+    /// Re-executes the given block and returns what the runtime has done during the execution.
+    state_traceBlock(block: HashHexString, targets: Option<Cow<'a, str>>, storage_keys: Option<Cow<'a, str>>, methods: Option<Cow<'a, str>>) -> BlockTrace,
     state_unsubscribeRuntimeVersion(subscription: Cow<'a, str>) -> bool [chain_unsubscribeRuntimeVersion],
     state_unsubscribeStorage(subscription: Cow<'a, str>) -> bool,
     system_accountNextIndex(account: AccountId) -> u64,
-    system_addReservedPeer() -> (), // TODO:
+    /// Adds the given multiaddress, which must end with `/p2p/<peer id>`, to the address book of
+    /// the node and opens a connection to this peer.
+    system_addReservedPeer(peer: Cow<'a, str>) -> (),
     system_chain() -> Cow<'a, str>,
     system_chainType() -> Cow<'a, str>,
-    system_dryRun() -> () [system_dryRunAt], // TODO:
+    /// Applies the given extrinsic on top of the state of the given block, or of the best block if
+    /// `None`, and returns the SCALE-encoded output of `BlockBuilder_apply_extrinsic`. The
+    /// extrinsic isn't broadcasted and the changes it makes aren't saved.
+    system_dryRun(extrinsic: HexString, at: Option<HashHexString>) -> HexString [system_dryRunAt],
     system_health() -> SystemHealth,
     system_localListenAddresses() -> Vec<String>,
     /// Returns the Base58 encoding of the network identity of the node on the peer-to-peer network.
@@ -447,7 +454,8 @@ define_methods! {
     system_nodeRoles() -> Cow<'a, [NodeRole]>,
     system_peers() -> Vec<SystemPeer>,
     system_properties() -> Box<serde_json::value::RawValue>,
-    system_removeReservedPeer() -> (), // TODO:
+    /// Disconnects from the peer whose Base58-encoded identity is passed as parameter.
+    system_removeReservedPeer(peer_id: Cow<'a, str>) -> (),
     /// Returns, as an opaque string, the version of the client serving these JSON-RPC requests.
     system_version() -> Cow<'a, str>,
 
@@ -495,7 +503,27 @@ define_methods! {
     chainSpec_v1_genesisHash() -> HashHexString,
     chainSpec_v1_properties() -> Box<serde_json::value::RawValue>,
 
+    /// Returns the resources used by the execution of the given block, if the node has
+    /// measured them.
+    sudo_unstable_blockExecutionProfile(hash: HashHexString) -> Option<BlockExecutionProfile>,
+    /// Returns the GrandPa authorities set and Babe epoch that the given block belongs to. This
+    /// is determined by replaying the headers of the chain from the genesis block, and can be
+    /// expensive.
+    sudo_unstable_blockAuthorities(hash: HashHexString) -> BlockAuthorities,
+    /// Returns the number of rows and the size of each table of the database of the node, and
+    /// the range of blocks whose storage and body are still available.
+    sudo_unstable_databaseStatistics() -> DatabaseStatistics,
+    /// Marks the given block and all its ancestors as finalized, without any finality proof.
+    /// Meant for development chains that lack a finality mechanism, and for recovering networks
+    /// whose finality has stalled.
+    sudo_unstable_forceFinalize(hash: HashHexString) -> (),
     sudo_unstable_p2pDiscover(multiaddr: Cow<'a, str>) -> (),
+    /// Pauses or resumes the authoring of blocks by the node, without interrupting the syncing
+    /// and the networking.
+    sudo_unstable_setAuthoringEnabled(enabled: bool) -> (),
+    /// Returns the storage entries whose value differs between the two given blocks, which
+    /// makes it possible to track the modifications of the storage without executing blocks.
+    sudo_unstable_storageDiff(#[rename = "oldHash"] old_hash: HashHexString, #[rename = "newHash"] new_hash: HashHexString) -> Vec<StorageDiffItem>,
     sudo_unstable_version() -> Cow<'a, str>,
 
     transaction_v1_broadcast(transaction: HexString) -> Cow<'a, str>,
@@ -1031,6 +1059,162 @@ pub struct StorageChangeSet {
     pub changes: Vec<(HexString, Option<HexString>)>,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockExecutionProfile {
+    #[serde(rename = "blockHash")]
+    pub block_hash: HashHexString,
+    #[serde(rename = "blockNumber")]
+    pub block_number: u64,
+    pub initialization: ExecutionStepProfile,
+    pub extrinsics: Vec<ExecutionStepProfile>,
+    pub finalization: ExecutionStepProfile,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockAuthorities {
+    #[serde(rename = "blockHash")]
+    pub block_hash: HashHexString,
+    #[serde(rename = "blockNumber")]
+    pub block_number: u64,
+    pub grandpa: Option<GrandpaAuthoritySet>,
+    pub babe: Option<BabeEpoch>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DatabaseStatistics {
+    pub tables: Vec<DatabaseTableStatistics>,
+    #[serde(rename = "finalizedBlockNumber")]
+    pub finalized_block_number: u64,
+    #[serde(rename = "oldestBlockWithState")]
+    pub oldest_block_with_state: Option<u64>,
+    #[serde(rename = "oldestBlockWithBody")]
+    pub oldest_block_with_body: Option<u64>,
+    #[serde(rename = "numJustifications")]
+    pub num_justifications: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DatabaseTableStatistics {
+    pub name: String,
+    #[serde(rename = "numRows")]
+    pub num_rows: u64,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StorageDiffItem {
+    pub key: HexString,
+    #[serde(rename = "oldValue")]
+    pub old_value: Option<HexString>,
+    #[serde(rename = "newValue")]
+    pub new_value: Option<HexString>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GrandpaAuthoritySet {
+    #[serde(rename = "setId")]
+    pub set_id: u64,
+    pub authorities: Vec<WeightedAuthority>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BabeEpoch {
+    #[serde(rename = "epochIndex")]
+    pub epoch_index: u64,
+    #[serde(rename = "startSlot")]
+    pub start_slot: Option<u64>,
+    pub authorities: Vec<WeightedAuthority>,
+    pub randomness: HashHexString,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WeightedAuthority {
+    #[serde(rename = "publicKey")]
+    pub public_key: HashHexString,
+    /// SS58 address corresponding to the public key, using the prefix of the chain.
+    pub address: String,
+    pub weight: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExecutionStepProfile {
+    #[serde(rename = "durationMicros")]
+    pub duration_micros: u64,
+    #[serde(rename = "databaseAccessesDurationMicros")]
+    pub database_accesses_duration_micros: u64,
+    #[serde(rename = "proofSize")]
+    pub proof_size: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockTrace {
+    #[serde(rename = "blockHash")]
+    pub block_hash: HashHexString,
+    #[serde(rename = "parentHash")]
+    pub parent_hash: HashHexString,
+    pub events: Vec<BlockTraceEvent>,
+}
+
+/// Event of a [`BlockTrace`].
+///
+/// Fields whose name ends with `Nibbles` contain `0x` followed with one hexadecimal digit per
+/// nibble, as these keys don't necessarily contain an even number of nibbles.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum BlockTraceEvent {
+    #[serde(rename = "storageGet")]
+    StorageGet {
+        #[serde(rename = "childTrie")]
+        child_trie: Option<HexString>,
+        key: HexString,
+        value: Option<HexString>,
+    },
+    #[serde(rename = "storageNextKey")]
+    StorageNextKey {
+        #[serde(rename = "childTrie")]
+        child_trie: Option<HexString>,
+        #[serde(rename = "keyNibbles")]
+        key_nibbles: String,
+        #[serde(rename = "nextKeyNibbles")]
+        next_key_nibbles: Option<String>,
+    },
+    #[serde(rename = "storageClosestDescendantMerkleValue")]
+    StorageClosestDescendantMerkleValue {
+        #[serde(rename = "childTrie")]
+        child_trie: Option<HexString>,
+        #[serde(rename = "keyNibbles")]
+        key_nibbles: String,
+        #[serde(rename = "merkleValue")]
+        merkle_value: Option<HexString>,
+    },
+    #[serde(rename = "signatureVerification")]
+    SignatureVerification {
+        #[serde(rename = "publicKey")]
+        public_key: HexString,
+        message: HexString,
+        signature: HexString,
+        success: bool,
+    },
+    #[serde(rename = "offchainStorageSet")]
+    OffchainStorageSet {
+        key: HexString,
+        value: Option<HexString>,
+    },
+    #[serde(rename = "log")]
+    Log {
+        target: Option<String>,
+        message: String,
+    },
+    #[serde(rename = "storageSet")]
+    StorageSet {
+        #[serde(rename = "childTrie")]
+        child_trie: Option<HexString>,
+        key: HexString,
+        value: Option<HexString>,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemHealth {
     pub is_syncing: bool,
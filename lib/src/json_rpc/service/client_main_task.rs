@@ -418,6 +418,7 @@ impl ClientMainTask {
                 | methods::MethodCall::state_getStorageSize { .. }
                 | methods::MethodCall::state_queryStorage { .. }
                 | methods::MethodCall::state_queryStorageAt { .. }
+                | methods::MethodCall::state_traceBlock { .. }
                 | methods::MethodCall::system_accountNextIndex { .. }
                 | methods::MethodCall::system_addReservedPeer { .. }
                 | methods::MethodCall::system_chain { .. }
@@ -437,7 +438,13 @@ impl ClientMainTask {
                 | methods::MethodCall::chainSpec_v1_genesisHash { .. }
                 | methods::MethodCall::chainSpec_v1_properties { .. }
                 | methods::MethodCall::rpc_methods { .. }
+                | methods::MethodCall::sudo_unstable_blockExecutionProfile { .. }
+                | methods::MethodCall::sudo_unstable_blockAuthorities { .. }
+                | methods::MethodCall::sudo_unstable_databaseStatistics { .. }
+                | methods::MethodCall::sudo_unstable_forceFinalize { .. }
                 | methods::MethodCall::sudo_unstable_p2pDiscover { .. }
+                | methods::MethodCall::sudo_unstable_setAuthoringEnabled { .. }
+                | methods::MethodCall::sudo_unstable_storageDiff { .. }
                 | methods::MethodCall::sudo_unstable_version { .. }
                 | methods::MethodCall::chainHead_v1_body { .. }
                 | methods::MethodCall::chainHead_v1_call { .. }
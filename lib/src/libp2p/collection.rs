@@ -69,7 +69,7 @@ use rand_chacha::{
 
 pub use super::peer_id::PeerId;
 pub use super::read_write::ReadWrite;
-pub use established::{InboundError, InboundTy, SubstreamFate};
+pub use established::{InboundError, InboundTy, MessageKind, MessageSizeViolation, SubstreamFate};
 pub use single_stream_handshake::HandshakeError;
 
 pub use multi_stream::MultiStreamConnectionTask;
@@ -2206,6 +2206,18 @@ pub enum NotificationsInClosedErr {
     Substream(established::NotificationsInClosedErr),
 }
 
+impl NotificationsInClosedErr {
+    /// Returns the size violation that has caused the substream to be closed, if any.
+    pub fn message_size_violation(&self) -> Option<&MessageSizeViolation> {
+        match self {
+            NotificationsInClosedErr::Substream(
+                established::NotificationsInClosedErr::MessageSizeViolation(violation),
+            ) => Some(violation),
+            _ => None,
+        }
+    }
+}
+
 /// Error potentially returned by [`Network::queue_notification`].
 #[derive(Debug, derive_more::Display)]
 pub enum QueueNotificationError {
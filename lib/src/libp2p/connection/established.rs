@@ -27,8 +27,8 @@ use core::time::Duration;
 pub use multi_stream::{MultiStream, SubstreamFate};
 pub use single_stream::{ConnectionPrototype, Error, SingleStream};
 pub use substream::{
-    InboundError, InboundTy, NotificationsInClosedErr, NotificationsOutErr, RequestError,
-    RespondInRequestError,
+    InboundError, InboundTy, MessageKind, MessageSizeViolation, NotificationsInClosedErr,
+    NotificationsOutErr, RequestError, RespondInRequestError,
 };
 
 /// Identifier of a request or a notifications substream.
@@ -573,22 +573,27 @@ where
                         match read_write.incoming_bytes_take_leb128(handshake_in_max_size) {
                             Ok(Some(s)) => handshake_in_size = Some(s),
                             Ok(None) => {}
-                            Err(error) => return (
-                                None,
-                                Some(Event::Response {
-                                    response: Err(match error {
-                                        read_write::IncomingBytesTakeLeb128Error::InvalidLeb128 => {
-                                            RequestError::ResponseInvalidLeb128
-                                        }
-                                        read_write::IncomingBytesTakeLeb128Error::ReadClosed => {
-                                            RequestError::SubstreamClosed
-                                        }
-                                        read_write::IncomingBytesTakeLeb128Error::TooLarge => {
-                                            RequestError::ResponseTooLarge
-                                        }
+                            Err(error) => {
+                                read_write.wake_up_asap();
+                                return (
+                                    Some(SubstreamInner::NotificationsOutNegotiationFailed),
+                                    Some(Event::NotificationsOutResult {
+                                        result: Err(match error {
+                                            read_write::IncomingBytesTakeLeb128Error::TooLarge {
+                                                decoded,
+                                                max,
+                                            } => NotificationsOutErr::MessageSizeViolation(
+                                                MessageSizeViolation {
+                                                    kind: MessageKind::Handshake,
+                                                    size: decoded,
+                                                    max_size: max,
+                                                },
+                                            ),
+                                            error => NotificationsOutErr::HandshakeRecvError(error),
+                                        }),
                                     }),
-                                }),
-                            ),
+                                );
+                            }
                         }
                     }
                 }
@@ -748,9 +753,16 @@ where
                                         read_write::IncomingBytesTakeLeb128Error::ReadClosed => {
                                             RequestError::SubstreamClosed
                                         }
-                                        read_write::IncomingBytesTakeLeb128Error::TooLarge => {
-                                            RequestError::ResponseTooLarge
-                                        }
+                                        read_write::IncomingBytesTakeLeb128Error::TooLarge {
+                                            decoded,
+                                            max,
+                                        } => RequestError::MessageSizeViolation(
+                                            MessageSizeViolation {
+                                                kind: MessageKind::Response,
+                                                size: decoded,
+                                                max_size: max,
+                                            },
+                                        ),
                                     }),
                                 }),
                             ),
@@ -799,6 +811,24 @@ where
                     match read_write.incoming_bytes_take_leb128(request_max_size) {
                         Ok(Some(s)) => request_size = Some(s),
                         Ok(None) => {}
+                        Err(read_write::IncomingBytesTakeLeb128Error::TooLarge {
+                            decoded,
+                            max,
+                        }) => {
+                            return (
+                                None,
+                                Some(Event::InboundError {
+                                    error: InboundError::MessageSizeViolation(
+                                        MessageSizeViolation {
+                                            kind: MessageKind::Request,
+                                            size: decoded,
+                                            max_size: max,
+                                        },
+                                    ),
+                                    was_accepted: true,
+                                }),
+                            )
+                        }
                         Err(error) => {
                             return (
                                 None,
@@ -863,6 +893,24 @@ where
                     match read_write.incoming_bytes_take_leb128(handshake_max_size) {
                         Ok(Some(s)) => handshake_size = Some(s),
                         Ok(None) => {}
+                        Err(read_write::IncomingBytesTakeLeb128Error::TooLarge {
+                            decoded,
+                            max,
+                        }) => {
+                            return (
+                                None,
+                                Some(Event::InboundError {
+                                    error: InboundError::MessageSizeViolation(
+                                        MessageSizeViolation {
+                                            kind: MessageKind::Handshake,
+                                            size: decoded,
+                                            max_size: max,
+                                        },
+                                    ),
+                                    was_accepted: true,
+                                }),
+                            )
+                        }
                         Err(error) => {
                             return (
                                 None,
@@ -961,7 +1009,19 @@ where
                             return (
                                 Some(SubstreamInner::NotificationsInClosed),
                                 Some(Event::NotificationsInClose {
-                                    outcome: Err(NotificationsInClosedErr::ProtocolError(error)),
+                                    outcome: Err(match error {
+                                        read_write::IncomingBytesTakeLeb128Error::TooLarge {
+                                            decoded,
+                                            max,
+                                        } => NotificationsInClosedErr::MessageSizeViolation(
+                                            MessageSizeViolation {
+                                                kind: MessageKind::Notification,
+                                                size: decoded,
+                                                max_size: max,
+                                            },
+                                        ),
+                                        error => NotificationsInClosedErr::ProtocolError(error),
+                                    }),
                                 }),
                             );
                         }
@@ -1546,6 +1606,9 @@ pub enum InboundError {
         fmt = "Unexpected end of file while receiving an inbound notifications substream handshake"
     )]
     NotificationsInUnexpectedEof,
+    /// The inbound request or notifications substream handshake is larger than allowed.
+    #[display(fmt = "{_0}")]
+    MessageSizeViolation(MessageSizeViolation),
 }
 
 /// Error that can happen during a request in a request-response scheme.
@@ -1566,8 +1629,9 @@ pub enum RequestError {
     NegotiationError(multistream_select::Error),
     /// Invalid LEB128 number when receiving the response.
     ResponseInvalidLeb128,
-    /// Number of bytes decoded is larger than expected when receiving the response.
-    ResponseTooLarge,
+    /// The response is larger than allowed.
+    #[display(fmt = "{_0}")]
+    MessageSizeViolation(MessageSizeViolation),
 }
 
 impl RequestError {
@@ -1581,7 +1645,7 @@ impl RequestError {
             RequestError::SubstreamReset => true,
             RequestError::NegotiationError(_) => true,
             RequestError::ResponseInvalidLeb128 => true,
-            RequestError::ResponseTooLarge => true,
+            RequestError::MessageSizeViolation(_) => true,
         }
     }
 }
@@ -1610,6 +1674,9 @@ pub enum NotificationsOutErr {
     /// Error while receiving the remote's handshake.
     #[display(fmt = "Error while receiving remote handshake: {_0}")]
     HandshakeRecvError(read_write::IncomingBytesTakeLeb128Error),
+    /// The remote's handshake is larger than allowed.
+    #[display(fmt = "{_0}")]
+    MessageSizeViolation(MessageSizeViolation),
 }
 
 /// Reason why an inbound notifications substream has been closed.
@@ -1624,4 +1691,40 @@ pub enum NotificationsInClosedErr {
     SubstreamReset,
     /// Substream has been force-closed because the graceful timeout has been reached.
     CloseDesiredTimeout,
+    /// The remote has sent a notification larger than allowed.
+    #[display(fmt = "{_0}")]
+    MessageSizeViolation(MessageSizeViolation),
+}
+
+/// Message sent by the remote whose size, as announced by its length prefix, exceeds the limit
+/// of its protocol.
+///
+/// Contrary to most other errors, a size violation is always the fault of the remote, and can
+/// be used to lower its reputation.
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Received {kind} of {size} bytes, while the limit is {max_size} bytes")]
+pub struct MessageSizeViolation {
+    /// Kind of message that has been received.
+    pub kind: MessageKind,
+    /// Size, in bytes, announced by the remote.
+    pub size: usize,
+    /// Maximum size, in bytes, that was allowed.
+    pub max_size: usize,
+}
+
+/// See [`MessageSizeViolation::kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum MessageKind {
+    /// Request of a request-response protocol.
+    #[display(fmt = "request")]
+    Request,
+    /// Response of a request-response protocol.
+    #[display(fmt = "response")]
+    Response,
+    /// Handshake of a notifications protocol.
+    #[display(fmt = "handshake")]
+    Handshake,
+    /// Notification of a notifications protocol.
+    #[display(fmt = "notification")]
+    Notification,
 }
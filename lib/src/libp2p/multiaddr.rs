@@ -206,6 +206,7 @@ pub enum Protocol<T = Vec<u8>> {
     Ip6([u8; 16]),
     P2p(Multihash<T>), // TODO: put directly a PeerId? unclear
     Quic,
+    QuicV1,
     Tcp(u16),
     Tls,
     Udp(u16),
@@ -276,6 +277,8 @@ impl<'a> Protocol<Cow<'a, [u8]>> {
                 ))
             }
             "tls" => Ok(Protocol::Tls),
+            "quic" => Ok(Protocol::Quic),
+            "quic-v1" => Ok(Protocol::QuicV1),
             "udp" => {
                 let port = iter.next().ok_or(ParseError::UnexpectedEof)?;
                 Ok(Protocol::Udp(
@@ -329,6 +332,7 @@ impl<T: AsRef<[u8]>> Protocol<T> {
             Protocol::Ip6(_) => 41,
             Protocol::P2p(_) => 421,
             Protocol::Quic => 460,
+            Protocol::QuicV1 => 461,
             Protocol::Tcp(_) => 6,
             Protocol::Tls => 448,
             Protocol::Udp(_) => 273,
@@ -403,6 +407,7 @@ impl<T: AsRef<[u8]>> fmt::Display for Protocol<T> {
                 write!(f, "/p2p/{}", bs58::encode(multihash.as_ref()).into_string())
             }
             Protocol::Quic => write!(f, "/quic"),
+            Protocol::QuicV1 => write!(f, "/quic-v1"),
             Protocol::Tcp(port) => write!(f, "/tcp/{port}"),
             Protocol::Tls => write!(f, "/tls"),
             Protocol::Udp(port) => write!(f, "/udp/{port}"),
@@ -543,6 +548,7 @@ fn protocol<'a, T: From<&'a [u8]> + AsRef<[u8]>, E: nom::error::ParseError<&'a [
             )(bytes),
             448 => Ok((bytes, Protocol::Tls)),
             460 => Ok((bytes, Protocol::Quic)),
+            461 => Ok((bytes, Protocol::QuicV1)),
             477 => Ok((bytes, Protocol::Ws)),
             478 => Ok((bytes, Protocol::Wss)),
             // TODO: unclear what the /memory payload is, see https://github.com/multiformats/multiaddr/issues/127
@@ -599,6 +605,8 @@ mod tests {
         check_valid("/dnsaddr/./tcp/55");
         check_valid("/memory/1234567890");
         check_valid("/webrtc-direct");
+        check_valid("/ip4/1.2.3.4/udp/30333/quic");
+        check_valid("/ip6/::1/udp/30333/quic-v1");
         // TODO: example valid /certhash
 
         check_invalid("/");
@@ -610,6 +618,7 @@ mod tests {
         check_invalid("/tcp/65536");
         check_invalid("/p2p/blablabla");
         check_invalid("/webrtc-direct/2");
+        check_invalid("/quic-v2");
         check_invalid("/certhash");
         check_invalid("/certhash/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN");
     }
@@ -162,7 +162,10 @@ impl<TNow> ReadWrite<TNow> {
             Ok((rest, num)) => {
                 if num > max_decoded_number {
                     // TODO: consider detecting earlier if `TooLarge` is reached; for example is max is 20 we know that it can't be more than one byte
-                    return Err(IncomingBytesTakeLeb128Error::TooLarge);
+                    return Err(IncomingBytesTakeLeb128Error::TooLarge {
+                        decoded: num,
+                        max: max_decoded_number,
+                    });
                 }
 
                 let consumed_bytes = self.incoming_buffer.len() - rest.len();
@@ -315,7 +318,13 @@ pub enum IncomingBytesTakeLeb128Error {
     /// Reading side of the stream is closed.
     ReadClosed,
     /// Number of bytes decoded is larger than expected.
-    TooLarge,
+    #[display(fmt = "Decoded number ({decoded}) is larger than the maximum ({max})")]
+    TooLarge {
+        /// Number that has been decoded.
+        decoded: usize,
+        /// Maximum that was passed to [`ReadWrite::incoming_bytes_take_leb128`].
+        max: usize,
+    },
 }
 
 #[cfg(test)]
@@ -15,8 +15,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-//! Implementation of a WebSocket client that wraps around an abstract representation of a TCP
-//! socket through the `AsyncRead` and `AsyncWrite` traits.
+//! Implementation of a WebSocket client and server that wraps around an abstract representation
+//! of a TCP socket through the `AsyncRead` and `AsyncWrite` traits.
 
 #![cfg(feature = "std")]
 #![cfg_attr(docsrs, doc(cfg(feature = "std")))]
@@ -75,6 +75,35 @@ pub async fn websocket_client_handshake<T: AsyncRead + AsyncWrite + Send + Unpin
     })
 }
 
+/// Waits for the HTTP-like request of a WebSocket client on the given socket and accepts it,
+/// then returns an object that translates reads and writes into WebSocket binary frames.
+///
+/// Must be used on incoming sockets, as opposed to [`websocket_client_handshake`].
+pub async fn websocket_server_handshake<T: AsyncRead + AsyncWrite + Send + Unpin + 'static>(
+    tcp_socket: T,
+) -> Result<Connection<T>, io::Error> {
+    let mut server = soketto::handshake::Server::new(tcp_socket);
+
+    let key = match server.receive_request().await {
+        Ok(request) => request.key(),
+        Err(err) => return Err(io::Error::other(err)),
+    };
+
+    let accept = soketto::handshake::server::Response::Accept {
+        key,
+        protocol: None,
+    };
+    if let Err(err) = server.send_response(&accept).await {
+        return Err(io::Error::other(err));
+    }
+
+    let (sender, receiver) = server.into_builder().finish();
+    Ok(Connection {
+        sender: Write::Idle(sender),
+        receiver: Read::Idle(receiver, Vec::with_capacity(1024), 0),
+    })
+}
+
 /// Negotiated WebSocket connection.
 ///
 /// Implements the `AsyncRead` and `AsyncWrite` traits.
@@ -0,0 +1,737 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of the metadata of a runtime.
+//!
+//! The metadata of a runtime is obtained by calling the `Metadata_metadata` runtime function
+//! (see also [`remove_metadata_length_prefix`](crate::json_rpc::methods::remove_metadata_length_prefix)).
+//! It describes, amongst other things, the list of pallets of the runtime and the value of
+//! their constants.
+//!
+//! Only the parts of the metadata that smoldot needs are decoded. In particular, the registry of
+//! types found at the beginning of the metadata is skipped over rather than interpreted. As a
+//! consequence, the types of the constants aren't known, and the functions of this module that
+//! interpret constants make assumptions about their layout. For example, the width of the
+//! `Balance` type of the chain is deduced from the size of the values that contain balances.
+//!
+//! Only versions 14 and 15 of the metadata format are supported.
+
+use alloc::vec::Vec;
+use core::str;
+
+/// Constant of a pallet, as found in the metadata. See [`decode_constants`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalletConstant<'a> {
+    /// Name of the pallet the constant belongs to.
+    pub pallet: &'a str,
+    /// Name of the constant.
+    pub name: &'a str,
+    /// SCALE-encoded value of the constant.
+    pub value: &'a [u8],
+}
+
+/// Decodes the metadata of a runtime and returns the list of all the constants of all the
+/// pallets.
+///
+/// The metadata must not contain any length prefix.
+pub fn decode_constants(metadata: &[u8]) -> Result<Vec<PalletConstant<'_>>, DecodeError> {
+    let Some(after_magic) = metadata.strip_prefix(&b"meta"[..]) else {
+        return Err(DecodeError::BadMagicNumber);
+    };
+
+    let (version, after_version) = after_magic.split_first().ok_or(DecodeError::ParseError)?;
+    let has_pallet_docs = match *version {
+        14 => false,
+        15 => true,
+        version => return Err(DecodeError::UnsupportedVersion(version)),
+    };
+
+    // Note that `all_consuming` isn't used, as the metadata contains more information after the
+    // list of pallets.
+    match nom_decode_constants::<nom::error::Error<&[u8]>>(has_pallet_docs)(after_version) {
+        Ok((_, constants)) => Ok(constants),
+        Err(_) => Err(DecodeError::ParseError),
+    }
+}
+
+/// Decodes the metadata of a runtime and returns the value of the given constant of the given
+/// pallet, or `None` if it can't be found.
+pub fn pallet_constant<'a>(
+    metadata: &'a [u8],
+    pallet: &str,
+    constant: &str,
+) -> Result<Option<&'a [u8]>, DecodeError> {
+    Ok(decode_constants(metadata)?
+        .into_iter()
+        .find(|c| c.pallet == pallet && c.name == constant)
+        .map(|c| c.value))
+}
+
+/// Fee-related constants of a runtime. See [`fee_constants`].
+///
+/// Each field is `None` if the constant can't be found in the metadata, or if its value doesn't
+/// have the expected format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeConstants {
+    /// Polynomial that converts a weight into a fee. Value of the `WeightToFee` constant of the
+    /// `TransactionPayment` pallet.
+    pub weight_to_fee: Option<Vec<WeightToFeeCoefficient>>,
+    /// Polynomial that converts the length in bytes of a transaction into a fee. Value of the
+    /// `LengthToFee` constant of the `TransactionPayment` pallet.
+    pub length_to_fee: Option<Vec<WeightToFeeCoefficient>>,
+    /// Fee per byte of transaction. Value of the `TransactionByteFee` constant of the
+    /// `TransactionPayment` pallet. Older runtimes use this constant instead of
+    /// [`FeeConstants::length_to_fee`].
+    pub transaction_byte_fee: Option<u128>,
+    /// Value of the `OperationalFeeMultiplier` constant of the `TransactionPayment` pallet.
+    pub operational_fee_multiplier: Option<u8>,
+    /// Value of the `ExistentialDeposit` constant of the `Balances` pallet.
+    pub existential_deposit: Option<u128>,
+    /// Reference time of the base weight of all transactions of the normal class, found in the
+    /// `BlockWeights` constant of the `System` pallet.
+    pub base_extrinsic_weight: Option<u64>,
+}
+
+impl FeeConstants {
+    /// Estimates the fee of a transaction of the normal class whose weight (reference time) and
+    /// length in bytes are given, not including any tip.
+    ///
+    /// The runtime multiplies the weight-related part of the fee by a multiplier that is adjusted
+    /// at each block depending on the congestion of the chain, and that is found in the storage
+    /// rather than in the metadata. This estimation assumes that this multiplier is equal to 1.
+    ///
+    /// Returns `None` if the constants necessary to estimate the fee are missing.
+    pub fn estimate_fee(&self, weight: u64, length: u32) -> Option<u128> {
+        let weight_to_fee = self.weight_to_fee.as_ref()?;
+
+        let length_fee = match (&self.length_to_fee, self.transaction_byte_fee) {
+            (Some(length_to_fee), _) => evaluate_fee_polynomial(length_to_fee, u64::from(length)),
+            (None, Some(byte_fee)) => byte_fee.saturating_mul(u128::from(length)),
+            (None, None) => return None,
+        };
+
+        let base_fee = evaluate_fee_polynomial(weight_to_fee, self.base_extrinsic_weight?);
+        let weight_fee = evaluate_fee_polynomial(weight_to_fee, weight);
+
+        Some(
+            base_fee
+                .saturating_add(length_fee)
+                .saturating_add(weight_fee),
+        )
+    }
+}
+
+/// Term of a polynomial that converts a weight or a length into a fee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightToFeeCoefficient {
+    /// Integral part of the coefficient.
+    pub coeff_integer: u128,
+    /// Fractional part of the coefficient, in parts per billion.
+    pub coeff_frac: u32,
+    /// If `true`, the term is subtracted from the total rather than added.
+    pub negative: bool,
+    /// Power the input is raised to.
+    pub degree: u8,
+}
+
+/// Evaluates the given polynomial for the given input, in the same way as the runtime would.
+///
+/// All operations saturate at `u128::MAX` and at 0. Since the runtime saturates at the maximum
+/// value of its own `Balance` type, the result might differ from the runtime's if the `Balance`
+/// type is smaller than 128 bits and an overflow happens.
+pub fn evaluate_fee_polynomial(polynomial: &[WeightToFeeCoefficient], input: u64) -> u128 {
+    polynomial.iter().fold(0u128, |acc, coefficient| {
+        let input = u128::from(input).saturating_pow(u32::from(coefficient.degree));
+        let frac = perbill_mul(coefficient.coeff_frac, input);
+        let integer = coefficient.coeff_integer.saturating_mul(input);
+        if coefficient.negative {
+            acc.saturating_sub(frac).saturating_sub(integer)
+        } else {
+            acc.saturating_add(frac).saturating_add(integer)
+        }
+    })
+}
+
+/// Decodes the metadata of a runtime and extracts the constants related to transaction fees.
+///
+/// The metadata must not contain any length prefix.
+pub fn fee_constants(metadata: &[u8]) -> Result<FeeConstants, DecodeError> {
+    let constants = decode_constants(metadata)?;
+    let find = |pallet: &str, name: &str| {
+        constants
+            .iter()
+            .find(|c| c.pallet == pallet && c.name == name)
+            .map(|c| c.value)
+    };
+
+    Ok(FeeConstants {
+        weight_to_fee: find("TransactionPayment", "WeightToFee").and_then(decode_polynomial),
+        length_to_fee: find("TransactionPayment", "LengthToFee").and_then(decode_polynomial),
+        transaction_byte_fee: find("TransactionPayment", "TransactionByteFee")
+            .and_then(decode_balance),
+        operational_fee_multiplier: find("TransactionPayment", "OperationalFeeMultiplier")
+            .and_then(|value| <[u8; 1]>::try_from(value).ok())
+            .map(|[multiplier]| multiplier),
+        existential_deposit: find("Balances", "ExistentialDeposit").and_then(decode_balance),
+        base_extrinsic_weight: find("System", "BlockWeights")
+            .and_then(decode_base_extrinsic_weight),
+    })
+}
+
+/// Potential error when decoding the metadata.
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum DecodeError {
+    /// The metadata doesn't start with the expected magic number.
+    BadMagicNumber,
+    /// The metadata uses a version of the format that smoldot doesn't support.
+    #[display(fmt = "Unsupported metadata version: {_0}")]
+    UnsupportedVersion(u8),
+    /// Failed to parse the metadata.
+    ParseError,
+}
+
+/// Multiplies `value` by `perbill` parts per billion, rounding to the nearest integer and
+/// rounding down in case of a tie, like the runtime does.
+fn perbill_mul(perbill: u32, value: u128) -> u128 {
+    const ACCURACY: u128 = 1_000_000_000;
+    let perbill = u128::from(perbill).min(ACCURACY);
+
+    let whole = (value / ACCURACY) * perbill;
+    let remainder = (value % ACCURACY) * perbill;
+    let rounding = if (remainder % ACCURACY) * 2 > ACCURACY {
+        1
+    } else {
+        0
+    };
+    whole + remainder / ACCURACY + rounding
+}
+
+/// Decodes a `Vec<WeightToFeeCoefficient<Balance>>`.
+///
+/// The width of `Balance` is deduced from the size of the encoded value.
+fn decode_polynomial(value: &[u8]) -> Option<Vec<WeightToFeeCoefficient>> {
+    let (coefficients, num_coefficients) =
+        crate::util::nom_scale_compact_usize::<nom::error::Error<&[u8]>>(value).ok()?;
+    if num_coefficients == 0 {
+        return if coefficients.is_empty() {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+
+    if coefficients.len() % num_coefficients != 0 {
+        return None;
+    }
+    let coefficient_size = coefficients.len() / num_coefficients;
+    // Each coefficient is made of a `Balance`, a `u32`, a `bool`, and a `u8`.
+    let balance_size = coefficient_size.checked_sub(6)?;
+    if !matches!(balance_size, 1 | 2 | 4 | 8 | 16) {
+        return None;
+    }
+
+    coefficients
+        .chunks_exact(coefficient_size)
+        .map(|coefficient| {
+            let (balance, rest) = coefficient.split_at(balance_size);
+            Some(WeightToFeeCoefficient {
+                coeff_integer: decode_balance(balance)?,
+                coeff_frac: u32::from_le_bytes(<[u8; 4]>::try_from(&rest[..4]).unwrap()),
+                negative: match rest[4] {
+                    0 => false,
+                    1 => true,
+                    _ => return None,
+                },
+                degree: rest[5],
+            })
+        })
+        .collect()
+}
+
+/// Decodes a `Balance`, whose width is deduced from the size of the encoded value.
+fn decode_balance(value: &[u8]) -> Option<u128> {
+    if !matches!(value.len(), 1 | 2 | 4 | 8 | 16) {
+        return None;
+    }
+
+    // The SCALE encoding of a number is the number in little endian.
+    Some(
+        value
+            .iter()
+            .rev()
+            .fold(0u128, |acc, byte| (acc << 8) | u128::from(*byte)),
+    )
+}
+
+/// Decodes a `BlockWeights` and returns the reference time of the base weight of the
+/// transactions of the normal class.
+fn decode_base_extrinsic_weight(value: &[u8]) -> Option<u64> {
+    // Older runtimes encode weights as a single `u64`, while newer runtimes encode weights as a
+    // compact reference time followed with a compact proof size.
+    if let Ok((_, weight)) = nom::combinator::all_consuming(nom_block_weights_base_extrinsic::<
+        nom::error::Error<&[u8]>,
+    >(nom_weight_v2))(value)
+    {
+        return Some(weight);
+    }
+
+    nom::combinator::all_consuming(
+        nom_block_weights_base_extrinsic::<nom::error::Error<&[u8]>>(
+            nom::number::streaming::le_u64,
+        ),
+    )(value)
+    .ok()
+    .map(|(_, weight)| weight)
+}
+
+fn nom_block_weights_base_extrinsic<'a, E: nom::error::ParseError<&'a [u8]>>(
+    weight: impl Fn(&'a [u8]) -> nom::IResult<&'a [u8], u64, E> + Copy,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], u64, E> {
+    move |bytes| {
+        let (bytes, (_base_block, _max_block)) = nom::sequence::tuple((weight, weight))(bytes)?;
+        let (bytes, normal_base_extrinsic) = nom_weights_per_class(weight)(bytes)?;
+        let (bytes, _operational_base_extrinsic) = nom_weights_per_class(weight)(bytes)?;
+        let (bytes, _mandatory_base_extrinsic) = nom_weights_per_class(weight)(bytes)?;
+        Ok((bytes, normal_base_extrinsic))
+    }
+}
+
+fn nom_weights_per_class<'a, E: nom::error::ParseError<&'a [u8]>>(
+    weight: impl Fn(&'a [u8]) -> nom::IResult<&'a [u8], u64, E> + Copy,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], u64, E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            weight,
+            crate::util::nom_option_decode(weight),
+            crate::util::nom_option_decode(weight),
+            crate::util::nom_option_decode(weight),
+        )),
+        |(base_extrinsic, _max_extrinsic, _max_total, _reserved)| base_extrinsic,
+    )
+}
+
+fn nom_weight_v2<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], u64, E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            crate::util::nom_scale_compact_u64,
+            crate::util::nom_scale_compact_u64,
+        )),
+        |(ref_time, _proof_size)| ref_time,
+    )(bytes)
+}
+
+fn nom_decode_constants<
+    'a,
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+>(
+    has_pallet_docs: bool,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], Vec<PalletConstant<'a>>, E> {
+    move |bytes| {
+        let (bytes, ()) = nom_skip_vec(nom_skip_type::<E>)(bytes)?;
+        let (mut bytes, num_pallets) = crate::util::nom_scale_compact_usize::<E>(bytes)?;
+
+        let mut constants = Vec::new();
+        for _ in 0..num_pallets {
+            let (rest, pallet) = crate::util::nom_string_decode::<E>(bytes)?;
+            let (rest, _storage) =
+                crate::util::nom_option_decode(nom_skip_pallet_storage::<E>)(rest)?;
+            let (rest, _calls_ty) =
+                crate::util::nom_option_decode(crate::util::nom_scale_compact_usize::<E>)(rest)?;
+            let (rest, _event_ty) =
+                crate::util::nom_option_decode(crate::util::nom_scale_compact_usize::<E>)(rest)?;
+
+            let (mut rest, num_constants) = crate::util::nom_scale_compact_usize::<E>(rest)?;
+            for _ in 0..num_constants {
+                let (after_constant, (name, _ty, value, ())) = nom::sequence::tuple((
+                    crate::util::nom_string_decode::<E>,
+                    crate::util::nom_scale_compact_usize,
+                    crate::util::nom_bytes_decode,
+                    nom_skip_docs,
+                ))(rest)?;
+                constants.push(PalletConstant {
+                    pallet,
+                    name,
+                    value,
+                });
+                rest = after_constant;
+            }
+
+            let (rest, _error_ty) =
+                crate::util::nom_option_decode(crate::util::nom_scale_compact_usize::<E>)(rest)?;
+            let (rest, _index) = nom::number::streaming::u8::<_, E>(rest)?;
+            let (rest, ()) = if has_pallet_docs {
+                nom_skip_docs::<E>(rest)?
+            } else {
+                (rest, ())
+            };
+            bytes = rest;
+        }
+
+        Ok((bytes, constants))
+    }
+}
+
+/// Skips over a SCALE-encoded `Vec` whose elements are decoded with `inner`.
+fn nom_skip_vec<'a, O, E: nom::error::ParseError<&'a [u8]>>(
+    mut inner: impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], O, E>,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (), E> {
+    move |bytes| {
+        let (mut bytes, num_elements) = crate::util::nom_scale_compact_usize::<E>(bytes)?;
+        for _ in 0..num_elements {
+            bytes = inner(bytes)?.0;
+        }
+        Ok((bytes, ()))
+    }
+}
+
+fn nom_skip_docs<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom_skip_vec(crate::util::nom_bytes_decode)(bytes)
+}
+
+/// Skips over an entry of the registry of types.
+fn nom_skip_type<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            // Identifier.
+            crate::util::nom_scale_compact_usize,
+            // Path.
+            nom_skip_vec(crate::util::nom_bytes_decode),
+            // Type parameters.
+            nom_skip_vec(nom::sequence::tuple((
+                crate::util::nom_bytes_decode,
+                crate::util::nom_option_decode(crate::util::nom_scale_compact_usize),
+            ))),
+            nom_skip_type_def,
+            nom_skip_docs,
+        )),
+        |_| (),
+    )(bytes)
+}
+
+fn nom_skip_type_def<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    let (bytes, variant) = nom::number::streaming::u8::<_, E>(bytes)?;
+    match variant {
+        // Composite.
+        0 => nom_skip_vec(nom_skip_field)(bytes),
+        // Variant.
+        1 => nom_skip_vec(nom::sequence::tuple((
+            crate::util::nom_bytes_decode,
+            nom_skip_vec(nom_skip_field),
+            nom::number::streaming::u8,
+            nom_skip_docs,
+        )))(bytes),
+        // Sequence and compact.
+        2 | 6 => nom::combinator::map(crate::util::nom_scale_compact_usize, |_| ())(bytes),
+        // Array.
+        3 => nom::combinator::map(
+            nom::sequence::tuple((
+                nom::number::streaming::le_u32,
+                crate::util::nom_scale_compact_usize,
+            )),
+            |_| (),
+        )(bytes),
+        // Tuple.
+        4 => nom_skip_vec(crate::util::nom_scale_compact_usize)(bytes),
+        // Primitive.
+        5 => nom::combinator::map(nom::number::streaming::u8, |_| ())(bytes),
+        // Bit sequence.
+        7 => nom::combinator::map(
+            nom::sequence::tuple((
+                crate::util::nom_scale_compact_usize,
+                crate::util::nom_scale_compact_usize,
+            )),
+            |_| (),
+        )(bytes),
+        _ => Err(nom::Err::Error(nom::error::make_error(
+            bytes,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+fn nom_skip_field<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            crate::util::nom_option_decode(crate::util::nom_bytes_decode),
+            crate::util::nom_scale_compact_usize,
+            crate::util::nom_option_decode(crate::util::nom_bytes_decode),
+            nom_skip_docs,
+        )),
+        |_| (),
+    )(bytes)
+}
+
+fn nom_skip_pallet_storage<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            // Prefix.
+            crate::util::nom_bytes_decode,
+            nom_skip_vec(nom::sequence::tuple((
+                // Name.
+                crate::util::nom_bytes_decode,
+                // Modifier.
+                nom::number::streaming::u8,
+                nom::branch::alt((
+                    // Plain.
+                    nom::combinator::map(
+                        nom::sequence::preceded(
+                            nom::bytes::streaming::tag(&[0]),
+                            crate::util::nom_scale_compact_usize,
+                        ),
+                        |_| (),
+                    ),
+                    // Map.
+                    nom::combinator::map(
+                        nom::sequence::preceded(
+                            nom::bytes::streaming::tag(&[1]),
+                            nom::sequence::tuple((
+                                nom_skip_vec(nom::number::streaming::u8),
+                                crate::util::nom_scale_compact_usize,
+                                crate::util::nom_scale_compact_usize,
+                            )),
+                        ),
+                        |_| (),
+                    ),
+                )),
+                // Default value.
+                crate::util::nom_bytes_decode,
+                nom_skip_docs,
+            ))),
+        )),
+        |_| (),
+    )(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FeeConstants, WeightToFeeCoefficient};
+
+    fn compact(n: usize) -> u8 {
+        assert!(n < 64);
+        u8::try_from(n << 2).unwrap()
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = vec![compact(s.len())];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn constant(name: &str, value: &[u8]) -> Vec<u8> {
+        let mut out = string(name);
+        out.push(compact(0));
+        out.push(compact(value.len()));
+        out.extend_from_slice(value);
+        out.push(compact(0));
+        out
+    }
+
+    fn coefficient(integer: u128, frac: u32, negative: bool, degree: u8) -> Vec<u8> {
+        let mut out = integer.to_le_bytes().to_vec();
+        out.extend_from_slice(&frac.to_le_bytes());
+        out.push(u8::from(negative));
+        out.push(degree);
+        out
+    }
+
+    fn metadata(version: u8) -> Vec<u8> {
+        let mut out = b"meta".to_vec();
+        out.push(version);
+
+        // Types.
+        out.push(compact(2));
+        // `u8`.
+        out.extend_from_slice(&[compact(0), compact(0), compact(0), 5, 2, compact(0)]);
+        // `enum Foo<T> { A { x: u8 } }`.
+        out.push(compact(1));
+        out.push(compact(1));
+        out.extend(string("Foo"));
+        out.push(compact(1));
+        out.extend(string("T"));
+        out.extend_from_slice(&[1, compact(0)]);
+        out.extend_from_slice(&[1, compact(1)]);
+        out.extend(string("A"));
+        out.push(compact(1));
+        out.push(1);
+        out.extend(string("x"));
+        out.push(compact(0));
+        out.push(1);
+        out.extend(string("u8"));
+        out.push(compact(1));
+        out.extend(string("doc"));
+        out.extend_from_slice(&[0, compact(0)]);
+        out.push(compact(0));
+
+        // Pallets.
+        out.push(compact(3));
+
+        out.extend(string("System"));
+        out.push(1);
+        out.extend(string("System"));
+        out.push(compact(2));
+        out.extend(string("Number"));
+        out.extend_from_slice(&[0, 0, compact(0), compact(1), 0, compact(0)]);
+        out.extend(string("Account"));
+        out.extend_from_slice(&[1, 1, compact(1), 2, compact(0), compact(1)]);
+        out.extend_from_slice(&[compact(0), compact(0)]);
+        out.extend_from_slice(&[1, compact(0), 0]);
+        out.push(compact(1));
+        let weight = [compact(50), compact(0)];
+        let mut block_weights = weight.repeat(2);
+        for _ in 0..3 {
+            block_weights.extend_from_slice(&weight);
+            block_weights.extend_from_slice(&[0, 0, 0]);
+        }
+        out.extend(constant("BlockWeights", &block_weights));
+        out.extend_from_slice(&[0, 0]);
+        if version == 15 {
+            out.push(compact(0));
+        }
+
+        out.extend(string("TransactionPayment"));
+        out.extend_from_slice(&[0, 0, 0]);
+        out.push(compact(3));
+        let mut weight_to_fee = vec![compact(1)];
+        weight_to_fee.extend(coefficient(2, 0, false, 1));
+        out.extend(constant("WeightToFee", &weight_to_fee));
+        let mut length_to_fee = vec![compact(1)];
+        length_to_fee.extend(coefficient(1000, 0, false, 1));
+        out.extend(constant("LengthToFee", &length_to_fee));
+        out.extend(constant("OperationalFeeMultiplier", &[5]));
+        out.extend_from_slice(&[0, 1]);
+        if version == 15 {
+            out.push(compact(1));
+            out.extend(string("doc"));
+        }
+
+        out.extend(string("Balances"));
+        out.extend_from_slice(&[0, 0, 0]);
+        out.push(compact(1));
+        out.extend(constant("ExistentialDeposit", &500u128.to_le_bytes()));
+        out.extend_from_slice(&[0, 2]);
+        if version == 15 {
+            out.push(compact(0));
+        }
+
+        // Rest of the metadata, which isn't decoded.
+        out.extend_from_slice(&[0xff; 4]);
+        out
+    }
+
+    #[test]
+    fn fee_constants_v14_and_v15() {
+        for version in [14, 15] {
+            let constants = super::fee_constants(&metadata(version)).unwrap();
+            assert_eq!(
+                constants,
+                FeeConstants {
+                    weight_to_fee: Some(vec![WeightToFeeCoefficient {
+                        coeff_integer: 2,
+                        coeff_frac: 0,
+                        negative: false,
+                        degree: 1,
+                    }]),
+                    length_to_fee: Some(vec![WeightToFeeCoefficient {
+                        coeff_integer: 1000,
+                        coeff_frac: 0,
+                        negative: false,
+                        degree: 1,
+                    }]),
+                    transaction_byte_fee: None,
+                    operational_fee_multiplier: Some(5),
+                    existential_deposit: Some(500),
+                    base_extrinsic_weight: Some(50),
+                }
+            );
+            assert_eq!(constants.estimate_fee(1000, 10), Some(12100));
+        }
+    }
+
+    #[test]
+    fn pallet_constant() {
+        assert_eq!(
+            super::pallet_constant(
+                &metadata(14),
+                "TransactionPayment",
+                "OperationalFeeMultiplier"
+            )
+            .unwrap(),
+            Some(&[5][..])
+        );
+        assert_eq!(
+            super::pallet_constant(&metadata(14), "Balances", "Foo").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(
+            super::decode_constants(&metadata(13)),
+            Err(super::DecodeError::UnsupportedVersion(13))
+        ));
+    }
+
+    #[test]
+    fn polynomial_evaluation() {
+        let polynomial = [
+            WeightToFeeCoefficient {
+                coeff_integer: 0,
+                coeff_frac: 500_000_000,
+                negative: false,
+                degree: 1,
+            },
+            WeightToFeeCoefficient {
+                coeff_integer: 1,
+                coeff_frac: 0,
+                negative: false,
+                degree: 2,
+            },
+            WeightToFeeCoefficient {
+                coeff_integer: 10,
+                coeff_frac: 0,
+                negative: true,
+                degree: 0,
+            },
+        ];
+
+        // 0.5 * 1001 = 500.5, rounded down to 500.
+        assert_eq!(
+            super::evaluate_fee_polynomial(&polynomial, 1001),
+            500 + 1001 * 1001 - 10
+        );
+        // 0.5 * 1003 = 501.5, rounded down to 501.
+        assert_eq!(
+            super::evaluate_fee_polynomial(&polynomial, 1003),
+            501 + 1003 * 1003 - 10
+        );
+        // Subtraction saturates at 0.
+        assert_eq!(super::evaluate_fee_polynomial(&polynomial, 0), 0);
+    }
+}
@@ -27,6 +27,8 @@ use core::{fmt, iter};
 
 mod block_announces;
 mod block_request;
+mod checkpoint;
+mod compression;
 mod grandpa;
 mod grandpa_warp_sync;
 mod identify;
@@ -36,6 +38,8 @@ mod storage_call_proof;
 
 pub use self::block_announces::*;
 pub use self::block_request::*;
+pub use self::checkpoint::*;
+pub use self::compression::*;
 pub use self::grandpa::*;
 pub use self::grandpa_warp_sync::*;
 pub use self::identify::*;
@@ -64,6 +68,15 @@ pub enum ProtocolName<'a> {
         genesis_hash: [u8; 32],
         fork_id: Option<&'a str>,
     },
+    /// Same as [`ProtocolName::Sync`], except that the responses can be compressed. See
+    /// [`decompress_response_if_necessary`].
+    ///
+    /// This protocol isn't supported by Substrate-based nodes. Requests should only be sent on
+    /// it to peers that advertise it in their response to an identify request.
+    SyncCompressed {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
     Light {
         genesis_hash: [u8; 32],
         fork_id: Option<&'a str>,
@@ -80,6 +93,19 @@ pub enum ProtocolName<'a> {
         genesis_hash: [u8; 32],
         fork_id: Option<&'a str>,
     },
+    /// Same as [`ProtocolName::State`], except that the responses can be compressed. See
+    /// [`decompress_response_if_necessary`].
+    ///
+    /// This protocol isn't supported by Substrate-based nodes. Requests should only be sent on
+    /// it to peers that advertise it in their response to an identify request.
+    StateCompressed {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Checkpoint {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
 }
 
 impl<'a> fmt::Debug for ProtocolName<'a> {
@@ -121,6 +147,10 @@ pub fn encode_protocol_name(
             genesis_hash,
             fork_id,
         } => (genesis_hash, fork_id, "sync/2"),
+        ProtocolName::SyncCompressed {
+            genesis_hash,
+            fork_id,
+        } => (genesis_hash, fork_id, "sync/2/zstd"),
         ProtocolName::Light {
             genesis_hash,
             fork_id,
@@ -137,6 +167,14 @@ pub fn encode_protocol_name(
             genesis_hash,
             fork_id,
         } => (genesis_hash, fork_id, "state/2"),
+        ProtocolName::StateCompressed {
+            genesis_hash,
+            fork_id,
+        } => (genesis_hash, fork_id, "state/2/zstd"),
+        ProtocolName::Checkpoint {
+            genesis_hash,
+            fork_id,
+        } => (genesis_hash, fork_id, "checkpoint/1"),
     };
 
     let genesis_hash = hex::encode(genesis_hash);
@@ -174,6 +212,101 @@ pub fn encode_protocol_name_string(protocol: ProtocolName<'_>) -> String {
     })
 }
 
+/// Name of the GrandPa protocol using the legacy naming scheme. Contrary to the other legacy
+/// protocol names (see [`LegacyProtocolName`]), it isn't specific to any chain.
+pub const LEGACY_GRANDPA_PROTOCOL_NAME: &str = "/paritytech/grandpa/1";
+
+/// Name of a protocol that is part of the Substrate/Polkadot networking, using the legacy naming
+/// scheme.
+///
+/// Before protocol names were derived from the genesis hash and fork id of the chain (see
+/// [`ProtocolName`]), they were derived from the `protocolId` field found in the specification
+/// of the chain. Older Substrate nodes only support these legacy names.
+///
+/// > **Note**: The legacy name of the GrandPa protocol, [`LEGACY_GRANDPA_PROTOCOL_NAME`],
+/// >           doesn't contain any chain-specific information and is therefore not part of this
+/// >           enum.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LegacyProtocolName<'a> {
+    BlockAnnounces { protocol_id: &'a str },
+    Transactions { protocol_id: &'a str },
+    Sync { protocol_id: &'a str },
+    Light { protocol_id: &'a str },
+    Kad { protocol_id: &'a str },
+    SyncWarp { protocol_id: &'a str },
+    State { protocol_id: &'a str },
+}
+
+impl<'a> fmt::Display for LegacyProtocolName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (protocol_id, base_protocol_name) = legacy_protocol_name_components(*self);
+        write!(f, "/{protocol_id}/{base_protocol_name}")
+    }
+}
+
+/// Turns a [`LegacyProtocolName`] into a string.
+pub fn encode_legacy_protocol_name_string(protocol: LegacyProtocolName<'_>) -> String {
+    let (protocol_id, base_protocol_name) = legacy_protocol_name_components(protocol);
+    let mut out = String::with_capacity(protocol_id.len() + base_protocol_name.len() + 2);
+    out.push('/');
+    out.push_str(protocol_id);
+    out.push('/');
+    out.push_str(base_protocol_name);
+    out
+}
+
+fn legacy_protocol_name_components(protocol: LegacyProtocolName<'_>) -> (&str, &'static str) {
+    match protocol {
+        LegacyProtocolName::BlockAnnounces { protocol_id } => (protocol_id, "block-announces/1"),
+        LegacyProtocolName::Transactions { protocol_id } => (protocol_id, "transactions/1"),
+        LegacyProtocolName::Sync { protocol_id } => (protocol_id, "sync/2"),
+        LegacyProtocolName::Light { protocol_id } => (protocol_id, "light/2"),
+        LegacyProtocolName::Kad { protocol_id } => (protocol_id, "kad"),
+        LegacyProtocolName::SyncWarp { protocol_id } => (protocol_id, "sync/warp"),
+        LegacyProtocolName::State { protocol_id } => (protocol_id, "state/2"),
+    }
+}
+
+/// Decodes a protocol name that uses the legacy naming scheme into its components.
+///
+/// Returns an error if the protocol name isn't recognized.
+///
+/// > **Note**: A protocol name that uses the genesis hash naming scheme and doesn't contain any
+/// >           fork id can also successfully be decoded by this function, with the hexadecimal
+/// >           genesis hash as protocol id. [`decode_protocol_name`] should be tried first.
+pub fn decode_legacy_protocol_name(
+    name: &str,
+) -> Result<LegacyProtocolName<'_>, UnrecognizedLegacyProtocolNameError> {
+    nom::combinator::all_consuming(nom::combinator::map_opt(
+        nom::sequence::tuple((
+            nom::bytes::complete::tag("/"),
+            nom::bytes::complete::take_till1(|c| c == '/'),
+            nom::bytes::complete::tag("/"),
+            protocol_ty,
+        )),
+        |(_, protocol_id, _, protocol_ty)| match protocol_ty {
+            ProtocolTy::BlockAnnounces => Some(LegacyProtocolName::BlockAnnounces { protocol_id }),
+            ProtocolTy::Transactions => Some(LegacyProtocolName::Transactions { protocol_id }),
+            ProtocolTy::Sync => Some(LegacyProtocolName::Sync { protocol_id }),
+            ProtocolTy::Light => Some(LegacyProtocolName::Light { protocol_id }),
+            ProtocolTy::Kad => Some(LegacyProtocolName::Kad { protocol_id }),
+            ProtocolTy::SyncWarp => Some(LegacyProtocolName::SyncWarp { protocol_id }),
+            ProtocolTy::State => Some(LegacyProtocolName::State { protocol_id }),
+            ProtocolTy::Grandpa
+            | ProtocolTy::SyncCompressed
+            | ProtocolTy::StateCompressed
+            | ProtocolTy::Checkpoint => None,
+        },
+    ))(name)
+    .map(|(_, parse_result)| parse_result)
+    .map_err(|_| UnrecognizedLegacyProtocolNameError)
+}
+
+/// Error potentially returned by [`decode_legacy_protocol_name`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+#[display(fmt = "Unrecognized legacy protocol name")]
+pub struct UnrecognizedLegacyProtocolNameError;
+
 /// Decodes a protocol name into its components.
 ///
 /// Returns an error if the protocol name isn't recognized.
@@ -227,10 +360,13 @@ enum ProtocolTy {
     Transactions,
     Grandpa,
     Sync,
+    SyncCompressed,
     Light,
     Kad,
     SyncWarp,
     State,
+    StateCompressed,
+    Checkpoint,
 }
 
 fn protocol_ty(name: &str) -> nom::IResult<&str, ProtocolTy> {
@@ -244,13 +380,24 @@ fn protocol_ty(name: &str) -> nom::IResult<&str, ProtocolTy> {
         nom::combinator::map(nom::bytes::complete::tag("grandpa/1"), |_| {
             ProtocolTy::Grandpa
         }),
+        // Note that the compressed variants must be tried before their non-compressed
+        // counterparts, as the latter are prefixes of the former.
+        nom::combinator::map(nom::bytes::complete::tag("sync/2/zstd"), |_| {
+            ProtocolTy::SyncCompressed
+        }),
         nom::combinator::map(nom::bytes::complete::tag("sync/2"), |_| ProtocolTy::Sync),
         nom::combinator::map(nom::bytes::complete::tag("light/2"), |_| ProtocolTy::Light),
         nom::combinator::map(nom::bytes::complete::tag("kad"), |_| ProtocolTy::Kad),
         nom::combinator::map(nom::bytes::complete::tag("sync/warp"), |_| {
             ProtocolTy::SyncWarp
         }),
+        nom::combinator::map(nom::bytes::complete::tag("state/2/zstd"), |_| {
+            ProtocolTy::StateCompressed
+        }),
         nom::combinator::map(nom::bytes::complete::tag("state/2"), |_| ProtocolTy::State),
+        nom::combinator::map(nom::bytes::complete::tag("checkpoint/1"), |_| {
+            ProtocolTy::Checkpoint
+        }),
     ))(name)
 }
 
@@ -276,6 +423,10 @@ fn protocol_ty_to_real_protocol(
             genesis_hash,
             fork_id,
         },
+        ProtocolTy::SyncCompressed => ProtocolName::SyncCompressed {
+            genesis_hash,
+            fork_id,
+        },
         ProtocolTy::Light => ProtocolName::Light {
             genesis_hash,
             fork_id,
@@ -292,7 +443,98 @@ fn protocol_ty_to_real_protocol(
             genesis_hash,
             fork_id,
         },
+        ProtocolTy::StateCompressed => ProtocolName::StateCompressed {
+            genesis_hash,
+            fork_id,
+        },
+        ProtocolTy::Checkpoint => ProtocolName::Checkpoint {
+            genesis_hash,
+            fork_id,
+        },
     }
 }
 
-// TODO: tests for the protocol names
+// TODO: more tests for the protocol names
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_legacy_protocol_name, decode_protocol_name, encode_legacy_protocol_name_string,
+        LegacyProtocolName, ProtocolName,
+    };
+
+    #[test]
+    fn legacy_protocol_name_round_trip() {
+        for protocol in [
+            LegacyProtocolName::BlockAnnounces { protocol_id: "dot" },
+            LegacyProtocolName::Transactions { protocol_id: "dot" },
+            LegacyProtocolName::Sync { protocol_id: "dot" },
+            LegacyProtocolName::Light { protocol_id: "dot" },
+            LegacyProtocolName::Kad { protocol_id: "dot" },
+            LegacyProtocolName::SyncWarp { protocol_id: "dot" },
+            LegacyProtocolName::State { protocol_id: "dot" },
+        ] {
+            let encoded = encode_legacy_protocol_name_string(protocol);
+            assert_eq!(encoded, protocol.to_string());
+            assert_eq!(decode_legacy_protocol_name(&encoded), Ok(protocol));
+        }
+
+        assert_eq!(
+            encode_legacy_protocol_name_string(LegacyProtocolName::Sync {
+                protocol_id: "ksmcc3"
+            }),
+            "/ksmcc3/sync/2"
+        );
+    }
+
+    #[test]
+    fn legacy_protocol_name_invalid() {
+        assert!(decode_legacy_protocol_name("/paritytech/grandpa/1").is_err());
+        assert!(decode_legacy_protocol_name("//sync/2").is_err());
+        assert!(decode_legacy_protocol_name("/dot/foo/sync/2").is_err());
+        assert!(decode_legacy_protocol_name("/dot/sync/3").is_err());
+        assert!(decode_legacy_protocol_name("/ipfs/id/1.0.0").is_err());
+    }
+
+    #[test]
+    fn modern_name_not_legacy() {
+        let name = ProtocolName::Sync {
+            genesis_hash: [0xab; 32],
+            fork_id: None,
+        }
+        .to_string();
+        assert_eq!(
+            decode_protocol_name(&name),
+            Ok(ProtocolName::Sync {
+                genesis_hash: [0xab; 32],
+                fork_id: None,
+            })
+        );
+    }
+
+    #[test]
+    fn compressed_protocol_names_round_trip() {
+        for fork_id in [None, Some("foo")] {
+            for protocol in [
+                ProtocolName::Sync {
+                    genesis_hash: [0xab; 32],
+                    fork_id,
+                },
+                ProtocolName::SyncCompressed {
+                    genesis_hash: [0xab; 32],
+                    fork_id,
+                },
+                ProtocolName::State {
+                    genesis_hash: [0xab; 32],
+                    fork_id,
+                },
+                ProtocolName::StateCompressed {
+                    genesis_hash: [0xab; 32],
+                    fork_id,
+                },
+            ] {
+                assert_eq!(decode_protocol_name(&protocol.to_string()), Ok(protocol));
+            }
+        }
+    }
+}
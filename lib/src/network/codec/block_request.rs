@@ -393,6 +393,8 @@ pub enum DecodeBlockResponseError {
     BodyDecodeError,
     /// List of justifications isn't in a correct format.
     InvalidJustifications,
+    /// Error while decompressing the response.
+    Decompression(super::DecompressResponseError),
 }
 
 fn decode_justifications<'a, E: nom::error::ParseError<&'a [u8]>>(
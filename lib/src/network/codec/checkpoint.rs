@@ -0,0 +1,71 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The checkpoint protocol is a request-response protocol.
+//!
+//! The request's body is empty.
+//!
+//! The response's body consists in the information about the latest finalized block known to the
+//! responder (its header, and the consensus-related information necessary to verify its
+//! descendants), serialized in the same format as
+//! [`finalized_serialize`](crate::database::finalized_serialize).
+//!
+//! By doing a checkpoint request, a node is capable of obtaining a recent trust anchor to start
+//! syncing from, without having to go through a warp sync starting at the genesis block.
+//!
+//! > **Note**: The response isn't verifiable. A malicious responder can send back the
+//! >           information about a block that isn't part of the canonical chain. A checkpoint
+//! >           should only be trusted if several unrelated peers agree on it.
+
+use crate::{chain::chain_information, database::finalized_serialize};
+
+use alloc::vec::Vec;
+
+/// Builds the bytes corresponding to a checkpoint response.
+pub fn build_checkpoint_response<'a>(
+    information: impl Into<chain_information::ValidChainInformationRef<'a>>,
+    block_number_bytes: usize,
+) -> Vec<u8> {
+    finalized_serialize::encode_chain(information, block_number_bytes).into_bytes()
+}
+
+/// Decodes a checkpoint response.
+pub fn decode_checkpoint_response(
+    response_bytes: &[u8],
+    block_number_bytes: usize,
+) -> Result<chain_information::ValidChainInformation, DecodeCheckpointResponseError> {
+    let response = core::str::from_utf8(response_bytes)
+        .map_err(|_| DecodeCheckpointResponseError::InvalidUtf8)?;
+    let decoded = finalized_serialize::decode_chain(response, block_number_bytes)
+        .map_err(DecodeCheckpointResponseError::Invalid)?;
+    if decoded.storage.is_some() {
+        return Err(DecodeCheckpointResponseError::UnexpectedStorage);
+    }
+    Ok(decoded.chain_information)
+}
+
+/// Error potentially returned by [`decode_checkpoint_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeCheckpointResponseError {
+    /// Response isn't valid UTF-8.
+    InvalidUtf8,
+    /// Response doesn't contain a valid chain information.
+    #[display(fmt = "{_0}")]
+    Invalid(finalized_serialize::CorruptedError),
+    /// Response contains the storage of the finalized block, which isn't expected.
+    UnexpectedStorage,
+}
@@ -0,0 +1,173 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compression of the responses of the block and state request protocols.
+//!
+//! # Overview
+//!
+//! The block request and state request protocols each have a variant, see
+//! [`ProtocolName::SyncCompressed`] and [`ProtocolName::StateCompressed`], whose requests are
+//! identical to the ones of the normal protocol but whose responses can be compressed. When a
+//! request is received on one of these variants, the remote is free to send back a response
+//! consisting of a zstandard frame containing the normal response.
+//!
+//! The decoding side can know whether a response is compressed by looking at its first bytes.
+//! Normal responses always start with a Protobuf tag, which never matches the zstandard magic
+//! number.
+//!
+//! This module only implements the decoding side. Compressing responses is optional, and left to
+//! the API user through [`Config::compress_response`].
+//!
+//! [`Config::compress_response`]: crate::network::service::Config::compress_response
+//!
+//! [`ProtocolName::SyncCompressed`]: super::ProtocolName::SyncCompressed
+//! [`ProtocolName::StateCompressed`]: super::ProtocolName::StateCompressed
+
+use alloc::borrow::Cow;
+
+/// Magic number found at the beginning of every zstandard frame.
+const ZSTD_MAGIC_NUMBER: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// If the given response to a block or state request is compressed, decompresses it. Otherwise,
+/// passes it through.
+///
+/// The decompressed data shall not be larger than `max_size`, in order to avoid zip bombs.
+pub fn decompress_response_if_necessary(
+    mut response: &[u8],
+    max_size: usize,
+) -> Result<Cow<'_, [u8]>, DecompressResponseError> {
+    if !response.starts_with(&ZSTD_MAGIC_NUMBER) {
+        return Ok(Cow::Borrowed(response));
+    }
+
+    let mut decoder = ruzstd::frame_decoder::FrameDecoder::new();
+    decoder
+        .init(&mut response)
+        .map_err(|_| DecompressResponseError::InvalidZstd)?;
+
+    match decoder.decode_blocks(
+        &mut response,
+        ruzstd::frame_decoder::BlockDecodingStrategy::UptoBytes(max_size),
+    ) {
+        Ok(true) => {}
+        Ok(false) => return Err(DecompressResponseError::TooLarge),
+        Err(_) => return Err(DecompressResponseError::InvalidZstd),
+    }
+
+    // When the decoding is finished, `Some` is always guaranteed to be returned.
+    // The last block might have brought the total size above `max_size`, as the decoder only
+    // checks the size in between blocks.
+    let out = decoder.collect().unwrap();
+    if out.len() > max_size {
+        return Err(DecompressResponseError::TooLarge);
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// Error potentially returned by [`decompress_response_if_necessary`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecompressResponseError {
+    /// The response is zstandard-compressed, but the data is in an invalid format.
+    InvalidZstd,
+    /// The decompressed response exceeds the maximum allowed size.
+    TooLarge,
+}
+
+#[cfg(test)]
+mod tests {
+    /// Builds a zstandard frame containing the given blocks. Each block is either raw, if its
+    /// second element is `None`, or an RLE block repeating the byte its second element
+    /// contains.
+    fn zstd_frame(blocks: &[(&[u8], Option<usize>)]) -> Vec<u8> {
+        let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd];
+        // Frame header descriptor: no frame content size, single segment, no checksum and no
+        // dictionary, followed with the window descriptor.
+        frame.push(0b0000_0000);
+        frame.push(0b0101_0000);
+
+        for (index, (content, rle_length)) in blocks.iter().enumerate() {
+            let last_block = u32::from(index == blocks.len() - 1);
+            let header = match rle_length {
+                None => (u32::try_from(content.len()).unwrap() << 3) | last_block,
+                Some(length) => (u32::try_from(*length).unwrap() << 3) | (1 << 1) | last_block,
+            };
+            frame.extend_from_slice(&header.to_le_bytes()[..3]);
+            frame.extend_from_slice(content);
+        }
+
+        frame
+    }
+
+    #[test]
+    fn decompress() {
+        let compressed = zstd_frame(&[(&[10, 200, 1], None), (&[0xab], Some(200))]);
+        let expected = [10u8, 200, 1]
+            .into_iter()
+            .chain(core::iter::repeat(0xab).take(200))
+            .collect::<Vec<_>>();
+
+        let decompressed =
+            super::decompress_response_if_necessary(&compressed, expected.len()).unwrap();
+        assert_eq!(&*decompressed, &expected[..]);
+    }
+
+    #[test]
+    fn decompress_reference_encoder() {
+        let response = (0..300_000u32)
+            .map(|n| {
+                if (n / 1000) % 2 == 0 {
+                    0
+                } else {
+                    (n % 251) as u8
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let compressed = zstd::bulk::compress(&response, 1).unwrap();
+        assert!(compressed.len() < response.len());
+
+        let decompressed =
+            super::decompress_response_if_necessary(&compressed, response.len()).unwrap();
+        assert_eq!(&*decompressed, &response[..]);
+    }
+
+    #[test]
+    fn uncompressed_passthrough() {
+        let response = [10u8, 3, 1, 2, 3];
+        let decompressed = super::decompress_response_if_necessary(&response, 0).unwrap();
+        assert_eq!(&*decompressed, &response[..]);
+    }
+
+    #[test]
+    fn invalid_zstd() {
+        let mut compressed = zstd_frame(&[(&[0xab], Some(200))]);
+        compressed.truncate(compressed.len() - 2);
+        assert!(matches!(
+            super::decompress_response_if_necessary(&compressed, 1000),
+            Err(super::DecompressResponseError::InvalidZstd)
+        ));
+    }
+
+    #[test]
+    fn decompressed_too_large() {
+        let compressed = zstd_frame(&[(&[0xab], Some(1024))]);
+        assert!(matches!(
+            super::decompress_response_if_necessary(&compressed, 1000),
+            Err(super::DecompressResponseError::TooLarge)
+        ));
+    }
+}
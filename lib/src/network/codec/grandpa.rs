@@ -42,9 +42,34 @@ impl<'a> GrandpaNotificationRef<'a> {
         block_number_bytes: usize,
     ) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
         match self {
-            GrandpaNotificationRef::Neighbor(n) => iter::once(either::Left(&[2u8]))
-                .chain(n.scale_encoding(block_number_bytes).map(either::Right)),
-            _ => todo!(),
+            GrandpaNotificationRef::Vote(v) => either::Left(iter::once(either::Right(
+                iter::once(0u8)
+                    .chain(v.scale_encoding_vec(block_number_bytes))
+                    .collect::<Vec<_>>(),
+            ))),
+            GrandpaNotificationRef::Commit(c) => either::Left(iter::once(either::Right(
+                iter::once(1u8)
+                    .chain(commit_message_scale_encoding_vec(c, block_number_bytes))
+                    .collect::<Vec<_>>(),
+            ))),
+            GrandpaNotificationRef::Neighbor(n) => either::Right(
+                iter::once(either::Left(either::Left(&[2u8]))).chain(
+                    n.scale_encoding(block_number_bytes)
+                        .map(|b| either::Left(either::Right(b))),
+                ),
+            ),
+            GrandpaNotificationRef::CatchUpRequest(r) => {
+                let mut out = Vec::with_capacity(1 + 8 + 8);
+                out.push(3u8);
+                out.extend_from_slice(&r.round_number.to_le_bytes());
+                out.extend_from_slice(&r.set_id.to_le_bytes());
+                either::Left(iter::once(either::Right(out)))
+            }
+            GrandpaNotificationRef::CatchUp(c) => either::Left(iter::once(either::Right(
+                iter::once(4u8)
+                    .chain(catch_up_scale_encoding_vec(c, block_number_bytes))
+                    .collect::<Vec<_>>(),
+            ))),
         }
     }
 }
@@ -58,6 +83,19 @@ pub struct VoteMessageRef<'a> {
     pub authority_public_key: &'a [u8; 32],
 }
 
+impl<'a> VoteMessageRef<'a> {
+    /// Returns the SCALE encoding of that object.
+    pub fn scale_encoding_vec(&self, block_number_bytes: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + 8 + 1 + 32 + block_number_bytes + 64 + 32);
+        out.extend_from_slice(&self.round_number.to_le_bytes());
+        out.extend_from_slice(&self.set_id.to_le_bytes());
+        out.extend(self.message.scale_encoding_vec(block_number_bytes));
+        out.extend_from_slice(self.signature);
+        out.extend_from_slice(self.authority_public_key);
+        out
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MessageRef<'a> {
     Prevote(UnsignedPrevoteRef<'a>),
@@ -65,6 +103,37 @@ pub enum MessageRef<'a> {
     PrimaryPropose(PrimaryProposeRef<'a>),
 }
 
+impl<'a> MessageRef<'a> {
+    /// Returns the SCALE encoding of that object.
+    pub fn scale_encoding_vec(&self, block_number_bytes: usize) -> Vec<u8> {
+        let (prefix, target_hash, target_number) = match self {
+            MessageRef::Prevote(v) => (0u8, v.target_hash, v.target_number),
+            MessageRef::Precommit(v) => (1u8, v.target_hash, v.target_number),
+            MessageRef::PrimaryPropose(v) => (2u8, v.target_hash, v.target_number),
+        };
+
+        let mut out = Vec::with_capacity(1 + 32 + block_number_bytes);
+        out.push(prefix);
+        out.extend_from_slice(target_hash);
+        out.extend(encode_block_number(target_number, block_number_bytes));
+        out
+    }
+
+    /// Returns the payload that the authority signs in order to produce
+    /// [`VoteMessageRef::signature`].
+    pub fn signature_payload(
+        &self,
+        round_number: u64,
+        set_id: u64,
+        block_number_bytes: usize,
+    ) -> Vec<u8> {
+        let mut out = self.scale_encoding_vec(block_number_bytes);
+        out.extend_from_slice(&round_number.to_le_bytes());
+        out.extend_from_slice(&set_id.to_le_bytes());
+        out
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnsignedPrevoteRef<'a> {
     pub target_hash: &'a [u8; 32],
@@ -91,17 +160,8 @@ impl NeighborPacket {
         &self,
         block_number_bytes: usize,
     ) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
-        let mut commit_finalized_height = Vec::with_capacity(cmp::max(
-            block_number_bytes,
-            mem::size_of_val(&self.commit_finalized_height),
-        ));
-        commit_finalized_height.extend(self.commit_finalized_height.to_le_bytes());
-        // TODO: unclear what to do if the block number doesn't fit in `block_number_bytes`
-        debug_assert!(!commit_finalized_height
-            .iter()
-            .skip(block_number_bytes)
-            .any(|b| *b != 0));
-        commit_finalized_height.resize(block_number_bytes, 0);
+        let commit_finalized_height =
+            encode_block_number(self.commit_finalized_height, block_number_bytes);
 
         [
             either::Right(either::Left([1u8])),
@@ -144,6 +204,87 @@ pub struct PrevoteRef<'a> {
     pub authority_public_key: &'a [u8; 32],
 }
 
+/// Returns the SCALE encoding of the given commit message, without the notification prefix.
+pub fn commit_message_scale_encoding_vec(
+    commit: &CommitMessageRef,
+    block_number_bytes: usize,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&commit.round_number.to_le_bytes());
+    out.extend_from_slice(&commit.set_id.to_le_bytes());
+    out.extend_from_slice(commit.target_hash);
+    out.extend(encode_block_number(
+        commit.target_number,
+        block_number_bytes,
+    ));
+    out.extend_from_slice(
+        crate::util::encode_scale_compact_usize(commit.precommits.len()).as_ref(),
+    );
+    for precommit in &commit.precommits {
+        out.extend_from_slice(precommit.target_hash);
+        out.extend(encode_block_number(
+            precommit.target_number,
+            block_number_bytes,
+        ));
+    }
+    out.extend_from_slice(crate::util::encode_scale_compact_usize(commit.auth_data.len()).as_ref());
+    for (signature, public_key) in &commit.auth_data {
+        out.extend_from_slice(&signature[..]);
+        out.extend_from_slice(&public_key[..]);
+    }
+    out
+}
+
+/// Returns the SCALE encoding of the given catch up message, without the notification prefix.
+pub fn catch_up_scale_encoding_vec(catch_up: &CatchUpRef, block_number_bytes: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&catch_up.set_id.to_le_bytes());
+    out.extend_from_slice(&catch_up.round_number.to_le_bytes());
+    out.extend_from_slice(
+        crate::util::encode_scale_compact_usize(catch_up.prevotes.len()).as_ref(),
+    );
+    for prevote in &catch_up.prevotes {
+        out.extend_from_slice(prevote.target_hash);
+        out.extend(encode_block_number(
+            prevote.target_number,
+            block_number_bytes,
+        ));
+        out.extend_from_slice(prevote.signature);
+        out.extend_from_slice(prevote.authority_public_key);
+    }
+    out.extend_from_slice(
+        crate::util::encode_scale_compact_usize(catch_up.precommits.len()).as_ref(),
+    );
+    for precommit in &catch_up.precommits {
+        out.extend_from_slice(precommit.target_hash);
+        out.extend(encode_block_number(
+            precommit.target_number,
+            block_number_bytes,
+        ));
+        out.extend_from_slice(precommit.signature);
+        out.extend_from_slice(precommit.authority_public_key);
+    }
+    out.extend_from_slice(catch_up.base_hash);
+    out.extend(encode_block_number(
+        catch_up.base_number,
+        block_number_bytes,
+    ));
+    out
+}
+
+/// Encodes a block number as little endian on `block_number_bytes` bytes.
+fn encode_block_number(block_number: u64, block_number_bytes: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(cmp::max(
+        block_number_bytes,
+        mem::size_of_val(&block_number),
+    ));
+    out.extend(block_number.to_le_bytes());
+    // TODO: unclear what to do if the block number doesn't fit in `block_number_bytes`
+    debug_assert!(!out.iter().skip(block_number_bytes).any(|b| *b != 0));
+    out.resize(block_number_bytes, 0);
+    out
+}
+
 /// Attempt to decode the given SCALE-encoded Grandpa notification.
 pub fn decode_grandpa_notification(
     scale_encoded: &[u8],
@@ -443,4 +584,113 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn vote_encode_decode() {
+        let vote = super::GrandpaNotificationRef::Vote(super::VoteMessageRef {
+            round_number: 12,
+            set_id: 3,
+            message: super::MessageRef::Precommit(super::UnsignedPrecommitRef {
+                target_hash: &[5; 32],
+                target_number: 1234,
+            }),
+            signature: &[6; 64],
+            authority_public_key: &[7; 32],
+        });
+
+        let encoded = vote.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            vote
+        );
+    }
+
+    #[test]
+    fn commit_encode_decode() {
+        let commit = super::GrandpaNotificationRef::Commit(super::CommitMessageRef {
+            round_number: 12,
+            set_id: 3,
+            target_hash: &[5; 32],
+            target_number: 1234,
+            precommits: vec![
+                super::UnsignedPrecommitRef {
+                    target_hash: &[5; 32],
+                    target_number: 1234,
+                },
+                super::UnsignedPrecommitRef {
+                    target_hash: &[8; 32],
+                    target_number: 1235,
+                },
+            ],
+            auth_data: vec![(&[6; 64], &[7; 32]), (&[9; 64], &[10; 32])],
+        });
+
+        let encoded = commit.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            commit
+        );
+    }
+
+    #[test]
+    fn catch_up_request_encode_decode() {
+        let request = super::GrandpaNotificationRef::CatchUpRequest(super::CatchUpRequest {
+            round_number: 12,
+            set_id: 3,
+        });
+
+        let encoded = request.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn catch_up_encode_decode() {
+        let catch_up = super::GrandpaNotificationRef::CatchUp(super::CatchUpRef {
+            set_id: 3,
+            round_number: 12,
+            prevotes: vec![super::PrevoteRef {
+                target_hash: &[5; 32],
+                target_number: 1234,
+                signature: &[6; 64],
+                authority_public_key: &[7; 32],
+            }],
+            precommits: vec![
+                super::PrecommitRef {
+                    target_hash: &[5; 32],
+                    target_number: 1234,
+                    signature: &[6; 64],
+                    authority_public_key: &[7; 32],
+                },
+                super::PrecommitRef {
+                    target_hash: &[8; 32],
+                    target_number: 1235,
+                    signature: &[9; 64],
+                    authority_public_key: &[10; 32],
+                },
+            ],
+            base_hash: &[11; 32],
+            base_number: 1200,
+        });
+
+        let encoded = catch_up.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            catch_up
+        );
+    }
 }
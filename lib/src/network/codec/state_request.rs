@@ -107,6 +107,58 @@ pub fn build_state_request(
         .chain(protobuf::bool_tag_encode(3, false).map(either::Left))
 }
 
+/// Decodes a state request.
+///
+/// Requests that ask for a response without a proof are refused with
+/// [`DecodeStateRequestError::NoProofNotSupported`].
+pub fn decode_state_request(
+    request_bytes: &[u8],
+) -> Result<StateRequest<'_>, DecodeStateRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] block_hash = 1 => protobuf::bytes_tag_decode,
+            #[repeated(max = 2)] start = 2 => protobuf::bytes_tag_decode,
+            #[optional] no_proof = 3 => protobuf::bool_tag_decode,
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStateRequestError::ProtobufDecode),
+    };
+
+    if decoded.no_proof.unwrap_or(false) {
+        return Err(DecodeStateRequestError::NoProofNotSupported);
+    }
+
+    let block_hash = <&[u8; 32]>::try_from(decoded.block_hash)
+        .map_err(|_| DecodeStateRequestError::InvalidBlockHashLength)?;
+
+    let start_key = match &decoded.start[..] {
+        [] => StateRequestStart::MainTrie(&[]),
+        &[key] => StateRequestStart::MainTrie(key),
+        &[child_trie, key] => StateRequestStart::ChildTrieDefault {
+            child_trie: child_trie
+                .strip_prefix(b":child_storage:default:")
+                .ok_or(DecodeStateRequestError::InvalidChildTrie)?,
+            key,
+        },
+        _ => unreachable!(),
+    };
+
+    Ok(StateRequest {
+        block_hash,
+        start_key,
+    })
+}
+
+/// Builds the bytes corresponding to a response to a state request.
+///
+/// `proof` must be a compact Merkle proof. See the module-level documentation.
+pub fn build_state_response(proof: &[u8]) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
+    protobuf::bytes_tag_encode(2, proof)
+}
+
 /// Decodes a response to a state request.
 ///
 /// On success, contains a Merkle proof.
@@ -125,10 +177,25 @@ pub fn decode_state_response(response_bytes: &[u8]) -> Result<&[u8], DecodeState
     Ok(proof)
 }
 
+/// Error potentially returned by [`decode_state_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStateRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Block hash length isn't correct.
+    InvalidBlockHashLength,
+    /// Start key of the request points to a child trie whose type isn't supported.
+    InvalidChildTrie,
+    /// Request asks for a response without a proof, which isn't supported.
+    NoProofNotSupported,
+}
+
 /// Error potentially returned by [`decode_state_response`].
 #[derive(Debug, derive_more::Display, Clone)]
 #[display(fmt = "Failed to decode response")]
 pub enum DecodeStateResponseError {
     /// Error while decoding the Protobuf encoding.
     ProtobufDecode,
+    /// Error while decompressing the response.
+    Decompression(super::DecompressResponseError),
 }
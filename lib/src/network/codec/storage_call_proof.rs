@@ -92,6 +92,133 @@ pub fn build_call_proof_request<'a>(
     )
 }
 
+/// Storage proof request or call proof request received from a remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageOrCallProofRequest<'a> {
+    /// Request for a Merkle proof of the given storage keys.
+    StorageProof {
+        /// Hash of the block whose storage is requested.
+        block_hash: &'a [u8; 32],
+        /// Name of the default child trie the keys belong to, without its
+        /// `:child_storage:default:` prefix, or `None` if the keys belong to the main trie.
+        child_trie: Option<&'a [u8]>,
+        /// List of storage keys to prove.
+        keys: Vec<&'a [u8]>,
+    },
+    /// Request for a Merkle proof of the storage entries accessed by a runtime call.
+    CallProof {
+        /// Hash of the block whose storage the call should be made against.
+        block_hash: &'a [u8; 32],
+        /// Name of the runtime function to call.
+        method: &'a str,
+        /// Parameter passed to the runtime function.
+        parameter: &'a [u8],
+    },
+}
+
+impl<'a> StorageOrCallProofRequest<'a> {
+    /// Returns the kind of request.
+    pub fn ty(&self) -> StorageOrCallProof {
+        match self {
+            StorageOrCallProofRequest::StorageProof { .. } => StorageOrCallProof::StorageProof,
+            StorageOrCallProofRequest::CallProof { .. } => StorageOrCallProof::CallProof,
+        }
+    }
+}
+
+/// Decodes a storage proof request or a call proof request.
+pub fn decode_storage_or_call_proof_request(
+    request_bytes: &[u8],
+) -> Result<StorageOrCallProofRequest<'_>, DecodeStorageOrCallProofRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] remote_call_request = 1 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[required] method = 3 => protobuf::string_tag_decode,
+                #[optional] data = 4 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] remote_read_request = 2 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[repeated(max = 4096)] keys = 3 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] remote_read_child_request = 4 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[required] storage_key = 3 => protobuf::bytes_tag_decode,
+                #[repeated(max = 4096)] keys = 6 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStorageOrCallProofRequestError::ProtobufDecode),
+    };
+
+    match (
+        decoded.remote_call_request,
+        decoded.remote_read_request,
+        decoded.remote_read_child_request,
+    ) {
+        (Some(call), None, None) => Ok(StorageOrCallProofRequest::CallProof {
+            block_hash: <&[u8; 32]>::try_from(call.block)
+                .map_err(|_| DecodeStorageOrCallProofRequestError::InvalidBlockHashLength)?,
+            method: call.method,
+            parameter: call.data.unwrap_or(&[]),
+        }),
+        (None, Some(read), None) => Ok(StorageOrCallProofRequest::StorageProof {
+            block_hash: <&[u8; 32]>::try_from(read.block)
+                .map_err(|_| DecodeStorageOrCallProofRequestError::InvalidBlockHashLength)?,
+            child_trie: None,
+            keys: read.keys,
+        }),
+        (None, None, Some(read)) => Ok(StorageOrCallProofRequest::StorageProof {
+            block_hash: <&[u8; 32]>::try_from(read.block)
+                .map_err(|_| DecodeStorageOrCallProofRequestError::InvalidBlockHashLength)?,
+            child_trie: Some(
+                read.storage_key
+                    .strip_prefix(&b":child_storage:default:"[..])
+                    .ok_or(DecodeStorageOrCallProofRequestError::UnsupportedChildTrie)?,
+            ),
+            keys: read.keys,
+        }),
+        _ => Err(DecodeStorageOrCallProofRequestError::BadRequestTy),
+    }
+}
+
+/// Error potentially returned by [`decode_storage_or_call_proof_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStorageOrCallProofRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Request contains zero or more than one request.
+    BadRequestTy,
+    /// Block hash doesn't have the correct length.
+    InvalidBlockHashLength,
+    /// Requests concerning child tries only support default child tries.
+    UnsupportedChildTrie,
+}
+
+/// Builds the bytes corresponding to a response to a storage proof request or a call proof
+/// request.
+///
+/// `proof` must be a SCALE-encoded Merkle proof, or `None` if the request couldn't be answered.
+pub fn build_storage_or_call_proof_response(
+    ty: StorageOrCallProof,
+    proof: Option<&[u8]>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
+    let field_num = match ty {
+        StorageOrCallProof::CallProof => 1,
+        StorageOrCallProof::StorageProof => 2,
+    };
+
+    protobuf::message_tag_encode(
+        field_num,
+        proof
+            .into_iter()
+            .flat_map(|proof| protobuf::bytes_tag_encode(2, proof)),
+    )
+}
+
 /// Decodes a response to a storage proof request or a call proof request.
 ///
 /// On success, returns a SCALE-encoded Merkle proof, or `None` if the remote couldn't answer
@@ -17,8 +17,25 @@
 
 // TODO: work in progress
 
+use crate::libp2p::PeerId;
+use sha2::{Digest as _, Sha256};
+
 pub mod kbuckets;
 
+/// Returns the Kademlia XOR distance between two peers, in other words the XOR of the SHA-256
+/// hashes of their identities.
+///
+/// Comparing two distances with `Ord` indicates which of the two peers is the closest.
+pub fn peers_distance(a: &PeerId, b: &PeerId) -> [u8; 32] {
+    let a: [u8; 32] = Sha256::digest(a.as_bytes()).into();
+    let b: [u8; 32] = Sha256::digest(b.as_bytes()).into();
+    let mut out = [0; 32];
+    for ((out, a), b) in out.iter_mut().zip(a.iter()).zip(b.iter()) {
+        *out = a ^ b;
+    }
+    out
+}
+
 /// Data structure containing the k-buckets and the state of the current Kademlia queries.
 // TODO: unused
 pub struct Kademlia {}
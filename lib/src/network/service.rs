@@ -76,13 +76,20 @@
 
 // TODO: expand explanations once the API is finalized
 
-use crate::libp2p::collection;
+use crate::chain::chain_information;
+use crate::libp2p::{collection, connection::established};
 use crate::network::codec;
 use crate::util::{self, SipHasherBuild};
 
-use alloc::{borrow::ToOwned as _, collections::BTreeSet, string::String, vec::Vec};
+use alloc::{
+    borrow::{Cow, ToOwned as _},
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    string::String,
+    vec::{self, Vec},
+};
 use core::{
-    fmt,
+    cmp, fmt,
     hash::Hash,
     iter,
     ops::{self, Add, Sub},
@@ -92,9 +99,10 @@ use rand_chacha::rand_core::{RngCore as _, SeedableRng as _};
 
 pub use crate::libp2p::{
     collection::{
-        ConnectionId, ConnectionToCoordinator, CoordinatorToConnection, InboundError,
-        MultiStreamConnectionTask, MultiStreamHandshakeKind, NotificationsOutErr, ReadWrite,
-        RequestError, SingleStreamConnectionTask, SingleStreamHandshakeKind, SubstreamId,
+        ConnectionId, ConnectionToCoordinator, CoordinatorToConnection, InboundError, MessageKind,
+        MessageSizeViolation, MultiStreamConnectionTask, MultiStreamHandshakeKind,
+        NotificationsOutErr, ReadWrite, RequestError, SingleStreamConnectionTask,
+        SingleStreamHandshakeKind, SubstreamId,
     },
     connection::noise::{self, NoiseKey},
     multiaddr::{self, Multiaddr},
@@ -103,6 +111,14 @@ pub use crate::libp2p::{
 
 pub use crate::network::codec::{BlockAnnouncesHandshakeDecodeError, Role};
 
+/// Maximum size, in bytes, of a request on the light client protocol. Remotes refuse requests
+/// larger than this.
+const LIGHT_PROTOCOL_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// Maximum size, in bytes, of a response to a block or state request after it has been
+/// decompressed.
+const MAX_DECOMPRESSED_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
 /// Configuration for a [`ChainNetwork`].
 pub struct Config {
     /// Capacity to initially reserve to the list of connections.
@@ -122,6 +138,13 @@ pub struct Config {
     /// Amount of time after which a connection hathat ndshake is considered to have taken too long
     /// and must be aborted.
     pub handshake_timeout: Duration,
+
+    /// Function used to compress the responses to the block and state requests received on
+    /// [`codec::ProtocolName::SyncCompressed`] and [`codec::ProtocolName::StateCompressed`].
+    /// Must return a zstandard frame, or the original response if compressing it isn't worth it.
+    ///
+    /// If `None`, responses are never compressed, which is always allowed by the protocol.
+    pub compress_response: Option<fn(Vec<u8>) -> Vec<u8>>,
 }
 
 /// Configuration for a specific overlay network.
@@ -142,6 +165,23 @@ pub struct ChainConfig<TChain> {
     /// >           "chain spec").
     pub fork_id: Option<String>,
 
+    /// If `Some`, the protocol names of the legacy naming scheme, derived from this protocol
+    /// identifier, are accepted and advertised in addition to the protocol names derived from
+    /// [`ChainConfig::genesis_hash`] and [`ChainConfig::fork_id`]. This makes it possible for
+    /// older Substrate nodes, that only support the legacy naming scheme, to open substreams
+    /// towards the local node.
+    ///
+    /// Substreams opened by the local node first try the protocol names derived from the
+    /// genesis hash, then the legacy protocol names if the remote doesn't support the former.
+    ///
+    /// The legacy name of the GrandPa protocol, [`codec::LEGACY_GRANDPA_PROTOCOL_NAME`], isn't
+    /// specific to any chain, and is only used if a single chain has both a legacy protocol
+    /// identifier and a [`ChainConfig::grandpa_protocol_config`].
+    ///
+    /// > **Note**: This value is typically found in the specification of the chain (the
+    /// >           "chain spec"), under the name `protocolId`.
+    pub legacy_protocol_id: Option<String>,
+
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
@@ -151,6 +191,22 @@ pub struct ChainConfig<TChain> {
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// `true` if incoming checkpoint requests are allowed.
+    ///
+    /// If `true`, the API user is expected to answer [`Event::CheckpointRequestIn`] events.
+    pub allow_inbound_checkpoint_requests: bool,
+
+    /// `true` if incoming state requests are allowed.
+    ///
+    /// If `true`, the API user is expected to answer [`Event::StateRequestIn`] events.
+    pub allow_inbound_state_requests: bool,
+
+    /// `true` if incoming storage proof and call proof requests are allowed.
+    ///
+    /// If `true`, the API user is expected to answer [`Event::StorageProofRequestIn`] and
+    /// [`Event::CallProofRequestIn`] events.
+    pub allow_inbound_light_requests: bool,
+
     /// Hash of the best block according to the local node.
     pub best_hash: [u8; 32],
     /// Height of the best block according to the local node.
@@ -183,6 +239,13 @@ pub struct ChainNetwork<TChain, TConn, TNow> {
     chains_by_protocol_info:
         hashbrown::HashMap<([u8; 32], Option<String>), usize, fnv::FnvBuildHasher>,
 
+    /// Chains indexed by legacy protocol id. Only contains the chains whose
+    /// [`ChainConfig::legacy_protocol_id`] is `Some`.
+    ///
+    /// The values are `usize`s that are indices into [`ChainNetwork::chains`].
+    // TODO: shrink to fit from time to time
+    chains_by_legacy_protocol_id: hashbrown::HashMap<String, usize, fnv::FnvBuildHasher>,
+
     /// List of all known network identities. The `usize` in [`PeerIndex`] refers to an index
     /// within this list.
     // TODO: shrink to fit from time to time
@@ -192,6 +255,22 @@ pub struct ChainNetwork<TChain, TConn, TNow> {
     // TODO: shrink to fit from time to time
     peers_by_peer_id: hashbrown::HashMap<PeerId, PeerIndex, SipHasherBuild>,
 
+    /// Addresses, encoded as multiaddresses, at which the local node can be reached and that are
+    /// advertised to the peers that send an identify request.
+    /// See [`ChainNetwork::set_external_addresses`].
+    external_addresses: Vec<Vec<u8>>,
+
+    /// See [`Config::compress_response`].
+    compress_response: Option<fn(Vec<u8>) -> Vec<u8>>,
+
+    /// List of peers that have advertised, in their response to an identify request, support
+    /// for the compressed variant of a protocol. Contains only [`Protocol::Sync`] and
+    /// [`Protocol::State`] whose `compressed` field is `true`.
+    ///
+    /// Block and state requests only ask for compressed responses if the target is in this list,
+    /// as the compressed variants of these protocols aren't supported by most peers.
+    compressed_protocols_peers: BTreeSet<(PeerIndex, Protocol)>,
+
     /// Connections indexed by the value in [`ConnectionInfo::peer_index`].
     connections_by_peer_id: BTreeSet<(PeerIndex, collection::ConnectionId)>,
 
@@ -233,6 +312,10 @@ pub struct ChainNetwork<TChain, TConn, TNow> {
         NotificationsSubstreamState,
         collection::SubstreamId,
     )>,
+
+    /// Number of bytes of payload sent and received so far, per kind of protocol.
+    /// See [`ChainNetwork::protocols_bandwidth`].
+    protocols_bandwidth: BTreeMap<ProtocolKind, ProtocolBandwidth>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -246,6 +329,8 @@ struct Chain<TChain> {
     genesis_hash: [u8; 32],
     /// See [`ChainConfig::fork_id`].
     fork_id: Option<String>,
+    /// See [`ChainConfig::legacy_protocol_id`].
+    legacy_protocol_id: Option<String>,
 
     /// See [`ChainConfig::role`].
     role: Role,
@@ -261,6 +346,15 @@ struct Chain<TChain> {
     /// See [`ChainConfig::allow_inbound_block_requests`].
     allow_inbound_block_requests: bool,
 
+    /// See [`ChainConfig::allow_inbound_checkpoint_requests`].
+    allow_inbound_checkpoint_requests: bool,
+
+    /// See [`ChainConfig::allow_inbound_state_requests`].
+    allow_inbound_state_requests: bool,
+
+    /// See [`ChainConfig::allow_inbound_light_requests`].
+    allow_inbound_light_requests: bool,
+
     /// See [`ChainConfig::user_data`].
     user_data: TChain,
 }
@@ -290,20 +384,85 @@ struct SubstreamInfo {
     connection_id: collection::ConnectionId,
     /// `None` if the substream concerns a chain that has been removed.
     protocol: Option<Protocol>,
+    /// For outgoing substreams, protocols and protocol names to try next, in order, if the remote
+    /// doesn't support the one that is being negotiated. Always empty for inbound substreams.
+    fallback_protocols: VecDeque<(Protocol, String)>,
+    /// For outgoing requests, information necessary to start the request again using one of the
+    /// [`SubstreamInfo::fallback_protocols`]. `None` for other substreams.
+    out_request_fallback: Option<Box<OutRequestFallback>>,
+}
+
+/// See [`SubstreamInfo::out_request_fallback`].
+#[derive(Debug, Clone)]
+struct OutRequestFallback {
+    /// Identifier of the request that has been returned to the API user. Different from the
+    /// identifier of the substream if the request has already been started again.
+    request_id: SubstreamId,
+    /// Payload of the request.
+    request_data: Vec<u8>,
+    /// Timeout of the request. Every new attempt uses this timeout again.
+    timeout: Duration,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum Protocol {
     Identify,
     Ping,
     Notifications(NotificationsProtocol),
-    Sync { chain_index: usize },
-    LightUnknown { chain_index: usize },
-    LightStorage { chain_index: usize },
-    LightCall { chain_index: usize },
-    Kad { chain_index: usize },
-    SyncWarp { chain_index: usize },
-    State { chain_index: usize },
+    /// `compressed` is `true` if the responses can be compressed.
+    /// See [`codec::ProtocolName::SyncCompressed`].
+    Sync {
+        chain_index: usize,
+        compressed: bool,
+    },
+    LightUnknown {
+        chain_index: usize,
+    },
+    LightStorage {
+        chain_index: usize,
+    },
+    LightCall {
+        chain_index: usize,
+    },
+    Kad {
+        chain_index: usize,
+    },
+    SyncWarp {
+        chain_index: usize,
+    },
+    /// `compressed` is `true` if the responses can be compressed.
+    /// See [`codec::ProtocolName::StateCompressed`].
+    State {
+        chain_index: usize,
+        compressed: bool,
+    },
+    Checkpoint {
+        chain_index: usize,
+    },
+}
+
+impl Protocol {
+    fn kind(&self) -> ProtocolKind {
+        match self {
+            Protocol::Identify => ProtocolKind::Identify,
+            Protocol::Ping => ProtocolKind::Ping,
+            Protocol::Notifications(NotificationsProtocol::BlockAnnounces { .. }) => {
+                ProtocolKind::BlockAnnounces
+            }
+            Protocol::Notifications(NotificationsProtocol::Transactions { .. }) => {
+                ProtocolKind::Transactions
+            }
+            Protocol::Notifications(NotificationsProtocol::Grandpa { .. }) => ProtocolKind::Grandpa,
+            Protocol::Sync { .. } => ProtocolKind::Sync,
+            Protocol::LightUnknown { .. }
+            | Protocol::LightStorage { .. }
+            | Protocol::LightCall { .. } => ProtocolKind::Light,
+            Protocol::Kad { .. } => ProtocolKind::Kademlia,
+            Protocol::SyncWarp { .. } => ProtocolKind::SyncWarp,
+            Protocol::State { .. } => ProtocolKind::State,
+            Protocol::Checkpoint { .. } => ProtocolKind::Checkpoint,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -313,6 +472,62 @@ enum NotificationsProtocol {
     Grandpa { chain_index: usize },
 }
 
+/// Kind of protocol, as reported by [`ChainNetwork::protocols_bandwidth`].
+///
+/// The protocols of all the chains are merged together.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolKind {
+    /// Identify request-response protocol.
+    Identify,
+    /// Ping protocol. Pings are handled internally and aren't counted.
+    Ping,
+    /// Block announces notifications protocol.
+    BlockAnnounces,
+    /// Transactions notifications protocol.
+    Transactions,
+    /// GrandPa notifications protocol.
+    Grandpa,
+    /// Blocks request-response protocol.
+    Sync,
+    /// Storage and call proofs request-response protocol.
+    Light,
+    /// Kademlia request-response protocol.
+    Kademlia,
+    /// Warp sync request-response protocol.
+    SyncWarp,
+    /// State request-response protocol.
+    State,
+    /// Checkpoint request-response protocol.
+    Checkpoint,
+}
+
+/// Number of bytes exchanged through a protocol. See [`ChainNetwork::protocols_bandwidth`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolBandwidth {
+    /// Number of bytes of payload received from the remotes.
+    pub bytes_in: u64,
+    /// Number of bytes of payload sent to the remotes.
+    pub bytes_out: u64,
+}
+
+/// Adds `num_bytes` to the entry of `kind` in [`ChainNetwork::protocols_bandwidth`].
+///
+/// This is a free function rather than a method in order to be usable while other fields of the
+/// [`ChainNetwork`] are borrowed.
+fn record_bandwidth(
+    protocols_bandwidth: &mut BTreeMap<ProtocolKind, ProtocolBandwidth>,
+    kind: ProtocolKind,
+    direction: SubstreamDirection,
+    num_bytes: usize,
+) {
+    let entry = protocols_bandwidth.entry(kind).or_default();
+    let counter = match direction {
+        SubstreamDirection::In => &mut entry.bytes_in,
+        SubstreamDirection::Out => &mut entry.bytes_out,
+    };
+    *counter = counter.saturating_add(u64::try_from(num_bytes).unwrap_or(u64::MAX));
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum SubstreamDirection {
     In,
@@ -378,6 +593,9 @@ where
                 fnv::FnvBuildHasher::default(),
             ),
             connections_by_peer_id: BTreeSet::new(),
+            external_addresses: Vec::new(),
+            compress_response: config.compress_response,
+            compressed_protocols_peers: BTreeSet::new(),
             notification_substreams_by_peer_id: BTreeSet::new(),
             gossip_desired_peers_by_chain: BTreeSet::new(),
             gossip_desired_peers: BTreeSet::new(),
@@ -398,6 +616,11 @@ where
                 config.chains_capacity,
                 Default::default(),
             ),
+            chains_by_legacy_protocol_id: hashbrown::HashMap::with_capacity_and_hasher(
+                config.chains_capacity,
+                Default::default(),
+            ),
+            protocols_bandwidth: BTreeMap::new(),
         }
     }
 
@@ -409,6 +632,14 @@ where
         let chain_entry = self.chains.vacant_entry();
         let chain_id = chain_entry.key();
 
+        if let Some(legacy_protocol_id) = &config.legacy_protocol_id {
+            if let Some(existing) = self.chains_by_legacy_protocol_id.get(legacy_protocol_id) {
+                return Err(AddChainError::DuplicateLegacyProtocolId {
+                    existing_identical: ChainId(*existing),
+                });
+            }
+        }
+
         match self
             .chains_by_protocol_info
             .entry((config.genesis_hash, config.fork_id.clone()))
@@ -423,14 +654,23 @@ where
             }
         }
 
+        if let Some(legacy_protocol_id) = &config.legacy_protocol_id {
+            self.chains_by_legacy_protocol_id
+                .insert(legacy_protocol_id.clone(), chain_id);
+        }
+
         chain_entry.insert(Chain {
             block_number_bytes: config.block_number_bytes,
             genesis_hash: config.genesis_hash,
             fork_id: config.fork_id,
+            legacy_protocol_id: config.legacy_protocol_id,
             role: config.role,
             best_hash: config.best_hash,
             best_number: config.best_number,
             allow_inbound_block_requests: config.allow_inbound_block_requests,
+            allow_inbound_checkpoint_requests: config.allow_inbound_checkpoint_requests,
+            allow_inbound_state_requests: config.allow_inbound_state_requests,
+            allow_inbound_light_requests: config.allow_inbound_light_requests,
             grandpa_protocol_config: config.grandpa_protocol_config,
             user_data: config.user_data,
         });
@@ -439,7 +679,12 @@ where
         self.inner.set_max_protocol_name_len(
             self.chains
                 .iter()
-                .map(|chain| chain.1.fork_id.as_ref().map_or(0, |s| s.len() + 1))
+                .map(|chain| {
+                    cmp::max(
+                        chain.1.fork_id.as_ref().map_or(0, |s| s.len() + 1),
+                        chain.1.legacy_protocol_id.as_ref().map_or(0, |s| s.len()),
+                    )
+                })
                 .max()
                 .unwrap_or(0)
                 + 64,
@@ -599,13 +844,14 @@ where
                     chain_index,
                 }))
                 | Some(Protocol::Notifications(NotificationsProtocol::Grandpa { chain_index }))
-                | Some(Protocol::Sync { chain_index })
+                | Some(Protocol::Sync { chain_index, .. })
                 | Some(Protocol::LightUnknown { chain_index })
                 | Some(Protocol::LightStorage { chain_index })
                 | Some(Protocol::LightCall { chain_index })
                 | Some(Protocol::Kad { chain_index })
                 | Some(Protocol::SyncWarp { chain_index })
-                | Some(Protocol::State { chain_index }) => {
+                | Some(Protocol::State { chain_index, .. })
+                | Some(Protocol::Checkpoint { chain_index }) => {
                     if chain_index != chain_id.0 {
                         continue;
                     }
@@ -621,10 +867,21 @@ where
 
         // Actually remove the chain. This will panic if the `ChainId` is invalid.
         let chain = self.chains.remove(chain_id.0);
+        self.compressed_protocols_peers
+            .retain(|(_, protocol)| match protocol {
+                Protocol::Sync { chain_index, .. } | Protocol::State { chain_index, .. } => {
+                    *chain_index != chain_id.0
+                }
+                _ => unreachable!(),
+            });
         let _was_in = self
             .chains_by_protocol_info
             .remove(&(chain.genesis_hash, chain.fork_id));
         debug_assert_eq!(_was_in, Some(chain_id.0));
+        if let Some(legacy_protocol_id) = &chain.legacy_protocol_id {
+            let _was_in = self.chains_by_legacy_protocol_id.remove(legacy_protocol_id);
+            debug_assert_eq!(_was_in, Some(chain_id.0));
+        }
 
         Ok(chain.user_data)
     }
@@ -1076,6 +1333,18 @@ where
         (id, task)
     }
 
+    /// Returns the number of bytes of payload sent and received so far, per kind of protocol.
+    ///
+    /// Only the payload of requests, responses, and notifications is counted. Notifications
+    /// handshakes and the overhead of the encryption, multiplexing, and protocol negotiation
+    /// layers aren't included.
+    /// Kinds of protocol through which no byte has been exchanged yet aren't returned.
+    pub fn protocols_bandwidth(
+        &'_ self,
+    ) -> impl ExactSizeIterator<Item = (ProtocolKind, ProtocolBandwidth)> + '_ {
+        self.protocols_bandwidth.iter().map(|(k, v)| (*k, *v))
+    }
+
     /// Returns the number of connections, both handshaking or established.
     pub fn num_connections(&self) -> usize {
         self.inner.len()
@@ -1108,6 +1377,61 @@ where
             .count()
     }
 
+    /// Returns the list of connections with the given peer that have finished their handshaking
+    /// phase and that aren't shutting down.
+    pub fn established_connections(
+        &'_ self,
+        peer_id: &PeerId,
+    ) -> impl Iterator<Item = ConnectionId> + '_ {
+        let peer_index = self.peers_by_peer_id.get(peer_id).copied();
+        peer_index
+            .into_iter()
+            .flat_map(move |peer_index| {
+                self.connections_by_peer_id
+                    .range((peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX))
+                    .map(|(_, connection_id)| *connection_id)
+            })
+            .filter(move |connection_id| {
+                let state = self.inner.connection_state(*connection_id);
+                state.established && !state.shutting_down
+            })
+    }
+
+    /// Starts shutting down all the connections with the given peer, both established and
+    /// handshaking. Returns the number of connections whose shutdown has been started.
+    ///
+    /// A [`Event::Disconnected`] or [`Event::PreHandshakeDisconnected`] is later generated for
+    /// each of these connections.
+    ///
+    /// If the peer is still marked as desired (see [`ChainNetwork::gossip_insert_desired`]), it
+    /// will be reported again by [`ChainNetwork::unconnected_desired`]. Use
+    /// [`ChainNetwork::gossip_remove_desired_all`] beforehand in order to prevent this.
+    ///
+    /// This function might generate messages destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_shutdown_peer(&mut self, peer_id: &PeerId) -> usize {
+        let Some(&peer_index) = self.peers_by_peer_id.get(peer_id) else {
+            return 0;
+        };
+
+        let to_shut_down = self
+            .connections_by_peer_id
+            .range((peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX))
+            .map(|(_, connection_id)| *connection_id)
+            .filter(|connection_id| !self.inner.connection_state(*connection_id).shutting_down)
+            .collect::<Vec<_>>();
+
+        for connection_id in &to_shut_down {
+            self.inner.start_shutdown(*connection_id);
+        }
+
+        if !to_shut_down.is_empty() {
+            self.update_desired_after_shutdown_start(peer_index);
+        }
+
+        to_shut_down.len()
+    }
+
     /// Pulls a message that must be sent to a connection.
     ///
     /// The message must be passed to [`SingleStreamConnectionTask::inject_coordinator_message`]
@@ -1298,52 +1622,7 @@ where
                         continue;
                     };
 
-                    // If peer is desired, and we have no connection or only shutting down
-                    // connections, add peer to `unconnected_desired` and remove it from
-                    // `connected_unopened_gossip_desired`.
-                    if self
-                        .gossip_desired_peers
-                        .range(
-                            (peer_index, GossipKind::ConsensusTransactions, usize::MIN)
-                                ..=(peer_index, GossipKind::ConsensusTransactions, usize::MAX),
-                        )
-                        .count()
-                        != 0
-                    {
-                        if !self
-                            .connections_by_peer_id
-                            .range(
-                                (peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX),
-                            )
-                            .any(|(_, connection_id)| {
-                                let state = self.inner.connection_state(*connection_id);
-                                !state.shutting_down
-                            })
-                        {
-                            self.unconnected_desired.insert(peer_index);
-                        }
-                        if !self
-                            .connections_by_peer_id
-                            .range(
-                                (peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX),
-                            )
-                            .any(|(_, connection_id)| {
-                                let state = self.inner.connection_state(*connection_id);
-                                state.established && !state.shutting_down
-                            })
-                        {
-                            for (_, _, chain_index) in self.gossip_desired_peers.range(
-                                (peer_index, GossipKind::ConsensusTransactions, usize::MIN)
-                                    ..=(peer_index, GossipKind::ConsensusTransactions, usize::MAX),
-                            ) {
-                                self.connected_unopened_gossip_desired.remove(&(
-                                    peer_index,
-                                    ChainId(*chain_index),
-                                    GossipKind::ConsensusTransactions,
-                                ));
-                            }
-                        }
-                    }
+                    self.update_desired_after_shutdown_start(peer_index);
                 }
 
                 collection::Event::Shutdown {
@@ -1386,6 +1665,21 @@ where
                     }
                 }
 
+                collection::Event::InboundError {
+                    id,
+                    error: InboundError::MessageSizeViolation(violation),
+                } => {
+                    // Inbound substreams can only be opened after the handshake, and thus the
+                    // `peer_index` is necessarily known.
+                    let Some(peer_index) = self.inner[id].peer_index else {
+                        unreachable!()
+                    };
+                    return Some(Event::ProtocolError {
+                        peer_id: self.peers[peer_index.0].clone(),
+                        error: ProtocolError::MessageSizeViolation(violation),
+                    });
+                }
+
                 collection::Event::InboundError { .. } => {
                     // TODO: report the error for diagnostic purposes, but revisit the concept of "InboundError"
                 }
@@ -1419,7 +1713,7 @@ where
                         Protocol::Notifications(p) => collection::InboundTy::Notifications {
                             max_handshake_size: self.notifications_protocol_max_handshake_size(p),
                         },
-                        Protocol::Sync { chain_index }
+                        Protocol::Sync { chain_index, .. }
                             if self.chains[chain_index].allow_inbound_block_requests =>
                         {
                             collection::InboundTy::Request {
@@ -1430,12 +1724,42 @@ where
                             self.inner.reject_inbound(substream_id);
                             continue;
                         }
+                        Protocol::Checkpoint { chain_index }
+                            if self.chains[chain_index].allow_inbound_checkpoint_requests =>
+                        {
+                            collection::InboundTy::Request {
+                                request_max_size: None,
+                            }
+                        }
+                        Protocol::Checkpoint { .. } => {
+                            self.inner.reject_inbound(substream_id);
+                            continue;
+                        }
+                        Protocol::State { chain_index, .. }
+                            if self.chains[chain_index].allow_inbound_state_requests =>
+                        {
+                            collection::InboundTy::Request {
+                                request_max_size: Some(1024),
+                            }
+                        }
+                        Protocol::State { .. } => {
+                            self.inner.reject_inbound(substream_id);
+                            continue;
+                        }
+                        Protocol::LightUnknown { chain_index }
+                            if self.chains[chain_index].allow_inbound_light_requests =>
+                        {
+                            collection::InboundTy::Request {
+                                request_max_size: Some(LIGHT_PROTOCOL_MAX_REQUEST_SIZE),
+                            }
+                        }
+                        Protocol::LightUnknown { .. } => {
+                            self.inner.reject_inbound(substream_id);
+                            continue;
+                        }
 
                         // TODO: the protocols below are not supported yet
-                        Protocol::LightUnknown { .. }
-                        | Protocol::Kad { .. }
-                        | Protocol::SyncWarp { .. }
-                        | Protocol::State { .. } => {
+                        Protocol::Kad { .. } | Protocol::SyncWarp { .. } => {
                             self.inner.reject_inbound(substream_id);
                             continue;
                         }
@@ -1452,6 +1776,8 @@ where
                         SubstreamInfo {
                             connection_id: id,
                             protocol: Some(protocol),
+                            fallback_protocols: VecDeque::new(),
+                            out_request_fallback: None,
                         },
                     );
                     debug_assert!(_prev_value.is_none());
@@ -1476,10 +1802,75 @@ where
                     response,
                 } => {
                     // Received a response to a request in a request-response protocol.
-                    let substream_info = self
+                    let mut substream_info = self
                         .substreams
                         .remove(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+
+                    // If the remote doesn't support the protocol that has been requested, start
+                    // the request again using the next protocol in the list, if any.
+                    if let (
+                        Some(_),
+                        Some(fallback),
+                        Err(RequestError::Substream(
+                            established::RequestError::ProtocolNotAvailable,
+                        )),
+                    ) = (
+                        substream_info.protocol,
+                        substream_info.out_request_fallback.as_ref(),
+                        &response,
+                    ) {
+                        // The peer no longer supports the compressed variant of the protocol, if
+                        // that was the one being tried.
+                        if let Some(peer_index) =
+                            self.inner[substream_info.connection_id].peer_index
+                        {
+                            self.compressed_protocols_peers
+                                .remove(&(peer_index, substream_info.protocol.unwrap()));
+                        }
+
+                        let connection_state =
+                            self.inner.connection_state(substream_info.connection_id);
+                        if connection_state.established && !connection_state.shutting_down {
+                            if let Some((protocol, protocol_name)) =
+                                substream_info.fallback_protocols.pop_front()
+                            {
+                                record_bandwidth(
+                                    &mut self.protocols_bandwidth,
+                                    protocol.kind(),
+                                    SubstreamDirection::Out,
+                                    fallback.request_data.len(),
+                                );
+                                let new_substream_id = self.inner.start_request(
+                                    substream_info.connection_id,
+                                    protocol_name,
+                                    Some(fallback.request_data.clone()),
+                                    fallback.timeout,
+                                    16 * 1024 * 1024,
+                                );
+                                substream_info.protocol = Some(protocol);
+                                let _prev_value =
+                                    self.substreams.insert(new_substream_id, substream_info);
+                                debug_assert!(_prev_value.is_none());
+                                continue;
+                            }
+                        }
+                    }
+
+                    // The identifier reported to the API user is the one of the first attempt.
+                    let substream_id = substream_info
+                        .out_request_fallback
+                        .as_ref()
+                        .map_or(substream_id, |fallback| fallback.request_id);
+
+                    if let (Some(protocol), Ok(response)) = (substream_info.protocol, &response) {
+                        record_bandwidth(
+                            &mut self.protocols_bandwidth,
+                            protocol.kind(),
+                            SubstreamDirection::In,
+                            response.len(),
+                        );
+                    }
                     let peer_index = *self.inner[substream_info.connection_id]
                         .peer_index
                         .as_ref()
@@ -1488,11 +1879,72 @@ where
                     // Decode/verify the response.
                     let (response, chain_index) = match substream_info.protocol {
                         None => continue,
-                        Some(Protocol::Identify) => todo!(), // TODO: we don't send identify requests yet, so it's fine to leave this unimplemented
-                        Some(Protocol::Sync { chain_index, .. }) => (
+                        Some(Protocol::Identify) => {
+                            let response = response
+                                .map_err(IdentifyRequestError::Request)
+                                .and_then(|payload| {
+                                    if let Err(err) = codec::decode_identify_response(&payload) {
+                                        Err(IdentifyRequestError::Decode(err))
+                                    } else {
+                                        Ok(EncodedIdentifyResponse(payload))
+                                    }
+                                });
+
+                            // Remember whether the peer supports the compressed variants of the
+                            // block and state request protocols.
+                            if let Ok(response) = &response {
+                                let compressed_protocols = response
+                                    .decode()
+                                    .protocols
+                                    .filter_map(|name| self.recognize_protocol(name).ok())
+                                    .filter(|protocol| {
+                                        matches!(
+                                            protocol,
+                                            Protocol::Sync {
+                                                compressed: true,
+                                                ..
+                                            } | Protocol::State {
+                                                compressed: true,
+                                                ..
+                                            }
+                                        )
+                                    })
+                                    .collect::<Vec<_>>();
+                                for protocol in compressed_protocols {
+                                    self.compressed_protocols_peers
+                                        .insert((peer_index, protocol));
+                                }
+                            }
+
+                            return Some(Event::IdentifyRequestResult {
+                                peer_id: self.peers[peer_index.0].clone(),
+                                substream_id,
+                                response,
+                            });
+                        }
+                        Some(Protocol::Sync {
+                            chain_index,
+                            compressed,
+                        }) => (
                             RequestResult::Blocks(
                                 response.map_err(BlocksRequestError::Request).and_then(
                                     |response| {
+                                        let response =
+                                            if compressed {
+                                                codec::decompress_response_if_necessary(
+                                                    &response,
+                                                    MAX_DECOMPRESSED_RESPONSE_SIZE,
+                                                )
+                                                .map_err(|err| {
+                                                    BlocksRequestError::Decode(
+                                                    codec::DecodeBlockResponseError::Decompression(
+                                                        err,
+                                                    ),
+                                                )
+                                                })?
+                                            } else {
+                                                Cow::Borrowed(&response[..])
+                                            };
                                         codec::decode_block_response(&response)
                                             .map_err(BlocksRequestError::Decode)
                                     },
@@ -1577,11 +2029,30 @@ where
                             ),
                             chain_index,
                         ),
-                        Some(Protocol::State { chain_index, .. }) => (
+                        Some(Protocol::State {
+                            chain_index,
+                            compressed,
+                        }) => (
                             RequestResult::State(
                                 response
                                     .map_err(StateRequestError::Request)
                                     .and_then(|payload| {
+                                        let payload = if compressed {
+                                            codec::decompress_response_if_necessary(
+                                                &payload,
+                                                MAX_DECOMPRESSED_RESPONSE_SIZE,
+                                            )
+                                            .map_err(|err| {
+                                                StateRequestError::Decode(
+                                                    codec::DecodeStateResponseError::Decompression(
+                                                        err,
+                                                    ),
+                                                )
+                                            })?
+                                            .into_owned()
+                                        } else {
+                                            payload
+                                        };
                                         if let Err(err) = codec::decode_state_response(&payload) {
                                             Err(StateRequestError::Decode(err))
                                         } else {
@@ -1591,6 +2062,20 @@ where
                             ),
                             chain_index,
                         ),
+                        Some(Protocol::Checkpoint { chain_index }) => (
+                            RequestResult::Checkpoint(
+                                response.map_err(CheckpointRequestError::Request).and_then(
+                                    |payload| {
+                                        codec::decode_checkpoint_response(
+                                            &payload,
+                                            self.chains[chain_index].block_number_bytes,
+                                        )
+                                        .map_err(CheckpointRequestError::Decode)
+                                    },
+                                ),
+                            ),
+                            chain_index,
+                        ),
 
                         // The protocols below aren't request-response protocols.
                         Some(Protocol::Ping) | Some(Protocol::Notifications(_)) => unreachable!(),
@@ -1613,6 +2098,14 @@ where
                         .substreams
                         .get(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+                    if let Some(protocol) = substream_info.protocol {
+                        record_bandwidth(
+                            &mut self.protocols_bandwidth,
+                            protocol.kind(),
+                            SubstreamDirection::In,
+                            request_payload.len(),
+                        );
+                    }
                     let peer_id = self.peers[self.inner[substream_info.connection_id]
                         .peer_index
                         .as_ref()
@@ -1642,7 +2135,7 @@ where
                                 });
                             }
                         }
-                        Some(Protocol::Sync { chain_index }) => {
+                        Some(Protocol::Sync { chain_index, .. }) => {
                             match codec::decode_block_request(
                                 self.chains[chain_index].block_number_bytes,
                                 &request_payload,
@@ -1653,7 +2146,7 @@ where
                                         chain_id: ChainId(chain_index),
                                         config,
                                         substream_id,
-                                    })
+                                    });
                                 }
                                 Err(error) => {
                                     let _ = self.substreams.remove(&substream_id);
@@ -1665,6 +2158,109 @@ where
                                 }
                             }
                         }
+                        Some(Protocol::Checkpoint { chain_index }) => {
+                            if request_payload.is_empty() {
+                                return Some(Event::CheckpointRequestIn {
+                                    peer_id,
+                                    chain_id: ChainId(chain_index),
+                                    substream_id,
+                                });
+                            } else {
+                                let _ = self.substreams.remove(&substream_id);
+                                self.inner.respond_in_request(substream_id, Err(()));
+                                return Some(Event::ProtocolError {
+                                    peer_id,
+                                    error: ProtocolError::BadCheckpointRequest,
+                                });
+                            }
+                        }
+                        Some(Protocol::State { chain_index, .. }) => {
+                            match codec::decode_state_request(&request_payload) {
+                                Ok(request) => {
+                                    let (child_trie, start_key) = match request.start_key {
+                                        codec::StateRequestStart::MainTrie(key) => {
+                                            (None, key.to_vec())
+                                        }
+                                        codec::StateRequestStart::ChildTrieDefault {
+                                            child_trie,
+                                            key,
+                                        } => (Some(child_trie.to_vec()), key.to_vec()),
+                                    };
+
+                                    return Some(Event::StateRequestIn {
+                                        peer_id,
+                                        chain_id: ChainId(chain_index),
+                                        block_hash: *request.block_hash,
+                                        child_trie,
+                                        start_key,
+                                        substream_id,
+                                    });
+                                }
+                                Err(error) => {
+                                    let _ = self.substreams.remove(&substream_id);
+                                    self.inner.respond_in_request(substream_id, Err(()));
+                                    return Some(Event::ProtocolError {
+                                        peer_id,
+                                        error: ProtocolError::BadStateRequest(error),
+                                    });
+                                }
+                            }
+                        }
+                        Some(Protocol::LightUnknown { chain_index }) => {
+                            match codec::decode_storage_or_call_proof_request(&request_payload) {
+                                Ok(request) => {
+                                    // Now that the kind of request is known, update the protocol
+                                    // of the substream so that the response can be encoded
+                                    // accordingly.
+                                    self.substreams
+                                        .get_mut(&substream_id)
+                                        .unwrap_or_else(|| unreachable!())
+                                        .protocol = Some(match request.ty() {
+                                        codec::StorageOrCallProof::StorageProof => {
+                                            Protocol::LightStorage { chain_index }
+                                        }
+                                        codec::StorageOrCallProof::CallProof => {
+                                            Protocol::LightCall { chain_index }
+                                        }
+                                    });
+
+                                    return Some(match request {
+                                        codec::StorageOrCallProofRequest::StorageProof {
+                                            block_hash,
+                                            child_trie,
+                                            keys,
+                                        } => Event::StorageProofRequestIn {
+                                            peer_id,
+                                            chain_id: ChainId(chain_index),
+                                            block_hash: *block_hash,
+                                            child_trie: child_trie.map(|t| t.to_vec()),
+                                            keys: keys.into_iter().map(|k| k.to_vec()).collect(),
+                                            substream_id,
+                                        },
+                                        codec::StorageOrCallProofRequest::CallProof {
+                                            block_hash,
+                                            method,
+                                            parameter,
+                                        } => Event::CallProofRequestIn {
+                                            peer_id,
+                                            chain_id: ChainId(chain_index),
+                                            block_hash: *block_hash,
+                                            method: method.to_owned(),
+                                            parameter: parameter.to_vec(),
+                                            substream_id,
+                                        },
+                                    });
+                                }
+                                Err(error) => {
+                                    let _ = self.substreams.remove(&substream_id);
+                                    self.inner.respond_in_request(substream_id, Err(()));
+                                    return Some(Event::ProtocolError {
+                                        peer_id,
+                                        error: ProtocolError::BadStorageOrCallProofRequest(error),
+                                    });
+                                }
+                            }
+                        }
                         // Any other protocol is declined when the protocol is negotiated.
                         _ => unreachable!(),
                     }
@@ -1714,7 +2310,33 @@ where
                     ));
                     debug_assert!(_was_in);
 
-                    // The behaviour is very specific to the protocol.
+                    // If the remote doesn't support the protocol name that has been requested,
+                    // try again using the next protocol name in the list, if any.
+                    if let Err(NotificationsOutErr::Substream(
+                        established::NotificationsOutErr::ProtocolNotAvailable,
+                    )) = result
+                    {
+                        if !substream_info.fallback_protocols.is_empty()
+                            && !self.inner.connection_state(connection_id).shutting_down
+                        {
+                            let new_substream_id = self.open_out_notifications(
+                                connection_id,
+                                substream_protocol,
+                                substream_info.fallback_protocols,
+                            );
+                            let _was_inserted = self.notification_substreams_by_peer_id.insert((
+                                substream_protocol,
+                                peer_index,
+                                SubstreamDirection::Out,
+                                NotificationsSubstreamState::Pending,
+                                new_substream_id,
+                            ));
+                            debug_assert!(_was_inserted);
+                            continue;
+                        }
+                    }
+
+                    // The behaviour is very specific to the protocol.
                     match substream_protocol {
                         NotificationsProtocol::BlockAnnounces { chain_index } => {
                             // Parse the handshake to check whether it's correct.
@@ -1735,6 +2357,7 @@ where
                                                 local_genesis: self.chains[chain_index]
                                                     .genesis_hash,
                                                 remote_genesis: *decoded_handshake.genesis_hash,
+                                                remote_role: decoded_handshake.role,
                                             })
                                         }
                                         Err(err) => Err(GossipConnectError::HandshakeDecode(err)),
@@ -1794,48 +2417,12 @@ where
                                             continue;
                                         }
 
-                                        let new_substream_id = self.inner.open_out_notifications(
+                                        let new_substream_id = self.open_out_notifications(
                                             connection_id,
-                                            codec::encode_protocol_name_string(
-                                                match other_protocol {
-                                                    NotificationsProtocol::Transactions {
-                                                        chain_index,
-                                                    } => codec::ProtocolName::Transactions {
-                                                        genesis_hash: self.chains[chain_index]
-                                                            .genesis_hash,
-                                                        fork_id: self.chains[chain_index]
-                                                            .fork_id
-                                                            .as_deref(),
-                                                    },
-                                                    NotificationsProtocol::Grandpa {
-                                                        chain_index,
-                                                    } => codec::ProtocolName::Grandpa {
-                                                        genesis_hash: self.chains[chain_index]
-                                                            .genesis_hash,
-                                                        fork_id: self.chains[chain_index]
-                                                            .fork_id
-                                                            .as_deref(),
-                                                    },
-                                                    _ => unreachable!(),
-                                                },
-                                            ),
-                                            self.notifications_protocol_handshake_timeout(
-                                                other_protocol,
-                                            ),
-                                            self.notifications_protocol_handshake(other_protocol),
-                                            self.notifications_protocol_max_handshake_size(
+                                            other_protocol,
+                                            self.out_protocols(Protocol::Notifications(
                                                 other_protocol,
-                                            ),
-                                        );
-
-                                        self.substreams.insert(
-                                            new_substream_id,
-                                            SubstreamInfo {
-                                                connection_id,
-                                                protocol: Some(Protocol::Notifications(
-                                                    other_protocol,
-                                                )),
-                                            },
+                                            )),
                                         );
 
                                         self.notification_substreams_by_peer_id.insert((
@@ -2084,34 +2671,10 @@ where
                                     continue;
                                 }
 
-                                let new_substream_id = self.inner.open_out_notifications(
+                                let new_substream_id = self.open_out_notifications(
                                     connection_id,
-                                    codec::encode_protocol_name_string(match substream_protocol {
-                                        NotificationsProtocol::Transactions { .. } => {
-                                            codec::ProtocolName::Transactions {
-                                                genesis_hash: self.chains[chain_index].genesis_hash,
-                                                fork_id: self.chains[chain_index]
-                                                    .fork_id
-                                                    .as_deref(),
-                                            }
-                                        }
-                                        NotificationsProtocol::Grandpa { .. } => {
-                                            codec::ProtocolName::Grandpa {
-                                                genesis_hash: self.chains[chain_index].genesis_hash,
-                                                fork_id: self.chains[chain_index]
-                                                    .fork_id
-                                                    .as_deref(),
-                                            }
-                                        }
-                                        _ => unreachable!(),
-                                    }),
-                                    self.notifications_protocol_handshake_timeout(
-                                        substream_protocol,
-                                    ),
-                                    self.notifications_protocol_handshake(substream_protocol),
-                                    self.notifications_protocol_max_handshake_size(
-                                        substream_protocol,
-                                    ),
+                                    substream_protocol,
+                                    self.out_protocols(Protocol::Notifications(substream_protocol)),
                                 );
                                 let _was_inserted =
                                     self.notification_substreams_by_peer_id.insert((
@@ -2122,14 +2685,6 @@ where
                                         new_substream_id,
                                     ));
                                 debug_assert!(_was_inserted);
-                                let _prev_value = self.substreams.insert(
-                                    new_substream_id,
-                                    SubstreamInfo {
-                                        connection_id,
-                                        protocol: substream_info.protocol,
-                                    },
-                                );
-                                debug_assert!(_prev_value.is_none());
                                 continue;
                             }
 
@@ -2164,6 +2719,12 @@ where
                                     a.extend_from_slice(b.as_ref());
                                     a
                                 });
+                                record_bandwidth(
+                                    &mut self.protocols_bandwidth,
+                                    ProtocolKind::Grandpa,
+                                    SubstreamDirection::Out,
+                                    packet.len(),
+                                );
                                 match self.inner.queue_notification(substream_id, packet) {
                                     Ok(()) => {}
                                     Err(collection::QueueNotificationError::QueueFull) => {
@@ -2393,33 +2954,10 @@ where
                                 continue;
                             }
 
-                            let new_substream_id = self.inner.open_out_notifications(
+                            let new_substream_id = self.open_out_notifications(
                                 connection_id,
-                                codec::encode_protocol_name_string(match substream_protocol {
-                                    NotificationsProtocol::Transactions { chain_index } => {
-                                        codec::ProtocolName::Transactions {
-                                            genesis_hash: self.chains[chain_index].genesis_hash,
-                                            fork_id: self.chains[chain_index].fork_id.as_deref(),
-                                        }
-                                    }
-                                    NotificationsProtocol::Grandpa { chain_index } => {
-                                        codec::ProtocolName::Grandpa {
-                                            genesis_hash: self.chains[chain_index].genesis_hash,
-                                            fork_id: self.chains[chain_index].fork_id.as_deref(),
-                                        }
-                                    }
-                                    _ => unreachable!(),
-                                }),
-                                self.notifications_protocol_handshake_timeout(substream_protocol),
-                                self.notifications_protocol_handshake(substream_protocol),
-                                self.notifications_protocol_max_handshake_size(substream_protocol),
-                            );
-                            self.substreams.insert(
-                                new_substream_id,
-                                SubstreamInfo {
-                                    connection_id,
-                                    protocol: Some(Protocol::Notifications(substream_protocol)),
-                                },
+                                substream_protocol,
+                                self.out_protocols(Protocol::Notifications(substream_protocol)),
                             );
                             self.notification_substreams_by_peer_id.insert((
                                 substream_protocol,
@@ -2627,6 +3165,14 @@ where
                         .substreams
                         .get(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+                    if let Some(protocol) = substream_info.protocol {
+                        record_bandwidth(
+                            &mut self.protocols_bandwidth,
+                            protocol.kind(),
+                            SubstreamDirection::In,
+                            notification.len(),
+                        );
+                    }
                     let substream_protocol = match substream_info.protocol {
                         None => {
                             // Substream concerns a chain that has been removed.
@@ -2722,6 +3268,17 @@ where
                                         },
                                     })
                                 }
+                                codec::GrandpaNotificationRef::Vote(_) => {
+                                    return Some(Event::GrandpaVoteMessage {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        message: EncodedGrandpaVoteMessage {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
+                                }
                                 codec::GrandpaNotificationRef::Neighbor(n) => {
                                     return Some(Event::GrandpaNeighborPacket {
                                         chain_id: ChainId(chain_index),
@@ -2733,18 +3290,35 @@ where
                                         },
                                     })
                                 }
-                                _ => {
-                                    // Any other type of message is currently ignored. Support
-                                    // for them could be added in the future.
+                                codec::GrandpaNotificationRef::CatchUpRequest(request) => {
+                                    return Some(Event::GrandpaCatchUpRequest {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        request,
+                                    })
+                                }
+                                codec::GrandpaNotificationRef::CatchUp(_) => {
+                                    return Some(Event::GrandpaCatchUp {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        message: EncodedGrandpaCatchUp {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
                                 }
                             }
                         }
                     }
                 }
 
-                collection::Event::NotificationsInClose { substream_id, .. } => {
+                collection::Event::NotificationsInClose {
+                    substream_id,
+                    outcome,
+                } => {
                     // An incoming notifications substream has been closed.
-                    // Nothing to do except clean up the local state.
+                    // Nothing to do except clean up the local state and report size violations.
                     let Some(substream_info) = self.substreams.remove(&substream_id) else {
                         unreachable!()
                     };
@@ -2781,6 +3355,17 @@ where
                         substream_id,
                     ));
                     debug_assert!(_was_in1 || _was_in2);
+
+                    if let Some(violation) = outcome
+                        .as_ref()
+                        .err()
+                        .and_then(|err| err.message_size_violation())
+                    {
+                        return Some(Event::ProtocolError {
+                            peer_id: self.peers[peer_index.0].clone(),
+                            error: ProtocolError::MessageSizeViolation(violation.clone()),
+                        });
+                    }
                 }
 
                 collection::Event::PingOutSuccess { id, ping_time } => {
@@ -2799,6 +3384,21 @@ where
         }
     }
 
+    /// Sends an identify request to the given peer.
+    ///
+    /// The response contains, amongst other things, the address of the local node as observed by
+    /// the remote. Once the request is finished, a [`Event::IdentifyRequestResult`] is generated.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_identify_request(
+        &mut self,
+        target: &PeerId,
+        timeout: Duration,
+    ) -> Result<SubstreamId, StartRequestError> {
+        self.start_request(target, Vec::new(), Protocol::Identify, timeout)
+    }
+
     /// Sends a blocks request to the given peer.
     ///
     /// The code in this module does not verify the response in any way. The blocks might be
@@ -2834,6 +3434,7 @@ where
             request_data,
             Protocol::Sync {
                 chain_index: chain_id.0,
+                compressed: false,
             },
             timeout,
         )
@@ -2905,6 +3506,39 @@ where
             request_data,
             Protocol::State {
                 chain_index: chain_id.0,
+                compressed: false,
+            },
+            timeout,
+        )
+    }
+
+    /// Sends a checkpoint request to a peer.
+    ///
+    /// A checkpoint request makes it possible to obtain the information about the latest
+    /// finalized block known to the peer, which can then be used as a starting point for
+    /// syncing. The response is not verified by this function. In other words, the peer is free
+    /// to send back information about a block that isn't part of the canonical chain. It is the
+    /// responsibility of the API user to compare the responses of multiple peers before trusting
+    /// a checkpoint.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn start_checkpoint_request(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        timeout: Duration,
+    ) -> Result<SubstreamId, StartRequestError> {
+        self.start_request(
+            target,
+            Vec::new(),
+            Protocol::Checkpoint {
+                chain_index: chain_id.0,
             },
             timeout,
         )
@@ -2935,7 +3569,9 @@ where
 
         // The request data can possibly by higher than the protocol limit, especially due to the
         // call data.
-        // TODO: check limit
+        if request_data.len() > LIGHT_PROTOCOL_MAX_REQUEST_SIZE {
+            return Err(StartRequestMaybeTooLargeError::RequestTooLarge);
+        }
 
         Ok(self.start_request(
             target,
@@ -2979,7 +3615,9 @@ where
 
         // The request data can possibly by higher than the protocol limit, especially due to the
         // call data.
-        // TODO: check limit
+        if request_data.len() > LIGHT_PROTOCOL_MAX_REQUEST_SIZE {
+            return Err(StartRequestMaybeTooLargeError::RequestTooLarge);
+        }
 
         Ok(self.start_request(
             target,
@@ -3050,85 +3688,53 @@ where
             })
             .ok_or(StartRequestError::NoConnection)?;
 
-        let protocol_name = {
-            let protocol_name = match protocol {
-                Protocol::Identify => codec::ProtocolName::Identify,
-                Protocol::Ping => codec::ProtocolName::Ping,
-                Protocol::Notifications(NotificationsProtocol::BlockAnnounces { chain_index }) => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::BlockAnnounces {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Notifications(NotificationsProtocol::Transactions { chain_index }) => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Transactions {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Notifications(NotificationsProtocol::Grandpa { chain_index }) => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Grandpa {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Sync { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Sync {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightUnknown { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightStorage { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightCall { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Kad { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::Kad {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::SyncWarp { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::SyncWarp {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::State { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    codec::ProtocolName::State {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-            };
-
-            codec::encode_protocol_name_string(protocol_name)
+        // Compressed responses are only asked for if the peer is known to support them, in
+        // order to not waste a round trip trying a protocol that the peer doesn't support.
+        let protocol = match protocol {
+            Protocol::Sync {
+                chain_index,
+                compressed: false,
+            } => Protocol::Sync {
+                chain_index,
+                compressed: self.compressed_protocols_peers.contains(&(
+                    peer_index,
+                    Protocol::Sync {
+                        chain_index,
+                        compressed: true,
+                    },
+                )),
+            },
+            Protocol::State {
+                chain_index,
+                compressed: false,
+            } => Protocol::State {
+                chain_index,
+                compressed: self.compressed_protocols_peers.contains(&(
+                    peer_index,
+                    Protocol::State {
+                        chain_index,
+                        compressed: true,
+                    },
+                )),
+            },
+            protocol => protocol,
         };
 
+        let mut fallback_protocols = self.out_protocols(protocol);
+        let (protocol, protocol_name) = fallback_protocols
+            .pop_front()
+            .unwrap_or_else(|| unreachable!());
+
+        record_bandwidth(
+            &mut self.protocols_bandwidth,
+            protocol.kind(),
+            SubstreamDirection::Out,
+            request_data.len(),
+        );
+
+        // The request data is kept in order to be able to start the request again.
+        let fallback_request_data = (!fallback_protocols.is_empty()).then(|| request_data.clone());
+
         let substream_id = self.inner.start_request(
             connection_id,
             protocol_name,
@@ -3142,6 +3748,14 @@ where
             SubstreamInfo {
                 connection_id,
                 protocol: Some(protocol),
+                fallback_protocols,
+                out_request_fallback: fallback_request_data.map(|request_data| {
+                    Box::new(OutRequestFallback {
+                        request_id: substream_id,
+                        request_data,
+                        timeout,
+                    })
+                }),
             },
         );
         debug_assert!(_prev_value.is_none());
@@ -3149,85 +3763,444 @@ where
         Ok(substream_id)
     }
 
-    /// Responds to an identify request. Call this function in response to
-    /// a [`Event::IdentifyRequestIn`].
-    ///
-    /// Only the `agent_version` needs to be specified. The other fields are automatically
-    /// filled by the [`ChainNetwork`].
-    ///
-    /// This function might generate a message destined a connection. Use
-    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
-    ///
-    /// # Panic
+    /// Starts opening an outbound notifications substream using the first element of
+    /// `protocols`, and inserts it in [`ChainNetwork::substreams`]. The other elements are tried
+    /// next if the remote doesn't support the protocol.
     ///
-    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a blocks request or
-    /// if the request has been cancelled with a [`Event::RequestInCancel`].
-    ///
-    pub fn respond_identify(&mut self, substream_id: SubstreamId, agent_version: &str) {
-        let substream_info = self.substreams.remove(&substream_id).unwrap();
-        assert!(matches!(
-            substream_info.protocol,
-            Some(Protocol::Identify { .. })
-        ));
+    /// The substream isn't inserted in [`ChainNetwork::notification_substreams_by_peer_id`].
+    fn open_out_notifications(
+        &mut self,
+        connection_id: ConnectionId,
+        protocol: NotificationsProtocol,
+        mut protocols: VecDeque<(Protocol, String)>,
+    ) -> SubstreamId {
+        let (_, protocol_name) = protocols.pop_front().unwrap_or_else(|| unreachable!());
 
-        let response = {
-            let observed_addr = &self.inner[substream_info.connection_id].address;
-            let ed25519_public_key = &self.inner[substream_info.connection_id].ed25519_public_key;
+        let substream_id = self.inner.open_out_notifications(
+            connection_id,
+            protocol_name,
+            self.notifications_protocol_handshake_timeout(protocol),
+            self.notifications_protocol_handshake(protocol),
+            self.notifications_protocol_max_handshake_size(protocol),
+        );
 
-            let supported_protocols = [codec::ProtocolName::Ping, codec::ProtocolName::Identify]
-                .into_iter()
-                .chain(self.chains.iter().flat_map(|(_, chain)| {
-                    [
-                        codec::ProtocolName::BlockAnnounces {
-                            genesis_hash: chain.genesis_hash,
-                            fork_id: chain.fork_id.as_deref(),
-                        },
-                        codec::ProtocolName::Transactions {
-                            genesis_hash: chain.genesis_hash,
-                            fork_id: chain.fork_id.as_deref(),
-                        },
-                    ]
-                    .into_iter()
-                    .chain(
-                        chain
-                            .grandpa_protocol_config
-                            .is_some()
-                            .then_some(codec::ProtocolName::Grandpa {
-                                genesis_hash: chain.genesis_hash,
-                                fork_id: chain.fork_id.as_deref(),
-                            })
-                            .into_iter(),
-                    )
-                    .chain(
-                        chain
-                            .allow_inbound_block_requests
-                            .then_some(codec::ProtocolName::Sync {
-                                genesis_hash: chain.genesis_hash,
-                                fork_id: chain.fork_id.as_deref(),
-                            })
-                            .into_iter(),
-                    )
-                }));
+        let _prev_value = self.substreams.insert(
+            substream_id,
+            SubstreamInfo {
+                connection_id,
+                protocol: Some(Protocol::Notifications(protocol)),
+                fallback_protocols: protocols,
+                out_request_fallback: None,
+            },
+        );
+        debug_assert!(_prev_value.is_none());
 
-            let supported_protocols_names = supported_protocols
-                .map(codec::encode_protocol_name_string)
-                .collect::<Vec<_>>();
+        substream_id
+    }
 
-            codec::build_identify_response(codec::IdentifyResponse {
-                protocol_version: "/substrate/1.0", // TODO: same value as in Substrate, see also https://github.com/paritytech/substrate/issues/14331
-                agent_version,
-                ed25519_public_key: *ed25519_public_key,
-                listen_addrs: iter::empty(), // TODO:
-                observed_addr,
-                protocols: supported_protocols_names.iter().map(|p| &p[..]),
-            })
-            .fold(Vec::new(), |mut a, b| {
-                a.extend_from_slice(b.as_ref());
-                a
-            })
+    /// Returns the list of protocols and protocol names to try, in order, when opening an outbound
+    /// substream of the given protocol.
+    ///
+    /// The first element is always the given protocol and its name. The next elements are the
+    /// non-compressed variant of the protocol, if relevant, and the legacy name of the protocol
+    /// if the chain has a [`ChainConfig::legacy_protocol_id`].
+    fn out_protocols(&self, protocol: Protocol) -> VecDeque<(Protocol, String)> {
+        let mut protocols = VecDeque::with_capacity(3);
+        protocols.push_back((protocol, self.protocol_name(protocol)));
+
+        let protocol = match protocol {
+            Protocol::Sync {
+                chain_index,
+                compressed: true,
+            } => {
+                let protocol = Protocol::Sync {
+                    chain_index,
+                    compressed: false,
+                };
+                protocols.push_back((protocol, self.protocol_name(protocol)));
+                protocol
+            }
+            Protocol::State {
+                chain_index,
+                compressed: true,
+            } => {
+                let protocol = Protocol::State {
+                    chain_index,
+                    compressed: false,
+                };
+                protocols.push_back((protocol, self.protocol_name(protocol)));
+                protocol
+            }
+            protocol => protocol,
         };
 
-        self.inner.respond_in_request(substream_id, Ok(response));
+        if let Some(legacy_name) = self.legacy_protocol_name(protocol) {
+            protocols.push_back((protocol, legacy_name));
+        }
+
+        protocols
+    }
+
+    /// Returns the name of the given protocol using the legacy naming scheme, or `None` if the
+    /// chain doesn't use legacy names or if the protocol doesn't have any legacy name.
+    fn legacy_protocol_name(&self, protocol: Protocol) -> Option<String> {
+        let protocol_id =
+            |chain_index: usize| self.chains[chain_index].legacy_protocol_id.as_deref();
+
+        let legacy_protocol = match protocol {
+            Protocol::Identify
+            | Protocol::Ping
+            | Protocol::Checkpoint { .. }
+            | Protocol::Sync {
+                compressed: true, ..
+            }
+            | Protocol::State {
+                compressed: true, ..
+            } => return None,
+            Protocol::Notifications(NotificationsProtocol::Grandpa { chain_index }) => {
+                return (self.legacy_grandpa_chain() == Some(chain_index))
+                    .then(|| codec::LEGACY_GRANDPA_PROTOCOL_NAME.to_owned());
+            }
+            Protocol::Notifications(NotificationsProtocol::BlockAnnounces { chain_index }) => {
+                codec::LegacyProtocolName::BlockAnnounces {
+                    protocol_id: protocol_id(chain_index)?,
+                }
+            }
+            Protocol::Notifications(NotificationsProtocol::Transactions { chain_index }) => {
+                codec::LegacyProtocolName::Transactions {
+                    protocol_id: protocol_id(chain_index)?,
+                }
+            }
+            Protocol::Sync { chain_index, .. } => codec::LegacyProtocolName::Sync {
+                protocol_id: protocol_id(chain_index)?,
+            },
+            Protocol::LightUnknown { chain_index }
+            | Protocol::LightStorage { chain_index }
+            | Protocol::LightCall { chain_index } => codec::LegacyProtocolName::Light {
+                protocol_id: protocol_id(chain_index)?,
+            },
+            Protocol::Kad { chain_index } => codec::LegacyProtocolName::Kad {
+                protocol_id: protocol_id(chain_index)?,
+            },
+            Protocol::SyncWarp { chain_index } => codec::LegacyProtocolName::SyncWarp {
+                protocol_id: protocol_id(chain_index)?,
+            },
+            Protocol::State { chain_index, .. } => codec::LegacyProtocolName::State {
+                protocol_id: protocol_id(chain_index)?,
+            },
+        };
+
+        Some(codec::encode_legacy_protocol_name_string(legacy_protocol))
+    }
+
+    /// Returns the chain that the legacy GrandPa protocol name refers to, if any.
+    ///
+    /// The legacy GrandPa protocol name doesn't contain any chain-specific information. It is
+    /// only used if a single chain has a [`ChainConfig::legacy_protocol_id`] and a
+    /// [`ChainConfig::grandpa_protocol_config`].
+    fn legacy_grandpa_chain(&self) -> Option<usize> {
+        let mut chains = self
+            .chains_by_legacy_protocol_id
+            .values()
+            .filter(|chain_index| self.chains[**chain_index].grandpa_protocol_config.is_some());
+        let chain_index = *chains.next()?;
+        if chains.next().is_some() {
+            return None;
+        }
+        Some(chain_index)
+    }
+
+    /// Returns the name of the given protocol, as negotiated on substreams.
+    fn protocol_name(&self, protocol: Protocol) -> String {
+        let protocol_name = match protocol {
+            Protocol::Identify => codec::ProtocolName::Identify,
+            Protocol::Ping => codec::ProtocolName::Ping,
+            Protocol::Notifications(NotificationsProtocol::BlockAnnounces { chain_index }) => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::BlockAnnounces {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Notifications(NotificationsProtocol::Transactions { chain_index }) => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Transactions {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Notifications(NotificationsProtocol::Grandpa { chain_index }) => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Grandpa {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Sync {
+                chain_index,
+                compressed: false,
+            } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Sync {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Sync {
+                chain_index,
+                compressed: true,
+            } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::SyncCompressed {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::LightUnknown { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Light {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::LightStorage { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Light {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::LightCall { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Light {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Kad { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Kad {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::SyncWarp { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::SyncWarp {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::State {
+                chain_index,
+                compressed: false,
+            } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::State {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::State {
+                chain_index,
+                compressed: true,
+            } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::StateCompressed {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+            Protocol::Checkpoint { chain_index } => {
+                let chain_info = &self.chains[chain_index];
+                codec::ProtocolName::Checkpoint {
+                    genesis_hash: chain_info.genesis_hash,
+                    fork_id: chain_info.fork_id.as_deref(),
+                }
+            }
+        };
+
+        codec::encode_protocol_name_string(protocol_name)
+    }
+
+    /// Responds to a request on a request-response protocol, and updates
+    /// [`ChainNetwork::protocols_bandwidth`].
+    fn respond_in_request(
+        &mut self,
+        substream_id: SubstreamId,
+        kind: ProtocolKind,
+        response: Result<Vec<u8>, ()>,
+    ) {
+        if let Ok(response) = &response {
+            record_bandwidth(
+                &mut self.protocols_bandwidth,
+                kind,
+                SubstreamDirection::Out,
+                response.len(),
+            );
+        }
+
+        self.inner.respond_in_request(substream_id, response);
+    }
+
+    /// Responds to an identify request. Call this function in response to
+    /// a [`Event::IdentifyRequestIn`].
+    ///
+    /// Only the `agent_version` needs to be specified. The other fields are automatically
+    /// filled by the [`ChainNetwork`].
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a blocks request or
+    /// if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_identify(&mut self, substream_id: SubstreamId, agent_version: &str) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Some(Protocol::Identify { .. })
+        ));
+
+        let response = {
+            let observed_addr = &self.inner[substream_info.connection_id].address;
+            let ed25519_public_key = &self.inner[substream_info.connection_id].ed25519_public_key;
+
+            let supported_protocols = [codec::ProtocolName::Ping, codec::ProtocolName::Identify]
+                .into_iter()
+                .chain(self.chains.iter().flat_map(|(_, chain)| {
+                    [
+                        codec::ProtocolName::BlockAnnounces {
+                            genesis_hash: chain.genesis_hash,
+                            fork_id: chain.fork_id.as_deref(),
+                        },
+                        codec::ProtocolName::Transactions {
+                            genesis_hash: chain.genesis_hash,
+                            fork_id: chain.fork_id.as_deref(),
+                        },
+                    ]
+                    .into_iter()
+                    .chain(
+                        chain
+                            .grandpa_protocol_config
+                            .is_some()
+                            .then_some(codec::ProtocolName::Grandpa {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_block_requests
+                            .then_some([
+                                codec::ProtocolName::Sync {
+                                    genesis_hash: chain.genesis_hash,
+                                    fork_id: chain.fork_id.as_deref(),
+                                },
+                                codec::ProtocolName::SyncCompressed {
+                                    genesis_hash: chain.genesis_hash,
+                                    fork_id: chain.fork_id.as_deref(),
+                                },
+                            ])
+                            .into_iter()
+                            .flatten(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_checkpoint_requests
+                            .then_some(codec::ProtocolName::Checkpoint {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_state_requests
+                            .then_some([
+                                codec::ProtocolName::State {
+                                    genesis_hash: chain.genesis_hash,
+                                    fork_id: chain.fork_id.as_deref(),
+                                },
+                                codec::ProtocolName::StateCompressed {
+                                    genesis_hash: chain.genesis_hash,
+                                    fork_id: chain.fork_id.as_deref(),
+                                },
+                            ])
+                            .into_iter()
+                            .flatten(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_light_requests
+                            .then_some(codec::ProtocolName::Light {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
+                }));
+
+            let supported_protocols_names = supported_protocols
+                .map(codec::encode_protocol_name_string)
+                .chain(self.chains.iter().flat_map(|(_, chain)| {
+                    chain
+                        .legacy_protocol_id
+                        .as_deref()
+                        .into_iter()
+                        .flat_map(|protocol_id| {
+                            [
+                                Some(codec::LegacyProtocolName::BlockAnnounces { protocol_id }),
+                                Some(codec::LegacyProtocolName::Transactions { protocol_id }),
+                                chain
+                                    .allow_inbound_block_requests
+                                    .then_some(codec::LegacyProtocolName::Sync { protocol_id }),
+                                chain
+                                    .allow_inbound_state_requests
+                                    .then_some(codec::LegacyProtocolName::State { protocol_id }),
+                                chain
+                                    .allow_inbound_light_requests
+                                    .then_some(codec::LegacyProtocolName::Light { protocol_id }),
+                            ]
+                            .into_iter()
+                            .flatten()
+                        })
+                        .map(codec::encode_legacy_protocol_name_string)
+                }))
+                .chain(
+                    self.legacy_grandpa_chain()
+                        .map(|_| codec::LEGACY_GRANDPA_PROTOCOL_NAME.to_owned()),
+                )
+                .collect::<Vec<_>>();
+
+            codec::build_identify_response(codec::IdentifyResponse {
+                protocol_version: "/substrate/1.0", // TODO: same value as in Substrate, see also https://github.com/paritytech/substrate/issues/14331
+                agent_version,
+                ed25519_public_key: *ed25519_public_key,
+                listen_addrs: self.external_addresses.iter().map(|a| &a[..]),
+                observed_addr,
+                protocols: supported_protocols_names.iter().map(|p| &p[..]),
+            })
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            })
+        };
+
+        self.respond_in_request(substream_id, ProtocolKind::Identify, Ok(response));
+    }
+
+    /// Sets the list of addresses, encoded as multiaddresses, at which the local node can be
+    /// reached. They are sent to the peers that send an identify request, which is how other
+    /// nodes learn how to connect to the local node.
+    ///
+    /// The list is empty by default.
+    pub fn set_external_addresses(&mut self, addresses: impl IntoIterator<Item = Vec<u8>>) {
+        self.external_addresses.clear();
+        self.external_addresses.extend(addresses);
     }
 
     /// Responds to a blocks request. Call this function in response to
@@ -3250,23 +4223,168 @@ where
         response: Option<Vec<codec::BlockData>>,
     ) {
         let substream_info = self.substreams.remove(&substream_id).unwrap();
-        assert!(matches!(
-            substream_info.protocol,
-            Some(Protocol::Sync { .. })
-        ));
+        let Some(Protocol::Sync { compressed, .. }) = substream_info.protocol else {
+            panic!()
+        };
 
         let response = if let Some(response) = response {
-            Ok(
-                codec::build_block_response(response).fold(Vec::new(), |mut a, b| {
-                    a.extend_from_slice(b.as_ref());
-                    a
-                }),
-            )
+            let response = codec::build_block_response(response).fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+            match (compressed, self.compress_response) {
+                (true, Some(compress_response)) => Ok(compress_response(response)),
+                _ => Ok(response),
+            }
         } else {
             Err(())
         };
 
-        self.inner.respond_in_request(substream_id, response);
+        self.respond_in_request(substream_id, ProtocolKind::Sync, response);
+    }
+
+    /// Responds to a checkpoint request. Call this function in response to
+    /// a [`Event::CheckpointRequestIn`].
+    ///
+    /// Pass `None` in order to deny the request. Do this if the information about the latest
+    /// finalized block isn't available locally.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a checkpoint request or
+    /// if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_checkpoint(
+        &mut self,
+        substream_id: SubstreamId,
+        response: Option<chain_information::ValidChainInformationRef>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        let Some(Protocol::Checkpoint { chain_index }) = substream_info.protocol else {
+            panic!()
+        };
+
+        let response = if let Some(response) = response {
+            Ok(codec::build_checkpoint_response(
+                response,
+                self.chains[chain_index].block_number_bytes,
+            ))
+        } else {
+            Err(())
+        };
+
+        self.respond_in_request(substream_id, ProtocolKind::Checkpoint, response);
+    }
+
+    /// Responds to a state request. Call this function in response to
+    /// a [`Event::StateRequestIn`].
+    ///
+    /// Pass `None` in order to deny the request. Do this if the requested block or its storage
+    /// isn't available locally.
+    ///
+    /// `proof` must be a compact Merkle proof of the storage entries that start at the requested
+    /// key, as described in the documentation of the state requests protocol.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a state request or
+    /// if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_state(&mut self, substream_id: SubstreamId, proof: Option<&[u8]>) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        let Some(Protocol::State { compressed, .. }) = substream_info.protocol else {
+            panic!()
+        };
+
+        let response = if let Some(proof) = proof {
+            let response = codec::build_state_response(proof).fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+            match (compressed, self.compress_response) {
+                (true, Some(compress_response)) => Ok(compress_response(response)),
+                _ => Ok(response),
+            }
+        } else {
+            Err(())
+        };
+
+        self.respond_in_request(substream_id, ProtocolKind::State, response);
+    }
+
+    /// Responds to a storage proof request. Call this function in response to
+    /// a [`Event::StorageProofRequestIn`].
+    ///
+    /// Pass `None` if the requested block or its storage isn't available locally.
+    ///
+    /// `proof` must be a SCALE-encoded Merkle proof containing the trie nodes necessary to
+    /// prove the values of all the requested keys.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a storage proof request
+    /// or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_storage_proof(&mut self, substream_id: SubstreamId, proof: Option<&[u8]>) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        let Some(Protocol::LightStorage { .. }) = substream_info.protocol else {
+            panic!()
+        };
+
+        let response = codec::build_storage_or_call_proof_response(
+            codec::StorageOrCallProof::StorageProof,
+            proof,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        self.respond_in_request(substream_id, ProtocolKind::Light, Ok(response));
+    }
+
+    /// Responds to a call proof request. Call this function in response to
+    /// a [`Event::CallProofRequestIn`].
+    ///
+    /// Pass `None` if the requested block or its storage isn't available locally, or if the
+    /// call has failed.
+    ///
+    /// `proof` must be a SCALE-encoded Merkle proof containing the trie nodes that the runtime
+    /// has accessed during the call.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a call proof request
+    /// or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_call_proof(&mut self, substream_id: SubstreamId, proof: Option<&[u8]>) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        let Some(Protocol::LightCall { .. }) = substream_info.protocol else {
+            panic!()
+        };
+
+        let response = codec::build_storage_or_call_proof_response(
+            codec::StorageOrCallProof::CallProof,
+            proof,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        self.respond_in_request(substream_id, ProtocolKind::Light, Ok(response));
     }
 
     /// Returns the list of all peers for a [`Event::GossipConnected`] event of the given kind has
@@ -3382,8 +4500,6 @@ where
             return Err(OpenGossipError::NoConnection);
         };
 
-        let chain_info = &self.chains[chain_id.0];
-
         // It is forbidden to open more than one gossip notifications substream with any given
         // peer.
         if self
@@ -3494,34 +4610,17 @@ where
         }
 
         // Open the block announces substream.
-        let substream_id = self.inner.open_out_notifications(
+        let substream_id = self.open_out_notifications(
             connection_id,
-            codec::encode_protocol_name_string(codec::ProtocolName::BlockAnnounces {
-                genesis_hash: chain_info.genesis_hash,
-                fork_id: chain_info.fork_id.as_deref(),
-            }),
-            self.notifications_protocol_handshake_timeout(NotificationsProtocol::BlockAnnounces {
-                chain_index: chain_id.0,
-            }),
-            self.notifications_protocol_handshake(NotificationsProtocol::BlockAnnounces {
-                chain_index: chain_id.0,
-            }),
-            self.notifications_protocol_max_handshake_size(NotificationsProtocol::BlockAnnounces {
+            NotificationsProtocol::BlockAnnounces {
                 chain_index: chain_id.0,
-            }),
-        );
-        let _prev_value = self.substreams.insert(
-            substream_id,
-            SubstreamInfo {
-                connection_id,
-                protocol: Some(Protocol::Notifications(
-                    NotificationsProtocol::BlockAnnounces {
-                        chain_index: chain_id.0,
-                    },
-                )),
             },
+            self.out_protocols(Protocol::Notifications(
+                NotificationsProtocol::BlockAnnounces {
+                    chain_index: chain_id.0,
+                },
+            )),
         );
-        debug_assert!(_prev_value.is_none());
         let _was_inserted = self.notification_substreams_by_peer_id.insert((
             NotificationsProtocol::BlockAnnounces {
                 chain_index: chain_id.0,
@@ -3718,34 +4817,175 @@ where
     /// In other words, calling this function atomically informs all the present and future peers
     /// of the state of the local node regarding the GrandPa protocol.
     ///
-    /// > **Note**: The information passed as parameter isn't validated in any way by this method.
+    /// > **Note**: The information passed as parameter isn't validated in any way by this method.
+    ///
+    /// This function might generate a message destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has GrandPa disabled.
+    ///
+    pub fn gossip_broadcast_grandpa_state_and_update(
+        &mut self,
+        chain_id: ChainId,
+        grandpa_state: GrandpaState,
+    ) {
+        // Bytes of the neighbor packet to send out.
+        let packet = codec::GrandpaNotificationRef::Neighbor(codec::NeighborPacket {
+            round_number: grandpa_state.round_number,
+            set_id: grandpa_state.set_id,
+            commit_finalized_height: grandpa_state.commit_finalized_height,
+        })
+        .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        // Now sending out to all the grandpa substreams that exist.
+        self.broadcast_grandpa_notification(chain_id, packet);
+
+        // Update the locally-stored state.
+        *self.chains[chain_id.0]
+            .grandpa_protocol_config
+            .as_mut()
+            .unwrap() = grandpa_state;
+    }
+
+    /// Sends the given GrandPa vote (prevote, precommit, or primary proposal) on all the active
+    /// GrandPa substreams.
+    ///
+    /// > **Note**: The vote isn't validated in any way by this method.
+    ///
+    /// This function might generate a message destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has GrandPa disabled.
+    ///
+    pub fn gossip_broadcast_grandpa_vote(
+        &mut self,
+        chain_id: ChainId,
+        vote: codec::VoteMessageRef,
+    ) {
+        assert!(self.chains[chain_id.0].grandpa_protocol_config.is_some());
+        let packet = codec::GrandpaNotificationRef::Vote(vote)
+            .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        self.broadcast_grandpa_notification(chain_id, packet);
+    }
+
+    /// Sends the given GrandPa commit message on all the active GrandPa substreams.
+    ///
+    /// > **Note**: The commit isn't validated in any way by this method.
+    ///
+    /// This function might generate a message destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has GrandPa disabled.
+    ///
+    pub fn gossip_broadcast_grandpa_commit(
+        &mut self,
+        chain_id: ChainId,
+        commit: codec::CommitMessageRef,
+    ) {
+        assert!(self.chains[chain_id.0].grandpa_protocol_config.is_some());
+        let packet = codec::GrandpaNotificationRef::Commit(commit)
+            .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        self.broadcast_grandpa_notification(chain_id, packet);
+    }
+
+    /// Sends a GrandPa catch up request to the given peer, asking for the votes of a round later
+    /// than the given one.
+    ///
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
+    /// returned.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has GrandPa disabled.
+    ///
+    pub fn gossip_send_grandpa_catch_up_request(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    ) -> Result<(), QueueNotificationError> {
+        assert!(self.chains[chain_id.0].grandpa_protocol_config.is_some());
+        let notification = codec::GrandpaNotificationRef::CatchUpRequest(request)
+            .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        self.queue_notification(
+            target,
+            NotificationsProtocol::Grandpa {
+                chain_index: chain_id.0,
+            },
+            notification,
+        )
+    }
+
+    /// Sends a GrandPa catch up message to the given peer, normally in response to a
+    /// [`Event::GrandpaCatchUpRequest`].
+    ///
+    /// > **Note**: The catch up message isn't validated in any way by this method.
     ///
-    /// This function might generate a message destined to connections. Use
-    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
     /// returned.
     ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
     /// # Panic
     ///
     /// Panics if [`ChainId`] is invalid, or if the chain has GrandPa disabled.
     ///
-    pub fn gossip_broadcast_grandpa_state_and_update(
+    pub fn gossip_send_grandpa_catch_up(
         &mut self,
+        target: &PeerId,
         chain_id: ChainId,
-        grandpa_state: GrandpaState,
-    ) {
-        // Bytes of the neighbor packet to send out.
-        let packet = codec::GrandpaNotificationRef::Neighbor(codec::NeighborPacket {
-            round_number: grandpa_state.round_number,
-            set_id: grandpa_state.set_id,
-            commit_finalized_height: grandpa_state.commit_finalized_height,
-        })
-        .scale_encoding(self.chains[chain_id.0].block_number_bytes)
-        .fold(Vec::new(), |mut a, b| {
-            a.extend_from_slice(b.as_ref());
-            a
-        });
+        catch_up: codec::CatchUpRef,
+    ) -> Result<(), QueueNotificationError> {
+        assert!(self.chains[chain_id.0].grandpa_protocol_config.is_some());
+        let notification = codec::GrandpaNotificationRef::CatchUp(catch_up)
+            .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        self.queue_notification(
+            target,
+            NotificationsProtocol::Grandpa {
+                chain_index: chain_id.0,
+            },
+            notification,
+        )
+    }
 
-        // Now sending out to all the grandpa substreams that exist.
+    /// Queues the given notification on all the outbound GrandPa substreams of the given chain
+    /// that are open.
+    fn broadcast_grandpa_notification(&mut self, chain_id: ChainId, packet: Vec<u8>) {
         // TODO: O(n)
         for (_, _, _, _, substream_id) in
             self.notification_substreams_by_peer_id
@@ -3758,16 +4998,15 @@ where
                 })
         {
             match self.inner.queue_notification(*substream_id, packet.clone()) {
-                Ok(()) => {}
+                Ok(()) => record_bandwidth(
+                    &mut self.protocols_bandwidth,
+                    ProtocolKind::Grandpa,
+                    SubstreamDirection::Out,
+                    packet.len(),
+                ),
                 Err(collection::QueueNotificationError::QueueFull) => {}
             }
         }
-
-        // Update the locally-stored state.
-        *self.chains[chain_id.0]
-            .grandpa_protocol_config
-            .as_mut()
-            .unwrap() = grandpa_state;
     }
 
     /// Sends a block announce gossip message to the given peer.
@@ -3863,6 +5102,12 @@ where
 
         assert!(self.chains.contains(chain_index));
 
+        // The remote would close the substream upon receiving a notification larger than the
+        // protocol limit.
+        if notification.len() > self.notifications_protocol_max_notification_size(protocol) {
+            return Err(QueueNotificationError::TooLarge);
+        }
+
         // We first find a block announces substream for that peer.
         // TODO: only relevant for GossipKind::ConsensusTransactions
         // If none is found, then we are not considered "gossip-connected", and return an error
@@ -3920,8 +5165,17 @@ where
             id
         };
 
+        let notification_len = notification.len();
         match self.inner.queue_notification(substream_id, notification) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                record_bandwidth(
+                    &mut self.protocols_bandwidth,
+                    Protocol::Notifications(protocol).kind(),
+                    SubstreamDirection::Out,
+                    notification_len,
+                );
+                Ok(())
+            }
             Err(collection::QueueNotificationError::QueueFull) => {
                 Err(QueueNotificationError::QueueFull)
             }
@@ -3929,7 +5183,11 @@ where
     }
 
     fn recognize_protocol(&self, protocol_name: &str) -> Result<Protocol, ()> {
-        Ok(match codec::decode_protocol_name(protocol_name)? {
+        let Ok(decoded) = codec::decode_protocol_name(protocol_name) else {
+            return self.recognize_legacy_protocol(protocol_name);
+        };
+
+        Ok(match decoded {
             codec::ProtocolName::Identify => Protocol::Identify,
             codec::ProtocolName::Ping => Protocol::Ping,
             codec::ProtocolName::BlockAnnounces {
@@ -3967,6 +5225,17 @@ where
                     .chains_by_protocol_info
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
+                compressed: false,
+            },
+            codec::ProtocolName::SyncCompressed {
+                genesis_hash,
+                fork_id,
+            } => Protocol::Sync {
+                chain_index: *self
+                    .chains_by_protocol_info
+                    .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
+                    .ok_or(())?,
+                compressed: true,
             },
             codec::ProtocolName::Light {
                 genesis_hash,
@@ -4003,10 +5272,121 @@ where
                     .chains_by_protocol_info
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
+                compressed: false,
+            },
+            codec::ProtocolName::StateCompressed {
+                genesis_hash,
+                fork_id,
+            } => Protocol::State {
+                chain_index: *self
+                    .chains_by_protocol_info
+                    .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
+                    .ok_or(())?,
+                compressed: true,
+            },
+            codec::ProtocolName::Checkpoint {
+                genesis_hash,
+                fork_id,
+            } => Protocol::Checkpoint {
+                chain_index: *self
+                    .chains_by_protocol_info
+                    .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
+                    .ok_or(())?,
+            },
+        })
+    }
+
+    /// Same as [`ChainNetwork::recognize_protocol`], but for protocol names that use the legacy
+    /// naming scheme. See [`ChainConfig::legacy_protocol_id`].
+    fn recognize_legacy_protocol(&self, protocol_name: &str) -> Result<Protocol, ()> {
+        if protocol_name == codec::LEGACY_GRANDPA_PROTOCOL_NAME {
+            return Ok(Protocol::Notifications(NotificationsProtocol::Grandpa {
+                chain_index: self.legacy_grandpa_chain().ok_or(())?,
+            }));
+        }
+
+        let decoded = codec::decode_legacy_protocol_name(protocol_name).map_err(|_| ())?;
+        let (codec::LegacyProtocolName::BlockAnnounces { protocol_id }
+        | codec::LegacyProtocolName::Transactions { protocol_id }
+        | codec::LegacyProtocolName::Sync { protocol_id }
+        | codec::LegacyProtocolName::Light { protocol_id }
+        | codec::LegacyProtocolName::Kad { protocol_id }
+        | codec::LegacyProtocolName::SyncWarp { protocol_id }
+        | codec::LegacyProtocolName::State { protocol_id }) = decoded;
+        let chain_index = *self
+            .chains_by_legacy_protocol_id
+            .get(protocol_id)
+            .ok_or(())?;
+
+        Ok(match decoded {
+            codec::LegacyProtocolName::BlockAnnounces { .. } => {
+                Protocol::Notifications(NotificationsProtocol::BlockAnnounces { chain_index })
+            }
+            codec::LegacyProtocolName::Transactions { .. } => {
+                Protocol::Notifications(NotificationsProtocol::Transactions { chain_index })
+            }
+            codec::LegacyProtocolName::Sync { .. } => Protocol::Sync {
+                chain_index,
+                compressed: false,
+            },
+            codec::LegacyProtocolName::Light { .. } => Protocol::LightUnknown { chain_index },
+            codec::LegacyProtocolName::Kad { .. } => Protocol::Kad { chain_index },
+            codec::LegacyProtocolName::SyncWarp { .. } => Protocol::SyncWarp { chain_index },
+            codec::LegacyProtocolName::State { .. } => Protocol::State {
+                chain_index,
+                compressed: false,
             },
         })
     }
 
+    /// Updates [`ChainNetwork::unconnected_desired`] and
+    /// [`ChainNetwork::connected_unopened_gossip_desired`] after one of the connections of the
+    /// given peer has started shutting down.
+    fn update_desired_after_shutdown_start(&mut self, peer_index: PeerIndex) {
+        // If peer is desired, and we have no connection or only shutting down
+        // connections, add peer to `unconnected_desired` and remove it from
+        // `connected_unopened_gossip_desired`.
+        if self
+            .gossip_desired_peers
+            .range(
+                (peer_index, GossipKind::ConsensusTransactions, usize::MIN)
+                    ..=(peer_index, GossipKind::ConsensusTransactions, usize::MAX),
+            )
+            .count()
+            != 0
+        {
+            if !self
+                .connections_by_peer_id
+                .range((peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX))
+                .any(|(_, connection_id)| {
+                    let state = self.inner.connection_state(*connection_id);
+                    !state.shutting_down
+                })
+            {
+                self.unconnected_desired.insert(peer_index);
+            }
+            if !self
+                .connections_by_peer_id
+                .range((peer_index, ConnectionId::MIN)..=(peer_index, ConnectionId::MAX))
+                .any(|(_, connection_id)| {
+                    let state = self.inner.connection_state(*connection_id);
+                    state.established && !state.shutting_down
+                })
+            {
+                for (_, _, chain_index) in self.gossip_desired_peers.range(
+                    (peer_index, GossipKind::ConsensusTransactions, usize::MIN)
+                        ..=(peer_index, GossipKind::ConsensusTransactions, usize::MAX),
+                ) {
+                    self.connected_unopened_gossip_desired.remove(&(
+                        peer_index,
+                        ChainId(*chain_index),
+                        GossipKind::ConsensusTransactions,
+                    ));
+                }
+            }
+        }
+    }
+
     /// Returns the [`PeerIndex`] of the given [`PeerId`], inserting it if isn't present in the
     /// list yet.
     fn peer_index_or_insert(&mut self, peer_id: PeerId) -> PeerIndex {
@@ -4050,6 +5430,17 @@ where
         let peer_id = self.peers.remove(peer_index.0);
         let _was_in = self.peers_by_peer_id.remove(&peer_id);
         debug_assert_eq!(_was_in, Some(peer_index));
+
+        let compressed_protocols = self
+            .compressed_protocols_peers
+            .range(
+                (peer_index, Protocol::Identify)..(PeerIndex(peer_index.0 + 1), Protocol::Identify),
+            )
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in compressed_protocols {
+            self.compressed_protocols_peers.remove(&entry);
+        }
     }
 
     /// Returns the maximum allowed size (in bytes) of the handshake of the given protocol.
@@ -4151,6 +5542,12 @@ pub enum AddChainError {
         /// Identifier of the chain that uses the same genesis hash and fork id.
         existing_identical: ChainId,
     },
+    /// The legacy protocol id is identical to the one of an existing chain.
+    #[display(fmt = "Legacy protocol id is identical to the one of an existing chain.")]
+    DuplicateLegacyProtocolId {
+        /// Identifier of the chain that uses the same legacy protocol id.
+        existing_identical: ChainId,
+    },
 }
 
 /// Error returned by [`ChainNetwork::remove_chain`].
@@ -4292,6 +5689,18 @@ pub enum Event<TConn> {
         response: RequestResult,
     },
 
+    /// An outgoing identify request started with [`ChainNetwork::start_identify_request`] has
+    /// finished, either successfully or not.
+    IdentifyRequestResult {
+        /// Peer that has answered the request.
+        peer_id: PeerId,
+        /// Identifier of the request that was returned by
+        /// [`ChainNetwork::start_identify_request`].
+        substream_id: SubstreamId,
+        /// Outcome of the request.
+        response: Result<EncodedIdentifyResponse, IdentifyRequestError>,
+    },
+
     /// Received a new block announce from a peer.
     ///
     /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
@@ -4318,6 +5727,18 @@ pub enum Event<TConn> {
         state: GrandpaState,
     },
 
+    /// Received a GrandPa vote (prevote, precommit, or primary proposal) from the network.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaVoteMessage {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the vote relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaVoteMessage,
+    },
+
     /// Received a GrandPa commit message from the network.
     ///
     /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
@@ -4330,6 +5751,35 @@ pub enum Event<TConn> {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a GrandPa catch up request from the network. The remote asks for the votes of
+    /// a round later than [`codec::CatchUpRequest::round_number`] that the local node has
+    /// completed. Use [`ChainNetwork::gossip_send_grandpa_catch_up`] to answer.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUpRequest {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the request relates to.
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    },
+
+    /// Received a GrandPa catch up message from the network, normally in response to a request
+    /// sent with [`ChainNetwork::gossip_send_grandpa_catch_up_request`].
+    ///
+    /// > **Note**: The catch up message isn't validated in any way.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUp {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the message relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaCatchUp,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -4367,6 +5817,84 @@ pub enum Event<TConn> {
         substream_id: SubstreamId,
     },
 
+    /// A remote has sent a request for the latest finalized checkpoint.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_checkpoint_requests`] is
+    /// `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_checkpoint`].
+    CheckpointRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a request for the storage of a block.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_state_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_state`].
+    StateRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// If `Some`, the request starts within the given default child trie. The entries of the
+        /// main trie that follow the child trie must be included after the child trie entries.
+        child_trie: Option<Vec<u8>>,
+        /// The response shouldn't contain any key lexicographically inferior to this key.
+        start_key: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a request for a Merkle proof of storage entries.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_light_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_storage_proof`].
+    StorageProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// Name of the default child trie the keys belong to, without its
+        /// `:child_storage:default:` prefix, or `None` if the keys belong to the main trie.
+        child_trie: Option<Vec<u8>>,
+        /// Keys whose value must be proven.
+        keys: Vec<Vec<u8>>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a request for a Merkle proof of the storage entries accessed by a
+    /// runtime call.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_light_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_call_proof`].
+    CallProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block whose storage the call must be made against.
+        block_hash: [u8; 32],
+        /// Name of the runtime function to call.
+        method: String,
+        /// Parameter to pass to the runtime function.
+        parameter: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
     /// A remote is no longer interested in the response to a request.
     ///
     /// Calling [`ChainNetwork::respond_identify`], [`ChainNetwork::respond_blocks`], or similar
@@ -4404,6 +5932,17 @@ pub enum ProtocolError {
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {_0}")]
     BadBlocksRequest(codec::DecodeBlockRequestError),
+    /// Received an invalid checkpoint request.
+    BadCheckpointRequest,
+    /// Error while decoding a received state request.
+    #[display(fmt = "Error while decoding a received state request: {_0}")]
+    BadStateRequest(codec::DecodeStateRequestError),
+    /// Error while decoding a received storage proof or call proof request.
+    #[display(fmt = "Error while decoding a received storage or call proof request: {_0}")]
+    BadStorageOrCallProofRequest(codec::DecodeStorageOrCallProofRequestError),
+    /// Remote has sent a message larger than what its protocol allows.
+    #[display(fmt = "{_0}")]
+    MessageSizeViolation(MessageSizeViolation),
 }
 
 /// Error potentially returned by [`ChainNetwork::gossip_open`].
@@ -4454,6 +5993,7 @@ pub enum RequestResult {
     Blocks(Result<Vec<codec::BlockData>, BlocksRequestError>),
     GrandpaWarpSync(Result<EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError>),
     State(Result<EncodedStateResponse, StateRequestError>),
+    Checkpoint(Result<chain_information::ValidChainInformation, CheckpointRequestError>),
     StorageProof(Result<EncodedMerkleProof, StorageProofRequestError>),
     CallProof(Result<EncodedMerkleProof, CallProofRequestError>),
     KademliaFindNode(Result<Vec<(peer_id::PeerId, Vec<Vec<u8>>)>, KademliaFindNodeError>),
@@ -4522,6 +6062,24 @@ pub enum StateRequestError {
     Decode(codec::DecodeStateResponseError),
 }
 
+/// Error returned by [`ChainNetwork::start_identify_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum IdentifyRequestError {
+    #[display(fmt = "{_0}")]
+    Request(RequestError),
+    #[display(fmt = "Response decoding error: {_0}")]
+    Decode(codec::DecodeIdentifyResponseError),
+}
+
+/// Error returned by [`ChainNetwork::start_checkpoint_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum CheckpointRequestError {
+    #[display(fmt = "{_0}")]
+    Request(RequestError),
+    #[display(fmt = "Response decoding error: {_0}")]
+    Decode(codec::DecodeCheckpointResponseError),
+}
+
 /// Error during [`ChainNetwork::start_kademlia_find_node_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum KademliaFindNodeError {
@@ -4540,6 +6098,8 @@ pub enum QueueNotificationError {
     NoConnection,
     /// Queue of notifications with that peer is full.
     QueueFull,
+    /// Size of the notification is over the maximum allowed by the protocol.
+    TooLarge,
 }
 
 /// Undecoded but valid block announce.
@@ -4609,6 +6169,28 @@ impl fmt::Debug for EncodedGrandpaWarpSyncResponse {
     }
 }
 
+/// Undecoded but valid identify response.
+#[derive(Clone)]
+pub struct EncodedIdentifyResponse(Vec<u8>);
+
+impl EncodedIdentifyResponse {
+    /// Returns the decoded version of the identify response.
+    pub fn decode(
+        &self,
+    ) -> codec::IdentifyResponse<'_, vec::IntoIter<&'_ [u8]>, vec::IntoIter<&'_ str>> {
+        match codec::decode_identify_response(&self.0) {
+            Ok(r) => r,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedIdentifyResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
 /// Undecoded but valid state response.
 // TODO: merge with EncodedMerkleProof?
 #[derive(Clone)]
@@ -4675,6 +6257,8 @@ pub enum GossipConnectError {
         local_genesis: [u8; 32],
         /// Hash of the genesis block of the chain according to the remote node.
         remote_genesis: [u8; 32],
+        /// Role the remote node reports playing on the network.
+        remote_role: Role,
     },
 }
 
@@ -4713,3 +6297,55 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid GrandPa vote message.
+#[derive(Clone)]
+pub struct EncodedGrandpaVoteMessage {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaVoteMessage {
+    /// Returns the encoded bytes of the vote message.
+    pub fn as_encoded(&self) -> &[u8] {
+        // Skip the first byte because `self.message` is a `GrandpaNotificationRef`.
+        &self.message[1..]
+    }
+
+    /// Returns the decoded version of the vote message.
+    pub fn decode(&self) -> codec::VoteMessageRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::Vote(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaVoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid GrandPa catch up message.
+#[derive(Clone)]
+pub struct EncodedGrandpaCatchUp {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaCatchUp {
+    /// Returns the decoded version of the catch up message.
+    pub fn decode(&self) -> codec::CatchUpRef<'_> {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::CatchUp(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaCatchUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
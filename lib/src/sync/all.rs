@@ -49,6 +49,7 @@ use core::{
 };
 
 pub use crate::executor::vm::ExecHint;
+pub use all_forks::ForceFinalizeError;
 pub use blocks_tree::{CommitVerifyError, JustificationVerifyError};
 pub use warp_sync::{
     BuildChainInformationError as WarpSyncBuildChainInformationError,
@@ -84,6 +85,11 @@ pub struct Config {
     /// `false` guarantee that the number of authorable blocks over the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Maximum duration by which the slot of a block is allowed to start in the future.
+    ///
+    /// See [`all_forks::Config::slot_drift_tolerance`].
+    pub slot_drift_tolerance: Duration,
+
     /// Pre-allocated capacity for the number of block sources.
     pub sources_capacity: usize,
 
@@ -124,6 +130,12 @@ pub struct Config {
     /// sync fragments instead.
     pub download_all_chain_information_storage_proofs: bool,
 
+    /// If `true`, the syncing starts by downloading and verifying GrandPa warp sync proofs in
+    /// order to jump directly to the latest finalized block, then downloads the state of this
+    /// block. If `false`, or if the chain doesn't use GrandPa, all the blocks are downloaded and
+    /// verified one by one starting from [`Config::chain_information`].
+    pub warp_sync: bool,
+
     /// Known valid Merkle value and storage value combination for the `:code` key.
     ///
     /// If provided, the warp syncing algorithm will first fetch the Merkle value of `:code`, and
@@ -195,20 +207,24 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     pub fn new(config: Config) -> Self {
         AllSync {
             // TODO: notify API user if can't start warp sync?
-            warp_sync: warp_sync::start_warp_sync(warp_sync::Config {
-                start_chain_information: config.chain_information.clone(),
-                block_number_bytes: config.block_number_bytes,
-                sources_capacity: config.sources_capacity,
-                requests_capacity: config.sources_capacity, // TODO: ?! add as config?
-                download_all_chain_information_storage_proofs: config
-                    .download_all_chain_information_storage_proofs,
-                code_trie_node_hint: config.code_trie_node_hint,
-                num_download_ahead_fragments: 128, // TODO: make configurable?
-                // TODO: make configurable?
-                warp_sync_minimum_gap: 32,
-                download_block_body: config.download_bodies,
-            })
-            .ok(),
+            warp_sync: if config.warp_sync {
+                warp_sync::start_warp_sync(warp_sync::Config {
+                    start_chain_information: config.chain_information.clone(),
+                    block_number_bytes: config.block_number_bytes,
+                    sources_capacity: config.sources_capacity,
+                    requests_capacity: config.sources_capacity, // TODO: ?! add as config?
+                    download_all_chain_information_storage_proofs: config
+                        .download_all_chain_information_storage_proofs,
+                    code_trie_node_hint: config.code_trie_node_hint,
+                    num_download_ahead_fragments: 128, // TODO: make configurable?
+                    // TODO: make configurable?
+                    warp_sync_minimum_gap: 32,
+                    download_block_body: config.download_bodies,
+                })
+                .ok()
+            } else {
+                None
+            },
             ready_to_transition: None,
             all_forks: Some(AllForksSync::new(all_forks::Config {
                 chain_information: config.chain_information,
@@ -217,6 +233,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 blocks_capacity: config.blocks_capacity,
                 download_bodies: config.download_bodies,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                slot_drift_tolerance: config.slot_drift_tolerance,
                 max_disjoint_headers: config.max_disjoint_headers,
                 max_requests_per_block: config.max_requests_per_block,
             })),
@@ -230,6 +247,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 max_requests_per_block: config.max_requests_per_block,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                slot_drift_tolerance: config.slot_drift_tolerance,
             },
         }
     }
@@ -350,8 +368,12 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     }
 
     /// Returns consensus information about the current best block of the chain.
-    pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
-        todo!() // TODO:
+    pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef<'_> {
+        let Some(all_forks) = &self.all_forks else {
+            unreachable!()
+        };
+
+        all_forks.best_block_consensus()
     }
 
     /// Returns the header of all known non-finalized blocks in the chain without any specific
@@ -980,6 +1002,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 blocks_capacity: self.shared.blocks_capacity,
                 download_bodies: self.shared.download_bodies,
                 allow_unknown_consensus_engines: self.shared.allow_unknown_consensus_engines,
+                slot_drift_tolerance: self.shared.slot_drift_tolerance,
                 max_disjoint_headers: self.shared.max_disjoint_headers,
                 max_requests_per_block: self.shared.max_requests_per_block,
             });
@@ -1196,6 +1219,45 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Marks the given block and all its ancestors as finalized, without verifying any finality
+    /// proof. The blocks that don't descend from it are pruned.
+    ///
+    /// This is meant to be used on development chains that lack a finality mechanism, or in
+    /// order to recover a network whose finality has stalled. The block must have been verified.
+    ///
+    /// Returns [`FinalityProofVerifyOutcome::AlreadyFinalized`] if the block is the current
+    /// finalized block, and [`FinalityProofVerifyOutcome::NewFinalized`] otherwise.
+    pub fn force_finalize(
+        &mut self,
+        block_hash: &[u8; 32],
+    ) -> Result<FinalityProofVerifyOutcome<TBl>, ForceFinalizeError> {
+        let Some(all_forks) = &mut self.all_forks else {
+            unreachable!()
+        };
+
+        match all_forks.force_finalize(block_hash)? {
+            all_forks::FinalityProofVerifyOutcome::NewFinalized {
+                finalized_blocks_newest_to_oldest,
+                pruned_blocks,
+                updates_best_block,
+            } => {
+                if let Some(warp_sync) = &mut self.warp_sync {
+                    warp_sync.set_chain_information(all_forks.as_chain_information())
+                }
+
+                Ok(new_finalized_outcome(
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks,
+                    updates_best_block,
+                ))
+            }
+            all_forks::FinalityProofVerifyOutcome::AlreadyFinalized => {
+                Ok(FinalityProofVerifyOutcome::AlreadyFinalized)
+            }
+            _ => unreachable!(),
+        }
+    }
+
     /// Inject a response to a previously-emitted blocks request.
     ///
     /// The blocks should be provided in decreasing number, with `first_block_hash` as the highest
@@ -2151,6 +2213,23 @@ impl<TRq, TSrc, TBl> HeaderVerifySuccess<TRq, TSrc, TBl> {
         self.inner.scale_encoded_header()
     }
 
+    /// Returns the number of blocks of the current best chain that would be reverted if the
+    /// block that was verified became the new best block.
+    pub fn reorg_depth(&self) -> u64 {
+        self.inner.reorg_depth()
+    }
+
+    /// Returns the slot number the block that was verified belongs to.
+    pub fn slot_number(&self) -> u64 {
+        self.inner.slot_number()
+    }
+
+    /// Returns the Sr25519 public key of the authority that has authored the block that was
+    /// verified.
+    pub fn author_public_key(&self) -> &[u8; 32] {
+        self.inner.author_public_key()
+    }
+
     /// Returns the SCALE-encoded header of the parent of the block.
     pub fn parent_scale_encoded_header(&self) -> &[u8] {
         self.inner.parent_scale_encoded_header()
@@ -2167,6 +2246,20 @@ impl<TRq, TSrc, TBl> HeaderVerifySuccess<TRq, TSrc, TBl> {
         }
     }
 
+    /// Drop the block and its known descendants without marking them as bad.
+    ///
+    /// Contrary to [`HeaderVerifySuccess::reject_bad_block`], the block can be downloaded and
+    /// verified again later if a source announces it again.
+    pub fn discard(self) -> AllSync<TRq, TSrc, TBl> {
+        let all_forks = self.inner.discard();
+        AllSync {
+            all_forks: Some(all_forks),
+            warp_sync: self.warp_sync,
+            ready_to_transition: self.ready_to_transition,
+            shared: self.shared,
+        }
+    }
+
     /// Reject the block and mark it as bad.
     pub fn reject_bad_block(self) -> AllSync<TRq, TSrc, TBl> {
         let all_forks = self.inner.reject_bad_block();
@@ -2209,6 +2302,14 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
         )
     }
 
+    /// Returns the consensus engine identifier and the SCALE-encoded justification to verify.
+    ///
+    /// Returns `None` if what is verified is a GrandPa commit message rather than a
+    /// justification.
+    pub fn justification(&self) -> Option<([u8; 4], &[u8])> {
+        self.inner.justification()
+    }
+
     /// Perform the verification.
     ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
@@ -2232,19 +2333,11 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
 
                 (
                     sync,
-                    // TODO: weird conversions
-                    FinalityProofVerifyOutcome::NewFinalized {
-                        finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
-                            .into_iter()
-                            .map(|b| Block {
-                                header: b.scale_encoded_header,
-                                block_hash: b.block_hash,
-                                user_data: b.user_data.unwrap(),
-                            })
-                            .collect(),
-                        pruned_blocks: pruned_blocks.into_iter().map(|b| b.block_hash).collect(),
+                    new_finalized_outcome(
+                        finalized_blocks_newest_to_oldest,
+                        pruned_blocks,
                         updates_best_block,
-                    },
+                    ),
                 )
             }
             (sync, all_forks::FinalityProofVerifyOutcome::AlreadyFinalized) => {
@@ -2273,6 +2366,28 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
     }
 }
 
+/// Converts the blocks finalized and pruned by the inner [`all_forks::AllForksSync`] into a
+/// [`FinalityProofVerifyOutcome::NewFinalized`].
+fn new_finalized_outcome<TBl>(
+    finalized_blocks_newest_to_oldest: Vec<all_forks::RemovedBlock<Option<TBl>>>,
+    pruned_blocks: Vec<all_forks::RemovedBlock<Option<TBl>>>,
+    updates_best_block: bool,
+) -> FinalityProofVerifyOutcome<TBl> {
+    // TODO: weird conversions
+    FinalityProofVerifyOutcome::NewFinalized {
+        finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
+            .into_iter()
+            .map(|b| Block {
+                header: b.scale_encoded_header,
+                block_hash: b.block_hash,
+                user_data: b.user_data.unwrap(),
+            })
+            .collect(),
+        pruned_blocks: pruned_blocks.into_iter().map(|b| b.block_hash).collect(),
+        updates_best_block,
+    }
+}
+
 /// Information about the outcome of verifying a finality proof.
 #[derive(Debug)]
 pub enum FinalityProofVerifyOutcome<TBl> {
@@ -2453,6 +2568,8 @@ struct Shared<TRq, TSrc> {
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::slot_drift_tolerance`].
+    slot_drift_tolerance: Duration,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
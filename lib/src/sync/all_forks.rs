@@ -90,6 +90,7 @@ use core::{
 
 mod disjoint;
 mod pending_blocks;
+mod tests;
 
 pub mod sources;
 
@@ -120,6 +121,11 @@ pub struct Config {
     /// `false` guarantee that the number of authorable blocks over the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Maximum duration by which the slot of a block is allowed to start in the future.
+    ///
+    /// See [`blocks_tree::Config::slot_drift_tolerance`].
+    pub slot_drift_tolerance: Duration,
+
     /// Pre-allocated capacity for the number of block sources.
     pub sources_capacity: usize,
 
@@ -423,6 +429,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             block_number_bytes: config.block_number_bytes,
             blocks_capacity: config.blocks_capacity,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            slot_drift_tolerance: config.slot_drift_tolerance,
         });
 
         Self {
@@ -489,6 +496,11 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.best_block_hash()
     }
 
+    /// Returns consensus information about the current best block of the chain.
+    pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef<'_> {
+        self.chain.best_block_consensus()
+    }
+
     /// Returns the header of all known non-finalized blocks in the chain without any specific
     /// order.
     pub fn non_finalized_blocks_unordered(
@@ -957,6 +969,31 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         GrandpaCommitMessageOutcome::Queued
     }
 
+    /// Marks the given block and all its ancestors as finalized, without verifying any finality
+    /// proof. The blocks that don't descend from it are pruned.
+    ///
+    /// This is meant to be used on development chains that lack a finality mechanism, or in
+    /// order to recover a network whose finality has stalled. The block must have been verified.
+    ///
+    /// Returns [`FinalityProofVerifyOutcome::AlreadyFinalized`] if the block is the current
+    /// finalized block, and [`FinalityProofVerifyOutcome::NewFinalized`] otherwise.
+    pub fn force_finalize(
+        &mut self,
+        block_hash: &[u8; 32],
+    ) -> Result<FinalityProofVerifyOutcome<TBl>, ForceFinalizeError> {
+        if *block_hash == *self.chain.finalized_block_hash() {
+            return Ok(FinalityProofVerifyOutcome::AlreadyFinalized);
+        }
+
+        let finalized_blocks_iter = self.chain.set_finalized_block(block_hash).map_err(
+            |blocks_tree::SetFinalizedError::UnknownBlock| ForceFinalizeError::UnknownBlock,
+        )?;
+        Ok(finalized_blocks_outcome(
+            &mut self.inner.blocks,
+            finalized_blocks_iter,
+        ))
+    }
+
     /// Process the next block in the queue of verification.
     ///
     /// This method takes ownership of the [`AllForksSync`] and starts a verification
@@ -2058,6 +2095,17 @@ impl<TBl, TRq, TSrc> HeaderVerifySuccess<TBl, TRq, TSrc> {
         self.verified_header.scale_encoded_header()
     }
 
+    /// Returns the slot number the block that was verified belongs to.
+    pub fn slot_number(&self) -> u64 {
+        self.verified_header.slot_number()
+    }
+
+    /// Returns the Sr25519 public key of the authority that has authored the block that was
+    /// verified.
+    pub fn author_public_key(&self) -> &[u8; 32] {
+        self.verified_header.author_public_key()
+    }
+
     /// Returns the list of SCALE-encoded extrinsics of the block to verify.
     ///
     /// This is `Some` if and only if [`Config::download_bodies`] is `true`
@@ -2085,6 +2133,15 @@ impl<TBl, TRq, TSrc> HeaderVerifySuccess<TBl, TRq, TSrc> {
         }
     }
 
+    /// Returns the number of blocks of the current best chain that would be reverted if the
+    /// block that was verified became the new best block.
+    pub fn reorg_depth(&self) -> u64 {
+        self.parent
+            .chain
+            .best_block_reorg_depth(&self.block_to_verify.parent_block_hash)
+            .unwrap_or_else(|| unreachable!())
+    }
+
     /// Returns the SCALE-encoded header of the block that was verified.
     pub fn parent_scale_encoded_header(&self) -> &[u8] {
         if self.block_to_verify.parent_block_hash == *self.parent.chain.finalized_block_hash() {
@@ -2102,6 +2159,22 @@ impl<TBl, TRq, TSrc> HeaderVerifySuccess<TBl, TRq, TSrc> {
         self.parent
     }
 
+    /// Drop the block and its known descendants without marking them as bad.
+    ///
+    /// Contrary to [`HeaderVerifySuccess::reject_bad_block`], the block can be downloaded and
+    /// verified again later if a source announces it again.
+    pub fn discard(mut self) -> AllForksSync<TBl, TRq, TSrc> {
+        self.parent
+            .inner
+            .blocks
+            .remove_unverified_block_and_descendants(
+                self.block_to_verify.block_number,
+                &self.block_to_verify.block_hash,
+            );
+
+        self.parent
+    }
+
     /// Reject the block and mark it as bad.
     pub fn reject_bad_block(mut self) -> AllForksSync<TBl, TRq, TSrc> {
         // Remove the block from `pending_blocks`.
@@ -2159,6 +2232,19 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
         (self.source_id, &self.parent[self.source_id])
     }
 
+    /// Returns the consensus engine identifier and the SCALE-encoded justification to verify.
+    ///
+    /// Returns `None` if what is verified is a GrandPa commit message rather than a
+    /// justification.
+    pub fn justification(&self) -> Option<([u8; 4], &[u8])> {
+        match &self.finality_proof_to_verify {
+            FinalityProof::GrandpaCommit(_) => None,
+            FinalityProof::Justification((engine_id, justification)) => {
+                Some((*engine_id, justification))
+            }
+        }
+    }
+
     /// Perform the verification.
     ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
@@ -2255,43 +2341,10 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
 
         // Commit or justification successfully verified.
         // Update the local state with the newly-finalized block.
-
         let finalized_blocks_iter = finality_apply.apply();
-        let updates_best_block = finalized_blocks_iter.updates_best_block();
-        let mut finalized_blocks = Vec::new();
-        let mut pruned_blocks = Vec::new();
-        // TODO: a bit weird to perform a conversion here
-        for block in finalized_blocks_iter {
-            if matches!(block.ty, blocks_tree::RemovedBlockType::Finalized) {
-                finalized_blocks.push(RemovedBlock {
-                    block_hash: block.block_hash,
-                    block_number: block.block_number,
-                    user_data: block.user_data,
-                    scale_encoded_header: block.scale_encoded_header,
-                });
-            } else {
-                pruned_blocks.push(RemovedBlock {
-                    block_hash: block.block_hash,
-                    block_number: block.block_number,
-                    user_data: block.user_data,
-                    scale_encoded_header: block.scale_encoded_header,
-                });
-            }
-        }
-        let _finalized_blocks = self
-            .parent
-            .inner
-            .blocks
-            .set_finalized_block_height(finalized_blocks.last().unwrap().block_number);
-
-        (
-            self.parent,
-            FinalityProofVerifyOutcome::NewFinalized {
-                finalized_blocks_newest_to_oldest: finalized_blocks,
-                pruned_blocks,
-                updates_best_block,
-            },
-        )
+        let outcome =
+            finalized_blocks_outcome(&mut self.parent.inner.blocks, finalized_blocks_iter);
+        (self.parent, outcome)
     }
 
     /// Do not actually proceed with the verification.
@@ -2300,6 +2353,51 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
     }
 }
 
+/// Builds a [`FinalityProofVerifyOutcome::NewFinalized`] from the blocks removed from the chain
+/// after a block has been finalized, and removes the pending blocks that are now below the
+/// finalized block.
+fn finalized_blocks_outcome<TBl, TRq, TSrc>(
+    pending_blocks: &mut pending_blocks::PendingBlocks<PendingBlock<TBl>, TRq, Source<TSrc>>,
+    finalized_blocks_iter: blocks_tree::SetFinalizedBlockIter<TBl>,
+) -> FinalityProofVerifyOutcome<TBl> {
+    let updates_best_block = finalized_blocks_iter.updates_best_block();
+    let mut finalized_blocks = Vec::new();
+    let mut pruned_blocks = Vec::new();
+    // TODO: a bit weird to perform a conversion here
+    for block in finalized_blocks_iter {
+        if matches!(block.ty, blocks_tree::RemovedBlockType::Finalized) {
+            finalized_blocks.push(RemovedBlock {
+                block_hash: block.block_hash,
+                block_number: block.block_number,
+                user_data: block.user_data,
+                scale_encoded_header: block.scale_encoded_header,
+            });
+        } else {
+            pruned_blocks.push(RemovedBlock {
+                block_hash: block.block_hash,
+                block_number: block.block_number,
+                user_data: block.user_data,
+                scale_encoded_header: block.scale_encoded_header,
+            });
+        }
+    }
+    let _finalized_blocks =
+        pending_blocks.set_finalized_block_height(finalized_blocks.last().unwrap().block_number);
+
+    FinalityProofVerifyOutcome::NewFinalized {
+        finalized_blocks_newest_to_oldest: finalized_blocks,
+        pruned_blocks,
+        updates_best_block,
+    }
+}
+
+/// Error returned by [`AllForksSync::force_finalize`].
+#[derive(Debug, derive_more::Display)]
+pub enum ForceFinalizeError {
+    /// The block isn't a verified block that descends from the finalized block.
+    UnknownBlock,
+}
+
 /// See [`AllForksSync::grandpa_commit_message`].
 #[derive(Debug, Clone)]
 pub enum GrandpaCommitMessageOutcome {
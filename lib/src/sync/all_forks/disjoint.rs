@@ -153,6 +153,64 @@ impl<TBl> DisjointBlocks<TBl> {
         self.blocks.remove(&(height, *hash)).unwrap().user_data
     }
 
+    /// Removes the block and all its known children from the collection, and returns them.
+    ///
+    /// Contrary to [`DisjointBlocks::set_block_bad`], children of this block that are later
+    /// added to the collection aren't affected.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the block with the given height and hash hasn't been inserted before.
+    ///
+    #[track_caller]
+    pub fn remove_with_descendants(
+        &mut self,
+        mut height: u64,
+        hash: &[u8; 32],
+    ) -> Vec<(u64, [u8; 32], TBl)> {
+        assert!(self.blocks.contains_key(&(height, *hash)));
+
+        let mut removed = Vec::new();
+
+        // Initially contains the concerned block, then will contain the children of the concerned
+        // block, then the grand-children, then the grand-grand-children, and so on.
+        let mut blocks =
+            hashbrown::HashSet::with_capacity_and_hasher(1, fnv::FnvBuildHasher::default());
+        blocks.insert(*hash);
+
+        while !blocks.is_empty() {
+            let mut children = hashbrown::HashSet::with_capacity_and_hasher(
+                blocks.len() * 4,
+                fnv::FnvBuildHasher::default(),
+            );
+
+            // Iterate over all blocks whose height is `height + 1` to try find children.
+            for ((_maybe_child_height, maybe_child_hash), maybe_child) in self
+                .blocks
+                .range((height + 1, [0; 32])..=(height + 1, [0xff; 32]))
+            {
+                debug_assert_eq!(*_maybe_child_height, height + 1);
+                if maybe_child
+                    .parent_hash
+                    .as_ref()
+                    .is_some_and(|p| blocks.contains(p))
+                {
+                    children.insert(*maybe_child_hash);
+                }
+            }
+
+            for hash in blocks {
+                let block = self.blocks.remove(&(height, hash)).unwrap();
+                removed.push((height, hash, block.user_data));
+            }
+
+            blocks = children;
+            height += 1;
+        }
+
+        removed
+    }
+
     /// Removes from the collection the blocks whose height is strictly inferior to the given
     /// value, and returns them.
     pub fn remove_below_height(
@@ -501,4 +559,37 @@ mod tests {
         assert!(collection.is_bad(2, &[2; 32]).unwrap());
         assert!(collection.is_bad(3, &[3; 32]).unwrap());
     }
+
+    #[test]
+    fn remove_with_descendants_doesnt_mark_bad() {
+        // Removing a block with its descendants removes its known children and grand-children,
+        // but blocks re-inserted afterwards aren't considered bad.
+
+        let mut collection = super::DisjointBlocks::new();
+
+        collection.insert(1, [1; 32], Some([0; 32]), ());
+        collection.insert(2, [2; 32], Some([1; 32]), ());
+        collection.insert(2, [21; 32], Some([1; 32]), ());
+        collection.insert(3, [3; 32], Some([2; 32]), ());
+
+        // Control sample.
+        collection.insert(2, [0x80; 32], Some([0x70; 32]), ());
+
+        let mut removed = collection
+            .remove_with_descendants(1, &[1; 32])
+            .into_iter()
+            .map(|(height, hash, ())| (height, hash))
+            .collect::<Vec<_>>();
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![(1, [1; 32]), (2, [2; 32]), (2, [21; 32]), (3, [3; 32])]
+        );
+        assert_eq!(collection.len(), 1);
+
+        collection.insert(1, [1; 32], Some([0; 32]), ());
+        collection.insert(2, [2; 32], Some([1; 32]), ());
+        assert!(!collection.is_bad(1, &[1; 32]).unwrap());
+        assert!(!collection.is_bad(2, &[2; 32]).unwrap());
+    }
 }
@@ -652,6 +652,18 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.remove(height, hash).user_data
     }
 
+    /// Removes the given unverified block and all its known children from the collection,
+    /// without marking them as bad.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the block wasn't present in the data structure.
+    ///
+    #[track_caller]
+    pub fn remove_unverified_block_and_descendants(&mut self, height: u64, hash: &[u8; 32]) {
+        let _ = self.blocks.remove_with_descendants(height, hash);
+    }
+
     /// Marks the given unverified block and all its known children as "bad".
     ///
     /// If a child of this block is later added to the collection, it is also automatically
@@ -0,0 +1,183 @@
+// Smoldot
+// Copyright (C) 2026  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use core::{
+    num::{NonZeroU32, NonZeroU64},
+    time::Duration,
+};
+
+use super::{AllForksSync, BlockAnnounceOutcome, Config, HeaderVerifyOutcome, ProcessOne};
+use crate::{chain::chain_information, header};
+
+fn keypair() -> schnorrkel::Keypair {
+    schnorrkel::MiniSecretKey::from_bytes(&[1; 32])
+        .unwrap()
+        .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+}
+
+/// Builds an [`AllForksSync`] using the Aura consensus with a single authority, whose finalized
+/// block is a genesis block.
+fn aura_sync(keypair: &schnorrkel::Keypair) -> AllForksSync<(), (), ()> {
+    AllForksSync::new(Config {
+        chain_information: chain_information::ChainInformation {
+            finalized_block_header: Box::new(header::Header {
+                parent_hash: [0; 32],
+                number: 0,
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                digest: header::Digest::from(header::DigestRef::empty()),
+            }),
+            consensus: chain_information::ChainInformationConsensus::Aura {
+                finalized_authorities_list: vec![header::AuraAuthority {
+                    public_key: keypair.public.to_bytes(),
+                }],
+                slot_duration: NonZeroU64::new(1000).unwrap(),
+            },
+            finality: chain_information::ChainInformationFinality::Outsourced,
+        }
+        .try_into()
+        .unwrap(),
+        block_number_bytes: 4,
+        allow_unknown_consensus_engines: false,
+        slot_drift_tolerance: crate::verify::aura::DEFAULT_SLOT_DRIFT_TOLERANCE,
+        sources_capacity: 4,
+        blocks_capacity: 8,
+        max_disjoint_headers: 16,
+        max_requests_per_block: NonZeroU32::new(1).unwrap(),
+        download_bodies: false,
+    })
+}
+
+/// Builds the SCALE-encoded header of a block signed by the given authority.
+fn aura_header(
+    keypair: &schnorrkel::Keypair,
+    parent_hash: [u8; 32],
+    number: u64,
+    slot_number: u64,
+) -> Vec<u8> {
+    let pre_digest = [header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+        slot_number,
+    })];
+    let mut block_header = header::Header {
+        parent_hash,
+        number,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::from_slice(&pre_digest).unwrap().into(),
+    };
+
+    let signature = keypair
+        .sign_simple(b"substrate", &block_header.hash(4))
+        .to_bytes();
+    let sealed_digest = [
+        pre_digest[0].clone(),
+        header::DigestItem::AuraSeal(signature),
+    ];
+    block_header.digest = header::DigestRef::from_slice(&sealed_digest)
+        .unwrap()
+        .into();
+    block_header.scale_encoding_vec(4)
+}
+
+/// Announces the given header, and returns `true` if it was unknown to the state machine.
+fn announce(
+    sync: &mut AllForksSync<(), (), ()>,
+    source_id: super::SourceId,
+    scale_encoded_header: Vec<u8>,
+) -> bool {
+    match sync.block_announce(source_id, scale_encoded_header, true) {
+        BlockAnnounceOutcome::Unknown(announce) => {
+            announce.insert_and_update_source(());
+            true
+        }
+        BlockAnnounceOutcome::AlreadyPending(announce)
+        | BlockAnnounceOutcome::AlreadyVerified(announce) => {
+            announce.update_source_and_block();
+            false
+        }
+        _ => panic!(),
+    }
+}
+
+/// Verifies the header of the next block ready to be verified, which must succeed.
+fn verify_next(sync: AllForksSync<(), (), ()>) -> super::HeaderVerifySuccess<(), (), ()> {
+    match sync.process_one() {
+        ProcessOne::BlockVerify(verify) => match verify.verify_header(Duration::from_secs(1000)) {
+            HeaderVerifyOutcome::Success { success, .. } => success,
+            HeaderVerifyOutcome::Error { error, .. } => panic!("{error}"),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn discarded_block_not_marked_bad() {
+    // A block that is discarded, for example because it would cause a too deep reorg, can be
+    // announced and verified again later, contrary to a block rejected as bad.
+
+    let keypair = keypair();
+    let mut sync = aura_sync(&keypair);
+    let genesis_hash = *sync.finalized_block_hash();
+
+    let source_id = match sync.prepare_add_source(0, genesis_hash) {
+        super::AddSource::OldBestBlock(add) => add.add_source(()),
+        _ => panic!(),
+    };
+
+    // Best chain: genesis <- 1 <- 2 <- 3
+    let mut parent_hash = genesis_hash;
+    for number in 1..=3 {
+        let header = aura_header(&keypair, parent_hash, number, number);
+        parent_hash = header::hash_from_scale_encoded_header(&header);
+        assert!(announce(&mut sync, source_id, header));
+        let success = verify_next(sync);
+        assert_eq!(success.reorg_depth(), 0);
+        sync = success.finish();
+    }
+    assert_eq!(sync.best_block_number(), 3);
+
+    // Fork: genesis <- 1' <- 2'
+    let fork1 = aura_header(&keypair, genesis_hash, 1, 5);
+    let fork1_hash = header::hash_from_scale_encoded_header(&fork1);
+    let fork2 = aura_header(&keypair, fork1_hash, 2, 6);
+    assert!(announce(&mut sync, source_id, fork1.clone()));
+    assert!(announce(&mut sync, source_id, fork2));
+
+    let success = verify_next(sync);
+    assert_eq!(*success.hash(), fork1_hash);
+    assert_eq!(success.reorg_depth(), 3);
+    sync = success.discard();
+
+    // The block and its child are no longer pending.
+    sync = match sync.process_one() {
+        ProcessOne::AllSync { sync } => sync,
+        _ => panic!(),
+    };
+    assert_eq!(*sync.best_block_hash(), parent_hash);
+
+    // Announcing the block again makes it possible to verify it again.
+    assert!(announce(&mut sync, source_id, fork1.clone()));
+    let success = verify_next(sync);
+    assert_eq!(*success.hash(), fork1_hash);
+    sync = success.reject_bad_block();
+
+    // Once rejected as bad, announcing the block again doesn't lead to a verification.
+    assert!(!announce(&mut sync, source_id, fork1));
+    assert!(matches!(sync.process_one(), ProcessOne::AllSync { .. }));
+}
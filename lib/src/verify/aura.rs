@@ -78,11 +78,35 @@ pub struct VerifyConfig<'a, TAuthList> {
     /// Duration of a slot in milliseconds.
     /// Can be found by calling the `AuraApi_slot_duration` runtime function.
     pub slot_duration: NonZeroU64,
+
+    /// Maximum duration by which the slot of the block is allowed to start after
+    /// [`VerifyConfig::now_from_unix_epoch`]. Accounts for the clock drift of the local node
+    /// and of the author of the block.
+    ///
+    /// If the local node is an authority itself, and the best block uses a slot number `N`
+    /// seconds in the future, then for the next `N` seconds the local node won't produce any
+    /// block. As such, a high tolerance constitutes an attack vector. Private networks whose
+    /// nodes are frequently paused and resumed might however want to use a high value.
+    ///
+    /// See also [`DEFAULT_SLOT_DRIFT_TOLERANCE`].
+    pub slot_drift_tolerance: Duration,
 }
 
+/// Typical value for [`VerifyConfig::slot_drift_tolerance`].
+pub const DEFAULT_SLOT_DRIFT_TOLERANCE: Duration = Duration::from_secs(30);
+
 /// Information yielded back after successfully verifying a block.
 #[derive(Debug)]
 pub struct VerifySuccess {
+    /// Slot number the block belongs to.
+    ///
+    /// > **Note**: This is a simple reminder. The value can also be found in the header of the
+    /// >           block.
+    pub slot_number: u64,
+
+    /// Sr25519 public key of the authority that has authored the block.
+    pub authority_public_key: [u8; 32],
+
     /// `Some` if the list of authorities is modified by this block. Contains the new list of
     /// authorities.
     pub authorities_change: Option<Vec<header::AuraAuthority>>,
@@ -140,13 +164,13 @@ pub fn verify_header<'a>(
     // Check that the slot number isn't a slot in the future.
     // Since there might be a clock drift (either locally or on the authority that created the
     // block), a tolerance period is added.
-    // If the local node is an authority itself, and the best block uses a slot number `N` seconds
-    // in the future, then for the next `N` seconds the local node won't produce any block. As
-    // such, a high tolerance level constitutes an attack vector.
     {
-        const TOLERANCE: Duration = Duration::from_secs(30);
-        let current_slot =
-            (config.now_from_unix_epoch + TOLERANCE).as_secs() * 1000 / config.slot_duration.get();
+        let current_slot = config
+            .now_from_unix_epoch
+            .saturating_add(config.slot_drift_tolerance)
+            .as_secs()
+            .saturating_mul(1000)
+            / config.slot_duration.get();
         if slot_number > current_slot {
             return Err(VerifyError::TooFarInFuture);
         }
@@ -200,16 +224,16 @@ pub fn verify_header<'a>(
     )
     .unwrap_or_else(|_| unreachable!());
 
+    let signing_authority_public_key = *config
+        .current_authorities
+        .nth(signing_authority)
+        .unwrap()
+        .public_key;
+
     // This `unwrap()` can only panic if `public_key` is the wrong length, which we know can't
     // happen as it's of type `[u8; 32]`.
-    let authority_public_key = schnorrkel::PublicKey::from_bytes(
-        config
-            .current_authorities
-            .nth(signing_authority)
-            .unwrap()
-            .public_key,
-    )
-    .unwrap();
+    let authority_public_key =
+        schnorrkel::PublicKey::from_bytes(&signing_authority_public_key).unwrap();
 
     // Now verifying the signature in the seal.
     authority_public_key
@@ -217,5 +241,9 @@ pub fn verify_header<'a>(
         .map_err(|_| VerifyError::BadSignature)?;
 
     // Success! 🚀
-    Ok(VerifySuccess { authorities_change })
+    Ok(VerifySuccess {
+        slot_number,
+        authority_public_key: signing_authority_public_key,
+        authorities_change,
+    })
 }
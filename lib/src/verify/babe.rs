@@ -182,6 +182,9 @@ pub struct VerifySuccess {
     /// `true` if the claimed slot is a primary slot. `false` if it is a secondary slot.
     pub is_primary_slot: bool,
 
+    /// Sr25519 public key of the authority that has authored the block.
+    pub authority_public_key: [u8; 32],
+
     /// If `Some`, the verified block contains an epoch transition describing the new "next epoch".
     /// When verifying blocks that are children of this one, the value in this field must be
     /// provided as [`VerifyConfig::parent_block_next_epoch`], and the value previously in
@@ -467,13 +470,8 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     if let Some((vrf_output, vrf_proof)) = vrf_output_and_proof {
         // In order to verify the VRF output, we first need to create a transcript containing all
         // the data to verify the VRF against.
-        let transcript = {
-            let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
-            transcript.append_u64(b"slot number", slot_number);
-            transcript.append_u64(b"current epoch", block_epoch_index);
-            transcript.append_message(b"chain randomness", &block_epoch_info.randomness[..]);
-            transcript
-        };
+        let transcript =
+            vrf_transcript(slot_number, block_epoch_index, block_epoch_info.randomness);
 
         // These `unwrap()`s can only panic if `vrf_output` or `vrf_proof` are of the wrong
         // length, which we know can't happen as they're of types `[u8; 32]` and `[u8; 64]`.
@@ -506,25 +504,13 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     // claim. If the block is a secondary slot claim, we need to make sure that the author
     // is indeed the one that is expected.
     if !is_primary_slot {
-        // Expected author is determined based on `blake2(randomness | slot_number)`.
-        let hash = {
-            let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
-            hash.update(block_epoch_info.randomness);
-            hash.update(&slot_number.to_le_bytes());
-            hash.finalize()
-        };
-
-        // The expected authority index is `hash % num_authorities`.
-        let expected_authority_index = {
-            let hash = num_bigint::BigUint::from_bytes_be(hash.as_bytes());
-            let authorities_len = num_bigint::BigUint::from(block_epoch_info.authorities.len());
-            debug_assert_ne!(block_epoch_info.authorities.len(), 0);
-            hash % authorities_len
-        };
+        let expected_authority_index = secondary_slot_author(
+            block_epoch_info.randomness,
+            slot_number,
+            block_epoch_info.authorities.len(),
+        );
 
-        if num_traits::cast::ToPrimitive::to_u32(&expected_authority_index)
-            .map_or(true, |v| v != authority_index)
-        {
+        if expected_authority_index != Some(authority_index) {
             return Err(VerifyError::BadSecondarySlotAuthor);
         }
     }
@@ -533,10 +519,50 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     Ok(VerifySuccess {
         slot_number,
         is_primary_slot,
+        authority_public_key: *signing_authority.public_key,
         epoch_transition_target,
     })
 }
 
+/// Builds the transcript that the VRF output and proof of a block authored during the given
+/// slot must be generated from.
+pub(crate) fn vrf_transcript(
+    slot_number: u64,
+    epoch_index: u64,
+    randomness: &[u8; 32],
+) -> merlin::Transcript {
+    let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
+    transcript.append_u64(b"slot number", slot_number);
+    transcript.append_u64(b"current epoch", epoch_index);
+    transcript.append_message(b"chain randomness", &randomness[..]);
+    transcript
+}
+
+/// Returns the index of the authority that is allowed to claim the given slot as a secondary
+/// slot, or `None` if `num_authorities` is 0 or if the index doesn't fit in a `u32`.
+pub(crate) fn secondary_slot_author(
+    randomness: &[u8; 32],
+    slot_number: u64,
+    num_authorities: usize,
+) -> Option<u32> {
+    if num_authorities == 0 {
+        return None;
+    }
+
+    // Expected author is determined based on `blake2(randomness | slot_number)`.
+    let hash = {
+        let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+        hash.update(randomness);
+        hash.update(&slot_number.to_le_bytes());
+        hash.finalize()
+    };
+
+    // The expected authority index is `hash % num_authorities`.
+    let hash = num_bigint::BigUint::from_bytes_be(hash.as_bytes());
+    let authorities_len = num_bigint::BigUint::from(num_authorities);
+    num_traits::cast::ToPrimitive::to_u32(&(hash % authorities_len))
+}
+
 // Because `f64::powf` isn't available in no-std contexts, we generate a version of this function
 // with either `f64::powf` or `libm::pow`. Both functions are equivalent, except that `f64::powf`
 // is expected to be faster on some platforms.
@@ -555,7 +581,7 @@ macro_rules! gen_calculate_primary_threshold {
         /// Panics if `authorities_weights` is empty.
         /// Panics if `authority_weight` is 0.
         ///
-        fn $name(
+        pub(crate) fn $name(
             c: (u64, u64),
             authorities_weights: impl Iterator<Item = u64>,
             authority_weight: u64, // TODO: use a NonZeroU64 once crate::header also has weights that use NonZeroU64
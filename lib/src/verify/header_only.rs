@@ -78,6 +78,9 @@ pub enum ConfigConsensus<'a> {
         /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
         /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
         now_from_unix_epoch: Duration,
+
+        /// See [`aura::VerifyConfig::slot_drift_tolerance`].
+        slot_drift_tolerance: Duration,
     },
 
     /// Chain is using the Babe consensus engine.
@@ -115,6 +118,15 @@ pub enum ConfigFinality {
 pub enum Success {
     /// Chain is using the Aura consensus engine.
     Aura {
+        /// Slot number the block belongs to.
+        ///
+        /// > **Note**: This is a simple reminder. The value can also be found in the header of the
+        /// >           block.
+        slot_number: u64,
+
+        /// Sr25519 public key of the authority that has authored the block.
+        authority_public_key: [u8; 32],
+
         /// `Some` if the list of authorities is modified by this block. Contains the new list of
         /// authorities.
         authorities_change: Option<Vec<header::AuraAuthority>>,
@@ -131,6 +143,9 @@ pub enum Success {
         /// `true` if the claimed slot is a primary slot. `false` if it is a secondary slot.
         is_primary_slot: bool,
 
+        /// Sr25519 public key of the authority that has authored the block.
+        authority_public_key: [u8; 32],
+
         /// If `Some`, the verified block contains an epoch transition describing the new
         /// "next epoch". When verifying blocks that are children of this one, the value in this
         /// field must be provided as [`ConfigConsensus::Babe::parent_block_next_epoch`], and the
@@ -232,6 +247,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
             current_authorities,
             slot_duration,
             now_from_unix_epoch,
+            slot_drift_tolerance,
         } => {
             if config.block_header.digest.has_any_babe() {
                 return Err(Error::MultipleConsensusEngines);
@@ -244,10 +260,13 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 now_from_unix_epoch,
                 current_authorities,
                 slot_duration,
+                slot_drift_tolerance,
             });
 
             match result {
                 Ok(s) => Ok(Success::Aura {
+                    slot_number: s.slot_number,
+                    authority_public_key: s.authority_public_key,
                     authorities_change: s.authorities_change,
                 }),
                 Err(err) => Err(Error::AuraVerification(err)),
@@ -277,6 +296,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 Ok(s) => Ok(Success::Babe {
                     epoch_transition_target: s.epoch_transition_target,
                     is_primary_slot: s.is_primary_slot,
+                    authority_public_key: s.authority_public_key,
                     slot_number: s.slot_number,
                 }),
                 Err(err) => Err(Error::BabeVerification(err)),
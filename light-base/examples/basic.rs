@@ -62,6 +62,10 @@ fn main() {
                 // start a lot of subscriptions, and a value such as 1024 is recommended.
                 // Similarly, if you don't want any limit, feel free to pass `u32::MAX`.
                 max_subscriptions: 1024,
+                // Limits to the number of blocks that the JSON-RPC client can keep pinned. The
+                // default values are appropriate unless a lot of chains or JSON-RPC clients are
+                // running at the same time.
+                pinned_blocks: Default::default(),
             },
 
             // This field is necessary only if adding a parachain.
@@ -99,6 +103,7 @@ fn main() {
             json_rpc: smoldot_light::AddChainConfigJsonRpc::Enabled {
                 max_pending_requests: NonZeroU32::new(128).unwrap(),
                 max_subscriptions: 1024,
+                pinned_blocks: Default::default(),
             },
             database_content: "",
             user_data: (),
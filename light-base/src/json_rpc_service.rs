@@ -51,7 +51,10 @@ use alloc::{
     string::{String, ToString as _},
     sync::Arc,
 };
-use core::{num::NonZeroU32, pin::Pin};
+use core::{
+    num::{NonZeroU32, NonZeroUsize},
+    pin::Pin,
+};
 use futures_lite::StreamExt as _;
 
 /// Configuration for [`service()`].
@@ -83,6 +86,9 @@ pub struct Config<TPlat: PlatformRef> {
     #[allow(unused)]
     pub max_subscriptions: u32,
 
+    /// Limits to the number of blocks that the JSON-RPC client can keep pinned.
+    pub pinned_blocks: PinnedBlocksConfig,
+
     /// Access to the network, and identifier of the chain from the point of view of the network
     /// service.
     pub network_service: Arc<network_service::NetworkServiceChain<TPlat>>,
@@ -117,6 +123,41 @@ pub struct Config<TPlat: PlatformRef> {
     pub genesis_block_hash: [u8; 32],
 }
 
+/// Limits to the number of blocks that a JSON-RPC client can keep pinned, and to the number of
+/// recent blocks whose information is kept in memory.
+///
+/// Each pinned block holds its header and, if it has a new runtime, its runtime in memory. These
+/// limits make it possible to bound the memory used by each JSON-RPC client.
+#[derive(Debug, Clone)]
+pub struct PinnedBlocksConfig {
+    /// Maximum number of simultaneous `chainHead_v1_follow` subscriptions. Any additional
+    /// subscription is immediately rejected.
+    pub max_follow_subscriptions: u32,
+
+    /// Maximum number of blocks that each `chainHead_v1_follow` subscription of the JSON-RPC
+    /// client can keep pinned. If a subscription exceeds this limit, it is stopped. The other
+    /// subscriptions aren't affected.
+    pub max_pinned_blocks: NonZeroUsize,
+
+    /// Number of finalized or pruned blocks whose information is kept in memory for the legacy
+    /// JSON-RPC API. Blocks beyond this number are unpinned, starting with the least recently
+    /// used ones.
+    ///
+    /// A lower value discards the data of blocks more aggressively, at the cost of having to
+    /// download it again if the JSON-RPC client asks about these blocks.
+    pub recent_blocks_cache_size: NonZeroUsize,
+}
+
+impl Default for PinnedBlocksConfig {
+    fn default() -> Self {
+        PinnedBlocksConfig {
+            max_follow_subscriptions: 2,
+            max_pinned_blocks: NonZeroUsize::new(32).unwrap(),
+            recent_blocks_cache_size: NonZeroUsize::new(32).unwrap(),
+        }
+    }
+}
+
 /// Creates a new JSON-RPC service with the given configuration.
 ///
 /// Returns a handler that allows sending requests and receiving responses.
@@ -153,6 +194,7 @@ pub fn service<TPlat: PlatformRef>(config: Config<TPlat>) -> Frontend<TPlat> {
                 system_name: config.system_name,
                 system_version: config.system_version,
                 genesis_block_hash: config.genesis_block_hash,
+                pinned_blocks: config.pinned_blocks,
             },
             requests_rx,
             responses_tx,
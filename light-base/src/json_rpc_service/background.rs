@@ -90,6 +90,9 @@ pub(super) struct Config<TPlat: PlatformRef> {
 
     /// Hash of the genesis block of the chain.
     pub genesis_block_hash: [u8; 32],
+
+    /// Limits to the number of blocks that the JSON-RPC client can keep pinned.
+    pub pinned_blocks: super::PinnedBlocksConfig,
 }
 
 /// Fields used to process JSON-RPC requests in the background.
@@ -142,6 +145,9 @@ struct Background<TPlat: PlatformRef> {
     chain_head_follow_subscriptions:
         hashbrown::HashMap<String, ChainHeadFollow, fnv::FnvBuildHasher>,
 
+    /// See [`Config::pinned_blocks`].
+    pinned_blocks_config: super::PinnedBlocksConfig,
+
     /// If `true`, we have already printed a warning about usage of the legacy JSON-RPC API. This
     /// flag prevents printing this message multiple times.
     printed_legacy_json_rpc_warning: bool,
@@ -539,14 +545,14 @@ pub(super) async fn run<TPlat: PlatformRef>(
         responses_tx,
         multistage_requests_to_advance: VecDeque::new(),
         block_headers_cache: lru::LruCache::with_hasher(
-            NonZeroUsize::new(32).unwrap_or_else(|| unreachable!()),
+            config.pinned_blocks.recent_blocks_cache_size,
             Default::default(),
         ),
         best_block_hash_pending: Vec::new(),
         pending_get_finalized_head: Vec::new(),
         block_headers_pending: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
         block_runtimes_cache: lru::LruCache::with_hasher(
-            NonZeroUsize::new(32).unwrap_or_else(|| unreachable!()),
+            config.pinned_blocks.recent_blocks_cache_size,
             Default::default(),
         ),
         block_runtimes_pending: hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
@@ -560,6 +566,7 @@ pub(super) async fn run<TPlat: PlatformRef>(
         ),
         genesis_block_hash: config.genesis_block_hash,
         printed_legacy_json_rpc_warning: false,
+        pinned_blocks_config: config.pinned_blocks,
         platform: config.platform,
     };
 
@@ -767,6 +774,7 @@ pub(super) async fn run<TPlat: PlatformRef>(
                     | methods::MethodCall::state_queryStorageAt { .. }
                     | methods::MethodCall::state_subscribeRuntimeVersion { .. }
                     | methods::MethodCall::state_subscribeStorage { .. }
+                    | methods::MethodCall::state_traceBlock { .. }
                     | methods::MethodCall::state_unsubscribeRuntimeVersion { .. }
                     | methods::MethodCall::state_unsubscribeStorage { .. }
                     | methods::MethodCall::system_accountNextIndex { .. }
@@ -818,7 +826,13 @@ pub(super) async fn run<TPlat: PlatformRef>(
                     | methods::MethodCall::chainSpec_v1_genesisHash { .. }
                     | methods::MethodCall::chainSpec_v1_properties { .. }
                     | methods::MethodCall::rpc_methods { .. }
+                    | methods::MethodCall::sudo_unstable_blockExecutionProfile { .. }
+                    | methods::MethodCall::sudo_unstable_blockAuthorities { .. }
+                    | methods::MethodCall::sudo_unstable_databaseStatistics { .. }
+                    | methods::MethodCall::sudo_unstable_forceFinalize { .. }
                     | methods::MethodCall::sudo_unstable_p2pDiscover { .. }
+                    | methods::MethodCall::sudo_unstable_setAuthoringEnabled { .. }
+                    | methods::MethodCall::sudo_unstable_storageDiff { .. }
                     | methods::MethodCall::sudo_unstable_version { .. }
                     | methods::MethodCall::transaction_v1_broadcast { .. }
                     | methods::MethodCall::transaction_v1_stop { .. }
@@ -2209,8 +2223,10 @@ pub(super) async fn run<TPlat: PlatformRef>(
 
                     methods::MethodCall::chainHead_v1_follow { with_runtime } => {
                         // Check that the number of existing subscriptions is below the limit.
-                        // TODO: configurable limit
-                        if me.chain_head_follow_subscriptions.len() >= 2 {
+                        if me.chain_head_follow_subscriptions.len()
+                            >= usize::try_from(me.pinned_blocks_config.max_follow_subscriptions)
+                                .unwrap_or(usize::MAX)
+                        {
                             let _ = me
                                 .responses_tx
                                 .send(parse::build_error_response(
@@ -2263,14 +2279,12 @@ pub(super) async fn run<TPlat: PlatformRef>(
                         // sync service.
                         if with_runtime {
                             let runtime_service = me.runtime_service.clone();
+                            let max_pinned_blocks = me.pinned_blocks_config.max_pinned_blocks;
                             me.background_tasks.push(Box::pin(async move {
                                 Event::ChainHeadSubscriptionWithRuntimeReady {
                                     subscription_id,
                                     subscription: runtime_service
-                                        .subscribe_all(
-                                            32,
-                                            NonZeroUsize::new(32).unwrap_or_else(|| unreachable!()),
-                                        )
+                                        .subscribe_all(32, max_pinned_blocks)
                                         .await,
                                 }
                             }))
@@ -2740,11 +2754,22 @@ pub(super) async fn run<TPlat: PlatformRef>(
                     | methods::MethodCall::state_getStorageHash { .. }
                     | methods::MethodCall::state_getStorageSize { .. }
                     | methods::MethodCall::state_queryStorage { .. }
+                    | methods::MethodCall::state_traceBlock { .. }
                     | methods::MethodCall::system_addReservedPeer { .. }
                     | methods::MethodCall::system_dryRun { .. }
                     | methods::MethodCall::system_localPeerId { .. }
                     | methods::MethodCall::system_networkState { .. }
                     | methods::MethodCall::system_removeReservedPeer { .. }
+                    | methods::MethodCall::sudo_unstable_blockExecutionProfile {
+                        ..
+                    }
+                    | methods::MethodCall::sudo_unstable_blockAuthorities { .. }
+                    | methods::MethodCall::sudo_unstable_databaseStatistics { .. }
+                    | methods::MethodCall::sudo_unstable_forceFinalize { .. }
+                    | methods::MethodCall::sudo_unstable_setAuthoringEnabled {
+                        ..
+                    }
+                    | methods::MethodCall::sudo_unstable_storageDiff { .. }
                     | methods::MethodCall::sudo_network_unstable_watch { .. }
                     | methods::MethodCall::sudo_network_unstable_unwatch { .. }) => {
                         // TODO: implement the ones that make sense to implement ^
@@ -4045,6 +4070,31 @@ pub(super) async fn run<TPlat: PlatformRef>(
                     }
                 }
 
+                // Stop the subscription if it keeps too many blocks pinned.
+                if me
+                    .chain_head_follow_subscriptions
+                    .get(&subscription_id)
+                    .is_some_and(|subscription| {
+                        chain_head_pinned_blocks_limit_exceeded(
+                            subscription,
+                            me.pinned_blocks_config.max_pinned_blocks,
+                        )
+                    })
+                {
+                    log!(
+                        &me.platform,
+                        Debug,
+                        &me.log_target,
+                        "chain-head-pinned-blocks-limit-reached",
+                        subscription_id,
+                        max_pinned_blocks = me.pinned_blocks_config.max_pinned_blocks
+                    );
+                    me.background_tasks.push(Box::pin(async move {
+                        Event::ChainHeadSubscriptionDeadSubcription { subscription_id }
+                    }));
+                    continue;
+                }
+
                 // Push a new task that will yield when the runtime service subscription generates
                 // the next notification.
                 me.background_tasks.push(Box::pin(async move {
@@ -4168,6 +4218,31 @@ pub(super) async fn run<TPlat: PlatformRef>(
                     }
                 }
 
+                // Stop the subscription if it keeps too many blocks pinned.
+                if me
+                    .chain_head_follow_subscriptions
+                    .get(&subscription_id)
+                    .is_some_and(|subscription| {
+                        chain_head_pinned_blocks_limit_exceeded(
+                            subscription,
+                            me.pinned_blocks_config.max_pinned_blocks,
+                        )
+                    })
+                {
+                    log!(
+                        &me.platform,
+                        Debug,
+                        &me.log_target,
+                        "chain-head-pinned-blocks-limit-reached",
+                        subscription_id,
+                        max_pinned_blocks = me.pinned_blocks_config.max_pinned_blocks
+                    );
+                    me.background_tasks.push(Box::pin(async move {
+                        Event::ChainHeadSubscriptionDeadSubcription { subscription_id }
+                    }));
+                    continue;
+                }
+
                 // Push a new task that will yield when the sync service subscription generates
                 // the next notification.
                 me.background_tasks.push(Box::pin(async move {
@@ -4576,7 +4651,7 @@ pub(super) async fn run<TPlat: PlatformRef>(
                 let mut pinned_blocks =
                     hashbrown::HashMap::with_capacity_and_hasher(32, Default::default());
                 let mut finalized_and_pruned_lru = lru::LruCache::with_hasher(
-                    NonZeroUsize::new(32).unwrap(),
+                    me.pinned_blocks_config.recent_blocks_cache_size,
                     fnv::FnvBuildHasher::default(),
                 );
 
@@ -5731,6 +5806,18 @@ pub(super) async fn run<TPlat: PlatformRef>(
     }
 }
 
+/// Returns `true` if the given `chainHead_v1_follow` subscription keeps more than
+/// `max_pinned_blocks` blocks pinned, in which case it must be stopped.
+///
+/// The limit applies to each subscription individually, so that a subscription that doesn't
+/// unpin its blocks doesn't affect the other subscriptions of the same JSON-RPC client.
+fn chain_head_pinned_blocks_limit_exceeded(
+    subscription: &ChainHeadFollow,
+    max_pinned_blocks: NonZeroUsize,
+) -> bool {
+    subscription.pinned_blocks_headers.len() > max_pinned_blocks.get()
+}
+
 fn convert_runtime_version_legacy(
     runtime_spec: &smoldot::executor::CoreVersion,
 ) -> methods::RuntimeVersion {
@@ -5764,3 +5851,43 @@ fn convert_runtime_version(runtime_spec: &smoldot::executor::CoreVersion) -> met
             .collect(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{chain_head_pinned_blocks_limit_exceeded, ChainHeadFollow};
+    use core::num::NonZeroUsize;
+
+    fn subscription_with_pinned_blocks(num_pinned_blocks: u8) -> ChainHeadFollow {
+        ChainHeadFollow {
+            pinned_blocks_headers: (0..num_pinned_blocks)
+                .map(|n| ([n; 32], Vec::new()))
+                .collect(),
+            operations_in_progress: Default::default(),
+            available_operation_slots: 32,
+            runtime_service_subscription_id: None,
+        }
+    }
+
+    #[test]
+    fn pinned_blocks_limit_stops_only_offending_subscription() {
+        let max_pinned_blocks = NonZeroUsize::new(32).unwrap();
+
+        // The two subscriptions together pin more than 32 blocks, but only one of them pins
+        // more than 32 blocks on its own.
+        let offending = subscription_with_pinned_blocks(33);
+        let other = subscription_with_pinned_blocks(31);
+
+        assert!(chain_head_pinned_blocks_limit_exceeded(
+            &offending,
+            max_pinned_blocks
+        ));
+        assert!(!chain_head_pinned_blocks_limit_exceeded(
+            &other,
+            max_pinned_blocks
+        ));
+        assert!(!chain_head_pinned_blocks_limit_exceeded(
+            &subscription_with_pinned_blocks(32),
+            max_pinned_blocks
+        ));
+    }
+}
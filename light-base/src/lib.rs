@@ -106,7 +106,7 @@ mod util;
 pub mod network_service;
 pub mod platform;
 
-pub use json_rpc_service::HandleRpcError;
+pub use json_rpc_service::{HandleRpcError, PinnedBlocksConfig};
 
 /// See [`Client::add_chain`].
 #[derive(Debug, Clone)]
@@ -177,6 +177,13 @@ pub enum AddChainConfigJsonRpc {
         /// While a typical reasonable value would be for example 64, existing UIs tend to start
         /// a lot of subscriptions, and a value such as 1024 is recommended.
         max_subscriptions: u32,
+
+        /// Limits to the number of blocks that the JSON-RPC client can keep pinned, and to the
+        /// number of recent blocks whose information is kept in memory.
+        ///
+        /// Embedders that serve a lot of JSON-RPC clients at the same time can lower these
+        /// limits in order to bound the memory usage of each client.
+        pinned_blocks: PinnedBlocksConfig,
     },
 }
 
@@ -927,6 +934,7 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
         let json_rpc_frontend = if let AddChainConfigJsonRpc::Enabled {
             max_pending_requests,
             max_subscriptions,
+            pinned_blocks,
         } = config.json_rpc
         {
             let frontend = json_rpc_service::service(json_rpc_service::Config {
@@ -934,6 +942,7 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                 log_name: log_name.clone(), // TODO: add a way to differentiate multiple different json-rpc services under the same chain
                 max_pending_requests,
                 max_subscriptions,
+                pinned_blocks,
                 sync_service: services.sync_service.clone(),
                 network_service: services.network_service.clone(),
                 transactions_service: services.transactions_service.clone(),
@@ -153,6 +153,8 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             chains_capacity: config.chains_capacity,
             connections_capacity: 32,
             handshake_timeout: Duration::from_secs(8),
+            // The light client doesn't answer block and state requests.
+            compress_response: None,
             randomness_seed: {
                 let mut seed = [0; 32];
                 config.platform.fill_random_bytes(&mut seed);
@@ -235,12 +237,16 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                     },
                 ),
                 fork_id: config.fork_id.clone(),
+                legacy_protocol_id: None,
                 block_number_bytes: config.block_number_bytes,
                 best_hash: config.best_block.1,
                 best_number: config.best_block.0,
                 genesis_hash: config.genesis_block_hash,
                 role: Role::Light,
                 allow_inbound_block_requests: false,
+                allow_inbound_checkpoint_requests: false,
+                allow_inbound_state_requests: false,
+                allow_inbound_light_requests: false,
                 user_data: Chain {
                     log_name: config.log_name,
                     block_number_bytes: config.block_number_bytes,
@@ -1064,6 +1070,10 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                             .unwrap();
                         existing_identical
                     }
+                    Err(service::AddChainError::DuplicateLegacyProtocolId { .. }) => {
+                        // Legacy protocol ids are never passed.
+                        unreachable!()
+                    }
                 };
 
                 task.chains_by_next_discovery.insert(
@@ -1543,6 +1553,18 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                             peers_queue_full.push(peer.to_base58())
                         }
                         Err(QueueNotificationError::NoConnection) => unreachable!(),
+                        Err(QueueNotificationError::TooLarge) => {
+                            log!(
+                                &task.platform,
+                                Warn,
+                                "network",
+                                "transaction-too-large",
+                                chain = task.network[chain_id].log_name,
+                                size = transaction.len(),
+                            );
+                            // The size limit is the same for all peers.
+                            break;
+                        }
                     }
                 }
 
@@ -2416,6 +2438,18 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .respond_identify(substream_id, &task.identify_agent_version);
             }
             WakeUpReason::NetworkEvent(service::Event::BlocksRequestIn { .. }) => unreachable!(),
+            WakeUpReason::NetworkEvent(service::Event::CheckpointRequestIn { .. }) => {
+                unreachable!()
+            }
+            WakeUpReason::NetworkEvent(service::Event::StateRequestIn { .. }) => unreachable!(),
+            WakeUpReason::NetworkEvent(service::Event::StorageProofRequestIn { .. }) => {
+                unreachable!()
+            }
+            WakeUpReason::NetworkEvent(service::Event::CallProofRequestIn { .. }) => unreachable!(),
+            WakeUpReason::NetworkEvent(service::Event::IdentifyRequestResult { .. }) => {
+                // Identify requests are never started.
+                unreachable!()
+            }
             WakeUpReason::NetworkEvent(service::Event::RequestInCancel { .. }) => {
                 // All incoming requests are immediately answered.
                 unreachable!()
@@ -2470,6 +2504,14 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 task.event_pending_send =
                     Some((chain_id, Event::GrandpaCommitMessage { peer_id, message }));
             }
+            WakeUpReason::NetworkEvent(
+                service::Event::GrandpaVoteMessage { .. }
+                | service::Event::GrandpaCatchUpRequest { .. }
+                | service::Event::GrandpaCatchUp { .. },
+            ) => {
+                // The light client doesn't participate in GrandPa rounds, and votes and catch up
+                // messages are thus ignored.
+            }
             WakeUpReason::NetworkEvent(service::Event::ProtocolError { peer_id, error }) => {
                 // TODO: handle properly?
                 log!(
@@ -44,6 +44,7 @@ use smoldot::{
     libp2p,
     network::{self, codec},
     sync::all,
+    verify,
 };
 
 /// Starts a sync service background task to synchronize a standalone chain (relay chain or not).
@@ -68,6 +69,7 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             // on the other hand, allows supporting chains that use custom consensus engines,
             // which is considered worth the trade-off.
             allow_unknown_consensus_engines: true,
+            slot_drift_tolerance: verify::aura::DEFAULT_SLOT_DRIFT_TOLERANCE,
             sources_capacity: 32,
             blocks_capacity: {
                 // This is the maximum number of blocks between two consecutive justifications.
@@ -93,6 +95,7 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             },
             download_bodies: false,
             download_all_chain_information_storage_proofs: false,
+            warp_sync: true,
             code_trie_node_hint: runtime_code_hint.map(|hint| all::ConfigCodeTrieNodeHint {
                 merkle_value: hint.merkle_value,
                 storage_value: hint.storage_value,
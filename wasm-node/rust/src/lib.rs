@@ -132,6 +132,7 @@ fn add_chain(
                             max_pending_requests: json_rpc_max_pending_requests,
                             // Note: the PolkadotJS UI is very heavy in terms of subscriptions.
                             max_subscriptions: json_rpc_max_subscriptions,
+                            pinned_blocks: Default::default(),
                         }
                     } else {
                         smoldot_light::AddChainConfigJsonRpc::Disabled